@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 use serde::Deserialize;
+use std::net::IpAddr;
 use std::path::PathBuf;
 use std::time::Duration;
 
@@ -22,11 +23,20 @@ pub struct InstallConfig {
     pub product_version: String,
     pub port: u16,
     pub management_port: Option<u16>,
+    pub http_redirect_port: Option<u16>,
     pub keystore: Option<super::KeystoreConfig>,
     pub client_auth_truststore: Option<super::ClientAuthTruststoreConfig>,
+    pub acme: Option<super::AcmeConfig>,
+    pub tls: Option<super::TlsConfig>,
     pub context_path: Option<String>,
+    pub context_path_aliases: Option<Vec<String>>,
+    pub canonical_url: Option<String>,
     pub use_console_log: Option<bool>,
     pub server: Option<super::ServerConfig>,
+    pub access_log: Option<super::AccessLogConfig>,
+    pub log_compression: Option<super::LogCompressionConfig>,
+    pub jemalloc: Option<super::JemallocConfig>,
+    pub profiling: Option<super::ProfilingConfig>,
 }
 
 #[derive(Deserialize)]
@@ -40,6 +50,105 @@ pub struct KeystoreConfig {
 #[serde(rename_all = "kebab-case")]
 pub struct ClientAuthTruststoreConfig {
     pub path: Option<PathBuf>,
+    pub client_auth: Option<super::ClientAuth>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TlsConfig {
+    pub protocol_versions: Option<Vec<super::TlsVersion>>,
+    pub cipher_suites: Option<Vec<super::CipherSuite>>,
+    pub key_exchange_groups: Option<Vec<super::KeyExchangeGroup>>,
+    pub ocsp_stapling: Option<super::OcspStaplingConfig>,
+    pub session_resumption: Option<super::SessionResumptionConfig>,
+    pub key_log_file: Option<PathBuf>,
+    pub crypto_provider: Option<super::CryptoProvider>,
+    pub fips: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SessionResumptionConfig {
+    pub enabled: Option<bool>,
+    #[serde(default, with = "humantime_serde")]
+    pub ticket_lifetime: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    pub ticket_key_rotation: Option<Duration>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct OcspStaplingConfig {
+    pub enabled: Option<bool>,
+    #[serde(default, with = "humantime_serde")]
+    pub refresh_interval: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AcmeConfig {
+    pub directory_url: Option<String>,
+    pub domains: Vec<String>,
+    pub contacts: Option<Vec<String>>,
+    pub challenge: Option<super::AcmeChallenge>,
+    #[serde(rename = "http-01-port")]
+    pub http01_port: Option<u16>,
+    #[serde(default, with = "humantime_serde")]
+    pub renew_before: Option<Duration>,
+    pub account_path: Option<PathBuf>,
+    pub agree_to_terms_of_service: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AccessLogConfig {
+    pub enabled: Option<bool>,
+    pub format: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LogCompressionConfig {
+    pub service: Option<super::LogCompression>,
+    pub request: Option<super::LogCompression>,
+    pub metric: Option<super::LogCompression>,
+    pub zstd_level: Option<i32>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct JemallocConfig {
+    pub background_threads: Option<bool>,
+    pub max_background_threads: Option<usize>,
+    #[serde(default, with = "humantime_serde")]
+    pub dirty_decay: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    pub muzzy_decay: Option<Duration>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProfilingConfig {
+    pub enabled: Option<bool>,
+    #[serde(default, with = "humantime_serde")]
+    pub sample_interval: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    pub retention: Option<Duration>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Http2Config {
+    pub max_concurrent_streams: Option<u32>,
+    pub initial_stream_window_size: Option<u32>,
+    pub initial_connection_window_size: Option<u32>,
+    pub max_frame_size: Option<u32>,
+    #[serde(default, with = "humantime_serde")]
+    pub keep_alive_interval: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    pub keep_alive_timeout: Option<Duration>,
 }
 
 #[derive(Deserialize)]
@@ -49,12 +158,29 @@ pub struct ServerConfig {
     pub min_threads: Option<usize>,
     pub max_threads: Option<usize>,
     pub max_connections: Option<usize>,
+    pub max_file_descriptor_utilization: Option<f64>,
     pub io_threads: Option<usize>,
     #[serde(default, with = "humantime_serde")]
     pub idle_thread_timeout: Option<Duration>,
     pub shutdown_timeout: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    pub unhealthy_deregistration_delay: Option<Duration>,
     pub gzip: Option<bool>,
+    pub metric_aliases: Option<bool>,
     pub http2: Option<bool>,
+    pub http2_settings: Option<super::Http2Config>,
+    pub strict_http_parsing: Option<bool>,
     #[serde(default, with = "humantime_serde")]
     pub idle_connection_timeout: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    pub request_body_read_timeout: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    pub response_write_timeout: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    pub header_read_timeout: Option<Duration>,
+    pub min_request_body_rate: Option<u64>,
+    #[serde(default, with = "humantime_serde")]
+    pub min_request_body_rate_grace_period: Option<Duration>,
+    pub max_concurrent_jobs: Option<usize>,
+    pub bind_addresses: Option<Vec<IpAddr>>,
 }
@@ -17,11 +17,16 @@ use serde::de::Error;
 use serde::{Deserialize, Deserializer};
 use staged_builder::{staged_builder, Validate};
 use std::env;
+use std::net::{IpAddr, Ipv4Addr};
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 mod de;
 
+const COMBINED_LOG_FORMAT: &str = r#"%h %l %u %t "%r" %>s %b "%{Referer}i" "%{User-Agent}i""#;
+
+const MAX_TICKET_LIFETIME: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
 /// The fixed configuration for a Witchcraft server.
 #[derive(Clone, PartialEq, Debug)]
 #[staged_builder]
@@ -34,34 +39,182 @@ pub struct InstallConfig {
     port: u16,
     #[builder(default, into)]
     management_port: Option<u16>,
+    #[builder(default, into)]
+    http_redirect_port: Option<u16>,
     #[builder(default)]
     keystore: KeystoreConfig,
     #[builder(default, into)]
     client_auth_truststore: Option<ClientAuthTruststoreConfig>,
+    #[builder(default, into)]
+    acme: Option<AcmeConfig>,
+    #[builder(default)]
+    tls: TlsConfig,
     #[builder(into, default = "/".to_string())]
     context_path: String,
+    #[builder(list(item(type = String, into)))]
+    context_path_aliases: Vec<String>,
+    #[builder(default, into)]
+    canonical_url: Option<String>,
     #[builder(default = env::var_os("CONTAINER").is_some())]
     use_console_log: bool,
     #[builder(default)]
     server: ServerConfig,
+    #[builder(default)]
+    access_log: AccessLogConfig,
+    #[builder(default)]
+    log_compression: LogCompressionConfig,
+    #[builder(default)]
+    jemalloc: JemallocConfig,
+    #[builder(default)]
+    profiling: ProfilingConfig,
 }
 
 impl Validate for InstallConfig {
     type Error = ConfigError;
 
     fn validate(&self) -> Result<(), Self::Error> {
-        if !(self.context_path == "/"
-            || (self.context_path.starts_with('/') && !self.context_path.ends_with('/')))
-        {
+        if !valid_context_path(&self.context_path) {
             return Err(ConfigError(
                 "context-path must either be `/` or start but not end with a `/`".to_string(),
             ));
         }
 
+        for alias in &self.context_path_aliases {
+            if !valid_context_path(alias) {
+                return Err(ConfigError(
+                    "context-path-aliases must either be `/` or start but not end with a `/`"
+                        .to_string(),
+                ));
+            }
+            if *alias == self.context_path {
+                return Err(ConfigError(
+                    "context-path-aliases must not contain the context-path".to_string(),
+                ));
+            }
+        }
+
+        if let Some(canonical_url) = &self.canonical_url {
+            let Some((scheme, rest)) = canonical_url.split_once("://") else {
+                return Err(ConfigError(
+                    "canonical-url must be an absolute URL".to_string(),
+                ));
+            };
+            if !(scheme == "http" || scheme == "https") {
+                return Err(ConfigError(
+                    "canonical-url must have an `http` or `https` scheme".to_string(),
+                ));
+            }
+            if rest.is_empty() || rest.starts_with('/') || rest.contains(['?', '#']) {
+                return Err(ConfigError(
+                    "canonical-url must have a host and no query or fragment".to_string(),
+                ));
+            }
+        }
+
+        if self.server.max_concurrent_jobs == 0 {
+            return Err(ConfigError(
+                "server.max-concurrent-jobs must be positive".to_string(),
+            ));
+        }
+
+        if self.server.min_request_body_rate == Some(0) {
+            return Err(ConfigError(
+                "server.min-request-body-rate must be positive".to_string(),
+            ));
+        }
+
+        if self.server.bind_addresses.is_empty() {
+            return Err(ConfigError(
+                "server.bind-addresses must not be empty".to_string(),
+            ));
+        }
+
+        if let Some(acme) = &self.acme {
+            if acme.domains.is_empty() {
+                return Err(ConfigError("acme.domains must not be empty".to_string()));
+            }
+        }
+
+        if self.tls.protocol_versions.is_empty() {
+            return Err(ConfigError(
+                "tls.protocol-versions must not be empty".to_string(),
+            ));
+        }
+
+        if !self.tls.cipher_suites.is_empty() {
+            for version in &self.tls.protocol_versions {
+                if !self
+                    .tls
+                    .cipher_suites
+                    .iter()
+                    .any(|s| s.protocol_version() == *version)
+                {
+                    let version = match version {
+                        TlsVersion::Tls12 => "TLSv1.2",
+                        TlsVersion::Tls13 => "TLSv1.3",
+                    };
+                    return Err(ConfigError(format!(
+                        "tls.cipher-suites must contain a {version} cipher suite when {version} is enabled"
+                    )));
+                }
+            }
+        }
+
+        if let Some(http_redirect_port) = self.http_redirect_port {
+            if http_redirect_port == self.port || Some(http_redirect_port) == self.management_port {
+                return Err(ConfigError(
+                    "http-redirect-port must differ from port and management-port".to_string(),
+                ));
+            }
+        }
+
+        if self.tls.ocsp_stapling.refresh_interval.is_zero() {
+            return Err(ConfigError(
+                "tls.ocsp-stapling.refresh-interval must be positive".to_string(),
+            ));
+        }
+
+        if self.tls.fips && self.tls.crypto_provider != CryptoProvider::AwsLcRs {
+            return Err(ConfigError(
+                "tls.fips requires the aws-lc-rs tls.crypto-provider".to_string(),
+            ));
+        }
+
+        let session_resumption = &self.tls.session_resumption;
+        if session_resumption.ticket_lifetime.is_zero()
+            || session_resumption.ticket_lifetime > MAX_TICKET_LIFETIME
+        {
+            return Err(ConfigError(
+                "tls.session-resumption.ticket-lifetime must be positive and at most 7 days"
+                    .to_string(),
+            ));
+        }
+        if session_resumption.ticket_key_rotation.is_zero() {
+            return Err(ConfigError(
+                "tls.session-resumption.ticket-key-rotation must be positive".to_string(),
+            ));
+        }
+
+        if self.profiling.sample_interval.is_zero() {
+            return Err(ConfigError(
+                "profiling.sample-interval must be positive".to_string(),
+            ));
+        }
+
+        if !(1..=19).contains(&self.log_compression.zstd_level) {
+            return Err(ConfigError(
+                "log-compression.zstd-level must be between 1 and 19".to_string(),
+            ));
+        }
+
         Ok(())
     }
 }
 
+fn valid_context_path(path: &str) -> bool {
+    path == "/" || (path.starts_with('/') && !path.ends_with('/'))
+}
+
 impl<'de> Deserialize<'de> for InstallConfig {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -75,21 +228,48 @@ impl<'de> Deserialize<'de> for InstallConfig {
         if let Some(management_port) = raw.management_port {
             builder = builder.management_port(management_port);
         }
+        if let Some(http_redirect_port) = raw.http_redirect_port {
+            builder = builder.http_redirect_port(http_redirect_port);
+        }
         if let Some(keystore) = raw.keystore {
             builder = builder.keystore(keystore);
         }
         if let Some(client_auth_truststore) = raw.client_auth_truststore {
             builder = builder.client_auth_truststore(client_auth_truststore);
         }
+        if let Some(acme) = raw.acme {
+            builder = builder.acme(acme);
+        }
+        if let Some(tls) = raw.tls {
+            builder = builder.tls(tls);
+        }
         if let Some(context_path) = raw.context_path {
             builder = builder.context_path(context_path);
         }
+        if let Some(context_path_aliases) = raw.context_path_aliases {
+            builder = builder.context_path_aliases(context_path_aliases);
+        }
+        if let Some(canonical_url) = raw.canonical_url {
+            builder = builder.canonical_url(canonical_url);
+        }
         if let Some(use_console_log) = raw.use_console_log {
             builder = builder.use_console_log(use_console_log);
         }
         if let Some(server) = raw.server {
             builder = builder.server(server);
         }
+        if let Some(access_log) = raw.access_log {
+            builder = builder.access_log(access_log);
+        }
+        if let Some(log_compression) = raw.log_compression {
+            builder = builder.log_compression(log_compression);
+        }
+        if let Some(jemalloc) = raw.jemalloc {
+            builder = builder.jemalloc(jemalloc);
+        }
+        if let Some(profiling) = raw.profiling {
+            builder = builder.profiling(profiling);
+        }
 
         builder.build().map_err(Error::custom)
     }
@@ -121,6 +301,8 @@ impl InstallConfig {
 
     /// Returns the port the server will listen on.
     ///
+    /// If `0`, the server will listen on an ephemeral port assigned by the operating system.
+    ///
     /// Required.
     #[inline]
     pub fn port(&self) -> u16 {
@@ -129,12 +311,29 @@ impl InstallConfig {
 
     /// Returns the port that the server's management APIs will listen on.
     ///
+    /// If `0`, the management APIs will listen on an ephemeral port assigned by the operating system, separate from
+    /// the service port.
+    ///
     /// Defaults to `port()`.
     pub fn management_port(&self) -> Option<u16> {
         self.management_port
     }
 
+    /// Returns the port of a plaintext HTTP listener which redirects requests to the service port.
+    ///
+    /// Requests to the listener receive a `301 Moved Permanently` response pointing at the same host, path, and query
+    /// over HTTPS, so browsers navigating to the server's bare hostname are sent to the right place rather than having
+    /// their connection reset. It must differ from `port()` and `management_port()`.
+    ///
+    /// Defaults to `None`, which disables the listener.
+    pub fn http_redirect_port(&self) -> Option<u16> {
+        self.http_redirect_port
+    }
+
     /// Returns the server's TLS key configuration.
+    ///
+    /// The key and certificate files are checked for changes every 10 seconds. When they change, new connections will
+    /// use the updated certificate while existing connections are unaffected.
     #[inline]
     pub fn keystore(&self) -> &KeystoreConfig {
         &self.keystore
@@ -142,9 +341,9 @@ impl InstallConfig {
 
     /// Returns the server's TLS client authentication truststore configuration.
     ///
-    /// If set, the server will request (but not require) the client to authenticate itself during the TLS handshake.
-    /// If a certificate is present and validated against the trust roots, all requests made over that connection will
-    /// include a `ClientCertificate` extension.
+    /// If set, the server will request the client to authenticate itself during the TLS handshake, as determined by
+    /// [`ClientAuthTruststoreConfig::client_auth`]. If a certificate is present and validated against the trust roots,
+    /// all requests made over that connection will include a `ClientCertificate` extension.
     ///
     /// Defaults to `None`.
     #[inline]
@@ -152,6 +351,24 @@ impl InstallConfig {
         self.client_auth_truststore.as_ref()
     }
 
+    /// Returns the server's ACME automatic certificate configuration.
+    ///
+    /// If set, the server obtains and renews its certificate from an ACME certificate authority like Let's Encrypt,
+    /// writing the key and certificate to the paths in [`Self::keystore`]. Requires the `acme` cargo feature of the
+    /// `witchcraft-server` crate.
+    ///
+    /// Defaults to `None`.
+    #[inline]
+    pub fn acme(&self) -> Option<&AcmeConfig> {
+        self.acme.as_ref()
+    }
+
+    /// Returns the server's TLS protocol configuration.
+    #[inline]
+    pub fn tls(&self) -> &TlsConfig {
+        &self.tls
+    }
+
     /// Returns the server's context path.
     ///
     /// This must either be equal to `/` or start but not end with a `/`.
@@ -162,6 +379,25 @@ impl InstallConfig {
         &self.context_path
     }
 
+    /// Returns additional context paths the server's endpoints are registered under.
+    ///
+    /// This supports renaming a service without breaking existing callers: each endpoint is also available under each
+    /// alias, and requests made via an alias are counted in the `server.request.context-path-alias` metric. Aliases
+    /// follow the same rules as the context path.
+    #[inline]
+    pub fn context_path_aliases(&self) -> &[String] {
+        &self.context_path_aliases
+    }
+
+    /// Returns the externally visible URL of the server's root, such as `https://api.example.com/foo`.
+    ///
+    /// If set, it is used in place of a request's forwarded and `Host` headers when computing the `BaseUrl` request
+    /// extension. The context path is appended to it.
+    #[inline]
+    pub fn canonical_url(&self) -> Option<&str> {
+        self.canonical_url.as_deref()
+    }
+
     /// If `true`, the server will log to standard output rather than to files.
     ///
     /// Defaults to `true` if running in a container and false otherwise.
@@ -175,6 +411,30 @@ impl InstallConfig {
     pub fn server(&self) -> &ServerConfig {
         &self.server
     }
+
+    /// Returns the server's access log configuration.
+    #[inline]
+    pub fn access_log(&self) -> &AccessLogConfig {
+        &self.access_log
+    }
+
+    /// Returns the server's log compression configuration.
+    #[inline]
+    pub fn log_compression(&self) -> &LogCompressionConfig {
+        &self.log_compression
+    }
+
+    /// Returns the server's jemalloc configuration.
+    #[inline]
+    pub fn jemalloc(&self) -> &JemallocConfig {
+        &self.jemalloc
+    }
+
+    /// Returns the server's background profiling configuration.
+    #[inline]
+    pub fn profiling(&self) -> &ProfilingConfig {
+        &self.profiling
+    }
 }
 
 /// TLS key configuration.
@@ -238,6 +498,8 @@ impl KeystoreConfig {
 pub struct ClientAuthTruststoreConfig {
     #[builder(into, default = PathBuf::from("var/security/ca.cer"))]
     path: PathBuf,
+    #[builder(default)]
+    client_auth: ClientAuth,
 }
 
 impl Default for ClientAuthTruststoreConfig {
@@ -257,6 +519,9 @@ impl<'de> Deserialize<'de> for ClientAuthTruststoreConfig {
         if let Some(path) = raw.path {
             builder = builder.path(path);
         }
+        if let Some(client_auth) = raw.client_auth {
+            builder = builder.client_auth(client_auth);
+        }
         Ok(builder.build())
     }
 }
@@ -270,167 +535,1391 @@ impl ClientAuthTruststoreConfig {
     pub fn path(&self) -> &Path {
         &self.path
     }
+
+    /// Returns whether clients are required to present a certificate.
+    ///
+    /// Defaults to [`ClientAuth::Optional`].
+    #[inline]
+    pub fn client_auth(&self) -> ClientAuth {
+        self.client_auth
+    }
 }
 
-/// Advanced server configuration.
+/// The TLS client authentication mode.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum ClientAuth {
+    /// Clients must present a certificate signed by one of the trust roots, and connections without one are
+    /// rejected during the handshake.
+    Required,
+    /// Clients are asked for a certificate, but connections without one are still accepted. A certificate that is
+    /// presented must be signed by one of the trust roots.
+    #[default]
+    Optional,
+    /// Clients are not asked for a certificate, and the trust roots are ignored.
+    None,
+}
+
+/// TLS protocol configuration.
+///
+/// This can be used to restrict the server to the protocol versions and algorithms required by a compliance regime.
+/// The server prefers cipher suites and key exchange groups in the order they are listed, regardless of the client's
+/// preferences.
 #[derive(Clone, PartialEq, Debug)]
 #[staged_builder]
-pub struct ServerConfig {
-    #[builder(default = num_cpus::get())]
-    processors: usize,
-    #[builder(default, custom(type = usize, convert = Some))]
-    min_threads: Option<usize>,
-    #[builder(default, custom(type = usize, convert = Some))]
-    max_threads: Option<usize>,
-    #[builder(default, custom(type = usize, convert = Some))]
-    max_connections: Option<usize>,
-    #[builder(default, custom(type = usize, convert = Some))]
-    io_threads: Option<usize>,
-    #[builder(default = Duration::from_secs(5 * 60))]
-    idle_thread_timeout: Duration,
-    #[builder(default = Duration::from_secs(15))]
-    shutdown_timeout: Duration,
-    #[builder(default = true)]
-    gzip: bool,
+pub struct TlsConfig {
+    #[builder(default = vec![TlsVersion::Tls12, TlsVersion::Tls13])]
+    protocol_versions: Vec<TlsVersion>,
+    #[builder(list(item(type = CipherSuite)))]
+    cipher_suites: Vec<CipherSuite>,
+    #[builder(list(item(type = KeyExchangeGroup)))]
+    key_exchange_groups: Vec<KeyExchangeGroup>,
+    #[builder(default)]
+    ocsp_stapling: OcspStaplingConfig,
+    #[builder(default)]
+    session_resumption: SessionResumptionConfig,
+    #[builder(default = env::var_os("SSLKEYLOGFILE").map(PathBuf::from), into)]
+    key_log_file: Option<PathBuf>,
+    #[builder(default)]
+    crypto_provider: CryptoProvider,
     #[builder(default = false)]
-    http2: bool,
-    #[builder(default, into)]
-    idle_connection_timeout: Option<Duration>,
+    fips: bool,
 }
 
-impl Default for ServerConfig {
+impl Default for TlsConfig {
     #[inline]
     fn default() -> Self {
-        ServerConfig::builder().build()
+        TlsConfig::builder().build()
     }
 }
 
-impl<'de> Deserialize<'de> for ServerConfig {
+impl<'de> Deserialize<'de> for TlsConfig {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        let raw = de::ServerConfig::deserialize(deserializer)?;
-        let mut builder = ServerConfig::builder();
-        if let Some(processors) = raw.processors {
-            builder = builder.processors(processors);
-        }
-        if let Some(min_threads) = raw.min_threads {
-            builder = builder.min_threads(min_threads);
-        }
-        if let Some(max_threads) = raw.max_threads {
-            builder = builder.max_threads(max_threads);
+        let raw = de::TlsConfig::deserialize(deserializer)?;
+        let mut builder = TlsConfig::builder();
+        if let Some(protocol_versions) = raw.protocol_versions {
+            builder = builder.protocol_versions(protocol_versions);
         }
-        if let Some(max_connections) = raw.max_connections {
-            builder = builder.max_connections(max_connections);
+        if let Some(cipher_suites) = raw.cipher_suites {
+            builder = builder.cipher_suites(cipher_suites);
         }
-        if let Some(io_threads) = raw.io_threads {
-            builder = builder.io_threads(io_threads);
+        if let Some(key_exchange_groups) = raw.key_exchange_groups {
+            builder = builder.key_exchange_groups(key_exchange_groups);
         }
-        if let Some(idle_thread_timeout) = raw.idle_thread_timeout {
-            builder = builder.idle_thread_timeout(idle_thread_timeout);
+        if let Some(ocsp_stapling) = raw.ocsp_stapling {
+            builder = builder.ocsp_stapling(ocsp_stapling);
         }
-        if let Some(shutdown_timeout) = raw.shutdown_timeout {
-            builder = builder.shutdown_timeout(shutdown_timeout);
+        if let Some(session_resumption) = raw.session_resumption {
+            builder = builder.session_resumption(session_resumption);
         }
-        if let Some(gzip) = raw.gzip {
-            builder = builder.gzip(gzip);
+        if let Some(key_log_file) = raw.key_log_file {
+            builder = builder.key_log_file(key_log_file);
         }
-        if let Some(http2) = raw.http2 {
-            builder = builder.http2(http2);
+        if let Some(crypto_provider) = raw.crypto_provider {
+            builder = builder.crypto_provider(crypto_provider);
         }
-        if let Some(idle_connection_timeout) = raw.idle_connection_timeout {
-            builder = builder.idle_connection_timeout(idle_connection_timeout);
+        if let Some(fips) = raw.fips {
+            builder = builder.fips(fips);
         }
-
         Ok(builder.build())
     }
 }
 
-impl ServerConfig {
-    /// Returns the number of processors the server is allocated.
+impl TlsConfig {
+    /// Returns the TLS protocol versions the server will negotiate.
     ///
-    /// This is only used to derive default values for other settings in this type.
+    /// Must not be empty.
     ///
-    /// Defaults to the number of logical CPUs.
+    /// Defaults to TLS 1.2 and TLS 1.3.
     #[inline]
-    pub fn processors(&self) -> usize {
-        self.processors
+    pub fn protocol_versions(&self) -> &[TlsVersion] {
+        &self.protocol_versions
     }
 
-    /// Returns the minimum number of threads in the pool used to process blocking endpoints.
+    /// Returns the cipher suites the server will negotiate, in order of preference.
     ///
-    /// Defaults to 8 times the number of processors.
+    /// If set, it must contain at least one cipher suite for each enabled protocol version.
+    ///
+    /// Defaults to all supported cipher suites.
     #[inline]
-    pub fn min_threads(&self) -> usize {
-        self.min_threads.unwrap_or(self.processors * 8)
+    pub fn cipher_suites(&self) -> &[CipherSuite] {
+        &self.cipher_suites
     }
 
-    /// Returns the maximum number of threads in the pool used to process blocking endpoints.
+    /// Returns the key exchange groups the server will negotiate, in order of preference.
     ///
-    /// Defaults to the maximum of 32 times the number of processors and 256.
+    /// Defaults to all supported key exchange groups.
     #[inline]
-    pub fn max_threads(&self) -> usize {
-        self.max_threads
-            .unwrap_or_else(|| usize::max(self.processors * 32, 256))
+    pub fn key_exchange_groups(&self) -> &[KeyExchangeGroup] {
+        &self.key_exchange_groups
     }
 
-    /// Returns the maximum number of live TCP connections the server will allow at any time.
-    ///
-    /// Defaults to 10 times the value of [`Self::max_threads`].
+    /// Returns the server's OCSP stapling configuration.
     #[inline]
-    pub fn max_connections(&self) -> usize {
-        self.max_connections
-            .unwrap_or_else(|| self.max_threads() * 10)
+    pub fn ocsp_stapling(&self) -> &OcspStaplingConfig {
+        &self.ocsp_stapling
     }
 
-    /// Returns the number of threads used for nonblocking operations in the server's Tokio runtime.
-    ///
-    /// Defaults to half the number of processors.
+    /// Returns the server's TLS session resumption configuration.
     #[inline]
-    pub fn io_threads(&self) -> usize {
-        self.io_threads
-            .unwrap_or_else(|| usize::max(1, self.processors / 2))
+    pub fn session_resumption(&self) -> &SessionResumptionConfig {
+        &self.session_resumption
     }
 
-    /// Returns the amount of time a thread in the blocking request pool will sit idle before shutting down.
+    /// Returns the path of a file to which TLS session secrets are written in the `SSLKEYLOGFILE` format.
     ///
-    /// Defaults to 5 minutes.
+    /// The file allows anyone who can read it to decrypt captured traffic, so it should only be set temporarily while
+    /// debugging TLS issues.
+    ///
+    /// Defaults to the value of the `SSLKEYLOGFILE` environment variable, or `None` if it is not set.
     #[inline]
-    pub fn idle_thread_timeout(&self) -> Duration {
-        self.idle_thread_timeout
+    pub fn key_log_file(&self) -> Option<&Path> {
+        self.key_log_file.as_deref()
     }
 
-    /// Returns the amount of time the server will wait for pending requests to complete when shutting down.
+    /// Returns the cryptography library used for TLS.
     ///
-    /// Defaults to 15 seconds.
+    /// Providers other than the default must be enabled with the corresponding cargo feature of the `witchcraft-server`
+    /// crate, and the server will fail to start if the configured provider was not compiled in.
+    ///
+    /// Defaults to [`CryptoProvider::AwsLcRs`].
     #[inline]
-    pub fn shutdown_timeout(&self) -> Duration {
-        self.shutdown_timeout
+    pub fn crypto_provider(&self) -> CryptoProvider {
+        self.crypto_provider
     }
 
-    /// Determines if responses larger than 1 MiB will be compressed with gzip.
+    /// Returns if the server only uses FIPS-approved cryptography for TLS.
     ///
-    /// Defaults to `true`.
+    /// When enabled, the server uses AWS-LC's FIPS 140-3 validated module, and cipher suites and key exchange groups
+    /// which are not FIPS-approved are not negotiated. It requires the `fips` cargo feature of the `witchcraft-server`
+    /// crate and the [`CryptoProvider::AwsLcRs`] provider.
+    ///
+    /// Defaults to `false`.
     #[inline]
-    pub fn gzip(&self) -> bool {
-        self.gzip
+    pub fn fips(&self) -> bool {
+        self.fips
     }
+}
 
-    /// Determines if the server will support the HTTP2 protocol.
+/// OCSP stapling configuration.
+///
+/// When enabled, the server fetches an OCSP response for its certificate from the responder listed in the
+/// certificate's Authority Information Access extension and includes it in TLS handshakes. The certificate file must
+/// contain the issuer's certificate after the server's certificate.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+pub struct OcspStaplingConfig {
+    #[builder(default = false)]
+    enabled: bool,
+    #[builder(default = Duration::from_secs(60 * 60))]
+    refresh_interval: Duration,
+    #[builder(default = Duration::from_secs(10))]
+    timeout: Duration,
+}
+
+impl Default for OcspStaplingConfig {
+    #[inline]
+    fn default() -> Self {
+        OcspStaplingConfig::builder().build()
+    }
+}
+
+impl<'de> Deserialize<'de> for OcspStaplingConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::OcspStaplingConfig::deserialize(deserializer)?;
+        let mut builder = OcspStaplingConfig::builder();
+        if let Some(enabled) = raw.enabled {
+            builder = builder.enabled(enabled);
+        }
+        if let Some(refresh_interval) = raw.refresh_interval {
+            builder = builder.refresh_interval(refresh_interval);
+        }
+        if let Some(timeout) = raw.timeout {
+            builder = builder.timeout(timeout);
+        }
+        Ok(builder.build())
+    }
+}
+
+impl OcspStaplingConfig {
+    /// Returns if OCSP stapling is enabled.
     ///
     /// Defaults to `false`.
     #[inline]
-    pub fn http2(&self) -> bool {
-        self.http2
+    pub fn enabled(&self) -> bool {
+        self.enabled
     }
 
-    /// Returns the amount of time the server allows TCP connections to remain idle before shutting them down.
+    /// Returns the interval at which the OCSP response is refreshed.
     ///
-    /// If `None`, defaults to 1 minute. If `Some`, the time will be included in HTTP responses in a `Keep-Alive`
-    /// header.
+    /// Failed refreshes are retried every minute.
+    ///
+    /// Defaults to 1 hour.
     #[inline]
-    pub fn idle_connection_timeout(&self) -> Option<Duration> {
-        self.idle_connection_timeout
+    pub fn refresh_interval(&self) -> Duration {
+        self.refresh_interval
+    }
+
+    /// Returns the timeout for requests to the OCSP responder.
+    ///
+    /// Defaults to 10 seconds.
+    #[inline]
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+/// TLS session resumption configuration.
+///
+/// Resumption allows a client reconnecting to the server to skip the expensive parts of the TLS handshake. Sessions
+/// are resumed with tickets encrypted by keys held in memory, which are rotated periodically. Longer ticket lifetimes
+/// and less frequent key rotations make resumption more effective, at the cost of forward secrecy: anyone who obtains
+/// a ticket key can decrypt the sessions resumed with tickets it issued.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+pub struct SessionResumptionConfig {
+    #[builder(default = true)]
+    enabled: bool,
+    #[builder(default = Duration::from_secs(6 * 60 * 60))]
+    ticket_lifetime: Duration,
+    #[builder(default = Duration::from_secs(6 * 60 * 60))]
+    ticket_key_rotation: Duration,
+}
+
+impl Default for SessionResumptionConfig {
+    #[inline]
+    fn default() -> Self {
+        SessionResumptionConfig::builder().build()
+    }
+}
+
+impl<'de> Deserialize<'de> for SessionResumptionConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::SessionResumptionConfig::deserialize(deserializer)?;
+        let mut builder = SessionResumptionConfig::builder();
+        if let Some(enabled) = raw.enabled {
+            builder = builder.enabled(enabled);
+        }
+        if let Some(ticket_lifetime) = raw.ticket_lifetime {
+            builder = builder.ticket_lifetime(ticket_lifetime);
+        }
+        if let Some(ticket_key_rotation) = raw.ticket_key_rotation {
+            builder = builder.ticket_key_rotation(ticket_key_rotation);
+        }
+        Ok(builder.build())
+    }
+}
+
+impl SessionResumptionConfig {
+    /// Returns if TLS session resumption is enabled.
+    ///
+    /// If `false`, every connection performs a full handshake.
+    ///
+    /// Defaults to `true`.
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns how long a session ticket can be used to resume a session after it is issued.
+    ///
+    /// Must be positive and at most 7 days.
+    ///
+    /// Defaults to 6 hours.
+    #[inline]
+    pub fn ticket_lifetime(&self) -> Duration {
+        self.ticket_lifetime
+    }
+
+    /// Returns the interval at which the key used to encrypt session tickets is replaced.
+    ///
+    /// Tickets encrypted with the previous key are still accepted, but tickets encrypted with older keys are not,
+    /// regardless of their lifetime.
+    ///
+    /// Defaults to 6 hours.
+    #[inline]
+    pub fn ticket_key_rotation(&self) -> Duration {
+        self.ticket_key_rotation
+    }
+}
+
+/// A TLS protocol version.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Deserialize)]
+#[non_exhaustive]
+pub enum TlsVersion {
+    /// TLS 1.2.
+    #[serde(rename = "TLSv1.2")]
+    Tls12,
+    /// TLS 1.3.
+    #[serde(rename = "TLSv1.3")]
+    Tls13,
+}
+
+/// A TLS cipher suite, identified by its IANA name.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Deserialize)]
+#[non_exhaustive]
+pub enum CipherSuite {
+    /// `TLS_AES_256_GCM_SHA384` (TLS 1.3).
+    #[serde(rename = "TLS_AES_256_GCM_SHA384")]
+    Aes256GcmSha384,
+    /// `TLS_AES_128_GCM_SHA256` (TLS 1.3).
+    #[serde(rename = "TLS_AES_128_GCM_SHA256")]
+    Aes128GcmSha256,
+    /// `TLS_CHACHA20_POLY1305_SHA256` (TLS 1.3).
+    #[serde(rename = "TLS_CHACHA20_POLY1305_SHA256")]
+    Chacha20Poly1305Sha256,
+    /// `TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384` (TLS 1.2).
+    #[serde(rename = "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384")]
+    EcdheEcdsaWithAes256GcmSha384,
+    /// `TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256` (TLS 1.2).
+    #[serde(rename = "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256")]
+    EcdheEcdsaWithAes128GcmSha256,
+    /// `TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256` (TLS 1.2).
+    #[serde(rename = "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256")]
+    EcdheEcdsaWithChacha20Poly1305Sha256,
+    /// `TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384` (TLS 1.2).
+    #[serde(rename = "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384")]
+    EcdheRsaWithAes256GcmSha384,
+    /// `TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256` (TLS 1.2).
+    #[serde(rename = "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256")]
+    EcdheRsaWithAes128GcmSha256,
+    /// `TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256` (TLS 1.2).
+    #[serde(rename = "TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256")]
+    EcdheRsaWithChacha20Poly1305Sha256,
+}
+
+impl CipherSuite {
+    /// Returns the protocol version the cipher suite is used with.
+    pub fn protocol_version(&self) -> TlsVersion {
+        match self {
+            CipherSuite::Aes256GcmSha384
+            | CipherSuite::Aes128GcmSha256
+            | CipherSuite::Chacha20Poly1305Sha256 => TlsVersion::Tls13,
+            CipherSuite::EcdheEcdsaWithAes256GcmSha384
+            | CipherSuite::EcdheEcdsaWithAes128GcmSha256
+            | CipherSuite::EcdheEcdsaWithChacha20Poly1305Sha256
+            | CipherSuite::EcdheRsaWithAes256GcmSha384
+            | CipherSuite::EcdheRsaWithAes128GcmSha256
+            | CipherSuite::EcdheRsaWithChacha20Poly1305Sha256 => TlsVersion::Tls12,
+        }
+    }
+}
+
+/// A TLS key exchange group.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum KeyExchangeGroup {
+    /// The NIST P-256 elliptic curve.
+    Secp256r1,
+    /// The NIST P-384 elliptic curve.
+    Secp384r1,
+    /// Curve25519.
+    X25519,
+}
+
+/// ACME automatic certificate configuration.
+///
+/// The server's certificate is obtained on startup if the key or certificate file is missing or self-signed, and
+/// renewed when it nears expiration. Until the first certificate is issued, the server presents a self-signed
+/// placeholder.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+pub struct AcmeConfig {
+    #[builder(into, default = "https://acme-v02.api.letsencrypt.org/directory".to_string())]
+    directory_url: String,
+    #[builder(list(item(type = String, into)))]
+    domains: Vec<String>,
+    #[builder(list(item(type = String, into)))]
+    contacts: Vec<String>,
+    #[builder(default)]
+    challenge: AcmeChallenge,
+    #[builder(default = 80)]
+    http01_port: u16,
+    #[builder(default = Duration::from_secs(30 * 24 * 60 * 60))]
+    renew_before: Duration,
+    #[builder(into, default = PathBuf::from("var/security/acme-account.json"))]
+    account_path: PathBuf,
+    #[builder(default)]
+    agree_to_terms_of_service: bool,
+}
+
+impl<'de> Deserialize<'de> for AcmeConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::AcmeConfig::deserialize(deserializer)?;
+        let mut builder = AcmeConfig::builder().domains(raw.domains);
+        if let Some(directory_url) = raw.directory_url {
+            builder = builder.directory_url(directory_url);
+        }
+        if let Some(contacts) = raw.contacts {
+            builder = builder.contacts(contacts);
+        }
+        if let Some(challenge) = raw.challenge {
+            builder = builder.challenge(challenge);
+        }
+        if let Some(http01_port) = raw.http01_port {
+            builder = builder.http01_port(http01_port);
+        }
+        if let Some(renew_before) = raw.renew_before {
+            builder = builder.renew_before(renew_before);
+        }
+        if let Some(account_path) = raw.account_path {
+            builder = builder.account_path(account_path);
+        }
+        if let Some(agree_to_terms_of_service) = raw.agree_to_terms_of_service {
+            builder = builder.agree_to_terms_of_service(agree_to_terms_of_service);
+        }
+        Ok(builder.build())
+    }
+}
+
+impl AcmeConfig {
+    /// Returns the URL of the ACME certificate authority's directory.
+    ///
+    /// Defaults to the Let's Encrypt production directory, `https://acme-v02.api.letsencrypt.org/directory`.
+    #[inline]
+    pub fn directory_url(&self) -> &str {
+        &self.directory_url
+    }
+
+    /// Returns the DNS names the certificate is issued for.
+    ///
+    /// Required.
+    #[inline]
+    pub fn domains(&self) -> &[String] {
+        &self.domains
+    }
+
+    /// Returns the contact URIs registered with the ACME account, e.g. `mailto:ops@example.com`.
+    ///
+    /// Defaults to an empty list.
+    #[inline]
+    pub fn contacts(&self) -> &[String] {
+        &self.contacts
+    }
+
+    /// Returns the challenge type used to prove control of the domains.
+    ///
+    /// Defaults to [`AcmeChallenge::TlsAlpn01`].
+    #[inline]
+    pub fn challenge(&self) -> AcmeChallenge {
+        self.challenge
+    }
+
+    /// Returns the port the server listens on for HTTP-01 challenge requests.
+    ///
+    /// The certificate authority always connects to port 80, so this should only be changed if another port is
+    /// forwarded to it. The listener only runs while a challenge is in progress.
+    ///
+    /// Defaults to 80.
+    #[inline]
+    pub fn http01_port(&self) -> u16 {
+        self.http01_port
+    }
+
+    /// Returns how long before the certificate expires it is renewed.
+    ///
+    /// Defaults to 30 days.
+    #[inline]
+    pub fn renew_before(&self) -> Duration {
+        self.renew_before
+    }
+
+    /// Returns the path to the file the ACME account credentials are stored in.
+    ///
+    /// Defaults to `var/security/acme-account.json`.
+    #[inline]
+    pub fn account_path(&self) -> &Path {
+        &self.account_path
+    }
+
+    /// Determines if the operator agrees to the certificate authority's terms of service.
+    ///
+    /// Certificate authorities require their terms of service to be agreed to before creating an account, so the
+    /// server will not start with ACME configured unless this is `true`.
+    ///
+    /// Defaults to `false`.
+    #[inline]
+    pub fn agree_to_terms_of_service(&self) -> bool {
+        self.agree_to_terms_of_service
+    }
+}
+
+/// A cryptography library used for TLS.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Deserialize)]
+#[non_exhaustive]
+pub enum CryptoProvider {
+    /// AWS-LC, via the `aws-lc-rs` crate.
+    ///
+    /// Requires the `aws-lc-rs` cargo feature of the `witchcraft-server` crate, which is enabled by default.
+    #[default]
+    #[serde(rename = "aws-lc-rs")]
+    AwsLcRs,
+    /// The `ring` crate.
+    ///
+    /// Requires the `ring` cargo feature of the `witchcraft-server` crate.
+    #[serde(rename = "ring")]
+    Ring,
+}
+
+/// An ACME challenge type.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Deserialize)]
+#[non_exhaustive]
+pub enum AcmeChallenge {
+    /// The certificate authority makes a TLS connection to the server and negotiates the `acme-tls/1` ALPN protocol.
+    ///
+    /// The certificate authority always connects to port 443, so the server's port must be reachable there.
+    #[default]
+    #[serde(rename = "tls-alpn-01")]
+    TlsAlpn01,
+    /// The certificate authority makes an HTTP request to port 80.
+    #[serde(rename = "http-01")]
+    Http01,
+}
+
+/// Access log configuration.
+///
+/// The access log is written in addition to the standard `request.2` log for use with tooling that expects a
+/// traditional text-based format.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+pub struct AccessLogConfig {
+    #[builder(default = false)]
+    enabled: bool,
+    #[builder(into, default = COMBINED_LOG_FORMAT.to_string())]
+    format: String,
+}
+
+impl Default for AccessLogConfig {
+    #[inline]
+    fn default() -> Self {
+        AccessLogConfig::builder().build()
+    }
+}
+
+impl<'de> Deserialize<'de> for AccessLogConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::AccessLogConfig::deserialize(deserializer)?;
+        let mut builder = AccessLogConfig::builder();
+        if let Some(enabled) = raw.enabled {
+            builder = builder.enabled(enabled);
+        }
+        if let Some(format) = raw.format {
+            builder = builder.format(format);
+        }
+        Ok(builder.build())
+    }
+}
+
+impl AccessLogConfig {
+    /// Determines if the server will write an access log.
+    ///
+    /// The log is written to `var/log/access.log`, or to standard output if [`InstallConfig::use_console_log`] is
+    /// set.
+    ///
+    /// Defaults to `false`.
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns the template used to format access log entries.
+    ///
+    /// The template uses a subset of the directives supported by Apache's `mod_log_config`:
+    ///
+    /// * `%h` - The client's IP address.
+    /// * `%l` - The remote logname, which is always `-`.
+    /// * `%u` - The authenticated user ID, or `-`.
+    /// * `%t` - The time the request was received.
+    /// * `%r` - The first line of the request.
+    /// * `%s`, `%>s` - The response status code.
+    /// * `%b` - The size of the response body in bytes, or `-` if empty.
+    /// * `%B` - The size of the response body in bytes.
+    /// * `%D` - The time taken to process the request in microseconds.
+    /// * `%T` - The time taken to process the request in seconds.
+    /// * `%m` - The request method.
+    /// * `%U` - The request path.
+    /// * `%q` - The request query string, including the leading `?`, or an empty string.
+    /// * `%H` - The request protocol.
+    /// * `%{Header}i` - The value of a request header, or `-`.
+    /// * `%%` - A literal `%`.
+    ///
+    /// Defaults to the Apache combined log format:
+    /// `%h %l %u %t "%r" %>s %b "%{Referer}i" "%{User-Agent}i"`.
+    #[inline]
+    pub fn format(&self) -> &str {
+        &self.format
+    }
+}
+
+/// Log compression configuration.
+///
+/// Compression only applies to logs written to files in `var/log`. It has no effect if
+/// [`InstallConfig::use_console_log`] is set.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+pub struct LogCompressionConfig {
+    #[builder(default)]
+    service: LogCompression,
+    #[builder(default)]
+    request: LogCompression,
+    #[builder(default)]
+    metric: LogCompression,
+    #[builder(default = 3)]
+    zstd_level: i32,
+}
+
+impl Default for LogCompressionConfig {
+    #[inline]
+    fn default() -> Self {
+        LogCompressionConfig::builder().build()
+    }
+}
+
+impl<'de> Deserialize<'de> for LogCompressionConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::LogCompressionConfig::deserialize(deserializer)?;
+        let mut builder = LogCompressionConfig::builder();
+        if let Some(service) = raw.service {
+            builder = builder.service(service);
+        }
+        if let Some(request) = raw.request {
+            builder = builder.request(request);
+        }
+        if let Some(metric) = raw.metric {
+            builder = builder.metric(metric);
+        }
+        if let Some(zstd_level) = raw.zstd_level {
+            builder = builder.zstd_level(zstd_level);
+        }
+        Ok(builder.build())
+    }
+}
+
+impl LogCompressionConfig {
+    /// Returns the compression used for the service log.
+    ///
+    /// Defaults to [`LogCompression::None`].
+    #[inline]
+    pub fn service(&self) -> LogCompression {
+        self.service
+    }
+
+    /// Returns the compression used for the request log.
+    ///
+    /// Defaults to [`LogCompression::None`].
+    #[inline]
+    pub fn request(&self) -> LogCompression {
+        self.request
+    }
+
+    /// Returns the compression used for the metric log.
+    ///
+    /// Defaults to [`LogCompression::None`].
+    #[inline]
+    pub fn metric(&self) -> LogCompression {
+        self.metric
+    }
+
+    /// Returns the zstd compression level used for logs with [`LogCompression::Zstd`] compression.
+    ///
+    /// Must be between 1 and 19. Defaults to 3.
+    #[inline]
+    pub fn zstd_level(&self) -> i32 {
+        self.zstd_level
+    }
+}
+
+/// A compression format for a log file.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum LogCompression {
+    /// The log is written uncompressed, and gzip-compressed when it is rotated.
+    #[default]
+    None,
+    /// The log is written through streaming zstd compression to a `.log.zst` file.
+    ///
+    /// The stream is flushed each time the server flushes the log, and a new zstd frame is started after every 1 MiB
+    /// of uncompressed log output, so tools tailing the file can decode everything written so far.
+    Zstd,
+}
+
+/// HTTP2 protocol configuration.
+///
+/// Unset values retain Hyper's defaults, which favor many concurrent small requests. High-throughput streaming
+/// workloads typically benefit from larger flow control windows.
+#[derive(Clone, PartialEq, Debug, Default)]
+#[staged_builder]
+#[builder(validate)]
+pub struct Http2Config {
+    #[builder(default, custom(type = u32, convert = Some))]
+    max_concurrent_streams: Option<u32>,
+    #[builder(default, custom(type = u32, convert = Some))]
+    initial_stream_window_size: Option<u32>,
+    #[builder(default, custom(type = u32, convert = Some))]
+    initial_connection_window_size: Option<u32>,
+    #[builder(default, custom(type = u32, convert = Some))]
+    max_frame_size: Option<u32>,
+    #[builder(default, into)]
+    keep_alive_interval: Option<Duration>,
+    #[builder(default, into)]
+    keep_alive_timeout: Option<Duration>,
+}
+
+// These bounds are set by the HTTP2 spec.
+const MAX_WINDOW_SIZE: u32 = (1 << 31) - 1;
+const MIN_FRAME_SIZE: u32 = 1 << 14;
+const MAX_FRAME_SIZE: u32 = (1 << 24) - 1;
+
+impl Validate for Http2Config {
+    type Error = ConfigError;
+
+    fn validate(&self) -> Result<(), Self::Error> {
+        for (name, size) in [
+            (
+                "initial-stream-window-size",
+                self.initial_stream_window_size,
+            ),
+            (
+                "initial-connection-window-size",
+                self.initial_connection_window_size,
+            ),
+        ] {
+            if size.is_some_and(|s| s > MAX_WINDOW_SIZE) {
+                return Err(ConfigError(format!(
+                    "{name} must be at most {MAX_WINDOW_SIZE}"
+                )));
+            }
+        }
+
+        if self
+            .max_frame_size
+            .is_some_and(|s| !(MIN_FRAME_SIZE..=MAX_FRAME_SIZE).contains(&s))
+        {
+            return Err(ConfigError(format!(
+                "max-frame-size must be between {MIN_FRAME_SIZE} and {MAX_FRAME_SIZE}"
+            )));
+        }
+
+        if self.keep_alive_interval.is_some_and(|d| d.is_zero())
+            || self.keep_alive_timeout.is_some_and(|d| d.is_zero())
+        {
+            return Err(ConfigError(
+                "keep-alive-interval and keep-alive-timeout must be positive".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for Http2Config {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::Http2Config::deserialize(deserializer)?;
+        let mut builder = Http2Config::builder();
+        if let Some(max_concurrent_streams) = raw.max_concurrent_streams {
+            builder = builder.max_concurrent_streams(max_concurrent_streams);
+        }
+        if let Some(initial_stream_window_size) = raw.initial_stream_window_size {
+            builder = builder.initial_stream_window_size(initial_stream_window_size);
+        }
+        if let Some(initial_connection_window_size) = raw.initial_connection_window_size {
+            builder = builder.initial_connection_window_size(initial_connection_window_size);
+        }
+        if let Some(max_frame_size) = raw.max_frame_size {
+            builder = builder.max_frame_size(max_frame_size);
+        }
+        if let Some(keep_alive_interval) = raw.keep_alive_interval {
+            builder = builder.keep_alive_interval(keep_alive_interval);
+        }
+        if let Some(keep_alive_timeout) = raw.keep_alive_timeout {
+            builder = builder.keep_alive_timeout(keep_alive_timeout);
+        }
+
+        builder.build().map_err(Error::custom)
+    }
+}
+
+impl Http2Config {
+    /// Returns the maximum number of concurrent streams a client may open on a connection.
+    ///
+    /// Hyper defaults to 200.
+    #[inline]
+    pub fn max_concurrent_streams(&self) -> Option<u32> {
+        self.max_concurrent_streams
+    }
+
+    /// Returns the initial flow control window size of each stream, in bytes.
+    ///
+    /// Hyper defaults to 1 MiB.
+    #[inline]
+    pub fn initial_stream_window_size(&self) -> Option<u32> {
+        self.initial_stream_window_size
+    }
+
+    /// Returns the initial flow control window size of each connection, in bytes.
+    ///
+    /// Hyper defaults to 1 MiB.
+    #[inline]
+    pub fn initial_connection_window_size(&self) -> Option<u32> {
+        self.initial_connection_window_size
+    }
+
+    /// Returns the maximum size of frames the server will accept, in bytes.
+    ///
+    /// Hyper defaults to 16 KiB.
+    #[inline]
+    pub fn max_frame_size(&self) -> Option<u32> {
+        self.max_frame_size
+    }
+
+    /// Returns the interval at which the server sends `PING` frames to keep idle connections alive.
+    ///
+    /// Defaults to `None`, which disables keep-alive pings.
+    #[inline]
+    pub fn keep_alive_interval(&self) -> Option<Duration> {
+        self.keep_alive_interval
+    }
+
+    /// Returns the amount of time the server waits for a `PING` acknowledgement before closing the connection.
+    ///
+    /// Only used if [`Self::keep_alive_interval`] is set. Hyper defaults to 20 seconds.
+    #[inline]
+    pub fn keep_alive_timeout(&self) -> Option<Duration> {
+        self.keep_alive_timeout
+    }
+}
+
+/// Advanced server configuration.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+pub struct ServerConfig {
+    #[builder(default = num_cpus::get())]
+    processors: usize,
+    #[builder(default, custom(type = usize, convert = Some))]
+    min_threads: Option<usize>,
+    #[builder(default, custom(type = usize, convert = Some))]
+    max_threads: Option<usize>,
+    #[builder(default, custom(type = usize, convert = Some))]
+    max_connections: Option<usize>,
+    #[builder(default = 0.95)]
+    max_file_descriptor_utilization: f64,
+    #[builder(default, custom(type = usize, convert = Some))]
+    io_threads: Option<usize>,
+    #[builder(default = Duration::from_secs(5 * 60))]
+    idle_thread_timeout: Duration,
+    #[builder(default = Duration::from_secs(15))]
+    shutdown_timeout: Duration,
+    #[builder(default = Duration::from_secs(5 * 60))]
+    unhealthy_deregistration_delay: Duration,
+    #[builder(default = true)]
+    gzip: bool,
+    #[builder(default = true)]
+    metric_aliases: bool,
+    #[builder(default = false)]
+    http2: bool,
+    #[builder(default)]
+    http2_settings: Http2Config,
+    #[builder(default = false)]
+    strict_http_parsing: bool,
+    #[builder(default, into)]
+    idle_connection_timeout: Option<Duration>,
+    #[builder(default, into)]
+    request_body_read_timeout: Option<Duration>,
+    #[builder(default, into)]
+    response_write_timeout: Option<Duration>,
+    #[builder(default = Duration::from_secs(30))]
+    header_read_timeout: Duration,
+    #[builder(default, into)]
+    min_request_body_rate: Option<u64>,
+    #[builder(default = Duration::from_secs(5))]
+    min_request_body_rate_grace_period: Duration,
+    #[builder(default = 8)]
+    max_concurrent_jobs: usize,
+    #[builder(default = vec![IpAddr::V4(Ipv4Addr::UNSPECIFIED)])]
+    bind_addresses: Vec<IpAddr>,
+}
+
+impl Default for ServerConfig {
+    #[inline]
+    fn default() -> Self {
+        ServerConfig::builder().build()
+    }
+}
+
+impl<'de> Deserialize<'de> for ServerConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::ServerConfig::deserialize(deserializer)?;
+        let mut builder = ServerConfig::builder();
+        if let Some(processors) = raw.processors {
+            builder = builder.processors(processors);
+        }
+        if let Some(min_threads) = raw.min_threads {
+            builder = builder.min_threads(min_threads);
+        }
+        if let Some(max_threads) = raw.max_threads {
+            builder = builder.max_threads(max_threads);
+        }
+        if let Some(max_connections) = raw.max_connections {
+            builder = builder.max_connections(max_connections);
+        }
+        if let Some(max_file_descriptor_utilization) = raw.max_file_descriptor_utilization {
+            builder = builder.max_file_descriptor_utilization(max_file_descriptor_utilization);
+        }
+        if let Some(io_threads) = raw.io_threads {
+            builder = builder.io_threads(io_threads);
+        }
+        if let Some(idle_thread_timeout) = raw.idle_thread_timeout {
+            builder = builder.idle_thread_timeout(idle_thread_timeout);
+        }
+        if let Some(shutdown_timeout) = raw.shutdown_timeout {
+            builder = builder.shutdown_timeout(shutdown_timeout);
+        }
+        if let Some(unhealthy_deregistration_delay) = raw.unhealthy_deregistration_delay {
+            builder = builder.unhealthy_deregistration_delay(unhealthy_deregistration_delay);
+        }
+        if let Some(gzip) = raw.gzip {
+            builder = builder.gzip(gzip);
+        }
+        if let Some(metric_aliases) = raw.metric_aliases {
+            builder = builder.metric_aliases(metric_aliases);
+        }
+        if let Some(http2) = raw.http2 {
+            builder = builder.http2(http2);
+        }
+        if let Some(http2_settings) = raw.http2_settings {
+            builder = builder.http2_settings(http2_settings);
+        }
+        if let Some(strict_http_parsing) = raw.strict_http_parsing {
+            builder = builder.strict_http_parsing(strict_http_parsing);
+        }
+        if let Some(idle_connection_timeout) = raw.idle_connection_timeout {
+            builder = builder.idle_connection_timeout(idle_connection_timeout);
+        }
+        if let Some(request_body_read_timeout) = raw.request_body_read_timeout {
+            builder = builder.request_body_read_timeout(request_body_read_timeout);
+        }
+        if let Some(response_write_timeout) = raw.response_write_timeout {
+            builder = builder.response_write_timeout(response_write_timeout);
+        }
+        if let Some(header_read_timeout) = raw.header_read_timeout {
+            builder = builder.header_read_timeout(header_read_timeout);
+        }
+        if let Some(min_request_body_rate) = raw.min_request_body_rate {
+            builder = builder.min_request_body_rate(min_request_body_rate);
+        }
+        if let Some(min_request_body_rate_grace_period) = raw.min_request_body_rate_grace_period {
+            builder =
+                builder.min_request_body_rate_grace_period(min_request_body_rate_grace_period);
+        }
+        if let Some(max_concurrent_jobs) = raw.max_concurrent_jobs {
+            builder = builder.max_concurrent_jobs(max_concurrent_jobs);
+        }
+        if let Some(bind_addresses) = raw.bind_addresses {
+            builder = builder.bind_addresses(bind_addresses);
+        }
+
+        Ok(builder.build())
+    }
+}
+
+impl ServerConfig {
+    /// Returns the number of processors the server is allocated.
+    ///
+    /// This is only used to derive default values for other settings in this type.
+    ///
+    /// Defaults to the number of logical CPUs.
+    #[inline]
+    pub fn processors(&self) -> usize {
+        self.processors
+    }
+
+    /// Returns the minimum number of threads in the pool used to process blocking endpoints.
+    ///
+    /// Defaults to 8 times the number of processors.
+    #[inline]
+    pub fn min_threads(&self) -> usize {
+        self.min_threads.unwrap_or(self.processors * 8)
+    }
+
+    /// Returns the maximum number of threads in the pool used to process blocking endpoints.
+    ///
+    /// Defaults to the maximum of 32 times the number of processors and 256.
+    #[inline]
+    pub fn max_threads(&self) -> usize {
+        self.max_threads
+            .unwrap_or_else(|| usize::max(self.processors * 32, 256))
+    }
+
+    /// Returns the maximum number of live TCP connections the server will allow at any time.
+    ///
+    /// Defaults to 10 times the value of [`Self::max_threads`].
+    #[inline]
+    pub fn max_connections(&self) -> usize {
+        self.max_connections
+            .unwrap_or_else(|| self.max_threads() * 10)
+    }
+
+    /// Returns the fraction of the process's file descriptor limit above which the server will stop accepting new
+    /// connections.
+    ///
+    /// Accepting connections while file descriptors are nearly exhausted tends to fail unpredictably elsewhere in the
+    /// process, so the server instead leaves new connections in the listen backlog and reports a health warning until
+    /// usage falls back below the limit. Only supported on Linux.
+    ///
+    /// Defaults to 0.95.
+    #[inline]
+    pub fn max_file_descriptor_utilization(&self) -> f64 {
+        self.max_file_descriptor_utilization
+    }
+
+    /// Returns the number of threads used for nonblocking operations in the server's Tokio runtime.
+    ///
+    /// Defaults to half the number of processors.
+    #[inline]
+    pub fn io_threads(&self) -> usize {
+        self.io_threads
+            .unwrap_or_else(|| usize::max(1, self.processors / 2))
+    }
+
+    /// Returns the amount of time a thread in the blocking request pool will sit idle before shutting down.
+    ///
+    /// Defaults to 5 minutes.
+    #[inline]
+    pub fn idle_thread_timeout(&self) -> Duration {
+        self.idle_thread_timeout
+    }
+
+    /// Returns the amount of time the server will wait for pending requests to complete when shutting down.
+    ///
+    /// Defaults to 15 seconds.
+    #[inline]
+    pub fn shutdown_timeout(&self) -> Duration {
+        self.shutdown_timeout
+    }
+
+    /// Returns the amount of time the server's health must remain at `ERROR` or worse before callbacks registered with
+    /// `Witchcraft::on_deregister` are invoked.
+    ///
+    /// Defaults to 5 minutes.
+    #[inline]
+    pub fn unhealthy_deregistration_delay(&self) -> Duration {
+        self.unhealthy_deregistration_delay
+    }
+
+    /// Determines if response bodies will be compressed with gzip, brotli, or zstd according to the client's
+    /// `Accept-Encoding` header.
+    ///
+    /// The minimum response size and eligible content types are set in the runtime configuration's `compression`
+    /// section.
+    ///
+    /// Defaults to `true`.
+    #[inline]
+    pub fn gzip(&self) -> bool {
+        self.gzip
+    }
+
+    /// Determines if metrics which have been renamed will also be reported under their old names.
+    ///
+    /// This allows dashboards and alerts to be migrated to the new names before the old ones stop being reported. Only
+    /// metrics the application has registered aliases for are affected.
+    ///
+    /// Defaults to `true`.
+    #[inline]
+    pub fn metric_aliases(&self) -> bool {
+        self.metric_aliases
+    }
+
+    /// Determines if the server will support the HTTP2 protocol.
+    ///
+    /// Defaults to `false`.
+    #[inline]
+    pub fn http2(&self) -> bool {
+        self.http2
+    }
+
+    /// Returns the server's HTTP2 protocol settings.
+    #[inline]
+    pub fn http2_settings(&self) -> &Http2Config {
+        &self.http2_settings
+    }
+
+    /// Determines if the server will reject requests with ambiguous or malformed framing.
+    ///
+    /// Requests with invalid or conflicting `Content-Length` headers, a `Transfer-Encoding` which doesn't end in
+    /// `chunked`, obsolete line-folded headers, or header values containing control characters are always rejected. In
+    /// strict mode, requests with both `Content-Length` and `Transfer-Encoding` headers, a `Transfer-Encoding` other
+    /// than a single `chunked`, or header values containing non-ASCII characters are also rejected with a
+    /// `400 Bad Request` and their connection closed, before they are routed or logged. Intermediaries disagree on how
+    /// to interpret such requests, which can be exploited for request smuggling, so this is recommended for servers
+    /// directly exposed to the internet.
+    ///
+    /// Defaults to `false`.
+    #[inline]
+    pub fn strict_http_parsing(&self) -> bool {
+        self.strict_http_parsing
+    }
+
+    /// Returns the amount of time the server allows TCP connections to remain idle before shutting them down.
+    ///
+    /// A connection is only idle while it has no requests in progress, so slow uploads and downloads are governed by
+    /// [`Self::request_body_read_timeout`] and [`Self::response_write_timeout`] instead.
+    ///
+    /// If `None`, defaults to 1 minute. If `Some`, the time will be included in HTTP responses in a `Keep-Alive`
+    /// header.
+    #[inline]
+    pub fn idle_connection_timeout(&self) -> Option<Duration> {
+        self.idle_connection_timeout
+    }
+
+    /// Returns the amount of time the server waits for the next chunk of a request body while a handler is reading it.
+    ///
+    /// If the timeout elapses, the read fails and the request is recorded as timed out by the client.
+    ///
+    /// If `None`, reads do not time out. Defaults to `None`.
+    #[inline]
+    pub fn request_body_read_timeout(&self) -> Option<Duration> {
+        self.request_body_read_timeout
+    }
+
+    /// Returns the amount of time the server waits for a client to accept response data before closing its
+    /// connection.
+    ///
+    /// The timer only runs while the server has data to write which the client is not reading, so a large response
+    /// which the client downloads steadily never times out.
+    ///
+    /// If `None`, writes do not time out. Defaults to `None`.
+    #[inline]
+    pub fn response_write_timeout(&self) -> Option<Duration> {
+        self.response_write_timeout
+    }
+
+    /// Returns the amount of time the server waits for a client to send the complete headers of an HTTP/1 request.
+    ///
+    /// The timer starts when the first byte of the request is received, and the connection is closed if it elapses
+    /// before the headers are complete. This prevents clients trickling header bytes from holding connections open
+    /// indefinitely.
+    ///
+    /// Defaults to 30 seconds.
+    #[inline]
+    pub fn header_read_timeout(&self) -> Duration {
+        self.header_read_timeout
+    }
+
+    /// Returns the minimum rate, in bytes per second, at which clients must send request bodies.
+    ///
+    /// Only time the server spends waiting on the client counts against the rate, and it is not enforced until the
+    /// grace period returned by [`Self::min_request_body_rate_grace_period`] has elapsed. The connection of a request
+    /// whose body arrives more slowly is closed.
+    ///
+    /// If set, it must be positive. If `None`, the rate is not enforced. Defaults to `None`.
+    #[inline]
+    pub fn min_request_body_rate(&self) -> Option<u64> {
+        self.min_request_body_rate
+    }
+
+    /// Returns the amount of time a client may spend sending a request body before the minimum rate is enforced.
+    ///
+    /// Defaults to 5 seconds.
+    #[inline]
+    pub fn min_request_body_rate_grace_period(&self) -> Duration {
+        self.min_request_body_rate_grace_period
+    }
+
+    /// Returns the maximum number of jobs submitted to the server's job manager which will run concurrently.
+    ///
+    /// Additional jobs are queued until a running job completes. Defaults to 8.
+    #[inline]
+    pub fn max_concurrent_jobs(&self) -> usize {
+        self.max_concurrent_jobs
+    }
+
+    /// Returns the IP addresses the server will listen on.
+    ///
+    /// Each address is bound separately with the same port, so both IPv4 and IPv6 addresses can be listed to accept
+    /// connections on a dual-stack host. IPv6 addresses only accept IPv6 connections.
+    ///
+    /// Defaults to `[0.0.0.0]`.
+    #[inline]
+    pub fn bind_addresses(&self) -> &[IpAddr] {
+        &self.bind_addresses
+    }
+}
+
+/// jemalloc configuration.
+///
+/// These settings are only used if the server is built with the `jemalloc` feature and without the `mimalloc` feature,
+/// which takes precedence. Unset values retain jemalloc's defaults. The number of arenas is fixed when the allocator
+/// initializes and so must be configured via the `MALLOC_CONF` environment variable (e.g. `MALLOC_CONF=narenas:4`)
+/// instead.
+#[derive(Clone, PartialEq, Debug, Default)]
+#[staged_builder]
+pub struct JemallocConfig {
+    #[builder(default, into)]
+    background_threads: Option<bool>,
+    #[builder(default, custom(type = usize, convert = Some))]
+    max_background_threads: Option<usize>,
+    #[builder(default, into)]
+    dirty_decay: Option<Duration>,
+    #[builder(default, into)]
+    muzzy_decay: Option<Duration>,
+}
+
+impl<'de> Deserialize<'de> for JemallocConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::JemallocConfig::deserialize(deserializer)?;
+        let mut builder = JemallocConfig::builder();
+        if let Some(background_threads) = raw.background_threads {
+            builder = builder.background_threads(background_threads);
+        }
+        if let Some(max_background_threads) = raw.max_background_threads {
+            builder = builder.max_background_threads(max_background_threads);
+        }
+        if let Some(dirty_decay) = raw.dirty_decay {
+            builder = builder.dirty_decay(dirty_decay);
+        }
+        if let Some(muzzy_decay) = raw.muzzy_decay {
+            builder = builder.muzzy_decay(muzzy_decay);
+        }
+        Ok(builder.build())
+    }
+}
+
+impl JemallocConfig {
+    /// Determines if jemalloc will use background threads to purge unused dirty pages.
+    ///
+    /// Background threads are enabled by default.
+    #[inline]
+    pub fn background_threads(&self) -> Option<bool> {
+        self.background_threads
+    }
+
+    /// Returns the maximum number of background threads jemalloc will create.
+    ///
+    /// jemalloc defaults to the number of CPUs.
+    #[inline]
+    pub fn max_background_threads(&self) -> Option<usize> {
+        self.max_background_threads
+    }
+
+    /// Returns the amount of time after which unused dirty pages in each arena are purged back to the operating
+    /// system.
+    ///
+    /// Lower values reduce memory fragmentation at the cost of more frequent page faults. jemalloc defaults to 10
+    /// seconds.
+    #[inline]
+    pub fn dirty_decay(&self) -> Option<Duration> {
+        self.dirty_decay
+    }
+
+    /// Returns the amount of time after which unused muzzy pages in each arena are purged back to the operating
+    /// system.
+    ///
+    /// jemalloc defaults to 0 seconds.
+    #[inline]
+    pub fn muzzy_decay(&self) -> Option<Duration> {
+        self.muzzy_decay
+    }
+}
+
+/// Background profiling configuration.
+///
+/// When enabled, the server periodically samples the stack of every thread and retains the samples for a fixed window,
+/// so the period leading up to an incident can be inspected after the fact. Profiling is only supported on Linux.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+pub struct ProfilingConfig {
+    #[builder(default = true)]
+    enabled: bool,
+    #[builder(default = Duration::from_secs(10))]
+    sample_interval: Duration,
+    #[builder(default = Duration::from_secs(5 * 60))]
+    retention: Duration,
+}
+
+impl Default for ProfilingConfig {
+    #[inline]
+    fn default() -> Self {
+        ProfilingConfig::builder().build()
+    }
+}
+
+impl<'de> Deserialize<'de> for ProfilingConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::ProfilingConfig::deserialize(deserializer)?;
+        let mut builder = ProfilingConfig::builder();
+        if let Some(enabled) = raw.enabled {
+            builder = builder.enabled(enabled);
+        }
+        if let Some(sample_interval) = raw.sample_interval {
+            builder = builder.sample_interval(sample_interval);
+        }
+        if let Some(retention) = raw.retention {
+            builder = builder.retention(retention);
+        }
+        Ok(builder.build())
+    }
+}
+
+impl ProfilingConfig {
+    /// Determines if the server samples thread stacks in the background.
+    ///
+    /// The samples are exposed by the `rust.thread.profile.v1` diagnostic.
+    ///
+    /// Defaults to `true`.
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns the interval between samples.
+    ///
+    /// Every thread is briefly paused while its stack is captured, so the interval should be long enough that the
+    /// pauses do not affect request latency.
+    ///
+    /// Defaults to 10 seconds.
+    #[inline]
+    pub fn sample_interval(&self) -> Duration {
+        self.sample_interval
+    }
+
+    /// Returns the amount of time samples are retained for.
+    ///
+    /// Defaults to 5 minutes.
+    #[inline]
+    pub fn retention(&self) -> Duration {
+        self.retention
     }
 }
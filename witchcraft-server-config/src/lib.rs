@@ -19,6 +19,7 @@ use core::fmt;
 use std::error::Error;
 
 pub mod install;
+pub mod net;
 pub mod runtime;
 
 /// A validation error retured by config structs.
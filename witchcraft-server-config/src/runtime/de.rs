@@ -11,8 +11,10 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use crate::net::IpNetwork;
 use serde::Deserialize;
 use std::collections::HashMap;
+use std::time::Duration;
 use witchcraft_log::LevelFilter;
 
 #[derive(Deserialize)]
@@ -22,18 +24,36 @@ pub struct RuntimeConfig {
     pub health_checks: super::HealthChecksConfig,
     pub logging: Option<super::LoggingConfig>,
     pub service_discovery: Option<super::ServicesConfig>,
+    pub user_agents: Option<super::UserAgentsConfig>,
+    pub ip_filter: Option<super::IpFilterConfig>,
+    pub slos: Option<HashMap<String, super::SloConfig>>,
+    pub memory_admission: Option<super::MemoryAdmissionConfig>,
+    pub redirects: Option<Vec<super::RedirectRule>>,
+    pub thread_pool: Option<super::ThreadPoolConfig>,
+    pub standby: Option<bool>,
+    pub compression: Option<super::CompressionConfig>,
+    pub slow_polls: Option<super::SlowPollsConfig>,
+    pub cpu_time: Option<super::CpuTimeConfig>,
+    pub max_request_size: Option<u64>,
+    pub fault_injection: Option<super::FaultInjectionConfig>,
+    pub response_limits: Option<super::ResponseLimitsConfig>,
+    pub request_priority: Option<super::RequestPriorityConfig>,
 }
 
 #[derive(Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct DiagnosticsConfig {
     pub debug_shared_secret: String,
+    pub echo_enabled: Option<bool>,
 }
 
 #[derive(Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct HealthChecksConfig {
     pub shared_secret: String,
+    #[serde(default, with = "humantime_serde")]
+    pub cache_ttl: Option<Duration>,
+    pub cache_validators: Option<bool>,
 }
 
 #[derive(Deserialize)]
@@ -43,3 +63,118 @@ pub struct LoggingConfig {
     pub loggers: Option<HashMap<String, LevelFilter>>,
     pub trace_rate: Option<f32>,
 }
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct UserAgentsConfig {
+    pub blocked: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct IpFilterConfig {
+    pub allow: Option<Vec<IpNetwork>>,
+    pub deny: Option<Vec<IpNetwork>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SloConfig {
+    #[serde(default, with = "humantime_serde")]
+    pub window: Option<Duration>,
+    pub availability: Option<f64>,
+    pub latency: Option<super::LatencyObjectiveConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LatencyObjectiveConfig {
+    #[serde(with = "humantime_serde")]
+    pub threshold: Duration,
+    pub target: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MemoryAdmissionConfig {
+    pub high_watermark: Option<u64>,
+    pub low_watermark: Option<u64>,
+    pub source: Option<super::MemorySource>,
+    pub exempt_endpoints: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RedirectRule {
+    pub from: String,
+    pub to: String,
+    pub path_match: Option<super::PathMatch>,
+    pub action: Option<super::RedirectAction>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TokenBucketConfig {
+    pub rate: f64,
+    pub burst: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ThreadPoolConfig {
+    pub min_threads: Option<usize>,
+    pub max_threads: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CompressionConfig {
+    pub min_size: Option<u64>,
+    pub content_types: Option<Vec<String>>,
+    pub max_decompressed_request_size: Option<u64>,
+    pub codings: Option<Vec<super::ContentCoding>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SlowPollsConfig {
+    pub enabled: Option<bool>,
+    #[serde(default, with = "humantime_serde")]
+    pub threshold: Option<Duration>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CpuTimeConfig {
+    pub enabled: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FaultInjectionConfig {
+    pub enabled: Option<bool>,
+    pub endpoints: Option<HashMap<String, super::EndpointFaultsConfig>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EndpointFaultsConfig {
+    #[serde(default, with = "humantime_serde")]
+    pub latency: Option<Duration>,
+    pub latency_rate: Option<f64>,
+    pub error_rate: Option<f64>,
+    pub reset_rate: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ResponseLimitsConfig {
+    pub max_size: Option<u64>,
+    pub endpoints: Option<HashMap<String, u64>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RequestPriorityConfig {
+    pub trusted_networks: Option<Vec<IpNetwork>>,
+}
@@ -12,12 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 //! Runtime-reloadable configuration.
+use crate::net::IpNetwork;
 use crate::ConfigError;
 use conjure_runtime_config::ServicesConfig;
+use regex::Regex;
 use serde::de::Error;
 use serde::{Deserialize, Deserializer};
 use staged_builder::{staged_builder, Validate};
 use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
 use witchcraft_log::LevelFilter;
 
 mod de;
@@ -32,6 +36,34 @@ pub struct RuntimeConfig {
     logging: LoggingConfig,
     #[builder(default)]
     service_discovery: ServicesConfig,
+    #[builder(default)]
+    user_agents: UserAgentsConfig,
+    #[builder(default)]
+    ip_filter: IpFilterConfig,
+    #[builder(map(key(type = String, into), value(type = SloConfig)))]
+    slos: HashMap<String, SloConfig>,
+    #[builder(default)]
+    memory_admission: MemoryAdmissionConfig,
+    #[builder(list(item(type = RedirectRule)))]
+    redirects: Vec<RedirectRule>,
+    #[builder(default)]
+    thread_pool: ThreadPoolConfig,
+    #[builder(default = false)]
+    standby: bool,
+    #[builder(default)]
+    compression: CompressionConfig,
+    #[builder(default)]
+    slow_polls: SlowPollsConfig,
+    #[builder(default)]
+    cpu_time: CpuTimeConfig,
+    #[builder(default, into)]
+    max_request_size: Option<u64>,
+    #[builder(default)]
+    fault_injection: FaultInjectionConfig,
+    #[builder(default)]
+    response_limits: ResponseLimitsConfig,
+    #[builder(default)]
+    request_priority: RequestPriorityConfig,
 }
 
 impl<'de> Deserialize<'de> for RuntimeConfig {
@@ -49,6 +81,48 @@ impl<'de> Deserialize<'de> for RuntimeConfig {
         if let Some(service_discovery) = raw.service_discovery {
             builder = builder.service_discovery(service_discovery);
         }
+        if let Some(user_agents) = raw.user_agents {
+            builder = builder.user_agents(user_agents);
+        }
+        if let Some(ip_filter) = raw.ip_filter {
+            builder = builder.ip_filter(ip_filter);
+        }
+        if let Some(slos) = raw.slos {
+            builder = builder.slos(slos);
+        }
+        if let Some(memory_admission) = raw.memory_admission {
+            builder = builder.memory_admission(memory_admission);
+        }
+        if let Some(redirects) = raw.redirects {
+            builder = builder.redirects(redirects);
+        }
+        if let Some(thread_pool) = raw.thread_pool {
+            builder = builder.thread_pool(thread_pool);
+        }
+        if let Some(standby) = raw.standby {
+            builder = builder.standby(standby);
+        }
+        if let Some(compression) = raw.compression {
+            builder = builder.compression(compression);
+        }
+        if let Some(slow_polls) = raw.slow_polls {
+            builder = builder.slow_polls(slow_polls);
+        }
+        if let Some(cpu_time) = raw.cpu_time {
+            builder = builder.cpu_time(cpu_time);
+        }
+        if let Some(max_request_size) = raw.max_request_size {
+            builder = builder.max_request_size(max_request_size);
+        }
+        if let Some(fault_injection) = raw.fault_injection {
+            builder = builder.fault_injection(fault_injection);
+        }
+        if let Some(response_limits) = raw.response_limits {
+            builder = builder.response_limits(response_limits);
+        }
+        if let Some(request_priority) = raw.request_priority {
+            builder = builder.request_priority(request_priority);
+        }
 
         Ok(builder.build())
     }
@@ -89,6 +163,103 @@ impl RuntimeConfig {
     pub fn service_discovery(&self) -> &ServicesConfig {
         &self.service_discovery
     }
+
+    /// Returns the server's user agent configuration.
+    #[inline]
+    pub fn user_agents(&self) -> &UserAgentsConfig {
+        &self.user_agents
+    }
+
+    /// Returns the server's IP filter configuration.
+    #[inline]
+    pub fn ip_filter(&self) -> &IpFilterConfig {
+        &self.ip_filter
+    }
+
+    /// Returns the service level objectives of the server's endpoints, keyed by `<service-name>.<endpoint-name>`.
+    #[inline]
+    pub fn slos(&self) -> &HashMap<String, SloConfig> {
+        &self.slos
+    }
+
+    /// Returns the server's memory-based request admission configuration.
+    #[inline]
+    pub fn memory_admission(&self) -> &MemoryAdmissionConfig {
+        &self.memory_admission
+    }
+
+    /// Returns the server's redirect and rewrite rules.
+    ///
+    /// Rules are matched in order against the path of each request, including the context path, and the first
+    /// matching rule is applied.
+    #[inline]
+    pub fn redirects(&self) -> &[RedirectRule] {
+        &self.redirects
+    }
+
+    /// Returns the server's blocking thread pool configuration.
+    #[inline]
+    pub fn thread_pool(&self) -> &ThreadPoolConfig {
+        &self.thread_pool
+    }
+
+    /// Determines if the server should start in standby mode.
+    ///
+    /// While in standby, the server binds its ports and serves its status and debug endpoints, but application
+    /// endpoints respond with `503 Service Unavailable`. The server is activated when this value changes to `false`
+    /// or a request is made to its `/debug/activate` endpoint. Activation is permanent for the life of the process.
+    ///
+    /// Defaults to `false`.
+    #[inline]
+    pub fn standby(&self) -> bool {
+        self.standby
+    }
+
+    /// Returns the server's request and response compression configuration.
+    #[inline]
+    pub fn compression(&self) -> &CompressionConfig {
+        &self.compression
+    }
+
+    /// Returns the server's slow poll detection configuration.
+    #[inline]
+    pub fn slow_polls(&self) -> &SlowPollsConfig {
+        &self.slow_polls
+    }
+
+    /// Returns the server's per-request CPU time measurement configuration.
+    #[inline]
+    pub fn cpu_time(&self) -> &CpuTimeConfig {
+        &self.cpu_time
+    }
+
+    /// Returns the largest request body, in bytes, the server will accept.
+    ///
+    /// Requests declaring a larger `Content-Length` are rejected with `413 Request Entity Too Large` before their body is
+    /// read. Bodies without a declared length, and compressed bodies once decompressed, fail with the same error as
+    /// soon as more than this many bytes have been read. If `None`, request bodies are not limited.
+    #[inline]
+    pub fn max_request_size(&self) -> Option<u64> {
+        self.max_request_size
+    }
+
+    /// Returns the server's fault injection configuration.
+    #[inline]
+    pub fn fault_injection(&self) -> &FaultInjectionConfig {
+        &self.fault_injection
+    }
+
+    /// Returns the server's response size limit configuration.
+    #[inline]
+    pub fn response_limits(&self) -> &ResponseLimitsConfig {
+        &self.response_limits
+    }
+
+    /// Returns the server's request priority header configuration.
+    #[inline]
+    pub fn request_priority(&self) -> &RequestPriorityConfig {
+        &self.request_priority
+    }
 }
 
 /// Diagnostics configuration.
@@ -97,6 +268,8 @@ impl RuntimeConfig {
 pub struct DiagnosticsConfig {
     #[builder(into)]
     debug_shared_secret: String,
+    #[builder(default = false)]
+    echo_enabled: bool,
 }
 
 impl<'de> Deserialize<'de> for DiagnosticsConfig {
@@ -105,7 +278,10 @@ impl<'de> Deserialize<'de> for DiagnosticsConfig {
         D: Deserializer<'de>,
     {
         let raw = de::DiagnosticsConfig::deserialize(deserializer)?;
-        let builder = DiagnosticsConfig::builder().debug_shared_secret(raw.debug_shared_secret);
+        let mut builder = DiagnosticsConfig::builder().debug_shared_secret(raw.debug_shared_secret);
+        if let Some(echo_enabled) = raw.echo_enabled {
+            builder = builder.echo_enabled(echo_enabled);
+        }
 
         Ok(builder.build())
     }
@@ -119,6 +295,17 @@ impl DiagnosticsConfig {
     pub fn debug_shared_secret(&self) -> &str {
         &self.debug_shared_secret
     }
+
+    /// Determines if the server's echo debug endpoint is enabled.
+    ///
+    /// The endpoint is authorized by the debug shared secret and reflects the request's method, headers, client IP,
+    /// negotiated TLS parameters, and trace IDs back to the caller to help diagnose connectivity issues.
+    ///
+    /// Defaults to `false`.
+    #[inline]
+    pub fn echo_enabled(&self) -> bool {
+        self.echo_enabled
+    }
 }
 
 /// Health checks configuration.
@@ -127,6 +314,10 @@ impl DiagnosticsConfig {
 pub struct HealthChecksConfig {
     #[builder(into)]
     shared_secret: String,
+    #[builder(default = Duration::ZERO)]
+    cache_ttl: Duration,
+    #[builder(default = true)]
+    cache_validators: bool,
 }
 
 impl<'de> Deserialize<'de> for HealthChecksConfig {
@@ -135,7 +326,13 @@ impl<'de> Deserialize<'de> for HealthChecksConfig {
         D: Deserializer<'de>,
     {
         let raw = de::HealthChecksConfig::deserialize(deserializer)?;
-        let builder = HealthChecksConfig::builder().shared_secret(raw.shared_secret);
+        let mut builder = HealthChecksConfig::builder().shared_secret(raw.shared_secret);
+        if let Some(cache_ttl) = raw.cache_ttl {
+            builder = builder.cache_ttl(cache_ttl);
+        }
+        if let Some(cache_validators) = raw.cache_validators {
+            builder = builder.cache_validators(cache_validators);
+        }
 
         Ok(builder.build())
     }
@@ -147,6 +344,30 @@ impl HealthChecksConfig {
     pub fn shared_secret(&self) -> &str {
         &self.shared_secret
     }
+
+    /// Returns the maximum amount of time the responses of the server's health and readiness endpoints will be cached
+    /// for.
+    ///
+    /// Each cached response expires after a randomly jittered fraction of this duration to avoid synchronized
+    /// recomputation. A value of zero disables caching.
+    ///
+    /// Defaults to 0 seconds.
+    #[inline]
+    pub fn cache_ttl(&self) -> Duration {
+        self.cache_ttl
+    }
+
+    /// Determines if successful responses of the server's health and readiness endpoints will include an `ETag` and a
+    /// `Cache-Control` header allowing them to be revalidated.
+    ///
+    /// Clients and proxies can then poll with an `If-None-Match` header and receive an empty `304 Not Modified`
+    /// response if the status has not changed. If disabled, the responses are marked as uncacheable.
+    ///
+    /// Defaults to `true`.
+    #[inline]
+    pub fn cache_validators(&self) -> bool {
+        self.cache_validators
+    }
 }
 
 /// Logging configuration.
@@ -230,3 +451,1109 @@ impl LoggingConfig {
         self.trace_rate
     }
 }
+
+/// User agent configuration.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+pub struct UserAgentsConfig {
+    #[builder(list(item(type = String, into)))]
+    blocked: Vec<String>,
+}
+
+impl<'de> Deserialize<'de> for UserAgentsConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::UserAgentsConfig::deserialize(deserializer)?;
+        let mut builder = UserAgentsConfig::builder();
+        if let Some(blocked) = raw.blocked {
+            builder = builder.blocked(blocked);
+        }
+
+        Ok(builder.build())
+    }
+}
+
+impl Default for UserAgentsConfig {
+    #[inline]
+    fn default() -> Self {
+        UserAgentsConfig::builder().build()
+    }
+}
+
+impl UserAgentsConfig {
+    /// Returns the agents whose requests will be rejected with a `403 Forbidden` response.
+    ///
+    /// Each entry is either an agent name (e.g. `bad-client`), which blocks all versions of the agent, or a name and
+    /// version (e.g. `bad-client/1.2.3`), which blocks only that version. Names are matched case-insensitively against
+    /// every agent in a request's `User-Agent` header.
+    #[inline]
+    pub fn blocked(&self) -> &[String] {
+        &self.blocked
+    }
+}
+
+/// IP filter configuration.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+pub struct IpFilterConfig {
+    #[builder(list(item(type = IpNetwork)))]
+    allow: Vec<IpNetwork>,
+    #[builder(list(item(type = IpNetwork)))]
+    deny: Vec<IpNetwork>,
+}
+
+impl<'de> Deserialize<'de> for IpFilterConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::IpFilterConfig::deserialize(deserializer)?;
+        let mut builder = IpFilterConfig::builder();
+        if let Some(allow) = raw.allow {
+            builder = builder.allow(allow);
+        }
+        if let Some(deny) = raw.deny {
+            builder = builder.deny(deny);
+        }
+
+        Ok(builder.build())
+    }
+}
+
+impl Default for IpFilterConfig {
+    #[inline]
+    fn default() -> Self {
+        IpFilterConfig::builder().build()
+    }
+}
+
+impl IpFilterConfig {
+    /// Returns the networks permitted to connect to the server.
+    ///
+    /// If empty, all networks not explicitly denied are permitted.
+    #[inline]
+    pub fn allow(&self) -> &[IpNetwork] {
+        &self.allow
+    }
+
+    /// Returns the networks prohibited from connecting to the server.
+    ///
+    /// Denials take precedence over the allow list.
+    #[inline]
+    pub fn deny(&self) -> &[IpNetwork] {
+        &self.deny
+    }
+
+    /// Determines if a client at the specified address is permitted to connect to the server.
+    pub fn permits(&self, addr: IpAddr) -> bool {
+        if self.deny.iter().any(|n| n.contains(addr)) {
+            return false;
+        }
+
+        self.allow.is_empty() || self.allow.iter().any(|n| n.contains(addr))
+    }
+}
+
+/// Service level objective configuration for an endpoint.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+#[builder(validate)]
+pub struct SloConfig {
+    #[builder(default = Duration::from_secs(60 * 60))]
+    window: Duration,
+    #[builder(default, into)]
+    availability: Option<f64>,
+    #[builder(default, into)]
+    latency: Option<LatencyObjectiveConfig>,
+}
+
+impl Validate for SloConfig {
+    type Error = ConfigError;
+
+    fn validate(&self) -> Result<(), Self::Error> {
+        if self.window.is_zero() {
+            return Err(ConfigError("window must be positive".to_string()));
+        }
+
+        if let Some(availability) = self.availability {
+            validate_target("availability", availability)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn validate_target(name: &str, target: f64) -> Result<(), ConfigError> {
+    if !(0.0..1.0).contains(&target) {
+        return Err(ConfigError(format!(
+            "{name} must be at least 0 and less than 1"
+        )));
+    }
+
+    Ok(())
+}
+
+impl<'de> Deserialize<'de> for SloConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::SloConfig::deserialize(deserializer)?;
+        let mut builder = SloConfig::builder();
+        if let Some(window) = raw.window {
+            builder = builder.window(window);
+        }
+        if let Some(availability) = raw.availability {
+            builder = builder.availability(availability);
+        }
+        if let Some(latency) = raw.latency {
+            builder = builder.latency(latency);
+        }
+
+        builder.build().map_err(Error::custom)
+    }
+}
+
+impl SloConfig {
+    /// Returns the rolling window over which the objectives are evaluated.
+    ///
+    /// Defaults to 1 hour.
+    #[inline]
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Returns the target fraction of requests which do not fail with a `5xx` status code, e.g. `0.999`.
+    #[inline]
+    pub fn availability(&self) -> Option<f64> {
+        self.availability
+    }
+
+    /// Returns the endpoint's latency objective.
+    #[inline]
+    pub fn latency(&self) -> Option<&LatencyObjectiveConfig> {
+        self.latency.as_ref()
+    }
+}
+
+/// Latency objective configuration.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+#[builder(validate)]
+pub struct LatencyObjectiveConfig {
+    threshold: Duration,
+    target: f64,
+}
+
+impl Validate for LatencyObjectiveConfig {
+    type Error = ConfigError;
+
+    fn validate(&self) -> Result<(), Self::Error> {
+        validate_target("latency target", self.target)
+    }
+}
+
+impl<'de> Deserialize<'de> for LatencyObjectiveConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::LatencyObjectiveConfig::deserialize(deserializer)?;
+        let builder = LatencyObjectiveConfig::builder()
+            .threshold(raw.threshold)
+            .target(raw.target);
+
+        builder.build().map_err(Error::custom)
+    }
+}
+
+impl LatencyObjectiveConfig {
+    /// Returns the latency within which requests must complete to meet the objective.
+    ///
+    /// Required.
+    #[inline]
+    pub fn threshold(&self) -> Duration {
+        self.threshold
+    }
+
+    /// Returns the target fraction of requests which complete within the threshold, e.g. `0.99`.
+    ///
+    /// Required.
+    #[inline]
+    pub fn target(&self) -> f64 {
+        self.target
+    }
+}
+
+/// Memory-based request admission configuration.
+///
+/// When the process's memory usage exceeds the high watermark, the server rejects low priority requests to non-exempt
+/// endpoints with a `503 Service Unavailable` response until usage falls below the low watermark. Requests are low
+/// priority if the server's request classifier or a trusted client's `Request-Priority` header marks them as such.
+/// Shedding load this way gives the server a chance to recover from a spike rather than being killed when it runs out
+/// of memory.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+#[builder(validate)]
+pub struct MemoryAdmissionConfig {
+    #[builder(default, into)]
+    high_watermark: Option<u64>,
+    #[builder(default, into)]
+    low_watermark: Option<u64>,
+    #[builder(default)]
+    source: MemorySource,
+    #[builder(list(item(type = String, into)))]
+    exempt_endpoints: Vec<String>,
+}
+
+impl Validate for MemoryAdmissionConfig {
+    type Error = ConfigError;
+
+    fn validate(&self) -> Result<(), Self::Error> {
+        match (self.low_watermark, self.high_watermark) {
+            (Some(_), None) => Err(ConfigError(
+                "low-watermark requires high-watermark to be set".to_string(),
+            )),
+            (Some(low), Some(high)) if low > high => Err(ConfigError(
+                "low-watermark must not be greater than high-watermark".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MemoryAdmissionConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::MemoryAdmissionConfig::deserialize(deserializer)?;
+        let mut builder = MemoryAdmissionConfig::builder();
+        if let Some(high_watermark) = raw.high_watermark {
+            builder = builder.high_watermark(high_watermark);
+        }
+        if let Some(low_watermark) = raw.low_watermark {
+            builder = builder.low_watermark(low_watermark);
+        }
+        if let Some(source) = raw.source {
+            builder = builder.source(source);
+        }
+        if let Some(exempt_endpoints) = raw.exempt_endpoints {
+            builder = builder.exempt_endpoints(exempt_endpoints);
+        }
+
+        builder.build().map_err(Error::custom)
+    }
+}
+
+impl Default for MemoryAdmissionConfig {
+    #[inline]
+    fn default() -> Self {
+        MemoryAdmissionConfig::builder().build().unwrap()
+    }
+}
+
+impl MemoryAdmissionConfig {
+    /// Returns the memory usage in bytes above which the server will begin to shed requests.
+    ///
+    /// If `None`, requests will never be shed.
+    #[inline]
+    pub fn high_watermark(&self) -> Option<u64> {
+        self.high_watermark
+    }
+
+    /// Returns the memory usage in bytes below which the server will stop shedding requests.
+    ///
+    /// Defaults to 90% of the high watermark.
+    #[inline]
+    pub fn low_watermark(&self) -> Option<u64> {
+        self.low_watermark
+            .or_else(|| self.high_watermark.map(|high| high / 10 * 9))
+    }
+
+    /// Returns the measure of memory usage compared against the watermarks.
+    ///
+    /// Defaults to [`MemorySource::Resident`].
+    #[inline]
+    pub fn source(&self) -> MemorySource {
+        self.source
+    }
+
+    /// Returns the endpoints which are never shed, identified by `<service-name>.<endpoint-name>`.
+    ///
+    /// The server's built-in endpoints, such as health checks, are always exempt.
+    #[inline]
+    pub fn exempt_endpoints(&self) -> &[String] {
+        &self.exempt_endpoints
+    }
+}
+
+/// A measure of the process's memory usage.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum MemorySource {
+    /// The number of bytes of the process's memory that are resident in physical memory.
+    ///
+    /// Only supported on Linux.
+    #[default]
+    Resident,
+    /// The number of bytes in active pages of the heap, as reported by the memory allocator.
+    ///
+    /// Requires the `jemalloc` or `mimalloc` feature of `witchcraft-server`.
+    Heap,
+}
+
+/// A rule redirecting or rewriting requests to a path.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+#[builder(validate)]
+pub struct RedirectRule {
+    #[builder(into)]
+    from: String,
+    #[builder(into)]
+    to: String,
+    #[builder(default)]
+    path_match: PathMatch,
+    #[builder(default)]
+    action: RedirectAction,
+}
+
+impl Validate for RedirectRule {
+    type Error = ConfigError;
+
+    fn validate(&self) -> Result<(), Self::Error> {
+        match self.path_match {
+            PathMatch::Regex => {
+                if let Err(e) = Regex::new(&self.from) {
+                    return Err(ConfigError(format!("invalid redirect regex: {e}")));
+                }
+            }
+            PathMatch::Exact | PathMatch::Prefix => {
+                if !self.from.starts_with('/') {
+                    return Err(ConfigError(
+                        "redirect from path must start with a `/`".to_string(),
+                    ));
+                }
+            }
+        }
+
+        let absolute = self.to.starts_with("http://") || self.to.starts_with("https://");
+        match self.action {
+            RedirectAction::Rewrite if !self.to.starts_with('/') => Err(ConfigError(
+                "rewrite to path must start with a `/`".to_string(),
+            )),
+            RedirectAction::PermanentRedirect | RedirectAction::TemporaryRedirect
+                if !(self.to.starts_with('/') || absolute) =>
+            {
+                Err(ConfigError(
+                    "redirect to must be a path starting with a `/` or an absolute URL".to_string(),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for RedirectRule {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::RedirectRule::deserialize(deserializer)?;
+        let mut builder = RedirectRule::builder().from(raw.from).to(raw.to);
+        if let Some(path_match) = raw.path_match {
+            builder = builder.path_match(path_match);
+        }
+        if let Some(action) = raw.action {
+            builder = builder.action(action);
+        }
+
+        builder.build().map_err(Error::custom)
+    }
+}
+
+impl RedirectRule {
+    /// Returns the path, path prefix, or regex matched against request paths.
+    ///
+    /// Regexes must match the entire path.
+    ///
+    /// Required.
+    #[inline]
+    pub fn from(&self) -> &str {
+        &self.from
+    }
+
+    /// Returns the target of the rule.
+    ///
+    /// For prefix matches, the remainder of the request's path after the prefix is appended. For regex matches,
+    /// capture groups can be referenced as `$1` or `${name}`. Redirects may target either a path or an absolute URL,
+    /// while rewrites must target a path. The request's query string is preserved unless the target has its own.
+    ///
+    /// Required.
+    #[inline]
+    pub fn to(&self) -> &str {
+        &self.to
+    }
+
+    /// Returns how [`Self::from`] is matched against request paths.
+    ///
+    /// Defaults to [`PathMatch::Exact`].
+    #[inline]
+    pub fn path_match(&self) -> PathMatch {
+        self.path_match
+    }
+
+    /// Returns the action taken for matching requests.
+    ///
+    /// Defaults to [`RedirectAction::PermanentRedirect`].
+    #[inline]
+    pub fn action(&self) -> RedirectAction {
+        self.action
+    }
+}
+
+/// A method of matching request paths.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum PathMatch {
+    /// The path must be equal to the pattern.
+    #[default]
+    Exact,
+    /// The path must be equal to the pattern or start with it followed by a `/`.
+    Prefix,
+    /// The path must match the pattern as a regex.
+    Regex,
+}
+
+/// An action taken for requests matching a [`RedirectRule`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum RedirectAction {
+    /// Responds with a `308 Permanent Redirect`.
+    #[default]
+    PermanentRedirect,
+    /// Responds with a `307 Temporary Redirect`.
+    TemporaryRedirect,
+    /// Handles the request as if it had been made to the target path.
+    Rewrite,
+}
+
+/// Token bucket configuration.
+///
+/// A token bucket holds up to `burst` tokens and is refilled at `rate` tokens per second.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+#[builder(validate)]
+pub struct TokenBucketConfig {
+    rate: f64,
+    #[builder(default, into)]
+    burst: Option<u64>,
+}
+
+impl Validate for TokenBucketConfig {
+    type Error = ConfigError;
+
+    fn validate(&self) -> Result<(), Self::Error> {
+        if !(self.rate.is_finite() && self.rate > 0.0) {
+            return Err(ConfigError("rate must be a positive number".to_string()));
+        }
+        if self.burst == Some(0) {
+            return Err(ConfigError("burst must be positive".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for TokenBucketConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::TokenBucketConfig::deserialize(deserializer)?;
+        let builder = TokenBucketConfig::builder().rate(raw.rate).burst(raw.burst);
+
+        builder.build().map_err(Error::custom)
+    }
+}
+
+impl TokenBucketConfig {
+    /// Returns the number of tokens added to the bucket per second.
+    ///
+    /// Required.
+    #[inline]
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Returns the maximum number of tokens the bucket can hold.
+    ///
+    /// Defaults to the rate rounded up, allowing a burst of one second's worth of tokens.
+    #[inline]
+    pub fn burst(&self) -> u64 {
+        self.burst.unwrap_or_else(|| self.rate.ceil() as u64)
+    }
+}
+
+/// Blocking thread pool configuration.
+///
+/// These values override the `server.min-threads` and `server.max-threads` install configuration values, allowing the
+/// size of the pool used to process blocking endpoints to be adjusted without restarting the server.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+#[builder(validate)]
+pub struct ThreadPoolConfig {
+    #[builder(default, into)]
+    min_threads: Option<usize>,
+    #[builder(default, into)]
+    max_threads: Option<usize>,
+}
+
+impl Validate for ThreadPoolConfig {
+    type Error = ConfigError;
+
+    fn validate(&self) -> Result<(), Self::Error> {
+        if self.max_threads == Some(0) {
+            return Err(ConfigError("max-threads must be positive".to_string()));
+        }
+        if let (Some(min), Some(max)) = (self.min_threads, self.max_threads) {
+            if min > max {
+                return Err(ConfigError(
+                    "min-threads must not be greater than max-threads".to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for ThreadPoolConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::ThreadPoolConfig::deserialize(deserializer)?;
+        let builder = ThreadPoolConfig::builder()
+            .min_threads(raw.min_threads)
+            .max_threads(raw.max_threads);
+
+        builder.build().map_err(Error::custom)
+    }
+}
+
+impl Default for ThreadPoolConfig {
+    #[inline]
+    fn default() -> Self {
+        ThreadPoolConfig::builder().build().unwrap()
+    }
+}
+
+impl ThreadPoolConfig {
+    /// Returns the minimum number of threads in the pool used to process blocking endpoints.
+    ///
+    /// If `None`, the install configuration's `server.min-threads` value is used.
+    #[inline]
+    pub fn min_threads(&self) -> Option<usize> {
+        self.min_threads
+    }
+
+    /// Returns the maximum number of threads in the pool used to process blocking endpoints.
+    ///
+    /// If `None`, the install configuration's `server.max-threads` value is used.
+    #[inline]
+    pub fn max_threads(&self) -> Option<usize> {
+        self.max_threads
+    }
+}
+
+/// Compression configuration.
+///
+/// Response compression is enabled by the install configuration's `server.gzip` value. Request bodies with a
+/// `Content-Encoding` of `gzip` or `zstd` are always decompressed, and those with any other coding are rejected.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+pub struct CompressionConfig {
+    #[builder(default = 1024 * 1024)]
+    min_size: u64,
+    #[builder(list(item(type = String, into)))]
+    content_types: Vec<String>,
+    #[builder(default = 100 * 1024 * 1024)]
+    max_decompressed_request_size: u64,
+    #[builder(default = vec![ContentCoding::Gzip])]
+    codings: Vec<ContentCoding>,
+}
+
+impl<'de> Deserialize<'de> for CompressionConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::CompressionConfig::deserialize(deserializer)?;
+        let mut builder = CompressionConfig::builder();
+        if let Some(min_size) = raw.min_size {
+            builder = builder.min_size(min_size);
+        }
+        if let Some(content_types) = raw.content_types {
+            builder = builder.content_types(content_types);
+        }
+        if let Some(max_decompressed_request_size) = raw.max_decompressed_request_size {
+            builder = builder.max_decompressed_request_size(max_decompressed_request_size);
+        }
+        if let Some(codings) = raw.codings {
+            builder = builder.codings(codings);
+        }
+
+        Ok(builder.build())
+    }
+}
+
+impl Default for CompressionConfig {
+    #[inline]
+    fn default() -> Self {
+        CompressionConfig::builder().build()
+    }
+}
+
+impl CompressionConfig {
+    /// Returns the size in bytes below which response bodies of a known size will not be compressed.
+    ///
+    /// Defaults to 1 MiB.
+    #[inline]
+    pub fn min_size(&self) -> u64 {
+        self.min_size
+    }
+
+    /// Returns the content type prefixes of responses which may be compressed.
+    ///
+    /// If empty, all responses are eligible for compression except those with content types indicating that they are
+    /// already compressed, such as images, video, and archives.
+    #[inline]
+    pub fn content_types(&self) -> &[String] {
+        &self.content_types
+    }
+
+    /// Returns the maximum size in bytes of a compressed request body once decompressed.
+    ///
+    /// Requests whose bodies exceed this size are rejected with a `413 Request Entity Too Large` error, preventing
+    /// small, highly compressed bodies from exhausting the server's memory.
+    ///
+    /// Defaults to 100 MiB.
+    #[inline]
+    pub fn max_decompressed_request_size(&self) -> u64 {
+        self.max_decompressed_request_size
+    }
+
+    /// Returns the content codings which may be used to compress responses, in order of preference.
+    ///
+    /// Clients choose among these with the `Accept-Encoding` header, and ties between the codings they accept are
+    /// broken by this order.
+    ///
+    /// Defaults to `[gzip]`.
+    #[inline]
+    pub fn codings(&self) -> &[ContentCoding] {
+        &self.codings
+    }
+}
+
+/// A content coding used to compress response bodies.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Deserialize)]
+#[non_exhaustive]
+pub enum ContentCoding {
+    /// The `gzip` coding.
+    #[serde(rename = "gzip")]
+    Gzip,
+    /// The `br` (Brotli) coding.
+    #[serde(rename = "br")]
+    Brotli,
+    /// The `zstd` (Zstandard) coding.
+    #[serde(rename = "zstd")]
+    Zstd,
+}
+
+/// Slow poll detection configuration.
+///
+/// Async endpoints run on the server's shared worker threads, so a handler which blocks or performs expensive
+/// computation within a single poll of its future delays every other request scheduled on the same thread. When
+/// enabled, the server times each poll of an endpoint's handler and response body, and reports polls that take longer
+/// than the threshold.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+#[builder(validate)]
+pub struct SlowPollsConfig {
+    #[builder(default = false)]
+    enabled: bool,
+    #[builder(default = Duration::from_millis(10))]
+    threshold: Duration,
+}
+
+impl Validate for SlowPollsConfig {
+    type Error = ConfigError;
+
+    fn validate(&self) -> Result<(), Self::Error> {
+        if self.threshold.is_zero() {
+            return Err(ConfigError("threshold must be positive".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for SlowPollsConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::SlowPollsConfig::deserialize(deserializer)?;
+        let mut builder = SlowPollsConfig::builder();
+        if let Some(enabled) = raw.enabled {
+            builder = builder.enabled(enabled);
+        }
+        if let Some(threshold) = raw.threshold {
+            builder = builder.threshold(threshold);
+        }
+
+        builder.build().map_err(Error::custom)
+    }
+}
+
+impl Default for SlowPollsConfig {
+    #[inline]
+    fn default() -> Self {
+        SlowPollsConfig::builder().build().unwrap()
+    }
+}
+
+impl SlowPollsConfig {
+    /// Determines if slow poll detection is enabled.
+    ///
+    /// Defaults to `false`.
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns the duration above which a single poll is considered slow.
+    ///
+    /// Defaults to 10 milliseconds.
+    #[inline]
+    pub fn threshold(&self) -> Duration {
+        self.threshold
+    }
+}
+
+/// Per-request CPU time measurement configuration.
+///
+/// Measuring the CPU time consumed by a request requires two system calls around each poll of its handler and
+/// response body, which is a noticeable cost for endpoints with many small polls. When disabled, the
+/// `server.response.cpu-time` metrics are not reported and cost accounting records no CPU time.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+pub struct CpuTimeConfig {
+    #[builder(default = false)]
+    enabled: bool,
+}
+
+impl<'de> Deserialize<'de> for CpuTimeConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::CpuTimeConfig::deserialize(deserializer)?;
+        let mut builder = CpuTimeConfig::builder();
+        if let Some(enabled) = raw.enabled {
+            builder = builder.enabled(enabled);
+        }
+
+        Ok(builder.build())
+    }
+}
+
+impl Default for CpuTimeConfig {
+    #[inline]
+    fn default() -> Self {
+        CpuTimeConfig::builder().build()
+    }
+}
+
+impl CpuTimeConfig {
+    /// Determines if the CPU time consumed by each request is measured.
+    ///
+    /// Defaults to `false`.
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+/// Fault injection configuration.
+///
+/// When enabled, the server injects latency, `503 Service Unavailable` responses, and aborted responses into requests
+/// to the configured endpoints at random, which can be used to test the resilience of the server's clients in staging
+/// environments without external tooling.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+pub struct FaultInjectionConfig {
+    #[builder(default = false)]
+    enabled: bool,
+    #[builder(map(key(type = String, into), value(type = EndpointFaultsConfig)))]
+    endpoints: HashMap<String, EndpointFaultsConfig>,
+}
+
+impl<'de> Deserialize<'de> for FaultInjectionConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::FaultInjectionConfig::deserialize(deserializer)?;
+        let mut builder = FaultInjectionConfig::builder();
+        if let Some(enabled) = raw.enabled {
+            builder = builder.enabled(enabled);
+        }
+        if let Some(endpoints) = raw.endpoints {
+            builder = builder.endpoints(endpoints);
+        }
+
+        Ok(builder.build())
+    }
+}
+
+impl Default for FaultInjectionConfig {
+    #[inline]
+    fn default() -> Self {
+        FaultInjectionConfig::builder().build()
+    }
+}
+
+impl FaultInjectionConfig {
+    /// Determines if fault injection is enabled.
+    ///
+    /// Defaults to `false`.
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns the faults to inject into requests to endpoints, keyed by `<service-name>.<endpoint-name>`.
+    #[inline]
+    pub fn endpoints(&self) -> &HashMap<String, EndpointFaultsConfig> {
+        &self.endpoints
+    }
+}
+
+/// The faults to inject into requests to an endpoint.
+///
+/// Each rate is the fraction of requests the fault is applied to. Latency is injected before the request is handled,
+/// and may be combined with either of the other faults, which are mutually exclusive.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+#[builder(validate)]
+pub struct EndpointFaultsConfig {
+    #[builder(default = Duration::ZERO)]
+    latency: Duration,
+    #[builder(default = 0.0)]
+    latency_rate: f64,
+    #[builder(default = 0.0)]
+    error_rate: f64,
+    #[builder(default = 0.0)]
+    reset_rate: f64,
+}
+
+impl Validate for EndpointFaultsConfig {
+    type Error = ConfigError;
+
+    fn validate(&self) -> Result<(), Self::Error> {
+        for (name, rate) in [
+            ("latency-rate", self.latency_rate),
+            ("error-rate", self.error_rate),
+            ("reset-rate", self.reset_rate),
+        ] {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(ConfigError(format!("{name} must be between 0 and 1")));
+            }
+        }
+
+        if self.error_rate + self.reset_rate > 1.0 {
+            return Err(ConfigError(
+                "error-rate and reset-rate must not sum to more than 1".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for EndpointFaultsConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::EndpointFaultsConfig::deserialize(deserializer)?;
+        let mut builder = EndpointFaultsConfig::builder();
+        if let Some(latency) = raw.latency {
+            builder = builder.latency(latency);
+        }
+        if let Some(latency_rate) = raw.latency_rate {
+            builder = builder.latency_rate(latency_rate);
+        }
+        if let Some(error_rate) = raw.error_rate {
+            builder = builder.error_rate(error_rate);
+        }
+        if let Some(reset_rate) = raw.reset_rate {
+            builder = builder.reset_rate(reset_rate);
+        }
+
+        builder.build().map_err(Error::custom)
+    }
+}
+
+impl EndpointFaultsConfig {
+    /// Returns the latency added to requests.
+    ///
+    /// Defaults to 0.
+    #[inline]
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    /// Returns the fraction of requests which are delayed by the configured latency.
+    ///
+    /// Defaults to 0.
+    #[inline]
+    pub fn latency_rate(&self) -> f64 {
+        self.latency_rate
+    }
+
+    /// Returns the fraction of requests which fail with a `503 Service Unavailable` response.
+    ///
+    /// Defaults to 0.
+    #[inline]
+    pub fn error_rate(&self) -> f64 {
+        self.error_rate
+    }
+
+    /// Returns the fraction of requests whose responses are aborted before their body is written.
+    ///
+    /// An aborted response closes an HTTP/1 connection or resets an HTTP/2 stream.
+    ///
+    /// Defaults to 0.
+    #[inline]
+    pub fn reset_rate(&self) -> f64 {
+        self.reset_rate
+    }
+}
+
+/// Response size limit configuration.
+///
+/// Response bodies larger than the applicable limit are aborted rather than sent in full, which protects the server
+/// from endpoints that unintentionally serialize unbounded amounts of data, such as a listing with no page size.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+pub struct ResponseLimitsConfig {
+    #[builder(default, into)]
+    max_size: Option<u64>,
+    #[builder(map(key(type = String, into), value(type = u64)))]
+    endpoints: HashMap<String, u64>,
+}
+
+impl<'de> Deserialize<'de> for ResponseLimitsConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::ResponseLimitsConfig::deserialize(deserializer)?;
+        let mut builder = ResponseLimitsConfig::builder();
+        if let Some(max_size) = raw.max_size {
+            builder = builder.max_size(max_size);
+        }
+        if let Some(endpoints) = raw.endpoints {
+            builder = builder.endpoints(endpoints);
+        }
+
+        Ok(builder.build())
+    }
+}
+
+impl Default for ResponseLimitsConfig {
+    #[inline]
+    fn default() -> Self {
+        ResponseLimitsConfig::builder().build()
+    }
+}
+
+impl ResponseLimitsConfig {
+    /// Returns the maximum size in bytes of the response body of endpoints without their own limit.
+    ///
+    /// If `None`, response bodies are not limited.
+    ///
+    /// Defaults to `None`.
+    #[inline]
+    pub fn max_size(&self) -> Option<u64> {
+        self.max_size
+    }
+
+    /// Returns the maximum sizes in bytes of the response bodies of individual endpoints, keyed by
+    /// `<service-name>.<endpoint-name>`.
+    ///
+    /// These override [`Self::max_size`].
+    #[inline]
+    pub fn endpoints(&self) -> &HashMap<String, u64> {
+        &self.endpoints
+    }
+}
+
+/// Request priority header configuration.
+///
+/// Callers in trusted networks can set the priority of their requests with the `Request-Priority` header, which
+/// determines how early they are rejected when the server is shedding load.
+#[derive(Clone, PartialEq, Debug)]
+#[staged_builder]
+pub struct RequestPriorityConfig {
+    #[builder(list(item(type = IpNetwork)))]
+    trusted_networks: Vec<IpNetwork>,
+}
+
+impl<'de> Deserialize<'de> for RequestPriorityConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = de::RequestPriorityConfig::deserialize(deserializer)?;
+        let mut builder = RequestPriorityConfig::builder();
+        if let Some(trusted_networks) = raw.trusted_networks {
+            builder = builder.trusted_networks(trusted_networks);
+        }
+
+        Ok(builder.build())
+    }
+}
+
+impl Default for RequestPriorityConfig {
+    #[inline]
+    fn default() -> Self {
+        RequestPriorityConfig::builder().build()
+    }
+}
+
+impl RequestPriorityConfig {
+    /// Returns the networks whose clients are trusted to set the priority of their requests.
+    ///
+    /// The header is ignored for requests from all other clients. The client's address is that of the TCP connection,
+    /// so if the server is behind a proxy, the proxy must strip the header from untrusted requests.
+    ///
+    /// Defaults to an empty list.
+    #[inline]
+    pub fn trusted_networks(&self) -> &[IpNetwork] {
+        &self.trusted_networks
+    }
+
+    /// Determines if a client at the specified address is trusted to set the priority of its requests.
+    pub fn trusts(&self, addr: IpAddr) -> bool {
+        self.trusted_networks.iter().any(|n| n.contains(addr))
+    }
+}
@@ -0,0 +1,70 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, Criterion};
+use http_body_util::{BodyExt, Full};
+use hyper::{Request, StatusCode};
+use server::{SendRequest, Server};
+use tokio::runtime::Runtime;
+
+// The benchmarks drive the same server binary as the ete tests, but only use part of the harness.
+#[allow(dead_code)]
+#[path = "../tests/ete/server/mod.rs"]
+mod server;
+
+fn tls_handshake(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("tls_handshake");
+    for (name, http2) in [("http1", false), ("http2", true)] {
+        let server = runtime.block_on(Server::builder().http2(http2).start("async"));
+        group.bench_function(name, |b| {
+            b.to_async(&runtime)
+                .iter(|| async { server.client::<Full<Bytes>>().await.unwrap() })
+        });
+        runtime.block_on(server.shutdown());
+    }
+    group.finish();
+}
+
+fn request(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("request");
+    for handler_type in ["blocking", "async"] {
+        let server = runtime.block_on(Server::builder().http2(true).start(handler_type));
+        let SendRequest::Http2(client) = runtime.block_on(server.client()).unwrap() else {
+            panic!("expected an HTTP/2 client");
+        };
+        group.bench_function(handler_type, |b| {
+            b.to_async(&runtime).iter(|| {
+                let mut client = client.clone();
+                async move {
+                    let request = Request::post("/witchcraft-ete/api/test/echo")
+                        .header("Content-Type", "application/octet-stream")
+                        .body(Full::new(Bytes::from("hello world")))
+                        .unwrap();
+                    let response = client.send_request(request).await.unwrap();
+                    assert_eq!(response.status(), StatusCode::OK);
+                    response.collect().await.unwrap();
+                }
+            })
+        });
+        runtime.block_on(server.shutdown());
+    }
+    group.finish();
+}
+
+criterion_group!(benches, tls_handshake, request);
+criterion_main!(benches);
@@ -13,13 +13,15 @@
 // limitations under the License.
 use crate::conjure::AsyncTestService;
 use conjure_error::{Error, InvalidArgument};
-use conjure_http::server::AsyncWriteBody;
-use http::{HeaderMap, HeaderValue};
+use conjure_http::server::{AsyncWriteBody, RequestContext};
+use http::{HeaderMap, HeaderName, HeaderValue};
 use std::pin::Pin;
+use std::thread;
 use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::{pin, time};
-use witchcraft_server::{RequestBody, ResponseWriter};
+use witchcraft_server::extensions::DeclaredTrailers;
+use witchcraft_server::{RequestBody, ResponseWriter, StreamingBody};
 
 pub struct TestResource;
 
@@ -27,6 +29,7 @@ impl AsyncTestService<RequestBody, ResponseWriter> for TestResource {
     type SlowBodyBody = SlowBodyBody;
     type TrailersBody = TrailersBody;
     type IoAfterEofBody = IoAfterEofBody;
+    type EchoBody = StreamingBody<Pin<Box<RequestBody>>>;
 
     async fn safe_params(
         &self,
@@ -52,11 +55,20 @@ impl AsyncTestService<RequestBody, ResponseWriter> for TestResource {
         Ok(())
     }
 
+    async fn blocking_sleep(&self, delay_millis: i32) -> Result<(), Error> {
+        thread::sleep(Duration::from_millis(delay_millis as u64));
+        Ok(())
+    }
+
     async fn slow_body(&self, delay_millis: i32) -> Result<SlowBodyBody, Error> {
         Ok(SlowBodyBody(Duration::from_millis(delay_millis as u64)))
     }
 
-    async fn trailers(&self, body: RequestBody) -> Result<TrailersBody, Error> {
+    async fn trailers(
+        &self,
+        body: RequestBody,
+        mut ctx: RequestContext<'_>,
+    ) -> Result<TrailersBody, Error> {
         pin!(body);
         let mut bytes = vec![];
         body.read_to_end(&mut bytes).await.unwrap();
@@ -68,6 +80,10 @@ impl AsyncTestService<RequestBody, ResponseWriter> for TestResource {
             "expected request trailer value",
         );
 
+        ctx.response_extensions_mut().insert(
+            DeclaredTrailers::new().with_trailer(HeaderName::from_static("response-trailer")),
+        );
+
         Ok(TrailersBody)
     }
 
@@ -81,6 +97,10 @@ impl AsyncTestService<RequestBody, ResponseWriter> for TestResource {
 
         Ok(IoAfterEofBody)
     }
+
+    async fn echo(&self, body: RequestBody) -> Result<Self::EchoBody, Error> {
+        Ok(StreamingBody::new(body.into_stream()))
+    }
 }
 
 pub struct SlowBodyBody(Duration);
@@ -0,0 +1,68 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use bytes::Bytes;
+use futures_util::future::BoxFuture;
+use futures_util::{stream, FutureExt};
+use http::header::CONTENT_TYPE;
+use http::{HeaderMap, HeaderValue, Request, Response};
+use http_body::Frame;
+use http_body_util::{BodyExt, StreamBody};
+use std::convert::Infallible;
+use std::task::{Context, Poll};
+use tonic::body::BoxBody;
+use tonic::server::NamedService;
+use tonic::Status;
+use tower_service::Service;
+
+#[derive(Clone)]
+pub struct GrpcEchoService;
+
+impl NamedService for GrpcEchoService {
+    const NAME: &'static str = "witchcraft.ete.Echo";
+}
+
+impl Service<Request<BoxBody>> for GrpcEchoService {
+    type Response = Response<BoxBody>;
+
+    type Error = Infallible;
+
+    type Future = BoxFuture<'static, Result<Response<BoxBody>, Infallible>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<BoxBody>) -> Self::Future {
+        async move {
+            let frames: Vec<Result<_, Status>> = match req.into_body().collect().await {
+                Ok(body) => vec![Ok(Frame::data(body.to_bytes())), Ok(trailers("0"))],
+                Err(_) => vec![Ok(trailers("13"))],
+            };
+
+            let body = StreamBody::new(stream::iter(frames));
+            let mut response = Response::new(tonic::body::boxed(body));
+            response
+                .headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+            Ok(response)
+        }
+        .boxed()
+    }
+}
+
+fn trailers(status: &'static str) -> Frame<Bytes> {
+    let mut trailers = HeaderMap::new();
+    trailers.insert("grpc-status", HeaderValue::from_static(status));
+    Frame::trailers(trailers)
+}
@@ -13,12 +13,13 @@
 // limitations under the License.
 use crate::conjure::TestService;
 use conjure_error::{Error, InvalidArgument};
-use conjure_http::server::WriteBody;
-use http::{HeaderMap, HeaderValue};
-use std::io::{Read, Write};
+use conjure_http::server::{RequestContext, WriteBody};
+use http::{HeaderMap, HeaderName, HeaderValue};
+use std::io::{self, Read, Write};
 use std::thread;
 use std::time::Duration;
 use witchcraft_server::blocking::{RequestBody, ResponseWriter};
+use witchcraft_server::extensions::DeclaredTrailers;
 
 pub struct TestResource;
 
@@ -26,6 +27,7 @@ impl TestService<RequestBody, ResponseWriter> for TestResource {
     type SlowBodyBody = SlowBodyBody;
     type TrailersBody = TrailersBody;
     type IoAfterEofBody = IoAfterEofBody;
+    type EchoBody = EchoBody;
 
     fn safe_params(
         &self,
@@ -51,11 +53,20 @@ impl TestService<RequestBody, ResponseWriter> for TestResource {
         Ok(())
     }
 
+    fn blocking_sleep(&self, delay_millis: i32) -> Result<(), Error> {
+        thread::sleep(Duration::from_millis(delay_millis as u64));
+        Ok(())
+    }
+
     fn slow_body(&self, delay_millis: i32) -> Result<SlowBodyBody, Error> {
         Ok(SlowBodyBody(Duration::from_millis(delay_millis as u64)))
     }
 
-    fn trailers(&self, mut body: RequestBody) -> Result<TrailersBody, Error> {
+    fn trailers(
+        &self,
+        mut body: RequestBody,
+        mut ctx: RequestContext<'_>,
+    ) -> Result<TrailersBody, Error> {
         let mut bytes = vec![];
         body.read_to_end(&mut bytes).unwrap();
         assert_eq!(bytes, b"expected request body");
@@ -66,6 +77,10 @@ impl TestService<RequestBody, ResponseWriter> for TestResource {
             "expected request trailer value",
         );
 
+        ctx.response_extensions_mut().insert(
+            DeclaredTrailers::new().with_trailer(HeaderName::from_static("response-trailer")),
+        );
+
         Ok(TrailersBody)
     }
 
@@ -78,6 +93,10 @@ impl TestService<RequestBody, ResponseWriter> for TestResource {
 
         Ok(IoAfterEofBody)
     }
+
+    fn echo(&self, body: RequestBody) -> Result<EchoBody, Error> {
+        Ok(EchoBody(body))
+    }
 }
 
 pub struct SlowBodyBody(Duration);
@@ -123,3 +142,12 @@ impl WriteBody<ResponseWriter> for IoAfterEofBody {
         Ok(())
     }
 }
+
+pub struct EchoBody(RequestBody);
+
+impl WriteBody<ResponseWriter> for EchoBody {
+    fn write_body(mut self: Box<Self>, w: &mut ResponseWriter) -> Result<(), Error> {
+        io::copy(&mut self.0, w).map_err(|e| Error::service_safe(e, InvalidArgument::new()))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,479 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! A load generator for benchmarking HTTP endpoints.
+//!
+//! A [`LoadTest`] is made up of weighted scenarios, each an async function sending a single request. The load test
+//! runs a fixed number of workers concurrently for a configured duration, with each worker repeatedly running the
+//! scenarios in proportion to their weights. The resulting [`Report`] summarizes the throughput, error count, and
+//! latency percentiles of each scenario, and can be compared against a [`Baseline`] from an earlier run to detect
+//! regressions.
+//!
+//! The load test is agnostic to the transport used by the scenarios, so it can drive a server over TLS, HTTP/2, or
+//! an in-memory connection alike.
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task;
+use tokio::time::Instant;
+
+type ScenarioFn =
+    Box<dyn Fn() -> BoxFuture<'static, Result<(), Box<dyn Error + Sync + Send>>> + Sync + Send>;
+
+struct Scenario {
+    name: String,
+    weight: u64,
+    run: ScenarioFn,
+}
+
+/// A load test of a set of weighted request scenarios.
+pub struct LoadTest {
+    concurrency: usize,
+    duration: Duration,
+    warmup: Duration,
+    scenarios: Vec<Scenario>,
+}
+
+impl Default for LoadTest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoadTest {
+    /// Creates a new load test with no scenarios.
+    pub fn new() -> Self {
+        LoadTest {
+            concurrency: 8,
+            duration: Duration::from_secs(10),
+            warmup: Duration::ZERO,
+            scenarios: vec![],
+        }
+    }
+
+    /// Sets the number of requests sent concurrently.
+    ///
+    /// Defaults to 8.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        assert!(concurrency > 0, "concurrency must be positive");
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Sets the duration over which requests are measured.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Sets the duration of the warmup period before requests are measured.
+    ///
+    /// Defaults to 0 seconds.
+    pub fn warmup(mut self, warmup: Duration) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    /// Adds a scenario to the load test.
+    ///
+    /// Scenarios are run in proportion to their weights. A scenario is considered to have failed if its future returns
+    /// an error.
+    pub fn scenario<F, G, E>(mut self, name: &str, weight: u64, run: F) -> Self
+    where
+        F: Fn() -> G + 'static + Sync + Send,
+        G: Future<Output = Result<(), E>> + 'static + Send,
+        E: Into<Box<dyn Error + Sync + Send>>,
+    {
+        assert!(weight > 0, "scenario weight must be positive");
+        self.scenarios.push(Scenario {
+            name: name.to_string(),
+            weight,
+            run: Box::new(move || run().map(|r| r.map_err(Into::into)).boxed()),
+        });
+        self
+    }
+
+    /// Runs the load test.
+    pub async fn run(self) -> Report {
+        assert!(!self.scenarios.is_empty(), "no scenarios configured");
+
+        let start = Instant::now();
+        let measure_start = start + self.warmup;
+        let end = measure_start + self.duration;
+
+        let shared = Arc::new(Shared {
+            total_weight: self.scenarios.iter().map(|s| s.weight).sum(),
+            scenarios: self.scenarios,
+            next: AtomicU64::new(0),
+        });
+
+        let workers = (0..self.concurrency)
+            .map(|_| task::spawn(worker(shared.clone(), measure_start, end)))
+            .collect::<Vec<_>>();
+
+        let mut samples = shared
+            .scenarios
+            .iter()
+            .map(|s| (s.name.clone(), Samples::default()))
+            .collect::<BTreeMap<_, _>>();
+        for worker in workers {
+            let worker_samples = worker.await.unwrap();
+            for (scenario, worker_samples) in shared.scenarios.iter().zip(worker_samples) {
+                let samples = samples.get_mut(&scenario.name).unwrap();
+                samples.latencies.extend(worker_samples.latencies);
+                samples.errors += worker_samples.errors;
+            }
+        }
+
+        Report {
+            duration: self.duration,
+            scenarios: samples
+                .into_iter()
+                .map(|(name, samples)| (name, ScenarioReport::new(samples, self.duration)))
+                .collect(),
+        }
+    }
+}
+
+struct Shared {
+    scenarios: Vec<Scenario>,
+    total_weight: u64,
+    next: AtomicU64,
+}
+
+impl Shared {
+    // Scenarios are selected round-robin across their weights rather than randomly so that short runs still follow the
+    // configured mix.
+    fn next_scenario(&self) -> usize {
+        let mut slot = self.next.fetch_add(1, Ordering::Relaxed) % self.total_weight;
+        for (i, scenario) in self.scenarios.iter().enumerate() {
+            if slot < scenario.weight {
+                return i;
+            }
+            slot -= scenario.weight;
+        }
+
+        unreachable!()
+    }
+}
+
+#[derive(Default)]
+struct Samples {
+    latencies: Vec<Duration>,
+    errors: u64,
+}
+
+async fn worker(shared: Arc<Shared>, measure_start: Instant, end: Instant) -> Vec<Samples> {
+    let mut samples = shared
+        .scenarios
+        .iter()
+        .map(|_| Samples::default())
+        .collect::<Vec<_>>();
+
+    loop {
+        let start = Instant::now();
+        if start >= end {
+            break;
+        }
+
+        let idx = shared.next_scenario();
+        let result = (shared.scenarios[idx].run)().await;
+        if start < measure_start {
+            continue;
+        }
+
+        let samples = &mut samples[idx];
+        match result {
+            Ok(()) => samples.latencies.push(start.elapsed()),
+            Err(_) => samples.errors += 1,
+        }
+    }
+
+    samples
+}
+
+/// The results of a [`LoadTest`].
+pub struct Report {
+    duration: Duration,
+    scenarios: BTreeMap<String, ScenarioReport>,
+}
+
+impl Report {
+    /// Returns the duration over which requests were measured.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns the results of the scenario with the specified name.
+    pub fn scenario(&self, name: &str) -> Option<&ScenarioReport> {
+        self.scenarios.get(name)
+    }
+
+    /// Returns an iterator over the names and results of all scenarios.
+    pub fn scenarios(&self) -> impl Iterator<Item = (&str, &ScenarioReport)> {
+        self.scenarios.iter().map(|(k, v)| (&**k, v))
+    }
+
+    /// Returns a baseline which later reports can be compared against.
+    pub fn baseline(&self) -> Baseline {
+        Baseline {
+            scenarios: self
+                .scenarios
+                .iter()
+                .map(|(name, report)| {
+                    (
+                        name.clone(),
+                        BaselineScenario {
+                            throughput: report.throughput(),
+                            p50: report.percentile(50.),
+                            p99: report.percentile(99.),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Compares this report against a baseline.
+    ///
+    /// A scenario has regressed if its throughput has dropped, or its median or 99th percentile latency has risen, by
+    /// more than the specified fraction of the baseline's value. Scenarios missing from either the report or the
+    /// baseline are ignored.
+    pub fn compare(&self, baseline: &Baseline, tolerance: f64) -> Vec<Regression> {
+        let mut regressions = vec![];
+
+        for (name, report) in &self.scenarios {
+            let Some(base) = baseline.scenarios.get(name) else {
+                continue;
+            };
+
+            let throughput = report.throughput();
+            if throughput < base.throughput * (1. - tolerance) {
+                regressions.push(Regression {
+                    scenario: name.clone(),
+                    measure: "throughput",
+                    baseline: base.throughput,
+                    current: throughput,
+                });
+            }
+
+            for (measure, base, current) in [
+                ("p50", base.p50, report.percentile(50.)),
+                ("p99", base.p99, report.percentile(99.)),
+            ] {
+                if current.as_secs_f64() > base.as_secs_f64() * (1. + tolerance) {
+                    regressions.push(Regression {
+                        scenario: name.clone(),
+                        measure,
+                        baseline: base.as_secs_f64() * 1000.,
+                        current: current.as_secs_f64() * 1000.,
+                    });
+                }
+            }
+        }
+
+        regressions
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<24} {:>10} {:>8} {:>10} {:>10} {:>10} {:>10}",
+            "scenario", "req/s", "errors", "p50 ms", "p90 ms", "p99 ms", "max ms"
+        )?;
+        for (name, report) in &self.scenarios {
+            writeln!(
+                f,
+                "{:<24} {:>10.1} {:>8} {:>10.3} {:>10.3} {:>10.3} {:>10.3}",
+                name,
+                report.throughput(),
+                report.errors(),
+                report.percentile(50.).as_secs_f64() * 1000.,
+                report.percentile(90.).as_secs_f64() * 1000.,
+                report.percentile(99.).as_secs_f64() * 1000.,
+                report.percentile(100.).as_secs_f64() * 1000.,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The results of a single scenario of a [`LoadTest`].
+pub struct ScenarioReport {
+    latencies: Vec<Duration>,
+    errors: u64,
+    duration: Duration,
+}
+
+impl ScenarioReport {
+    fn new(mut samples: Samples, duration: Duration) -> Self {
+        samples.latencies.sort_unstable();
+
+        ScenarioReport {
+            latencies: samples.latencies,
+            errors: samples.errors,
+            duration,
+        }
+    }
+
+    /// Returns the number of successful requests.
+    pub fn requests(&self) -> u64 {
+        self.latencies.len() as u64
+    }
+
+    /// Returns the number of failed requests.
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// Returns the rate of successful requests per second.
+    pub fn throughput(&self) -> f64 {
+        self.requests() as f64 / self.duration.as_secs_f64()
+    }
+
+    /// Returns the latency of successful requests at the specified percentile, between 0 and 100.
+    ///
+    /// Returns zero if no requests succeeded.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+
+        let rank = (percentile / 100. * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+}
+
+/// The summarized results of an earlier load test.
+///
+/// Baselines are stored in a line-oriented text format via their `Display` and `FromStr` implementations, with one
+/// line per scenario containing its name, throughput in requests per second, and median and 99th percentile latencies
+/// in microseconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Baseline {
+    scenarios: BTreeMap<String, BaselineScenario>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct BaselineScenario {
+    throughput: f64,
+    p50: Duration,
+    p99: Duration,
+}
+
+impl fmt::Display for Baseline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, scenario) in &self.scenarios {
+            writeln!(
+                f,
+                "{} {:.1} {} {}",
+                name,
+                scenario.throughput,
+                scenario.p50.as_micros(),
+                scenario.p99.as_micros(),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Baseline {
+    type Err = ParseBaselineError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut scenarios = BTreeMap::new();
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let err = || ParseBaselineError { line: i + 1 };
+            let [name, throughput, p50, p99] = line.split_whitespace().collect::<Vec<_>>()[..]
+            else {
+                return Err(err());
+            };
+            let scenario = BaselineScenario {
+                throughput: throughput.parse().map_err(|_| err())?,
+                p50: Duration::from_micros(p50.parse().map_err(|_| err())?),
+                p99: Duration::from_micros(p99.parse().map_err(|_| err())?),
+            };
+            scenarios.insert(name.to_string(), scenario);
+        }
+
+        Ok(Baseline { scenarios })
+    }
+}
+
+/// An error parsing a [`Baseline`].
+#[derive(Debug)]
+pub struct ParseBaselineError {
+    line: usize,
+}
+
+impl fmt::Display for ParseBaselineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid baseline on line {}", self.line)
+    }
+}
+
+impl Error for ParseBaselineError {}
+
+/// A measure of a scenario which regressed relative to a [`Baseline`].
+#[derive(Debug)]
+pub struct Regression {
+    scenario: String,
+    measure: &'static str,
+    baseline: f64,
+    current: f64,
+}
+
+impl Regression {
+    /// Returns the name of the scenario which regressed.
+    pub fn scenario(&self) -> &str {
+        &self.scenario
+    }
+
+    /// Returns the name of the measure which regressed: `throughput`, `p50`, or `p99`.
+    pub fn measure(&self) -> &str {
+        self.measure
+    }
+}
+
+impl fmt::Display for Regression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = if self.measure == "throughput" {
+            "req/s"
+        } else {
+            "ms"
+        };
+        write!(
+            f,
+            "{} {} regressed from {:.3} {unit} to {:.3} {unit}",
+            self.scenario, self.measure, self.baseline, self.current,
+        )
+    }
+}
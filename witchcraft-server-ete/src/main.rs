@@ -13,15 +13,19 @@
 // limitations under the License.
 use crate::audit_service::AuditService;
 use crate::conjure::{AsyncTestServiceEndpoints, TestServiceEndpoints};
+use crate::grpc_service::GrpcEchoService;
 use conjure_error::Error;
+use futures_util::{SinkExt, StreamExt};
 use refreshable::Refreshable;
 use std::env;
+use witchcraft_server::cluster::{ClusterInfo, ClusterInfoProvider, ClusterMember};
 use witchcraft_server::config::install::InstallConfig;
 use witchcraft_server::config::runtime::RuntimeConfig;
 use witchcraft_server::Witchcraft;
 
 mod async_handler;
 mod audit_service;
+mod grpc_service;
 mod handler;
 
 #[allow(dead_code, warnings)]
@@ -37,6 +41,7 @@ fn main(
 ) -> Result<(), Error> {
     match &*env::var("HANDLER_TYPE").unwrap() {
         "async" => {
+            wc.may_block("TestService", "trailers");
             wc.api(AsyncTestServiceEndpoints::new(async_handler::TestResource));
             wc.api(AuditService);
         }
@@ -47,5 +52,27 @@ fn main(
         ty => panic!("invalid handler type {ty}"),
     }
 
+    wc.cluster_info_provider(TestCluster);
+
+    wc.grpc(GrpcEchoService);
+
+    wc.websocket("/ws/echo", |mut socket| async move {
+        while let Some(Ok(message)) = socket.next().await {
+            if (message.is_text() || message.is_binary()) && socket.send(message).await.is_err() {
+                break;
+            }
+        }
+    });
+
     Ok(())
 }
+
+struct TestCluster;
+
+impl ClusterInfoProvider for TestCluster {
+    fn cluster_info(&self) -> ClusterInfo {
+        ClusterInfo::new("witchcraft-ete")
+            .with_local_member("node-1")
+            .with_member(ClusterMember::new("node-1"))
+    }
+}
@@ -13,16 +13,23 @@
 // limitations under the License.
 use bytes::Bytes;
 use conjure_object::Any;
+use futures_util::{stream, SinkExt, StreamExt};
 use http::{HeaderMap, HeaderValue};
-use http_body_util::{BodyExt, Empty, Full};
+use http_body_util::{BodyExt, Empty, Full, StreamBody};
 use hyper::body::{Body, Frame};
 use hyper::{Request, StatusCode};
-use server::Server;
+use hyper_util::rt::TokioIo;
+use server::{SendRequest, Server};
 use std::pin::Pin;
 use std::str;
 use std::task::{Context, Poll};
 use std::time::{Duration, Instant};
 use tokio::time;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use witchcraft_server_ete::load::{Baseline, LoadTest};
 
 mod server;
 
@@ -60,6 +67,10 @@ async fn safe_params() {
         assert_eq!(request.params().get("unsafePath"), None);
         assert_eq!(request.params().get("unsafeQuery"), None);
         assert_eq!(request.params().get("unsafeHeader"), None);
+        assert_eq!(
+            request.params()["disposition"],
+            Any::new("COMPLETED").unwrap(),
+        );
     }).await;
 }
 
@@ -148,6 +159,41 @@ async fn keep_alive_slow_body() {
     .await;
 }
 
+#[tokio::test]
+async fn may_block() {
+    // the server is configured with a single IO thread
+    Server::with(|server| async move {
+        let mut blocking_client = server.client().await.unwrap();
+        let mut client = server.client().await.unwrap();
+
+        let request = Request::builder()
+            .uri("/witchcraft-ete/api/test/blockingSleep?delayMillis=1500")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let blocking = tokio::spawn(async move { blocking_client.send_request(request).await });
+
+        // give the blocking handler time to start
+        time::sleep(Duration::from_millis(250)).await;
+
+        // a blocked handler shouldn't stall requests running concurrently
+        let request = Request::builder()
+            .uri("/witchcraft-ete/api/test/slowHeaders?delayMillis=0")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let start = Instant::now();
+        let response = client.send_request(request).await.unwrap();
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let response = blocking.await.unwrap().unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        drop(client);
+        server.shutdown().await;
+    })
+    .await;
+}
+
 #[tokio::test]
 async fn graceful_shutdown() {
     Server::with(|mut server| async move {
@@ -229,6 +275,36 @@ async fn health_check() {
     .await;
 }
 
+#[tokio::test]
+async fn cluster_info() {
+    Server::with(|server| async move {
+        let request = Request::builder()
+            .uri("/witchcraft-ete/status/cluster")
+            .header("Authorization", "Bearer health-check")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let response = server
+            .client()
+            .await
+            .unwrap()
+            .send_request(request)
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = str::from_utf8(&body).unwrap();
+        assert_eq!(
+            body,
+            r#"{"name":"witchcraft-ete","localMember":"node-1","members":[{"id":"node-1","state":"ACTIVE"}]}"#,
+        );
+
+        server.shutdown().await;
+    })
+    .await;
+}
+
 #[tokio::test]
 async fn diagnostic_types_bad_auth() {
     Server::with(|server| async move {
@@ -284,6 +360,64 @@ async fn diagnostic_types_diagnostic() {
     .await;
 }
 
+#[tokio::test]
+async fn debug_echo() {
+    Server::with(|server| async move {
+        let request = Request::builder()
+            .uri("/witchcraft-ete/debug/echo")
+            .header("Authorization", "Bearer debug")
+            .header("Echo-Test", "hello")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let response = server
+            .client()
+            .await
+            .unwrap()
+            .send_request(request)
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body = str::from_utf8(&body).unwrap();
+        assert!(body.contains("\"method\":\"GET\""));
+        assert!(body.contains("\"echo-test\":[\"hello\"]"));
+        assert!(body.contains("\"authorization\":[\"<redacted>\"]"));
+        assert!(!body.contains("Bearer debug"));
+        assert!(body.contains("\"clientIp\":\"127.0.0.1\""));
+        assert!(body.contains("\"tls\":{\"protocol\":"));
+        assert!(body.contains("\"traceId\":"));
+
+        server.shutdown().await;
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn sigquit_does_not_terminate() {
+    Server::with(|server| async move {
+        server.signal(libc::SIGQUIT);
+        time::sleep(Duration::from_secs(1)).await;
+
+        let request = Request::builder()
+            .uri("/witchcraft-ete/status/liveness")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let response = server
+            .client()
+            .await
+            .unwrap()
+            .send_request(request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        server.shutdown().await;
+    })
+    .await;
+}
+
 #[tokio::test]
 #[cfg(target_os = "linux")]
 async fn thread_dump_diagnostic() {
@@ -350,13 +484,24 @@ async fn audit_logs() {
 
 #[tokio::test]
 async fn trailers() {
+    trailers_inner(true).await;
+}
+
+#[tokio::test]
+async fn http1_trailers() {
+    trailers_inner(false).await;
+}
+
+async fn trailers_inner(http2: bool) {
     Server::builder()
-        .http2(true)
+        .http2(http2)
         .with(|server| async move {
             let request = Request::builder()
                 .method("POST")
                 .uri("/witchcraft-ete/api/test/trailers")
                 .header("Content-Type", "application/octet-stream")
+                .header("TE", "trailers")
+                .header("Trailer", "Request-Trailer")
                 .body(TrailersBody { state: 0 })
                 .unwrap();
             let response = server
@@ -436,6 +581,262 @@ async fn io_after_eof() {
     .await;
 }
 
+#[tokio::test]
+async fn expect_continue() {
+    Server::with(|server| async move {
+        // The body is never sent, so the server must respond without reading it.
+        let request = Request::builder()
+            .method("POST")
+            .uri("/witchcraft-ete/api/test/echo")
+            .header("Content-Type", "application/octet-stream")
+            .header("Content-Length", "2097152")
+            .header("Expect", "100-continue")
+            .body(StreamBody::new(stream::pending::<
+                Result<Frame<Bytes>, String>,
+            >()))
+            .unwrap();
+        let response = server
+            .client()
+            .await
+            .unwrap()
+            .send_request(request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        drop(response);
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/witchcraft-ete/api/test/echo")
+            .header("Content-Type", "application/octet-stream")
+            .header("Expect", "bogus")
+            .body(Full::new(Bytes::from("hello world")))
+            .unwrap();
+        let response = server
+            .client()
+            .await
+            .unwrap()
+            .send_request(request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::EXPECTATION_FAILED);
+        drop(response);
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/witchcraft-ete/api/test/echo")
+            .header("Content-Type", "application/octet-stream")
+            .header("Expect", "100-continue")
+            .body(Full::new(Bytes::from("hello world")))
+            .unwrap();
+        let response = server
+            .client()
+            .await
+            .unwrap()
+            .send_request(request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello world");
+
+        server.shutdown().await;
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn echo() {
+    Server::with(|server| async move {
+        let chunks = (0..100)
+            .map(|i| Ok::<_, String>(Frame::data(Bytes::from(format!("chunk {i}\n")))))
+            .collect::<Vec<_>>();
+        let expected = (0..100).map(|i| format!("chunk {i}\n")).collect::<String>();
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/witchcraft-ete/api/test/echo")
+            .header("Content-Type", "application/octet-stream")
+            .body(StreamBody::new(stream::iter(chunks)))
+            .unwrap();
+        let response = server
+            .client()
+            .await
+            .unwrap()
+            .send_request(request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.collect().await.unwrap().to_bytes();
+        assert_eq!(body, expected);
+
+        server.shutdown().await;
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn standby() {
+    Server::builder()
+        .standby()
+        .with(|server| async move {
+            let echo = || {
+                Request::builder()
+                    .method("POST")
+                    .uri("/witchcraft-ete/api/test/echo")
+                    .header("Content-Type", "application/octet-stream")
+                    .body(Full::new(Bytes::from("hello")))
+                    .unwrap()
+            };
+
+            let response = server
+                .client()
+                .await
+                .unwrap()
+                .send_request(echo())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+            let request = Request::builder()
+                .uri("/witchcraft-ete/status/liveness")
+                .body(Full::new(Bytes::new()))
+                .unwrap();
+            let response = server
+                .client()
+                .await
+                .unwrap()
+                .send_request(request)
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+            let request = Request::builder()
+                .method("POST")
+                .uri("/witchcraft-ete/debug/activate")
+                .header("Authorization", "Bearer debug")
+                .body(Full::new(Bytes::new()))
+                .unwrap();
+            let response = server
+                .client()
+                .await
+                .unwrap()
+                .send_request(request)
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+            let response = server
+                .client()
+                .await
+                .unwrap()
+                .send_request(echo())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = response.collect().await.unwrap().to_bytes();
+            assert_eq!(body, "hello");
+
+            server.shutdown().await;
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn websocket() {
+    Server::with(|mut server| async move {
+        let request = Request::builder()
+            .uri("/witchcraft-ete/ws/echo")
+            .header("Connection", "Upgrade")
+            .header("Upgrade", "websocket")
+            .header("Sec-WebSocket-Version", "13")
+            .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let response = server
+            .client()
+            .await
+            .unwrap()
+            .send_request(request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(
+            response.headers().get("Sec-WebSocket-Accept").unwrap(),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=",
+        );
+
+        let upgraded = hyper::upgrade::on(response).await.unwrap();
+        let mut socket =
+            WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Client, None).await;
+
+        socket.send(Message::text("hello")).await.unwrap();
+        let message = socket.next().await.unwrap().unwrap();
+        assert_eq!(message, Message::text("hello"));
+
+        server.start_shutdown();
+
+        let Message::Close(Some(frame)) = socket.next().await.unwrap().unwrap() else {
+            panic!("expected close frame");
+        };
+        assert_eq!(frame.code, CloseCode::Away);
+        assert!(socket.next().await.is_none());
+
+        server.finish_shutdown().await;
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn grpc() {
+    Server::builder()
+        .http2(true)
+        .with(|server| async move {
+            let request = Request::builder()
+                .method("POST")
+                .uri("/witchcraft.ete.Echo/Echo")
+                .header("Content-Type", "application/grpc")
+                .header("TE", "trailers")
+                .body(Full::new(Bytes::from_static(b"\0\0\0\0\x05hello")))
+                .unwrap();
+            let response = server
+                .client()
+                .await
+                .unwrap()
+                .send_request(request)
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers().get("Content-Type").unwrap(),
+                "application/grpc",
+            );
+
+            let body = response.collect().await.unwrap();
+            assert_eq!(body.trailers().unwrap().get("grpc-status").unwrap(), "0");
+            assert_eq!(body.to_bytes(), &b"\0\0\0\0\x05hello"[..]);
+
+            let request = Request::builder()
+                .method("POST")
+                .uri("/witchcraft.ete.Echo/Echo")
+                .header("Content-Type", "application/json")
+                .body(Full::new(Bytes::new()))
+                .unwrap();
+            let response = server
+                .client()
+                .await
+                .unwrap()
+                .send_request(request)
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+            server.shutdown().await;
+        })
+        .await;
+}
+
 #[tokio::test]
 async fn management_port() {
     Server::builder()
@@ -503,3 +904,62 @@ async fn management_port() {
         })
         .await;
 }
+
+#[tokio::test]
+async fn load() {
+    Server::builder()
+        .http2(true)
+        .with(|server| async move {
+            let SendRequest::Http2(client) = server.client().await.unwrap() else {
+                panic!("expected an HTTP/2 client");
+            };
+
+            let scenario = |method: &'static str, uri: &'static str| {
+                let client = client.clone();
+                move || {
+                    let mut client = client.clone();
+                    async move {
+                        let request = Request::builder()
+                            .method(method)
+                            .uri(uri)
+                            .header("Content-Type", "application/octet-stream")
+                            .body(Full::new(Bytes::from("hello world")))
+                            .unwrap();
+                        let response = client.send_request(request).await?;
+                        if !response.status().is_success() {
+                            return Err(format!("unexpected status {}", response.status()).into());
+                        }
+                        response.collect().await?;
+                        Ok::<_, Box<dyn std::error::Error + Sync + Send>>(())
+                    }
+                }
+            };
+
+            let report = LoadTest::new()
+                .concurrency(4)
+                .warmup(Duration::from_millis(100))
+                .duration(Duration::from_secs(1))
+                .scenario("echo", 3, scenario("POST", "/witchcraft-ete/api/test/echo"))
+                .scenario(
+                    "liveness",
+                    1,
+                    scenario("GET", "/witchcraft-ete/status/liveness"),
+                )
+                .run()
+                .await;
+
+            let echo = report.scenario("echo").unwrap();
+            let liveness = report.scenario("liveness").unwrap();
+            assert_eq!(echo.errors(), 0, "{report}");
+            assert_eq!(liveness.errors(), 0, "{report}");
+            assert!(liveness.requests() > 0, "{report}");
+            assert!(echo.requests() > liveness.requests(), "{report}");
+
+            let baseline = report.baseline().to_string().parse::<Baseline>().unwrap();
+            let regressions = report.compare(&baseline, 0.1);
+            assert!(regressions.is_empty(), "{regressions:?}\n{report}");
+
+            server.shutdown().await;
+        })
+        .await;
+}
@@ -23,8 +23,7 @@ use openssl::ssl::{SslConnector, SslMethod};
 use std::error::{self, Error};
 use std::fs::File;
 use std::future::Future;
-use std::io::{Read, Write};
-use std::net::TcpListener;
+use std::io::{BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::process::{Child, Command, Stdio};
@@ -32,18 +31,12 @@ use std::time::Duration;
 use std::{env, fs, thread};
 use tempfile::TempDir;
 use tokio::net::TcpStream;
-use tokio::sync::oneshot;
+use tokio::sync::{mpsc, oneshot};
 use tokio::{task, time};
 use tokio_openssl::SslStream;
 use witchcraft_server::logging::api::{AuditLogV3, LogLevel, RequestLogV2, ServiceLogV1};
 
-// this is a bit racy, but should work in practice
-fn open_port() -> u16 {
-    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
-    listener.local_addr().unwrap().port()
-}
-
-fn setup_config(dir: &Path, builder: &Builder, port: u16) {
+fn setup_config(dir: &Path, builder: &Builder) {
     let conf = dir.join("var/conf");
     fs::create_dir_all(&conf).unwrap();
     fs::write(
@@ -54,22 +47,28 @@ fn setup_config(dir: &Path, builder: &Builder, port: u16) {
     fs::write(
         conf.join("install.yml"),
         include_str!("install.yml")
-            .replace("<PORT>", &port.to_string())
             .replace(
                 "<MANAGEMENT_PORT>",
-                &builder
-                    .management_port
-                    .map_or("null".to_string(), |p| p.to_string()),
+                if builder.management_port { "0" } else { "null" },
             )
             .replace("<HTTP2>", &builder.http2.to_string()),
     )
     .unwrap();
-    fs::write(conf.join("runtime.yml"), include_str!("runtime.yml")).unwrap();
+    let mut runtime = include_str!("runtime.yml").to_string();
+    if builder.standby {
+        runtime.push_str("standby: true\n");
+    }
+    fs::write(conf.join("runtime.yml"), runtime).unwrap();
 
     let security = dir.join("var/security");
     fs::create_dir_all(&security).unwrap();
     fs::write(security.join("cert.cer"), include_str!("cert.cer")).unwrap();
     fs::write(security.join("key.pem"), include_str!("key.pem")).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(security.join("key.pem"), fs::Permissions::from_mode(0o600)).unwrap();
+    }
 }
 
 pub struct Server {
@@ -100,8 +99,9 @@ impl Drop for Server {
 impl Server {
     pub fn builder() -> Builder {
         Builder {
-            management_port: None,
+            management_port: false,
             http2: false,
+            standby: false,
         }
     }
 
@@ -115,9 +115,8 @@ impl Server {
 
     async fn new(builder: &Builder, handler_type: &str) -> Self {
         let dir = TempDir::new().unwrap();
-        let port = open_port();
 
-        setup_config(dir.path(), builder, port);
+        setup_config(dir.path(), builder);
 
         let binary = env::current_exe()
             .unwrap()
@@ -132,14 +131,39 @@ impl Server {
             .spawn()
             .unwrap();
 
-        let mut stdout = child.stdout.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
         let (tx, stdout_rx) = oneshot::channel();
+        let (listening_tx, mut listening_rx) = mpsc::unbounded_channel();
         thread::spawn(move || {
             let mut buf = String::new();
-            stdout.read_to_string(&mut buf).unwrap();
+            for line in BufReader::new(stdout).lines() {
+                let line = line.unwrap();
+                if let Some(listening) = parse_listening(&line) {
+                    let _ = listening_tx.send(listening);
+                }
+                buf.push_str(&line);
+                buf.push('\n');
+            }
             let _ = tx.send(buf);
         });
 
+        // The server listens on ephemeral ports, so we need to wait for it to tell us which it picked.
+        let mut port = None;
+        let mut management_port = None;
+        while port.is_none() || (builder.management_port && management_port.is_none()) {
+            let (listener, listening_port) =
+                time::timeout(Duration::from_secs(5), listening_rx.recv())
+                    .await
+                    .expect("timed out waiting for the server to start listening")
+                    .expect("server exited before listening");
+            match &*listener {
+                "service" => port = Some(listening_port),
+                "management" => management_port = Some(listening_port),
+                listener => panic!("unknown listener {listener}"),
+            }
+        }
+        let port = port.unwrap();
+
         let mut ctx = SslConnector::builder(SslMethod::tls()).unwrap();
         ctx.set_ca_file(dir.path().join("var/security/cert.cer"))
             .unwrap();
@@ -159,7 +183,7 @@ impl Server {
             stdout_rx: Some(stdout_rx),
             ctx,
             port,
-            management_port: builder.management_port,
+            management_port,
             shutdown: false,
             http2: builder.http2,
         };
@@ -246,7 +270,7 @@ impl Server {
                 .await
                 .unwrap();
             task::spawn(async {
-                let _ = connection.await;
+                let _ = connection.with_upgrades().await;
             });
 
             Ok(SendRequest::Http1(client))
@@ -258,6 +282,12 @@ impl Server {
         self.finish_shutdown().await
     }
 
+    pub fn signal(&self, signal: libc::c_int) {
+        unsafe {
+            libc::kill(self.child.id() as libc::pid_t, signal);
+        }
+    }
+
     pub fn start_shutdown(&mut self) {
         unsafe {
             libc::kill(self.child.id() as libc::pid_t, libc::SIGINT);
@@ -289,14 +319,35 @@ impl Server {
     }
 }
 
+fn parse_listening(line: &str) -> Option<(String, u16)> {
+    if !line.contains("service.1") {
+        return None;
+    }
+
+    let log = json::server_from_str::<ServiceLogV1>(line).ok()?;
+    if log.message() != "server listening" {
+        return None;
+    }
+
+    let listener = log
+        .params()
+        .get("listener")?
+        .clone()
+        .deserialize_into()
+        .ok()?;
+    let port = log.params().get("port")?.clone().deserialize_into().ok()?;
+    Some((listener, port))
+}
+
 pub struct Builder {
-    management_port: Option<u16>,
+    management_port: bool,
     http2: bool,
+    standby: bool,
 }
 
 impl Builder {
     pub fn management_port(mut self) -> Self {
-        self.management_port = Some(open_port());
+        self.management_port = true;
         self
     }
 
@@ -305,16 +356,25 @@ impl Builder {
         self
     }
 
+    pub fn standby(mut self) -> Self {
+        self.standby = true;
+        self
+    }
+
     pub async fn with<F, G>(self, test: F)
     where
         F: Fn(Server) -> G,
         G: Future<Output = ()>,
     {
         for handler_type in ["blocking", "async"] {
-            let server = Server::new(&self, handler_type).await;
+            let server = self.start(handler_type).await;
             test(server).await;
         }
     }
+
+    pub async fn start(&self, handler_type: &str) -> Server {
+        Server::new(self, handler_type).await
+    }
 }
 
 pub struct ServerLogs {
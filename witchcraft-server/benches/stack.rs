@@ -0,0 +1,179 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use bytes::Bytes;
+use conjure_error::Error;
+use conjure_http::server::StdResponseSerializer;
+use conjure_http::{conjure_endpoints, endpoint};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use http::{Request, StatusCode};
+use std::collections::BTreeMap;
+use tokio::runtime::Runtime;
+use witchcraft_server::testing::{TestClient, TestServer};
+
+#[conjure_endpoints]
+trait BenchService {
+    #[endpoint(method = GET, path = "/users", produces = StdResponseSerializer)]
+    async fn list_users(&self) -> Result<String, Error>;
+
+    #[endpoint(method = GET, path = "/users/{user_id}", produces = StdResponseSerializer)]
+    async fn get_user(&self, #[path(safe)] user_id: String) -> Result<String, Error>;
+
+    #[endpoint(method = DELETE, path = "/users/{user_id}")]
+    async fn delete_user(&self, #[path(safe)] user_id: String) -> Result<(), Error>;
+
+    #[endpoint(method = GET, path = "/users/{user_id}/groups", produces = StdResponseSerializer)]
+    async fn get_user_groups(&self, #[path(safe)] user_id: String) -> Result<String, Error>;
+
+    #[endpoint(method = GET, path = "/groups/{group_id}", produces = StdResponseSerializer)]
+    async fn get_group(&self, #[path(safe)] group_id: String) -> Result<String, Error>;
+
+    #[endpoint(
+        method = GET,
+        path = "/groups/{group_id}/members/{user_id}",
+        produces = StdResponseSerializer
+    )]
+    async fn get_member(
+        &self,
+        #[path(safe)] group_id: String,
+        #[path(safe)] user_id: String,
+    ) -> Result<String, Error>;
+
+    #[endpoint(method = GET, path = "/ping")]
+    async fn ping(&self) -> Result<(), Error>;
+
+    #[endpoint(method = GET, path = "/records/{count}", produces = StdResponseSerializer)]
+    async fn records(
+        &self,
+        #[path(safe)] count: usize,
+    ) -> Result<Vec<BTreeMap<String, String>>, Error>;
+}
+
+struct BenchResource;
+
+impl BenchService for BenchResource {
+    async fn list_users(&self) -> Result<String, Error> {
+        Ok(String::new())
+    }
+
+    async fn get_user(&self, user_id: String) -> Result<String, Error> {
+        Ok(user_id)
+    }
+
+    async fn delete_user(&self, _: String) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn get_user_groups(&self, user_id: String) -> Result<String, Error> {
+        Ok(user_id)
+    }
+
+    async fn get_group(&self, group_id: String) -> Result<String, Error> {
+        Ok(group_id)
+    }
+
+    async fn get_member(&self, _: String, user_id: String) -> Result<String, Error> {
+        Ok(user_id)
+    }
+
+    async fn ping(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn records(&self, count: usize) -> Result<Vec<BTreeMap<String, String>>, Error> {
+        let records = (0..count)
+            .map(|i| {
+                let mut record = BTreeMap::new();
+                record.insert("id".to_string(), i.to_string());
+                record.insert("name".to_string(), format!("record {i}"));
+                record.insert("description".to_string(), "a benchmark record".to_string());
+                record
+            })
+            .collect();
+        Ok(records)
+    }
+}
+
+fn client() -> TestClient {
+    let mut server = TestServer::new();
+    server.api(BenchServiceEndpoints::new(BenchResource));
+    server.client()
+}
+
+async fn send(client: &TestClient, request: Request<Bytes>, status: StatusCode) {
+    let response = client.send(request).await.unwrap();
+    assert_eq!(response.status(), status);
+}
+
+fn routing(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let client = client();
+
+    let mut group = c.benchmark_group("routing");
+    for (name, path, status) in [
+        ("static", "/api/ping", StatusCode::NO_CONTENT),
+        ("one_param", "/api/users/abc", StatusCode::OK),
+        ("two_params", "/api/groups/abc/members/def", StatusCode::OK),
+        ("not_found", "/api/unknown/abc", StatusCode::NOT_FOUND),
+    ] {
+        group.bench_function(name, |b| {
+            b.to_async(&runtime).iter(|| {
+                let request = Request::get(path).body(Bytes::new()).unwrap();
+                send(&client, request, status)
+            })
+        });
+    }
+    group.finish();
+}
+
+fn header_parsing(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let client = client();
+
+    let mut group = c.benchmark_group("header_parsing");
+    for count in [0, 16, 64] {
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, &count| {
+            b.to_async(&runtime).iter(|| {
+                let mut request = Request::get("/api/ping");
+                for i in 0..count {
+                    request = request.header(format!("X-Header-{i}"), "some header value");
+                }
+                let request = request.body(Bytes::new()).unwrap();
+                send(&client, request, StatusCode::NO_CONTENT)
+            })
+        });
+    }
+    group.finish();
+}
+
+fn serialization(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let client = client();
+
+    let mut group = c.benchmark_group("serialization");
+    for count in [1, 100, 10_000] {
+        group.throughput(Throughput::Elements(count));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, &count| {
+            b.to_async(&runtime).iter(|| {
+                let request = Request::get(format!("/api/records/{count}"))
+                    .body(Bytes::new())
+                    .unwrap();
+                send(&client, request, StatusCode::OK)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, routing, header_parsing, serialization);
+criterion_main!(benches);
@@ -0,0 +1,91 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Automatic certificate management via the ACME protocol.
+//!
+//! The obtained key and certificate are written to the keystore paths, where the TLS layer picks them up the same way
+//! it picks up certificates rotated by an operator. The ACME client itself requires the `acme` cargo feature.
+#[cfg(not(feature = "acme"))]
+use conjure_error::Error;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio_rustls::rustls::sign::CertifiedKey;
+#[cfg(not(feature = "acme"))]
+use witchcraft_server_config::install::InstallConfig;
+
+#[cfg(feature = "acme")]
+pub use client::{init, Acme};
+
+#[cfg(feature = "acme")]
+mod client;
+
+/// The ALPN protocol negotiated by TLS-ALPN-01 challenge connections.
+pub const ACME_TLS_ALPN_PROTOCOL: &[u8] = b"acme-tls/1";
+
+const HTTP_CHALLENGE_PREFIX: &str = "/.well-known/acme-challenge/";
+
+/// The challenge responses the server is currently presenting.
+#[derive(Debug, Default)]
+pub struct Challenges {
+    tls_alpn: Mutex<HashMap<String, Arc<CertifiedKey>>>,
+    http: Mutex<HashMap<String, String>>,
+}
+
+impl Challenges {
+    /// Returns the TLS-ALPN-01 challenge certificate for a domain, if a challenge is in progress.
+    pub fn tls_alpn(&self, domain: &str) -> Option<Arc<CertifiedKey>> {
+        self.tls_alpn.lock().get(domain).cloned()
+    }
+
+    /// Returns the HTTP-01 key authorization to serve at a request path, if a challenge is in progress.
+    pub fn http(&self, path: &str) -> Option<String> {
+        let token = path.strip_prefix(HTTP_CHALLENGE_PREFIX)?;
+        self.http.lock().get(token).cloned()
+    }
+
+    #[cfg(feature = "acme")]
+    fn clear(&self) {
+        self.tls_alpn.lock().clear();
+        self.http.lock().clear();
+    }
+}
+
+/// Fails if ACME is configured, since the client was not compiled in.
+#[cfg(not(feature = "acme"))]
+pub fn init(config: &InstallConfig) -> Result<(), Error> {
+    if config.acme().is_some() {
+        return Err(Error::internal_safe(
+            "acme is configured but witchcraft-server was built without the `acme` cargo feature",
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, not(feature = "acme")))]
+mod test {
+    use super::*;
+    use witchcraft_server_config::install::AcmeConfig;
+
+    #[test]
+    fn acme_not_compiled_in() {
+        let config = InstallConfig::builder()
+            .product_name("product")
+            .product_version("1.0.0")
+            .port(0)
+            .acme(AcmeConfig::builder().push_domains("example.com").build())
+            .build();
+        init(&config).unwrap_err();
+    }
+}
@@ -0,0 +1,54 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Server announcements.
+use std::net::SocketAddr;
+
+/// Information about a running server, provided to callbacks registered with
+/// [`Witchcraft::on_startup`](crate::Witchcraft::on_startup).
+///
+/// This is intended to be used to register the server with a service discovery system, particularly when the server
+/// is configured to listen on an ephemeral port.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Announcement {
+    pub(crate) service_addr: SocketAddr,
+    pub(crate) management_addr: SocketAddr,
+}
+
+impl Announcement {
+    /// Returns the address the server's service port is bound to.
+    #[inline]
+    pub fn service_addr(&self) -> SocketAddr {
+        self.service_addr
+    }
+
+    /// Returns the address the server's management port is bound to.
+    ///
+    /// This is equal to [`Self::service_addr`] if the server is not configured with a separate management port.
+    #[inline]
+    pub fn management_addr(&self) -> SocketAddr {
+        self.management_addr
+    }
+}
+
+/// The reason a server is removing itself from service discovery, provided to callbacks registered with
+/// [`Witchcraft::on_deregister`](crate::Witchcraft::on_deregister).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DeregistrationReason {
+    /// The server has begun to shut down.
+    Shutdown,
+    /// The server's health has remained at `ERROR` or worse for longer than the configured
+    /// `server.unhealthy-deregistration-delay`.
+    Unhealthy,
+}
@@ -11,7 +11,7 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-use crate::body::ClientIo;
+use crate::body::{ClientIo, RequestBodyError};
 use crate::server::RawBody;
 use bytes::{Buf, Bytes, BytesMut};
 use conjure_error::Error;
@@ -53,11 +53,12 @@ impl RequestBody {
         self.trailers.take()
     }
 
-    fn next_raw(&mut self) -> Result<Option<Bytes>, Box<dyn error::Error + Sync + Send>> {
+    fn next_raw(&mut self) -> Result<Option<Bytes>, Error> {
         loop {
             let next = self
                 .handle
-                .block_on(async { time::timeout(IO_TIMEOUT, self.inner.frame()).await })?
+                .block_on(async { time::timeout(IO_TIMEOUT, self.inner.frame()).await })
+                .map_err(|e| Error::service_safe(e, ClientIo))?
                 .transpose()?;
 
             let Some(next) = next else {
@@ -87,9 +88,7 @@ impl Iterator for RequestBody {
             return Some(Ok(mem::take(&mut self.cur)));
         }
 
-        self.next_raw()
-            .map_err(|e| Error::service_safe(e, ClientIo))
-            .transpose()
+        self.next_raw().transpose()
     }
 }
 
@@ -108,7 +107,7 @@ impl BufRead for RequestBody {
         while self.cur.is_empty() {
             match self
                 .next_raw()
-                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, RequestBodyError(e)))?
             {
                 Some(bytes) => self.cur = bytes,
                 None => break,
@@ -154,7 +153,9 @@ impl ResponseWriter {
 
     /// Writes the response's trailers.
     ///
-    /// The body must be fully written before calling this method.
+    /// The body must be fully written before calling this method. The trailers' names should be declared up front
+    /// with a [`DeclaredTrailers`](crate::extensions::DeclaredTrailers) response extension so that they can be sent
+    /// to HTTP/1.1 clients.
     pub fn send_trailers(&mut self, trailers: HeaderMap) -> Result<(), Error> {
         self.send_inner(BodyPart::Frame(Frame::trailers(trailers)))
     }
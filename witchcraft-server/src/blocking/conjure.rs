@@ -16,11 +16,14 @@ use crate::blocking::cancellation::CancellationGuard;
 use crate::blocking::pool::ThreadPool;
 use crate::blocking::{Cancellation, RequestBody, ResponseWriter};
 use crate::body::ClientIo;
-use crate::endpoint::{errors, WitchcraftEndpoint};
+use crate::endpoint::{errors, validation, WitchcraftEndpoint};
+use crate::extensions::RequestDeadline;
 use crate::health::endpoint_500s::EndpointHealth;
+use crate::outbound::OutboundCalls;
 use crate::server::RawBody;
-use crate::service::endpoint_metrics::EndpointMetrics;
+use crate::service::endpoint_metrics::{CpuTime, EndpointMetrics};
 use crate::service::handler::{BodyWriteAborted, EmptyBody};
+use crate::slo::SloRegistry;
 use async_trait::async_trait;
 use bytes::Bytes;
 use conjure_error::Error;
@@ -35,6 +38,7 @@ use std::panic::AssertUnwindSafe;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Instant;
 use std::{mem, panic};
 use tokio::runtime::Handle;
 use witchcraft_log::{info, mdc};
@@ -52,11 +56,12 @@ pub struct ConjureBlockingEndpoint {
 impl ConjureBlockingEndpoint {
     pub fn new(
         metrics: &MetricRegistry,
+        slos: &SloRegistry,
         thread_pool: &Arc<ThreadPool>,
         inner: Box<dyn Endpoint<RequestBody, ResponseWriter> + Sync + Send>,
     ) -> Self {
         ConjureBlockingEndpoint {
-            metrics: EndpointMetrics::new(metrics, &inner),
+            metrics: EndpointMetrics::new(metrics, slos, &inner),
             health: Arc::new(EndpointHealth::new()),
             inner: Arc::from(inner),
             thread_pool: thread_pool.clone(),
@@ -104,6 +109,11 @@ impl WitchcraftEndpoint for ConjureBlockingEndpoint {
         &self,
         mut req: Request<RawBody>,
     ) -> Response<BoxBody<Bytes, BodyWriteAborted>> {
+        let deadline = req.extensions().get::<RequestDeadline>().copied();
+        if deadline.is_some_and(|d| *d <= Instant::now()) {
+            return service_unavailable();
+        }
+
         let (cancellation, guard) = Cancellation::new();
         req.extensions_mut().insert(cancellation);
 
@@ -113,7 +123,10 @@ impl WitchcraftEndpoint for ConjureBlockingEndpoint {
         let endpoint = self.inner.clone();
         let handle = Handle::current();
 
-        let blocking = move || {
+        let cpu_time = req.extensions().get::<CpuTime>().cloned();
+        let outbound = req.extensions().get::<OutboundCalls>().cloned();
+
+        let run = move || {
             let _guard = trace_context.map(zipkin::set_current);
             mdc::set(snapshot);
 
@@ -121,7 +134,7 @@ impl WitchcraftEndpoint for ConjureBlockingEndpoint {
             let mut response_extensions = Extensions::new();
 
             let mut response = match panic::catch_unwind(AssertUnwindSafe(|| {
-                endpoint.handle(req, &mut response_extensions)
+                validation::sync_scope(|| endpoint.handle(req, &mut response_extensions))
             })) {
                 Ok(Ok(resp)) => resp,
                 Ok(Err(e)) => errors::to_response(e, |o| {
@@ -148,15 +161,36 @@ impl WitchcraftEndpoint for ConjureBlockingEndpoint {
                 }
             }
         };
+        let blocking = move || {
+            let run = || match outbound {
+                Some(outbound) => outbound.scope(run),
+                None => run(),
+            };
+            match cpu_time {
+                Some(cpu_time) => cpu_time.measure(run),
+                None => run(),
+            }
+        };
 
-        if self.thread_pool.try_execute(blocking).is_err() {
-            let mut response = Response::new(EmptyBody.boxed());
-            *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
-            return response;
+        let (expired_sender, mut expired_receiver) = oneshot::channel();
+        let submitted = match deadline {
+            Some(deadline) => self
+                .thread_pool
+                .try_execute_until(blocking, *deadline, move || {
+                    let _ = expired_sender.send(());
+                }),
+            None => self.thread_pool.try_execute(blocking),
+        };
+        if submitted.is_err() {
+            return service_unavailable();
         }
 
         match receiver.await {
             Ok(response) => response,
+            // The pool notifies us before dropping an expired job, so we'll see that here if it never ran.
+            Err(_canceled) if matches!(expired_receiver.try_recv(), Ok(Some(()))) => {
+                service_unavailable()
+            }
             // If we don't get a response, the handler must have panicked. We don't actually care about the payload at
             // this point (it's already been logged), so we just want to propagate a panic with an arbitrary payload to
             // have the same panicking behavior as the async implementation.
@@ -165,6 +199,12 @@ impl WitchcraftEndpoint for ConjureBlockingEndpoint {
     }
 }
 
+fn service_unavailable() -> Response<BoxBody<Bytes, BodyWriteAborted>> {
+    let mut response = Response::new(EmptyBody.boxed());
+    *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+    response
+}
+
 struct ResponseBody {
     state: State,
     _guard: CancellationGuard,
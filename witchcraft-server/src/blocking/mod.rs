@@ -18,4 +18,5 @@ pub use cancellation::Cancellation;
 mod body;
 mod cancellation;
 pub(crate) mod conjure;
+pub mod multipart;
 pub(crate) mod pool;
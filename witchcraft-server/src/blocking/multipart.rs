@@ -0,0 +1,229 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! `multipart/form-data` request bodies.
+//!
+//! This is the blocking equivalent of the [`multipart`](crate::multipart) module. A [`Part`] can be consumed by chunk or
+//! through its [`Read`] and [`BufRead`] implementations.
+//!
+//! # Examples
+//!
+//! ```
+//! use conjure_error::Error;
+//! use http::HeaderMap;
+//! use std::io;
+//! use witchcraft_server::blocking::multipart::Multipart;
+//! use witchcraft_server::blocking::RequestBody;
+//!
+//! fn upload(body: RequestBody, request_headers: &HeaderMap) -> Result<(), Error> {
+//!     let mut multipart = Multipart::new(body, request_headers)?;
+//!
+//!     while let Some(mut part) = multipart.next_part()? {
+//!         if part.file_name().is_some() {
+//!             io::copy(&mut part, &mut io::sink()).map_err(Error::internal_safe)?;
+//!         }
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+use crate::body::RequestBodyError;
+use crate::multipart::{self, Event, Parser, PartHeaders};
+use bytes::{Buf, Bytes, BytesMut};
+use conjure_error::{Error, InvalidArgument};
+use http::header::CONTENT_TYPE;
+use http::{HeaderMap, HeaderValue};
+use std::io::{self, BufRead, Read};
+
+/// A blocking reader of the parts of a `multipart/form-data` body.
+pub struct Multipart<I> {
+    body: I,
+    parser: Parser,
+}
+
+impl<I> Multipart<I>
+where
+    I: Iterator<Item = Result<Bytes, Error>>,
+{
+    /// Creates a new `Multipart` reading `body`, with the boundary taken from the request's `Content-Type` header.
+    ///
+    /// Returns an error if the request isn't a multipart request.
+    pub fn new(body: I, request_headers: &HeaderMap) -> Result<Self, Error> {
+        Ok(Self::with_boundary(
+            body,
+            &multipart::boundary(request_headers)?,
+        ))
+    }
+
+    /// Creates a new `Multipart` reading `body` with an explicit boundary.
+    pub fn with_boundary(body: I, boundary: &str) -> Self {
+        Multipart {
+            body,
+            parser: Parser::new(boundary),
+        }
+    }
+
+    /// Sets the maximum size of the contents of each part.
+    ///
+    /// Defaults to 10 MiB.
+    pub fn max_part_size(mut self, max_part_size: u64) -> Self {
+        self.parser.max_part_size = max_part_size;
+        self
+    }
+
+    /// Sets the maximum size of the headers of each part.
+    ///
+    /// Defaults to 8 KiB.
+    pub fn max_headers_size(mut self, max_headers_size: usize) -> Self {
+        self.parser.max_headers_size = max_headers_size;
+        self
+    }
+
+    /// Returns the next part of the body, or `None` if all parts have been read.
+    ///
+    /// Any unread contents of the previous part are discarded.
+    pub fn next_part(&mut self) -> Result<Option<Part<'_, I>>, Error> {
+        loop {
+            match self.next_event()? {
+                Event::Headers(headers) => {
+                    return Ok(Some(Part {
+                        headers: PartHeaders::new(headers)?,
+                        multipart: self,
+                        cur: Bytes::new(),
+                        done: false,
+                    }))
+                }
+                Event::Data(_) | Event::PartEnd => {}
+                Event::End => return Ok(None),
+            }
+        }
+    }
+
+    fn next_event(&mut self) -> Result<Event, Error> {
+        loop {
+            if let Some(event) = self.parser.next_event()? {
+                return Ok(event);
+            }
+
+            match self.body.next() {
+                Some(chunk) => self.parser.feed(&chunk?),
+                None => self.parser.feed_eof(),
+            }
+        }
+    }
+}
+
+/// A part of a `multipart/form-data` body.
+pub struct Part<'a, I> {
+    multipart: &'a mut Multipart<I>,
+    headers: PartHeaders,
+    cur: Bytes,
+    done: bool,
+}
+
+impl<I> Part<'_, I>
+where
+    I: Iterator<Item = Result<Bytes, Error>>,
+{
+    /// Returns the part's headers.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers.headers
+    }
+
+    /// Returns the form field name from the part's `Content-Disposition` header.
+    pub fn name(&self) -> Option<&str> {
+        self.headers.name.as_deref()
+    }
+
+    /// Returns the file name from the part's `Content-Disposition` header.
+    ///
+    /// Browsers set this for file uploads. It is provided by the client and must not be trusted as a filesystem path.
+    pub fn file_name(&self) -> Option<&str> {
+        self.headers.file_name.as_deref()
+    }
+
+    /// Returns the part's `Content-Type` header.
+    pub fn content_type(&self) -> Option<&HeaderValue> {
+        self.headers.headers.get(CONTENT_TYPE)
+    }
+
+    /// Returns the next chunk of the part's contents, or `None` if the contents have been fully read.
+    pub fn chunk(&mut self) -> Result<Option<Bytes>, Error> {
+        if !self.cur.is_empty() {
+            return Ok(Some(std::mem::take(&mut self.cur)));
+        }
+
+        if self.done {
+            return Ok(None);
+        }
+
+        match self.multipart.next_event()? {
+            Event::Data(data) => Ok(Some(data)),
+            _ => {
+                self.done = true;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Reads the part's entire contents into memory.
+    pub fn bytes(mut self) -> Result<Bytes, Error> {
+        let mut buf = BytesMut::new();
+        while let Some(chunk) = self.chunk()? {
+            buf.extend_from_slice(&chunk);
+        }
+
+        Ok(buf.freeze())
+    }
+
+    /// Reads the part's entire contents into memory as a UTF-8 string.
+    pub fn text(self) -> Result<String, Error> {
+        let bytes = self.bytes()?;
+        String::from_utf8(bytes.into()).map_err(|e| Error::service(e, InvalidArgument::new()))
+    }
+}
+
+impl<I> Read for Part<'_, I>
+where
+    I: Iterator<Item = Result<Bytes, Error>>,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let in_buf = self.fill_buf()?;
+        let len = usize::min(in_buf.len(), buf.len());
+        buf[..len].copy_from_slice(&in_buf[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<I> BufRead for Part<'_, I>
+where
+    I: Iterator<Item = Result<Bytes, Error>>,
+{
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.cur.is_empty() {
+            if let Some(chunk) = self
+                .chunk()
+                .map_err(|e| io::Error::other(RequestBodyError(e)))?
+            {
+                self.cur = chunk;
+            }
+        }
+
+        Ok(&self.cur)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.cur.advance(amt)
+    }
+}
@@ -25,7 +25,7 @@ struct Node {
 
 struct State<T> {
     head: *mut Node,
-    jobs: VecDeque<T>,
+    jobs: VecDeque<(Option<Instant>, T)>,
 }
 
 unsafe impl<T> Sync for State<T> where T: Sync {}
@@ -33,8 +33,9 @@ unsafe impl<T> Send for State<T> where T: Send {}
 
 /// A blocking queue that is "maximally unfair" to waiters.
 ///
-/// That is, while jobs are processed FIFO, waiters are processed LIFO. This allows us to keep the number of threads in
-/// the pool to the minimum number required to keep up with the current request volume.
+/// That is, while jobs are processed in earliest-deadline-first order (and FIFO among jobs with equal or no deadlines),
+/// waiters are processed LIFO. This allows us to keep the number of threads in the pool to the minimum number required
+/// to keep up with the current request volume.
 ///
 /// To make this happen, we unfortunately need to use a manual queueing implementation with intrusive lists rather than
 /// a simple Mutex + Condvar.
@@ -56,10 +57,19 @@ impl<T> JobQueue<T> {
         self.state.lock().jobs.len()
     }
 
-    pub fn push(&self, job: T) {
+    pub fn push(&self, job: T, deadline: Option<Instant>) {
         let mut state = self.state.lock();
 
-        state.jobs.push_back(job);
+        // Jobs without a deadline sort after all jobs with one.
+        let idx = match deadline {
+            Some(deadline) => state
+                .jobs
+                .iter()
+                .position(|(d, _)| d.map_or(true, |d| d > deadline))
+                .unwrap_or(state.jobs.len()),
+            None => state.jobs.len(),
+        };
+        state.jobs.insert(idx, (deadline, job));
 
         unsafe {
             if !state.head.is_null() {
@@ -76,14 +86,25 @@ impl<T> JobQueue<T> {
     }
 
     pub fn try_pop(&self) -> Option<T> {
-        self.state.lock().jobs.pop_front()
+        self.state.lock().jobs.pop_front().map(|(_, job)| job)
+    }
+
+    /// Removes all jobs whose deadline is at or before `now`.
+    pub fn drain_expired(&self, now: Instant) -> Vec<T> {
+        let mut state = self.state.lock();
+        let expired = state
+            .jobs
+            .iter()
+            .take_while(|(d, _)| d.is_some_and(|d| d <= now))
+            .count();
+        state.jobs.drain(..expired).map(|(_, job)| job).collect()
     }
 
     pub fn pop_until(&self, timeout: Instant) -> Option<T> {
         let mut state = self.state.lock();
 
         loop {
-            if let Some(job) = state.jobs.pop_front() {
+            if let Some((_, job)) = state.jobs.pop_front() {
                 return Some(job);
             }
 
@@ -130,11 +151,11 @@ mod test {
     fn single_threaded() {
         let queue = JobQueue::new();
 
-        queue.push(0);
+        queue.push(0, None);
         assert_eq!(queue.try_pop(), Some(0));
         assert_eq!(queue.try_pop(), None);
 
-        queue.push(1);
+        queue.push(1, None);
         let start = Instant::now();
         assert_eq!(queue.pop_until(start - Duration::from_millis(10)), Some(1));
         let elapsed = start.elapsed();
@@ -165,9 +186,42 @@ mod test {
 
         // wait for threads to get set up
         thread::sleep(Duration::from_secs(1));
-        queue.push(0);
+        queue.push(0, None);
 
         assert_eq!(handle1.join().unwrap(), None);
         assert_eq!(handle2.join().unwrap(), Some(0));
     }
+
+    #[test]
+    fn earliest_deadline_first() {
+        let queue = JobQueue::new();
+        let now = Instant::now();
+
+        queue.push(0, None);
+        queue.push(1, Some(now + Duration::from_secs(2)));
+        queue.push(2, None);
+        queue.push(3, Some(now + Duration::from_secs(1)));
+        queue.push(4, Some(now + Duration::from_secs(2)));
+
+        assert_eq!(queue.try_pop(), Some(3));
+        assert_eq!(queue.try_pop(), Some(1));
+        assert_eq!(queue.try_pop(), Some(4));
+        assert_eq!(queue.try_pop(), Some(0));
+        assert_eq!(queue.try_pop(), Some(2));
+    }
+
+    #[test]
+    fn drain_expired() {
+        let queue = JobQueue::new();
+        let now = Instant::now();
+
+        queue.push(0, None);
+        queue.push(1, Some(now - Duration::from_secs(1)));
+        queue.push(2, Some(now + Duration::from_secs(1)));
+        queue.push(3, Some(now));
+
+        assert_eq!(queue.drain_expired(now), vec![1, 3]);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.try_pop(), Some(2));
+    }
 }
@@ -14,13 +14,15 @@
 use crate::blocking::pool::job_queue::JobQueue;
 use conjure_error::Error;
 use parking_lot::Mutex;
+use refreshable::{Refreshable, Subscription};
 use std::panic::{self, AssertUnwindSafe};
 use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
-use witchcraft_log::error;
-use witchcraft_metrics::MetricRegistry;
+use witchcraft_log::{error, info};
+use witchcraft_metrics::{Meter, MetricRegistry};
 use witchcraft_server_config::install::InstallConfig;
+use witchcraft_server_config::runtime::ThreadPoolConfig;
 
 mod job_queue;
 
@@ -28,6 +30,8 @@ struct State {
     threads: usize,
     idle_threads: usize,
     next_id: usize,
+    min_threads: usize,
+    max_threads: usize,
 }
 
 impl State {
@@ -37,35 +41,125 @@ impl State {
 }
 
 struct Shared {
-    min_threads: usize,
-    max_threads: usize,
     idle_timeout: Duration,
-    queue: JobQueue<Box<dyn FnOnce() + Send>>,
+    queue: JobQueue<Job>,
     state: Mutex<State>,
+    expired: Arc<Meter>,
+}
+
+struct Job {
+    run: Box<dyn FnOnce() + Send>,
+    deadline: Option<(Instant, Box<dyn FnOnce() + Send>)>,
 }
 
 impl Shared {
     fn max(&self) -> usize {
-        self.max_threads
+        self.state.lock().max_threads
     }
 
     fn active(&self) -> usize {
         self.state.lock().active()
     }
 
+    fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
     fn utilization_max(&self) -> f64 {
-        let used = self.state.lock().active();
-        let available = self.max_threads;
-        used as f64 / available as f64
+        let state = self.state.lock();
+        state.active() as f64 / state.max_threads as f64
+    }
+
+    fn resize(self: &Arc<Self>, min_threads: usize, max_threads: usize) {
+        let mut state = self.state.lock();
+        if state.min_threads == min_threads && state.max_threads == max_threads {
+            return;
+        }
+
+        // The pool is sized for the first time when it's created.
+        if state.max_threads != 0 {
+            info!(
+                "resizing blocking thread pool",
+                safe: {
+                    minThreads: min_threads,
+                    maxThreads: max_threads,
+                },
+            );
+        }
+        state.min_threads = min_threads;
+        state.max_threads = max_threads;
+
+        // Surplus threads exit once they finish their current job or time out while idle.
+        while state.threads < min_threads {
+            if !self.add_thread(&mut state) {
+                break;
+            }
+        }
+    }
+
+    fn add_thread(self: &Arc<Self>, state: &mut State) -> bool {
+        if state.threads >= state.max_threads {
+            return false;
+        }
+
+        let id = state.next_id;
+        state.next_id += 1;
+        let r = thread::Builder::new().name(format!("server-{id}")).spawn({
+            let shared = self.clone();
+            move || shared.worker_loop()
+        });
+
+        match r {
+            Ok(_) => {
+                state.threads += 1;
+                true
+            }
+            Err(e) => {
+                error!(
+                    "failed to spawn new worker thread",
+                    error: Error::internal_safe(e),
+                );
+                false
+            }
+        }
     }
 
     fn worker_loop(&self) {
         while let Some(job) = self.get_job() {
-            let _ = panic::catch_unwind(AssertUnwindSafe(job));
+            match job.deadline {
+                Some((deadline, expire)) if deadline <= Instant::now() => self.expire(expire),
+                _ => {
+                    let _ = panic::catch_unwind(AssertUnwindSafe(job.run));
+                }
+            }
+        }
+    }
+
+    // Jobs whose deadline has passed are dropped without running, since their result would be of no use to the client.
+    fn expire_jobs(&self) {
+        for job in self.queue.drain_expired(Instant::now()) {
+            if let Some((_, expire)) = job.deadline {
+                self.expire(expire);
+            }
         }
     }
 
-    fn get_job(&self) -> Option<Box<dyn FnOnce() + Send>> {
+    fn expire(&self, expire: Box<dyn FnOnce() + Send>) {
+        self.expired.mark(1);
+        let _ = panic::catch_unwind(AssertUnwindSafe(expire));
+    }
+
+    fn get_job(&self) -> Option<Job> {
+        {
+            let mut state = self.state.lock();
+            if state.threads > state.max_threads {
+                state.threads -= 1;
+                return None;
+            }
+        }
+
+        self.expire_jobs();
+
         // fast path if there's a job ready
         if let Some(job) = self.queue.try_pop() {
             return Some(job);
@@ -83,7 +177,7 @@ impl Shared {
             match r {
                 Some(job) => return Some(job),
                 None => {
-                    if state.threads > self.min_threads {
+                    if state.threads > state.min_threads {
                         state.threads -= 1;
                         return None;
                     } else {
@@ -97,64 +191,62 @@ impl Shared {
 
 pub struct ThreadPool {
     shared: Arc<Shared>,
+    _subscription: Subscription<ThreadPoolConfig, Error>,
 }
 
 impl ThreadPool {
-    pub fn new(config: &InstallConfig, metrics: &MetricRegistry) -> Self {
-        let pool = ThreadPool {
-            shared: Arc::new(Shared {
-                min_threads: config.server().min_threads(),
-                max_threads: config.server().max_threads(),
-                idle_timeout: config.server().idle_thread_timeout(),
-                queue: JobQueue::new(),
-                state: Mutex::new(State {
-                    threads: 0,
-                    idle_threads: 0,
-                    next_id: 0,
-                }),
+    pub fn new(
+        config: &InstallConfig,
+        runtime: &Refreshable<ThreadPoolConfig, Error>,
+        metrics: &MetricRegistry,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            idle_timeout: config.server().idle_thread_timeout(),
+            queue: JobQueue::new(),
+            state: Mutex::new(State {
+                threads: 0,
+                idle_threads: 0,
+                next_id: 0,
+                min_threads: 0,
+                max_threads: 0,
             }),
-        };
+            expired: metrics.meter("server.worker.expired"),
+        });
 
         metrics.gauge("server.worker.max", {
-            let shared = pool.shared.clone();
+            let shared = shared.clone();
             move || shared.max()
         });
         metrics.gauge("server.worker.active", {
-            let shared = pool.shared.clone();
+            let shared = shared.clone();
             move || shared.active()
         });
+        metrics.gauge("server.worker.queued", {
+            let shared = shared.clone();
+            move || shared.queued()
+        });
         metrics.gauge("server.worker.utilization-max", {
-            let shared = pool.shared.clone();
+            let shared = shared.clone();
             move || shared.utilization_max()
         });
 
-        let mut state = pool.shared.state.lock();
-        for _ in 0..config.server().min_threads() {
-            pool.add_thread(&mut state);
-        }
-        drop(state);
-
-        pool
-    }
-
-    fn add_thread(&self, state: &mut State) {
-        if state.threads >= self.shared.max_threads {
-            return;
-        }
-
-        let id = state.next_id;
-        state.next_id += 1;
-        let r = thread::Builder::new().name(format!("server-{id}")).spawn({
-            let shared = self.shared.clone();
-            move || shared.worker_loop()
+        let subscription = runtime.subscribe({
+            let shared = shared.clone();
+            let min_threads = config.server().min_threads();
+            let max_threads = config.server().max_threads();
+            move |runtime| {
+                let max_threads = runtime.max_threads().unwrap_or(max_threads);
+                let min_threads = runtime
+                    .min_threads()
+                    .unwrap_or(min_threads)
+                    .min(max_threads);
+                shared.resize(min_threads, max_threads);
+            }
         });
 
-        match r {
-            Ok(_) => state.threads += 1,
-            Err(e) => error!(
-                "failed to spawn new worker thread",
-                error: Error::internal_safe(e),
-            ),
+        ThreadPool {
+            shared,
+            _subscription: subscription,
         }
     }
 
@@ -162,18 +254,125 @@ impl ThreadPool {
     where
         F: FnOnce() + 'static + Send,
     {
+        self.try_execute_inner(f, None)
+    }
+
+    /// Like [`Self::try_execute`], but with a deadline by which the job must start.
+    ///
+    /// Queued jobs are run in earliest-deadline-first order. If the deadline passes before a worker picks up the job,
+    /// `expire` is called instead of `f`.
+    pub fn try_execute_until<F, G>(&self, f: F, deadline: Instant, expire: G) -> Result<(), F>
+    where
+        F: FnOnce() + 'static + Send,
+        G: FnOnce() + 'static + Send,
+    {
+        self.try_execute_inner(f, Some((deadline, Box::new(expire))))
+    }
+
+    fn try_execute_inner<F>(
+        &self,
+        f: F,
+        deadline: Option<(Instant, Box<dyn FnOnce() + Send>)>,
+    ) -> Result<(), F>
+    where
+        F: FnOnce() + 'static + Send,
+    {
+        // Expired jobs shouldn't count against the pool's capacity.
+        self.shared.expire_jobs();
+
         let mut state = self.shared.state.lock();
         let current_jobs = self.shared.queue.len() + state.active();
-        if current_jobs >= self.shared.max_threads {
+        if current_jobs >= state.max_threads {
             return Err(f);
         }
 
-        self.shared.queue.push(Box::new(f));
+        let queue_deadline = deadline.as_ref().map(|(deadline, _)| *deadline);
+        self.shared.queue.push(
+            Job {
+                run: Box::new(f),
+                deadline,
+            },
+            queue_deadline,
+        );
 
         if self.shared.queue.len() > state.idle_threads {
-            self.add_thread(&mut state);
+            self.shared.add_thread(&mut state);
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc;
+    use witchcraft_server_config::install::ServerConfig;
+
+    fn threads(pool: &ThreadPool) -> usize {
+        pool.shared.state.lock().threads
+    }
+
+    fn wait_for_threads(pool: &ThreadPool, threads: usize) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while self::threads(pool) != threads {
+            assert!(
+                Instant::now() < deadline,
+                "pool did not reach {threads} threads"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn resize() {
+        let install = InstallConfig::builder()
+            .product_name("foo")
+            .product_version("1.0.0")
+            .port(0)
+            .server(
+                ServerConfig::builder()
+                    .min_threads(1)
+                    .max_threads(2)
+                    .idle_thread_timeout(Duration::from_millis(10))
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        let (runtime, mut handle) = Refreshable::new(ThreadPoolConfig::default());
+        let pool = ThreadPool::new(&install, &runtime, &MetricRegistry::new());
+
+        assert_eq!(threads(&pool), 1);
+        assert_eq!(pool.shared.max(), 2);
+
+        handle
+            .refresh(
+                ThreadPoolConfig::builder()
+                    .min_threads(3)
+                    .max_threads(4)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(threads(&pool), 3);
+        assert_eq!(pool.shared.max(), 4);
+
+        handle
+            .refresh(ThreadPoolConfig::builder().max_threads(1).build().unwrap())
+            .unwrap();
+        assert_eq!(pool.shared.max(), 1);
+        wait_for_threads(&pool, 1);
+
+        let (tx, rx) = mpsc::channel::<()>();
+        assert!(pool
+            .try_execute(move || {
+                let _ = rx.recv();
+            })
+            .is_ok());
+        while pool.shared.active() == 0 {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(pool.try_execute(|| {}).is_err());
+        drop(tx);
+    }
+}
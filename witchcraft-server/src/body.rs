@@ -11,14 +11,19 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use crate::headers::{self, InvalidHeader, TypedHeader};
 use crate::server::RawBody;
 use bytes::{Buf, Bytes, BytesMut};
 use conjure_error::{Error, ErrorCode, ErrorType};
+use conjure_http::server::{
+    AsyncResponseBody, AsyncSerializeResponse, AsyncWriteBody, BoxAsyncWriteBody, ConjureRuntime,
+};
 use conjure_object::Uuid;
 use futures_channel::mpsc;
 use futures_sink::Sink;
-use futures_util::{future, ready, SinkExt, Stream};
-use http::HeaderMap;
+use futures_util::{future, pin_mut, ready, SinkExt, Stream, StreamExt};
+use http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use http::{HeaderMap, HeaderName, HeaderValue, Response};
 use http_body::{Body, Frame};
 use pin_project::pin_project;
 use serde::ser::SerializeStruct;
@@ -26,8 +31,11 @@ use serde::{Serialize, Serializer};
 use std::marker::PhantomPinned;
 use std::pin::Pin;
 use std::task::{Context, Poll};
-use std::{io, mem};
+use std::time::Duration;
+use std::{error, fmt, io, mem};
 use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, ReadBuf};
+use tokio::select;
+use tokio::time::{self, Instant};
 
 /// A streaming request body.
 #[pin_project]
@@ -49,6 +57,15 @@ impl RequestBody {
             _p: PhantomPinned,
         }
     }
+
+    /// Converts the body into an [`Unpin`] stream of its chunks.
+    ///
+    /// The returned value can be consumed directly with the combinators of [`StreamExt`] and
+    /// [`AsyncReadExt`](tokio::io::AsyncReadExt), or passed to a [`StreamingBody`], without pinning it first.
+    pub fn into_stream(self) -> Pin<Box<RequestBody>> {
+        Box::pin(self)
+    }
+
     /// Returns the request's trailers, if any are present.
     ///
     /// The body must have been completely read before this is called.
@@ -59,7 +76,7 @@ impl RequestBody {
     fn poll_next_raw(
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
-    ) -> Poll<Option<Result<Bytes, hyper::Error>>> {
+    ) -> Poll<Option<Result<Bytes, Error>>> {
         let mut this = self.project();
 
         loop {
@@ -95,7 +112,6 @@ impl Stream for RequestBody {
         }
 
         self.poll_next_raw(cx)
-            .map_err(|e| Error::service_safe(e, ClientIo))
     }
 }
 
@@ -119,7 +135,7 @@ impl AsyncBufRead for RequestBody {
         while self.cur.is_empty() {
             match ready!(self.as_mut().poll_next_raw(cx))
                 .transpose()
-                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, RequestBodyError(e)))?
             {
                 Some(bytes) => *self.as_mut().project().cur = bytes,
                 None => break,
@@ -162,7 +178,9 @@ impl ResponseWriter {
 
     /// Like [`SinkExt::send`] except that it sends the response's trailers.
     ///
-    /// The body must be fully written before calling this method.
+    /// The body must be fully written before calling this method. The trailers' names should be declared up front
+    /// with a [`DeclaredTrailers`](crate::extensions::DeclaredTrailers) response extension so that they can be sent
+    /// to HTTP/1.1 clients.
     pub async fn send_trailers(mut self: Pin<&mut Self>, trailers: HeaderMap) -> Result<(), Error> {
         future::poll_fn(|cx| self.as_mut().poll_flush_shallow(cx))
             .await
@@ -280,6 +298,402 @@ impl AsyncWrite for ResponseWriter {
     }
 }
 
+const DEFAULT_STREAMING_BUFFER_SIZE: usize = 8 * 1024;
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// An item of the stream written by a [`StreamingBody`].
+///
+/// This trait is sealed, and implemented for [`Bytes`] and `Result<Bytes, Error>`.
+pub trait BodyChunk: private::Sealed {
+    #[doc(hidden)]
+    fn into_result(self) -> Result<Bytes, Error>;
+}
+
+impl private::Sealed for Bytes {}
+
+impl BodyChunk for Bytes {
+    fn into_result(self) -> Result<Bytes, Error> {
+        Ok(self)
+    }
+}
+
+impl private::Sealed for Result<Bytes, Error> {}
+
+impl BodyChunk for Result<Bytes, Error> {
+    fn into_result(self) -> Result<Bytes, Error> {
+        self
+    }
+}
+
+/// A streaming response body which writes out the chunks of a [`Stream`].
+///
+/// It can be returned directly from an async handler of a Conjure endpoint returning `binary`. Small chunks are
+/// coalesced into writes of up to the buffer size, and the response is flushed whenever the stream has no chunk
+/// immediately available, so data reaches the client promptly without flushing after every chunk. If the client
+/// disconnects, the stream is dropped without being polled further.
+///
+/// If the stream yields an error, the response is aborted.
+///
+/// # Examples
+///
+/// ```ignore
+/// async fn echo(&self, body: RequestBody) -> Result<StreamingBody<Pin<Box<RequestBody>>>, Error> {
+///     Ok(StreamingBody::new(body.into_stream()))
+/// }
+/// ```
+pub struct StreamingBody<S> {
+    stream: S,
+    buffer_size: usize,
+}
+
+impl<S> StreamingBody<S> {
+    /// Creates a new `StreamingBody` writing out the chunks of a stream.
+    pub fn new(stream: S) -> Self {
+        StreamingBody {
+            stream,
+            buffer_size: DEFAULT_STREAMING_BUFFER_SIZE,
+        }
+    }
+
+    /// Sets the size of the buffer used to coalesce small chunks.
+    ///
+    /// Chunks at least this large are written out directly. Defaults to 8 KiB.
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+}
+
+impl<S> AsyncWriteBody<ResponseWriter> for StreamingBody<S>
+where
+    S: Stream + Send,
+    S::Item: BodyChunk + Send,
+{
+    async fn write_body(self, mut w: Pin<&mut ResponseWriter>) -> Result<(), Error> {
+        let stream = self.stream;
+        pin_mut!(stream);
+        let mut buf = BytesMut::new();
+
+        loop {
+            let next = match future::poll_immediate(stream.next()).await {
+                Some(next) => next,
+                None => {
+                    // the stream has stalled, so push out everything written so far before waiting on it
+                    if !buf.is_empty() {
+                        w.feed(buf.split().freeze()).await?;
+                    }
+                    w.flush().await?;
+                    stream.next().await
+                }
+            };
+            let Some(chunk) = next else {
+                break;
+            };
+            let chunk = chunk.into_result()?;
+
+            if buf.is_empty() && chunk.len() >= self.buffer_size {
+                w.feed(chunk).await?;
+                continue;
+            }
+
+            buf.extend_from_slice(&chunk);
+            if buf.len() >= self.buffer_size {
+                w.feed(buf.split().freeze()).await?;
+            }
+        }
+
+        if !buf.is_empty() {
+            w.feed(buf.freeze()).await?;
+        }
+
+        Ok(())
+    }
+}
+
+const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+#[allow(clippy::declare_interior_mutable_const)]
+const TEXT_EVENT_STREAM: HeaderValue = HeaderValue::from_static("text/event-stream");
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_CACHE: HeaderValue = HeaderValue::from_static("no-cache");
+
+/// A Server-Sent Event.
+///
+/// All fields are optional. Multi-line data is split into one `data` field per line, so clients receive it unchanged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Event {
+    event: Option<String>,
+    id: Option<String>,
+    retry: Option<Duration>,
+    data: Option<String>,
+}
+
+impl Event {
+    /// Creates a new empty event.
+    pub fn new() -> Self {
+        Event::default()
+    }
+
+    /// Sets the event's type.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the type contains a carriage return or line feed.
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        let event = event.into();
+        assert!(!has_line_break(&event), "event type contains a line break");
+        self.event = Some(event);
+        self
+    }
+
+    /// Sets the event's ID.
+    ///
+    /// Clients send the ID of the last event they received in the `Last-Event-ID` header when reconnecting.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the ID contains a carriage return, line feed, or NUL character.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        let id = id.into();
+        assert!(
+            !has_line_break(&id) && !id.contains('\0'),
+            "event ID contains a line break or NUL character",
+        );
+        self.id = Some(id);
+        self
+    }
+
+    /// Sets the client's reconnection delay.
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Sets the event's data.
+    pub fn data(mut self, data: impl Into<String>) -> Self {
+        self.data = Some(data.into());
+        self
+    }
+
+    fn encode(&self, buf: &mut BytesMut) {
+        if let Some(event) = &self.event {
+            write_field(buf, "event", event);
+        }
+        if let Some(id) = &self.id {
+            write_field(buf, "id", id);
+        }
+        if let Some(retry) = self.retry {
+            write_field(buf, "retry", &retry.as_millis().to_string());
+        }
+        if let Some(data) = &self.data {
+            for line in data.split('\n') {
+                let line = line.strip_suffix('\r').unwrap_or(line);
+                for line in line.split('\r') {
+                    write_field(buf, "data", line);
+                }
+            }
+        }
+        buf.extend_from_slice(b"\n");
+    }
+}
+
+fn has_line_break(s: &str) -> bool {
+    s.contains(['\r', '\n'])
+}
+
+fn write_field(buf: &mut BytesMut, name: &str, value: &str) {
+    buf.extend_from_slice(name.as_bytes());
+    buf.extend_from_slice(b": ");
+    buf.extend_from_slice(value.as_bytes());
+    buf.extend_from_slice(b"\n");
+}
+
+/// An item of the stream written by an [`EventStream`].
+///
+/// This trait is sealed, and implemented for [`Event`] and `Result<Event, Error>`.
+pub trait EventChunk: private::Sealed {
+    #[doc(hidden)]
+    fn into_result(self) -> Result<Event, Error>;
+}
+
+impl private::Sealed for Event {}
+
+impl EventChunk for Event {
+    fn into_result(self) -> Result<Event, Error> {
+        Ok(self)
+    }
+}
+
+impl private::Sealed for Result<Event, Error> {}
+
+impl EventChunk for Result<Event, Error> {
+    fn into_result(self) -> Result<Event, Error> {
+        self
+    }
+}
+
+/// A Server-Sent Events response body which writes out the events of a [`Stream`].
+///
+/// It should be returned from an async handler of a Conjure endpoint using the [`EventStreamResponseSerializer`].
+/// Events are flushed to the client as soon as the stream has no event immediately available, and a keep-alive
+/// comment is written whenever the stream has been idle for the keep-alive interval so that intermediate proxies don't
+/// time out the connection. Reconnecting clients identify the last event they received with the [`LastEventId`]
+/// header.
+///
+/// If the stream yields an error, the response is aborted.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[endpoint(method = GET, path = "/events", produces = EventStreamResponseSerializer)]
+/// async fn events(
+///     &self,
+///     #[header(name = "Last-Event-ID", decoder = TypedHeaderDecoder)] last_event_id: Option<LastEventId>,
+/// ) -> Result<EventStream<BoxStream<'static, Event>>, Error>;
+/// ```
+pub struct EventStream<S> {
+    stream: S,
+    keep_alive: Duration,
+}
+
+impl<S> EventStream<S> {
+    /// Creates a new `EventStream` writing out the events of a stream.
+    pub fn new(stream: S) -> Self {
+        EventStream {
+            stream,
+            keep_alive: DEFAULT_KEEP_ALIVE,
+        }
+    }
+
+    /// Sets the interval after which a keep-alive comment is written to an idle stream.
+    ///
+    /// Defaults to 15 seconds.
+    pub fn keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+}
+
+impl<S> AsyncWriteBody<ResponseWriter> for EventStream<S>
+where
+    S: Stream + Send,
+    S::Item: EventChunk + Send,
+{
+    async fn write_body(self, mut w: Pin<&mut ResponseWriter>) -> Result<(), Error> {
+        let stream = self.stream;
+        pin_mut!(stream);
+        let keep_alive = time::sleep(self.keep_alive);
+        pin_mut!(keep_alive);
+        let mut buf = BytesMut::new();
+
+        loop {
+            let next = match future::poll_immediate(stream.next()).await {
+                Some(next) => next,
+                None => {
+                    if !buf.is_empty() {
+                        w.feed(buf.split().freeze()).await?;
+                    }
+                    w.flush().await?;
+                    keep_alive.as_mut().reset(Instant::now() + self.keep_alive);
+
+                    loop {
+                        select! {
+                            next = stream.next() => break next,
+                            _ = keep_alive.as_mut() => {
+                                w.send(Bytes::from_static(b":\n\n")).await?;
+                                keep_alive.as_mut().reset(Instant::now() + self.keep_alive);
+                            }
+                        }
+                    }
+                }
+            };
+            let Some(event) = next else {
+                break;
+            };
+
+            event.into_result()?.encode(&mut buf);
+            if buf.len() >= DEFAULT_STREAMING_BUFFER_SIZE {
+                w.feed(buf.split().freeze()).await?;
+            }
+        }
+
+        if !buf.is_empty() {
+            w.feed(buf.freeze()).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A response serializer for [`EventStream`] bodies.
+///
+/// It sets the `Content-Type` of the response to `text/event-stream` and disables caching.
+pub enum EventStreamResponseSerializer {}
+
+impl<S> AsyncSerializeResponse<EventStream<S>, ResponseWriter> for EventStreamResponseSerializer
+where
+    S: Stream + Send + 'static,
+    S::Item: EventChunk + Send,
+{
+    fn serialize(
+        _: &ConjureRuntime,
+        _: &HeaderMap,
+        value: EventStream<S>,
+    ) -> Result<Response<AsyncResponseBody<ResponseWriter>>, Error> {
+        let mut response =
+            Response::new(AsyncResponseBody::Streaming(BoxAsyncWriteBody::new(value)));
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, TEXT_EVENT_STREAM);
+        response.headers_mut().insert(CACHE_CONTROL, NO_CACHE);
+
+        Ok(response)
+    }
+}
+
+/// The `Last-Event-ID` header sent by clients reconnecting to an event stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LastEventId(String);
+
+impl LastEventId {
+    /// Returns the ID of the last event received by the client.
+    pub fn id(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TypedHeader for LastEventId {
+    const NAME: HeaderName = HeaderName::from_static("last-event-id");
+
+    const SAFE: bool = false;
+
+    fn decode<'a, I>(values: I) -> Result<Self, InvalidHeader>
+    where
+        I: IntoIterator<Item = &'a HeaderValue>,
+    {
+        headers::single_value(values).map(|v| LastEventId(v.to_string()))
+    }
+}
+
+/// A standard error wrapping an error reading a request body.
+#[derive(Debug)]
+pub(crate) struct RequestBodyError(pub Error);
+
+impl fmt::Display for RequestBodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.0.cause(), f)
+    }
+}
+
+impl error::Error for RequestBodyError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.0.cause().source()
+    }
+}
+
 pub(crate) struct ClientIo;
 
 impl Serialize for ClientIo {
@@ -317,4 +731,159 @@ mod test {
     fn conjure_error_from_client_io() {
         Error::service_safe("", ClientIo);
     }
+
+    async fn write_streaming<S>(body: StreamingBody<S>) -> (Result<(), Error>, Vec<Bytes>)
+    where
+        S: Stream + Send,
+        S::Item: BodyChunk + Send,
+    {
+        let (sender, receiver) = mpsc::channel(100);
+        let result = {
+            let writer = ResponseWriter::new(sender);
+            pin_mut!(writer);
+            body.write_body(writer).await
+        };
+
+        let frames = receiver
+            .map(|frame| frame.into_data().unwrap())
+            .collect()
+            .await;
+        (result, frames)
+    }
+
+    #[tokio::test]
+    async fn streaming_body_coalesces_chunks() {
+        let chunks = (0..10).map(|_| Bytes::from_static(b"abc"));
+        let body = StreamingBody::new(futures_util::stream::iter(chunks)).buffer_size(8);
+
+        let (result, frames) = write_streaming(body).await;
+        result.unwrap();
+        assert_eq!(frames, ["abcabcabc", "abcabcabc", "abcabcabc", "abc"]);
+    }
+
+    #[tokio::test]
+    async fn streaming_body_flushes_when_stalled() {
+        let (tx, rx) = mpsc::unbounded();
+        tx.unbounded_send(Bytes::from_static(b"hello")).unwrap();
+
+        let (sender, mut receiver) = mpsc::channel(100);
+        let handle = tokio::spawn(async move {
+            let writer = ResponseWriter::new(sender);
+            pin_mut!(writer);
+            StreamingBody::new(rx).write_body(writer).await
+        });
+
+        let frame = receiver.next().await.unwrap();
+        assert_eq!(frame.into_data().unwrap(), "hello");
+
+        drop(tx);
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn streaming_body_stops_on_disconnect() {
+        let (sender, receiver) = mpsc::channel(100);
+        drop(receiver);
+
+        let stream = futures_util::stream::repeat(Bytes::from_static(b"abc"));
+        let writer = ResponseWriter::new(sender);
+        pin_mut!(writer);
+        StreamingBody::new(stream)
+            .buffer_size(1)
+            .write_body(writer)
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn streaming_body_propagates_errors() {
+        let chunks = vec![
+            Ok(Bytes::from_static(b"abc")),
+            Err(Error::internal_safe("blammo")),
+        ];
+        let body = StreamingBody::new(futures_util::stream::iter(chunks));
+
+        let (result, _) = write_streaming(body).await;
+        result.unwrap_err();
+    }
+
+    #[test]
+    fn event_encoding() {
+        let event = Event::new()
+            .event("update")
+            .id("5")
+            .retry(Duration::from_secs(2))
+            .data("foo\nbar\r\nbaz\rqux");
+
+        let mut buf = BytesMut::new();
+        event.encode(&mut buf);
+        assert_eq!(
+            buf,
+            "event: update\nid: 5\nretry: 2000\ndata: foo\ndata: bar\ndata: baz\ndata: qux\n\n",
+        );
+    }
+
+    #[tokio::test]
+    async fn event_stream_writes_events() {
+        let (sender, receiver) = mpsc::channel(100);
+        let events = vec![Event::new().data("a"), Event::new().data("b")];
+        {
+            let writer = ResponseWriter::new(sender);
+            pin_mut!(writer);
+            EventStream::new(futures_util::stream::iter(events))
+                .write_body(writer)
+                .await
+                .unwrap();
+        }
+
+        let frames = receiver
+            .map(|frame| frame.into_data().unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(frames, ["data: a\n\ndata: b\n\n"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn event_stream_keep_alive() {
+        let (tx, rx) = mpsc::unbounded();
+
+        let (sender, mut receiver) = mpsc::channel(100);
+        let handle = tokio::spawn(async move {
+            let writer = ResponseWriter::new(sender);
+            pin_mut!(writer);
+            EventStream::new(rx)
+                .keep_alive(Duration::from_secs(1))
+                .write_body(writer)
+                .await
+        });
+
+        let frame = receiver.next().await.unwrap();
+        assert_eq!(frame.into_data().unwrap(), ":\n\n");
+
+        tx.unbounded_send(Event::new().data("hello")).unwrap();
+        let frame = receiver.next().await.unwrap();
+        assert_eq!(frame.into_data().unwrap(), "data: hello\n\n");
+
+        drop(tx);
+        handle.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn event_stream_response_headers() {
+        let response = <EventStreamResponseSerializer as AsyncSerializeResponse<_, _>>::serialize(
+            &ConjureRuntime::new(),
+            &HeaderMap::new(),
+            EventStream::new(futures_util::stream::empty::<Event>()),
+        )
+        .unwrap();
+
+        assert_eq!(response.headers()[CONTENT_TYPE], "text/event-stream");
+        assert_eq!(response.headers()[CACHE_CONTROL], "no-cache");
+    }
+
+    #[test]
+    #[should_panic]
+    fn event_id_rejects_line_breaks() {
+        Event::new().id("a\nb");
+    }
 }
@@ -0,0 +1,168 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Declarative endpoint caching.
+//!
+//! By default, the server disables caching of responses to `GET` requests which do not already contain a
+//! `Cache-Control` header. A [`CachePolicy`] registered for an endpoint with [`Witchcraft::cache_policy`] instead
+//! renders its `Cache-Control` and `Vary` headers onto the endpoint's successful responses, so handlers don't need to
+//! set them by hand. Error responses are never cached, and a `Cache-Control` header set by the handler takes
+//! precedence over the policy.
+//!
+//! # Examples
+//!
+//! ```
+//! use http::header::ACCEPT_LANGUAGE;
+//! use std::time::Duration;
+//! use witchcraft_server::cache::CachePolicy;
+//! # fn register(witchcraft: &mut witchcraft_server::Witchcraft) {
+//!
+//! witchcraft.cache_policy(
+//!     "CatalogService",
+//!     "getCatalog",
+//!     CachePolicy::public(Duration::from_secs(300)).with_vary(ACCEPT_LANGUAGE),
+//! );
+//! # }
+//! ```
+//!
+//! [`Witchcraft::cache_policy`]: crate::Witchcraft::cache_policy
+use conjure_http::server::EndpointMetadata;
+use http::{HeaderName, HeaderValue};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// The cacheability of an endpoint's successful responses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachePolicy {
+    public: bool,
+    max_age: Duration,
+    vary: Vec<HeaderName>,
+}
+
+impl CachePolicy {
+    /// Creates a policy allowing responses to be cached by the client only, for up to the specified duration.
+    pub fn private(max_age: Duration) -> Self {
+        CachePolicy {
+            public: false,
+            max_age,
+            vary: vec![],
+        }
+    }
+
+    /// Creates a policy allowing responses to be cached by the client and shared caches, for up to the specified
+    /// duration.
+    pub fn public(max_age: Duration) -> Self {
+        CachePolicy {
+            public: true,
+            max_age,
+            vary: vec![],
+        }
+    }
+
+    /// Adds a request header which the response varies on.
+    #[inline]
+    pub fn with_vary(mut self, header: HeaderName) -> Self {
+        self.vary.push(header);
+        self
+    }
+
+    /// Returns `true` if responses can be stored in shared caches.
+    #[inline]
+    pub fn is_public(&self) -> bool {
+        self.public
+    }
+
+    /// Returns the maximum age of cached responses.
+    #[inline]
+    pub fn max_age(&self) -> Duration {
+        self.max_age
+    }
+
+    /// Returns the request headers which responses vary on.
+    #[inline]
+    pub fn vary(&self) -> &[HeaderName] {
+        &self.vary
+    }
+
+    fn cache_control(&self) -> HeaderValue {
+        let visibility = if self.public { "public" } else { "private" };
+        HeaderValue::try_from(format!("{visibility}, max-age={}", self.max_age.as_secs())).unwrap()
+    }
+
+    fn vary_header(&self) -> Option<HeaderValue> {
+        if self.vary.is_empty() {
+            return None;
+        }
+
+        let vary = self
+            .vary
+            .iter()
+            .map(|h| h.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        Some(HeaderValue::try_from(vary).unwrap())
+    }
+}
+
+/// The headers rendered from a [`CachePolicy`].
+pub(crate) struct CacheHeaders {
+    pub cache_control: HeaderValue,
+    pub vary: Option<HeaderValue>,
+}
+
+/// The cache policies registered for endpoints, keyed by service and endpoint name.
+#[derive(Default)]
+pub(crate) struct CachePolicies {
+    services: HashMap<String, HashMap<String, CacheHeaders>>,
+}
+
+impl CachePolicies {
+    pub fn insert(&mut self, service_name: &str, endpoint_name: &str, policy: &CachePolicy) {
+        self.services
+            .entry(service_name.to_string())
+            .or_default()
+            .insert(
+                endpoint_name.to_string(),
+                CacheHeaders {
+                    cache_control: policy.cache_control(),
+                    vary: policy.vary_header(),
+                },
+            );
+    }
+
+    pub fn get(&self, endpoint: &dyn EndpointMetadata) -> Option<&CacheHeaders> {
+        self.services
+            .get(endpoint.service_name())?
+            .get(endpoint.name())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use http::header::{ACCEPT, ACCEPT_LANGUAGE};
+
+    #[test]
+    fn rendering() {
+        let policy = CachePolicy::private(Duration::from_secs(60));
+        assert_eq!(policy.cache_control(), "private, max-age=60");
+        assert_eq!(policy.vary_header(), None);
+
+        let policy = CachePolicy::public(Duration::from_millis(90_500))
+            .with_vary(ACCEPT)
+            .with_vary(ACCEPT_LANGUAGE);
+        assert_eq!(policy.cache_control(), "public, max-age=90");
+        assert_eq!(policy.vary_header().unwrap(), "accept, accept-language");
+    }
+}
@@ -0,0 +1,43 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Classification of requests.
+//!
+//! A [`RequestClassifier`] installed via [`Witchcraft::request_classifier`] is invoked with the method, path, and
+//! headers of each request to the service port. Its result is inserted into the request's extensions as a
+//! [`RequestClass`], and its labels are recorded in the `requestClass` parameter of the request log and used to tag
+//! the `server.request.classified` metric. Low [`Priority`] requests are rejected while the server is shedding load
+//! due to high memory usage.
+//!
+//! Callers in the networks listed in the `request-priority.trusted-networks` runtime configuration can additionally
+//! set the priority of their requests with the [`REQUEST_PRIORITY`] header, for example to mark batch traffic as
+//! `low` priority so that it is shed first. The header's value is one of `low`, `normal`, or `critical`, and it
+//! overrides the priority assigned by the classifier. Rate limiters can take the priority into account with
+//! [`TokenBucket::try_acquire_with_priority`].
+//!
+//! [`Witchcraft::request_classifier`]: crate::Witchcraft::request_classifier
+//! [`TokenBucket::try_acquire_with_priority`]: crate::throttle::TokenBucket::try_acquire_with_priority
+pub use crate::extensions::{Priority, RequestClass};
+use http::{HeaderMap, HeaderName, Method};
+
+/// The header trusted callers use to set the priority of their requests.
+#[allow(clippy::declare_interior_mutable_const)]
+pub const REQUEST_PRIORITY: HeaderName = HeaderName::from_static("request-priority");
+
+/// A classifier of requests.
+pub trait RequestClassifier {
+    /// Returns the classification of a request.
+    ///
+    /// This is called synchronously for every request, so implementations should not block.
+    fn classify(&self, method: &Method, path: &str, headers: &HeaderMap) -> RequestClass;
+}
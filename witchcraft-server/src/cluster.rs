@@ -0,0 +1,238 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Cluster membership reporting.
+//!
+//! Sharded and replicated services can describe the cluster they belong to by installing a [`ClusterInfoProvider`] via
+//! [`Witchcraft::cluster_info_provider`]. The provider is invoked on demand, and its [`ClusterInfo`] is served as JSON
+//! from the `/status/cluster` endpoint and the `cluster.info.v1` diagnostic, giving operators and tooling a consistent
+//! view of cluster state across services.
+//!
+//! [`Witchcraft::cluster_info_provider`]: crate::Witchcraft::cluster_info_provider
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::sync::Arc;
+
+/// A provider of information about the cluster the server is a member of.
+pub trait ClusterInfoProvider {
+    /// Returns the current state of the cluster.
+    ///
+    /// This is called synchronously when the information is requested, so implementations should not block.
+    fn cluster_info(&self) -> ClusterInfo;
+}
+
+/// Information about a cluster of server instances.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClusterInfo {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shard: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    local_member: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    leader: Option<String>,
+    members: Vec<ClusterMember>,
+}
+
+impl ClusterInfo {
+    /// Creates a new `ClusterInfo` for the named cluster with no members.
+    pub fn new(name: impl Into<String>) -> Self {
+        ClusterInfo {
+            name: name.into(),
+            shard: None,
+            local_member: None,
+            leader: None,
+            members: vec![],
+        }
+    }
+
+    /// Sets the shard of the cluster the server is responsible for.
+    pub fn with_shard(mut self, shard: impl Into<String>) -> Self {
+        self.shard = Some(shard.into());
+        self
+    }
+
+    /// Sets the ID of the member corresponding to this server.
+    pub fn with_local_member(mut self, id: impl Into<String>) -> Self {
+        self.local_member = Some(id.into());
+        self
+    }
+
+    /// Sets the ID of the cluster's leader.
+    pub fn with_leader(mut self, id: impl Into<String>) -> Self {
+        self.leader = Some(id.into());
+        self
+    }
+
+    /// Adds a member to the cluster.
+    pub fn with_member(mut self, member: ClusterMember) -> Self {
+        self.members.push(member);
+        self
+    }
+
+    /// Returns the name of the cluster.
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the shard of the cluster the server is responsible for.
+    #[inline]
+    pub fn shard(&self) -> Option<&str> {
+        self.shard.as_deref()
+    }
+
+    /// Returns the ID of the member corresponding to this server.
+    #[inline]
+    pub fn local_member(&self) -> Option<&str> {
+        self.local_member.as_deref()
+    }
+
+    /// Returns the ID of the cluster's leader.
+    #[inline]
+    pub fn leader(&self) -> Option<&str> {
+        self.leader.as_deref()
+    }
+
+    /// Returns the members of the cluster.
+    #[inline]
+    pub fn members(&self) -> &[ClusterMember] {
+        &self.members
+    }
+}
+
+/// A member of a cluster.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClusterMember {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    address: Option<String>,
+    state: MemberState,
+}
+
+impl ClusterMember {
+    /// Creates a new active `ClusterMember` with the specified ID.
+    pub fn new(id: impl Into<String>) -> Self {
+        ClusterMember {
+            id: id.into(),
+            address: None,
+            state: MemberState::Active,
+        }
+    }
+
+    /// Sets the address peers use to reach the member.
+    pub fn with_address(mut self, address: impl Into<String>) -> Self {
+        self.address = Some(address.into());
+        self
+    }
+
+    /// Sets the state of the member.
+    pub fn with_state(mut self, state: MemberState) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// Returns the member's ID.
+    #[inline]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns the address peers use to reach the member.
+    #[inline]
+    pub fn address(&self) -> Option<&str> {
+        self.address.as_deref()
+    }
+
+    /// Returns the state of the member.
+    #[inline]
+    pub fn state(&self) -> MemberState {
+        self.state
+    }
+}
+
+/// The state of a member of a cluster.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[non_exhaustive]
+pub enum MemberState {
+    /// The member is joining the cluster and is not yet serving its share of the work.
+    Joining,
+    /// The member is serving its share of the work.
+    Active,
+    /// The member is leaving the cluster.
+    Leaving,
+    /// The member cannot be reached by this server.
+    Unreachable,
+}
+
+pub(crate) struct ClusterRegistry {
+    provider: Mutex<Option<Arc<dyn ClusterInfoProvider + Sync + Send>>>,
+}
+
+impl ClusterRegistry {
+    pub fn new() -> Self {
+        ClusterRegistry {
+            provider: Mutex::new(None),
+        }
+    }
+
+    pub fn set_provider(&self, provider: Arc<dyn ClusterInfoProvider + Sync + Send>) {
+        *self.provider.lock() = Some(provider);
+    }
+
+    pub fn cluster_info(&self) -> Option<ClusterInfo> {
+        let provider = self.provider.lock().clone();
+        provider.map(|p| p.cluster_info())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct TestProvider;
+
+    impl ClusterInfoProvider for TestProvider {
+        fn cluster_info(&self) -> ClusterInfo {
+            ClusterInfo::new("cache")
+                .with_shard("shard-1")
+                .with_local_member("node-1")
+                .with_member(ClusterMember::new("node-1").with_address("10.0.0.1:8443"))
+                .with_member(ClusterMember::new("node-2").with_state(MemberState::Unreachable))
+        }
+    }
+
+    #[test]
+    fn serialization() {
+        let registry = ClusterRegistry::new();
+        assert_eq!(registry.cluster_info(), None);
+
+        registry.set_provider(Arc::new(TestProvider));
+        let info = serde_json::to_value(registry.cluster_info().unwrap()).unwrap();
+        assert_eq!(
+            info,
+            serde_json::json!({
+                "name": "cache",
+                "shard": "shard-1",
+                "localMember": "node-1",
+                "members": [
+                    {"id": "node-1", "address": "10.0.0.1:8443", "state": "ACTIVE"},
+                    {"id": "node-2", "state": "UNREACHABLE"},
+                ],
+            }),
+        );
+    }
+}
@@ -15,6 +15,7 @@ use conjure_error::Error;
 use refreshable::{RefreshHandle, Refreshable};
 use serde::de::DeserializeOwned;
 use serde_encrypted_value::{Key, ReadOnly};
+use serde_yaml::{Mapping, Value};
 use sha2::digest::Output;
 use sha2::{Digest, Sha256};
 use std::collections::{HashMap, HashSet};
@@ -25,20 +26,34 @@ use std::time::Duration;
 use std::{fs, io};
 use tokio::runtime::Handle;
 use tokio::{task, time};
-use witchcraft_log::{error, info};
+use witchcraft_log::{error, info, warn};
 
 const RELOAD_INTERVAL: Duration = Duration::from_secs(3);
 const INSTALL_YML: &str = "var/conf/install.yml";
 const RUNTIME_YML: &str = "var/conf/runtime.yml";
 const ENCRYPTED_CONFIG_VALUE_KEY: &str = "var/conf/encrypted-config-value.key";
 
+/// A configuration key which has been renamed.
+///
+/// The old key is still accepted and moved to its new location before the config is deserialized.
+struct RenamedKey {
+    old: &'static [&'static str],
+    new: &'static [&'static str],
+    /// The version in which the old key will no longer be accepted.
+    removal: &'static str,
+}
+
+const INSTALL_RENAMED_KEYS: &[RenamedKey] = &[];
+
+const RUNTIME_RENAMED_KEYS: &[RenamedKey] = &[];
+
 pub fn load_install<T>() -> Result<T, Error>
 where
     T: DeserializeOwned,
 {
     let key = load_key()?;
     let bytes = load_file(INSTALL_YML)?;
-    parse(&bytes, key.as_ref()).0
+    parse(INSTALL_YML, &bytes, INSTALL_RENAMED_KEYS, key.as_ref()).0
 }
 
 pub fn load_runtime<T>(
@@ -50,7 +65,7 @@ where
 {
     let key = load_key()?;
     let bytes = load_file(RUNTIME_YML)?;
-    let (value, files) = parse(&bytes, key.as_ref());
+    let (value, files) = parse(RUNTIME_YML, &bytes, RUNTIME_RENAMED_KEYS, key.as_ref());
     let value = value?;
 
     let (refreshable, handle) = Refreshable::new(value);
@@ -113,7 +128,12 @@ fn load_file(path: &str) -> Result<Vec<u8>, Error> {
     fs::read(path).map_err(|e| Error::internal_safe(e).with_safe_param("path", path))
 }
 
-fn parse<T>(raw: &[u8], key: Option<&Key<ReadOnly>>) -> (Result<T, Error>, ConfigFiles)
+fn parse<T>(
+    path: &str,
+    raw: &[u8],
+    renamed_keys: &[RenamedKey],
+    key: Option<&Key<ReadOnly>>,
+) -> (Result<T, Error>, ConfigFiles)
 where
     T: DeserializeOwned,
 {
@@ -124,12 +144,116 @@ where
     };
     let mut callback = |path: &Path, r: &io::Result<Vec<u8>>| files.add(path, r);
 
-    let de = serde_yaml::Deserializer::from_slice(raw);
+    // Deserializing from the YAML source directly preserves the location of errors, so the config is only converted to
+    // a Value when a renamed key has to be moved.
+    let value = match migrated(path, raw, renamed_keys) {
+        Some(value) => deserialize(value, key, &mut callback),
+        None => deserialize(
+            serde_yaml::Deserializer::from_slice(raw),
+            key,
+            &mut callback,
+        ),
+    };
+    (value, files)
+}
+
+fn deserialize<'de, T, D, F>(
+    de: D,
+    key: Option<&Key<ReadOnly>>,
+    callback: &mut F,
+) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+    D: serde::Deserializer<'de>,
+    F: FnMut(&Path, &io::Result<Vec<u8>>),
+{
     let de = serde_encrypted_value::Deserializer::new(de, key);
-    let de = serde_file_value::Deserializer::new(de, &mut callback);
+    let de = serde_file_value::Deserializer::new(de, callback);
+    T::deserialize(de).map_err(Error::internal)
+}
 
-    let value = T::deserialize(de).map_err(Error::internal);
-    (value, files)
+// Returns None if the config doesn't contain any renamed keys, or can't be parsed at all.
+fn migrated(path: &str, raw: &[u8], renamed_keys: &[RenamedKey]) -> Option<Value> {
+    if renamed_keys.is_empty() {
+        return None;
+    }
+
+    let mut value = serde_yaml::from_slice(raw).ok()?;
+    if migrate(path, &mut value, renamed_keys) {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+// Returns true if any renamed keys were present.
+fn migrate(path: &str, value: &mut Value, renamed_keys: &[RenamedKey]) -> bool {
+    let mut migrated = false;
+    for renamed in renamed_keys {
+        let Some(old_value) = remove(value, renamed.old) else {
+            continue;
+        };
+        migrated = true;
+
+        let old_key = renamed.old.join(".");
+        let new_key = renamed.new.join(".");
+        if insert(value, renamed.new, old_value) {
+            warn!(
+                "config key has been renamed",
+                safe: {
+                    file: path,
+                    oldKey: old_key,
+                    newKey: new_key,
+                    removalVersion: renamed.removal,
+                },
+            );
+        } else {
+            warn!(
+                "ignoring renamed config key since its replacement is also set",
+                safe: {
+                    file: path,
+                    oldKey: old_key,
+                    newKey: new_key,
+                    removalVersion: renamed.removal,
+                },
+            );
+        }
+    }
+
+    migrated
+}
+
+fn remove(value: &mut Value, path: &[&str]) -> Option<Value> {
+    let (last, parents) = path.split_last()?;
+    let mut mapping = value.as_mapping_mut()?;
+    for parent in parents {
+        mapping = mapping.get_mut(*parent)?.as_mapping_mut()?;
+    }
+    mapping.remove(*last)
+}
+
+// Returns false without modifying the value if the key is already present.
+fn insert(value: &mut Value, path: &[&str], new_value: Value) -> bool {
+    let Some((last, parents)) = path.split_last() else {
+        return false;
+    };
+    let Some(mut mapping) = value.as_mapping_mut() else {
+        return false;
+    };
+    for parent in parents {
+        let child = mapping
+            .entry(Value::from(*parent))
+            .or_insert_with(|| Value::Mapping(Mapping::new()));
+        mapping = match child.as_mapping_mut() {
+            Some(mapping) => mapping,
+            None => return false,
+        };
+    }
+    if mapping.contains_key(*last) {
+        return false;
+    }
+    mapping.insert(Value::from(*last), new_value);
+    true
 }
 
 async fn runtime_reload<T>(
@@ -161,7 +285,8 @@ async fn runtime_reload<T>(
                 return;
             }
 
-            let (value, new_files) = parse(&new_bytes, key.as_ref());
+            let (value, new_files) =
+                parse(RUNTIME_YML, &new_bytes, RUNTIME_RENAMED_KEYS, key.as_ref());
             files = new_files;
             let value = match value {
                 Ok(value) => value,
@@ -186,3 +311,82 @@ async fn runtime_reload<T>(
         });
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::Deserialize;
+
+    const RENAMED_KEYS: &[RenamedKey] = &[RenamedKey {
+        old: &["server", "old-key"],
+        new: &["server", "nested", "new-key"],
+        removal: "5.0.0",
+    }];
+
+    #[test]
+    fn migrate_renamed_key() {
+        let mut value = serde_yaml::from_str::<Value>("server: { old-key: 1, other: 2 }").unwrap();
+        assert!(migrate("test.yml", &mut value, RENAMED_KEYS));
+
+        let expected =
+            serde_yaml::from_str::<Value>("server: { other: 2, nested: { new-key: 1 } }").unwrap();
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn migrate_prefers_new_key() {
+        let mut value =
+            serde_yaml::from_str::<Value>("server: { old-key: 1, nested: { new-key: 2 } }")
+                .unwrap();
+        assert!(migrate("test.yml", &mut value, RENAMED_KEYS));
+
+        let expected = serde_yaml::from_str::<Value>("server: { nested: { new-key: 2 } }").unwrap();
+        assert_eq!(value, expected);
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    #[serde(rename_all = "kebab-case")]
+    struct TestConfig {
+        server: TestServerConfig,
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    #[serde(rename_all = "kebab-case")]
+    struct TestServerConfig {
+        nested: TestNestedConfig,
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    #[serde(rename_all = "kebab-case")]
+    struct TestNestedConfig {
+        new_key: u32,
+    }
+
+    #[test]
+    fn parse_renamed_key() {
+        let (config, _) =
+            parse::<TestConfig>("test.yml", b"server: { old-key: 1 }", RENAMED_KEYS, None);
+        assert_eq!(
+            config.unwrap(),
+            TestConfig {
+                server: TestServerConfig {
+                    nested: TestNestedConfig { new_key: 1 },
+                },
+            },
+        );
+    }
+
+    #[test]
+    fn parse_errors_have_locations() {
+        let (config, _) = parse::<TestConfig>(
+            "test.yml",
+            b"server:\n  nested:\n    new-key: one\n",
+            RENAMED_KEYS,
+            None,
+        );
+        assert!(
+            config.unwrap_err().cause().to_string().contains("line 3"),
+            "error should include the location",
+        );
+    }
+}
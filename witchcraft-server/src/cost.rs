@@ -0,0 +1,220 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Per-tenant cost accounting.
+//!
+//! A [`CostAttributor`] installed via [`Witchcraft::cost_attributor`] is invoked with the method, path, headers, and
+//! extensions of each request to the service port to determine the tenant it is made on behalf of. The resources used
+//! by each request are summed per tenant, and once a minute the totals are written to the service log as a
+//! `request cost summary` message for each tenant, with the following safe parameters:
+//!
+//! * `tenant` - The tenant, or `unattributed` for requests the attributor did not assign to a tenant.
+//! * `periodSeconds` - The length of the period the totals cover.
+//! * `requests` - The number of requests completed.
+//! * `cpuMicros` - The CPU time spent handling the requests. Only measured on Linux, and only when enabled by the
+//!     runtime configuration's `cpu-time.enabled` value.
+//! * `requestBytes` - The number of request body bytes read, before decompression.
+//! * `responseBytes` - The number of response body bytes written, after compression.
+//! * `outboundRequests` - The number of outbound requests tracked with the [`outbound`](crate::outbound) module.
+//!
+//! Requests are included in the summary for the period in which their response completes. Any remaining totals are
+//! written when the server shuts down.
+//!
+//! [`Witchcraft::cost_attributor`]: crate::Witchcraft::cost_attributor
+use http::{Extensions, HeaderMap, Method};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::mem;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time;
+use tokio_util::sync::CancellationToken;
+use witchcraft_log::info;
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(60);
+const UNATTRIBUTED: &str = "unattributed";
+
+/// An attributor of requests to tenants.
+pub trait CostAttributor {
+    /// Returns the tenant a request is made on behalf of, such as an organization or API key ID.
+    ///
+    /// The tenant is logged as a safe parameter, so it must not contain sensitive information like the API key
+    /// itself. This is called synchronously for every request, so implementations should not block.
+    fn tenant(
+        &self,
+        method: &Method,
+        path: &str,
+        headers: &HeaderMap,
+        extensions: &Extensions,
+    ) -> Option<String>;
+}
+
+#[derive(Default, Debug, PartialEq)]
+pub(crate) struct Usage {
+    pub(crate) requests: u64,
+    pub(crate) cpu_time: Duration,
+    pub(crate) request_bytes: u64,
+    pub(crate) response_bytes: u64,
+    pub(crate) outbound_requests: u64,
+}
+
+impl Usage {
+    fn add(&mut self, other: Usage) {
+        self.requests += other.requests;
+        self.cpu_time += other.cpu_time;
+        self.request_bytes += other.request_bytes;
+        self.response_bytes += other.response_bytes;
+        self.outbound_requests += other.outbound_requests;
+    }
+}
+
+struct Period {
+    start: Instant,
+    usage: HashMap<String, Usage>,
+}
+
+/// The per-tenant usage totals of the current reporting period.
+pub(crate) struct CostLedger {
+    attributor: Arc<dyn CostAttributor + Sync + Send>,
+    period: Mutex<Period>,
+}
+
+impl CostLedger {
+    pub(crate) fn new(attributor: Arc<dyn CostAttributor + Sync + Send>) -> Self {
+        CostLedger {
+            attributor,
+            period: Mutex::new(Period {
+                start: Instant::now(),
+                usage: HashMap::new(),
+            }),
+        }
+    }
+
+    pub(crate) fn tenant(
+        &self,
+        method: &Method,
+        path: &str,
+        headers: &HeaderMap,
+        extensions: &Extensions,
+    ) -> String {
+        self.attributor
+            .tenant(method, path, headers, extensions)
+            .unwrap_or_else(|| UNATTRIBUTED.to_string())
+    }
+
+    pub(crate) fn record(&self, tenant: String, usage: Usage) {
+        self.period
+            .lock()
+            .usage
+            .entry(tenant)
+            .or_default()
+            .add(usage);
+    }
+
+    pub(crate) async fn run(self: Arc<Self>, shutdown: CancellationToken) {
+        let mut interval = time::interval(REPORT_INTERVAL);
+        // The first tick completes immediately.
+        interval.tick().await;
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => self.report(),
+                _ = shutdown.cancelled() => break,
+            }
+        }
+
+        self.report();
+    }
+
+    fn report(&self) {
+        let (elapsed, usage) = {
+            let mut period = self.period.lock();
+            let start = mem::replace(&mut period.start, Instant::now());
+            (start.elapsed(), mem::take(&mut period.usage))
+        };
+
+        for (tenant, usage) in usage {
+            info!(
+                "request cost summary",
+                safe: {
+                    tenant: tenant,
+                    periodSeconds: elapsed.as_secs(),
+                    requests: usage.requests,
+                    cpuMicros: usage.cpu_time.as_micros() as u64,
+                    requestBytes: usage.request_bytes,
+                    responseBytes: usage.response_bytes,
+                    outboundRequests: usage.outbound_requests,
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct HeaderAttributor;
+
+    impl CostAttributor for HeaderAttributor {
+        fn tenant(
+            &self,
+            _: &Method,
+            _: &str,
+            headers: &HeaderMap,
+            _: &Extensions,
+        ) -> Option<String> {
+            headers
+                .get("tenant")
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        }
+    }
+
+    #[test]
+    fn aggregate_by_tenant() {
+        let ledger = CostLedger::new(Arc::new(HeaderAttributor));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("tenant", "acme".parse().unwrap());
+        let acme = ledger.tenant(&Method::GET, "/", &headers, &Extensions::new());
+        let other = ledger.tenant(&Method::GET, "/", &HeaderMap::new(), &Extensions::new());
+        assert_eq!(acme, "acme");
+        assert_eq!(other, UNATTRIBUTED);
+
+        for bytes in [10, 20] {
+            ledger.record(
+                acme.clone(),
+                Usage {
+                    requests: 1,
+                    response_bytes: bytes,
+                    outbound_requests: 2,
+                    ..Usage::default()
+                },
+            );
+        }
+        ledger.record(other, Usage::default());
+
+        let period = ledger.period.lock();
+        assert_eq!(
+            period.usage["acme"],
+            Usage {
+                requests: 2,
+                response_bytes: 30,
+                outbound_requests: 4,
+                ..Usage::default()
+            },
+        );
+        assert_eq!(period.usage.len(), 2);
+    }
+}
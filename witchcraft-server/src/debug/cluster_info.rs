@@ -0,0 +1,57 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::cluster::ClusterRegistry;
+use crate::debug::Diagnostic;
+use bytes::Bytes;
+use conjure_error::{Error, NotFound};
+use conjure_serde::json;
+use http::HeaderValue;
+use std::sync::Arc;
+
+pub struct ClusterInfoDiagnostic {
+    cluster: Arc<ClusterRegistry>,
+}
+
+impl ClusterInfoDiagnostic {
+    pub fn new(cluster: &Arc<ClusterRegistry>) -> Self {
+        ClusterInfoDiagnostic {
+            cluster: cluster.clone(),
+        }
+    }
+}
+
+impl Diagnostic for ClusterInfoDiagnostic {
+    fn type_(&self) -> &str {
+        "cluster.info.v1"
+    }
+
+    fn content_type(&self) -> HeaderValue {
+        HeaderValue::from_static("application/json")
+    }
+
+    fn safe_loggable(&self) -> bool {
+        false
+    }
+
+    fn result(&self) -> Result<Bytes, Error> {
+        let Some(info) = self.cluster.cluster_info() else {
+            return Err(Error::service_safe(
+                "no cluster info provider installed",
+                NotFound::new(),
+            ));
+        };
+        let body = json::to_vec(&info).unwrap();
+        Ok(Bytes::from(body))
+    }
+}
@@ -0,0 +1,267 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::debug::Diagnostic;
+use crate::health::service_dependency::{self, IDLE_TIMEOUT};
+use bytes::Bytes;
+use conjure_error::Error;
+use conjure_runtime::config::ServicesConfig;
+use conjure_runtime::{HostMetrics, HostMetricsRegistry};
+use conjure_serde::json;
+use http::HeaderValue;
+use refreshable::Refreshable;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// A diagnostic which returns the JSON-formatted dependencies of the service along with their current health.
+pub struct DependencyGraphDiagnostic {
+    service: String,
+    config: Refreshable<ServicesConfig, Error>,
+    host_metrics: Arc<HostMetricsRegistry>,
+}
+
+impl DependencyGraphDiagnostic {
+    pub fn new(
+        service: &str,
+        config: Refreshable<ServicesConfig, Error>,
+        host_metrics: &Arc<HostMetricsRegistry>,
+    ) -> Self {
+        DependencyGraphDiagnostic {
+            service: service.to_string(),
+            config,
+            host_metrics: host_metrics.clone(),
+        }
+    }
+}
+
+impl Diagnostic for DependencyGraphDiagnostic {
+    fn type_(&self) -> &str {
+        "service.dependency.graph.v1"
+    }
+
+    fn content_type(&self) -> HeaderValue {
+        HeaderValue::from_static("application/json")
+    }
+
+    fn safe_loggable(&self) -> bool {
+        false
+    }
+
+    fn result(&self) -> Result<Bytes, Error> {
+        let cutoff = Instant::now() - IDLE_TIMEOUT;
+        let hosts = self.host_metrics.hosts();
+        let hosts = hosts.iter().map(|m| HostStats::new(m, cutoff));
+        let graph = DependencyGraph::new(&self.service, &self.config.get(), hosts);
+
+        let body = json::to_vec(&graph).unwrap();
+        Ok(Bytes::from(body))
+    }
+}
+
+struct HostStats {
+    service: String,
+    host: Host,
+}
+
+impl HostStats {
+    fn new(metrics: &HostMetrics, cutoff: Instant) -> Self {
+        let state = if metrics.last_update() <= cutoff {
+            DependencyState::Idle
+        } else if service_dependency::is_failing(metrics) {
+            DependencyState::Unhealthy
+        } else {
+            DependencyState::Healthy
+        };
+
+        let timers = [
+            metrics.response_1xx(),
+            metrics.response_2xx(),
+            metrics.response_3xx(),
+            metrics.response_4xx(),
+            metrics.response_5xx(),
+            metrics.response_qos(),
+            metrics.response_other(),
+        ];
+        let request_rate = timers.iter().map(|t| t.five_minute_rate()).sum::<f64>()
+            + metrics.io_error().five_minute_rate();
+        let error_rate =
+            metrics.response_5xx().five_minute_rate() + metrics.io_error().five_minute_rate();
+        let latency = metrics.response_2xx().snapshot();
+
+        HostStats {
+            service: metrics.service_name().to_string(),
+            host: Host {
+                host: metrics.hostname().to_string(),
+                port: metrics.port(),
+                state,
+                request_rate,
+                error_rate,
+                p50_latency_millis: latency.value(0.5) / 1_000_000.,
+                p99_latency_millis: latency.value(0.99) / 1_000_000.,
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DependencyGraph {
+    service: String,
+    dependencies: Vec<Dependency>,
+}
+
+impl DependencyGraph {
+    fn new<I>(service: &str, config: &ServicesConfig, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = HostStats>,
+    {
+        let mut hosts_by_service = BTreeMap::<_, Vec<_>>::new();
+        for stats in hosts {
+            hosts_by_service
+                .entry(stats.service)
+                .or_default()
+                .push(stats.host);
+        }
+
+        let dependencies = hosts_by_service
+            .into_iter()
+            .map(|(service, mut hosts)| {
+                hosts.sort_by(|a, b| (&a.host, a.port).cmp(&(&b.host, b.port)));
+                let uris = config.merged_service(&service).map_or_else(Vec::new, |c| {
+                    c.uris().iter().map(|u| u.to_string()).collect()
+                });
+
+                Dependency {
+                    state: DependencyState::aggregate(&hosts),
+                    service,
+                    uris,
+                    hosts,
+                }
+            })
+            .collect();
+
+        DependencyGraph {
+            service: service.to_string(),
+            dependencies,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Dependency {
+    service: String,
+    state: DependencyState,
+    uris: Vec<String>,
+    hosts: Vec<Host>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Host {
+    host: String,
+    port: u16,
+    state: DependencyState,
+    request_rate: f64,
+    error_rate: f64,
+    p50_latency_millis: f64,
+    p99_latency_millis: f64,
+}
+
+#[derive(Serialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum DependencyState {
+    Healthy,
+    Degraded,
+    Unhealthy,
+    Idle,
+}
+
+impl DependencyState {
+    fn aggregate(hosts: &[Host]) -> Self {
+        let mut active = hosts.iter().filter(|h| h.state != DependencyState::Idle);
+        let Some(first) = active.next() else {
+            return DependencyState::Idle;
+        };
+
+        let mut state = first.state;
+        for host in active {
+            if host.state != state {
+                state = DependencyState::Degraded;
+            }
+        }
+
+        state
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn stats(service: &str, host: &str, state: DependencyState) -> HostStats {
+        HostStats {
+            service: service.to_string(),
+            host: Host {
+                host: host.to_string(),
+                port: 443,
+                state,
+                request_rate: 1.,
+                error_rate: 0.,
+                p50_latency_millis: 2.,
+                p99_latency_millis: 10.,
+            },
+        }
+    }
+
+    #[test]
+    fn graph() {
+        let config = serde_yaml::from_str::<ServicesConfig>(
+            r#"
+services:
+  auth:
+    uris:
+      - https://auth-1/auth
+      - https://auth-2/auth
+"#,
+        )
+        .unwrap();
+
+        let graph = DependencyGraph::new(
+            "my-service",
+            &config,
+            [
+                stats("auth", "auth-2", DependencyState::Unhealthy),
+                stats("auth", "auth-1", DependencyState::Healthy),
+                stats("cache", "cache-1", DependencyState::Idle),
+            ],
+        );
+
+        let value = serde_json::to_value(&graph).unwrap();
+        assert_eq!(value["service"], "my-service");
+        assert_eq!(value["dependencies"][0]["service"], "auth");
+        assert_eq!(value["dependencies"][0]["state"], "DEGRADED");
+        assert_eq!(
+            value["dependencies"][0]["uris"],
+            json!(["https://auth-1/auth", "https://auth-2/auth"]),
+        );
+        assert_eq!(value["dependencies"][0]["hosts"][0]["host"], "auth-1");
+        assert_eq!(value["dependencies"][0]["hosts"][1]["state"], "UNHEALTHY");
+        assert_eq!(value["dependencies"][1]["service"], "cache");
+        assert_eq!(value["dependencies"][1]["state"], "IDLE");
+        assert_eq!(value["dependencies"][1]["uris"], json!([]));
+    }
+}
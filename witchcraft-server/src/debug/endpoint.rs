@@ -12,14 +12,24 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 use crate::debug::DiagnosticRegistry;
+use crate::extensions::PeerAddr;
+use crate::service::request_id::RequestId;
+use crate::standby::Standby;
+use crate::tls::TlsSession;
 use bytes::Bytes;
 use conjure_error::{Error, NotFound, PermissionDenied};
-use conjure_http::server::{AsyncResponseBody, AsyncSerializeResponse, ConjureRuntime};
+use conjure_http::server::{
+    AsyncResponseBody, AsyncSerializeResponse, ConjureRuntime, RequestContext,
+    StdResponseSerializer,
+};
 use conjure_http::{conjure_endpoints, endpoint};
 use conjure_object::BearerToken;
-use http::header::{HeaderName, CONTENT_TYPE};
-use http::{HeaderMap, HeaderValue, Response};
+use http::header::{HeaderName, AUTHORIZATION, CONTENT_TYPE, COOKIE};
+use http::{HeaderMap, HeaderValue, Method, Response};
 use refreshable::Refreshable;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
 use std::sync::Arc;
 use subtle::ConstantTimeEq;
 use tokio::task;
@@ -32,6 +42,8 @@ const TRUE_VALUE: HeaderValue = HeaderValue::from_static("true");
 #[allow(clippy::declare_interior_mutable_const)]
 const FALSE_VALUE: HeaderValue = HeaderValue::from_static("false");
 
+const REDACTED: &str = "<redacted>";
+
 #[conjure_endpoints]
 pub trait DebugService {
     #[endpoint(path = "/debug/diagnostic/{diagnostic_type}", method = GET, produces = DiagnosticResponseSerializer)]
@@ -40,6 +52,23 @@ pub trait DebugService {
         #[auth] token: BearerToken,
         #[path(safe)] diagnostic_type: String,
     ) -> Result<DiagnosticResponse, Error>;
+
+    #[endpoint(path = "/debug/echo", method = GET, produces = StdResponseSerializer)]
+    async fn echo(
+        &self,
+        #[auth] token: BearerToken,
+        #[context] ctx: RequestContext<'_>,
+    ) -> Result<Echo, Error>;
+
+    #[endpoint(path = "/debug/echo", method = POST, name = "echoPost", produces = StdResponseSerializer)]
+    async fn echo_post(
+        &self,
+        #[auth] token: BearerToken,
+        #[context] ctx: RequestContext<'_>,
+    ) -> Result<Echo, Error>;
+
+    #[endpoint(path = "/debug/activate", method = POST)]
+    async fn activate(&self, #[auth] token: BearerToken) -> Result<(), Error>;
 }
 
 pub struct DiagnosticResponse {
@@ -71,30 +100,92 @@ impl<W> AsyncSerializeResponse<DiagnosticResponse, W> for DiagnosticResponseSeri
     }
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Echo {
+    method: String,
+    uri: String,
+    headers: BTreeMap<String, Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_ip: Option<IpAddr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tls: Option<EchoTls>,
+    trace_id: String,
+    span_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl Echo {
+    fn new(method: Method, ctx: &RequestContext<'_>) -> Self {
+        let mut headers = BTreeMap::<_, Vec<_>>::new();
+        for (name, value) in ctx.request_headers() {
+            let value = if name == AUTHORIZATION || name == COOKIE {
+                REDACTED.to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+            headers.entry(name.to_string()).or_default().push(value);
+        }
+
+        let extensions = ctx.request_extensions();
+        let trace = zipkin::current().expect("zipkin trace not initialized");
+
+        Echo {
+            method: method.to_string(),
+            uri: ctx.request_uri().to_string(),
+            headers,
+            client_ip: extensions.get::<PeerAddr>().map(|a| a.ip()),
+            tls: extensions.get::<TlsSession>().map(|s| EchoTls {
+                protocol: s.protocol().to_string(),
+                cipher_suite: s.cipher_suite().to_string(),
+                alpn_protocol: s.alpn_protocol().map(str::to_string),
+                server_name: s.server_name().map(str::to_string),
+            }),
+            trace_id: trace.trace_id().to_string(),
+            span_id: trace.span_id().to_string(),
+            request_id: extensions.get::<RequestId>().map(|id| id.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EchoTls {
+    protocol: String,
+    cipher_suite: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alpn_protocol: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server_name: Option<String>,
+}
+
 pub struct DebugResource {
     debug_secret: Refreshable<String, Error>,
+    echo_enabled: Refreshable<bool, Error>,
     diagnostics: Arc<DiagnosticRegistry>,
+    standby: Arc<Standby>,
 }
 
 impl DebugResource {
-    pub fn new<R>(runtime: &Refreshable<R, Error>, diagnostics: &Arc<DiagnosticRegistry>) -> Self
+    pub fn new<R>(
+        runtime: &Refreshable<R, Error>,
+        diagnostics: &Arc<DiagnosticRegistry>,
+        standby: &Arc<Standby>,
+    ) -> Self
     where
         R: AsRef<RuntimeConfig> + PartialEq + 'static + Sync + Send,
     {
         DebugResource {
             debug_secret: runtime
                 .map(|c| c.as_ref().diagnostics().debug_shared_secret().to_string()),
+            echo_enabled: runtime.map(|c| c.as_ref().diagnostics().echo_enabled()),
             diagnostics: diagnostics.clone(),
+            standby: standby.clone(),
         }
     }
-}
 
-impl DebugService for DebugResource {
-    async fn diagnostic(
-        &self,
-        token: BearerToken,
-        diagnostic_type: String,
-    ) -> Result<DiagnosticResponse, Error> {
+    fn authorize(&self, token: &BearerToken) -> Result<(), Error> {
         let expected = self.debug_secret.get();
         if !bool::from(token.as_str().as_bytes().ct_eq(expected.as_bytes())) {
             return Err(Error::service_safe(
@@ -103,6 +194,35 @@ impl DebugService for DebugResource {
             ));
         }
 
+        Ok(())
+    }
+
+    fn echo_inner(
+        &self,
+        method: Method,
+        token: &BearerToken,
+        ctx: &RequestContext<'_>,
+    ) -> Result<Echo, Error> {
+        if !*self.echo_enabled.get() {
+            return Err(Error::service_safe(
+                "echo endpoint disabled",
+                NotFound::new(),
+            ));
+        }
+        self.authorize(token)?;
+
+        Ok(Echo::new(method, ctx))
+    }
+}
+
+impl DebugService for DebugResource {
+    async fn diagnostic(
+        &self,
+        token: BearerToken,
+        diagnostic_type: String,
+    ) -> Result<DiagnosticResponse, Error> {
+        self.authorize(&token)?;
+
         let diagnostic = match self.diagnostics.get(&diagnostic_type) {
             Some(diagnostic) => diagnostic,
             None => {
@@ -126,4 +246,19 @@ impl DebugService for DebugResource {
             body,
         })
     }
+
+    async fn echo(&self, token: BearerToken, ctx: RequestContext<'_>) -> Result<Echo, Error> {
+        self.echo_inner(Method::GET, &token, &ctx)
+    }
+
+    async fn echo_post(&self, token: BearerToken, ctx: RequestContext<'_>) -> Result<Echo, Error> {
+        self.echo_inner(Method::POST, &token, &ctx)
+    }
+
+    async fn activate(&self, token: BearerToken) -> Result<(), Error> {
+        self.authorize(&token)?;
+        self.standby.activate();
+
+        Ok(())
+    }
 }
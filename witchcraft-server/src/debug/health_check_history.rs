@@ -0,0 +1,53 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::debug::Diagnostic;
+use crate::health::HealthCheckRegistry;
+use bytes::Bytes;
+use conjure_error::Error;
+use conjure_serde::json;
+use http::HeaderValue;
+use std::sync::Arc;
+
+/// A diagnostic which returns the JSON-formatted recent results of every health check in the server's registry.
+pub struct HealthCheckHistoryDiagnostic {
+    health_checks: Arc<HealthCheckRegistry>,
+}
+
+impl HealthCheckHistoryDiagnostic {
+    pub fn new(health_checks: &Arc<HealthCheckRegistry>) -> Self {
+        HealthCheckHistoryDiagnostic {
+            health_checks: health_checks.clone(),
+        }
+    }
+}
+
+impl Diagnostic for HealthCheckHistoryDiagnostic {
+    fn type_(&self) -> &str {
+        "health.check.history.v1"
+    }
+
+    fn content_type(&self) -> HeaderValue {
+        HeaderValue::from_static("application/json")
+    }
+
+    fn safe_loggable(&self) -> bool {
+        false
+    }
+
+    fn result(&self) -> Result<Bytes, Error> {
+        let history = self.health_checks.history();
+        let body = json::to_vec(&history).unwrap();
+        Ok(Bytes::from(body))
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::debug::Diagnostic;
+use crate::jemalloc::{self, MALLCTL_ARENAS_ALL};
+use bytes::Bytes;
+use conjure_error::Error;
+use conjure_serde::json;
+use http::HeaderValue;
+use parking_lot::Mutex;
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::time::Instant;
+use tikv_jemalloc_ctl::epoch;
+
+/// A diagnostic which returns the change in heap allocations by size class and arena since it was last called.
+///
+/// The first call establishes a baseline and reports no deltas. Comparing successive calls over time can help
+/// identify slow leaks without the overhead of a heap profiler.
+///
+/// Requires jemalloc.
+pub struct HeapDiffDiagnostic {
+    previous: Mutex<Option<HeapSnapshot>>,
+}
+
+impl HeapDiffDiagnostic {
+    pub fn new() -> Self {
+        HeapDiffDiagnostic {
+            previous: Mutex::new(None),
+        }
+    }
+}
+
+impl Diagnostic for HeapDiffDiagnostic {
+    fn type_(&self) -> &str {
+        "rust.heap.diff.v1"
+    }
+
+    fn content_type(&self) -> HeaderValue {
+        HeaderValue::from_static("application/json")
+    }
+
+    fn safe_loggable(&self) -> bool {
+        true
+    }
+
+    fn result(&self) -> Result<Bytes, Error> {
+        epoch::advance().map_err(Error::internal_safe)?;
+        let snapshot = HeapSnapshot::capture();
+
+        let previous = self.previous.lock().replace(snapshot.clone());
+        let diff = HeapDiff::new(previous.as_ref(), &snapshot);
+
+        let body = json::to_vec(&diff).unwrap();
+        Ok(Bytes::from(body))
+    }
+}
+
+#[derive(Clone)]
+struct HeapSnapshot {
+    time: Instant,
+    size_classes: BTreeMap<SizeClass, usize>,
+    // keyed by arena index
+    arenas: BTreeMap<usize, usize>,
+}
+
+impl HeapSnapshot {
+    fn capture() -> Self {
+        let mut size_classes = BTreeMap::new();
+        for (bin, size) in jemalloc::bin_sizes().into_iter().enumerate() {
+            let name = format!("stats.arenas.{MALLCTL_ARENAS_ALL}.bins.{bin}.curregs");
+            if let Some(regions) = jemalloc::read_stat(&name) {
+                size_classes.insert(SizeClass::Small(size), regions * size);
+            }
+        }
+        let name = format!("stats.arenas.{MALLCTL_ARENAS_ALL}.large.allocated");
+        if let Some(allocated) = jemalloc::read_stat(&name) {
+            size_classes.insert(SizeClass::Large, allocated);
+        }
+
+        let mut arenas = BTreeMap::new();
+        for arena in 0..jemalloc::narenas() {
+            // uninitialized arenas have no statistics
+            let small = jemalloc::read_stat(&format!("stats.arenas.{arena}.small.allocated"));
+            let large = jemalloc::read_stat(&format!("stats.arenas.{arena}.large.allocated"));
+            if let (Some(small), Some(large)) = (small, large) {
+                arenas.insert(arena, small + large);
+            }
+        }
+
+        HeapSnapshot {
+            time: Instant::now(),
+            size_classes,
+            arenas,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum SizeClass {
+    Small(usize),
+    Large,
+}
+
+impl Serialize for SizeClass {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            SizeClass::Small(size) => serializer.collect_str(size),
+            SizeClass::Large => serializer.serialize_str("large"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HeapDiff {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    elapsed_millis: Option<u128>,
+    size_classes: BTreeMap<SizeClass, Allocated>,
+    arenas: BTreeMap<usize, Allocated>,
+}
+
+impl HeapDiff {
+    fn new(previous: Option<&HeapSnapshot>, current: &HeapSnapshot) -> Self {
+        HeapDiff {
+            elapsed_millis: previous
+                .map(|p| current.time.saturating_duration_since(p.time).as_millis()),
+            size_classes: diff(previous.map(|p| &p.size_classes), &current.size_classes),
+            arenas: diff(previous.map(|p| &p.arenas), &current.arenas),
+        }
+    }
+}
+
+#[derive(Serialize, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Allocated {
+    bytes: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delta: Option<i64>,
+}
+
+fn diff<K>(
+    previous: Option<&BTreeMap<K, usize>>,
+    current: &BTreeMap<K, usize>,
+) -> BTreeMap<K, Allocated>
+where
+    K: Ord + Clone,
+{
+    current
+        .iter()
+        .map(|(key, &bytes)| {
+            let delta = previous.map(|p| {
+                let before = p.get(key).copied().unwrap_or(0);
+                bytes as i64 - before as i64
+            });
+            (key.clone(), Allocated { bytes, delta })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn baseline() {
+        let current = BTreeMap::from([(0, 100)]);
+        assert_eq!(
+            diff(None, &current),
+            BTreeMap::from([(
+                0,
+                Allocated {
+                    bytes: 100,
+                    delta: None
+                }
+            )]),
+        );
+    }
+
+    #[test]
+    fn deltas() {
+        let previous = BTreeMap::from([(0, 100), (1, 50), (2, 10)]);
+        let current = BTreeMap::from([(0, 150), (1, 20), (3, 30)]);
+        assert_eq!(
+            diff(Some(&previous), &current),
+            BTreeMap::from([
+                (
+                    0,
+                    Allocated {
+                        bytes: 150,
+                        delta: Some(50)
+                    }
+                ),
+                (
+                    1,
+                    Allocated {
+                        bytes: 20,
+                        delta: Some(-30)
+                    }
+                ),
+                (
+                    3,
+                    Allocated {
+                        bytes: 30,
+                        delta: Some(30)
+                    }
+                ),
+            ]),
+        );
+    }
+}
@@ -15,11 +15,14 @@ use crate::debug::Diagnostic;
 use bytes::Bytes;
 use conjure_error::Error;
 use http::HeaderValue;
+#[cfg(feature = "mimalloc")]
+use std::ffi::{c_char, c_void, CStr};
+#[cfg(all(feature = "jemalloc", not(feature = "mimalloc")))]
 use tikv_jemalloc_ctl::stats_print;
 
 /// A diagnostic which returns heap statistics.
 ///
-/// Requires jemalloc.
+/// Requires jemalloc or mimalloc.
 pub struct HeapStatsDiagnostic;
 
 impl Diagnostic for HeapStatsDiagnostic {
@@ -35,10 +38,25 @@ impl Diagnostic for HeapStatsDiagnostic {
         true
     }
 
+    #[cfg(all(feature = "jemalloc", not(feature = "mimalloc")))]
     fn result(&self) -> Result<Bytes, Error> {
         let mut buf = vec![];
         stats_print::stats_print(&mut buf, stats_print::Options::default())
             .map_err(Error::internal_safe)?;
         Ok(Bytes::from(buf))
     }
+
+    #[cfg(feature = "mimalloc")]
+    fn result(&self) -> Result<Bytes, Error> {
+        unsafe extern "C" fn write(msg: *const c_char, arg: *mut c_void) {
+            let buf = &mut *arg.cast::<Vec<u8>>();
+            buf.extend_from_slice(CStr::from_ptr(msg).to_bytes());
+        }
+
+        let mut buf = Vec::<u8>::new();
+        unsafe {
+            libmimalloc_sys::mi_stats_print_out(Some(write), (&mut buf as *mut Vec<u8>).cast());
+        }
+        Ok(Bytes::from(buf))
+    }
 }
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 use crate::debug::Diagnostic;
+use crate::metric_aliases::MetricAliases;
 use bytes::Bytes;
 use conjure_error::Error;
 use conjure_serde::json;
@@ -19,17 +20,20 @@ use http::HeaderValue;
 use serde::ser::{SerializeMap, SerializeSeq};
 use serde::{Serialize, Serializer};
 use std::sync::Arc;
-use witchcraft_metrics::{MetricId, MetricRegistry, Metrics, Tags};
+use witchcraft_metrics::{Metric, MetricId, MetricRegistry, Tags};
 
-/// A diagnostic which returns the JSON-formatted names of every metric in the server's registry.
+/// A diagnostic which returns the JSON-formatted names of every metric in the server's registry, including the
+/// aliases they are reported under.
 pub struct MetricNamesDiagnostic {
     metrics: Arc<MetricRegistry>,
+    aliases: Arc<MetricAliases>,
 }
 
 impl MetricNamesDiagnostic {
-    pub fn new(metrics: &Arc<MetricRegistry>) -> Self {
+    pub fn new(metrics: &Arc<MetricRegistry>, aliases: &Arc<MetricAliases>) -> Self {
         MetricNamesDiagnostic {
             metrics: metrics.clone(),
+            aliases: aliases.clone(),
         }
     }
 }
@@ -48,13 +52,13 @@ impl Diagnostic for MetricNamesDiagnostic {
     }
 
     fn result(&self) -> Result<Bytes, Error> {
-        let metrics = self.metrics.metrics();
+        let metrics = self.aliases.metrics(&self.metrics);
         let body = json::to_vec(&MetricNames(metrics)).unwrap();
         Ok(Bytes::from(body))
     }
 }
 
-struct MetricNames(Metrics);
+struct MetricNames(Vec<(MetricId, Metric)>);
 
 impl Serialize for MetricNames {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -99,3 +103,32 @@ impl Serialize for MetricTags<'_> {
         s.end()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn aliases() {
+        let metrics = Arc::new(MetricRegistry::new());
+        metrics
+            .counter(MetricId::new("new.name").with_tag("tag", "value"))
+            .inc();
+        let aliases = Arc::new(MetricAliases::new(true));
+        aliases.register("new.name", "old.name");
+
+        let diagnostic = MetricNamesDiagnostic::new(&metrics, &aliases);
+        let mut names =
+            serde_json::from_slice::<Vec<Value>>(&diagnostic.result().unwrap()).unwrap();
+        names.sort_by_key(|n| n["name"].as_str().unwrap().to_string());
+
+        assert_eq!(
+            names,
+            [
+                json!({"name": "new.name", "tags": {"tag": "value"}}),
+                json!({"name": "old.name", "tags": {"tag": "value"}}),
+            ],
+        );
+    }
+}
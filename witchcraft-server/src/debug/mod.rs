@@ -23,13 +23,22 @@ use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 use regex::Regex;
 
+pub(crate) mod cluster_info;
+pub(crate) mod dependency_graph;
 pub(crate) mod diagnostic_types;
 pub(crate) mod endpoint;
-#[cfg(feature = "jemalloc")]
+pub(crate) mod health_check_history;
+#[cfg(all(feature = "jemalloc", not(feature = "mimalloc")))]
+pub(crate) mod heap_diff;
+#[cfg(any(feature = "jemalloc", feature = "mimalloc"))]
 pub(crate) mod heap_stats;
 pub(crate) mod metric_names;
+pub(crate) mod overload;
+pub(crate) mod signal_dump;
 #[cfg(target_os = "linux")]
 pub(crate) mod thread_dump;
+#[cfg(target_os = "linux")]
+pub(crate) mod thread_profile;
 
 static TYPE_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"([a-z0-9]+\.)+v[0-9]+").unwrap());
 
@@ -0,0 +1,222 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::debug::{signal_dump, Diagnostic};
+use crate::memory_admission::MemoryAdmission;
+use crate::service::in_flight::InFlightRequests;
+use bytes::Bytes;
+use conjure_error::Error;
+use conjure_serde::json;
+use http::HeaderValue;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use witchcraft_metrics::{Metric, MetricId, MetricRegistry};
+
+const SLOWEST_REQUESTS: usize = 10;
+
+// Gauges reporting connection and thread pool utilization.
+const UTILIZATION_GAUGES: &[&str] = &[
+    "server.connection.utilization",
+    "server.connection.fd-exhausted",
+    "server.worker.active",
+    "server.worker.max",
+    "server.worker.queued",
+    "server.memory-admission.shedding",
+    "server.standby",
+];
+
+// Meters recording requests or connections rejected to protect the server.
+const SHED_METERS: &[&str] = &[
+    "server.connection.killed",
+    "server.memory-admission.shed",
+    "server.request.rejected",
+    "server.token-bucket.throttled",
+    "server.worker.expired",
+];
+
+/// A diagnostic which summarizes the server's current overload indicators in a single JSON payload.
+pub struct OverloadDiagnostic {
+    metrics: Arc<MetricRegistry>,
+    memory_admission: Arc<MemoryAdmission>,
+    in_flight: Arc<InFlightRequests>,
+}
+
+impl OverloadDiagnostic {
+    pub fn new(
+        metrics: &Arc<MetricRegistry>,
+        memory_admission: &Arc<MemoryAdmission>,
+        in_flight: &Arc<InFlightRequests>,
+    ) -> Self {
+        OverloadDiagnostic {
+            metrics: metrics.clone(),
+            memory_admission: memory_admission.clone(),
+            in_flight: in_flight.clone(),
+        }
+    }
+
+    fn report(&self) -> OverloadReport {
+        let mut report = OverloadReport {
+            active_connections: signal_dump::active(&self.metrics, "server.connection.active"),
+            active_requests: signal_dump::active(&self.metrics, "server.request.active"),
+            gauges: BTreeMap::new(),
+            shed: BTreeMap::new(),
+            slowest_requests: self
+                .in_flight
+                .slowest(SLOWEST_REQUESTS)
+                .into_iter()
+                .map(|r| InFlightRequest {
+                    method: r.method().to_string(),
+                    service_name: r.endpoint().map(|(service, _)| service.to_string()),
+                    endpoint: r.endpoint().map(|(_, endpoint)| endpoint.to_string()),
+                    request_id: r.request_id().map(|id| id.to_string()),
+                    duration_millis: r.duration().as_millis() as u64,
+                })
+                .collect(),
+            memory: Memory {
+                usage_bytes: self.memory_admission.usage(),
+                headroom_bytes: self.memory_admission.headroom(),
+                shedding: self.memory_admission.shedding(),
+            },
+        };
+
+        for (id, metric) in &self.metrics.metrics() {
+            match metric {
+                Metric::Gauge(gauge) if UTILIZATION_GAUGES.contains(&id.name()) => {
+                    let value = serde_json::to_value(gauge.value()).unwrap_or_default();
+                    report.gauges.insert(metric_name(id), value);
+                }
+                Metric::Meter(meter) if SHED_METERS.contains(&id.name()) => {
+                    report.shed.insert(
+                        metric_name(id),
+                        Shed {
+                            count: meter.count(),
+                            one_minute_rate: meter.one_minute_rate(),
+                        },
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        report
+    }
+}
+
+impl Diagnostic for OverloadDiagnostic {
+    fn type_(&self) -> &str {
+        "server.overload.v1"
+    }
+
+    fn content_type(&self) -> HeaderValue {
+        HeaderValue::from_static("application/json")
+    }
+
+    fn safe_loggable(&self) -> bool {
+        true
+    }
+
+    fn result(&self) -> Result<Bytes, Error> {
+        let body = json::to_vec(&self.report()).unwrap();
+        Ok(Bytes::from(body))
+    }
+}
+
+/// Formats a metric ID as its name followed by its tags, e.g. `server.connection.killed[reason:idle]`.
+fn metric_name(id: &MetricId) -> String {
+    let mut name = id.name().to_string();
+    let tags = id
+        .tags()
+        .iter()
+        .map(|(key, value)| format!("{key}:{value}"))
+        .collect::<Vec<_>>();
+    if !tags.is_empty() {
+        name.push('[');
+        name.push_str(&tags.join(","));
+        name.push(']');
+    }
+    name
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OverloadReport {
+    active_connections: BTreeMap<String, i64>,
+    active_requests: BTreeMap<String, i64>,
+    gauges: BTreeMap<String, serde_json::Value>,
+    shed: BTreeMap<String, Shed>,
+    slowest_requests: Vec<InFlightRequest>,
+    memory: Memory,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Shed {
+    count: i64,
+    one_minute_rate: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InFlightRequest {
+    method: String,
+    service_name: Option<String>,
+    endpoint: Option<String>,
+    request_id: Option<String>,
+    duration_millis: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Memory {
+    usage_bytes: Option<u64>,
+    headroom_bytes: Option<u64>,
+    shedding: bool,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use refreshable::Refreshable;
+    use witchcraft_server_config::runtime::MemoryAdmissionConfig;
+
+    #[test]
+    fn report() {
+        let metrics = Arc::new(MetricRegistry::new());
+        metrics
+            .counter(MetricId::new("server.connection.active").with_tag("listener", "service"))
+            .add(4);
+        metrics.gauge("server.worker.queued", || 7);
+        metrics.gauge("unrelated.gauge", || 1);
+        metrics
+            .meter(MetricId::new("server.connection.killed").with_tag("reason", "idle"))
+            .mark(3);
+
+        let (config, _handle) = Refreshable::new(MemoryAdmissionConfig::default());
+        let memory_admission = MemoryAdmission::new(&metrics, config);
+        let in_flight = Arc::new(InFlightRequests::default());
+
+        let diagnostic = OverloadDiagnostic::new(&metrics, &memory_admission, &in_flight);
+        let report = serde_json::to_value(diagnostic.report()).unwrap();
+
+        assert_eq!(report["activeConnections"]["service"], 4);
+        assert_eq!(report["gauges"]["server.worker.queued"], 7);
+        assert!(report["gauges"].get("unrelated.gauge").is_none());
+        assert_eq!(
+            report["shed"]["server.connection.killed[reason:idle]"]["count"],
+            3
+        );
+        assert_eq!(report["memory"]["shedding"], false);
+        assert_eq!(report["slowestRequests"], serde_json::json!([]));
+    }
+}
@@ -0,0 +1,126 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#[cfg(target_os = "linux")]
+use crate::debug::thread_dump::ThreadDumpDiagnostic;
+#[cfg(target_os = "linux")]
+use crate::debug::Diagnostic;
+use conjure_error::Error;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::signal::unix::{self, SignalKind};
+#[cfg(target_os = "linux")]
+use tokio::task;
+use witchcraft_log::{info, warn};
+use witchcraft_metrics::{Metric, MetricRegistry};
+
+/// Logs a diagnostic dump to the service log each time the process receives a `SIGQUIT`.
+///
+/// Like the JVM, the process continues running rather than terminating. The dump contains the number of active
+/// connections and in-flight requests on each listener, and on Linux a stack trace of every thread in the process.
+pub async fn run(metrics: Arc<MetricRegistry>) {
+    let mut signal = match unix::signal(SignalKind::quit()) {
+        Ok(signal) => signal,
+        Err(e) => {
+            warn!(
+                "unable to install SIGQUIT handler",
+                error: Error::internal_safe(e)
+            );
+            return;
+        }
+    };
+
+    while signal.recv().await.is_some() {
+        dump(&metrics).await;
+    }
+}
+
+async fn dump(metrics: &MetricRegistry) {
+    let connections = active(metrics, "server.connection.active");
+    let requests = active(metrics, "server.request.active");
+
+    #[cfg(target_os = "linux")]
+    let threads = match task::spawn_blocking(|| ThreadDumpDiagnostic.result()).await {
+        Ok(Ok(dump)) => Some(String::from_utf8_lossy(&dump).into_owned()),
+        Ok(Err(e)) => {
+            warn!("error collecting thread dump", error: e);
+            None
+        }
+        Err(e) => {
+            warn!(
+                "error collecting thread dump",
+                error: Error::internal_safe(e)
+            );
+            None
+        }
+    };
+    #[cfg(not(target_os = "linux"))]
+    let threads = None::<String>;
+
+    info!(
+        "SIGQUIT diagnostic dump",
+        safe: {
+            activeConnections: connections,
+            activeRequests: requests,
+            threadDump: threads,
+        },
+    );
+}
+
+/// Returns the values of the counter with the specified name, keyed by listener.
+pub(crate) fn active(metrics: &MetricRegistry, name: &str) -> BTreeMap<String, i64> {
+    let mut values = BTreeMap::new();
+    for (id, metric) in &metrics.metrics() {
+        let Metric::Counter(counter) = metric else {
+            continue;
+        };
+        if id.name() != name {
+            continue;
+        }
+
+        let listener = id
+            .tags()
+            .iter()
+            .find(|(key, _)| *key == "listener")
+            .map_or("unknown", |(_, value)| value);
+        *values.entry(listener.to_string()).or_default() += counter.count();
+    }
+
+    values
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use witchcraft_metrics::MetricId;
+
+    #[test]
+    fn active_counts() {
+        let metrics = MetricRegistry::new();
+        metrics
+            .counter(MetricId::new("server.request.active").with_tag("listener", "service"))
+            .add(3);
+        metrics
+            .counter(MetricId::new("server.request.active").with_tag("listener", "management"))
+            .add(1);
+        metrics
+            .counter(MetricId::new("server.connection.active").with_tag("listener", "service"))
+            .add(5);
+
+        let requests = active(&metrics, "server.request.active");
+        assert_eq!(
+            requests,
+            BTreeMap::from([("management".to_string(), 1), ("service".to_string(), 3)]),
+        );
+    }
+}
@@ -17,6 +17,7 @@ use crate::minidump::log;
 use bytes::Bytes;
 use conjure_error::Error;
 use http::HeaderValue;
+use minidump_processor::ProcessState;
 use minidump_writer::minidump_writer::MinidumpWriter;
 use std::ffi::OsString;
 use std::fs;
@@ -46,22 +47,30 @@ impl Diagnostic for ThreadDumpDiagnostic {
     }
 
     fn result(&self) -> Result<Bytes, Error> {
-        let target_file = NamedTempFile::new_in("var/data/tmp").map_err(Error::internal_safe)?;
-        let handle = Handle::current();
-
-        let client = handle.block_on(minidump::connect())?;
-        client
-            .send_message(0, target_file.path().as_os_str().as_bytes())
-            .map_err(Error::internal_safe)?;
-        // send_message doesn't wait for a response, so send a ping after to synchronize
-        client.ping().map_err(Error::internal_safe)?;
-
-        let info = handle.block_on(log::process_minidump(target_file.path()))?;
+        let state = capture()?;
+        let info = log::format_dump(&state);
 
         Ok(Bytes::from(info))
     }
 }
 
+/// Captures and symbolicates the stack of every thread in the server.
+///
+/// It must be called from a blocking context.
+pub fn capture() -> Result<ProcessState, Error> {
+    let target_file = NamedTempFile::new_in("var/data/tmp").map_err(Error::internal_safe)?;
+    let handle = Handle::current();
+
+    let client = handle.block_on(minidump::connect())?;
+    client
+        .send_message(0, target_file.path().as_os_str().as_bytes())
+        .map_err(Error::internal_safe)?;
+    // send_message doesn't wait for a response, so send a ping after to synchronize
+    client.ping().map_err(Error::internal_safe)?;
+
+    handle.block_on(log::read_minidump(target_file.path()))
+}
+
 pub fn handle_request(buf: Vec<u8>) {
     let target_file = PathBuf::from(OsString::from_vec(buf));
     let ppid = process::parent_id();
@@ -0,0 +1,451 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::debug::thread_dump;
+use crate::debug::Diagnostic;
+use bytes::Bytes;
+use conjure_error::Error;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use http::HeaderValue;
+use minidump_processor::ProcessState;
+use parking_lot::Mutex;
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::runtime::Handle;
+use tokio::task;
+use tokio::time::{self, MissedTickBehavior};
+use witchcraft_log::debug;
+use witchcraft_server_config::install::ProfilingConfig;
+
+/// A diagnostic which returns a wall-clock profile of every thread in the server over the recent past.
+///
+/// The stack of each thread is sampled at a low, fixed rate in the background and retained for a fixed window. The
+/// profile is encoded in the gzip-compressed [pprof] protobuf format, with each sample labeled with its thread's name.
+///
+/// It is only supported on Linux.
+///
+/// [pprof]: https://github.com/google/pprof/blob/main/proto/profile.proto
+pub struct ThreadProfileDiagnostic {
+    profiler: Arc<Profiler>,
+}
+
+impl ThreadProfileDiagnostic {
+    pub fn new(config: &ProfilingConfig, handle: &Handle) -> Self {
+        let profiler = Arc::new(Profiler {
+            interval: config.sample_interval(),
+            retention: config.retention(),
+            samples: Mutex::new(VecDeque::new()),
+        });
+        handle.spawn(Profiler::run(Arc::downgrade(&profiler)));
+
+        ThreadProfileDiagnostic { profiler }
+    }
+}
+
+impl Diagnostic for ThreadProfileDiagnostic {
+    fn type_(&self) -> &str {
+        "rust.thread.profile.v1"
+    }
+
+    fn content_type(&self) -> HeaderValue {
+        HeaderValue::from_static("application/octet-stream")
+    }
+
+    fn safe_loggable(&self) -> bool {
+        true
+    }
+
+    fn result(&self) -> Result<Bytes, Error> {
+        let profile = self.profiler.encode();
+
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&profile).unwrap();
+        let body = encoder.finish().unwrap();
+
+        Ok(Bytes::from(body))
+    }
+}
+
+struct Profiler {
+    interval: Duration,
+    retention: Duration,
+    samples: Mutex<VecDeque<Sample>>,
+}
+
+impl Profiler {
+    /// Periodically samples thread stacks until the profiler is dropped.
+    async fn run(profiler: Weak<Profiler>) {
+        let Some(interval) = profiler.upgrade().map(|p| p.interval) else {
+            return;
+        };
+
+        let mut interval = time::interval(interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if profiler.strong_count() == 0 {
+                break;
+            }
+
+            let state = match task::spawn_blocking(thread_dump::capture).await {
+                Ok(Ok(state)) => state,
+                Ok(Err(e)) => {
+                    debug!("error sampling thread stacks", error: e);
+                    continue;
+                }
+                Err(e) => {
+                    debug!(
+                        "error sampling thread stacks",
+                        error: Error::internal_safe(e)
+                    );
+                    continue;
+                }
+            };
+
+            let Some(profiler) = profiler.upgrade() else {
+                break;
+            };
+            profiler.record(Sample::new(SystemTime::now(), &state));
+        }
+    }
+
+    fn record(&self, sample: Sample) {
+        let mut samples = self.samples.lock();
+
+        while samples.front().is_some_and(|s| {
+            sample
+                .time
+                .duration_since(s.time)
+                .is_ok_and(|age| age > self.retention)
+        }) {
+            samples.pop_front();
+        }
+
+        samples.push_back(sample);
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let samples = self.samples.lock();
+
+        let mut profile = ProfileBuilder::new();
+        for sample in &*samples {
+            for thread in &sample.threads {
+                profile.add_sample(thread, self.interval);
+            }
+        }
+
+        let start = samples.front().map_or(UNIX_EPOCH, |s| s.time);
+        let end = samples
+            .back()
+            .map_or(UNIX_EPOCH, |s| s.time + self.interval);
+        profile.build(start, end, self.interval)
+    }
+}
+
+struct Sample {
+    time: SystemTime,
+    threads: Vec<ThreadStack>,
+}
+
+impl Sample {
+    fn new(time: SystemTime, state: &ProcessState) -> Self {
+        let threads = state
+            .threads
+            .iter()
+            .map(|thread| ThreadStack {
+                name: thread
+                    .thread_name
+                    .clone()
+                    .unwrap_or_else(|| thread.thread_id.to_string()),
+                locations: thread
+                    .frames
+                    .iter()
+                    .map(|frame| {
+                        // Inlined functions come first, followed by the function they were inlined into.
+                        let mut lines = frame
+                            .inlines
+                            .iter()
+                            .map(|inline| Line {
+                                function: inline.function_name.clone(),
+                                file: inline.source_file_name.clone(),
+                                line: inline.source_line.unwrap_or(0),
+                            })
+                            .collect::<Vec<_>>();
+                        lines.push(Line {
+                            function: frame.function_name.as_deref().unwrap_or("???").to_string(),
+                            file: frame.source_file_name.clone(),
+                            line: frame.source_line.unwrap_or(0),
+                        });
+                        lines
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Sample { time, threads }
+    }
+}
+
+struct ThreadStack {
+    name: String,
+    // Ordered from the innermost frame outwards.
+    locations: Vec<Vec<Line>>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Line {
+    function: String,
+    file: Option<String>,
+    line: u32,
+}
+
+// Field numbers from https://github.com/google/pprof/blob/main/proto/profile.proto
+const PROFILE_SAMPLE_TYPE: u32 = 1;
+const PROFILE_SAMPLE: u32 = 2;
+const PROFILE_LOCATION: u32 = 4;
+const PROFILE_FUNCTION: u32 = 5;
+const PROFILE_STRING_TABLE: u32 = 6;
+const PROFILE_TIME_NANOS: u32 = 9;
+const PROFILE_DURATION_NANOS: u32 = 10;
+const PROFILE_PERIOD_TYPE: u32 = 11;
+const PROFILE_PERIOD: u32 = 12;
+const VALUE_TYPE_TYPE: u32 = 1;
+const VALUE_TYPE_UNIT: u32 = 2;
+const SAMPLE_LOCATION_ID: u32 = 1;
+const SAMPLE_VALUE: u32 = 2;
+const SAMPLE_LABEL: u32 = 3;
+const LABEL_KEY: u32 = 1;
+const LABEL_STR: u32 = 2;
+const LOCATION_ID: u32 = 1;
+const LOCATION_LINE: u32 = 4;
+const LINE_FUNCTION_ID: u32 = 1;
+const LINE_LINE: u32 = 2;
+const FUNCTION_ID: u32 = 1;
+const FUNCTION_NAME: u32 = 2;
+const FUNCTION_FILENAME: u32 = 4;
+
+/// A minimal encoder for the pprof profile protobuf message.
+struct ProfileBuilder {
+    strings: HashMap<String, u64>,
+    string_table: Vec<u8>,
+    functions: HashMap<(String, Option<String>), u64>,
+    function_table: Vec<u8>,
+    locations: HashMap<Vec<Line>, u64>,
+    location_table: Vec<u8>,
+    samples: Vec<u8>,
+}
+
+impl ProfileBuilder {
+    fn new() -> Self {
+        let mut builder = ProfileBuilder {
+            strings: HashMap::new(),
+            string_table: vec![],
+            functions: HashMap::new(),
+            function_table: vec![],
+            locations: HashMap::new(),
+            location_table: vec![],
+            samples: vec![],
+        };
+        // The first entry of the string table must be the empty string.
+        builder.string("");
+        builder
+    }
+
+    fn string(&mut self, s: &str) -> u64 {
+        if let Some(id) = self.strings.get(s) {
+            return *id;
+        }
+
+        let id = self.strings.len() as u64;
+        bytes_field(&mut self.string_table, PROFILE_STRING_TABLE, s.as_bytes());
+        self.strings.insert(s.to_string(), id);
+        id
+    }
+
+    fn function(&mut self, name: &str, file: Option<&str>) -> u64 {
+        let key = (name.to_string(), file.map(str::to_string));
+        if let Some(id) = self.functions.get(&key) {
+            return *id;
+        }
+
+        let id = self.functions.len() as u64 + 1;
+        let mut function = vec![];
+        uint_field(&mut function, FUNCTION_ID, id);
+        uint_field(&mut function, FUNCTION_NAME, self.string(name));
+        if let Some(file) = file {
+            uint_field(&mut function, FUNCTION_FILENAME, self.string(file));
+        }
+        bytes_field(&mut self.function_table, PROFILE_FUNCTION, &function);
+        self.functions.insert(key, id);
+        id
+    }
+
+    fn location(&mut self, lines: &[Line]) -> u64 {
+        if let Some(id) = self.locations.get(lines) {
+            return *id;
+        }
+
+        let id = self.locations.len() as u64 + 1;
+        let mut location = vec![];
+        uint_field(&mut location, LOCATION_ID, id);
+        for line in lines {
+            let mut entry = vec![];
+            uint_field(
+                &mut entry,
+                LINE_FUNCTION_ID,
+                self.function(&line.function, line.file.as_deref()),
+            );
+            uint_field(&mut entry, LINE_LINE, u64::from(line.line));
+            bytes_field(&mut location, LOCATION_LINE, &entry);
+        }
+        bytes_field(&mut self.location_table, PROFILE_LOCATION, &location);
+        self.locations.insert(lines.to_vec(), id);
+        id
+    }
+
+    fn add_sample(&mut self, thread: &ThreadStack, interval: Duration) {
+        let location_ids = thread
+            .locations
+            .iter()
+            .map(|lines| self.location(lines))
+            .collect::<Vec<_>>();
+
+        let mut label = vec![];
+        uint_field(&mut label, LABEL_KEY, self.string("thread"));
+        uint_field(&mut label, LABEL_STR, self.string(&thread.name));
+
+        let mut sample = vec![];
+        packed_field(&mut sample, SAMPLE_LOCATION_ID, &location_ids);
+        packed_field(&mut sample, SAMPLE_VALUE, &[1, interval.as_nanos() as u64]);
+        bytes_field(&mut sample, SAMPLE_LABEL, &label);
+        bytes_field(&mut self.samples, PROFILE_SAMPLE, &sample);
+    }
+
+    fn value_type(&mut self, type_: &str, unit: &str) -> Vec<u8> {
+        let mut value_type = vec![];
+        uint_field(&mut value_type, VALUE_TYPE_TYPE, self.string(type_));
+        uint_field(&mut value_type, VALUE_TYPE_UNIT, self.string(unit));
+        value_type
+    }
+
+    fn build(mut self, start: SystemTime, end: SystemTime, interval: Duration) -> Vec<u8> {
+        let samples_type = self.value_type("samples", "count");
+        let wall_type = self.value_type("wall", "nanoseconds");
+
+        let mut profile = vec![];
+        bytes_field(&mut profile, PROFILE_SAMPLE_TYPE, &samples_type);
+        bytes_field(&mut profile, PROFILE_SAMPLE_TYPE, &wall_type);
+        profile.extend_from_slice(&self.samples);
+        profile.extend_from_slice(&self.location_table);
+        profile.extend_from_slice(&self.function_table);
+        profile.extend_from_slice(&self.string_table);
+        let time = start.duration_since(UNIX_EPOCH).unwrap_or_default();
+        uint_field(&mut profile, PROFILE_TIME_NANOS, time.as_nanos() as u64);
+        let duration = end.duration_since(start).unwrap_or_default();
+        uint_field(
+            &mut profile,
+            PROFILE_DURATION_NANOS,
+            duration.as_nanos() as u64,
+        );
+        bytes_field(&mut profile, PROFILE_PERIOD_TYPE, &wall_type);
+        uint_field(&mut profile, PROFILE_PERIOD, interval.as_nanos() as u64);
+        profile
+    }
+}
+
+fn varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn uint_field(buf: &mut Vec<u8>, field: u32, value: u64) {
+    varint(buf, u64::from(field) << 3);
+    varint(buf, value);
+}
+
+fn bytes_field(buf: &mut Vec<u8>, field: u32, value: &[u8]) {
+    varint(buf, (u64::from(field) << 3) | 2);
+    varint(buf, value.len() as u64);
+    buf.extend_from_slice(value);
+}
+
+fn packed_field(buf: &mut Vec<u8>, field: u32, values: &[u64]) {
+    let mut packed = vec![];
+    for value in values {
+        varint(&mut packed, *value);
+    }
+    bytes_field(buf, field, &packed);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn line(function: &str) -> Line {
+        Line {
+            function: function.to_string(),
+            file: Some("src/main.rs".to_string()),
+            line: 1,
+        }
+    }
+
+    #[test]
+    fn varint_encoding() {
+        let mut buf = vec![];
+        varint(&mut buf, 1);
+        varint(&mut buf, 300);
+        assert_eq!(buf, [0x01, 0xac, 0x02]);
+    }
+
+    #[test]
+    fn deduplicates_locations() {
+        let mut profile = ProfileBuilder::new();
+        let thread = ThreadStack {
+            name: "main".to_string(),
+            locations: vec![vec![line("inner"), line("outer")], vec![line("main")]],
+        };
+        profile.add_sample(&thread, Duration::from_secs(1));
+        profile.add_sample(&thread, Duration::from_secs(1));
+
+        assert_eq!(profile.locations.len(), 2);
+        assert_eq!(profile.functions.len(), 3);
+        // "", the file name, the three function names, and "thread". The thread name reuses the "main" function name.
+        assert_eq!(profile.strings.len(), 6);
+    }
+
+    #[test]
+    fn retention() {
+        let profiler = Profiler {
+            interval: Duration::from_secs(10),
+            retention: Duration::from_secs(30),
+            samples: Mutex::new(VecDeque::new()),
+        };
+
+        for i in 0..10 {
+            profiler.record(Sample {
+                time: UNIX_EPOCH + Duration::from_secs(i * 10),
+                threads: vec![],
+            });
+        }
+
+        let samples = profiler.samples.lock();
+        assert_eq!(samples.len(), 4);
+        assert_eq!(samples[0].time, UNIX_EPOCH + Duration::from_secs(60));
+    }
+}
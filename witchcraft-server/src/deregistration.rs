@@ -0,0 +1,156 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::announcement::DeregistrationReason;
+use crate::health::api::HealthState;
+use crate::health::HealthCheckRegistry;
+use futures_util::future::{self, BoxFuture};
+use parking_lot::Mutex;
+use std::mem;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tokio::time::{self, Instant};
+use witchcraft_log::info;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub type DeregisterHook = Box<dyn FnOnce(DeregistrationReason) -> BoxFuture<'static, ()> + Send>;
+
+/// Invokes the server's deregistration callbacks at most once, when it either begins shutting down or remains
+/// unhealthy for too long.
+pub struct Deregistration {
+    hooks: Mutex<Vec<DeregisterHook>>,
+}
+
+impl Deregistration {
+    pub fn new(hooks: Vec<DeregisterHook>) -> Arc<Self> {
+        Arc::new(Deregistration {
+            hooks: Mutex::new(hooks),
+        })
+    }
+
+    /// Runs the deregistration callbacks if they have not already been run.
+    pub async fn deregister(&self, reason: DeregistrationReason) {
+        let hooks = mem::take(&mut *self.hooks.lock());
+        if hooks.is_empty() {
+            return;
+        }
+
+        info!(
+            "deregistering server",
+            safe: {
+                reason: format_args!("{reason:?}"),
+            },
+        );
+        future::join_all(hooks.into_iter().map(|hook| hook(reason))).await;
+    }
+
+    /// Periodically checks the server's health until the deregistration is dropped, deregistering once it has been
+    /// unhealthy for longer than the delay.
+    pub async fn monitor(
+        deregistration: Weak<Deregistration>,
+        health_checks: Arc<HealthCheckRegistry>,
+        delay: Duration,
+    ) {
+        let mut tracker = UnhealthyTracker::new(delay);
+        let mut interval = time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            let Some(deregistration) = deregistration.upgrade() else {
+                break;
+            };
+
+            let unhealthy = health_checks
+                .run_checks()
+                .checks()
+                .values()
+                .any(|r| *r.state() >= HealthState::Error);
+
+            if tracker.update(unhealthy, Instant::now()) {
+                deregistration
+                    .deregister(DeregistrationReason::Unhealthy)
+                    .await;
+                break;
+            }
+        }
+    }
+}
+
+struct UnhealthyTracker {
+    delay: Duration,
+    unhealthy_since: Option<Instant>,
+}
+
+impl UnhealthyTracker {
+    fn new(delay: Duration) -> Self {
+        UnhealthyTracker {
+            delay,
+            unhealthy_since: None,
+        }
+    }
+
+    /// Records the server's current health, returning `true` if it has been continuously unhealthy for the delay.
+    fn update(&mut self, unhealthy: bool, now: Instant) -> bool {
+        if !unhealthy {
+            self.unhealthy_since = None;
+            return false;
+        }
+
+        let since = *self.unhealthy_since.get_or_insert(now);
+        now - since >= self.delay
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::FutureExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn sustained_unhealthy() {
+        let start = Instant::now();
+        let mut tracker = UnhealthyTracker::new(Duration::from_secs(10));
+
+        assert!(!tracker.update(true, start));
+        assert!(!tracker.update(true, start + Duration::from_secs(5)));
+        assert!(!tracker.update(false, start + Duration::from_secs(6)));
+        assert!(!tracker.update(true, start + Duration::from_secs(7)));
+        assert!(!tracker.update(true, start + Duration::from_secs(16)));
+        assert!(tracker.update(true, start + Duration::from_secs(17)));
+    }
+
+    #[test]
+    fn deregisters_once() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let hook: DeregisterHook = Box::new({
+            let calls = calls.clone();
+            move |reason| {
+                assert_eq!(reason, DeregistrationReason::Unhealthy);
+                calls.fetch_add(1, Ordering::Relaxed);
+                async {}.boxed()
+            }
+        });
+        let deregistration = Deregistration::new(vec![hook]);
+
+        deregistration
+            .deregister(DeregistrationReason::Unhealthy)
+            .now_or_never()
+            .unwrap();
+        deregistration
+            .deregister(DeregistrationReason::Shutdown)
+            .now_or_never()
+            .unwrap();
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,156 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::health::service_dependency::IDLE_TIMEOUT;
+use conjure_error::Error;
+use conjure_runtime::HostMetricsRegistry;
+use parking_lot::Mutex;
+use std::collections::BTreeSet;
+use std::net::IpAddr;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use tokio::net;
+use tokio::time;
+use witchcraft_metrics::{Meter, MetricId, MetricRegistry, Timer};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The state of DNS resolution for the hosts the server's clients connect to.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DnsHealth {
+    Healthy,
+    /// The most recent lookups of the hosts failed.
+    Failing(BTreeSet<String>),
+}
+
+/// A periodic probe of DNS resolution for the hosts the server's clients connect to.
+///
+/// Outbound clients resolve hostnames on their own, so the probe periodically looks up the hosts they have recently
+/// connected to, as reported by the host metrics registry. This surfaces DNS outages in the `dns.lookup` metrics and
+/// the `DNS_RESOLUTION` health check rather than only as client timeouts. The results of the lookups are not used by
+/// the clients.
+pub struct DnsProbe {
+    host_metrics: Arc<HostMetricsRegistry>,
+    failing: Mutex<BTreeSet<String>>,
+    lookup: Arc<Timer>,
+    failures: Arc<Meter>,
+}
+
+impl DnsProbe {
+    pub fn new(metrics: &MetricRegistry, host_metrics: &Arc<HostMetricsRegistry>) -> Self {
+        DnsProbe {
+            host_metrics: host_metrics.clone(),
+            failing: Mutex::new(BTreeSet::new()),
+            lookup: metrics.timer(MetricId::new("dns.lookup")),
+            failures: metrics.meter(MetricId::new("dns.lookup.failure")),
+        }
+    }
+
+    /// Looks up a hostname, recording whether it resolved.
+    pub async fn probe(&self, host: &str) -> Result<(), Error> {
+        let start = Instant::now();
+        let result = match time::timeout(LOOKUP_TIMEOUT, net::lookup_host((host, 0))).await {
+            Ok(Ok(mut addrs)) => {
+                if addrs.next().is_some() {
+                    Ok(())
+                } else {
+                    Err(Error::internal_safe("host resolved to no addresses"))
+                }
+            }
+            Ok(Err(e)) => Err(Error::internal_safe(e)),
+            Err(e) => Err(Error::internal_safe(e)),
+        };
+        self.lookup.update(start.elapsed());
+
+        match result {
+            Ok(()) => {
+                self.failing.lock().remove(host);
+                Ok(())
+            }
+            Err(e) => {
+                self.failures.mark(1);
+                self.failing.lock().insert(host.to_string());
+                Err(e.with_safe_param("host", host))
+            }
+        }
+    }
+
+    /// Returns the state of resolution for the hosts the server's clients have recently used.
+    pub fn health(&self) -> DnsHealth {
+        let failing = self.failing.lock();
+        if failing.is_empty() {
+            DnsHealth::Healthy
+        } else {
+            DnsHealth::Failing(failing.clone())
+        }
+    }
+
+    /// Periodically looks up the hosts the server's clients have recently used until the probe is dropped.
+    pub async fn run(probe: Weak<DnsProbe>) {
+        let mut interval = time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let Some(probe) = probe.upgrade() else {
+                break;
+            };
+
+            let hosts = probe.active_hosts();
+            probe.failing.lock().retain(|host| hosts.contains(host));
+            for host in &hosts {
+                let _ = probe.probe(host).await;
+            }
+        }
+    }
+
+    fn active_hosts(&self) -> BTreeSet<String> {
+        let cutoff = Instant::now() - IDLE_TIMEOUT;
+        self.host_metrics
+            .hosts()
+            .iter()
+            .filter(|m| m.last_update() > cutoff)
+            .map(|m| m.hostname().to_string())
+            .filter(|h| h.parse::<IpAddr>().is_err())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn probe() -> DnsProbe {
+        DnsProbe::new(
+            &MetricRegistry::new(),
+            &Arc::new(HostMetricsRegistry::new()),
+        )
+    }
+
+    #[tokio::test]
+    async fn success() {
+        let probe = probe();
+        probe.probe("localhost").await.unwrap();
+        assert_eq!(probe.health(), DnsHealth::Healthy);
+    }
+
+    #[tokio::test]
+    async fn failure() {
+        let probe = probe();
+        probe.probe("does-not-exist.invalid").await.unwrap_err();
+        assert_eq!(
+            probe.health(),
+            DnsHealth::Failing(BTreeSet::from(["does-not-exist.invalid".to_string()])),
+        );
+        assert_eq!(probe.failures.count(), 1);
+    }
+}
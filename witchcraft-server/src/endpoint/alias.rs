@@ -0,0 +1,114 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::endpoint::WitchcraftEndpoint;
+use crate::health::endpoint_500s::EndpointHealth;
+use crate::server::RawBody;
+use crate::service::endpoint_metrics::EndpointMetrics;
+use crate::service::handler::BodyWriteAborted;
+use bytes::Bytes;
+use conjure_http::server::{EndpointMetadata, PathSegment};
+use futures_util::future::BoxFuture;
+use http::{Method, Request, Response};
+use http_body_util::combinators::BoxBody;
+use std::sync::Arc;
+use witchcraft_metrics::{Meter, MetricId, MetricRegistry};
+
+/// A [`WitchcraftEndpoint`] shared between the server's context path and its aliases.
+///
+/// Requests made via an alias are counted in a meter tagged with the alias.
+pub struct AliasEndpoint {
+    inner: Arc<dyn WitchcraftEndpoint + Sync + Send>,
+    requests: Option<Arc<Meter>>,
+}
+
+impl AliasEndpoint {
+    /// Creates the endpoint registered under the server's context path.
+    pub fn primary(inner: Arc<dyn WitchcraftEndpoint + Sync + Send>) -> Self {
+        AliasEndpoint {
+            inner,
+            requests: None,
+        }
+    }
+
+    /// Creates an endpoint registered under a context path alias.
+    pub fn alias(
+        metrics: &MetricRegistry,
+        inner: Arc<dyn WitchcraftEndpoint + Sync + Send>,
+        context_path: &str,
+    ) -> Self {
+        // Like the rest of the endpoint metrics, the server's built-in endpoints are not tracked.
+        let requests = inner.metrics().map(|_| {
+            metrics.meter(
+                MetricId::new("server.request.context-path-alias")
+                    .with_tag("context-path", context_path.to_string())
+                    .with_tag("service-name", inner.service_name().to_string())
+                    .with_tag("endpoint", inner.name().to_string()),
+            )
+        });
+
+        AliasEndpoint { inner, requests }
+    }
+}
+
+impl EndpointMetadata for AliasEndpoint {
+    fn method(&self) -> Method {
+        self.inner.method()
+    }
+
+    fn path(&self) -> &[PathSegment] {
+        self.inner.path()
+    }
+
+    fn template(&self) -> &str {
+        self.inner.template()
+    }
+
+    fn service_name(&self) -> &str {
+        self.inner.service_name()
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn deprecated(&self) -> Option<&str> {
+        self.inner.deprecated()
+    }
+}
+
+impl WitchcraftEndpoint for AliasEndpoint {
+    fn metrics(&self) -> Option<&EndpointMetrics> {
+        self.inner.metrics()
+    }
+
+    fn health(&self) -> Option<&Arc<EndpointHealth>> {
+        self.inner.health()
+    }
+
+    // manually implementing to avoid double boxing the inner future
+    fn handle<'life0, 'async_trait>(
+        &'life0 self,
+        req: Request<RawBody>,
+    ) -> BoxFuture<'async_trait, Response<BoxBody<Bytes, BodyWriteAborted>>>
+    where
+        'life0: 'async_trait,
+        Self: 'async_trait,
+    {
+        if let Some(requests) = &self.requests {
+            requests.mark(1);
+        }
+
+        self.inner.handle(req)
+    }
+}
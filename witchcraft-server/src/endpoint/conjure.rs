@@ -11,11 +11,13 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-use crate::endpoint::{errors, WitchcraftEndpoint};
+use crate::endpoint::{errors, validation, WitchcraftEndpoint};
 use crate::health::endpoint_500s::EndpointHealth;
+use crate::outbound::OutboundCalls;
 use crate::server::RawBody;
-use crate::service::endpoint_metrics::EndpointMetrics;
+use crate::service::endpoint_metrics::{CpuTime, EndpointMetrics};
 use crate::service::handler::BodyWriteAborted;
+use crate::slo::SloRegistry;
 use crate::{RequestBody, ResponseWriter};
 use async_trait::async_trait;
 use bytes::Bytes;
@@ -31,6 +33,7 @@ use http::{Extensions, Method, Request, Response, StatusCode};
 use http_body::{Body, Frame, SizeHint};
 use http_body_util::combinators::BoxBody;
 use http_body_util::BodyExt;
+use std::any::Any;
 use std::future::Future;
 use std::mem;
 use std::panic::AssertUnwindSafe;
@@ -38,25 +41,30 @@ use std::pin::{pin, Pin};
 use std::sync::Arc;
 use std::task::{Context, Poll};
 use sync_wrapper::SyncWrapper;
-use witchcraft_log::info;
+use tokio::runtime::Handle;
+use tokio::task;
+use witchcraft_log::{info, mdc};
 use witchcraft_metrics::MetricRegistry;
 
 /// A [`WitchcraftEndpoint`] wrapping a Conjure [`AsyncEndpoint`].
 pub struct ConjureEndpoint {
-    inner: BoxAsyncEndpoint<'static, RequestBody, ResponseWriter>,
+    inner: Arc<dyn AsyncEndpoint<RequestBody, ResponseWriter> + Sync + Send>,
     metrics: Option<EndpointMetrics>,
     health: Option<Arc<EndpointHealth>>,
+    may_block: bool,
 }
 
 impl ConjureEndpoint {
     pub fn new(
-        metrics: Option<&MetricRegistry>,
+        metrics: Option<(&MetricRegistry, &SloRegistry)>,
         inner: BoxAsyncEndpoint<'static, RequestBody, ResponseWriter>,
+        may_block: bool,
     ) -> Self {
         ConjureEndpoint {
-            metrics: metrics.map(|metrics| EndpointMetrics::new(metrics, &inner)),
+            metrics: metrics.map(|(metrics, slos)| EndpointMetrics::new(metrics, slos, &inner)),
             health: metrics.map(|_| Arc::new(EndpointHealth::new())),
-            inner,
+            inner: Arc::from(inner),
+            may_block,
         }
     }
 }
@@ -99,12 +107,20 @@ impl WitchcraftEndpoint for ConjureEndpoint {
 
     async fn handle(&self, req: Request<RawBody>) -> Response<BoxBody<Bytes, BodyWriteAborted>> {
         let req = req.map(RequestBody::new);
-        let mut response_extensions = Extensions::new();
 
-        let mut response = match AssertUnwindSafe(self.inner.handle(req, &mut response_extensions))
+        let (result, response_extensions) = if self.may_block {
+            self.handle_blocking(req).await
+        } else {
+            let mut response_extensions = Extensions::new();
+            let result = AssertUnwindSafe(validation::scope(
+                self.inner.handle(req, &mut response_extensions),
+            ))
             .catch_unwind()
-            .await
-        {
+            .await;
+            (result, response_extensions)
+        };
+
+        let mut response = match result {
             Ok(Ok(response)) => response.map(ResponseBody::new),
             Ok(Err(error)) => errors::to_response(error, |o| {
                 o.map_or(
@@ -129,6 +145,56 @@ impl WitchcraftEndpoint for ConjureEndpoint {
     }
 }
 
+impl ConjureEndpoint {
+    /// Runs the handler to completion on a thread of Tokio's blocking pool, so blocking calls it makes don't stall the
+    /// runtime's worker threads.
+    async fn handle_blocking(&self, req: Request<RequestBody>) -> (HandleResult, Extensions) {
+        let trace_context = zipkin::current();
+        let snapshot = mdc::snapshot();
+        let inner = self.inner.clone();
+        let handle = Handle::current();
+
+        let cpu_time = req.extensions().get::<CpuTime>().cloned();
+        let outbound = req.extensions().get::<OutboundCalls>().cloned();
+
+        let run = move || {
+            let _guard = trace_context.map(zipkin::set_current);
+            let previous = mdc::snapshot();
+            mdc::set(snapshot);
+
+            let mut response_extensions = Extensions::new();
+            let result = handle.block_on(
+                AssertUnwindSafe(validation::scope(
+                    inner.handle(req, &mut response_extensions),
+                ))
+                .catch_unwind(),
+            );
+
+            // the blocking pool's threads are shared with the rest of the process
+            mdc::set(previous);
+            (result, response_extensions)
+        };
+        let blocking = move || {
+            let run = || match outbound {
+                Some(outbound) => outbound.scope(run),
+                None => run(),
+            };
+            match cpu_time {
+                Some(cpu_time) => cpu_time.measure(run),
+                None => run(),
+            }
+        };
+
+        match task::spawn_blocking(blocking).await {
+            Ok(r) => r,
+            Err(e) => (Ok(Err(Error::internal_safe(e))), Extensions::new()),
+        }
+    }
+}
+
+type HandleResult =
+    Result<Result<Response<AsyncResponseBody<ResponseWriter>>, Error>, Box<dyn Any + Send>>;
+
 enum State {
     Empty,
     Fixed(Frame<Bytes>),
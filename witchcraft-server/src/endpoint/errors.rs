@@ -13,10 +13,11 @@ use std::sync::Arc;
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use crate::retry::Retryability;
 use bytes::Bytes;
 use conjure_error::{Error, ErrorKind};
 use conjure_serde::json;
-use http::header::{CONTENT_TYPE, RETRY_AFTER};
+use http::header::CONTENT_TYPE;
 use http::{HeaderValue, Response, StatusCode};
 use witchcraft_log::error;
 
@@ -38,13 +39,9 @@ where
                 .insert(CONTENT_TYPE, APPLICATION_JSON);
             response
         }
-        ErrorKind::Throttle(throttle) => {
+        ErrorKind::Throttle(_) => {
             let mut response = Response::new(body_creator(None));
             *response.status_mut() = StatusCode::TOO_MANY_REQUESTS;
-            if let Some(duration) = throttle.duration() {
-                let header = HeaderValue::from(duration.as_secs());
-                response.headers_mut().insert(RETRY_AFTER, header);
-            }
             response
         }
         ErrorKind::Unavailable(_) => {
@@ -60,6 +57,10 @@ where
         }
     };
 
+    if let Some(retryability) = Retryability::of(&error) {
+        retryability.apply(response.headers_mut());
+    }
+
     response.extensions_mut().insert(Arc::new(error));
     response
 }
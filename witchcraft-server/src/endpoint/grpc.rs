@@ -0,0 +1,197 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::body::RequestBodyError;
+use crate::endpoint::WitchcraftEndpoint;
+use crate::health::endpoint_500s::EndpointHealth;
+use crate::server::RawBody;
+use crate::service::endpoint_metrics::EndpointMetrics;
+use crate::service::handler::{self, BodyWriteAborted};
+use crate::slo::SloRegistry;
+use async_trait::async_trait;
+use bytes::Bytes;
+use conjure_error::{Error, InvalidArgument};
+use conjure_http::server::{EndpointMetadata, PathSegment};
+use futures_util::future::BoxFuture;
+use http::header::CONTENT_TYPE;
+use http::{Method, Request, Response};
+use http_body::{Body, Frame};
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use std::borrow::Cow;
+use std::error;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use sync_wrapper::SyncWrapper;
+use witchcraft_log::info;
+use witchcraft_metrics::MetricRegistry;
+
+pub type Handler = Arc<
+    dyn Fn(Request<tonic::body::BoxBody>) -> BoxFuture<'static, Result<Response<GrpcBody>, Error>>
+        + Sync
+        + Send,
+>;
+
+/// An endpoint which dispatches all methods of a gRPC service to a handler.
+///
+/// gRPC clients don't support path prefixes, so the endpoint is installed at `/<service>/{method}` outside of the
+/// server's context path.
+pub struct GrpcEndpoint {
+    path: Vec<PathSegment>,
+    template: String,
+    service_name: String,
+    handler: Handler,
+    metrics: Option<EndpointMetrics>,
+    health: Option<Arc<EndpointHealth>>,
+}
+
+impl GrpcEndpoint {
+    pub fn new(
+        metrics: &MetricRegistry,
+        slos: &SloRegistry,
+        service_name: &str,
+        handler: Handler,
+    ) -> Self {
+        let mut endpoint = GrpcEndpoint {
+            path: vec![
+                PathSegment::Literal(Cow::Owned(service_name.to_string())),
+                PathSegment::Parameter {
+                    name: Cow::Borrowed("method"),
+                    regex: None,
+                },
+            ],
+            template: format!("/{service_name}/{{method}}"),
+            service_name: service_name.to_string(),
+            handler,
+            metrics: None,
+            health: Some(Arc::new(EndpointHealth::new())),
+        };
+        endpoint.metrics = Some(EndpointMetrics::new(metrics, slos, &endpoint));
+
+        endpoint
+    }
+}
+
+impl EndpointMetadata for GrpcEndpoint {
+    fn method(&self) -> Method {
+        Method::POST
+    }
+
+    fn path(&self) -> &[PathSegment] {
+        &self.path
+    }
+
+    fn template(&self) -> &str {
+        &self.template
+    }
+
+    fn service_name(&self) -> &str {
+        &self.service_name
+    }
+
+    fn name(&self) -> &str {
+        &self.template
+    }
+
+    fn deprecated(&self) -> Option<&str> {
+        None
+    }
+}
+
+#[async_trait]
+impl WitchcraftEndpoint for GrpcEndpoint {
+    fn metrics(&self) -> Option<&EndpointMetrics> {
+        self.metrics.as_ref()
+    }
+
+    fn health(&self) -> Option<&Arc<EndpointHealth>> {
+        self.health.as_ref()
+    }
+
+    async fn handle(&self, req: Request<RawBody>) -> Response<BoxBody<Bytes, BodyWriteAborted>> {
+        if !is_grpc(&req) {
+            return handler::error_response(Error::service_safe(
+                "expected a gRPC request",
+                InvalidArgument::new(),
+            ));
+        }
+
+        let req = req.map(|b| tonic::body::boxed(b.map_err(RequestBodyError)));
+        match (self.handler)(req).await {
+            Ok(response) => response.map(|b| b.boxed()),
+            Err(e) => handler::error_response(e),
+        }
+    }
+}
+
+fn is_grpc<B>(req: &Request<B>) -> bool {
+    req.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("application/grpc"))
+        .is_some_and(|v| v.is_empty() || v.starts_with('+') || v.starts_with(';'))
+}
+
+/// A gRPC response body.
+pub struct GrpcBody {
+    inner: SyncWrapper<tonic::body::BoxBody>,
+}
+
+impl GrpcBody {
+    pub fn new<B>(inner: B) -> Self
+    where
+        B: Body<Data = Bytes> + 'static + Send,
+        B::Error: Into<Box<dyn error::Error + Sync + Send>>,
+    {
+        GrpcBody {
+            inner: SyncWrapper::new(tonic::body::boxed(inner)),
+        }
+    }
+}
+
+impl Body for GrpcBody {
+    type Data = Bytes;
+
+    type Error = BodyWriteAborted;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Pin::new(self.inner.get_mut()).poll_frame(cx).map_err(|e| {
+            info!("error writing response body", error: Error::internal_safe(e));
+            BodyWriteAborted
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn grpc_detection() {
+        let request = |content_type| {
+            Request::builder()
+                .header(CONTENT_TYPE, content_type)
+                .body(())
+                .unwrap()
+        };
+
+        assert!(is_grpc(&request("application/grpc")));
+        assert!(is_grpc(&request("application/grpc+proto")));
+        assert!(!is_grpc(&request("application/grpc-web")));
+        assert!(!is_grpc(&request("application/json")));
+    }
+}
@@ -23,9 +23,17 @@ use http::{Request, Response};
 use http_body_util::combinators::BoxBody;
 use std::sync::Arc;
 
+pub mod alias;
 pub mod conjure;
 pub mod errors;
 pub mod extended_path;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod upgrade;
+pub mod validation;
+pub mod versioned;
+#[cfg(feature = "websocket")]
+pub mod websocket;
 
 #[async_trait]
 pub trait WitchcraftEndpoint: EndpointMetadata {
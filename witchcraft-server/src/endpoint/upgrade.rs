@@ -0,0 +1,269 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::endpoint::WitchcraftEndpoint;
+use crate::extensions::ShutdownSignal;
+use crate::health::endpoint_500s::EndpointHealth;
+use crate::server::RawBody;
+use crate::service::endpoint_metrics::EndpointMetrics;
+use crate::service::handler::{self, BodyWriteAborted, EmptyBody};
+use crate::slo::SloRegistry;
+use crate::upgrade::{UpgradeHandler, UpgradedConnection};
+use async_trait::async_trait;
+use bytes::Bytes;
+use conjure_error::{Error, InvalidArgument};
+use conjure_http::server::{EndpointMetadata, PathSegment};
+use http::header::{CONNECTION, UPGRADE};
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode, Version};
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use hyper::ext::Protocol;
+use std::borrow::Cow;
+use std::sync::Arc;
+use tokio_util::task::TaskTracker;
+use witchcraft_log::debug;
+use witchcraft_metrics::{Counter, MetricId, MetricRegistry};
+
+const SERVICE_NAME: &str = "Upgrade";
+
+struct Shared<T> {
+    protocol: String,
+    handler: T,
+    shutdown_signal: ShutdownSignal,
+    tasks: TaskTracker,
+    active: Arc<Counter>,
+}
+
+/// An endpoint which upgrades requests to a custom protocol and hands the upgraded connections to a handler.
+///
+/// HTTP/1.1 upgrades and HTTP/2 extended `CONNECT` requests are routed on different methods, so a pair of endpoints
+/// sharing a handler is installed for each path.
+pub struct UpgradeEndpoint<T> {
+    method: Method,
+    path: Vec<PathSegment>,
+    template: String,
+    shared: Arc<Shared<T>>,
+    metrics: Option<EndpointMetrics>,
+    health: Option<Arc<EndpointHealth>>,
+}
+
+impl<T> UpgradeEndpoint<T>
+where
+    T: UpgradeHandler + 'static + Sync + Send,
+{
+    pub fn new_pair(
+        metrics: &MetricRegistry,
+        slos: &SloRegistry,
+        template: &str,
+        protocol: &str,
+        handler: T,
+        shutdown_signal: &ShutdownSignal,
+        tasks: &TaskTracker,
+    ) -> [Self; 2] {
+        let shared = Arc::new(Shared {
+            protocol: protocol.to_string(),
+            handler,
+            shutdown_signal: shutdown_signal.clone(),
+            tasks: tasks.clone(),
+            active: metrics.counter(
+                MetricId::new("server.upgrade.active").with_tag("protocol", protocol.to_string()),
+            ),
+        });
+
+        [Method::GET, Method::CONNECT].map(|method| {
+            let mut endpoint = UpgradeEndpoint {
+                method,
+                path: template
+                    .split('/')
+                    .filter(|s| !s.is_empty())
+                    .map(|s| PathSegment::Literal(Cow::Owned(s.to_string())))
+                    .collect(),
+                template: template.to_string(),
+                shared: shared.clone(),
+                metrics: None,
+                health: Some(Arc::new(EndpointHealth::new())),
+            };
+            endpoint.metrics = Some(EndpointMetrics::new(metrics, slos, &endpoint));
+            endpoint
+        })
+    }
+
+    fn upgrade(&self, mut req: Request<RawBody>) -> Result<HeaderMap, Error> {
+        if !is_upgrade(&req, &self.shared.protocol) {
+            return Err(Error::service_safe(
+                "expected a protocol upgrade request",
+                InvalidArgument::new(),
+            )
+            .with_safe_param("protocol", &self.shared.protocol));
+        }
+
+        let on_upgrade = hyper::upgrade::on(&mut req);
+        let (parts, _) = req.into_parts();
+        let headers = self.shared.handler.accept(&parts)?;
+
+        let shared = self.shared.clone();
+        self.shared.tasks.spawn(async move {
+            let upgraded = match on_upgrade.await {
+                Ok(upgraded) => upgraded,
+                Err(e) => {
+                    debug!(
+                        "protocol upgrade failed",
+                        safe: { protocol: shared.protocol },
+                        error: Error::internal_safe(e),
+                    );
+                    return;
+                }
+            };
+
+            let connection =
+                UpgradedConnection::new(upgraded, parts, shared.shutdown_signal.clone());
+
+            shared.active.inc();
+            let _guard = ActiveGuard(&shared.active);
+            shared.handler.handle(connection).await;
+        });
+
+        Ok(headers)
+    }
+}
+
+impl<T> EndpointMetadata for UpgradeEndpoint<T> {
+    fn method(&self) -> Method {
+        self.method.clone()
+    }
+
+    fn path(&self) -> &[PathSegment] {
+        &self.path
+    }
+
+    fn template(&self) -> &str {
+        &self.template
+    }
+
+    fn service_name(&self) -> &str {
+        SERVICE_NAME
+    }
+
+    fn name(&self) -> &str {
+        &self.template
+    }
+
+    fn deprecated(&self) -> Option<&str> {
+        None
+    }
+}
+
+#[async_trait]
+impl<T> WitchcraftEndpoint for UpgradeEndpoint<T>
+where
+    T: UpgradeHandler + 'static + Sync + Send,
+{
+    fn metrics(&self) -> Option<&EndpointMetrics> {
+        self.metrics.as_ref()
+    }
+
+    fn health(&self) -> Option<&Arc<EndpointHealth>> {
+        self.health.as_ref()
+    }
+
+    async fn handle(&self, req: Request<RawBody>) -> Response<BoxBody<Bytes, BodyWriteAborted>> {
+        let http2 = req.version() == Version::HTTP_2;
+        let headers = match self.upgrade(req) {
+            Ok(headers) => headers,
+            Err(e) => return handler::error_response(e),
+        };
+
+        let mut response = Response::new(EmptyBody.boxed());
+        *response.headers_mut() = headers;
+        if http2 {
+            // RFC 8441 extended CONNECT streams are established with a 2xx response.
+            *response.status_mut() = StatusCode::OK;
+        } else {
+            *response.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+            response
+                .headers_mut()
+                .insert(CONNECTION, HeaderValue::from_static("upgrade"));
+            response.headers_mut().insert(
+                UPGRADE,
+                HeaderValue::try_from(&self.shared.protocol).unwrap(),
+            );
+        }
+        response
+    }
+}
+
+struct ActiveGuard<'a>(&'a Counter);
+
+impl Drop for ActiveGuard<'_> {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+fn is_upgrade<B>(req: &Request<B>, protocol: &str) -> bool {
+    match req.version() {
+        Version::HTTP_11 => {
+            req.method() == Method::GET
+                && has_token(req.headers(), &CONNECTION, "upgrade")
+                && has_token(req.headers(), &UPGRADE, protocol)
+        }
+        Version::HTTP_2 => {
+            req.method() == Method::CONNECT
+                && req
+                    .extensions()
+                    .get::<Protocol>()
+                    .map_or(false, |p| p.as_str().eq_ignore_ascii_case(protocol))
+        }
+        _ => false,
+    }
+}
+
+fn has_token(headers: &HeaderMap, name: &HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| v.trim().eq_ignore_ascii_case(token))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn upgrade_detection() {
+        let req = Request::builder()
+            .header(CONNECTION, "Upgrade")
+            .header(UPGRADE, "my-protocol")
+            .body(())
+            .unwrap();
+        assert!(is_upgrade(&req, "my-protocol"));
+        assert!(!is_upgrade(&req, "other-protocol"));
+
+        let req = Request::builder()
+            .method(Method::CONNECT)
+            .version(Version::HTTP_2)
+            .extension(Protocol::from_static("my-protocol"))
+            .body(())
+            .unwrap();
+        assert!(is_upgrade(&req, "my-protocol"));
+
+        let req = Request::builder()
+            .method(Method::CONNECT)
+            .version(Version::HTTP_2)
+            .body(())
+            .unwrap();
+        assert!(!is_upgrade(&req, "my-protocol"));
+    }
+}
@@ -0,0 +1,291 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Structured reporting of invalid Conjure request bodies.
+//!
+//! The server's Conjure runtime wraps its encodings so that a failure to deserialize a request body records the path
+//! of the offending field along with the reason it was rejected. The endpoint wrappers then replace the generic
+//! `Default:InvalidArgument` error with one listing each violation as a parameter keyed by its field path.
+use conjure_error::{Error, ErrorCode, ErrorKind, SerializableError};
+use conjure_http::server::{
+    ConjureRuntime, DeserializerState, Encoding, JsonEncoding, SerializerState, SmileEncoding,
+};
+use erased_serde::Deserializer as ErasedDeserializer;
+use http::HeaderValue;
+use serde::de::{self, Visitor};
+use serde::Deserializer;
+use serde_path_to_error::Track;
+use std::cell::RefCell;
+use std::future::Future;
+use std::{error, fmt};
+
+const INVALID_ARGUMENT: &str = "Default:InvalidArgument";
+
+tokio::task_local! {
+    static VIOLATIONS: RefCell<Vec<Violation>>;
+}
+
+/// Creates the server's Conjure runtime, with request body violation tracking enabled.
+pub fn runtime() -> ConjureRuntime {
+    ConjureRuntime::builder()
+        .encoding(TrackingEncoding(JsonEncoding))
+        .encoding(TrackingEncoding(SmileEncoding))
+        .build()
+}
+
+/// Runs an async endpoint, reporting any request body violations in its error.
+pub async fn scope<F, T>(future: F) -> Result<T, Error>
+where
+    F: Future<Output = Result<T, Error>>,
+{
+    VIOLATIONS
+        .scope(RefCell::new(vec![]), async {
+            future
+                .await
+                .map_err(|e| VIOLATIONS.with(|v| report(e, v.take())))
+        })
+        .await
+}
+
+/// Runs a blocking endpoint, reporting any request body violations in its error.
+pub fn sync_scope<F, T>(f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    VIOLATIONS.sync_scope(RefCell::new(vec![]), || {
+        f().map_err(|e| VIOLATIONS.with(|v| report(e, v.take())))
+    })
+}
+
+fn report(error: Error, violations: Vec<Violation>) -> Error {
+    if violations.is_empty() {
+        return error;
+    }
+
+    let ErrorKind::Service(service) = error.kind() else {
+        return error;
+    };
+    if service.error_name() != INVALID_ARGUMENT {
+        return error;
+    }
+
+    let mut builder = SerializableError::builder()
+        .error_code(ErrorCode::InvalidArgument)
+        .error_name(INVALID_ARGUMENT)
+        .error_instance_id(service.error_instance_id());
+    for violation in &violations {
+        builder = builder.insert_parameters(&*violation.path, &*violation.reason);
+    }
+
+    Error::propagated_service(InvalidRequestBody(violations), builder.build())
+}
+
+struct Violation {
+    path: String,
+    reason: String,
+}
+
+#[derive(Debug)]
+struct InvalidRequestBody(Vec<Violation>);
+
+impl fmt::Display for InvalidRequestBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid request body")?;
+        for (i, violation) in self.0.iter().enumerate() {
+            let separator = if i == 0 { ": " } else { "; " };
+            write!(f, "{separator}{}: {}", violation.path, violation.reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.reason)
+    }
+}
+
+impl error::Error for InvalidRequestBody {}
+
+struct TrackingEncoding<E>(E);
+
+impl<E> Encoding for TrackingEncoding<E>
+where
+    E: Encoding,
+{
+    fn content_type(&self) -> HeaderValue {
+        self.0.content_type()
+    }
+
+    fn serializer<'a>(&self, w: &'a mut Vec<u8>) -> Box<dyn SerializerState<'a> + 'a> {
+        self.0.serializer(w)
+    }
+
+    fn deserializer<'a>(&self, buf: &'a [u8]) -> Box<dyn DeserializerState<'a> + 'a> {
+        Box::new(TrackingDeserializerState(self.0.deserializer(buf)))
+    }
+}
+
+struct TrackingDeserializerState<'de>(Box<dyn DeserializerState<'de> + 'de>);
+
+impl<'de> DeserializerState<'de> for TrackingDeserializerState<'de> {
+    fn deserializer<'a>(&'a mut self) -> Box<dyn ErasedDeserializer<'de> + 'a> {
+        Box::new(<dyn ErasedDeserializer>::erase(TrackingDeserializer(
+            self.0.deserializer(),
+        )))
+    }
+}
+
+/// A deserializer which records the path and reason of an error to the current scope.
+struct TrackingDeserializer<D>(D);
+
+fn record<E>(track: Track, error: E) -> E
+where
+    E: de::Error,
+{
+    let _ = VIOLATIONS.try_with(|v| {
+        v.borrow_mut().push(Violation {
+            path: track.path().to_string(),
+            reason: error.to_string(),
+        })
+    });
+    error
+}
+
+macro_rules! forward {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            fn $method<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: Visitor<'de>,
+            {
+                let mut track = Track::new();
+                let result = serde_path_to_error::Deserializer::new(self.0, &mut track)
+                    .$method($($arg,)* visitor);
+                result.map_err(|e| record(track, e))
+            }
+        )*
+    };
+}
+
+impl<'de, D> Deserializer<'de> for TrackingDeserializer<D>
+where
+    D: Deserializer<'de>,
+{
+    type Error = D::Error;
+
+    forward! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+        deserialize_ignored_any();
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.0.is_human_readable()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use conjure_http::server::DeserializeRequest;
+    use conjure_http::server::StdRequestDeserializer;
+    use http::header::CONTENT_TYPE;
+    use http::HeaderMap;
+    use serde::Deserialize;
+    use std::iter;
+
+    #[derive(Deserialize, Debug)]
+    #[allow(dead_code)]
+    struct Request {
+        name: String,
+        items: Vec<Item>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    #[allow(dead_code)]
+    struct Item {
+        count: u32,
+    }
+
+    fn deserialize(body: &'static str) -> Result<Request, Error> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        sync_scope(|| {
+            <StdRequestDeserializer>::deserialize(
+                &runtime(),
+                &headers,
+                iter::once(Ok(body.as_bytes().into())),
+            )
+        })
+    }
+
+    #[test]
+    fn field_paths() {
+        let error = deserialize(r#"{"name": "foo", "items": [{"count": 1}, {"count": "two"}]}"#)
+            .unwrap_err();
+        let ErrorKind::Service(service) = error.kind() else {
+            panic!("expected a service error");
+        };
+        assert_eq!(service.error_name(), INVALID_ARGUMENT);
+        assert_eq!(service.parameters().len(), 1);
+        assert!(service.parameters()["items[1].count"].starts_with("invalid type"));
+
+        let error = deserialize(r#"{"items": []}"#).unwrap_err();
+        let ErrorKind::Service(service) = error.kind() else {
+            panic!("expected a service error");
+        };
+        assert!(service.parameters()["."].starts_with("missing field `name`"));
+    }
+
+    #[test]
+    fn valid_body() {
+        deserialize(r#"{"name": "foo", "items": [{"count": 1}]}"#).unwrap();
+    }
+
+    #[test]
+    fn unrelated_errors() {
+        let error = sync_scope::<_, ()>(|| Err(Error::internal_safe("foo")));
+        assert!(
+            matches!(error.unwrap_err().kind(), ErrorKind::Service(s) if s.error_name() == "Default:Internal")
+        );
+    }
+}
@@ -0,0 +1,225 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::endpoint::{errors, WitchcraftEndpoint};
+use crate::headers::{HeaderMapExt, TypedHeader};
+use crate::health::endpoint_500s::EndpointHealth;
+use crate::server::RawBody;
+use crate::service::endpoint_metrics::EndpointMetrics;
+use crate::service::handler::BodyWriteAborted;
+use crate::versioning::ApiVersion;
+use async_trait::async_trait;
+use bytes::Bytes;
+use conjure_error::{Error, InvalidArgument};
+use conjure_http::server::{EndpointMetadata, PathSegment};
+use http::{HeaderValue, Method, Request, Response};
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Empty, Full};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use witchcraft_metrics::{Meter, MetricId, MetricRegistry};
+
+struct Version {
+    endpoint: Box<dyn WitchcraftEndpoint + Sync + Send>,
+    requests: Arc<Meter>,
+}
+
+/// A [`WitchcraftEndpoint`] dispatching requests to one of several versions of an endpoint based on the request's
+/// `Api-Version` header.
+pub struct VersionedEndpoint {
+    versions: BTreeMap<ApiVersion, Version>,
+}
+
+impl VersionedEndpoint {
+    /// Creates a new versioned endpoint.
+    ///
+    /// The endpoints must all have the same method and path, and there must be at least one.
+    pub fn new(
+        metrics: &MetricRegistry,
+        endpoints: BTreeMap<ApiVersion, Box<dyn WitchcraftEndpoint + Sync + Send>>,
+    ) -> Self {
+        assert!(!endpoints.is_empty());
+
+        let versions = endpoints
+            .into_iter()
+            .map(|(version, endpoint)| {
+                let requests = metrics.meter(
+                    MetricId::new("server.request.api-version")
+                        .with_tag("service-name", endpoint.service_name().to_string())
+                        .with_tag("endpoint", endpoint.name().to_string())
+                        .with_tag("api-version", version.to_string()),
+                );
+                (version, Version { endpoint, requests })
+            })
+            .collect();
+
+        VersionedEndpoint { versions }
+    }
+
+    fn latest(&self) -> &(dyn WitchcraftEndpoint + Sync + Send) {
+        &*self.versions.values().next_back().unwrap().endpoint
+    }
+
+    fn select(&self, requested: Option<ApiVersion>) -> Result<(ApiVersion, &Version), Error> {
+        let selected = match requested {
+            Some(requested) => self.versions.range(..=requested).next_back(),
+            None => self.versions.iter().next(),
+        };
+
+        selected.map(|(v, e)| (*v, e)).ok_or_else(|| {
+            Error::service_safe("unsupported API version", InvalidArgument::new())
+                .with_safe_param("requested", requested.map(ApiVersion::get))
+                .with_safe_param(
+                    "supported",
+                    self.versions.keys().map(|v| v.get()).collect::<Vec<_>>(),
+                )
+        })
+    }
+}
+
+impl EndpointMetadata for VersionedEndpoint {
+    fn method(&self) -> Method {
+        self.latest().method()
+    }
+
+    fn path(&self) -> &[PathSegment] {
+        self.latest().path()
+    }
+
+    fn template(&self) -> &str {
+        self.latest().template()
+    }
+
+    fn service_name(&self) -> &str {
+        self.latest().service_name()
+    }
+
+    fn name(&self) -> &str {
+        self.latest().name()
+    }
+
+    fn deprecated(&self) -> Option<&str> {
+        self.latest().deprecated()
+    }
+}
+
+#[async_trait]
+impl WitchcraftEndpoint for VersionedEndpoint {
+    fn metrics(&self) -> Option<&EndpointMetrics> {
+        self.latest().metrics()
+    }
+
+    fn health(&self) -> Option<&Arc<EndpointHealth>> {
+        self.latest().health()
+    }
+
+    async fn handle(
+        &self,
+        mut req: Request<RawBody>,
+    ) -> Response<BoxBody<Bytes, BodyWriteAborted>> {
+        let selected = req
+            .headers()
+            .typed_get::<ApiVersion>()
+            .and_then(|v| self.select(v));
+        let (version, endpoint) = match selected {
+            Ok(selected) => selected,
+            Err(error) => {
+                return errors::to_response(error, |o| match o {
+                    Some(body) => Full::new(body).map_err(|e| match e {}).boxed(),
+                    None => Empty::new().map_err(|e| match e {}).boxed(),
+                })
+            }
+        };
+
+        endpoint.requests.mark(1);
+        req.extensions_mut().insert(version);
+
+        let mut response = endpoint.endpoint.handle(req).await;
+        response
+            .headers_mut()
+            .insert(ApiVersion::NAME, HeaderValue::from(version.get()));
+        response
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct TestEndpoint;
+
+    impl EndpointMetadata for TestEndpoint {
+        fn method(&self) -> Method {
+            Method::GET
+        }
+
+        fn path(&self) -> &[PathSegment] {
+            &[]
+        }
+
+        fn template(&self) -> &str {
+            ""
+        }
+
+        fn service_name(&self) -> &str {
+            "service"
+        }
+
+        fn name(&self) -> &str {
+            "endpoint"
+        }
+
+        fn deprecated(&self) -> Option<&str> {
+            None
+        }
+    }
+
+    #[async_trait]
+    impl WitchcraftEndpoint for TestEndpoint {
+        fn metrics(&self) -> Option<&EndpointMetrics> {
+            None
+        }
+
+        fn health(&self) -> Option<&Arc<EndpointHealth>> {
+            None
+        }
+
+        async fn handle(&self, _: Request<RawBody>) -> Response<BoxBody<Bytes, BodyWriteAborted>> {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn selection() {
+        let endpoint = VersionedEndpoint::new(
+            &MetricRegistry::new(),
+            [2, 4]
+                .into_iter()
+                .map(|v| (ApiVersion::new(v), Box::new(TestEndpoint) as _))
+                .collect(),
+        );
+
+        let select = |v: Option<u32>| {
+            endpoint
+                .select(v.map(ApiVersion::new))
+                .map(|(v, _)| v.get())
+                .ok()
+        };
+        assert_eq!(select(None), Some(2));
+        assert_eq!(select(Some(2)), Some(2));
+        assert_eq!(select(Some(3)), Some(2));
+        assert_eq!(select(Some(4)), Some(4));
+        assert_eq!(select(Some(10)), Some(4));
+        assert_eq!(select(Some(1)), None);
+    }
+}
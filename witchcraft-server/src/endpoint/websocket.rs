@@ -0,0 +1,254 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::endpoint::WitchcraftEndpoint;
+use crate::extensions::ShutdownSignal;
+use crate::health::endpoint_500s::EndpointHealth;
+use crate::server::RawBody;
+use crate::service::endpoint_metrics::EndpointMetrics;
+use crate::service::handler::{self, BodyWriteAborted, EmptyBody};
+use crate::slo::SloRegistry;
+use crate::websocket::{Handler, WebSocket};
+use async_trait::async_trait;
+use bytes::Bytes;
+use conjure_error::{Error, InvalidArgument};
+use conjure_http::server::{EndpointMetadata, PathSegment};
+use http::header::{
+    CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_VERSION, UPGRADE,
+};
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode, Version};
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use hyper_util::rt::TokioIo;
+use std::borrow::Cow;
+use std::sync::Arc;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::WebSocketStream;
+use tokio_util::task::TaskTracker;
+use witchcraft_log::debug;
+use witchcraft_metrics::{Counter, MetricRegistry};
+
+const SERVICE_NAME: &str = "WebSocket";
+
+/// An endpoint which upgrades requests to the WebSocket protocol and hands the upgraded connections to a handler.
+pub struct WebSocketEndpoint {
+    path: Vec<PathSegment>,
+    template: String,
+    handler: Handler,
+    shutdown_signal: ShutdownSignal,
+    tasks: TaskTracker,
+    active: Arc<Counter>,
+    metrics: Option<EndpointMetrics>,
+    health: Option<Arc<EndpointHealth>>,
+}
+
+impl WebSocketEndpoint {
+    pub fn new(
+        metrics: &MetricRegistry,
+        slos: &SloRegistry,
+        template: &str,
+        handler: Handler,
+        shutdown_signal: &ShutdownSignal,
+        tasks: &TaskTracker,
+    ) -> Self {
+        let mut endpoint = WebSocketEndpoint {
+            path: template
+                .split('/')
+                .filter(|s| !s.is_empty())
+                .map(|s| PathSegment::Literal(Cow::Owned(s.to_string())))
+                .collect(),
+            template: template.to_string(),
+            handler,
+            shutdown_signal: shutdown_signal.clone(),
+            tasks: tasks.clone(),
+            active: metrics.counter("server.websocket.active"),
+            metrics: None,
+            health: Some(Arc::new(EndpointHealth::new())),
+        };
+        endpoint.metrics = Some(EndpointMetrics::new(metrics, slos, &endpoint));
+
+        endpoint
+    }
+
+    fn upgrade(&self, mut req: Request<RawBody>) -> Result<HeaderValue, Error> {
+        if !is_upgrade(&req) {
+            return Err(Error::service_safe(
+                "expected a websocket upgrade request",
+                InvalidArgument::new(),
+            ));
+        }
+        let accept = accept_key(req.headers())?;
+
+        let on_upgrade = hyper::upgrade::on(&mut req);
+        let (parts, _) = req.into_parts();
+        let handler = self.handler.clone();
+        let shutdown_signal = self.shutdown_signal.clone();
+        let active = self.active.clone();
+        self.tasks.spawn(async move {
+            let upgraded = match on_upgrade.await {
+                Ok(upgraded) => upgraded,
+                Err(e) => {
+                    debug!("websocket upgrade failed", error: Error::internal_safe(e));
+                    return;
+                }
+            };
+
+            let stream =
+                WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None).await;
+            let socket = WebSocket::new(stream, parts, async move { shutdown_signal.wait().await });
+
+            active.inc();
+            let _guard = ActiveGuard(&active);
+            handler(socket).await;
+        });
+
+        Ok(accept)
+    }
+}
+
+impl EndpointMetadata for WebSocketEndpoint {
+    fn method(&self) -> Method {
+        Method::GET
+    }
+
+    fn path(&self) -> &[PathSegment] {
+        &self.path
+    }
+
+    fn template(&self) -> &str {
+        &self.template
+    }
+
+    fn service_name(&self) -> &str {
+        SERVICE_NAME
+    }
+
+    fn name(&self) -> &str {
+        &self.template
+    }
+
+    fn deprecated(&self) -> Option<&str> {
+        None
+    }
+}
+
+#[async_trait]
+impl WitchcraftEndpoint for WebSocketEndpoint {
+    fn metrics(&self) -> Option<&EndpointMetrics> {
+        self.metrics.as_ref()
+    }
+
+    fn health(&self) -> Option<&Arc<EndpointHealth>> {
+        self.health.as_ref()
+    }
+
+    async fn handle(&self, req: Request<RawBody>) -> Response<BoxBody<Bytes, BodyWriteAborted>> {
+        let accept = match self.upgrade(req) {
+            Ok(accept) => accept,
+            Err(e) => return handler::error_response(e),
+        };
+
+        let mut response = Response::new(EmptyBody.boxed());
+        *response.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+        response
+            .headers_mut()
+            .insert(CONNECTION, HeaderValue::from_static("upgrade"));
+        response
+            .headers_mut()
+            .insert(UPGRADE, HeaderValue::from_static("websocket"));
+        response.headers_mut().insert(SEC_WEBSOCKET_ACCEPT, accept);
+        response
+    }
+}
+
+struct ActiveGuard<'a>(&'a Counter);
+
+impl Drop for ActiveGuard<'_> {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+fn is_upgrade<B>(req: &Request<B>) -> bool {
+    req.version() == Version::HTTP_11
+        && has_token(req.headers(), &CONNECTION, "upgrade")
+        && has_token(req.headers(), &UPGRADE, "websocket")
+}
+
+fn has_token(headers: &HeaderMap, name: &HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| v.trim().eq_ignore_ascii_case(token))
+}
+
+fn accept_key(headers: &HeaderMap) -> Result<HeaderValue, Error> {
+    if headers.get(SEC_WEBSOCKET_VERSION).map(|v| v.as_bytes()) != Some(b"13") {
+        return Err(Error::service_safe(
+            "unsupported websocket version",
+            InvalidArgument::new(),
+        ));
+    }
+
+    let Some(key) = headers.get(SEC_WEBSOCKET_KEY) else {
+        return Err(Error::service_safe(
+            "missing websocket key",
+            InvalidArgument::new(),
+        ));
+    };
+
+    Ok(HeaderValue::try_from(derive_accept_key(key.as_bytes())).unwrap())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn accept() {
+        // from RFC 6455 section 1.3
+        let mut headers = HeaderMap::new();
+        headers.insert(SEC_WEBSOCKET_VERSION, HeaderValue::from_static("13"));
+        headers.insert(
+            SEC_WEBSOCKET_KEY,
+            HeaderValue::from_static("dGhlIHNhbXBsZSBub25jZQ=="),
+        );
+
+        assert_eq!(
+            accept_key(&headers).unwrap(),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=",
+        );
+
+        headers.insert(SEC_WEBSOCKET_VERSION, HeaderValue::from_static("8"));
+        accept_key(&headers).unwrap_err();
+    }
+
+    #[test]
+    fn upgrade_detection() {
+        let req = Request::builder()
+            .header(CONNECTION, "keep-alive, Upgrade")
+            .header(UPGRADE, "websocket")
+            .body(())
+            .unwrap();
+        assert!(is_upgrade(&req));
+
+        let req = Request::builder()
+            .header(UPGRADE, "websocket")
+            .body(())
+            .unwrap();
+        assert!(!is_upgrade(&req));
+    }
+}
@@ -14,8 +14,13 @@
 
 //! Types used with the extensions maps of requests or responses in a Witchcraft server.
 
+use http::HeaderName;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::net::SocketAddr;
 use std::ops::Deref;
+use std::time::Instant;
+use tokio_util::sync::CancellationToken;
 
 use crate::logging::api::AuditLogV3;
 
@@ -34,6 +39,206 @@ impl Deref for PeerAddr {
     }
 }
 
+/// An extension containing geographic information about the client of a request.
+///
+/// It will be present in the extensions of requests to the service port if a [`GeoLookup`] has been installed via
+/// [`Witchcraft::geo_lookup`].
+///
+/// [`GeoLookup`]: crate::geo::GeoLookup
+/// [`Witchcraft::geo_lookup`]: crate::Witchcraft::geo_lookup
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GeoInfo {
+    country: Option<String>,
+    asn: Option<u32>,
+}
+
+impl GeoInfo {
+    /// Creates a new `GeoInfo` with no information.
+    #[inline]
+    pub fn new() -> Self {
+        GeoInfo::default()
+    }
+
+    /// Sets the client's country, as an ISO 3166-1 alpha-2 code.
+    #[inline]
+    pub fn with_country(mut self, country: impl Into<String>) -> Self {
+        self.country = Some(country.into());
+        self
+    }
+
+    /// Sets the client's autonomous system number.
+    #[inline]
+    pub fn with_asn(mut self, asn: u32) -> Self {
+        self.asn = Some(asn);
+        self
+    }
+
+    /// Returns the client's country, as an ISO 3166-1 alpha-2 code.
+    #[inline]
+    pub fn country(&self) -> Option<&str> {
+        self.country.as_deref()
+    }
+
+    /// Returns the client's autonomous system number.
+    #[inline]
+    pub fn asn(&self) -> Option<u32> {
+        self.asn
+    }
+}
+
+/// An extension containing the classification of a request.
+///
+/// It will be present in the extensions of requests to the service port if a [`RequestClassifier`] has been installed
+/// via [`Witchcraft::request_classifier`].
+///
+/// [`RequestClassifier`]: crate::classification::RequestClassifier
+/// [`Witchcraft::request_classifier`]: crate::Witchcraft::request_classifier
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RequestClass {
+    labels: BTreeMap<String, String>,
+    priority: Priority,
+}
+
+impl RequestClass {
+    /// Creates a new `RequestClass` with no labels and normal priority.
+    #[inline]
+    pub fn new() -> Self {
+        RequestClass::default()
+    }
+
+    /// Adds a label to the classification.
+    ///
+    /// Labels are used as tags of the `server.request.classified` metric, so they should have a small number of
+    /// distinct values.
+    #[inline]
+    pub fn with_label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.labels.insert(key.into(), value.into());
+        self
+    }
+
+    /// Sets the request's load shedding priority.
+    #[inline]
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Returns the value of the specified label.
+    #[inline]
+    pub fn label(&self, key: &str) -> Option<&str> {
+        self.labels.get(key).map(|s| &**s)
+    }
+
+    /// Returns the request's labels.
+    #[inline]
+    pub fn labels(&self) -> &BTreeMap<String, String> {
+        &self.labels
+    }
+
+    /// Returns the request's load shedding priority.
+    #[inline]
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+}
+
+/// The priority of a request when the server is shedding load.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Priority {
+    /// The request will be shed when memory usage exceeds the high watermark, and is throttled before others by
+    /// priority-aware rate limiters.
+    Low,
+    /// The request will not be shed due to memory usage.
+    #[default]
+    Normal,
+    /// The request will not be shed due to memory usage, and is never throttled by priority-aware rate limiters.
+    Critical,
+}
+
+/// An extension which signals that the server has begun to shut down.
+///
+/// It will be present in the extensions of every request. The server waits for in-flight requests to complete during
+/// its graceful shutdown, so handlers of long-lived requests like streaming responses should watch for this signal
+/// and finish up promptly, for example by writing a terminal message, rather than being cut off when the shutdown
+/// timeout elapses.
+///
+/// It can also be obtained outside of a request via [`Witchcraft::shutdown_signal`].
+///
+/// [`Witchcraft::shutdown_signal`]: crate::Witchcraft::shutdown_signal
+#[derive(Clone, Default)]
+pub struct ShutdownSignal(CancellationToken);
+
+impl ShutdownSignal {
+    pub(crate) fn new() -> Self {
+        ShutdownSignal::default()
+    }
+
+    pub(crate) fn trigger(&self) {
+        self.0.cancel();
+    }
+
+    /// Returns `true` if the server has begun to shut down.
+    #[inline]
+    pub fn is_shutting_down(&self) -> bool {
+        self.0.is_cancelled()
+    }
+
+    /// Waits until the server begins to shut down.
+    pub async fn wait(&self) {
+        self.0.cancelled().await
+    }
+}
+
+/// An extension containing the deadline of a request.
+///
+/// It will be present in the extensions of requests which include an `X-Request-Timeout` header, containing the number
+/// of milliseconds the client is willing to wait for a response. Requests to blocking endpoints which are still queued
+/// for a worker thread when the deadline passes will fail with a `503 Service Unavailable` response.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RequestDeadline(pub Instant);
+
+impl Deref for RequestDeadline {
+    type Target = Instant;
+
+    #[inline]
+    fn deref(&self) -> &Instant {
+        &self.0
+    }
+}
+
+/// An extension containing the externally visible base URL of the server, for handlers which generate links or
+/// redirects.
+///
+/// It will be present in the extensions of every request whose host is known. If the install configuration has a
+/// `canonical-url`, it is used directly. Otherwise, the scheme and host are taken from the request's `Forwarded`,
+/// `X-Forwarded-Proto` and `X-Forwarded-Host` headers, falling back to its `Host` header and `https`. In both cases,
+/// the server's context path is appended.
+///
+/// Forwarded headers are supplied by the client or an intermediate proxy and are not authenticated, so servers
+/// exposed directly to untrusted clients should configure a `canonical-url`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BaseUrl(pub(crate) String);
+
+impl BaseUrl {
+    /// Returns the base URL as a string, without a trailing `/`.
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns the absolute URL of a path relative to the base URL.
+    pub fn join(&self, path: &str) -> String {
+        format!("{}/{}", self.0, path.trim_start_matches('/'))
+    }
+}
+
+impl fmt::Display for BaseUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
 /// An extension containing an audit log entry for a request.
 ///
 /// If this is present in the response extensions of a request, it will be written to the audit log before the server
@@ -48,3 +253,35 @@ impl AuditLogEntry {
         AuditLogEntry(entry)
     }
 }
+
+/// An extension declaring the names of the trailers which will be sent after a response's body.
+///
+/// HTTP/1.1 clients must be told which trailers to expect before the body begins, so endpoints which send trailers via
+/// [`ResponseWriter::send_trailers`] should insert this into their response extensions. The server uses it to set the
+/// response's `Trailer` header. Trailers are sent over HTTP/2, and over HTTP/1.1 when the response body is chunked
+/// rather than having a known length. They are discarded otherwise.
+///
+/// [`ResponseWriter::send_trailers`]: crate::ResponseWriter::send_trailers
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeclaredTrailers(Vec<HeaderName>);
+
+impl DeclaredTrailers {
+    /// Creates a new `DeclaredTrailers` with no trailers.
+    #[inline]
+    pub fn new() -> Self {
+        DeclaredTrailers::default()
+    }
+
+    /// Adds a trailer to the declaration.
+    #[inline]
+    pub fn with_trailer(mut self, name: HeaderName) -> Self {
+        self.0.push(name);
+        self
+    }
+
+    /// Returns the names of the declared trailers.
+    #[inline]
+    pub fn names(&self) -> &[HeaderName] {
+        &self.0
+    }
+}
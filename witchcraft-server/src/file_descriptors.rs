@@ -0,0 +1,137 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::metrics;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time;
+use witchcraft_log::{info, warn};
+use witchcraft_metrics::MetricRegistry;
+use witchcraft_server_config::install::InstallConfig;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Tracks the process's file descriptor usage to determine when the server should stop accepting new connections.
+pub struct FileDescriptorMonitor {
+    max_utilization: f64,
+    exhausted: AtomicBool,
+    available: Notify,
+}
+
+impl FileDescriptorMonitor {
+    pub fn new(config: &InstallConfig, metrics: &MetricRegistry) -> Arc<Self> {
+        let monitor = Arc::new(FileDescriptorMonitor {
+            max_utilization: config.server().max_file_descriptor_utilization(),
+            exhausted: AtomicBool::new(false),
+            available: Notify::new(),
+        });
+
+        metrics.gauge("server.connection.fd-exhausted", {
+            let monitor = Arc::downgrade(&monitor);
+            move || monitor.upgrade().is_some_and(|m| m.exhausted()) as i64
+        });
+
+        monitor
+    }
+
+    /// Periodically samples the process's file descriptor usage until the monitor is dropped.
+    pub async fn run(monitor: Weak<FileDescriptorMonitor>) {
+        let mut interval = time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            let Some(monitor) = monitor.upgrade() else {
+                break;
+            };
+
+            monitor.update(metrics::file_descriptor_utilization());
+        }
+    }
+
+    fn update(&self, utilization: Option<f32>) {
+        let exhausted = utilization.is_some_and(|u| f64::from(u) >= self.max_utilization);
+        if exhausted == self.exhausted.swap(exhausted, Ordering::Relaxed) {
+            return;
+        }
+
+        if exhausted {
+            warn!(
+                "file descriptor usage exceeded limit, no longer accepting connections",
+                safe: {
+                    utilization: utilization,
+                    maxUtilization: self.max_utilization,
+                },
+            );
+        } else {
+            info!("file descriptor usage fell below limit, accepting connections");
+            self.available.notify_waiters();
+        }
+    }
+
+    /// Returns `true` if file descriptor usage is above the configured limit.
+    pub fn exhausted(&self) -> bool {
+        self.exhausted.load(Ordering::Relaxed)
+    }
+
+    /// Waits until file descriptor usage is below the configured limit.
+    pub async fn wait_available(&self) {
+        loop {
+            // Register for the notification before checking to avoid missing a wakeup in between.
+            let available = self.available.notified();
+            if !self.exhausted() {
+                return;
+            }
+            available.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::FutureExt;
+
+    fn monitor() -> Arc<FileDescriptorMonitor> {
+        let config = InstallConfig::builder()
+            .product_name("foo")
+            .product_version("1.0.0")
+            .port(0)
+            .build()
+            .unwrap();
+        FileDescriptorMonitor::new(&config, &MetricRegistry::new())
+    }
+
+    #[tokio::test]
+    async fn wait_for_availability() {
+        let monitor = monitor();
+        monitor.wait_available().await;
+
+        monitor.update(Some(0.99));
+        assert!(monitor.exhausted());
+        let mut wait = Box::pin(monitor.wait_available());
+        assert!((&mut wait).now_or_never().is_none());
+
+        monitor.update(Some(0.5));
+        assert!(!monitor.exhausted());
+        wait.await;
+    }
+
+    #[test]
+    fn unknown_utilization() {
+        let monitor = monitor();
+        monitor.update(Some(0.99));
+        monitor.update(None);
+        assert!(!monitor.exhausted());
+    }
+}
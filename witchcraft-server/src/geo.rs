@@ -0,0 +1,124 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Geographic enrichment of requests.
+//!
+//! A [`GeoLookup`] installed via [`Witchcraft::geo_lookup`] is invoked with the IP address of the client of each
+//! request to the service port. Its result is inserted into the request's extensions as a [`GeoInfo`], recorded in the
+//! request log, and used to tag the `server.request.country` metric.
+//!
+//! With the `maxminddb` Cargo feature enabled, [`MaxMindGeoLookup`] provides an implementation backed by MaxMind
+//! GeoIP2/GeoLite2 databases.
+//!
+//! [`Witchcraft::geo_lookup`]: crate::Witchcraft::geo_lookup
+pub use crate::extensions::GeoInfo;
+#[cfg(feature = "maxminddb")]
+use conjure_error::Error;
+#[cfg(feature = "maxminddb")]
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+use std::net::IpAddr;
+#[cfg(feature = "maxminddb")]
+use std::path::Path;
+#[cfg(feature = "maxminddb")]
+use witchcraft_log::debug;
+
+/// A source of geographic information about client IP addresses.
+pub trait GeoLookup {
+    /// Returns information about the specified address.
+    ///
+    /// This is called synchronously for every request, so implementations should not block.
+    fn lookup(&self, addr: IpAddr) -> GeoInfo;
+}
+
+/// A [`GeoLookup`] backed by MaxMind databases.
+#[cfg(feature = "maxminddb")]
+#[derive(Default)]
+pub struct MaxMindGeoLookup {
+    country: Option<Reader<Vec<u8>>>,
+    asn: Option<Reader<Vec<u8>>>,
+}
+
+#[cfg(feature = "maxminddb")]
+impl MaxMindGeoLookup {
+    /// Creates a new lookup with no databases.
+    pub fn new() -> Self {
+        MaxMindGeoLookup::default()
+    }
+
+    /// Loads a country database (e.g. `GeoLite2-Country.mmdb`) used to determine the client's country.
+    ///
+    /// City databases are also supported.
+    pub fn with_country_database<P>(mut self, path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        self.country = Some(open(path.as_ref())?);
+        Ok(self)
+    }
+
+    /// Loads an ASN database (e.g. `GeoLite2-ASN.mmdb`) used to determine the client's autonomous system number.
+    pub fn with_asn_database<P>(mut self, path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        self.asn = Some(open(path.as_ref())?);
+        Ok(self)
+    }
+}
+
+#[cfg(feature = "maxminddb")]
+fn open(path: &Path) -> Result<Reader<Vec<u8>>, Error> {
+    Reader::open_readfile(path).map_err(|e| {
+        Error::internal_safe(e).with_safe_param("path", path.to_string_lossy().into_owned())
+    })
+}
+
+#[cfg(feature = "maxminddb")]
+impl GeoLookup for MaxMindGeoLookup {
+    fn lookup(&self, addr: IpAddr) -> GeoInfo {
+        let mut info = GeoInfo::new();
+
+        if let Some(reader) = &self.country {
+            let country = reader
+                .lookup::<geoip2::Country<'_>>(addr)
+                .map(|c| c.country.and_then(|c| c.iso_code));
+            if let Some(country) = ok_or_log(country).flatten() {
+                info = info.with_country(country);
+            }
+        }
+
+        if let Some(reader) = &self.asn {
+            let asn = reader
+                .lookup::<geoip2::Asn<'_>>(addr)
+                .map(|a| a.autonomous_system_number);
+            if let Some(asn) = ok_or_log(asn).flatten() {
+                info = info.with_asn(asn);
+            }
+        }
+
+        info
+    }
+}
+
+#[cfg(feature = "maxminddb")]
+fn ok_or_log<T>(result: Result<T, MaxMindDBError>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        // Addresses in private or unallocated ranges aren't present in the databases.
+        Err(MaxMindDBError::AddressNotFoundError(_)) => None,
+        Err(e) => {
+            debug!("error looking up client address", error: Error::internal_safe(e));
+            None
+        }
+    }
+}
@@ -0,0 +1,835 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed request header extraction.
+//!
+//! The [`TypedHeader`] trait parses and validates a header into a Rust type. Headers can be extracted from a
+//! [`HeaderMap`] with the [`HeaderMapExt`] extension trait from within layers or handlers with access to a
+//! [`RequestContext`](conjure_http::server::RequestContext), or as a Conjure endpoint argument with the
+//! [`TypedHeaderDecoder`].
+//!
+//! Malformed headers produce a consistent `Default:InvalidArgument` error with a `header` safe parameter containing
+//! the header's name. The header's raw value is additionally included as a `value` parameter, which is only marked
+//! safe if the header's [`TypedHeader::SAFE`] flag is set.
+//!
+//! # Examples
+//!
+//! ```
+//! use http::HeaderName;
+//! use http::HeaderValue;
+//! use witchcraft_server::headers::{self, InvalidHeader, TypedHeader};
+//!
+//! struct TenantId(String);
+//!
+//! impl TypedHeader for TenantId {
+//!     const NAME: HeaderName = HeaderName::from_static("x-tenant-id");
+//!
+//!     const SAFE: bool = true;
+//!
+//!     fn decode<'a, I>(values: I) -> Result<Self, InvalidHeader>
+//!     where
+//!         I: IntoIterator<Item = &'a HeaderValue>,
+//!     {
+//!         let value = headers::single_value(values)?;
+//!         if value.is_empty() || !value.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+//!             return Err(InvalidHeader::new("invalid tenant ID"));
+//!         }
+//!
+//!         Ok(TenantId(value.to_string()))
+//!     }
+//! }
+//! ```
+use conjure_error::{Error, InvalidArgument};
+use conjure_http::server::{ConjureRuntime, DecodeHeader};
+use http::header::{ACCEPT, IF_MATCH, IF_NONE_MATCH, IF_RANGE, RANGE};
+use http::{HeaderMap, HeaderName, HeaderValue};
+use std::{error, fmt, ops};
+
+/// A header which can be parsed from a request.
+pub trait TypedHeader: Sized {
+    /// The name of the header.
+    const NAME: HeaderName;
+
+    /// Whether the header's raw values are safe to log.
+    ///
+    /// Defaults to `false`.
+    const SAFE: bool = false;
+
+    /// Decodes the header from its values.
+    ///
+    /// This is only called if at least one value is present in the request. The message of a returned error must be
+    /// safe to log.
+    fn decode<'a, I>(values: I) -> Result<Self, InvalidHeader>
+    where
+        I: IntoIterator<Item = &'a HeaderValue>;
+}
+
+/// An error decoding a [`TypedHeader`].
+#[derive(Debug)]
+pub struct InvalidHeader {
+    message: String,
+}
+
+impl InvalidHeader {
+    /// Creates a new error with a safe-to-log message.
+    pub fn new(message: impl Into<String>) -> Self {
+        InvalidHeader {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for InvalidHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.message, f)
+    }
+}
+
+impl error::Error for InvalidHeader {}
+
+/// Returns the value of a header which must appear exactly once, as a string.
+pub fn single_value<'a, I>(values: I) -> Result<&'a str, InvalidHeader>
+where
+    I: IntoIterator<Item = &'a HeaderValue>,
+{
+    let mut values = values.into_iter();
+    let value = values
+        .next()
+        .ok_or_else(|| InvalidHeader::new("expected a value"))?;
+    if values.next().is_some() {
+        return Err(InvalidHeader::new("expected a single value"));
+    }
+
+    value
+        .to_str()
+        .map_err(|_| InvalidHeader::new("value is not visible ASCII"))
+}
+
+/// Returns the elements of a comma-separated list header, which may be split across multiple values.
+///
+/// Empty elements are skipped, as required by RFC 9110.
+pub fn list_values<'a, I>(values: I) -> impl Iterator<Item = Result<&'a str, InvalidHeader>>
+where
+    I: IntoIterator<Item = &'a HeaderValue>,
+{
+    values
+        .into_iter()
+        .map(|v| {
+            v.to_str()
+                .map_err(|_| InvalidHeader::new("value is not visible ASCII"))
+        })
+        .flat_map(|r| {
+            let (elements, error) = match r {
+                Ok(v) => (Some(v.split(',')), None),
+                Err(e) => (None, Some(Err(e))),
+            };
+            elements
+                .into_iter()
+                .flatten()
+                .map(str::trim)
+                .filter(|e| !e.is_empty())
+                .map(Ok)
+                .chain(error)
+        })
+}
+
+fn decode<H>(values: &[&HeaderValue]) -> Result<H, Error>
+where
+    H: TypedHeader,
+{
+    H::decode(values.iter().copied()).map_err(|e| {
+        let value = values
+            .iter()
+            .map(|v| String::from_utf8_lossy(v.as_bytes()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let error = Error::service_safe(e, InvalidArgument::new())
+            .with_safe_param("header", H::NAME.as_str());
+        if H::SAFE {
+            error.with_safe_param("value", value)
+        } else {
+            error.with_unsafe_param("value", value)
+        }
+    })
+}
+
+fn decode_optional<'a, H, I>(values: I) -> Result<Option<H>, Error>
+where
+    H: TypedHeader,
+    I: IntoIterator<Item = &'a HeaderValue>,
+{
+    let values = values.into_iter().collect::<Vec<_>>();
+    if values.is_empty() {
+        return Ok(None);
+    }
+
+    decode(&values).map(Some)
+}
+
+fn missing<H>() -> Error
+where
+    H: TypedHeader,
+{
+    Error::service_safe("required header is missing", InvalidArgument::new())
+        .with_safe_param("header", H::NAME.as_str())
+}
+
+/// An extension trait adding typed header extraction to [`HeaderMap`].
+pub trait HeaderMapExt {
+    /// Decodes a header, returning `None` if it is not present.
+    fn typed_get<H>(&self) -> Result<Option<H>, Error>
+    where
+        H: TypedHeader;
+
+    /// Decodes a header, returning an error if it is not present.
+    fn typed_require<H>(&self) -> Result<H, Error>
+    where
+        H: TypedHeader;
+}
+
+impl HeaderMapExt for HeaderMap {
+    fn typed_get<H>(&self) -> Result<Option<H>, Error>
+    where
+        H: TypedHeader,
+    {
+        decode_optional(self.get_all(H::NAME))
+    }
+
+    fn typed_require<H>(&self) -> Result<H, Error>
+    where
+        H: TypedHeader,
+    {
+        self.typed_get()?.ok_or_else(missing::<H>)
+    }
+}
+
+/// A Conjure header decoder for [`TypedHeader`]s.
+///
+/// It supports both required and optional arguments. The name in the `#[header]` attribute should match the
+/// header's [`TypedHeader::NAME`].
+pub enum TypedHeaderDecoder {}
+
+impl<H> DecodeHeader<H> for TypedHeaderDecoder
+where
+    H: TypedHeader,
+{
+    fn decode<'a, I>(_: &ConjureRuntime, headers: I) -> Result<H, Error>
+    where
+        I: IntoIterator<Item = &'a HeaderValue>,
+    {
+        decode_optional(headers)?.ok_or_else(missing::<H>)
+    }
+}
+
+impl<H> DecodeHeader<Option<H>> for TypedHeaderDecoder
+where
+    H: TypedHeader,
+{
+    fn decode<'a, I>(_: &ConjureRuntime, headers: I) -> Result<Option<H>, Error>
+    where
+        I: IntoIterator<Item = &'a HeaderValue>,
+    {
+        decode_optional(headers)
+    }
+}
+
+/// An HTTP entity tag.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EntityTag {
+    weak: bool,
+    tag: String,
+}
+
+impl EntityTag {
+    /// Creates a new entity tag from its opaque value, not including the surrounding quotes.
+    ///
+    /// Returns `None` if the value contains characters not permitted in an entity tag.
+    pub fn new(weak: bool, tag: &str) -> Option<Self> {
+        if !tag.bytes().all(|b| b == 0x21 || (0x23..=0x7e).contains(&b)) {
+            return None;
+        }
+
+        Some(EntityTag {
+            weak,
+            tag: tag.to_string(),
+        })
+    }
+
+    /// Parses an entity tag in its header form, for example `"abc"` or `W/"abc"`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (weak, s) = match s.strip_prefix("W/") {
+            Some(s) => (true, s),
+            None => (false, s),
+        };
+        let tag = s.strip_prefix('"')?.strip_suffix('"')?;
+        EntityTag::new(weak, tag)
+    }
+
+    /// Returns `true` if this is a weak entity tag.
+    #[inline]
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// Returns the opaque value of the tag, not including the surrounding quotes.
+    #[inline]
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Compares two tags with the strong comparison function, which requires both to be strong.
+    pub fn strong_eq(&self, other: &EntityTag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// Compares two tags with the weak comparison function, which ignores their weakness.
+    pub fn weak_eq(&self, other: &EntityTag) -> bool {
+        self.tag == other.tag
+    }
+}
+
+impl fmt::Display for EntityTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.weak {
+            f.write_str("W/")?;
+        }
+        write!(f, "\"{}\"", self.tag)
+    }
+}
+
+fn decode_entity_tags<'a, I>(values: I) -> Result<Option<Vec<EntityTag>>, InvalidHeader>
+where
+    I: IntoIterator<Item = &'a HeaderValue>,
+{
+    let elements = list_values(values).collect::<Result<Vec<_>, _>>()?;
+    if elements == ["*"] {
+        return Ok(None);
+    }
+
+    let tags = elements
+        .into_iter()
+        .map(|e| EntityTag::parse(e).ok_or_else(|| InvalidHeader::new("invalid entity tag")))
+        .collect::<Result<Vec<_>, _>>()?;
+    if tags.is_empty() {
+        return Err(InvalidHeader::new("expected at least one entity tag"));
+    }
+
+    Ok(Some(tags))
+}
+
+/// The `If-Match` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IfMatch {
+    /// Matches any current representation.
+    Any,
+    /// Matches a current representation with one of the entity tags.
+    Tags(Vec<EntityTag>),
+}
+
+impl IfMatch {
+    /// Determines if the header matches the entity tag of the current representation, using the strong comparison
+    /// function.
+    pub fn matches(&self, etag: &EntityTag) -> bool {
+        match self {
+            IfMatch::Any => true,
+            IfMatch::Tags(tags) => tags.iter().any(|t| t.strong_eq(etag)),
+        }
+    }
+}
+
+impl TypedHeader for IfMatch {
+    const NAME: HeaderName = IF_MATCH;
+
+    const SAFE: bool = true;
+
+    fn decode<'a, I>(values: I) -> Result<Self, InvalidHeader>
+    where
+        I: IntoIterator<Item = &'a HeaderValue>,
+    {
+        Ok(decode_entity_tags(values)?.map_or(IfMatch::Any, IfMatch::Tags))
+    }
+}
+
+/// The `If-None-Match` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IfNoneMatch {
+    /// Matches any current representation.
+    Any,
+    /// Matches a current representation with one of the entity tags.
+    Tags(Vec<EntityTag>),
+}
+
+impl IfNoneMatch {
+    /// Determines if the header matches the entity tag of the current representation, using the weak comparison
+    /// function.
+    pub fn matches(&self, etag: &EntityTag) -> bool {
+        match self {
+            IfNoneMatch::Any => true,
+            IfNoneMatch::Tags(tags) => tags.iter().any(|t| t.weak_eq(etag)),
+        }
+    }
+}
+
+impl TypedHeader for IfNoneMatch {
+    const NAME: HeaderName = IF_NONE_MATCH;
+
+    const SAFE: bool = true;
+
+    fn decode<'a, I>(values: I) -> Result<Self, InvalidHeader>
+    where
+        I: IntoIterator<Item = &'a HeaderValue>,
+    {
+        Ok(decode_entity_tags(values)?.map_or(IfNoneMatch::Any, IfNoneMatch::Tags))
+    }
+}
+
+/// The `If-Range` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IfRange {
+    /// An entity tag validator.
+    EntityTag(EntityTag),
+    /// A last-modified date validator, in its raw HTTP-date form.
+    Date(String),
+}
+
+impl IfRange {
+    /// Determines if the header matches the entity tag of the current representation, using the strong comparison
+    /// function.
+    ///
+    /// Date validators never match.
+    pub fn matches(&self, etag: &EntityTag) -> bool {
+        match self {
+            IfRange::EntityTag(tag) => tag.strong_eq(etag),
+            IfRange::Date(_) => false,
+        }
+    }
+}
+
+impl TypedHeader for IfRange {
+    const NAME: HeaderName = IF_RANGE;
+
+    const SAFE: bool = true;
+
+    fn decode<'a, I>(values: I) -> Result<Self, InvalidHeader>
+    where
+        I: IntoIterator<Item = &'a HeaderValue>,
+    {
+        let value = single_value(values)?;
+        if value.ends_with('"') {
+            EntityTag::parse(value)
+                .map(IfRange::EntityTag)
+                .ok_or_else(|| InvalidHeader::new("invalid entity tag"))
+        } else {
+            Ok(IfRange::Date(value.to_string()))
+        }
+    }
+}
+
+/// A single range of a `Range` header.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ByteRange {
+    /// The bytes from the first offset to the second, inclusive.
+    FromTo(u64, u64),
+    /// The bytes from the offset to the end of the representation.
+    AllFrom(u64),
+    /// The specified number of bytes at the end of the representation.
+    Last(u64),
+}
+
+impl ByteRange {
+    /// Resolves the range against a representation of the specified length.
+    ///
+    /// Returns a half-open range of offsets, or `None` if the range is not satisfiable.
+    pub fn resolve(&self, len: u64) -> Option<ops::Range<u64>> {
+        let range = match *self {
+            ByteRange::FromTo(start, end) => start..u64::min(end.saturating_add(1), len),
+            ByteRange::AllFrom(start) => start..len,
+            ByteRange::Last(n) => len.saturating_sub(n)..len,
+        };
+
+        if range.is_empty() {
+            None
+        } else {
+            Some(range)
+        }
+    }
+}
+
+/// The `Range` header.
+///
+/// Only the `bytes` range unit is supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Range {
+    ranges: Vec<ByteRange>,
+}
+
+impl Range {
+    /// The maximum number of ranges accepted in a single header.
+    pub const MAX_RANGES: usize = 32;
+
+    /// Returns the requested ranges, in the order they were requested.
+    #[inline]
+    pub fn ranges(&self) -> &[ByteRange] {
+        &self.ranges
+    }
+
+    /// Resolves the satisfiable ranges against a representation of the specified length.
+    pub fn resolve(&self, len: u64) -> Vec<ops::Range<u64>> {
+        self.ranges.iter().filter_map(|r| r.resolve(len)).collect()
+    }
+}
+
+impl TypedHeader for Range {
+    const NAME: HeaderName = RANGE;
+
+    const SAFE: bool = true;
+
+    fn decode<'a, I>(values: I) -> Result<Self, InvalidHeader>
+    where
+        I: IntoIterator<Item = &'a HeaderValue>,
+    {
+        let value = single_value(values)?;
+        let ranges = value
+            .strip_prefix("bytes=")
+            .ok_or_else(|| InvalidHeader::new("unsupported range unit"))?;
+
+        let ranges = ranges
+            .split(',')
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+            .map(|r| parse_byte_range(r).ok_or_else(|| InvalidHeader::new("invalid byte range")))
+            .collect::<Result<Vec<_>, _>>()?;
+        if ranges.is_empty() {
+            return Err(InvalidHeader::new("expected at least one byte range"));
+        }
+        if ranges.len() > Range::MAX_RANGES {
+            return Err(InvalidHeader::new("too many byte ranges"));
+        }
+
+        Ok(Range { ranges })
+    }
+}
+
+fn parse_byte_range(s: &str) -> Option<ByteRange> {
+    let (start, end) = s.split_once('-')?;
+    let parse = |s: &str| {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        s.parse::<u64>().ok()
+    };
+
+    let range = match (start, end) {
+        ("", end) => ByteRange::Last(parse(end)?),
+        (start, "") => ByteRange::AllFrom(parse(start)?),
+        (start, end) => {
+            let start = parse(start)?;
+            let end = parse(end)?;
+            if end < start {
+                return None;
+            }
+            ByteRange::FromTo(start, end)
+        }
+    };
+
+    Some(range)
+}
+
+/// A media range of an `Accept` header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaRange {
+    type_: String,
+    subtype: String,
+    quality: f32,
+}
+
+impl MediaRange {
+    /// Returns the media type, or `*` for any type.
+    #[inline]
+    pub fn type_(&self) -> &str {
+        &self.type_
+    }
+
+    /// Returns the media subtype, or `*` for any subtype.
+    #[inline]
+    pub fn subtype(&self) -> &str {
+        &self.subtype
+    }
+
+    /// Returns the quality value of the range, between 0 and 1.
+    #[inline]
+    pub fn quality(&self) -> f32 {
+        self.quality
+    }
+
+    // Returns the specificity of the range's match of the type and subtype, if it matches.
+    fn specificity(&self, type_: &str, subtype: &str) -> Option<u8> {
+        if self.type_ == "*" {
+            return Some(0);
+        }
+        if !self.type_.eq_ignore_ascii_case(type_) {
+            return None;
+        }
+        if self.subtype == "*" {
+            return Some(1);
+        }
+        if !self.subtype.eq_ignore_ascii_case(subtype) {
+            return None;
+        }
+        Some(2)
+    }
+}
+
+/// The `Accept` header.
+///
+/// Media type parameters other than the quality value are ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accept {
+    media_ranges: Vec<MediaRange>,
+}
+
+impl Accept {
+    /// Returns the media ranges, in the order they were listed.
+    #[inline]
+    pub fn media_ranges(&self) -> &[MediaRange] {
+        &self.media_ranges
+    }
+
+    /// Returns the quality value of a media type, using the most specific matching media range.
+    ///
+    /// Returns 0 if the type is not acceptable.
+    pub fn quality(&self, media_type: &str) -> f32 {
+        let (type_, subtype) = media_type.split_once('/').unwrap_or((media_type, ""));
+        self.media_ranges
+            .iter()
+            .filter_map(|r| r.specificity(type_, subtype).map(|s| (s, r.quality)))
+            .max_by_key(|(s, _)| *s)
+            .map_or(0., |(_, q)| q)
+    }
+
+    /// Selects the offered media type with the highest quality value, preferring earlier offers in a tie.
+    ///
+    /// Returns `None` if none of the offered types are acceptable.
+    pub fn preferred<'a>(&self, offered: &[&'a str]) -> Option<&'a str> {
+        let mut best = None;
+        for &media_type in offered {
+            let quality = self.quality(media_type);
+            if quality > 0. && best.map_or(true, |(_, q)| quality > q) {
+                best = Some((media_type, quality));
+            }
+        }
+
+        best.map(|(t, _)| t)
+    }
+}
+
+impl TypedHeader for Accept {
+    const NAME: HeaderName = ACCEPT;
+
+    const SAFE: bool = true;
+
+    fn decode<'a, I>(values: I) -> Result<Self, InvalidHeader>
+    where
+        I: IntoIterator<Item = &'a HeaderValue>,
+    {
+        let media_ranges = list_values(values)
+            .map(|r| {
+                r.and_then(|e| {
+                    parse_media_range(e).ok_or_else(|| InvalidHeader::new("invalid media range"))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Accept { media_ranges })
+    }
+}
+
+fn parse_media_range(s: &str) -> Option<MediaRange> {
+    let mut parts = s.split(';');
+    let (type_, subtype) = parts.next()?.trim().split_once('/')?;
+    if !is_token(type_) || !is_token(subtype) || (type_ == "*" && subtype != "*") {
+        return None;
+    }
+
+    let mut quality = 1.;
+    for param in parts {
+        let (name, value) = param.trim().split_once('=')?;
+        if name.trim_end().eq_ignore_ascii_case("q") {
+            quality = parse_quality(value.trim_start())?;
+        }
+    }
+
+    Some(MediaRange {
+        type_: type_.to_string(),
+        subtype: subtype.to_string(),
+        quality,
+    })
+}
+
+fn parse_quality(s: &str) -> Option<f32> {
+    let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+    if frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    match int {
+        "0" => format!("0.{frac}").parse().ok(),
+        "1" if frac.bytes().all(|b| b == b'0') => Some(1.),
+        _ => None,
+    }
+}
+
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use conjure_error::ErrorKind;
+    use conjure_object::Any;
+
+    fn headers(name: HeaderName, values: &[&'static str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(name.clone(), HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn if_match() {
+        let if_match = headers(IF_MATCH, &[r#""a", W/"b""#, r#""c""#])
+            .typed_get::<IfMatch>()
+            .unwrap()
+            .unwrap();
+        assert!(if_match.matches(&EntityTag::parse(r#""a""#).unwrap()));
+        assert!(!if_match.matches(&EntityTag::parse(r#""b""#).unwrap()));
+        assert!(if_match.matches(&EntityTag::parse(r#""c""#).unwrap()));
+
+        let if_match = headers(IF_MATCH, &["*"]).typed_get::<IfMatch>().unwrap();
+        assert_eq!(if_match, Some(IfMatch::Any));
+
+        let if_none_match = headers(IF_NONE_MATCH, &[r#"W/"b""#])
+            .typed_require::<IfNoneMatch>()
+            .unwrap();
+        assert!(if_none_match.matches(&EntityTag::parse(r#""b""#).unwrap()));
+    }
+
+    #[test]
+    fn range() {
+        let range = headers(RANGE, &["bytes=0-9, 20-, -5"])
+            .typed_require::<Range>()
+            .unwrap();
+        assert_eq!(
+            range.ranges(),
+            [
+                ByteRange::FromTo(0, 9),
+                ByteRange::AllFrom(20),
+                ByteRange::Last(5)
+            ]
+        );
+        assert_eq!(range.resolve(15), [0..10, 10..15]);
+        assert_eq!(range.resolve(100), [0..10, 20..100, 95..100]);
+
+        for invalid in ["items=0-9", "bytes=9-0", "bytes=-", "bytes=a-b", "bytes="] {
+            headers(RANGE, &[invalid]).typed_get::<Range>().unwrap_err();
+        }
+    }
+
+    #[test]
+    fn accept() {
+        let accept = headers(
+            ACCEPT,
+            &["text/*;q=0.5, application/json", "*/*;q=0.1, text/html;q=0"],
+        )
+        .typed_require::<Accept>()
+        .unwrap();
+        assert_eq!(accept.quality("application/json"), 1.);
+        assert_eq!(accept.quality("text/plain"), 0.5);
+        assert_eq!(accept.quality("text/html"), 0.);
+        assert_eq!(accept.quality("image/png"), 0.1);
+        assert_eq!(
+            accept.preferred(&["text/html", "text/plain", "application/json"]),
+            Some("application/json")
+        );
+        assert_eq!(accept.preferred(&["text/html"]), None);
+
+        headers(ACCEPT, &["text/plain;q=2"])
+            .typed_get::<Accept>()
+            .unwrap_err();
+    }
+
+    struct TenantId(String);
+
+    impl TypedHeader for TenantId {
+        const NAME: HeaderName = HeaderName::from_static("x-tenant-id");
+
+        fn decode<'a, I>(values: I) -> Result<Self, InvalidHeader>
+        where
+            I: IntoIterator<Item = &'a HeaderValue>,
+        {
+            let value = single_value(values)?;
+            if value.is_empty() {
+                return Err(InvalidHeader::new("empty tenant ID"));
+            }
+            Ok(TenantId(value.to_string()))
+        }
+    }
+
+    #[test]
+    fn custom() {
+        let tenant = headers(TenantId::NAME, &["foo"])
+            .typed_require::<TenantId>()
+            .unwrap();
+        assert_eq!(tenant.0, "foo");
+
+        assert!(HeaderMap::new().typed_get::<TenantId>().unwrap().is_none());
+
+        let error = HeaderMap::new().typed_require::<TenantId>().err().unwrap();
+        assert_eq!(
+            error.safe_params()["header"],
+            Any::new("x-tenant-id").unwrap()
+        );
+    }
+
+    #[test]
+    fn unified_errors() {
+        let error = headers(TenantId::NAME, &["foo", "bar"])
+            .typed_get::<TenantId>()
+            .err()
+            .unwrap();
+        let ErrorKind::Service(service) = error.kind() else {
+            panic!("expected a service error");
+        };
+        assert_eq!(service.error_name(), "Default:InvalidArgument");
+        assert_eq!(
+            error.safe_params()["header"],
+            Any::new("x-tenant-id").unwrap()
+        );
+        assert_eq!(
+            error.unsafe_params()["value"],
+            Any::new("foo, bar").unwrap()
+        );
+
+        let error = headers(RANGE, &["bytes=a-b"])
+            .typed_get::<Range>()
+            .err()
+            .unwrap();
+        assert_eq!(error.safe_params()["header"], Any::new("range").unwrap());
+        assert_eq!(error.safe_params()["value"], Any::new("bytes=a-b").unwrap());
+    }
+}
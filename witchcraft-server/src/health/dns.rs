@@ -0,0 +1,48 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::dns::{DnsHealth, DnsProbe};
+use crate::health::{HealthCheck, HealthCheckResult, HealthState};
+use std::sync::Arc;
+
+/// A health check which reports a warning state if hosts used by the server's clients can't be resolved.
+pub struct DnsHealthCheck {
+    probe: Arc<DnsProbe>,
+}
+
+impl DnsHealthCheck {
+    pub fn new(probe: &Arc<DnsProbe>) -> Self {
+        DnsHealthCheck {
+            probe: probe.clone(),
+        }
+    }
+}
+
+impl HealthCheck for DnsHealthCheck {
+    fn type_(&self) -> &str {
+        "DNS_RESOLUTION"
+    }
+
+    fn result(&self) -> HealthCheckResult {
+        match self.probe.health() {
+            DnsHealth::Healthy => HealthCheckResult::builder()
+                .state(HealthState::Healthy)
+                .build(),
+            DnsHealth::Failing(hosts) => HealthCheckResult::builder()
+                .state(HealthState::Warning)
+                .message("DNS lookups are failing for remote hosts".to_string())
+                .insert_params("hosts", hosts)
+                .build(),
+        }
+    }
+}
@@ -0,0 +1,66 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::health::{HealthCheck, HealthCheckResult, HealthState};
+use crate::slo::SloRegistry;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// A health check which reports a warning state if any endpoint's observed p99 latency exceeds the latency declared
+/// for it.
+pub struct EndpointSlaHealthCheck {
+    slos: Arc<SloRegistry>,
+}
+
+impl EndpointSlaHealthCheck {
+    pub fn new(slos: &Arc<SloRegistry>) -> Self {
+        EndpointSlaHealthCheck { slos: slos.clone() }
+    }
+}
+
+impl HealthCheck for EndpointSlaHealthCheck {
+    fn type_(&self) -> &str {
+        "ENDPOINT_SLA"
+    }
+
+    fn result(&self) -> HealthCheckResult {
+        let violations = self
+            .slos
+            .endpoints()
+            .into_iter()
+            .filter_map(|endpoint| {
+                endpoint.sla_violation().map(|(p99, slow_fraction)| {
+                    (
+                        endpoint.key(),
+                        format!(
+                            "{:.1}% of requests exceeded the declared p99 latency of {p99:?}",
+                            slow_fraction * 100.
+                        ),
+                    )
+                })
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        if violations.is_empty() {
+            return HealthCheckResult::builder()
+                .state(HealthState::Healthy)
+                .build();
+        }
+
+        HealthCheckResult::builder()
+            .state(HealthState::Warning)
+            .message("Endpoints are exceeding their declared p99 latencies".to_string())
+            .insert_params("endpoints", violations)
+            .build()
+    }
+}
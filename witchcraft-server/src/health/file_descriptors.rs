@@ -0,0 +1,52 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::file_descriptors::FileDescriptorMonitor;
+use crate::health::{HealthCheck, HealthCheckResult, HealthState};
+use std::sync::Arc;
+
+/// A health check which reports a warning state if the server has stopped accepting connections due to file descriptor
+/// exhaustion.
+pub struct FileDescriptorsHealthCheck {
+    monitor: Arc<FileDescriptorMonitor>,
+}
+
+impl FileDescriptorsHealthCheck {
+    pub fn new(monitor: &Arc<FileDescriptorMonitor>) -> Self {
+        FileDescriptorsHealthCheck {
+            monitor: monitor.clone(),
+        }
+    }
+}
+
+impl HealthCheck for FileDescriptorsHealthCheck {
+    fn type_(&self) -> &str {
+        "FILE_DESCRIPTORS"
+    }
+
+    fn result(&self) -> HealthCheckResult {
+        if !self.monitor.exhausted() {
+            return HealthCheckResult::builder()
+                .state(HealthState::Healthy)
+                .build();
+        }
+
+        HealthCheckResult::builder()
+            .state(HealthState::Warning)
+            .message(
+                "File descriptor usage is above its limit, so new connections are not being accepted"
+                    .to_string(),
+            )
+            .build()
+    }
+}
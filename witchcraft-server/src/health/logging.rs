@@ -0,0 +1,125 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::health::{HealthCheck, HealthCheckResult, HealthState};
+use crate::logging::{self, QueueStats};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+// How long a queue must stay above its high watermark before the check reports it.
+const SUSTAINED_PERIOD: Duration = Duration::from_secs(30);
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct QueueParams {
+    queue_size: usize,
+    queue_limit: usize,
+    backlogged_seconds: u64,
+}
+
+/// A health check which reports a warning state if a log appender's queue has stayed close to full for a sustained
+/// period, before the appender begins to drop logs.
+pub struct LoggingHealthCheck;
+
+impl HealthCheck for LoggingHealthCheck {
+    fn type_(&self) -> &str {
+        "LOGGING"
+    }
+
+    fn result(&self) -> HealthCheckResult {
+        check(logging::queue_stats(), Instant::now())
+    }
+}
+
+fn check(stats: Vec<QueueStats>, now: Instant) -> HealthCheckResult {
+    let backlogged = stats
+        .into_iter()
+        .filter_map(|stats| {
+            let backlogged_for = now.saturating_duration_since(stats.backlogged_since?);
+            if backlogged_for < SUSTAINED_PERIOD {
+                return None;
+            }
+
+            let params = QueueParams {
+                queue_size: stats.len,
+                queue_limit: stats.limit,
+                backlogged_seconds: backlogged_for.as_secs(),
+            };
+            Some((stats.type_, params))
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    if backlogged.is_empty() {
+        return HealthCheckResult::builder()
+            .state(HealthState::Healthy)
+            .build();
+    }
+
+    HealthCheckResult::builder()
+        .state(HealthState::Warning)
+        .message(format!(
+            "Log appenders have been falling behind for more than {SUSTAINED_PERIOD:?}, and will begin dropping logs \
+             if their queues fill"
+        ))
+        .insert_params("queues", backlogged)
+        .build()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stats(backlogged_since: Option<Instant>) -> QueueStats {
+        QueueStats {
+            type_: "service.1",
+            len: 6_000,
+            limit: 10_000,
+            backlogged_since,
+        }
+    }
+
+    #[test]
+    fn healthy() {
+        let now = Instant::now();
+
+        let result = check(vec![stats(None)], now);
+        assert_eq!(*result.state(), HealthState::Healthy);
+
+        let result = check(vec![stats(Some(now - Duration::from_secs(5)))], now);
+        assert_eq!(*result.state(), HealthState::Healthy);
+    }
+
+    #[test]
+    fn sustained_backlog() {
+        let now = Instant::now();
+
+        let result = check(
+            vec![stats(None), stats(Some(now - Duration::from_secs(45)))],
+            now,
+        );
+        assert_eq!(*result.state(), HealthState::Warning);
+
+        let queues = serde_json::to_value(&result.params()["queues"]).unwrap();
+        assert_eq!(
+            queues,
+            serde_json::json!({
+                "service.1": {
+                    "queueSize": 6000,
+                    "queueLimit": 10000,
+                    "backloggedSeconds": 45,
+                },
+            }),
+        );
+    }
+}
@@ -25,11 +25,17 @@ use std::sync::Arc;
 #[rustfmt::skip]
 pub(crate) mod api;
 pub(crate) mod config_reload;
+pub(crate) mod dns;
 pub(crate) mod endpoint_500s;
+pub(crate) mod endpoint_sla;
+pub(crate) mod file_descriptors;
+pub(crate) mod logging;
 pub(crate) mod minidump;
+pub(crate) mod ocsp_stapling;
 pub(crate) mod panics;
 mod registry;
 pub(crate) mod service_dependency;
+pub(crate) mod slo;
 
 mod private {
     pub struct PrivacyToken;
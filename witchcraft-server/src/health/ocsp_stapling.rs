@@ -0,0 +1,67 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::health::{HealthCheck, HealthCheckResult, HealthState};
+use crate::ocsp::{OcspStapler, StaplingHealth};
+use std::sync::Arc;
+
+/// A health check which reports a warning state if the server's OCSP response can't be refreshed, and an error state
+/// once there is no valid response to staple or the certificate has been revoked.
+pub struct OcspStaplingHealthCheck {
+    stapler: Arc<OcspStapler>,
+}
+
+impl OcspStaplingHealthCheck {
+    pub fn new(stapler: &Arc<OcspStapler>) -> Self {
+        OcspStaplingHealthCheck {
+            stapler: stapler.clone(),
+        }
+    }
+}
+
+impl HealthCheck for OcspStaplingHealthCheck {
+    fn type_(&self) -> &str {
+        "OCSP_STAPLING"
+    }
+
+    fn result(&self) -> HealthCheckResult {
+        let (state, message) = match self.stapler.health() {
+            StaplingHealth::Healthy => {
+                return HealthCheckResult::builder()
+                    .state(HealthState::Healthy)
+                    .build()
+            }
+            StaplingHealth::RefreshFailing => (
+                HealthState::Warning,
+                "Unable to refresh the OCSP response for the server's certificate",
+            ),
+            StaplingHealth::Unknown => (
+                HealthState::Warning,
+                "The OCSP responder does not recognize the server's certificate",
+            ),
+            StaplingHealth::Unavailable => (
+                HealthState::Error,
+                "No valid OCSP response is available for the server's certificate",
+            ),
+            StaplingHealth::Revoked => (
+                HealthState::Error,
+                "The OCSP responder reports that the server's certificate has been revoked",
+            ),
+        };
+
+        HealthCheckResult::builder()
+            .state(state)
+            .message(message.to_string())
+            .build()
+    }
+}
@@ -15,11 +15,13 @@ use super::HealthState;
 use crate::health::api::{self, CheckType, HealthStatus};
 use crate::health::{HealthCheck, HealthCheckResult};
 use arc_swap::ArcSwap;
+use conjure_object::{DateTime, Utc};
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 use regex::Regex;
+use serde::Serialize;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::panic::{self, AssertUnwindSafe};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -30,11 +32,16 @@ use tokio::time;
 const CHECK_RUN_INTERVAL: Duration = Duration::from_secs(30);
 const STALENESS_THRESHOLD: Duration = Duration::from_secs(5 * 60);
 const HEALTH_CHECK_COMPUTATION_STALENESS_TYPE: &str = "HEALTH_CHECK_COMPUTATION_STALENESS";
+// The number of recent results retained for each check.
+const HISTORY_SIZE: usize = 10;
+// The number of state transitions within the retained history at which a check is considered to be flapping.
+const FLAP_THRESHOLD: usize = 4;
 static TYPE_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new("^[A-Z_]+$").unwrap());
 
 struct TimestampedResult {
     result: HealthCheckResult,
     time: Instant,
+    timestamp: DateTime<Utc>,
 }
 
 impl TimestampedResult {
@@ -42,13 +49,61 @@ impl TimestampedResult {
         TimestampedResult {
             result,
             time: Instant::now(),
+            timestamp: Utc::now(),
         }
     }
 }
 
+struct CheckState {
+    latest: ArcSwap<TimestampedResult>,
+    history: Mutex<VecDeque<Arc<TimestampedResult>>>,
+}
+
+impl CheckState {
+    fn new() -> Self {
+        CheckState {
+            latest: ArcSwap::new(Arc::new(TimestampedResult::new(
+                computing_for_the_first_time(),
+            ))),
+            history: Mutex::new(VecDeque::with_capacity(HISTORY_SIZE)),
+        }
+    }
+
+    fn update(&self, result: HealthCheckResult) {
+        let result = Arc::new(TimestampedResult::new(result));
+
+        let mut history = self.history.lock();
+        if history.len() == HISTORY_SIZE {
+            history.pop_front();
+        }
+        history.push_back(result.clone());
+        drop(history);
+
+        self.latest.store(result);
+    }
+
+    /// Returns the worst state in the check's recent history if its state has changed frequently enough to be
+    /// considered flapping.
+    fn flapping_state(&self) -> Option<HealthState> {
+        let history = self.history.lock();
+
+        let transitions = history
+            .iter()
+            .zip(history.iter().skip(1))
+            .filter(|(a, b)| a.result.state() != b.result.state())
+            .count();
+
+        if transitions < FLAP_THRESHOLD {
+            return None;
+        }
+
+        history.iter().map(|r| r.result.state()).max().cloned()
+    }
+}
+
 struct InstalledCheck {
     check: Arc<dyn HealthCheck>,
-    result: Arc<ArcSwap<TimestampedResult>>,
+    state: Arc<CheckState>,
     handle: JoinHandle<()>,
 }
 
@@ -122,30 +177,50 @@ impl HealthCheckRegistry {
     fn make_check(&self, check: Arc<dyn HealthCheck>) -> InstalledCheck {
         let _guard = self.handle.enter();
 
-        let result = Arc::new(ArcSwap::new(Arc::new(TimestampedResult::new(
-            computing_for_the_first_time(),
-        ))));
-        let handle = task::spawn(run_check(check.clone(), result.clone()));
+        let state = Arc::new(CheckState::new());
+        let handle = task::spawn(run_check(check.clone(), state.clone()));
 
         InstalledCheck {
             check,
-            result,
+            state,
             handle,
         }
     }
 
+    pub(crate) fn history(&self) -> BTreeMap<String, Vec<HistoricalResult>> {
+        self.checks
+            .lock()
+            .iter()
+            .map(|(type_, check)| {
+                let history = check
+                    .state
+                    .history
+                    .lock()
+                    .iter()
+                    .map(|r| HistoricalResult {
+                        time: r.timestamp,
+                        state: r.result.state().clone(),
+                        message: r.result.message().map(|s| s.to_string()),
+                    })
+                    .collect();
+
+                (type_.0.clone(), history)
+            })
+            .collect()
+    }
+
     pub(crate) fn run_checks(&self) -> HealthStatus {
         let threshold = Instant::now() - STALENESS_THRESHOLD;
         let mut stale_checks = vec![];
 
         let status = HealthStatus::builder().extend_checks(self.checks.lock().iter().map(
             |(type_, check)| {
-                let result = check.result.load();
+                let result = check.state.latest.load();
                 if result.time < threshold {
                     stale_checks.push(type_.clone());
                 }
 
-                let result = api::HealthCheckResult::builder()
+                let builder = api::HealthCheckResult::builder()
                     .type_(type_.clone())
                     .state(result.result.state().clone())
                     .message(result.result.message().map(|s| s.to_string()))
@@ -155,8 +230,17 @@ impl HealthCheckRegistry {
                             .params()
                             .iter()
                             .map(|(k, v)| (k.clone(), v.clone())),
-                    )
-                    .build();
+                    );
+
+                // Report the worst recent state of flapping checks to keep the aggregate status from oscillating.
+                let result = match check.state.flapping_state() {
+                    Some(state) => builder
+                        .state(state)
+                        .insert_params("flapping", true)
+                        .insert_params("currentState", result.result.state())
+                        .build(),
+                    None => builder.build(),
+                };
 
                 (type_.clone(), result)
             },
@@ -205,7 +289,7 @@ fn panic_error() -> HealthCheckResult {
         .build()
 }
 
-async fn run_check(check: Arc<dyn HealthCheck>, state: Arc<ArcSwap<TimestampedResult>>) {
+async fn run_check(check: Arc<dyn HealthCheck>, state: Arc<CheckState>) {
     loop {
         // it's okay to use block_in_place here since we know this future is running as its own task.
         let result = task::block_in_place(|| {
@@ -218,8 +302,63 @@ async fn run_check(check: Arc<dyn HealthCheck>, state: Arc<ArcSwap<TimestampedRe
             Err(_) => panic_error(),
         };
 
-        state.store(Arc::new(TimestampedResult::new(result)));
+        state.update(result);
 
         time::sleep(CHECK_RUN_INTERVAL).await;
     }
 }
+
+#[derive(Serialize)]
+pub(crate) struct HistoricalResult {
+    time: DateTime<Utc>,
+    state: HealthState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn result(state: HealthState) -> HealthCheckResult {
+        HealthCheckResult::builder().state(state).build()
+    }
+
+    #[test]
+    fn stable_check_not_flapping() {
+        let state = CheckState::new();
+        for _ in 0..HISTORY_SIZE {
+            state.update(result(HealthState::Healthy));
+        }
+        state.update(result(HealthState::Error));
+
+        assert_eq!(state.flapping_state(), None);
+    }
+
+    #[test]
+    fn oscillating_check_flapping() {
+        let state = CheckState::new();
+        for _ in 0..FLAP_THRESHOLD / 2 + 1 {
+            state.update(result(HealthState::Healthy));
+            state.update(result(HealthState::Warning));
+        }
+        state.update(result(HealthState::Healthy));
+
+        assert_eq!(state.flapping_state(), Some(HealthState::Warning));
+    }
+
+    #[test]
+    fn flapping_clears_once_stable() {
+        let state = CheckState::new();
+        for _ in 0..FLAP_THRESHOLD {
+            state.update(result(HealthState::Healthy));
+            state.update(result(HealthState::Error));
+        }
+        for _ in 0..HISTORY_SIZE {
+            state.update(result(HealthState::Healthy));
+        }
+
+        assert_eq!(state.flapping_state(), None);
+        assert_eq!(state.history.lock().len(), HISTORY_SIZE);
+    }
+}
@@ -12,13 +12,20 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 use crate::health::{HealthCheck, HealthCheckResult, HealthState};
-use conjure_runtime::HostMetricsRegistry;
+use conjure_runtime::{HostMetrics, HostMetricsRegistry};
 use itertools::Itertools;
 use std::collections::BTreeSet;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-const IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// The amount of time after which a host which hasn't been used is no longer considered.
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Determines if calls to a host are failing more often than they succeed.
+pub fn is_failing(metrics: &HostMetrics) -> bool {
+    metrics.response_5xx().five_minute_rate() > metrics.response_2xx().five_minute_rate()
+        || metrics.io_error().five_minute_rate() > metrics.response_2xx().five_minute_rate()
+}
 
 /// A health check which reports failures in calls from this service to others.
 pub struct ServiceDependencyHealthCheck {
@@ -45,10 +52,7 @@ impl HealthCheck for ServiceDependencyHealthCheck {
         let bad_hosts_by_service = hosts
             .iter()
             .filter(|m| m.last_update() > cutoff)
-            .filter(|m| {
-                m.response_5xx().five_minute_rate() > m.response_2xx().five_minute_rate()
-                    || m.io_error().five_minute_rate() > m.response_2xx().five_minute_rate()
-            })
+            .filter(|m| is_failing(m))
             .map(|m| (m.service_name(), format!("{}:{}", m.hostname(), m.port())))
             .into_grouping_map()
             .collect::<BTreeSet<_>>();
@@ -0,0 +1,65 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::health::{HealthCheck, HealthCheckResult, HealthState};
+use crate::slo::{Objective, SloRegistry};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+/// A health check which reports a warning state if any endpoint has exhausted the error budget of one of its
+/// configured service level objectives.
+pub struct SloHealthCheck {
+    slos: Arc<SloRegistry>,
+}
+
+impl SloHealthCheck {
+    pub fn new(slos: &Arc<SloRegistry>) -> Self {
+        SloHealthCheck { slos: slos.clone() }
+    }
+}
+
+impl HealthCheck for SloHealthCheck {
+    fn type_(&self) -> &str {
+        "SLO_ERROR_BUDGET"
+    }
+
+    fn result(&self) -> HealthCheckResult {
+        let mut availability = BTreeSet::new();
+        let mut latency = BTreeSet::new();
+
+        for endpoint in self.slos.endpoints() {
+            if endpoint.exhausted(Objective::Availability) {
+                availability.insert(endpoint.key().to_string());
+            }
+            if endpoint.exhausted(Objective::Latency) {
+                latency.insert(endpoint.key().to_string());
+            }
+        }
+
+        if availability.is_empty() && latency.is_empty() {
+            return HealthCheckResult::builder()
+                .state(HealthState::Healthy)
+                .build();
+        }
+
+        HealthCheckResult::builder()
+            .state(HealthState::Warning)
+            .message(
+                "Endpoints have exhausted the error budgets of their service level objectives"
+                    .to_string(),
+            )
+            .insert_params("availability", availability)
+            .insert_params("latency", latency)
+            .build()
+    }
+}
@@ -0,0 +1,211 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::acme::Challenges;
+use crate::server::Listener;
+use crate::service::accept::AcceptService;
+use crate::service::Service;
+use crate::shutdown_hooks::ShutdownPhase;
+use crate::Witchcraft;
+use bytes::Bytes;
+use conjure_error::Error;
+use http::header::{CONNECTION, HOST, LOCATION};
+use http::{HeaderValue, Request, Response, StatusCode};
+use http_body_util::Full;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::{TokioIo, TokioTimer};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::task;
+use witchcraft_log::info;
+use witchcraft_metrics::{Meter, MetricId};
+
+/// Starts the plaintext HTTP listener which redirects requests to the service port.
+pub(crate) async fn start(witchcraft: &mut Witchcraft, port: u16) -> Result<SocketAddr, Error> {
+    let accept = AcceptService::new(
+        &witchcraft.install_config,
+        &witchcraft.metrics,
+        Listener::HttpRedirect,
+        port,
+    )?;
+    let local_addrs = accept.local_addrs()?;
+    info!(
+        "server listening",
+        safe: {
+            listener: Listener::HttpRedirect.tag(),
+            port: local_addrs[0].port(),
+            addresses: format_args!("{local_addrs:?}"),
+        },
+    );
+
+    let redirector = Arc::new(Redirector {
+        canonical_url: witchcraft
+            .install_config
+            .canonical_url()
+            .filter(|s| s.starts_with("https://"))
+            .map(|s| s.trim_end_matches('/').to_string()),
+        https_port: witchcraft.install_config.port(),
+        challenges: witchcraft.acme_challenges.clone(),
+        redirected: witchcraft
+            .metrics
+            .meter(MetricId::new("server.http-redirect.redirected")),
+    });
+    let header_read_timeout = witchcraft.install_config.server().header_read_timeout();
+
+    let handle = task::spawn(async move {
+        loop {
+            let stream = accept.call(()).await;
+            let redirector = redirector.clone();
+            task::spawn(async move {
+                let service = service_fn(|req| {
+                    let response = redirector.response(&req);
+                    async move { Ok::<_, Infallible>(response) }
+                });
+                let _ = http1::Builder::new()
+                    .timer(TokioTimer::new())
+                    .header_read_timeout(header_read_timeout)
+                    .keep_alive(false)
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+
+    witchcraft.on_shutdown_phase(
+        ShutdownPhase::StopAccepting,
+        Listener::HttpRedirect.tag(),
+        async move {
+            handle.abort();
+        },
+    );
+
+    Ok(local_addrs[0])
+}
+
+struct Redirector {
+    canonical_url: Option<String>,
+    https_port: u16,
+    challenges: Arc<Challenges>,
+    redirected: Arc<Meter>,
+}
+
+impl Redirector {
+    fn response<B>(&self, req: &Request<B>) -> Response<Full<Bytes>> {
+        if let Some(key_authorization) = self.challenges.http(req.uri().path()) {
+            return Response::new(Full::new(Bytes::from(key_authorization)));
+        }
+
+        let mut response = Response::new(Full::new(Bytes::new()));
+        response
+            .headers_mut()
+            .insert(CONNECTION, HeaderValue::from_static("close"));
+
+        let location = self
+            .location(req)
+            .and_then(|l| HeaderValue::try_from(l).ok());
+        match location {
+            Some(location) => {
+                self.redirected.mark(1);
+                *response.status_mut() = StatusCode::MOVED_PERMANENTLY;
+                response.headers_mut().insert(LOCATION, location);
+            }
+            None => *response.status_mut() = StatusCode::BAD_REQUEST,
+        }
+
+        response
+    }
+
+    fn location<B>(&self, req: &Request<B>) -> Option<String> {
+        let path_and_query = req.uri().path_and_query().map_or("/", |p| p.as_str());
+
+        if let Some(canonical_url) = &self.canonical_url {
+            return Some(format!("{canonical_url}{path_and_query}"));
+        }
+
+        let host = req.headers().get(HOST)?.to_str().ok()?;
+        let host = strip_port(host);
+        if host.is_empty() {
+            return None;
+        }
+
+        let location = if self.https_port == 443 {
+            format!("https://{host}{path_and_query}")
+        } else {
+            format!("https://{host}:{}{path_and_query}", self.https_port)
+        };
+        Some(location)
+    }
+}
+
+fn strip_port(host: &str) -> &str {
+    // IPv6 literals are bracketed and contain colons of their own.
+    match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => &host[..i],
+        _ => host,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use witchcraft_metrics::MetricRegistry;
+
+    fn redirector(canonical_url: Option<&str>, https_port: u16) -> Redirector {
+        Redirector {
+            canonical_url: canonical_url.map(|s| s.to_string()),
+            https_port,
+            challenges: Arc::new(Challenges::default()),
+            redirected: MetricRegistry::new()
+                .meter(MetricId::new("server.http-redirect.redirected")),
+        }
+    }
+
+    fn request(host: Option<&str>, uri: &str) -> Request<()> {
+        let mut builder = Request::builder().uri(uri);
+        if let Some(host) = host {
+            builder = builder.header(HOST, host);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn redirect() {
+        let response = redirector(None, 443).response(&request(Some("foo.com"), "/a/b?c=d"));
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.headers()[LOCATION], "https://foo.com/a/b?c=d");
+
+        let response = redirector(None, 8443).response(&request(Some("foo.com:80"), "/"));
+        assert_eq!(response.headers()[LOCATION], "https://foo.com:8443/");
+
+        let response = redirector(None, 443).response(&request(Some("[::1]:80"), "/a"));
+        assert_eq!(response.headers()[LOCATION], "https://[::1]/a");
+
+        let response = redirector(None, 443).response(&request(Some("[::1]"), "/a"));
+        assert_eq!(response.headers()[LOCATION], "https://[::1]/a");
+    }
+
+    #[test]
+    fn canonical_url() {
+        let response = redirector(Some("https://bar.com:1234"), 443)
+            .response(&request(Some("foo.com"), "/a?b"));
+        assert_eq!(response.headers()[LOCATION], "https://bar.com:1234/a?b");
+    }
+
+    #[test]
+    fn missing_host() {
+        let response = redirector(None, 443).response(&request(None, "/a"));
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
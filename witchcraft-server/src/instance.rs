@@ -0,0 +1,173 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Identification of the server across process restarts.
+//!
+//! The instance ID is generated the first time the server starts in a given installation and persisted under
+//! `var/data`, while the boot epoch is incremented on every start. Log pipelines can then tell a restart of an existing
+//! deployment (same ID, higher epoch) apart from a new deployment (new ID).
+use conjure_error::Error;
+use conjure_object::Uuid;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use tempfile::NamedTempFile;
+use witchcraft_log::warn;
+use witchcraft_metrics::{MetricId, MetricRegistry};
+
+pub const INSTANCE_ID_KEY: &str = "instanceId";
+pub const BOOT_EPOCH_KEY: &str = "bootEpoch";
+
+const STATE_DIR: &str = "var/data";
+const STATE_FILE: &str = "instance.json";
+
+static INSTANCE: OnceCell<Instance> = OnceCell::new();
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Instance {
+    instance_id: String,
+    boot_epoch: u64,
+}
+
+impl Instance {
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    pub fn boot_epoch(&self) -> u64 {
+        self.boot_epoch
+    }
+}
+
+/// Loads the persisted instance state, records a new boot, and installs it as the process-wide instance.
+pub fn init() -> Result<(), Error> {
+    let instance = boot(Path::new(STATE_DIR))?;
+    let _ = INSTANCE.set(instance);
+    Ok(())
+}
+
+/// Returns the process-wide instance, if it has been initialized.
+pub fn get() -> Option<&'static Instance> {
+    INSTANCE.get()
+}
+
+/// Registers the `server.instance.epoch` gauge.
+pub fn register_metric(metrics: &MetricRegistry) {
+    let Some(instance) = get() else {
+        return;
+    };
+
+    let epoch = instance.boot_epoch;
+    metrics.gauge(
+        MetricId::new("server.instance.epoch")
+            .with_tag("instance-id", instance.instance_id.clone()),
+        move || epoch,
+    );
+}
+
+fn boot(dir: &Path) -> Result<Instance, Error> {
+    let path = dir.join(STATE_FILE);
+    let instance = match load(&path) {
+        Ok(Some(previous)) => Instance {
+            instance_id: previous.instance_id,
+            boot_epoch: previous.boot_epoch + 1,
+        },
+        Ok(None) => new_instance(),
+        Err(e) => {
+            warn!(
+                "unable to read persisted instance state, generating a new instance ID",
+                error: Error::internal_safe(e),
+            );
+            new_instance()
+        }
+    };
+
+    store(dir, &instance)
+        .map_err(|e| Error::internal_safe(e).with_safe_param("path", path.to_string_lossy()))?;
+
+    Ok(instance)
+}
+
+fn new_instance() -> Instance {
+    Instance {
+        instance_id: Uuid::new_v4().to_string(),
+        boot_epoch: 0,
+    }
+}
+
+fn load(path: &Path) -> io::Result<Option<Instance>> {
+    let buf = match fs::read(path) {
+        Ok(buf) => buf,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    serde_json::from_slice(&buf)
+        .map(Some)
+        .map_err(io::Error::from)
+}
+
+fn store(dir: &Path, instance: &Instance) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    // write to a temporary file first so a crash mid-write can't leave a truncated state file behind
+    let mut file = NamedTempFile::new_in(dir)?;
+    serde_json::to_writer(&mut file, instance)?;
+    file.flush()?;
+    file.as_file().sync_all()?;
+    file.persist(dir.join(STATE_FILE))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn restart_increments_epoch() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let first = boot(dir.path()).unwrap();
+        assert_eq!(first.boot_epoch(), 0);
+
+        let second = boot(dir.path()).unwrap();
+        assert_eq!(second.instance_id(), first.instance_id());
+        assert_eq!(second.boot_epoch(), 1);
+    }
+
+    #[test]
+    fn corrupt_state_is_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let first = boot(dir.path()).unwrap();
+        fs::write(dir.path().join(STATE_FILE), "not json").unwrap();
+
+        let second = boot(dir.path()).unwrap();
+        assert_ne!(second.instance_id(), first.instance_id());
+        assert_eq!(second.boot_epoch(), 0);
+
+        let third = boot(dir.path()).unwrap();
+        assert_eq!(
+            third,
+            Instance {
+                boot_epoch: 1,
+                ..second
+            }
+        );
+    }
+}
@@ -0,0 +1,103 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use conjure_error::Error;
+use std::time::Duration;
+use tikv_jemalloc_ctl::{arenas, background_thread, max_background_threads, raw};
+use witchcraft_log::warn;
+use witchcraft_server_config::install::JemallocConfig;
+
+/// jemalloc's pseudo-arena index which refers to all arenas.
+pub const MALLCTL_ARENAS_ALL: usize = 4096;
+
+/// Applies the install-time jemalloc configuration.
+///
+/// Errors are logged rather than returned since the allocator will continue to function with its default settings.
+pub fn init(config: &JemallocConfig) {
+    if let Some(max) = config.max_background_threads() {
+        if let Err(e) = max_background_threads::write(max) {
+            warn!(
+                "error setting jemalloc max background threads",
+                error: Error::internal_safe(e)
+            );
+        }
+    }
+
+    if let Some(enabled) = config.background_threads() {
+        if let Err(e) = background_thread::write(enabled) {
+            warn!(
+                "error setting jemalloc background threads",
+                error: Error::internal_safe(e)
+            );
+        }
+    }
+
+    if let Some(dirty_decay) = config.dirty_decay() {
+        set_decay("dirty_decay_ms", dirty_decay);
+    }
+
+    if let Some(muzzy_decay) = config.muzzy_decay() {
+        set_decay("muzzy_decay_ms", muzzy_decay);
+    }
+}
+
+// The arena-specific setting applies to existing arenas, while the `arenas` default applies to ones created later.
+fn set_decay(name: &str, decay: Duration) {
+    let ms = decay.as_millis().try_into().unwrap_or(isize::MAX);
+
+    for key in [
+        format!("arena.{MALLCTL_ARENAS_ALL}.{name}\0"),
+        format!("arenas.{name}\0"),
+    ] {
+        if let Err(e) = unsafe { raw::write::<isize>(key.as_bytes(), ms) } {
+            warn!(
+                "error setting jemalloc decay time",
+                safe: {
+                    key: key.trim_end_matches('\0'),
+                },
+                error: Error::internal_safe(e),
+            );
+        }
+    }
+}
+
+/// Returns the sizes of jemalloc's small size classes, indexed by bin.
+pub fn bin_sizes() -> Vec<usize> {
+    let Some(nbins) = read::<libc::c_uint>("arenas.nbins") else {
+        return vec![];
+    };
+
+    (0..nbins)
+        .map_while(|bin| read::<usize>(&format!("arenas.bin.{bin}.size")))
+        .collect()
+}
+
+/// Returns the number of arenas.
+pub fn narenas() -> usize {
+    arenas::narenas::read().map_or(0, |n| n as usize)
+}
+
+/// Reads a `size_t` statistic by name.
+///
+/// The statistics epoch must be advanced for the value to be up to date.
+pub fn read_stat(name: &str) -> Option<usize> {
+    read(name)
+}
+
+fn read<T>(name: &str) -> Option<T>
+where
+    T: Copy,
+{
+    let name = format!("{name}\0");
+    unsafe { raw::read(name.as_bytes()).ok() }
+}
@@ -0,0 +1,59 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::jobs::{JobRecord, Jobs};
+use conjure_error::{Error, NotFound};
+use conjure_http::server::StdResponseSerializer;
+use conjure_http::{conjure_endpoints, endpoint};
+use conjure_object::Uuid;
+use std::sync::Arc;
+
+#[conjure_endpoints]
+pub trait JobsService {
+    #[endpoint(path = "/jobs/{job_id}", method = GET, name = "getJob", produces = StdResponseSerializer)]
+    async fn get_job(&self, #[path(safe)] job_id: Uuid) -> Result<JobRecord, Error>;
+
+    #[endpoint(path = "/jobs/{job_id}", method = DELETE, name = "cancelJob")]
+    async fn cancel_job(&self, #[path(safe)] job_id: Uuid) -> Result<(), Error>;
+}
+
+pub struct JobsResource {
+    jobs: Arc<Jobs>,
+}
+
+impl JobsResource {
+    pub fn new(jobs: &Arc<Jobs>) -> Self {
+        JobsResource { jobs: jobs.clone() }
+    }
+
+    fn load(&self, job_id: Uuid) -> Result<JobRecord, Error> {
+        self.jobs.get(job_id)?.ok_or_else(|| {
+            Error::service_safe("job not found", NotFound::new()).with_safe_param("jobId", job_id)
+        })
+    }
+}
+
+impl JobsService for JobsResource {
+    async fn get_job(&self, job_id: Uuid) -> Result<JobRecord, Error> {
+        self.load(job_id)
+    }
+
+    async fn cancel_job(&self, job_id: Uuid) -> Result<(), Error> {
+        if !self.jobs.cancel(job_id) {
+            // Distinguish jobs which have already finished from those which never existed.
+            self.load(job_id)?;
+        }
+
+        Ok(())
+    }
+}
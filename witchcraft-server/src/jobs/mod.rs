@@ -0,0 +1,491 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Long-running job submission.
+//!
+//! Endpoints which start work that outlives a single request can submit it to the server's [`Jobs`] manager, obtained
+//! via [`Witchcraft::jobs`], and immediately return the job's ID to the client. Up to the install configuration's
+//! `server.max-concurrent-jobs` jobs run at once, and the rest are queued in submission order. Every transition of a
+//! job's [`JobState`] is saved to the [`JobStore`] installed via [`Witchcraft::job_store`], which defaults to an
+//! in-memory store.
+//!
+//! If the manager is used, the server exposes two endpoints under its `/api` prefix for clients to track their jobs:
+//!
+//! * `GET /jobs/{jobId}` - Returns the job's [`JobRecord`] as JSON.
+//! * `DELETE /jobs/{jobId}` - Cancels the job if it has not already finished.
+//!
+//! Job IDs are random UUIDs, so knowledge of an ID acts as the capability to poll and cancel its job.
+//!
+//! Cancelling a job drops its future, and the [`JobContext`] passed to the job can be used to propagate the
+//! cancellation to any work it has spawned. When the server shuts down, all outstanding jobs are cancelled and recorded
+//! as such in the store so clients polling for them learn of their fate.
+//!
+//! [`Witchcraft::jobs`]: crate::Witchcraft::jobs
+//! [`Witchcraft::job_store`]: crate::Witchcraft::job_store
+use conjure_error::{Error, ErrorCode, ErrorKind, Internal, SerializableError};
+use conjure_object::{DateTime, Utc, Uuid};
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use witchcraft_log::{log, warn, Level};
+use witchcraft_server_config::install::InstallConfig;
+
+pub(crate) mod endpoint;
+
+// The in-memory store forgets finished jobs after this long to bound its size.
+const MEMORY_RETENTION: Duration = Duration::from_secs(60 * 60);
+
+/// The state of a job.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[non_exhaustive]
+pub enum JobState {
+    /// The job is waiting for a slot to run in.
+    Queued,
+    /// The job is running.
+    Running,
+    /// The job completed successfully.
+    Done,
+    /// The job completed with an error.
+    Failed,
+    /// The job was cancelled before it completed, either explicitly or by the server shutting down.
+    Cancelled,
+}
+
+impl JobState {
+    /// Determines if the state is final.
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        match self {
+            JobState::Queued | JobState::Running => false,
+            JobState::Done | JobState::Failed | JobState::Cancelled => true,
+        }
+    }
+}
+
+/// The status of a job, as saved in a [`JobStore`] and returned to clients polling the job.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobRecord {
+    id: Uuid,
+    name: String,
+    state: JobState,
+    submitted_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    started_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    finished_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    result: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<SerializableError>,
+}
+
+impl JobRecord {
+    /// Returns the job's ID.
+    #[inline]
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// Returns the name the job was submitted with.
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the job's state.
+    #[inline]
+    pub fn state(&self) -> JobState {
+        self.state
+    }
+
+    /// Returns the time the job was submitted.
+    #[inline]
+    pub fn submitted_at(&self) -> DateTime<Utc> {
+        self.submitted_at
+    }
+
+    /// Returns the time the job started running.
+    #[inline]
+    pub fn started_at(&self) -> Option<DateTime<Utc>> {
+        self.started_at
+    }
+
+    /// Returns the time the job finished.
+    #[inline]
+    pub fn finished_at(&self) -> Option<DateTime<Utc>> {
+        self.finished_at
+    }
+
+    /// Returns the serialized output of a job in the [`JobState::Done`] state.
+    #[inline]
+    pub fn result(&self) -> Option<&serde_json::Value> {
+        self.result.as_ref()
+    }
+
+    /// Returns the error of a job in the [`JobState::Failed`] state.
+    ///
+    /// Only the service error returned by the job is exposed to clients; all other errors are reported as internal
+    /// errors.
+    #[inline]
+    pub fn error(&self) -> Option<&SerializableError> {
+        self.error.as_ref()
+    }
+
+    fn finish(&mut self, state: JobState) {
+        self.state = state;
+        self.finished_at = Some(Utc::now());
+    }
+}
+
+/// A persistent store of job records.
+pub trait JobStore {
+    /// Saves a job's record, replacing any existing record with the same ID.
+    ///
+    /// This is called synchronously on every change of a job's state, so implementations should not block for
+    /// extended periods.
+    fn save(&self, job: &JobRecord) -> Result<(), Error>;
+
+    /// Loads the record of the job with the specified ID.
+    fn load(&self, id: Uuid) -> Result<Option<JobRecord>, Error>;
+}
+
+/// A [`JobStore`] which keeps records in memory.
+///
+/// Records of finished jobs are discarded an hour after they finish.
+#[derive(Default)]
+pub struct MemoryJobStore {
+    jobs: Mutex<HashMap<Uuid, (JobRecord, Option<Instant>)>>,
+}
+
+impl MemoryJobStore {
+    /// Creates a new, empty store.
+    pub fn new() -> Self {
+        MemoryJobStore::default()
+    }
+}
+
+impl JobStore for MemoryJobStore {
+    fn save(&self, job: &JobRecord) -> Result<(), Error> {
+        let now = Instant::now();
+        let mut jobs = self.jobs.lock();
+        jobs.retain(|_, (_, finished)| {
+            finished.map_or(true, |finished| {
+                now.duration_since(finished) < MEMORY_RETENTION
+            })
+        });
+
+        let finished = job.state.is_finished().then_some(now);
+        jobs.insert(job.id, (job.clone(), finished));
+        Ok(())
+    }
+
+    fn load(&self, id: Uuid) -> Result<Option<JobRecord>, Error> {
+        Ok(self.jobs.lock().get(&id).map(|(job, _)| job.clone()))
+    }
+}
+
+/// A handle passed to a running job.
+#[derive(Clone)]
+pub struct JobContext {
+    id: Uuid,
+    cancellation: CancellationToken,
+}
+
+impl JobContext {
+    /// Returns the job's ID.
+    #[inline]
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// Returns `true` if the job has been cancelled.
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /// Waits until the job is cancelled.
+    pub async fn cancelled(&self) {
+        self.cancellation.cancelled().await
+    }
+}
+
+/// The server's manager of long-running jobs.
+pub struct Jobs {
+    store: Arc<dyn JobStore + Sync + Send>,
+    handle: Handle,
+    permits: Arc<Semaphore>,
+    tasks: TaskTracker,
+    active: Mutex<HashMap<Uuid, CancellationToken>>,
+    shutdown: CancellationToken,
+}
+
+impl Jobs {
+    pub(crate) fn new(
+        install_config: &InstallConfig,
+        handle: &Handle,
+        store: Arc<dyn JobStore + Sync + Send>,
+    ) -> Self {
+        Jobs {
+            store,
+            handle: handle.clone(),
+            permits: Arc::new(Semaphore::new(
+                install_config.server().max_concurrent_jobs(),
+            )),
+            tasks: TaskTracker::new(),
+            active: Mutex::new(HashMap::new()),
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    /// Submits a job, returning its ID.
+    ///
+    /// The job is queued until a slot is available to run it in. Its output is serialized as JSON into its record when
+    /// it completes. An error is returned if the job's initial record could not be saved or the server is shutting
+    /// down.
+    pub fn submit<F, Fut, T>(self: &Arc<Self>, name: &str, job: F) -> Result<Uuid, Error>
+    where
+        F: FnOnce(JobContext) -> Fut,
+        Fut: Future<Output = Result<T, Error>> + 'static + Send,
+        T: Serialize,
+    {
+        if self.shutdown.is_cancelled() {
+            return Err(Error::unavailable("server is shutting down"));
+        }
+
+        let id = Uuid::new_v4();
+        let record = JobRecord {
+            id,
+            name: name.to_string(),
+            state: JobState::Queued,
+            submitted_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            result: None,
+            error: None,
+        };
+        self.store.save(&record)?;
+
+        let cancellation = self.shutdown.child_token();
+        let job = job(JobContext {
+            id,
+            cancellation: cancellation.clone(),
+        })
+        .map(|r| r.and_then(|v| serde_json::to_value(v).map_err(Error::internal)))
+        .boxed();
+
+        self.active.lock().insert(id, cancellation.clone());
+        self.tasks
+            .spawn_on(self.clone().run(record, cancellation, job), &self.handle);
+
+        Ok(id)
+    }
+
+    /// Returns the record of the job with the specified ID.
+    pub fn get(&self, id: Uuid) -> Result<Option<JobRecord>, Error> {
+        self.store.load(id)
+    }
+
+    /// Cancels the job with the specified ID.
+    ///
+    /// Returns `false` if the job is not queued or running.
+    pub fn cancel(&self, id: Uuid) -> bool {
+        match self.active.lock().get(&id) {
+            Some(cancellation) => {
+                cancellation.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    async fn run(
+        self: Arc<Self>,
+        mut record: JobRecord,
+        cancellation: CancellationToken,
+        job: BoxFuture<'static, Result<serde_json::Value, Error>>,
+    ) {
+        let permit = tokio::select! {
+            permit = self.permits.clone().acquire_owned() => permit.unwrap(),
+            _ = cancellation.cancelled() => {
+                record.finish(JobState::Cancelled);
+                self.complete(&record);
+                return;
+            }
+        };
+
+        record.state = JobState::Running;
+        record.started_at = Some(Utc::now());
+        self.save(&record);
+
+        let result = tokio::select! {
+            result = job => Some(result),
+            _ = cancellation.cancelled() => None,
+        };
+        drop(permit);
+
+        match result {
+            Some(Ok(value)) => {
+                record.result = Some(value);
+                record.finish(JobState::Done);
+            }
+            Some(Err(error)) => {
+                let serialized = match error.kind() {
+                    ErrorKind::Service(error) => error.clone(),
+                    _ => conjure_error::encode(&Internal::new()),
+                };
+                let level = match serialized.error_code() {
+                    ErrorCode::Internal => Level::Error,
+                    _ => Level::Info,
+                };
+                log!(
+                    level,
+                    "job failed",
+                    safe: { jobId: record.id, name: &record.name },
+                    error: error,
+                );
+                record.error = Some(serialized);
+                record.finish(JobState::Failed);
+            }
+            None => record.finish(JobState::Cancelled),
+        }
+        self.complete(&record);
+    }
+
+    fn complete(&self, record: &JobRecord) {
+        self.save(record);
+        self.active.lock().remove(&record.id);
+    }
+
+    fn save(&self, record: &JobRecord) {
+        if let Err(e) = self.store.save(record) {
+            warn!(
+                "error saving job record",
+                safe: { jobId: record.id, state: format_args!("{:?}", record.state) },
+                error: e,
+            );
+        }
+    }
+
+    /// Cancels all outstanding jobs and waits for their final states to be saved.
+    pub(crate) async fn shutdown(&self) {
+        self.shutdown.cancel();
+        self.tasks.close();
+        self.tasks.wait().await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use witchcraft_server_config::install::ServerConfig;
+
+    fn jobs(max_concurrent_jobs: usize) -> Arc<Jobs> {
+        let install_config = InstallConfig::builder()
+            .product_name("foo")
+            .product_version("0.0.0")
+            .port(0)
+            .server(
+                ServerConfig::builder()
+                    .max_concurrent_jobs(max_concurrent_jobs)
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        Arc::new(Jobs::new(
+            &install_config,
+            &Handle::current(),
+            Arc::new(MemoryJobStore::new()),
+        ))
+    }
+
+    async fn wait_for(jobs: &Jobs, id: Uuid, state: JobState) -> JobRecord {
+        loop {
+            let record = jobs.get(id).unwrap().unwrap();
+            if record.state() == state {
+                return record;
+            }
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn lifecycle() {
+        let jobs = jobs(1);
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let first = jobs
+            .submit("first", |_| async move {
+                rx.await.unwrap();
+                Ok(1)
+            })
+            .unwrap();
+        let second = jobs
+            .submit("second", |_| async {
+                Err::<(), _>(Error::internal_safe("blammo"))
+            })
+            .unwrap();
+
+        wait_for(&jobs, first, JobState::Running).await;
+        assert_eq!(jobs.get(second).unwrap().unwrap().state(), JobState::Queued);
+
+        tx.send(()).unwrap();
+        let record = wait_for(&jobs, first, JobState::Done).await;
+        assert_eq!(record.name(), "first");
+        assert_eq!(record.result(), Some(&serde_json::json!(1)));
+        assert!(record.finished_at().is_some());
+
+        let record = wait_for(&jobs, second, JobState::Failed).await;
+        assert_eq!(record.error().unwrap().error_name(), "Default:Internal");
+        assert!(!jobs.cancel(second));
+    }
+
+    #[tokio::test]
+    async fn cancellation() {
+        let jobs = jobs(1);
+
+        let running = jobs.submit("running", |_| future_pending()).unwrap();
+        let queued = jobs.submit("queued", |_| future_pending()).unwrap();
+        wait_for(&jobs, running, JobState::Running).await;
+
+        assert!(jobs.cancel(queued));
+        wait_for(&jobs, queued, JobState::Cancelled).await;
+        assert_eq!(
+            jobs.get(running).unwrap().unwrap().state(),
+            JobState::Running
+        );
+
+        jobs.shutdown().await;
+        assert_eq!(
+            jobs.get(running).unwrap().unwrap().state(),
+            JobState::Cancelled,
+        );
+        assert!(jobs.submit("late", |_| future_pending()).is_err());
+    }
+
+    async fn future_pending() -> Result<(), Error> {
+        std::future::pending().await
+    }
+}
@@ -38,6 +38,84 @@
 //! The function is provided with the server's install and runtime configuration, as well as the [`Witchcraft`] object
 //! which can be used to configure the server. Once the initialization function returns, the server will start.
 //!
+//! ## Preflight checks
+//!
+//! Before the initialization function is called, the server checks that its environment is usable: the log directory
+//! is writable, the TLS key and certificate files are readable, the bind addresses can be bound, the open file limit is
+//! at least `server.max-connections`, and the system clock is plausible. All failures are reported together in a
+//! single startup error. A private key readable by all users is reported as a warning.
+//!
+//! ## Automatic certificates
+//!
+//! If `acme` is set in the install configuration, the server obtains its certificate from an ACME certificate authority
+//! like Let's Encrypt, proving control of the configured domains with the TLS-ALPN-01 or HTTP-01 challenge. The
+//! operator must agree to the certificate authority's terms of service by setting `acme.agree-to-terms-of-service` to
+//! `true`, or the server fails to start. The key and certificate are written to the `keystore` paths, and renewed
+//! certificates are picked up by new connections without a restart. If no certificate exists yet, a self-signed
+//! placeholder is used until the first one is issued. The ACME client requires the `acme` cargo feature; the server
+//! fails to start if `acme` is configured without it.
+//!
+//! ## OCSP stapling
+//!
+//! If `tls.ocsp-stapling.enabled` is set in the install configuration, the server fetches an OCSP response for its
+//! certificate from the responder listed in the certificate and staples it to TLS handshakes, so clients don't need
+//! to contact the responder themselves. The response is refreshed in the background every
+//! `tls.ocsp-stapling.refresh-interval`, and immediately after the certificate changes. The certificate file must
+//! include the issuer's certificate.
+//!
+//! ## HTTP redirect
+//!
+//! If `http-redirect-port` is set in the install configuration, the server also listens for plaintext HTTP on that
+//! port and responds to every request with a `301 Moved Permanently` redirect to the same path and query on the
+//! service port over HTTPS. The redirect targets the `canonical-url` if it is an `https` URL, and the request's host
+//! otherwise. When the port matches `acme.http01-port`, the listener also serves ACME HTTP-01 challenges.
+//!
+//! ## TLS session resumption
+//!
+//! Clients reconnecting to the server can resume their previous TLS session with a session ticket, skipping the
+//! expensive parts of the handshake. Tickets are valid for `tls.session-resumption.ticket-lifetime`, and the key used
+//! to encrypt them is replaced every `tls.session-resumption.ticket-key-rotation`. Shorter values limit the traffic
+//! exposed if a ticket key is compromised, at the cost of more full handshakes. Tickets remain valid across key
+//! rotations until they expire, and are encrypted with the configured crypto provider. Resumption can be disabled
+//! entirely by setting `tls.session-resumption.enabled` to `false`.
+//!
+//! ## TLS key logging
+//!
+//! To debug TLS issues with a packet capture, the server can write the secrets of each TLS session to a file in the
+//! `SSLKEYLOGFILE` format understood by tools like Wireshark. Key logging is disabled by default, and is enabled by
+//! setting `tls.key-log-file` in the install configuration or the `SSLKEYLOGFILE` environment variable. A warning is
+//! logged at startup whenever it is enabled. Anyone who can read the file can decrypt the server's traffic, so it
+//! should only be enabled temporarily.
+//!
+//! ## TLS crypto provider
+//!
+//! The server uses [AWS-LC](https://github.com/aws/aws-lc) for TLS cryptography by default, via the default
+//! `aws-lc-rs` cargo feature. Setting `tls.crypto-provider` to `ring` in the install configuration switches to the
+//! `ring` crate, which requires the `ring` cargo feature. Applications using `ring` can disable default features to
+//! avoid building AWS-LC at all. Deployments which require FIPS-validated cryptography can set `tls.fips` to `true`,
+//! which requires the `fips` cargo feature to build AWS-LC's FIPS module, and limits the server to FIPS-approved
+//! cipher suites and key exchange groups. The server fails to start if the configured provider was not compiled in.
+//! The provider is also installed as the process-wide rustls default unless the application has already installed
+//! one.
+//!
+//! ## Standby
+//!
+//! If `standby` is set in the runtime configuration, the server starts in a warm standby mode: it binds its ports and
+//! serves its status and debug endpoints, but responds to requests to application endpoints with a
+//! `503 Service Unavailable` status code. The server is activated when `standby` is set back to `false` or a request is
+//! made to the `/debug/activate` endpoint. Activation is permanent for the life of the process.
+//!
+//! ## Shutdown
+//!
+//! When the server receives a `SIGINT` or `SIGTERM` signal, it runs its shutdown hooks in a sequence of
+//! [`ShutdownPhase`]s: it stops accepting new connections, drains in-flight requests, stops background tasks, and
+//! finally flushes logs. Applications can register hooks into a phase with [`Witchcraft::on_shutdown_phase`] and bound
+//! an individual phase with [`Witchcraft::shutdown_phase_timeout`]. The shutdown process as a whole is bounded by
+//! `server.shutdown-timeout`, and is cut short if a second signal is received. Phases which start after the timeout
+//! has elapsed still run, but are given only 1 second each, so background tasks are stopped and logs are flushed even
+//! if draining requests takes the entire timeout. The server logs the duration of each hook and phase, along with the
+//! hooks still pending when a phase times out.
+//!
 //! ## Note
 //!
 //! The initialization function is expected to return quickly - any long-running work required should happen in the
@@ -55,6 +133,11 @@
 //! Configuration is loaded from the `var/conf/install.yml` and `var/conf/runtime.yml` files respectively. The
 //! `runtime.yml` file is automatically checked for updates every few seconds.
 //!
+//! Keys which are renamed in a future version of `witchcraft-server` will still be accepted under their old names. They
+//! are moved to their new location before the file is deserialized, and a warning is logged naming the replacement key
+//! and the version in which the old key will be removed. If both the old and new keys are set, the old key is
+//! ignored.
+//!
 //! ## Extension
 //!
 //! The configuration files are deserialized into Rust types via the [`serde::Deserialize`] trait. `witchcraft-server`'s
@@ -117,7 +200,112 @@
 //! which will place the endpoints under the `/api` route. If necessary, the [`Witchcraft::app`] and
 //! [`Witchcraft::blocking_app`] methods can be used to place the endpoints directly at the root route instead.
 //!
+//! `HEAD` requests to a path without a `HEAD` endpoint are handled by its `GET` endpoint, with the response body
+//! discarded.
+//! `OPTIONS` requests, and requests using a method not supported by any endpoint at their path, are answered with
+//! `204 No Content` and `405 Method Not Allowed` responses respectively, with an `Allow` header listing the methods
+//! supported at the path.
+//!
+//! Async endpoints whose handlers still make blocking calls can be marked with [`Witchcraft::may_block`] before their
+//! service is installed. The server then runs those handlers on Tokio's blocking thread pool so they don't stall the
+//! other requests sharing the runtime's worker threads.
+//!
+//! Endpoints which start work that outlives the request can submit it to the server's job manager, returned by
+//! [`Witchcraft::jobs`], which tracks its status and exposes endpoints for clients to poll and cancel it. See the
+//! [`jobs`] module for details.
+//!
+//! The server waits for in-flight requests to complete when it shuts down. Endpoints serving long-lived requests such
+//! as streaming responses can use the [`ShutdownSignal`] request extension to learn when
+//! shutdown has begun so they can finish cleanly within the configured shutdown timeout.
+//!
+//! Async handlers of Conjure endpoints returning `binary` can return a [`StreamingBody`] to stream a response from any
+//! [`Stream`] of [`Bytes`](bytes::Bytes), and [`RequestBody::into_stream`] converts a streamed
+//! request body into an [`Unpin`] stream that can be consumed without pinning it first.
+//!
+//! Server-Sent Events endpoints can return an [`EventStream`] of [`Event`]s with the [`EventStreamResponseSerializer`].
+//! It handles the `text/event-stream` wire format and writes keep-alive comments while the stream is idle, and the
+//! [`LastEventId`] header identifies where a reconnecting client left off.
+//!
+//! The [`headers`] module provides typed parsing of request headers such as `If-Match`, `Range`, and `Accept` for
+//! use in handlers and layers, along with a Conjure header decoder. Malformed headers are rejected with a consistent
+//! `Default:InvalidArgument` error identifying the header.
+//!
+//! Endpoints serving large binary blobs can support resumable downloads with the [`range`] module, which handles
+//! single-range `Range` and `If-Range` requests with `206 Partial Content` and `416 Range Not Satisfiable` responses.
+//!
+//! Endpoints accepting browser form submissions and file uploads can read `multipart/form-data` bodies with the
+//! [`multipart`] module, or [`blocking::multipart`] for blocking endpoints. Parts are streamed rather than buffered,
+//! and are subject to configurable size limits.
+//!
+//! If a Conjure endpoint's request body fails to deserialize, the `Default:InvalidArgument` error returned to the
+//! client includes a parameter for each offending field, keyed by its path in the body (for example
+//! `items[1].count`, or `.` for the body itself) with the reason it was rejected as the value.
+//!
+//! APIs making breaking changes to their payloads can register an implementation for each version with
+//! [`Witchcraft::versioned_api`], and requests are dispatched between them by their `Api-Version` header. See the
+//! [`versioning`] module for details.
+//!
+//! List endpoints can paginate their results consistently with the [`pagination`] module, which handles the
+//! `pageToken` and `pageSize` query parameters, enforces a maximum page size, and signs page tokens so clients cannot
+//! forge them.
+//!
+//! Handlers can mark the errors they return as retryable or non-retryable with the [`retry`] module, and the server
+//! translates that into `Retryable` and `Retry-After` response headers so client retry policies behave consistently.
+//!
+//! Responses to `GET` requests are not cacheable unless the handler sets a `Cache-Control` header. Endpoints can
+//! instead declare a cache policy when they are registered with [`Witchcraft::cache_policy`]; see the [`cache`] module
+//! for details.
+//!
+//! Response bodies are compressed according to the client's `Accept-Encoding` header. Only gzip is used by default, but
+//! brotli and zstd can be enabled by listing them in the runtime configuration's `compression.codings`. The minimum
+//! size of compressed responses and an allowlist of compressible content types can also be set in the `compression`
+//! section. The sizes recorded in the request log are those of the compressed bodies sent to the client. Request bodies
+//! with a `Content-Encoding` of `gzip` or `zstd` are decompressed before they reach endpoint handlers, and requests
+//! with any other coding are rejected with a `415 Unsupported Media Type` response. Requests which decompress to more
+//! than `compression.max-decompressed-request-size` bytes (100 MiB by default) are rejected with a
+//! `413 Request Entity Too Large` error.
+//!
+//! Requests declaring a `Content-Length` larger than the runtime configuration's `max-request-size` are rejected with a
+//! `413 Request Entity Too Large` error before they reach endpoint handlers. Bodies without a declared length are
+//! counted as they are read, and fail with the same error once they exceed the limit. Requests with an `Expect` header
+//! other than `100-continue` are rejected with `417 Expectation Failed`. A `100 Continue` response is only sent once
+//! the endpoint handler starts reading the request body, so clients which wait for it do not upload bodies that will
+//! be rejected.
+//!
+//! Response bodies can be limited in size through the runtime configuration's `response-limits` section, which guards
+//! against endpoints accidentally serializing unbounded amounts of data. `response-limits.max-size` applies to every
+//! endpoint, and can be overridden for individual endpoints in `response-limits.endpoints`, keyed by
+//! `<service-name>.<endpoint-name>`. A response whose size is known before it is sent and exceeds its limit is replaced
+//! with a `500 Internal Server Error`, while a streaming response is aborted as soon as it crosses the limit, which
+//! closes an HTTP/1 connection or resets an HTTP/2 stream. An error is logged in either case.
+//!
+//! For resilience testing, faults can be injected into requests to individual endpoints through the runtime
+//! configuration's `fault-injection` section. Once `fault-injection.enabled` is set, requests to the endpoints listed
+//! in `fault-injection.endpoints`, keyed by `<service-name>.<endpoint-name>`, are randomly delayed, rejected with a
+//! `503 Service Unavailable` error, or have their responses aborted at the configured rates.
+//!
+//! Async endpoints share the server's worker threads, so a handler which blocks within a single poll of its future
+//! stalls every other request scheduled on the same thread. Setting `slow-polls.enabled` in the runtime configuration
+//! times each poll of an endpoint's handler and response body, and logs a warning identifying the endpoint whenever
+//! one exceeds `slow-polls.threshold` (10 milliseconds by default).
+//!
+//! WebSocket endpoints can be registered with [`Witchcraft::websocket`]. Their handlers are given a typed [`WebSocket`]
+//! stream of messages, and open sockets are sent a close frame when the server shuts down. See the [`websocket`] module
+//! for details. WebSocket support requires the `websocket` cargo feature. Handlers for other protocols can take over
+//! the raw connection of HTTP/1.1 upgrade and HTTP/2 extended `CONNECT` requests via [`Witchcraft::upgrade`]; see the
+//! [`upgrade`] module for details.
+//!
+//! gRPC services, such as those generated by `tonic`, can be served alongside Conjure endpoints with
+//! [`Witchcraft::grpc`]. They share the server's port, TLS configuration, metrics, and trace propagation, but are
+//! routed at the root of the server rather than under its context path since gRPC clients don't support path prefixes.
+//! gRPC support requires the `grpc` cargo feature.
+//!
+//! Endpoints can be unit tested without starting a server with the [`testing`] module's [`TestServer`], which sends
+//! requests to them over an in-memory connection.
+//!
 //! [`Service`]: conjure_http::server::Service
+//! [`TestServer`]: testing::TestServer
+//! [`WebSocket`]: websocket::WebSocket
 //! [Conjure]: https://github.com/palantir/conjure
 //! [definition]: https://palantir.github.io/conjure/#/docs/spec/conjure_definitions
 //! [`conjure-codegen`]: https://docs.rs/conjure-codegen
@@ -129,10 +317,22 @@
 //! automatically update based on changes to the runtime configuration. See the documentation of the [`conjure_runtime`]
 //! crate for more details.
 //!
+//! Requests made with those clients while handling a request can be wrapped with the [`outbound`] module to report how
+//! many outbound requests each endpoint makes and how long it spends waiting on them.
+//!
 //! # Status endpoints
 //!
 //! The server exposes several "status" endpoints to report various aspects of the server.
 //!
+//! Responses from the readiness and health endpoints can be cached for a short time by setting
+//! `health-checks.cache-ttl` in the runtime configuration. Cached responses include an `Age` header reporting the
+//! number of seconds since the response was computed.
+//!
+//! Successful responses from those endpoints also include an `ETag` and a `Cache-Control: no-cache` header, so
+//! frequent pollers and intermediate proxies can revalidate with an `If-None-Match` header and receive an empty
+//! `304 Not Modified` response while the status is unchanged. This can be disabled by setting
+//! `health-checks.cache-validators` to `false` in the runtime configuration.
+//!
 //! ## Liveness
 //!
 //! The `/status/liveness` endpoint returns a successful response to all requests, indicating that the server is alive.
@@ -145,6 +345,9 @@
 //! returned by the [`Witchcraft::readiness_checks`] method. Any long-running initialization logic should happen
 //! asynchronously and use a readiness check to indicate completion.
 //!
+//! Both the readiness and health check registries can be cloned out of the [`Witchcraft`] context and used after the
+//! server has started, for example to register checks for a subsystem that is lazily initialized in the background.
+//!
 //! ## Health
 //!
 //! The `/status/health` endpoint returns a response indicating the server's overall health. Deployment infrastructure
@@ -157,9 +360,34 @@
 //! * `CONFIG_RELOAD` - Reports an error state if the runtime configuration failed to reload properly.
 //! * `ENDPOINT_FIVE_HUNDREDS` - Reports a warning if an endpoint has a high rate of `500 Internal Server Error`
 //!     responses.
+//! * `ENDPOINT_SLA` - Reports a warning if more than 1% of an endpoint's requests over the last 10 minutes took longer
+//!     than the p99 latency declared for it with [`Witchcraft::endpoint_sla`].
 //! * `SERVICE_DEPENDENCY` - Tracks the status of requests made with HTTP clients created via the server's client
 //!     factory, and reports a warning state of requests to a remote service have a high failure rate.
+//! * `DNS_RESOLUTION` - Periodically looks up the hostnames of remote services recently used by the server's clients,
+//!     and reports a warning if the most recent lookups of any of them failed. The clients resolve hostnames on their
+//!     own, so this only probes resolution and does not affect the addresses they use.
 //! * `PANICS` - Reports a warning if the server has panicked at any point.
+//! * `FILE_DESCRIPTORS` - Reports a warning if the server has stopped accepting new connections because its file
+//!     descriptor usage exceeded the `server.max-file-descriptor-utilization` limit in the install configuration.
+//! * `LOGGING` - Reports a warning if a log appender's queue has stayed more than half full for over 30 seconds,
+//!     including the size of each backlogged queue, since the appender will begin dropping logs if its queue fills.
+//! * `SLO_ERROR_BUDGET` - Reports a warning if an endpoint has exhausted the error budget of one of the service level
+//!     objectives configured in the `slos` section of the runtime configuration.
+//! * `OCSP_STAPLING` - Only registered if `tls.ocsp-stapling.enabled` is set in the install configuration. Reports a
+//!     warning if the OCSP response for the server's certificate can't be refreshed, and an error if no unexpired
+//!     response is available or the certificate has been revoked.
+//!
+//! Each check's recent results are retained and exposed by the `health.check.history.v1` diagnostic. If a check's
+//! state changes repeatedly over its recent history, it is considered to be flapping and its worst recent state is
+//! reported instead of its current state until it stabilizes.
+//!
+//! ## Cluster
+//!
+//! The `/status/cluster` endpoint returns the membership of the cluster the server belongs to, as reported by the
+//! [`cluster::ClusterInfoProvider`] installed via the [`Witchcraft::cluster_info_provider`] method. If no provider is
+//! installed, the endpoint returns a `404 Not Found` response. Like the health endpoint, requests to this endpoint must
+//! be authenticated with the `health-checks.shared-secret` bearer token in runtime configuration.
 //!
 //! # Diagnostics
 //!
@@ -169,11 +397,42 @@
 //! Several diagnostic types are defined:
 //!
 //! * `diagnostic.types.v1` - Returns a JSON-encoded list of all valid diagnostic types.
-//! * `rust.heap.status.v1` - Returns detailed statistics about the state of the heap. Requires the `jemalloc` feature
-//!     (enabled by default).
-//! * `metric.names.v1` - Returns a JSON-encoded list of the names of all metrics registered with the server.
+//! * `rust.heap.status.v1` - Returns detailed statistics about the state of the heap in the allocator's own text
+//!     format. Requires the `jemalloc` feature (enabled by default) or the `mimalloc` feature.
+//! * `rust.heap.diff.v1` - Returns a JSON-encoded summary of the bytes allocated in each size class and arena, along
+//!     with the change since the previous request for this diagnostic. Requires the `jemalloc` feature (enabled by
+//!     default).
+//! * `metric.names.v1` - Returns a JSON-encoded list of the names of all metrics registered with the server,
+//!     including their aliases.
+//! * `server.overload.v1` - Returns a JSON-encoded summary of the server's current overload indicators: active
+//!     connections and requests on each listener, connection and thread pool utilization including the number of
+//!     requests queued for blocking endpoints, the counts and rates of shed requests and connections, the 10
+//!     longest-running in-flight requests, and the memory usage and headroom below the memory admission high watermark.
+//! * `health.check.history.v1` - Returns a JSON-encoded map of the most recent results of each health check.
+//! * `cluster.info.v1` - Returns the JSON-encoded cluster membership served by the `/status/cluster` endpoint.
+//! * `service.dependency.graph.v1` - Returns a JSON-encoded description of the remote services the server has created
+//!     clients for, including their configured URIs and the recent request rate, error rate, latency, and health of
+//!     each host.
 //! * `rust.thread.dump.v1` - Returns a stack trace of every thread in the process. Only supported when running on
 //!     Linux.
+//! * `rust.thread.profile.v1` - Returns a wall-clock profile of every thread in the process over the last 5 minutes
+//!     in the gzip-compressed [pprof] format, built from stack samples taken every 10 seconds in the background. The
+//!     sampling rate and window are configured by the `profiling` section of the install configuration. Only
+//!     supported when running on Linux.
+//!
+//! [pprof]: https://github.com/google/pprof
+//!
+//! If `diagnostics.echo-enabled` is set in the runtime configuration, the `/debug/echo` endpoint accepts `GET` and
+//! `POST` requests authenticated with the same bearer token and responds with a JSON-encoded description of the
+//! request as the server saw it: its method, URI, and headers (with `Authorization` and `Cookie` values redacted), the
+//! client's IP address, the negotiated TLS protocol, cipher suite, ALPN protocol, and SNI server name, and the
+//! request's trace, span, and request IDs. This can be used to debug connectivity through proxies and load balancers.
+//!
+//! The `POST /debug/activate` endpoint, authenticated with the same bearer token, activates a server in standby mode.
+//!
+//! Like the JVM, the server responds to `SIGQUIT` by logging a diagnostic dump to the service log rather than
+//! terminating. The dump contains the number of active connections and in-flight requests on each listener and, on
+//! Linux, the `rust.thread.dump.v1` stack trace of every thread in the process.
 //!
 //! # Logging
 //!
@@ -182,8 +441,20 @@
 //! automatically rotated and compressed based on a non-configurable policy. If running in a Docker container or if the
 //! `use-console-log` setting is enabled in the install configuration, logs will instead be written to standard out.
 //!
+//! The service, request, and metric logs can instead be written through streaming zstd compression to a `.log.zst` file
+//! by setting the corresponding field of the `log-compression` section of the install configuration to `zstd`. The
+//! compressed stream is flushed along with the log, so tools like `zstdcat` can read everything written so far, and a
+//! new zstd frame is started after every 1 MiB of uncompressed output.
+//!
 //! [witchcraft-api spec]: https://github.com/palantir/witchcraft-api
 //!
+//! Every JSON log entry identifies the server instance that produced it with `instanceId` and `bootEpoch` values. The
+//! instance ID is generated the first time the server starts and persisted in `var/data/instance.json`, and the boot
+//! epoch counts the number of times the server has restarted since then. Entries with the same instance ID and
+//! different boot epochs were produced by restarts of the same deployment, while a new deployment has a new instance
+//! ID. The values are included in the `tags` of service, trace span, metric, and event logs, in the `params` of request
+//! logs, and in the `requestFields` of audit logs.
+//!
 //! ## Service
 //!
 //! The service log contains the messages emitted by invocations of the macros in the [`witchcraft_log`] crate. Messages
@@ -194,7 +465,15 @@
 //! ## Request
 //!
 //! The request log records an entry for each HTTP request processed by the server. Parameters marked marked as safe by
-//! an endpoint's Conjure definition will be included as parameters in the log record.
+//! an endpoint's Conjure definition will be included as parameters in the log record. If a [`geo::GeoLookup`] has been
+//! installed, the client's country and autonomous system number are included as the `country` and `asn` parameters.
+//! If a [`classification::RequestClassifier`] has been installed, the request's labels are included as the
+//! `requestClass` parameter.
+//! The negotiated TLS protocol version and cipher suite are included as the `tlsProtocol` and `tlsCipher` parameters,
+//! and if the client presented a certificate, its subject, subject common name, subject alternative names, and SHA-256
+//! fingerprint are included as the `clientCertificateSubject`, `clientCertificateCommonName`,
+//! `clientCertificateSubjectAltNames`, and `clientCertificateFingerprint` parameters. Handlers can access the same
+//! details, along with the rest of the certificate chain, through the [`tls::ClientCertificate`] request extension.
 //!
 //! ## Trace
 //!
@@ -206,12 +485,26 @@
 //!
 //! [Zipkin]: https://zipkin.io/
 //!
+//! ## Access
+//!
+//! If the `access-log.enabled` setting is enabled in the install configuration, the server will additionally write an
+//! `access.log` in the Apache Common/Combined Log Format for consumption by standard log tooling. Unlike the other
+//! logs, its entries are plain text rather than JSON. The format of each entry is controlled by the `access-log.format`
+//! setting, which defaults to the Combined Log Format. Note that the access log contains full request paths, query
+//! strings, and header values, and should be treated as unsafe.
+//!
 //! ## Metric
 //!
 //! The metric log contains the values of metrics reporting the state of various components of the server. Metrics are
 //! recorded every 30 seconds. Server logic can create additional metrics with the [`MetricRegistry`] returned by the
 //! [`Witchcraft::metrics`] method. See the documentation of the [`witchcraft_metrics`] crate for more details.
 //!
+//! A renamed application metric can continue to be reported under its old name by registering that name with
+//! [`Witchcraft::metric_alias`]. Aliases are reported with the same tags and values as the metric itself, are listed
+//! in the `metric.names.v1` diagnostic, and can be disabled by setting `server.metric-aliases` to `false` in the
+//! install configuration once dashboards and alerts have moved to the new name. This is a facility for applications
+//! only: none of the server's own metrics have been renamed, so it registers no aliases itself.
+//!
 //! # Metrics
 //!
 //! The server reports a variety of metrics by default:
@@ -219,10 +512,53 @@
 //! ## Thread Pool
 //!
 //! * `server.worker.max` (gauge) - The configured maximum size of the server's thread pool used for requests to
-//!     blocking endpoints.
+//!     blocking endpoints. The `thread-pool.min-threads` and `thread-pool.max-threads` values in the runtime
+//!     configuration override the corresponding `server` values in the install configuration and resize the pool
+//!     without a restart. The number of IO threads is fixed at startup.
 //! * `server.worker.active` (gauge) - The number of threads actively processing requests to blocking endpoints.
+//! * `server.worker.queued` (gauge) - The number of requests to blocking endpoints waiting for a thread.
 //! * `server.worker.utilization-max` (gauge) - `server.worker.active` divided by `server.worker.max`. If this is 1, the
 //!     server will immediately reject calls to blocking endpoints with a `503 Service Unavailable` status code.
+//! * `server.worker.expired` (meter) - The rate at which queued requests to blocking endpoints were rejected with a
+//!     `503 Service Unavailable` status code because their `X-Request-Timeout` deadline passed before a thread was
+//!     available. Queued requests are processed in earliest-deadline-first order.
+//!
+//! ## Memory Admission
+//!
+//! * `server.memory-admission.shedding` (gauge) - 1 if the server is rejecting low priority requests because its
+//!     memory usage exceeded the `memory-admission.high-watermark` in the runtime configuration, and 0 otherwise.
+//! * `server.memory-admission.shed` (meter) - The rate of requests rejected with a `503 Service Unavailable` status
+//!     code due to high memory usage. Only requests classified with [`classification::Priority::Low`] are rejected,
+//!     either by the installed [`classification::RequestClassifier`] or by trusted clients via the
+//!     [`classification::REQUEST_PRIORITY`] header.
+//!
+//! ## Standby
+//!
+//! * `server.standby` (gauge) - 1 if the server is in standby mode and rejecting requests to application endpoints
+//!     with a `503 Service Unavailable` status code, and 0 otherwise.
+//!
+//! ## Outbox
+//!
+//! * `server.outbox.published` (meter) - The rate of events published by the [`outbox`] dispatcher.
+//! * `server.outbox.failed` (meter) - The rate of failed attempts to publish an outbox event.
+//! * `server.outbox.lag` (timer) - The time between an outbox event's creation and its publication.
+//!
+//! ## Token Buckets
+//!
+//! * `server.token-bucket.available (name: <name>)` (gauge) - The number of tokens currently available in a
+//!     [`throttle::TokenBucket`].
+//! * `server.token-bucket.throttled (name: <name>)` (meter) - The rate of attempts to take tokens from the bucket which
+//!     were rejected because too few were available.
+//!
+//! ## Webhooks
+//!
+//! * `server.webhook.delivery (webhook: <name>)` (timer) - The duration of successful deliveries of events to a
+//!     [`webhook::Webhook`].
+//! * `server.webhook.delivered (webhook: <name>)` (meter) - The rate of events successfully delivered to the webhook.
+//! * `server.webhook.retried (webhook: <name>)` (meter) - The rate of failed deliveries to the webhook which were
+//!     retried.
+//! * `server.webhook.dead-lettered (webhook: <name>)` (meter) - The rate of events abandoned after exhausting their
+//!     delivery attempts and written to the service log.
 //!
 //! ## Logging
 //!
@@ -230,12 +566,19 @@
 //!
 //! ## Process
 //!
+//! The `jemalloc` and `mimalloc` features each replace the system allocator. If both are enabled, `mimalloc` takes
+//! precedence and jemalloc is not used, so the `mimalloc` feature can be enabled without disabling default features.
+//!
 //! * `process.heap` (gauge) - The total number of bytes allocated from the heap. Requires the `jemalloc` feature
 //!     (enabled by default).
 //! * `process.heap.active` (gauge) - The total number of bytes in active pages. Requires the `jemalloc` feature
-//!     (enabled by default).
-//! * `process.heap.resident` (gauge) - The total number of bytes in physically resident pages. Requires the `jemalloc` feature
-//!     (enabled by default).
+//!     (enabled by default) or the `mimalloc` feature, in which case it reports the number of committed bytes.
+//! * `process.heap.resident` (gauge) - The total number of bytes in physically resident pages. Requires the `jemalloc`
+//!     feature (enabled by default) or the `mimalloc` feature.
+//! * `process.heap.size-class (size-class: <size>)` (gauge) - The total number of bytes allocated in small allocations
+//!     of the given size class, or in large allocations if the size class is `large`. Comparing these with
+//!     `process.heap.active` can help identify sources of fragmentation. Requires the `jemalloc` feature (enabled by
+//!     default).
 //! * `process.uptime` (gauge) - The number of microseconds that have elapsed since the server started.
 //! * `process.panics` (counter) - The number of times the server has panicked.
 //! * `process.user-time` (gauge) - The number of microseconds the process has spent running in user-space.
@@ -254,16 +597,67 @@
 //! * `server.connection.active` (counter) - The number of TCP sockets currently connected to the HTTP server.
 //! * `server.connection.utilization` (gauge) - `server.connection.active` divided by the maximum number of connections
 //!     the server will accept.
+//! * `server.connection.accepted (listener: <listener>, address: <address>)` (meter) - The rate of TCP connections
+//!     accepted on each of the server's bind addresses.
+//! * `server.connection.filtered (listener: <listener>)` (meter) - The rate of TCP connections closed before the TLS
+//!     handshake because the client's address is not permitted by the `ip-filter` field of the runtime configuration.
+//! * `server.connection.plaintext (listener: <listener>)` (meter) - The rate of TCP connections which sent a plaintext
+//!     HTTP request rather than starting a TLS handshake. These clients are sent a `400 Bad Request` response
+//!     explaining that TLS is required.
+//! * `server.connection.fd-exhausted` (gauge) - 1 if the server has stopped accepting connections because
+//!     `process.filedescriptor` exceeded the `server.max-file-descriptor-utilization` limit in the install
+//!     configuration, and 0 otherwise.
+//! * `server.connection.killed (reason: <reason>)` (meter) - The rate of connections closed because their client sent
+//!     or received data too slowly, by reason: `header-read-timeout` if the headers of an HTTP/1 request were not
+//!     received within `server.header-read-timeout`, `min-transfer-rate` if a request body was sent more slowly than
+//!     `server.min-request-body-rate`, or `write-timeout` if the client accepted no response data within
+//!     `server.response-write-timeout` in the install configuration.
+//! * `server.connection.drain (listener: <listener>)` (timer) - The time taken during graceful shutdown for the
+//!     listener's connections to close after HTTP/2 clients are sent a `GOAWAY` frame and HTTP/1 clients are sent
+//!     `Connection: close`.
+//! * `server.http-redirect.redirected` (meter) - The rate of requests to the `http-redirect-port` listener which were
+//!     redirected to the service port.
+//!
+//! ## DNS
+//!
+//! * `dns.lookup` (timer) - The time taken by the periodic lookups of the hostnames of remote services used by the
+//!     server's clients.
+//! * `dns.lookup.failure` (meter) - The rate of failed lookups, including those which timed out after 10 seconds.
 //!
 //! ## TLS
 //!
 //! * `tls.handshake (context: server, protocol: <protocol>, cipher: <cipher>)` (meter) - The rate of TLS handshakes
 //!     completed by the HTTP server.
+//! * `tls.handshake.kind (context: server, kind: <kind>)` (meter) - The rate of TLS handshakes completed by the HTTP
+//!     server which resumed a previous session (`resumed`) or negotiated a new one (`full`).
 //!
 //! ## Server
 //!
+//! * `server.instance.epoch (instance-id: <instance ID>)` (gauge) - The boot epoch of the server instance.
 //! * `server.request.active` (counter) - The number of requests being actively processed.
 //! * `server.request.unmatched` (meter) - The rate of `404 Not Found` responses returned by the server.
+//! * `server.request.country (country: <country>)` (meter) - The rate of requests made from each country, as
+//!     determined by the installed [`geo::GeoLookup`]. Only reported if a lookup has been installed.
+//! * `server.request.classified (<label>: <value>, ...)` (meter) - The rate of requests with each set of labels, as
+//!     determined by the installed [`classification::RequestClassifier`]. Only reported if a classifier has been
+//!     installed.
+//! * `server.request.priority (priority: <priority>)` (meter) - The rate of requests from trusted clients whose
+//!     priority was set to `low`, `normal`, or `critical` by the [`classification::REQUEST_PRIORITY`] header.
+//! * `server.request.priority.untrusted` (meter) - The rate of requests with a [`classification::REQUEST_PRIORITY`]
+//!     header which was ignored because the client is not in the `request-priority.trusted-networks` runtime
+//!     configuration.
+//! * `server.request.user-agent (agent: <agent>)` (meter) - The rate of requests made by each agent, as identified by
+//!     the first agent in the request's `User-Agent` header. At most 100 agents are tracked; requests from additional
+//!     agents are recorded with an agent of `other`, and requests without a parseable header with an agent of
+//!     `unknown`. Requests from agents listed in the `user-agents.blocked` field of the runtime configuration are
+//!     rejected with a `403 Forbidden` response.
+//! * `server.request.rejected (reason: <reason>)` (meter) - The rate of requests rejected with a `400 Bad Request`
+//!     because they are malformed, by reason: `malformed` for HTTP/1 requests which could not be parsed at all, and
+//!     `conflicting-framing`, `invalid-transfer-encoding`, or `invalid-header-value` for requests rejected by the
+//!     `server.strict-http-parsing` mode of the install configuration. Rejected requests are not recorded in the
+//!     request log or endpoint metrics.
+//! * `server.fault-injection.injected (fault: <fault>)` (meter) - The rate of faults injected into requests by the
+//!     `fault-injection` section of the runtime configuration, by fault: `latency`, `error`, or `reset`.
 //! * `server.response.all` (meter) - The rate of responses returned by the server.
 //! * `server.response.1xx` (meter) - The rate of `1xx` responses returned by the server.
 //! * `server.response.2xx` (meter) - The rate of `2xx` responses returned by the server.
@@ -271,6 +665,10 @@
 //! * `server.response.4xx` (meter) - The rate of `4xx` responses returned by the server.
 //! * `server.response.5xx` (meter) - The rate of `5xx` responses returned by the server.
 //! * `server.response.500` (meter) - The rate of `500 Internal Server Error` responses returned by the server.
+//! * `server.websocket.active` (counter) - The number of WebSocket connections being actively handled. Requires the
+//!     `websocket` feature.
+//! * `server.upgrade.active (protocol: <protocol>)` (counter) - The number of connections upgraded via an
+//!     [`upgrade::UpgradeHandler`] being actively handled.
 //!
 //! ## Endpoints
 //!
@@ -278,20 +676,50 @@
 //!     process each request to the endpoint, including sending the entire response body.
 //! * `server.response.error (service-name: <service_name>, endpoint: <endpoint>)` (meter) - The rate of `5xx` errors
 //!     returned for requests to the endpoint.
+//! * `server.response.cpu-time (service-name: <service_name>, endpoint: <endpoint>)` (histogram) - The number of
+//!     microseconds of CPU time consumed processing each request to the endpoint, including writing the response
+//!     body. Unlike `server.response`, this excludes time spent waiting on IO or other tasks. Only reported on Linux,
+//!     and only when enabled by the runtime configuration's `cpu-time.enabled` value.
+//! * `server.response.outbound-requests (service-name: <service_name>, endpoint: <endpoint>)` (histogram) - The number
+//!     of outbound requests tracked with the [`outbound`] module made while processing each request to the endpoint.
+//! * `server.response.outbound-time (service-name: <service_name>, endpoint: <endpoint>)` (histogram) - The number of
+//!     microseconds spent waiting on outbound requests tracked with the [`outbound`] module while processing each
+//!     request to the endpoint.
+//! * `server.response.retryability (service-name: <service_name>, endpoint: <endpoint>, retryable: <true|false>)`
+//!     (meter) - The rate of error responses from the endpoint which were marked retryable or non-retryable with the
+//!     [`retry`] module, or whose error kind implies a retryability.
+//! * `server.response.slow-poll (service-name: <service_name>, endpoint: <endpoint>)` (timer) - The duration of each
+//!     poll of the endpoint's handler or response body which exceeded the `slow-polls.threshold` runtime configuration
+//!     value. Only updated when `slow-polls.enabled` is set.
+//! * `server.response.too-large (service-name: <service_name>, endpoint: <endpoint>)` (meter) - The rate of responses
+//!     from the endpoint which were aborted because their bodies exceeded the limit set in the `response-limits`
+//!     section of the runtime configuration.
+//! * `server.slo.burn-rate (service-name: <service_name>, endpoint: <endpoint>, objective: <objective>)` (gauge) - The
+//!     rate at which the endpoint is consuming the error budget of its `availability` or `latency` objective over the
+//!     objective's window. A value above 1 indicates that the objective is not being met. Only reported for
+//!     objectives configured in the `slos` section of the runtime configuration.
+//! * `server.request.context-path-alias (context-path: <alias>, service-name: <service_name>, endpoint: <endpoint>)`
+//!     (meter) - The rate of requests to the endpoint made via one of the `context-path-aliases` in the install
+//!     configuration rather than the context path.
+//! * `server.request.api-version (service-name: <service_name>, endpoint: <endpoint>, api-version: <version>)`
+//!     (meter) - The rate of requests handled by each version of an endpoint registered with
+//!     [`Witchcraft::versioned_api`] or [`Witchcraft::blocking_versioned_api`].
 //!
 //! ## HTTP clients
 //!
 //! See the documentation of the [`conjure_runtime`] crate for the metrics reported by HTTP clients.
 #![warn(missing_docs)]
 
+use std::collections::HashMap;
 use std::env;
+use std::mem;
 use std::process;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use conjure_error::Error;
-use conjure_http::server::{AsyncService, ConjureRuntime};
+use conjure_http::server::AsyncService;
 use conjure_runtime::{Agent, ClientFactory, HostMetricsRegistry, UserAgent};
 use debug::endpoint::DebugResource;
 use debug::endpoint::DebugServiceEndpoints;
@@ -303,55 +731,129 @@ use status::StatusResource;
 use status::StatusServiceEndpoints;
 use tokio::runtime::{Handle, Runtime};
 use tokio::signal::unix::{self, SignalKind};
-use tokio::{pin, runtime, select, time};
-use witchcraft_log::{error, fatal, info};
+use tokio::{pin, runtime, select};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use witchcraft_log::{error, fatal, info, warn};
 use witchcraft_metrics::MetricRegistry;
 
-pub use body::{RequestBody, ResponseWriter};
+pub use body::{
+    BodyChunk, Event, EventChunk, EventStream, EventStreamResponseSerializer, LastEventId,
+    RequestBody, ResponseWriter, StreamingBody,
+};
 use config::install::InstallConfig;
 use config::runtime::RuntimeConfig;
+pub use shutdown_hooks::ShutdownPhase;
 pub use witchcraft::Witchcraft;
 #[doc(inline)]
 pub use witchcraft_server_config as config;
 #[doc(inline)]
 pub use witchcraft_server_macros::main;
 
+#[cfg(feature = "acme")]
+use crate::acme::Acme;
+use crate::acme::Challenges;
+use crate::announcement::{Announcement, DeregistrationReason};
+use crate::cache::CachePolicies;
+use crate::cluster::ClusterRegistry;
+use crate::debug::cluster_info::ClusterInfoDiagnostic;
+use crate::debug::dependency_graph::DependencyGraphDiagnostic;
 use crate::debug::diagnostic_types::DiagnosticTypesDiagnostic;
-#[cfg(feature = "jemalloc")]
+use crate::debug::health_check_history::HealthCheckHistoryDiagnostic;
+#[cfg(all(feature = "jemalloc", not(feature = "mimalloc")))]
+use crate::debug::heap_diff::HeapDiffDiagnostic;
+#[cfg(any(feature = "jemalloc", feature = "mimalloc"))]
 use crate::debug::heap_stats::HeapStatsDiagnostic;
 use crate::debug::metric_names::MetricNamesDiagnostic;
+use crate::debug::overload::OverloadDiagnostic;
+use crate::debug::signal_dump;
 #[cfg(all(
     target_os = "linux",
     any(target_arch = "x86_64", target_arch = "aarch64")
 ))]
 use crate::debug::thread_dump::ThreadDumpDiagnostic;
+#[cfg(target_os = "linux")]
+use crate::debug::thread_profile::ThreadProfileDiagnostic;
 use crate::debug::DiagnosticRegistry;
+use crate::deregistration::Deregistration;
+use crate::dns::DnsProbe;
+use crate::endpoint::validation;
+use crate::extensions::ShutdownSignal;
+use crate::file_descriptors::FileDescriptorMonitor;
 use crate::health::config_reload::ConfigReloadHealthCheck;
+use crate::health::dns::DnsHealthCheck;
 use crate::health::endpoint_500s::Endpoint500sHealthCheck;
+use crate::health::endpoint_sla::EndpointSlaHealthCheck;
+use crate::health::file_descriptors::FileDescriptorsHealthCheck;
+use crate::health::logging::LoggingHealthCheck;
 use crate::health::minidump::MinidumpHealthCheck;
+use crate::health::ocsp_stapling::OcspStaplingHealthCheck;
 use crate::health::panics::PanicsHealthCheck;
 use crate::health::service_dependency::ServiceDependencyHealthCheck;
+use crate::health::slo::SloHealthCheck;
 use crate::health::HealthCheckRegistry;
+use crate::jobs::endpoint::{JobsResource, JobsServiceEndpoints};
+use crate::memory_admission::MemoryAdmission;
+use crate::metric_aliases::MetricAliases;
+use crate::ocsp::OcspStapler;
 use crate::readiness::ReadinessCheckRegistry;
 use crate::server::Listener;
+use crate::service::in_flight::InFlightRequests;
 use crate::shutdown_hooks::ShutdownHooks;
+use crate::slo::SloRegistry;
+use crate::standby::Standby;
 
+mod acme;
+pub mod announcement;
 pub mod blocking;
 mod body;
+pub mod cache;
+pub mod classification;
+pub mod cluster;
 mod configs;
+pub mod cost;
 pub mod debug;
+mod deregistration;
+mod dns;
 mod endpoint;
 pub mod extensions;
+mod file_descriptors;
+pub mod geo;
+pub mod headers;
 pub mod health;
+mod http_redirect;
+mod instance;
+#[cfg(all(feature = "jemalloc", not(feature = "mimalloc")))]
+mod jemalloc;
+pub mod jobs;
 pub mod logging;
+mod memory_admission;
+mod metric_aliases;
 mod metrics;
 mod minidump;
+pub mod multipart;
+mod ocsp;
+pub mod outbound;
+pub mod outbox;
+pub mod pagination;
+mod preflight;
+pub mod range;
 pub mod readiness;
+pub mod retry;
 mod server;
 mod service;
 mod shutdown_hooks;
+mod slo;
+mod standby;
 mod status;
+pub mod testing;
+pub mod throttle;
 pub mod tls;
+pub mod upgrade;
+pub mod versioning;
+pub mod webhook;
+#[cfg(feature = "websocket")]
+pub mod websocket;
 mod witchcraft;
 
 /// Initializes a Witchcraft server.
@@ -416,6 +918,11 @@ where
 
     let install_config = load_install()?;
 
+    tls::crypto_provider::init(install_config.as_ref().tls())?;
+    acme::init(install_config.as_ref())?;
+    preflight::run(install_config.as_ref())?;
+    instance::init()?;
+
     let thread_id = AtomicUsize::new(0);
     let runtime = runtime::Builder::new_multi_thread()
         .enable_all()
@@ -435,9 +942,13 @@ where
     let runtime_config = load_runtime(&handle, &runtime_config_ok)?;
 
     let metrics = Arc::new(MetricRegistry::new());
+    let metric_aliases = Arc::new(MetricAliases::new(
+        install_config.as_ref().server().metric_aliases(),
+    ));
 
     let loggers = handle.block_on(logging::init(
         &metrics,
+        &metric_aliases,
         install_config.as_ref(),
         &runtime_config.map(|c| c.as_ref().logging().clone()),
         runtime.logger_shutdown.as_mut().unwrap(),
@@ -445,6 +956,9 @@ where
 
     info!("server starting");
 
+    #[cfg(all(feature = "jemalloc", not(feature = "mimalloc")))]
+    jemalloc::init(install_config.as_ref().jemalloc());
+
     let minidump_ok = Arc::new(AtomicBool::new(false));
     let minidump_ok_cloned = minidump_ok.clone();
     handle.spawn(minidump::init().then(|result| async move {
@@ -455,24 +969,84 @@ where
     }));
 
     metrics::init(&metrics);
+    instance::register_metric(&metrics);
 
     let host_metrics = Arc::new(HostMetricsRegistry::new());
 
     let health_checks = Arc::new(HealthCheckRegistry::new(&handle));
     health_checks.register(ServiceDependencyHealthCheck::new(&host_metrics));
+
+    let dns_probe = Arc::new(DnsProbe::new(&metrics, &host_metrics));
+    handle.spawn(DnsProbe::run(Arc::downgrade(&dns_probe)));
+    health_checks.register(DnsHealthCheck::new(&dns_probe));
     health_checks.register(PanicsHealthCheck::new());
+    health_checks.register(LoggingHealthCheck);
     health_checks.register(ConfigReloadHealthCheck::new(runtime_config_ok));
     health_checks.register(MinidumpHealthCheck::new(minidump_ok));
 
+    let slos = Arc::new(SloRegistry::new(
+        &metrics,
+        runtime_config.map(|c| c.as_ref().clone()),
+    ));
+    health_checks.register(SloHealthCheck::new(&slos));
+    health_checks.register(EndpointSlaHealthCheck::new(&slos));
+
+    let file_descriptors = FileDescriptorMonitor::new(install_config.as_ref(), &metrics);
+    handle.spawn(FileDescriptorMonitor::run(Arc::downgrade(
+        &file_descriptors,
+    )));
+    health_checks.register(FileDescriptorsHealthCheck::new(&file_descriptors));
+
+    let ocsp_stapler = OcspStapler::new(install_config.as_ref()).map(Arc::new);
+    if let Some(ocsp_stapler) = &ocsp_stapler {
+        handle.spawn(OcspStapler::run(Arc::downgrade(ocsp_stapler)));
+        health_checks.register(OcspStaplingHealthCheck::new(ocsp_stapler));
+    }
+
+    let memory_admission = MemoryAdmission::new(
+        &metrics,
+        runtime_config.map(|c| c.as_ref().memory_admission().clone()),
+    );
+    handle.spawn(MemoryAdmission::run(Arc::downgrade(&memory_admission)));
+
+    let standby = Standby::new(&metrics, runtime_config.map(|c| c.as_ref().standby()));
+
+    let in_flight = Arc::new(InFlightRequests::default());
+
     let readiness_checks = Arc::new(ReadinessCheckRegistry::new());
 
+    let cluster = Arc::new(ClusterRegistry::new());
+
     let diagnostics = Arc::new(DiagnosticRegistry::new());
-    diagnostics.register(MetricNamesDiagnostic::new(&metrics));
-    #[cfg(feature = "jemalloc")]
+    diagnostics.register(MetricNamesDiagnostic::new(&metrics, &metric_aliases));
+    diagnostics.register(OverloadDiagnostic::new(
+        &metrics,
+        &memory_admission,
+        &in_flight,
+    ));
+    diagnostics.register(HealthCheckHistoryDiagnostic::new(&health_checks));
+    diagnostics.register(ClusterInfoDiagnostic::new(&cluster));
+    diagnostics.register(DependencyGraphDiagnostic::new(
+        install_config.as_ref().product_name(),
+        runtime_config.map(|c| c.as_ref().service_discovery().clone()),
+        &host_metrics,
+    ));
+    #[cfg(any(feature = "jemalloc", feature = "mimalloc"))]
     diagnostics.register(HeapStatsDiagnostic);
+    #[cfg(all(feature = "jemalloc", not(feature = "mimalloc")))]
+    diagnostics.register(HeapDiffDiagnostic::new());
     #[cfg(target_os = "linux")]
     diagnostics.register(ThreadDumpDiagnostic);
+    #[cfg(target_os = "linux")]
+    if install_config.as_ref().profiling().enabled() {
+        diagnostics.register(ThreadProfileDiagnostic::new(
+            install_config.as_ref().profiling(),
+            &handle,
+        ));
+    }
     diagnostics.register(DiagnosticTypesDiagnostic::new(Arc::downgrade(&diagnostics)));
+    handle.spawn(signal_dump::run(metrics.clone()));
+
     let client_factory = ClientFactory::builder()
         .config(runtime_config.map(|c| c.as_ref().service_discovery().clone()))
         .user_agent(UserAgent::new(Agent::new(
@@ -492,15 +1066,53 @@ where
         handle: handle.clone(),
         install_config: install_config.as_ref().clone(),
         thread_pool: None,
+        thread_pool_config: runtime_config.map(|c| c.as_ref().thread_pool().clone()),
         endpoints: vec![],
+        versioned_endpoints: HashMap::new(),
+        cache_policies: CachePolicies::default(),
+        may_block: HashMap::new(),
         shutdown_hooks: ShutdownHooks::new(),
-        conjure_runtime: Arc::new(ConjureRuntime::new()),
+        startup_hooks: vec![],
+        deregister_hooks: vec![],
+        conjure_runtime: Arc::new(validation::runtime()),
+        geo_lookup: None,
+        request_classifier: None,
+        cost_ledger: None,
+        cluster: cluster.clone(),
+        upgrade_tasks: TaskTracker::new(),
+        extended_connect: false,
+        shutdown_signal: ShutdownSignal::new(),
+        slos: slos.clone(),
+        metric_aliases,
+        memory_admission,
+        in_flight,
+        standby: standby.clone(),
+        file_descriptors,
+        acme_challenges: Arc::new(Challenges::default()),
+        ocsp_stapler,
+        private_key_provider: None,
+        jobs: None,
+        job_store: None,
+        webhooks: None,
     };
 
+    witchcraft.on_shutdown_phase(ShutdownPhase::StopAccepting, "shutdown-signal", {
+        let shutdown_signal = witchcraft.shutdown_signal.clone();
+        async move { shutdown_signal.trigger() }
+    });
+    witchcraft.on_shutdown_phase(ShutdownPhase::DrainRequests, "upgraded-connections", {
+        let upgrade_tasks = witchcraft.upgrade_tasks.clone();
+        async move {
+            upgrade_tasks.close();
+            upgrade_tasks.wait().await;
+        }
+    });
+
     let status_endpoints = StatusServiceEndpoints::new(StatusResource::new(
         &runtime_config,
         &witchcraft.health_checks,
         &witchcraft.readiness_checks,
+        &cluster,
     ));
     witchcraft.endpoints(
         None,
@@ -509,36 +1121,131 @@ where
     );
 
     let debug_endpoints =
-        DebugServiceEndpoints::new(DebugResource::new(&runtime_config, &diagnostics));
+        DebugServiceEndpoints::new(DebugResource::new(&runtime_config, &diagnostics, &standby));
     witchcraft.app(debug_endpoints);
 
     // server::start clears out the previously-registered endpoints so the existing Witchcraft
     // is ready to reuse for the main port afterwards.
+    // The user's init function takes ownership of the runtime config, so retain a copy for the server's own layers.
+    let server_runtime_config = runtime_config.map(|c| c.as_ref().clone());
+
+    let mut management_addr = None;
     if let Some(management_port) = install_config.as_ref().management_port() {
-        if management_port != install_config.as_ref().port() {
-            handle.block_on(server::start(
+        // An ephemeral management port is always distinct from the service port.
+        if management_port == 0 || management_port != install_config.as_ref().port() {
+            management_addr = Some(handle.block_on(server::start(
                 &mut witchcraft,
                 &loggers,
+                &server_runtime_config,
                 Listener::Management,
                 management_port,
-            ))?;
+            ))?);
         }
     }
 
     init(install_config, runtime_config, &mut witchcraft)?;
 
+    if let Some(jobs) = witchcraft.jobs.clone() {
+        witchcraft.api(JobsServiceEndpoints::new(JobsResource::new(&jobs)));
+        witchcraft.on_shutdown_phase(ShutdownPhase::StopBackgroundTasks, "jobs", async move {
+            jobs.shutdown().await
+        });
+    }
+    if let Some(webhooks) = witchcraft.webhooks.clone() {
+        witchcraft.on_shutdown_phase(ShutdownPhase::StopBackgroundTasks, "webhooks", async move {
+            webhooks.shutdown().await
+        });
+    }
+    if let Some(cost_ledger) = witchcraft.cost_ledger.clone() {
+        let shutdown = CancellationToken::new();
+        let task = handle.spawn(cost_ledger.run(shutdown.clone()));
+        witchcraft.on_shutdown_phase(
+            ShutdownPhase::StopBackgroundTasks,
+            "cost-accounting",
+            async move {
+                shutdown.cancel();
+                let _ = task.await;
+            },
+        );
+    }
+
+    for ((service_name, endpoint_name), installed) in &witchcraft.may_block {
+        if !installed {
+            warn!(
+                "endpoint marked as possibly blocking was never installed",
+                safe: {
+                    serviceName: service_name,
+                    endpointName: endpoint_name,
+                },
+            );
+        }
+    }
+
     witchcraft
         .health_checks
         .register(Endpoint500sHealthCheck::new(&witchcraft.endpoints));
 
     let port = witchcraft.install_config.port();
-    handle.block_on(server::start(
+    let local_addr = handle.block_on(server::start(
         &mut witchcraft,
         &loggers,
+        &server_runtime_config,
         Listener::Service,
         port,
     ))?;
 
+    if let Some(http_redirect_port) = witchcraft.install_config.http_redirect_port() {
+        handle.block_on(http_redirect::start(&mut witchcraft, http_redirect_port))?;
+    }
+
+    info!(
+        "server started",
+        safe: {
+            port: local_addr.port(),
+        },
+    );
+
+    let announcement = Announcement {
+        service_addr: local_addr,
+        management_addr: management_addr.unwrap_or(local_addr),
+    };
+    {
+        // Allow the hooks to spawn tasks, e.g. to register with a service discovery system.
+        let _guard = handle.enter();
+        for hook in mem::take(&mut witchcraft.startup_hooks) {
+            hook(announcement);
+        }
+    }
+
+    // Certificates are requested once the server is listening so that it can respond to the challenges.
+    #[cfg(feature = "acme")]
+    if let Some(acme) = Acme::new(&witchcraft.install_config, &witchcraft.acme_challenges) {
+        let shutdown = CancellationToken::new();
+        let task = handle.spawn(acme.run(shutdown.clone()));
+        witchcraft.on_shutdown_phase(ShutdownPhase::StopBackgroundTasks, "acme", async move {
+            shutdown.cancel();
+            let _ = task.await;
+        });
+    }
+
+    let deregister_hooks = mem::take(&mut witchcraft.deregister_hooks);
+    if !deregister_hooks.is_empty() {
+        let deregistration = Deregistration::new(deregister_hooks);
+        handle.spawn(Deregistration::monitor(
+            Arc::downgrade(&deregistration),
+            witchcraft.health_checks.clone(),
+            witchcraft
+                .install_config
+                .server()
+                .unhealthy_deregistration_delay(),
+        ));
+        witchcraft.on_shutdown_phase(ShutdownPhase::StopAccepting, "deregistration", async move {
+            deregistration
+                .deregister(DeregistrationReason::Shutdown)
+                .await
+        });
+    }
+
     handle.block_on(shutdown(
         witchcraft.shutdown_hooks,
         witchcraft.install_config.server().shutdown_timeout(),
@@ -554,16 +1261,8 @@ async fn shutdown(shutdown_hooks: ShutdownHooks, timeout: Duration) -> Result<()
     info!("server shutting down");
 
     select! {
-        _ = shutdown_hooks => {}
+        _ = shutdown_hooks.run(Some(timeout)) => {}
         _ = signals.next() => info!("graceful shutdown interrupted by signal"),
-        _ = time::sleep(timeout) => {
-            info!(
-                "graceful shutdown timed out",
-                safe: {
-                    timeout: format_args!("{timeout:?}"),
-                },
-            );
-        }
     }
 
     Ok(())
@@ -588,7 +1287,7 @@ struct RuntimeGuard {
 impl Drop for RuntimeGuard {
     fn drop(&mut self) {
         let runtime = self.runtime.take().unwrap();
-        runtime.block_on(self.logger_shutdown.take().unwrap());
+        runtime.block_on(self.logger_shutdown.take().unwrap().run(None));
         runtime.shutdown_background()
     }
 }
@@ -0,0 +1,287 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::logging::format::{LogFormat, StandardReporter};
+use conjure_error::Error;
+use conjure_object::{DateTime, Utc};
+use http::{HeaderMap, HeaderName};
+use std::fmt::{self, Write};
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// A single pre-rendered line of the access log.
+pub struct AccessLog(String);
+
+impl fmt::Display for AccessLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl LogFormat for AccessLog {
+    const TYPE: &'static str = "access";
+    const FILE_STEM: &'static str = "access";
+    const SIZE_LIMIT_GB: u32 = 5;
+    const TIME_LIMIT_DAYS: u32 = 30;
+
+    type Reporter = StandardReporter<Self>;
+}
+
+/// The information about a request used to render an access log entry.
+pub struct AccessLogEntry {
+    pub remote_addr: Option<IpAddr>,
+    pub user: Option<String>,
+    pub time: DateTime<Utc>,
+    pub method: String,
+    pub path: String,
+    pub query: Option<String>,
+    pub protocol: String,
+    pub headers: HeaderMap,
+    pub status: u16,
+    pub response_size: i64,
+    pub duration: Duration,
+}
+
+enum Directive {
+    Literal(String),
+    RemoteHost,
+    RemoteLogname,
+    RemoteUser,
+    Time,
+    RequestLine,
+    Status,
+    ResponseSizeClf,
+    ResponseSize,
+    DurationMicros,
+    DurationSeconds,
+    Method,
+    Path,
+    Query,
+    Protocol,
+    RequestHeader(HeaderName),
+}
+
+/// A parsed access log template.
+pub struct AccessLogFormat {
+    directives: Vec<Directive>,
+}
+
+impl AccessLogFormat {
+    pub fn parse(template: &str) -> Result<Self, Error> {
+        let mut directives = vec![];
+        let mut literal = String::new();
+        let mut chars = template.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                literal.push(c);
+                continue;
+            }
+
+            let directive = match chars.next() {
+                Some('%') => {
+                    literal.push('%');
+                    continue;
+                }
+                Some('h') => Directive::RemoteHost,
+                Some('l') => Directive::RemoteLogname,
+                Some('u') => Directive::RemoteUser,
+                Some('t') => Directive::Time,
+                Some('r') => Directive::RequestLine,
+                Some('s') => Directive::Status,
+                // `%>s` is the final status of an internally redirected request, which is the only status we have.
+                Some('>') => {
+                    let c = chars.next();
+                    if c != Some('s') {
+                        return Err(Error::internal_safe("unsupported access log directive")
+                            .with_safe_param(
+                                "directive",
+                                Some('>').into_iter().chain(c).collect::<String>(),
+                            ));
+                    }
+                    Directive::Status
+                }
+                Some('b') => Directive::ResponseSizeClf,
+                Some('B') => Directive::ResponseSize,
+                Some('D') => Directive::DurationMicros,
+                Some('T') => Directive::DurationSeconds,
+                Some('m') => Directive::Method,
+                Some('U') => Directive::Path,
+                Some('q') => Directive::Query,
+                Some('H') => Directive::Protocol,
+                Some('{') => {
+                    let name = chars.by_ref().take_while(|c| *c != '}').collect::<String>();
+                    if chars.next() != Some('i') {
+                        return Err(Error::internal_safe("unsupported access log directive")
+                            .with_safe_param("directive", format!("%{{{name}}}")));
+                    }
+                    let name = HeaderName::try_from(name).map_err(|e| {
+                        Error::internal_safe(e).with_safe_param("template", template)
+                    })?;
+                    Directive::RequestHeader(name)
+                }
+                c => {
+                    return Err(Error::internal_safe("unsupported access log directive")
+                        .with_safe_param("directive", c.map(String::from)))
+                }
+            };
+
+            if !literal.is_empty() {
+                directives.push(Directive::Literal(literal.split_off(0)));
+            }
+            directives.push(directive);
+        }
+
+        if !literal.is_empty() {
+            directives.push(Directive::Literal(literal));
+        }
+
+        Ok(AccessLogFormat { directives })
+    }
+
+    /// Returns the names of the request headers referenced by the template.
+    pub fn request_headers(&self) -> impl Iterator<Item = &HeaderName> {
+        self.directives.iter().filter_map(|d| match d {
+            Directive::RequestHeader(name) => Some(name),
+            _ => None,
+        })
+    }
+
+    pub fn render(&self, entry: &AccessLogEntry) -> AccessLog {
+        let mut out = String::new();
+
+        for directive in &self.directives {
+            match directive {
+                Directive::Literal(s) => out.push_str(s),
+                Directive::RemoteHost => match entry.remote_addr {
+                    Some(addr) => write!(out, "{addr}").unwrap(),
+                    None => out.push('-'),
+                },
+                Directive::RemoteLogname => out.push('-'),
+                Directive::RemoteUser => match &entry.user {
+                    Some(user) => escape(&mut out, user),
+                    None => out.push('-'),
+                },
+                Directive::Time => {
+                    write!(out, "{}", entry.time.format("[%d/%b/%Y:%H:%M:%S %z]")).unwrap()
+                }
+                Directive::RequestLine => {
+                    escape(&mut out, &entry.method);
+                    out.push(' ');
+                    escape(&mut out, &entry.path);
+                    if let Some(query) = &entry.query {
+                        out.push('?');
+                        escape(&mut out, query);
+                    }
+                    out.push(' ');
+                    out.push_str(&entry.protocol);
+                }
+                Directive::Status => write!(out, "{}", entry.status).unwrap(),
+                Directive::ResponseSizeClf if entry.response_size == 0 => out.push('-'),
+                Directive::ResponseSizeClf | Directive::ResponseSize => {
+                    write!(out, "{}", entry.response_size).unwrap()
+                }
+                Directive::DurationMicros => write!(out, "{}", entry.duration.as_micros()).unwrap(),
+                Directive::DurationSeconds => write!(out, "{}", entry.duration.as_secs()).unwrap(),
+                Directive::Method => escape(&mut out, &entry.method),
+                Directive::Path => escape(&mut out, &entry.path),
+                Directive::Query => {
+                    if let Some(query) = &entry.query {
+                        out.push('?');
+                        escape(&mut out, query);
+                    }
+                }
+                Directive::Protocol => out.push_str(&entry.protocol),
+                Directive::RequestHeader(name) => match entry.headers.get(name) {
+                    Some(value) => escape(&mut out, &String::from_utf8_lossy(value.as_bytes())),
+                    None => out.push('-'),
+                },
+            }
+        }
+
+        AccessLog(out)
+    }
+}
+
+// Escapes quotes, backslashes, and control characters in the same way as Apache so entries can't be forged.
+fn escape(out: &mut String, s: &str) {
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_ascii_control() => write!(out, "\\x{:02x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use conjure_object::chrono::TimeZone;
+    use http::HeaderValue;
+
+    fn entry() -> AccessLogEntry {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "User-Agent",
+            HeaderValue::from_static("curl/8.0 \"quoted\""),
+        );
+
+        AccessLogEntry {
+            remote_addr: Some("127.0.0.1".parse().unwrap()),
+            user: None,
+            time: Utc.with_ymd_and_hms(2000, 10, 10, 13, 55, 36).unwrap(),
+            method: "GET".to_string(),
+            path: "/api/foo".to_string(),
+            query: Some("a=b".to_string()),
+            protocol: "HTTP/1.1".to_string(),
+            headers,
+            status: 200,
+            response_size: 2326,
+            duration: Duration::from_millis(1500),
+        }
+    }
+
+    #[test]
+    fn combined_format() {
+        let format =
+            AccessLogFormat::parse(r#"%h %l %u %t "%r" %>s %b "%{Referer}i" "%{User-Agent}i""#)
+                .unwrap();
+
+        assert_eq!(
+            format.render(&entry()).to_string(),
+            r#"127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] "GET /api/foo?a=b HTTP/1.1" 200 2326 "-" "curl/8.0 \"quoted\"""#,
+        );
+    }
+
+    #[test]
+    fn custom_format() {
+        let format = AccessLogFormat::parse("%m %U%q %s %B %D %T 100%%").unwrap();
+
+        assert_eq!(
+            format.render(&entry()).to_string(),
+            "GET /api/foo?a=b 200 2326 1500000 1 100%",
+        );
+    }
+
+    #[test]
+    fn invalid_directive() {
+        assert!(AccessLogFormat::parse("%z").is_err());
+        assert!(AccessLogFormat::parse("%{Referer}o").is_err());
+        assert!(AccessLogFormat::parse("trailing %").is_err());
+        assert!(AccessLogFormat::parse("trailing %>").is_err());
+        assert!(AccessLogFormat::parse("%>%").is_err());
+        assert!(AccessLogFormat::parse("%>h").is_err());
+    }
+}
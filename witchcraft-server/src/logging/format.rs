@@ -11,12 +11,15 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use crate::instance::{Instance, BOOT_EPOCH_KEY, INSTANCE_ID_KEY};
 use crate::logging::api::{
+    audit_log_v3, event_log_v2, metric_log_v1, request_log_v2, service_log_v1, span, trace_log_v1,
     AuditLogV3, EventLogV2, LogLevel, MetricLogV1, RequestLogV2, ServiceLogV1, TraceLogV1,
 };
 use std::marker::PhantomData;
 use std::sync::Arc;
 use witchcraft_metrics::{Meter, MetricId, MetricRegistry};
+use witchcraft_server_config::install::{LogCompression, LogCompressionConfig};
 
 pub trait LogFormat: Sized {
     const TYPE: &'static str;
@@ -25,6 +28,16 @@ pub trait LogFormat: Sized {
     const TIME_LIMIT_DAYS: u32;
 
     type Reporter: ReportLog<Self>;
+
+    /// Returns the compression used when writing the log to a file.
+    fn compression(_: &LogCompressionConfig) -> LogCompression {
+        LogCompression::None
+    }
+
+    /// Attaches the server's instance ID and boot epoch to the log.
+    fn with_instance(self, _: &Instance) -> Self {
+        self
+    }
 }
 
 pub trait ReportLog<T> {
@@ -40,6 +53,17 @@ impl LogFormat for MetricLogV1 {
     const TIME_LIMIT_DAYS: u32 = 5;
 
     type Reporter = StandardReporter<Self>;
+
+    fn compression(config: &LogCompressionConfig) -> LogCompression {
+        config.metric()
+    }
+
+    fn with_instance(self, instance: &Instance) -> Self {
+        metric_log_v1::Builder::from(self)
+            .insert_tags(INSTANCE_ID_KEY, instance.instance_id())
+            .insert_tags(BOOT_EPOCH_KEY, instance.boot_epoch().to_string())
+            .build()
+    }
 }
 
 impl LogFormat for RequestLogV2 {
@@ -49,6 +73,17 @@ impl LogFormat for RequestLogV2 {
     const TIME_LIMIT_DAYS: u32 = 30;
 
     type Reporter = StandardReporter<Self>;
+
+    fn compression(config: &LogCompressionConfig) -> LogCompression {
+        config.request()
+    }
+
+    fn with_instance(self, instance: &Instance) -> Self {
+        request_log_v2::Builder::from(self)
+            .insert_params(INSTANCE_ID_KEY, instance.instance_id())
+            .insert_params(BOOT_EPOCH_KEY, instance.boot_epoch())
+            .build()
+    }
 }
 
 impl LogFormat for ServiceLogV1 {
@@ -58,6 +93,17 @@ impl LogFormat for ServiceLogV1 {
     const TIME_LIMIT_DAYS: u32 = 30;
 
     type Reporter = ServiceLogReporter;
+
+    fn compression(config: &LogCompressionConfig) -> LogCompression {
+        config.service()
+    }
+
+    fn with_instance(self, instance: &Instance) -> Self {
+        service_log_v1::Builder::from(self)
+            .insert_tags(INSTANCE_ID_KEY, instance.instance_id())
+            .insert_tags(BOOT_EPOCH_KEY, instance.boot_epoch().to_string())
+            .build()
+    }
 }
 
 impl LogFormat for TraceLogV1 {
@@ -67,6 +113,14 @@ impl LogFormat for TraceLogV1 {
     const TIME_LIMIT_DAYS: u32 = 5;
 
     type Reporter = StandardReporter<Self>;
+
+    fn with_instance(self, instance: &Instance) -> Self {
+        let span = span::Builder::from(self.span().clone())
+            .insert_tags(INSTANCE_ID_KEY, instance.instance_id())
+            .insert_tags(BOOT_EPOCH_KEY, instance.boot_epoch().to_string())
+            .build();
+        trace_log_v1::Builder::from(self).span(span).build()
+    }
 }
 
 impl LogFormat for AuditLogV3 {
@@ -79,6 +133,13 @@ impl LogFormat for AuditLogV3 {
     const TIME_LIMIT_DAYS: u32 = 30;
 
     type Reporter = StandardReporter<Self>;
+
+    fn with_instance(self, instance: &Instance) -> Self {
+        audit_log_v3::Builder::from(self)
+            .insert_request_fields(INSTANCE_ID_KEY, instance.instance_id())
+            .insert_request_fields(BOOT_EPOCH_KEY, instance.boot_epoch())
+            .build()
+    }
 }
 
 impl LogFormat for EventLogV2 {
@@ -88,6 +149,13 @@ impl LogFormat for EventLogV2 {
     const TIME_LIMIT_DAYS: u32 = 30;
 
     type Reporter = StandardReporter<Self>;
+
+    fn with_instance(self, instance: &Instance) -> Self {
+        event_log_v2::Builder::from(self)
+            .insert_tags(INSTANCE_ID_KEY, instance.instance_id())
+            .insert_tags(BOOT_EPOCH_KEY, instance.boot_epoch().to_string())
+            .build()
+    }
 }
 
 pub struct ServiceLogReporter {
@@ -13,7 +13,7 @@
 // limitations under the License.
 use crate::logging::format::LogFormat;
 use crate::logging::logger::Payload;
-use crate::shutdown_hooks::ShutdownHooks;
+use crate::shutdown_hooks::{ShutdownHooks, ShutdownPhase};
 use core::fmt;
 use futures_sink::Sink;
 use futures_util::ready;
@@ -23,8 +23,9 @@ use std::collections::VecDeque;
 use std::error;
 use std::future::Future;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 use std::task::{Context, Poll, Waker};
+use std::time::Instant;
 use tokio::task::{self, JoinHandle};
 use witchcraft_metrics::{MetricId, MetricRegistry};
 
@@ -40,6 +41,49 @@ impl fmt::Display for Closed {
 impl error::Error for Closed {}
 
 const QUEUE_LIMIT: usize = 10_000;
+// The queue length at which an appender is considered to be falling behind.
+const HIGH_WATERMARK: usize = QUEUE_LIMIT / 2;
+
+static QUEUES: Mutex<Vec<Weak<dyn QueueMonitor>>> = Mutex::new(vec![]);
+
+/// A snapshot of the state of an appender's queue.
+pub struct QueueStats {
+    pub type_: &'static str,
+    pub len: usize,
+    pub limit: usize,
+    /// The time at which the queue most recently rose above its high watermark, if it has not since drained below it.
+    pub backlogged_since: Option<Instant>,
+}
+
+/// Returns the current state of the queues of all live appenders.
+pub fn queue_stats() -> Vec<QueueStats> {
+    let mut queues = QUEUES.lock();
+    queues.retain(|queue| queue.strong_count() > 0);
+    queues
+        .iter()
+        .filter_map(|queue| queue.upgrade())
+        .map(|queue| queue.stats())
+        .collect()
+}
+
+trait QueueMonitor: Sync + Send {
+    fn stats(&self) -> QueueStats;
+}
+
+impl<T> QueueMonitor for Mutex<State<T>>
+where
+    T: LogFormat + Send,
+{
+    fn stats(&self) -> QueueStats {
+        let state = self.lock();
+        QueueStats {
+            type_: T::TYPE,
+            len: state.queue.len(),
+            limit: QUEUE_LIMIT,
+            backlogged_since: state.backlogged_since,
+        }
+    }
+}
 
 struct State<T> {
     queue: VecDeque<Payload<T>>,
@@ -47,6 +91,7 @@ struct State<T> {
     read_waker: Option<Waker>,
     flushed: bool,
     closed: bool,
+    backlogged_since: Option<Instant>,
 }
 
 impl<T> State<T> {
@@ -59,11 +104,22 @@ impl<T> State<T> {
 
         self.queue.push_back(item);
         self.flushed = false;
+        if self.queue.len() >= HIGH_WATERMARK && self.backlogged_since.is_none() {
+            self.backlogged_since = Some(Instant::now());
+        }
         if let Some(waker) = self.read_waker.take() {
             waker.wake();
         }
     }
 
+    fn pop_front(&mut self) -> Option<Payload<T>> {
+        let value = self.queue.pop_front();
+        if self.queue.len() < HIGH_WATERMARK {
+            self.backlogged_since = None;
+        }
+        value
+    }
+
     fn start_close(&mut self) {
         if self.closed {
             return;
@@ -102,8 +158,13 @@ impl<T> AsyncAppender<T> {
             read_waker: None,
             flushed: true,
             closed: false,
+            backlogged_since: None,
         }));
 
+        QUEUES
+            .lock()
+            .push(Arc::downgrade(&state) as Weak<dyn QueueMonitor>);
+
         metrics.gauge(MetricId::new("logging.queue").with_tag("type", T::TYPE), {
             let state = state.clone();
             move || state.lock().queue.len()
@@ -114,10 +175,14 @@ impl<T> AsyncAppender<T> {
             WorkerFuture { state, inner }
         });
 
-        hooks.push(ShutdownFuture {
-            state: state.clone(),
-            handle,
-        });
+        hooks.push(
+            ShutdownPhase::FlushLogs,
+            T::TYPE,
+            ShutdownFuture {
+                state: state.clone(),
+                handle,
+            },
+        );
 
         AsyncAppender { state }
     }
@@ -240,7 +305,7 @@ where
 
             // Even though we've released the lock since seeing that the queue was not empty, we know that fact hasn't
             // changed since this task is the only thing that removes items from the queue.
-            let value = state.queue.pop_front().unwrap();
+            let value = state.pop_front().unwrap();
             if let Some(waker) = state.write_waker.take() {
                 waker.wake();
             }
@@ -12,28 +12,34 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 use crate::logging::format::LogFormat;
+use crate::logging::logger::instance::InstanceAppender;
 use crate::logging::logger::json::JsonAppender;
 use crate::logging::logger::metrics::MetricsAppender;
 use crate::logging::logger::r#async::AsyncAppender;
 use crate::logging::logger::rolling_file::RollingFileAppender;
 use crate::logging::logger::stdout::StdoutAppender;
+use crate::logging::logger::text::TextAppender;
 use crate::shutdown_hooks::ShutdownHooks;
 use bytes::Bytes;
 use conjure_error::Error;
 use futures_channel::oneshot;
 use futures_sink::Sink;
 use serde::Serialize;
+use std::fmt::Display;
 use std::io;
 use std::pin::Pin;
 use witchcraft_metrics::MetricRegistry;
-use witchcraft_server_config::install::InstallConfig;
+use witchcraft_server_config::install::{InstallConfig, LogCompression};
 
 pub mod r#async;
 mod byte_buffer;
+pub mod instance;
 pub mod json;
 pub mod metrics;
 pub mod rolling_file;
 pub mod stdout;
+pub mod text;
+mod zstd;
 
 pub type Appender<T> = AsyncAppender<T>;
 
@@ -51,19 +57,54 @@ where
     T: Serialize + LogFormat + 'static + Send,
     T::Reporter: 'static + Send,
 {
-    let appender: Pin<Box<dyn Sink<Payload<Bytes>, Error = io::Error> + Sync + Send>> = if config
-        .use_console_log()
-    {
-        Box::pin(StdoutAppender::new())
-    } else {
-        let appender =
-            RollingFileAppender::new(T::FILE_STEM, T::SIZE_LIMIT_GB, T::TIME_LIMIT_DAYS).await?;
-        Box::pin(appender)
-    };
+    let appender = JsonAppender::new(raw_appender::<T>(config).await?);
+    let appender = MetricsAppender::new(appender, metrics);
+    let appender = InstanceAppender::new(appender);
+    let appender = AsyncAppender::new(appender, metrics, hooks);
+
+    Ok(appender)
+}
 
-    let appender = JsonAppender::new(appender);
+/// Like [`appender`], but writes the [`Display`] representation of each value rather than its JSON representation.
+pub async fn text_appender<T>(
+    config: &InstallConfig,
+    metrics: &MetricRegistry,
+    hooks: &mut ShutdownHooks,
+) -> Result<Appender<T>, Error>
+where
+    T: Display + LogFormat + 'static + Send,
+    T::Reporter: 'static + Send,
+{
+    let appender = TextAppender::new(raw_appender::<T>(config).await?);
     let appender = MetricsAppender::new(appender, metrics);
     let appender = AsyncAppender::new(appender, metrics, hooks);
 
     Ok(appender)
 }
+
+async fn raw_appender<T>(
+    config: &InstallConfig,
+) -> Result<Pin<Box<dyn Sink<Payload<Bytes>, Error = io::Error> + Sync + Send>>, Error>
+where
+    T: LogFormat,
+{
+    let appender: Pin<Box<dyn Sink<Payload<Bytes>, Error = io::Error> + Sync + Send>> =
+        if config.use_console_log() {
+            Box::pin(StdoutAppender::new())
+        } else {
+            let zstd_level = match T::compression(config.log_compression()) {
+                LogCompression::Zstd => Some(config.log_compression().zstd_level()),
+                _ => None,
+            };
+            let appender = RollingFileAppender::new(
+                T::FILE_STEM,
+                T::SIZE_LIMIT_GB,
+                T::TIME_LIMIT_DAYS,
+                zstd_level,
+            )
+            .await?;
+            Box::pin(appender)
+        };
+
+    Ok(appender)
+}
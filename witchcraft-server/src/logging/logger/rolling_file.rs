@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 use crate::logging::logger::byte_buffer::BufBytesSink;
+use crate::logging::logger::zstd::ZstdBytesSink;
 use crate::logging::logger::Payload;
 use async_compression::tokio::write::GzipEncoder;
 use bytes::{Buf, Bytes};
@@ -33,13 +34,28 @@ use tokio::task;
 
 const MAX_LOG_SIZE: u64 = 1024 * 1024 * 1024;
 
+type FileSink = Pin<Box<dyn Sink<Bytes, Error = io::Error> + Sync + Send>>;
+
 struct CurrentFile {
-    sink: BufBytesSink<FileBytesSink>,
+    sink: BufBytesSink<FileSink>,
     len: u64,
     date: NaiveDate,
 }
 
 impl CurrentFile {
+    fn new(file: File, len: u64, zstd_level: Option<i32>) -> io::Result<Self> {
+        let sink: FileSink = match zstd_level {
+            Some(level) => Box::pin(ZstdBytesSink::new(FileBytesSink::new(file), level)?),
+            None => Box::pin(FileBytesSink::new(file)),
+        };
+
+        Ok(CurrentFile {
+            sink: BufBytesSink::new(sink),
+            len,
+            date: Utc::now().date_naive(),
+        })
+    }
+
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         Pin::new(&mut self.sink).poll_ready(cx)
     }
@@ -64,10 +80,15 @@ enum State {
     Rotating(Pin<Box<dyn Future<Output = io::Result<File>> + Sync + Send>>),
 }
 
+/// An appender writing to a log file in `var/log`, rotating it daily or once it grows too large.
+///
+/// Uncompressed logs are written to `<name>.log` and gzip-compressed when rotated. If a zstd level is provided, logs are
+/// instead written through zstd compression to `<name>.log.zst`, and archived as-is.
 pub struct RollingFileAppender {
     state: State,
     next_archive_index: u32,
     name: &'static str,
+    zstd_level: Option<i32>,
     max_archive_size: u64,
     max_archive_days: u32,
     archive_locator: Arc<ArchiveLocator>,
@@ -78,6 +99,7 @@ impl RollingFileAppender {
         name: &'static str,
         size_limit_gb: u32,
         max_archive_days: u32,
+        zstd_level: Option<i32>,
     ) -> Result<Self, Error> {
         let max_archive_size = u64::from(size_limit_gb) * 1024 * 1024 * 1024;
 
@@ -85,14 +107,11 @@ impl RollingFileAppender {
         fs::create_dir_all(&dir)
             .await
             .map_err(Error::internal_safe)?;
-        let file_path = log_path(dir, name);
-        let file = open_log(&file_path).await.map_err(Error::internal_safe)?;
-        let len = file.metadata().await.map_err(Error::internal_safe)?.len();
 
         let archive_locator = ArchiveLocator::new(name);
         let date = Utc::now().date_naive();
 
-        let next_archive_index = archive_locator
+        let mut next_archive_index = archive_locator
             .archived_logs(dir)
             .await
             .map_err(Error::internal_safe)?
@@ -109,6 +128,30 @@ impl RollingFileAppender {
             .max()
             .map_or(0, |n| n + 1);
 
+        // A zstd log left behind by a previous process may end in a partial frame which would make everything appended
+        // after it undecodable, so it's always archived rather than reused. Logs left behind in the other format are
+        // archived as well.
+        if zstd_level.is_some() {
+            archive_existing(
+                &log_path(dir, name),
+                &archive_path(dir, name, date, next_archive_index),
+                &mut next_archive_index,
+            )
+            .await
+            .map_err(Error::internal_safe)?;
+        }
+        archive_existing(
+            &zstd_log_path(dir, name),
+            &archive_zst_path(dir, name, date, next_archive_index),
+            &mut next_archive_index,
+        )
+        .await
+        .map_err(Error::internal_safe)?;
+
+        let file_path = active_log_path(dir, name, zstd_level);
+        let file = open_log(&file_path).await.map_err(Error::internal_safe)?;
+        let len = file.metadata().await.map_err(Error::internal_safe)?.len();
+
         clear_old_archives(
             dir,
             date,
@@ -127,13 +170,12 @@ impl RollingFileAppender {
             .map_err(Error::internal_safe)?;
 
         Ok(RollingFileAppender {
-            state: State::Live(CurrentFile {
-                sink: BufBytesSink::new(FileBytesSink::new(file)),
-                len,
-                date,
-            }),
+            state: State::Live(
+                CurrentFile::new(file, len, zstd_level).map_err(Error::internal_safe)?,
+            ),
             next_archive_index,
             name,
+            zstd_level,
             max_archive_size,
             max_archive_days,
             archive_locator: Arc::new(archive_locator),
@@ -166,6 +208,7 @@ impl Sink<Payload<Bytes>> for RollingFileAppender {
                     this.state = State::Rotating(Box::pin(rotate(
                         log_dir(),
                         this.name,
+                        this.zstd_level.is_some(),
                         file.date,
                         number,
                         this.max_archive_size,
@@ -173,21 +216,19 @@ impl Sink<Payload<Bytes>> for RollingFileAppender {
                         this.archive_locator.clone(),
                     )));
                 }
-                State::Rotating(future) => match ready!(future.as_mut().poll(cx)) {
-                    Ok(file) => {
-                        self.state = State::Live(CurrentFile {
-                            sink: BufBytesSink::new(FileBytesSink::new(file)),
-                            len: 0,
-                            date: Utc::now().date_naive(),
-                        });
-                    }
-                    Err(e) => {
-                        let path = log_path(log_dir(), this.name);
-                        this.state =
-                            State::Rotating(Box::pin(async move { open_log(&path).await }));
-                        return Poll::Ready(Err(e));
+                State::Rotating(future) => {
+                    match ready!(future.as_mut().poll(cx))
+                        .and_then(|file| CurrentFile::new(file, 0, this.zstd_level))
+                    {
+                        Ok(file) => this.state = State::Live(file),
+                        Err(e) => {
+                            let path = active_log_path(log_dir(), this.name, this.zstd_level);
+                            this.state =
+                                State::Rotating(Box::pin(async move { open_log(&path).await }));
+                            return Poll::Ready(Err(e));
+                        }
                     }
-                },
+                }
             }
         }
     }
@@ -233,6 +274,19 @@ fn log_path(dir: &Path, name: &str) -> PathBuf {
     path
 }
 
+fn zstd_log_path(dir: &Path, name: &str) -> PathBuf {
+    let mut path = dir.to_path_buf();
+    path.push(format!("{}.log.zst", name));
+    path
+}
+
+fn active_log_path(dir: &Path, name: &str, zstd_level: Option<i32>) -> PathBuf {
+    match zstd_level {
+        Some(_) => zstd_log_path(dir, name),
+        None => log_path(dir, name),
+    }
+}
+
 fn archive_path(dir: &Path, name: &str, date: NaiveDate, number: u32) -> PathBuf {
     let mut path = dir.to_path_buf();
     path.push(format!("{}-{}-{}.log", name, date, number));
@@ -251,6 +305,33 @@ fn archive_gz_path(dir: &Path, name: &str, date: NaiveDate, number: u32) -> Path
     path
 }
 
+fn archive_zst_path(dir: &Path, name: &str, date: NaiveDate, number: u32) -> PathBuf {
+    let mut path = dir.to_path_buf();
+    path.push(format!("{}-{}-{}.log.zst", name, date, number));
+    path
+}
+
+async fn archive_existing(
+    path: &Path,
+    archive_path: &Path,
+    next_archive_index: &mut u32,
+) -> io::Result<()> {
+    let len = match fs::metadata(path).await {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    if len == 0 {
+        return fs::remove_file(path).await;
+    }
+
+    fs::rename(path, archive_path).await?;
+    *next_archive_index += 1;
+
+    Ok(())
+}
+
 async fn clear_old_archives(
     dir: &Path,
     date: NaiveDate,
@@ -335,23 +416,34 @@ async fn compress(dir: &Path, name: &str, date: NaiveDate, number: u32) -> io::R
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn rotate(
     dir: &Path,
     name: &'static str,
+    zstd: bool,
     date: NaiveDate,
     number: u32,
     max_archive_size: u64,
     max_archive_days: u32,
     archive_locator: Arc<ArchiveLocator>,
 ) -> io::Result<File> {
-    let log_path = log_path(dir, name);
-    let tmp_path = archive_path(dir, name, date, number);
+    // zstd logs are already compressed, so they can be archived directly.
+    let (log_path, tmp_path) = if zstd {
+        (
+            zstd_log_path(dir, name),
+            archive_zst_path(dir, name, date, number),
+        )
+    } else {
+        (log_path(dir, name), archive_path(dir, name, date, number))
+    };
 
     fs::rename(&log_path, &tmp_path).await?;
 
     let dir = dir.to_path_buf();
     task::spawn(async move {
-        let _ = compress(&dir, name, date, number).await;
+        if !zstd {
+            let _ = compress(&dir, name, date, number).await;
+        }
         // clear archives based on the current date rather than the date of the log being archived.
         let _ = clear_old_archives(
             &dir,
@@ -370,6 +462,7 @@ struct ArchiveLocator {
     gz_regex: Regex,
     gz_tmp_regex: Regex,
     raw_regex: Regex,
+    zst_regex: Regex,
 }
 
 impl ArchiveLocator {
@@ -386,10 +479,15 @@ impl ArchiveLocator {
             r"^{}-(\d{{4}})-(\d{{2}})-(\d{{2}})-(\d+)\.log$",
             regex::escape(name)
         );
+        let zst_regex = format!(
+            r"^{}-(\d{{4}})-(\d{{2}})-(\d{{2}})-(\d+)\.log\.zst$",
+            regex::escape(name)
+        );
         ArchiveLocator {
             gz_regex: Regex::new(&gz_regex).unwrap(),
             gz_tmp_regex: Regex::new(&gz_tmp_regex).unwrap(),
             raw_regex: Regex::new(&raw_regex).unwrap(),
+            zst_regex: Regex::new(&zst_regex).unwrap(),
         }
     }
 
@@ -402,7 +500,9 @@ impl ArchiveLocator {
     }
 
     async fn archived_logs(&self, dir: &Path) -> io::Result<Vec<ArchivedLog>> {
-        self.get_logs(&self.gz_regex, dir).await
+        let mut logs = self.get_logs(&self.gz_regex, dir).await?;
+        logs.extend(self.get_logs(&self.zst_regex, dir).await?);
+        Ok(logs)
     }
 
     async fn get_logs(&self, regex: &Regex, dir: &Path) -> io::Result<Vec<ArchivedLog>> {
@@ -540,6 +640,47 @@ mod test {
         );
     }
 
+    #[test]
+    fn zstd_paths_format() {
+        let name = "service";
+        let date = NaiveDate::from_ymd_opt(2017, 4, 20).unwrap();
+        let number = 3;
+
+        assert_eq!(
+            zstd_log_path(log_dir(), name),
+            Path::new("var/log/service.log.zst"),
+        );
+        assert_eq!(
+            archive_zst_path(log_dir(), name, date, number),
+            Path::new("var/log/service-2017-04-20-3.log.zst"),
+        );
+    }
+
+    #[tokio::test]
+    async fn archive_existing_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let date = NaiveDate::from_ymd_opt(2017, 4, 20).unwrap();
+        let mut next_archive_index = 2;
+
+        let path = zstd_log_path(dir.path(), "service");
+        let archive = archive_zst_path(dir.path(), "service", date, next_archive_index);
+        fs::write(&path, b"hello").await.unwrap();
+
+        archive_existing(&path, &archive, &mut next_archive_index)
+            .await
+            .unwrap();
+        assert!(!path.exists());
+        assert_eq!(fs::read(&archive).await.unwrap(), b"hello");
+        assert_eq!(next_archive_index, 3);
+
+        fs::write(&path, b"").await.unwrap();
+        archive_existing(&path, &archive, &mut next_archive_index)
+            .await
+            .unwrap();
+        assert!(!path.exists());
+        assert_eq!(next_archive_index, 3);
+    }
+
     #[tokio::test]
     async fn compress_validity() {
         let dir = tempfile::tempdir().unwrap();
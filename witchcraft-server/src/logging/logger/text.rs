@@ -0,0 +1,67 @@
+// Copyright 2021 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::logging::logger::Payload;
+use bytes::{BufMut, Bytes, BytesMut};
+use futures_sink::Sink;
+use pin_project::pin_project;
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+#[pin_project]
+pub struct TextAppender<S> {
+    #[pin]
+    inner: S,
+    buf: BytesMut,
+}
+
+impl<S> TextAppender<S> {
+    pub fn new(inner: S) -> Self {
+        TextAppender {
+            inner,
+            buf: BytesMut::new(),
+        }
+    }
+}
+
+impl<T, S> Sink<Payload<T>> for TextAppender<S>
+where
+    T: Display,
+    S: Sink<Payload<Bytes>, Error = io::Error>,
+{
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, value: Payload<T>) -> io::Result<()> {
+        let this = self.project();
+        writeln!(this.buf.writer(), "{}", value.value)?;
+
+        this.inner.start_send(Payload {
+            value: this.buf.split().freeze(),
+            cb: value.cb,
+        })
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_close(cx)
+    }
+}
@@ -0,0 +1,225 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use bytes::Bytes;
+use futures_sink::Sink;
+use futures_util::ready;
+use pin_project::pin_project;
+use std::io;
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use zstd::stream::raw::{Encoder, InBuffer, Operation, OutBuffer};
+
+// Bounds the amount of data a reader has to decode from the start of a frame, and the amount lost if the file is
+// truncated mid-frame.
+const MAX_FRAME_SIZE: u64 = 1024 * 1024;
+
+const OUTPUT_CHUNK: usize = 8 * 1024;
+
+/// A sink which zstd-compresses the bytes written through it.
+///
+/// Each flush writes out all of the data received so far as complete zstd blocks, so a streaming decoder reading the
+/// file can decode everything up to the last flush. A frame is ended at the first flush after `MAX_FRAME_SIZE`
+/// uncompressed bytes, and when the sink is closed.
+#[pin_project]
+pub struct ZstdBytesSink<S> {
+    #[pin]
+    inner: S,
+    encoder: Encoder<'static>,
+    out: Vec<u8>,
+    frame_len: u64,
+    needs_flush: bool,
+}
+
+impl<S> ZstdBytesSink<S> {
+    pub fn new(inner: S, level: i32) -> io::Result<Self> {
+        Ok(ZstdBytesSink {
+            inner,
+            encoder: Encoder::new(level)?,
+            out: vec![],
+            frame_len: 0,
+            needs_flush: false,
+        })
+    }
+}
+
+impl<S> ZstdBytesSink<S>
+where
+    S: Sink<Bytes, Error = io::Error>,
+{
+    fn compress(self: Pin<&mut Self>, item: &[u8]) -> io::Result<()> {
+        let this = self.project();
+
+        let mut input = InBuffer::around(item);
+        while input.pos() < item.len() {
+            this.out.reserve(OUTPUT_CHUNK);
+            let pos = this.out.len();
+            this.encoder
+                .run(&mut input, &mut OutBuffer::around_pos(this.out, pos))?;
+        }
+
+        *this.frame_len += item.len() as u64;
+        *this.needs_flush = true;
+
+        Ok(())
+    }
+
+    fn sync(self: Pin<&mut Self>, end_frame: bool) -> io::Result<()> {
+        let this = self.project();
+        let pending = if end_frame {
+            *this.frame_len > 0
+        } else {
+            *this.needs_flush
+        };
+        if !pending {
+            return Ok(());
+        }
+
+        loop {
+            this.out.reserve(OUTPUT_CHUNK);
+            let pos = this.out.len();
+            let mut output = OutBuffer::around_pos(this.out, pos);
+            let remaining = if end_frame {
+                this.encoder.finish(&mut output, false)?
+            } else {
+                this.encoder.flush(&mut output)?
+            };
+            if remaining == 0 {
+                break;
+            }
+        }
+
+        if end_frame {
+            *this.frame_len = 0;
+        }
+        *this.needs_flush = false;
+
+        Ok(())
+    }
+
+    fn poll_write_out(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut this = self.project();
+        if this.out.is_empty() {
+            return Poll::Ready(Ok(()));
+        }
+
+        ready!(this.inner.as_mut().poll_ready(cx))?;
+        this.inner.start_send(Bytes::from(mem::take(this.out)))?;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S> Sink<Bytes> for ZstdBytesSink<S>
+where
+    S: Sink<Bytes, Error = io::Error>,
+{
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Bytes) -> io::Result<()> {
+        self.compress(&item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let end_frame = self.frame_len >= MAX_FRAME_SIZE;
+        self.as_mut().sync(end_frame)?;
+        ready!(self.as_mut().poll_write_out(cx))?;
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.as_mut().sync(true)?;
+        ready!(self.as_mut().poll_write_out(cx))?;
+        self.project().inner.poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures_util::SinkExt;
+
+    struct VecSink(Vec<u8>);
+
+    impl Sink<Bytes> for VecSink {
+        type Error = io::Error;
+
+        fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn start_send(mut self: Pin<&mut Self>, item: Bytes) -> io::Result<()> {
+            self.0.extend_from_slice(&item);
+            Ok(())
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn decode_available(buf: &[u8]) -> Vec<u8> {
+        let mut decoder = zstd::stream::raw::Decoder::new().unwrap();
+        let mut input = InBuffer::around(buf);
+        let mut out = vec![];
+        while input.pos() < buf.len() {
+            out.reserve(OUTPUT_CHUNK);
+            let pos = out.len();
+            decoder
+                .run(&mut input, &mut OutBuffer::around_pos(&mut out, pos))
+                .unwrap();
+        }
+        out
+    }
+
+    #[tokio::test]
+    async fn flush_is_decodable() {
+        let mut sink = ZstdBytesSink::new(VecSink(vec![]), 3).unwrap();
+
+        sink.send(Bytes::from_static(b"hello\n")).await.unwrap();
+        assert_eq!(decode_available(&sink.inner.0), b"hello\n");
+        // the frame is still open
+        assert!(zstd::decode_all(&*sink.inner.0).is_err());
+
+        sink.send(Bytes::from_static(b"world\n")).await.unwrap();
+        assert_eq!(decode_available(&sink.inner.0), b"hello\nworld\n");
+
+        sink.close().await.unwrap();
+        assert_eq!(zstd::decode_all(&*sink.inner.0).unwrap(), b"hello\nworld\n");
+    }
+
+    #[tokio::test]
+    async fn frames_end_at_size_limit() {
+        let mut sink = ZstdBytesSink::new(VecSink(vec![]), 3).unwrap();
+
+        let line = Bytes::from(vec![b'a'; MAX_FRAME_SIZE as usize]);
+        sink.send(line.clone()).await.unwrap();
+        let first_frame_len = sink.inner.0.len();
+        assert_eq!(zstd::decode_all(&*sink.inner.0).unwrap(), line);
+
+        sink.send(Bytes::from_static(b"b")).await.unwrap();
+        sink.close().await.unwrap();
+        let expected = [&line[..], b"b"].concat();
+        assert_eq!(zstd::decode_all(&*sink.inner.0).unwrap(), expected);
+        assert!(sink.inner.0.len() > first_frame_len);
+    }
+}
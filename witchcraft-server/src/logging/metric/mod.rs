@@ -15,6 +15,7 @@ use crate::logging::api::{metric_log_v1, MetricLogV1};
 use crate::logging::logger::r#async::Closed;
 use crate::logging::logger::{self, Appender, Payload};
 use crate::logging::metric::gauge_reporter::GaugeReporter;
+use crate::metric_aliases::MetricAliases;
 use crate::shutdown_hooks::ShutdownHooks;
 use conjure_error::Error;
 use conjure_object::Utc;
@@ -40,11 +41,12 @@ const NANOS_PER_MICRO_F64: f64 = NANOS_PER_MICRO as f64;
 
 pub async fn init(
     metrics: &Arc<MetricRegistry>,
+    aliases: &Arc<MetricAliases>,
     install: &InstallConfig,
     hooks: &mut ShutdownHooks,
 ) -> Result<(), Error> {
     let appender = logger::appender(install, metrics, hooks).await?;
-    task::spawn(log_metrics(appender, metrics.clone()));
+    task::spawn(log_metrics(appender, metrics.clone(), aliases.clone()));
 
     Ok(())
 }
@@ -60,7 +62,11 @@ pub async fn init(
 /// tasks. We collect and output the results of the gauges during the "idle" time when waiting for the next collection
 /// interval. This makes the implementation a bit more complex but avoids having to have multiple owners of the
 /// appender.
-async fn log_metrics(mut appender: Appender<MetricLogV1>, metrics: Arc<MetricRegistry>) {
+async fn log_metrics(
+    mut appender: Appender<MetricLogV1>,
+    metrics: Arc<MetricRegistry>,
+    aliases: Arc<MetricAliases>,
+) {
     let mut gauge_reporter = GaugeReporter::new();
 
     let mut next = Instant::now() + LOG_INTERVAL;
@@ -68,7 +74,7 @@ async fn log_metrics(mut appender: Appender<MetricLogV1>, metrics: Arc<MetricReg
     loop {
         idle(&mut gauge_reporter, &mut appender, next).await;
 
-        for (id, metric) in &metrics.metrics() {
+        for (id, metric) in &aliases.metrics(&metrics) {
             let builder = match metric {
                 Metric::Counter(m) => builder(id)
                     .metric_type("counter")
@@ -14,13 +14,16 @@
 
 //! Logging APIs
 use crate::extensions::AuditLogEntry;
+use crate::logging::access::{AccessLog, AccessLogFormat};
 use crate::logging::api::{AuditLogV3, EventLogV2, RequestLogV2};
+use crate::metric_aliases::MetricAliases;
 use crate::shutdown_hooks::ShutdownHooks;
 use conjure_error::Error;
 use conjure_serde::json;
 use futures::executor::block_on;
 use futures_channel::oneshot;
 use lazycell::AtomicLazyCell;
+pub(crate) use logger::r#async::{queue_stats, QueueStats};
 pub(crate) use logger::{Appender, Payload};
 use once_cell::sync::OnceCell;
 use refreshable::Refreshable;
@@ -36,6 +39,7 @@ use witchcraft_server_config::runtime::LoggingConfig;
 #[allow(warnings)]
 #[rustfmt::skip]
 pub mod api;
+pub(crate) mod access;
 mod cleanup;
 mod format;
 mod logger;
@@ -55,6 +59,12 @@ pub(crate) const SAMPLED_KEY: &str = "_sampled";
 pub(crate) struct Loggers {
     pub request_logger: Arc<Appender<RequestLogV2>>,
     pub audit_logger: Arc<Mutex<Appender<AuditLogV3>>>,
+    pub access_logger: Option<Arc<AccessLogger>>,
+}
+
+pub(crate) struct AccessLogger {
+    pub appender: Appender<AccessLog>,
+    pub format: AccessLogFormat,
 }
 
 pub(crate) fn early_init() {
@@ -63,11 +73,12 @@ pub(crate) fn early_init() {
 
 pub(crate) async fn init(
     metrics: &Arc<MetricRegistry>,
+    metric_aliases: &Arc<MetricAliases>,
     install: &InstallConfig,
     runtime: &Refreshable<LoggingConfig, Error>,
     hooks: &mut ShutdownHooks,
 ) -> Result<Loggers, Error> {
-    metric::init(metrics, install, hooks).await?;
+    metric::init(metrics, metric_aliases, install, hooks).await?;
     service::init(metrics, install, runtime, hooks).await?;
     trace::init(metrics, install, runtime, hooks).await?;
     let request_logger = logger::appender(install, metrics, hooks).await?;
@@ -75,6 +86,13 @@ pub(crate) async fn init(
     let audit_logger = logger::appender(install, metrics, hooks).await?;
     let audit_logger = Arc::new(Mutex::new(audit_logger));
     let event_logger = logger::appender(install, metrics, hooks).await?;
+    let access_logger = if install.access_log().enabled() {
+        let format = AccessLogFormat::parse(install.access_log().format())?;
+        let appender = logger::text_appender(install, metrics, hooks).await?;
+        Some(Arc::new(AccessLogger { appender, format }))
+    } else {
+        None
+    };
 
     AUDIT_LOGGER
         .fill(audit_logger.clone())
@@ -91,6 +109,7 @@ pub(crate) async fn init(
     Ok(Loggers {
         request_logger,
         audit_logger,
+        access_logger,
     })
 }
 
@@ -38,9 +38,12 @@ pub async fn init(
     let reporter = WitchcraftReporter { appender };
 
     let mut local_endpoint = zipkin::Endpoint::builder();
-    local_endpoint
-        .service_name(install.product_name())
-        .port(install.port());
+    local_endpoint.service_name(install.product_name());
+
+    // An ephemeral port isn't known until the server starts, so we omit it rather than reporting 0.
+    if install.port() != 0 {
+        local_endpoint.port(install.port());
+    }
 
     if let Some(ip) = ifaddrs::get_ip() {
         local_endpoint.ip(ip);
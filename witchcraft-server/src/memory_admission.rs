@@ -0,0 +1,285 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::extensions::Priority;
+use crate::metrics;
+use conjure_error::Error;
+use conjure_http::server::EndpointMetadata;
+use parking_lot::Mutex;
+use refreshable::Refreshable;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tokio::time;
+use witchcraft_log::{info, warn};
+use witchcraft_metrics::{Meter, MetricRegistry};
+use witchcraft_server_config::runtime::{MemoryAdmissionConfig, MemorySource};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Tracks the process's memory usage and determines when low priority requests should be shed to avoid running out
+/// of memory.
+///
+/// Shedding begins when usage rises above the configured high watermark and ends once it falls below the low
+/// watermark, so the server doesn't flap between states while usage hovers around a single threshold.
+pub struct MemoryAdmission {
+    config: Refreshable<MemoryAdmissionConfig, Error>,
+    shedding: AtomicBool,
+    usage: Mutex<Option<u64>>,
+    shed: Arc<Meter>,
+}
+
+impl MemoryAdmission {
+    pub fn new(
+        metrics: &MetricRegistry,
+        config: Refreshable<MemoryAdmissionConfig, Error>,
+    ) -> Arc<Self> {
+        let admission = Arc::new(MemoryAdmission {
+            config,
+            shedding: AtomicBool::new(false),
+            usage: Mutex::new(None),
+            shed: metrics.meter("server.memory-admission.shed"),
+        });
+
+        metrics.gauge("server.memory-admission.shedding", {
+            let admission = Arc::downgrade(&admission);
+            move || {
+                admission
+                    .upgrade()
+                    .is_some_and(|a| a.shedding.load(Ordering::Relaxed)) as i64
+            }
+        });
+
+        admission
+    }
+
+    /// Periodically samples the process's memory usage until the admission controller is dropped.
+    pub async fn run(admission: Weak<MemoryAdmission>) {
+        let mut interval = time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            let Some(admission) = admission.upgrade() else {
+                break;
+            };
+
+            let config = admission.config.get();
+            if config.high_watermark().is_none() {
+                admission.update(&config, None);
+                continue;
+            }
+
+            let usage = match config.source() {
+                MemorySource::Resident => metrics::resident_bytes(),
+                MemorySource::Heap => metrics::heap_active_bytes(),
+                _ => None,
+            };
+            admission.update(&config, usage);
+        }
+    }
+
+    fn update(&self, config: &MemoryAdmissionConfig, usage: Option<u64>) {
+        *self.usage.lock() = usage;
+
+        let (Some(high), Some(low), Some(usage)) =
+            (config.high_watermark(), config.low_watermark(), usage)
+        else {
+            if self.shedding.swap(false, Ordering::Relaxed) {
+                warn!("memory usage is unavailable, no longer shedding requests");
+            }
+            return;
+        };
+
+        let shedding = self.shedding.load(Ordering::Relaxed);
+        if !shedding && usage > high {
+            self.shedding.store(true, Ordering::Relaxed);
+            warn!(
+                "memory usage exceeded high watermark, shedding requests",
+                safe: {
+                    usage: usage,
+                    highWatermark: high,
+                },
+            );
+        } else if shedding && usage < low {
+            self.shedding.store(false, Ordering::Relaxed);
+            info!(
+                "memory usage fell below low watermark, no longer shedding requests",
+                safe: {
+                    usage: usage,
+                    lowWatermark: low,
+                },
+            );
+        }
+    }
+
+    /// Returns the most recently sampled memory usage in bytes.
+    ///
+    /// Usage is only sampled while a high watermark is configured.
+    pub fn usage(&self) -> Option<u64> {
+        *self.usage.lock()
+    }
+
+    /// Returns the number of bytes by which memory usage can grow before the server starts shedding requests.
+    pub fn headroom(&self) -> Option<u64> {
+        let high = self.config.get().high_watermark()?;
+        Some(high.saturating_sub(self.usage()?))
+    }
+
+    /// Returns true if the server is currently shedding requests.
+    pub fn shedding(&self) -> bool {
+        self.shedding.load(Ordering::Relaxed)
+    }
+
+    /// Determines if a request to the endpoint with the specified priority should be admitted.
+    ///
+    /// Only low priority requests are rejected, and only while shedding and if the endpoint isn't exempt. Rejections
+    /// are recorded in the `server.memory-admission.shed` meter.
+    pub fn admit(&self, endpoint: &dyn EndpointMetadata, priority: Priority) -> bool {
+        if priority > Priority::Low || !self.shedding.load(Ordering::Relaxed) {
+            return true;
+        }
+
+        let exempt = self.config.get().exempt_endpoints().iter().any(|e| {
+            e.split_once('.').is_some_and(|(service, name)| {
+                service == endpoint.service_name() && name == endpoint.name()
+            })
+        });
+        if exempt {
+            return true;
+        }
+
+        self.shed.mark(1);
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct TestEndpoint;
+
+    impl EndpointMetadata for TestEndpoint {
+        fn method(&self) -> http::Method {
+            http::Method::GET
+        }
+
+        fn path(&self) -> &[conjure_http::server::PathSegment] {
+            &[]
+        }
+
+        fn template(&self) -> &str {
+            "/"
+        }
+
+        fn service_name(&self) -> &str {
+            "TestService"
+        }
+
+        fn name(&self) -> &str {
+            "test"
+        }
+
+        fn deprecated(&self) -> Option<&str> {
+            None
+        }
+    }
+
+    fn config(high: u64, low: u64) -> MemoryAdmissionConfig {
+        MemoryAdmissionConfig::builder()
+            .high_watermark(high)
+            .low_watermark(low)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn hysteresis() {
+        let metrics = MetricRegistry::new();
+        let config = config(100, 80);
+        let (refreshable, _handle) = Refreshable::new(config.clone());
+        let admission = MemoryAdmission::new(&metrics, refreshable);
+
+        admission.update(&config, Some(100));
+        assert!(!admission.shedding.load(Ordering::Relaxed));
+
+        admission.update(&config, Some(101));
+        assert!(admission.shedding.load(Ordering::Relaxed));
+
+        admission.update(&config, Some(90));
+        assert!(admission.shedding.load(Ordering::Relaxed));
+
+        admission.update(&config, Some(79));
+        assert!(!admission.shedding.load(Ordering::Relaxed));
+
+        admission.update(&config, Some(101));
+        assert!(admission.shedding.load(Ordering::Relaxed));
+
+        admission.update(&config, None);
+        assert!(!admission.shedding.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn headroom() {
+        let metrics = MetricRegistry::new();
+        let config = config(100, 80);
+        let (refreshable, _handle) = Refreshable::new(config.clone());
+        let admission = MemoryAdmission::new(&metrics, refreshable);
+        assert_eq!(admission.headroom(), None);
+
+        admission.update(&config, Some(90));
+        assert_eq!(admission.usage(), Some(90));
+        assert_eq!(admission.headroom(), Some(10));
+
+        admission.update(&config, Some(110));
+        assert_eq!(admission.headroom(), Some(0));
+    }
+
+    #[test]
+    fn priority() {
+        let metrics = MetricRegistry::new();
+        let config = config(100, 80);
+        let (refreshable, _handle) = Refreshable::new(config.clone());
+        let admission = MemoryAdmission::new(&metrics, refreshable);
+
+        admission.update(&config, Some(90));
+        assert!(admission.admit(&TestEndpoint, Priority::Critical));
+        assert!(admission.admit(&TestEndpoint, Priority::Normal));
+        assert!(admission.admit(&TestEndpoint, Priority::Low));
+
+        admission.update(&config, Some(101));
+        assert!(admission.admit(&TestEndpoint, Priority::Critical));
+        assert!(admission.admit(&TestEndpoint, Priority::Normal));
+        assert!(!admission.admit(&TestEndpoint, Priority::Low));
+
+        admission.update(&config, Some(90));
+        assert!(!admission.admit(&TestEndpoint, Priority::Low));
+
+        admission.update(&config, Some(79));
+        assert!(admission.admit(&TestEndpoint, Priority::Low));
+    }
+
+    #[test]
+    fn exempt_endpoints() {
+        let metrics = MetricRegistry::new();
+        let config = MemoryAdmissionConfig::builder()
+            .high_watermark(100)
+            .exempt_endpoints(["TestService.test".to_string()])
+            .build()
+            .unwrap();
+        let (refreshable, _handle) = Refreshable::new(config.clone());
+        let admission = MemoryAdmission::new(&metrics, refreshable);
+
+        admission.update(&config, Some(101));
+        assert!(admission.admit(&TestEndpoint, Priority::Low));
+    }
+}
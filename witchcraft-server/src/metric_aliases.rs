@@ -0,0 +1,131 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use witchcraft_metrics::{Metric, MetricId, MetricRegistry};
+
+/// The old names of renamed metrics.
+///
+/// Aliases are only registered by applications via `Witchcraft::metric_alias`; the server's own metrics have none.
+///
+/// While enabled, a metric with aliases is reported under each of its aliases as well as its own name, with the same
+/// tags and values.
+pub struct MetricAliases {
+    enabled: bool,
+    aliases: RwLock<HashMap<String, Vec<String>>>,
+}
+
+impl MetricAliases {
+    pub fn new(enabled: bool) -> Self {
+        MetricAliases {
+            enabled,
+            aliases: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn register(&self, name: &str, alias: &str) {
+        let mut aliases = self.aliases.write();
+        let aliases = aliases.entry(name.to_string()).or_default();
+        if !aliases.iter().any(|a| a == alias) {
+            aliases.push(alias.to_string());
+        }
+    }
+
+    /// Returns a snapshot of the registry's metrics, including an entry for each alias of each metric.
+    pub fn metrics(&self, registry: &MetricRegistry) -> Vec<(MetricId, Metric)> {
+        let mut metrics = registry
+            .metrics()
+            .iter()
+            .map(|(id, metric)| (id.clone(), metric.clone()))
+            .collect::<Vec<_>>();
+
+        if !self.enabled {
+            return metrics;
+        }
+
+        let aliases = self.aliases.read();
+        if aliases.is_empty() {
+            return metrics;
+        }
+
+        let mut aliased = vec![];
+        for (id, metric) in &metrics {
+            let Some(aliases) = aliases.get(id.name()) else {
+                continue;
+            };
+
+            for alias in aliases {
+                let alias_id = id
+                    .tags()
+                    .iter()
+                    .fold(MetricId::new(alias.clone()), |alias_id, (key, value)| {
+                        alias_id.with_tag(key.to_string(), value.to_string())
+                    });
+                aliased.push((alias_id, metric.clone()));
+            }
+        }
+        metrics.extend(aliased);
+
+        metrics
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn aliases() {
+        let registry = MetricRegistry::new();
+        registry
+            .counter(MetricId::new("new.name").with_tag("tag", "value"))
+            .add(2);
+        registry.counter("other").inc();
+
+        let aliases = MetricAliases::new(true);
+        aliases.register("new.name", "old.name");
+
+        let mut metrics = aliases.metrics(&registry);
+        metrics.sort_by(|a, b| a.0.cmp(&b.0));
+        let names = metrics
+            .iter()
+            .map(|(id, _)| (id.name(), id.tags().iter().collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                ("new.name", vec![("tag", "value")]),
+                ("old.name", vec![("tag", "value")]),
+                ("other", vec![]),
+            ]
+        );
+        match &metrics[1].1 {
+            Metric::Counter(c) => assert_eq!(c.count(), 2),
+            _ => panic!("expected a counter"),
+        }
+    }
+
+    #[test]
+    fn disabled() {
+        let registry = MetricRegistry::new();
+        registry.counter("new.name").inc();
+
+        let aliases = MetricAliases::new(false);
+        aliases.register("new.name", "old.name");
+
+        let metrics = aliases.metrics(&registry);
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics[0].0.name(), "new.name");
+    }
+}
@@ -17,8 +17,9 @@ use parking_lot::Mutex;
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-use tikv_jemalloc_ctl::{epoch, stats};
-use witchcraft_metrics::MetricRegistry;
+use crate::jemalloc::{self, MALLCTL_ARENAS_ALL};
+use tikv_jemalloc_ctl::{epoch, raw, stats};
+use witchcraft_metrics::{MetricId, MetricRegistry};
 
 pub fn register_metrics(metrics: &MetricRegistry) {
     let advance = Arc::new(Mutex::new(Debounced::new(|| {
@@ -36,10 +37,55 @@ pub fn register_metrics(metrics: &MetricRegistry) {
         advance_for_active.lock().call_debounced();
         stats::active::read().unwrap_or(0)
     });
+    let advance_for_resident = advance.clone();
     metrics.gauge("process.heap.resident", move || {
-        advance.lock().call_debounced();
+        advance_for_resident.lock().call_debounced();
         stats::resident::read().unwrap_or(0)
     });
+
+    register_size_class_metrics(metrics, &advance);
+}
+
+/// Returns the up-to-date number of bytes in active pages.
+pub fn active_bytes() -> Option<u64> {
+    epoch::advance().ok()?;
+    stats::active::read().ok().map(|b| b as u64)
+}
+
+// Small allocations are grouped into "bins" by size class, while large allocations are each given their own extent.
+fn register_size_class_metrics<F>(metrics: &MetricRegistry, advance: &Arc<Mutex<Debounced<F>>>)
+where
+    F: FnMut() + 'static + Send,
+{
+    for (bin, size) in jemalloc::bin_sizes().into_iter().enumerate() {
+        let mut mib = [0; 6];
+        let name = format!("stats.arenas.{MALLCTL_ARENAS_ALL}.bins.{bin}.curregs\0");
+        if raw::name_to_mib(name.as_bytes(), &mut mib).is_err() {
+            continue;
+        }
+
+        let advance = advance.clone();
+        metrics.gauge(
+            MetricId::new("process.heap.size-class").with_tag("size-class", size.to_string()),
+            move || {
+                advance.lock().call_debounced();
+                unsafe { raw::read_mib::<usize>(&mib) }.map_or(0, |regions| regions * size)
+            },
+        );
+    }
+
+    let mut mib = [0; 5];
+    let name = format!("stats.arenas.{MALLCTL_ARENAS_ALL}.large.allocated\0");
+    if raw::name_to_mib(name.as_bytes(), &mut mib).is_ok() {
+        let advance = advance.clone();
+        metrics.gauge(
+            MetricId::new("process.heap.size-class").with_tag("size-class", "large"),
+            move || {
+                advance.lock().call_debounced();
+                unsafe { raw::read_mib::<usize>(&mib) }.unwrap_or(0)
+            },
+        );
+    }
 }
 
 struct Debounced<F>
@@ -0,0 +1,57 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use libmimalloc_sys::mi_process_info;
+use std::ptr;
+use witchcraft_metrics::MetricRegistry;
+
+pub fn register_metrics(metrics: &MetricRegistry) {
+    // mimalloc only tracks the number of bytes allocated in builds with statistics enabled, so `process.heap` is not
+    // reported.
+    metrics.gauge("process.heap.active", || ProcessInfo::get().current_commit);
+    metrics.gauge("process.heap.resident", || ProcessInfo::get().current_rss);
+}
+
+/// Returns the number of bytes committed by the allocator.
+pub fn active_bytes() -> Option<u64> {
+    Some(ProcessInfo::get().current_commit as u64)
+}
+
+struct ProcessInfo {
+    current_rss: usize,
+    current_commit: usize,
+}
+
+impl ProcessInfo {
+    fn get() -> Self {
+        let mut current_rss = 0;
+        let mut current_commit = 0;
+        unsafe {
+            mi_process_info(
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut current_rss,
+                ptr::null_mut(),
+                &mut current_commit,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            );
+        }
+
+        ProcessInfo {
+            current_rss,
+            current_commit,
+        }
+    }
+}
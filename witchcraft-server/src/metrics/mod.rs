@@ -16,11 +16,13 @@ use std::panic;
 use std::time::Instant;
 use witchcraft_metrics::MetricRegistry;
 
-#[cfg(feature = "jemalloc")]
+#[cfg(all(feature = "jemalloc", not(feature = "mimalloc")))]
 mod jemalloc;
+#[cfg(feature = "mimalloc")]
+mod mimalloc;
 #[cfg(target_os = "linux")]
-mod proc;
-mod rusage;
+pub mod proc;
+pub mod rusage;
 
 pub fn init(metrics: &MetricRegistry) {
     register_uptime_metric(metrics);
@@ -28,8 +30,63 @@ pub fn init(metrics: &MetricRegistry) {
     register_rusage_metrics(metrics);
     #[cfg(target_os = "linux")]
     proc::register_metrics(metrics);
-    #[cfg(feature = "jemalloc")]
+    #[cfg(all(feature = "jemalloc", not(feature = "mimalloc")))]
     jemalloc::register_metrics(metrics);
+    #[cfg(feature = "mimalloc")]
+    mimalloc::register_metrics(metrics);
+}
+
+/// Returns the number of bytes of the process's memory that are resident in physical memory, if supported on this
+/// platform.
+pub fn resident_bytes() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        proc::resident_bytes()
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Returns the fraction of the process's file descriptor limit currently in use, if supported on this platform.
+pub fn file_descriptor_utilization() -> Option<f32> {
+    #[cfg(target_os = "linux")]
+    {
+        proc::file_descriptor_utilization().ok()
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Returns the process's soft limit on open file descriptors, if supported on this platform.
+pub fn file_descriptor_limit() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        proc::file_descriptor_limit().ok()
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Returns the number of bytes in active pages of the heap, if supported by the memory allocator.
+pub fn heap_active_bytes() -> Option<u64> {
+    #[cfg(all(feature = "jemalloc", not(feature = "mimalloc")))]
+    {
+        jemalloc::active_bytes()
+    }
+    #[cfg(feature = "mimalloc")]
+    {
+        mimalloc::active_bytes()
+    }
+    #[cfg(not(any(feature = "jemalloc", feature = "mimalloc")))]
+    {
+        None
+    }
 }
 
 fn register_uptime_metric(metrics: &MetricRegistry) {
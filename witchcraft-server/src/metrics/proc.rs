@@ -18,7 +18,9 @@ use witchcraft_metrics::MetricRegistry;
 pub fn register_metrics(metrics: &MetricRegistry) {
     metrics.gauge("process.threads", || num_threads().unwrap_or(0));
 
-    metrics.gauge("process.filedescriptor", || filedescriptor().unwrap_or(0.));
+    metrics.gauge("process.filedescriptor", || {
+        file_descriptor_utilization().unwrap_or(0.)
+    });
 }
 
 fn num_threads() -> Option<i64> {
@@ -36,7 +38,25 @@ fn parse_num_threads(stat: &str) -> Option<i64> {
     stat.rsplit(')').next()?.split(' ').nth(18)?.parse().ok()
 }
 
-fn filedescriptor() -> io::Result<f32> {
+/// Returns the number of bytes of the process's memory that are resident in physical memory.
+pub fn resident_bytes() -> Option<u64> {
+    let statm = fs::read_to_string("/proc/self/statm").ok()?;
+    let pages = parse_resident_pages(&statm)?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if page_size <= 0 {
+        return None;
+    }
+
+    Some(pages * page_size as u64)
+}
+
+fn parse_resident_pages(statm: &str) -> Option<u64> {
+    // statm contains space-separated page counts, the second of which is the resident set size.
+    statm.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Returns the fraction of the process's file descriptor limit currently in use.
+pub fn file_descriptor_utilization() -> io::Result<f32> {
     let mut files = 0;
     for r in fs::read_dir("/proc/self/fd")? {
         r?;
@@ -48,6 +68,11 @@ fn filedescriptor() -> io::Result<f32> {
     Ok(files as f32 / max_files as f32)
 }
 
+/// Returns the process's soft limit on open file descriptors.
+pub fn file_descriptor_limit() -> io::Result<u64> {
+    Rlimit::nofile().map(|l| l.cur())
+}
+
 struct Rlimit(libc::rlimit);
 
 impl Rlimit {
@@ -81,4 +106,13 @@ mod test {
 
         assert_eq!(parse_num_threads(stat), Some(1));
     }
+
+    #[test]
+    fn resident_pages() {
+        assert_eq!(
+            parse_resident_pages("5314 1204 987 6 0 431 0\n"),
+            Some(1204)
+        );
+        assert_eq!(parse_resident_pages("5314"), None);
+    }
 }
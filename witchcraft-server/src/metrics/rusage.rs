@@ -29,6 +29,18 @@ impl Rusage {
         }
     }
 
+    #[cfg(target_os = "linux")]
+    pub fn get_thread() -> io::Result<Self> {
+        unsafe {
+            let mut rusage = MaybeUninit::uninit();
+            if libc::getrusage(libc::RUSAGE_THREAD, rusage.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(Rusage(rusage.assume_init()))
+        }
+    }
+
     pub fn user_time(&self) -> Duration {
         Duration::new(
             self.0.ru_utime.tv_sec as u64,
@@ -43,6 +55,10 @@ impl Rusage {
         )
     }
 
+    pub fn cpu_time(&self) -> Duration {
+        self.user_time() + self.system_time()
+    }
+
     pub fn blocks_read(&self) -> u64 {
         self.0.ru_inblock as u64
     }
@@ -51,3 +67,15 @@ impl Rusage {
         self.0.ru_oublock as u64
     }
 }
+
+/// Returns the CPU time consumed by the current thread, if supported on this platform.
+pub fn thread_cpu_time() -> Option<Duration> {
+    #[cfg(target_os = "linux")]
+    {
+        Rusage::get_thread().ok().map(|r| r.cpu_time())
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
@@ -36,20 +36,25 @@ pub async fn log_minidump(p: &Path) -> Result<(), Error> {
 }
 
 pub async fn process_minidump(p: &Path) -> Result<String, Error> {
+    let state = read_minidump(p).await?;
+    let info = format_dump(&state);
+
+    Ok(info)
+}
+
+/// Reads a minidump and symbolicates the stack of each thread.
+pub async fn read_minidump(p: &Path) -> Result<ProcessState, Error> {
     let dump = Minidump::read_path(p).map_err(Error::internal_safe)?;
 
     let arena = Arena::new();
     let symbol_provider = WitchcraftSymbolProvider::new(&arena);
 
-    let state = minidump_processor::process_minidump(&dump, &symbol_provider)
+    minidump_processor::process_minidump(&dump, &symbol_provider)
         .await
-        .map_err(Error::internal_safe)?;
-    let info = format_dump(&state);
-
-    Ok(info)
+        .map_err(Error::internal_safe)
 }
 
-fn format_dump(state: &ProcessState) -> String {
+pub fn format_dump(state: &ProcessState) -> String {
     let mut buf = String::new();
 
     if let Some(info) = &state.exception_info {
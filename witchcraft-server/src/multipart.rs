@@ -0,0 +1,624 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! `multipart/form-data` request bodies.
+//!
+//! A [`Multipart`] reads the parts of a multipart body one at a time, streaming each part's contents rather than
+//! buffering the entire body in memory. This allows endpoints to accept browser file uploads of arbitrary size. The
+//! equivalent type for blocking endpoints is [`blocking::multipart::Multipart`](crate::blocking::multipart::Multipart).
+//!
+//! Parts larger than the configured limit (10 MiB by default) are rejected with a `413 Request Entity Too Large` error,
+//! and malformed bodies with a `400 Bad Request` error.
+//!
+//! # Examples
+//!
+//! ```
+//! use conjure_error::Error;
+//! use http::HeaderMap;
+//! use witchcraft_server::multipart::Multipart;
+//! use witchcraft_server::RequestBody;
+//!
+//! async fn upload(body: RequestBody, request_headers: &HeaderMap) -> Result<(), Error> {
+//!     let mut multipart =
+//!         Multipart::new(body.into_stream(), request_headers)?.max_part_size(1024 * 1024 * 1024);
+//!
+//!     while let Some(mut part) = multipart.next_part().await? {
+//!         if part.file_name().is_some() {
+//!             while let Some(chunk) = part.chunk().await? {
+//!                 // write the chunk to storage
+//!             }
+//!         } else {
+//!             let value = part.text().await?;
+//!         }
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+use bytes::{Bytes, BytesMut};
+use conjure_error::{Error, InvalidArgument, RequestEntityTooLarge};
+use futures_util::{Stream, StreamExt};
+use http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use http::{HeaderMap, HeaderName, HeaderValue};
+use memchr::memmem;
+
+const DEFAULT_MAX_PART_SIZE: u64 = 10 * 1024 * 1024;
+const DEFAULT_MAX_HEADERS_SIZE: usize = 8 * 1024;
+const MAX_HEADERS: usize = 32;
+// Bounds the transport padding permitted after a boundary.
+const MAX_BOUNDARY_LINE: usize = 1024;
+
+/// An async reader of the parts of a `multipart/form-data` body.
+pub struct Multipart<S> {
+    body: S,
+    parser: Parser,
+}
+
+impl<S> Multipart<S>
+where
+    S: Stream<Item = Result<Bytes, Error>> + Unpin,
+{
+    /// Creates a new `Multipart` reading `body`, with the boundary taken from the request's `Content-Type` header.
+    ///
+    /// Returns an error if the request isn't a multipart request.
+    pub fn new(body: S, request_headers: &HeaderMap) -> Result<Self, Error> {
+        Ok(Self::with_boundary(body, &boundary(request_headers)?))
+    }
+
+    /// Creates a new `Multipart` reading `body` with an explicit boundary.
+    pub fn with_boundary(body: S, boundary: &str) -> Self {
+        Multipart {
+            body,
+            parser: Parser::new(boundary),
+        }
+    }
+
+    /// Sets the maximum size of the contents of each part.
+    ///
+    /// Defaults to 10 MiB.
+    pub fn max_part_size(mut self, max_part_size: u64) -> Self {
+        self.parser.max_part_size = max_part_size;
+        self
+    }
+
+    /// Sets the maximum size of the headers of each part.
+    ///
+    /// Defaults to 8 KiB.
+    pub fn max_headers_size(mut self, max_headers_size: usize) -> Self {
+        self.parser.max_headers_size = max_headers_size;
+        self
+    }
+
+    /// Returns the next part of the body, or `None` if all parts have been read.
+    ///
+    /// Any unread contents of the previous part are discarded.
+    pub async fn next_part(&mut self) -> Result<Option<Part<'_, S>>, Error> {
+        loop {
+            match self.next_event().await? {
+                Event::Headers(headers) => {
+                    return Ok(Some(Part {
+                        headers: PartHeaders::new(headers)?,
+                        multipart: self,
+                        done: false,
+                    }))
+                }
+                Event::Data(_) | Event::PartEnd => {}
+                Event::End => return Ok(None),
+            }
+        }
+    }
+
+    async fn next_event(&mut self) -> Result<Event, Error> {
+        loop {
+            if let Some(event) = self.parser.next_event()? {
+                return Ok(event);
+            }
+
+            match self.body.next().await {
+                Some(chunk) => self.parser.feed(&chunk?),
+                None => self.parser.feed_eof(),
+            }
+        }
+    }
+}
+
+/// A part of a `multipart/form-data` body.
+pub struct Part<'a, S> {
+    multipart: &'a mut Multipart<S>,
+    headers: PartHeaders,
+    done: bool,
+}
+
+impl<S> Part<'_, S>
+where
+    S: Stream<Item = Result<Bytes, Error>> + Unpin,
+{
+    /// Returns the part's headers.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers.headers
+    }
+
+    /// Returns the form field name from the part's `Content-Disposition` header.
+    pub fn name(&self) -> Option<&str> {
+        self.headers.name.as_deref()
+    }
+
+    /// Returns the file name from the part's `Content-Disposition` header.
+    ///
+    /// Browsers set this for file uploads. It is provided by the client and must not be trusted as a filesystem path.
+    pub fn file_name(&self) -> Option<&str> {
+        self.headers.file_name.as_deref()
+    }
+
+    /// Returns the part's `Content-Type` header.
+    pub fn content_type(&self) -> Option<&HeaderValue> {
+        self.headers.headers.get(CONTENT_TYPE)
+    }
+
+    /// Returns the next chunk of the part's contents, or `None` if the contents have been fully read.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, Error> {
+        if self.done {
+            return Ok(None);
+        }
+
+        match self.multipart.next_event().await? {
+            Event::Data(data) => Ok(Some(data)),
+            _ => {
+                self.done = true;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Reads the part's entire contents into memory.
+    pub async fn bytes(mut self) -> Result<Bytes, Error> {
+        let mut buf = BytesMut::new();
+        while let Some(chunk) = self.chunk().await? {
+            buf.extend_from_slice(&chunk);
+        }
+
+        Ok(buf.freeze())
+    }
+
+    /// Reads the part's entire contents into memory as a UTF-8 string.
+    pub async fn text(self) -> Result<String, Error> {
+        let bytes = self.bytes().await?;
+        String::from_utf8(bytes.into()).map_err(|e| Error::service(e, InvalidArgument::new()))
+    }
+}
+
+/// Extracts the boundary from a `multipart/*` `Content-Type` header.
+pub(crate) fn boundary(request_headers: &HeaderMap) -> Result<String, Error> {
+    let content_type = request_headers
+        .get(CONTENT_TYPE)
+        .ok_or_else(|| invalid("missing Content-Type header"))?
+        .to_str()
+        .map_err(|e| Error::service_safe(e, InvalidArgument::new()))?;
+
+    let (mime, params) = content_type.split_once(';').unwrap_or((content_type, ""));
+    if !mime
+        .trim()
+        .get(..10)
+        .is_some_and(|m| m.eq_ignore_ascii_case("multipart/"))
+    {
+        return Err(invalid("expected a multipart Content-Type"));
+    }
+
+    let boundary = parameters(params)
+        .find(|(key, _)| key.eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value)
+        .ok_or_else(|| invalid("missing multipart boundary"))?;
+
+    if boundary.is_empty() || boundary.len() > 70 {
+        return Err(invalid("invalid multipart boundary"));
+    }
+
+    Ok(boundary)
+}
+
+/// Parses the `;`-separated `key=value` parameters of a header, unquoting quoted values.
+fn parameters(mut s: &str) -> impl Iterator<Item = (&str, String)> {
+    std::iter::from_fn(move || loop {
+        s = s.trim_start_matches([';', ' ', '\t']);
+        if s.is_empty() {
+            return None;
+        }
+
+        let (key, rest) = match s.split_once('=') {
+            Some((key, rest)) => (key.trim(), rest.trim_start()),
+            None => {
+                s = s.split_once(';').map_or("", |(_, rest)| rest);
+                continue;
+            }
+        };
+
+        let value = match rest.strip_prefix('"') {
+            Some(quoted) => {
+                let mut value = String::new();
+                let mut chars = quoted.char_indices();
+                let mut end = quoted.len();
+                while let Some((i, c)) = chars.next() {
+                    match c {
+                        '\\' => {
+                            if let Some((_, c)) = chars.next() {
+                                value.push(c);
+                            }
+                        }
+                        '"' => {
+                            end = i + 1;
+                            break;
+                        }
+                        c => value.push(c),
+                    }
+                }
+                s = &quoted[end..];
+                value
+            }
+            None => {
+                let end = rest.find(';').unwrap_or(rest.len());
+                let value = rest[..end].trim().to_string();
+                s = &rest[end..];
+                value
+            }
+        };
+
+        return Some((key, value));
+    })
+}
+
+fn invalid(message: &'static str) -> Error {
+    Error::service_safe(message, InvalidArgument::new())
+}
+
+pub(crate) struct PartHeaders {
+    pub(crate) headers: HeaderMap,
+    pub(crate) name: Option<String>,
+    pub(crate) file_name: Option<String>,
+}
+
+impl PartHeaders {
+    pub(crate) fn new(headers: HeaderMap) -> Result<Self, Error> {
+        let mut name = None;
+        let mut file_name = None;
+
+        if let Some(disposition) = headers.get(CONTENT_DISPOSITION) {
+            let disposition = disposition
+                .to_str()
+                .map_err(|e| Error::service_safe(e, InvalidArgument::new()))?;
+            let params = disposition.split_once(';').map_or("", |(_, params)| params);
+            for (key, value) in parameters(params) {
+                if key.eq_ignore_ascii_case("name") {
+                    name = Some(value);
+                } else if key.eq_ignore_ascii_case("filename") {
+                    file_name = Some(value);
+                }
+            }
+        }
+
+        Ok(PartHeaders {
+            headers,
+            name,
+            file_name,
+        })
+    }
+}
+
+pub(crate) enum Event {
+    Headers(HeaderMap),
+    Data(Bytes),
+    PartEnd,
+    End,
+}
+
+enum State {
+    Preamble,
+    Boundary,
+    Headers,
+    Body,
+    End,
+}
+
+/// An incremental parser of multipart bodies, shared by the async and blocking readers.
+pub(crate) struct Parser {
+    dash_boundary: Vec<u8>,
+    delimiter: Vec<u8>,
+    buf: BytesMut,
+    eof: bool,
+    state: State,
+    part_size: u64,
+    pub(crate) max_part_size: u64,
+    pub(crate) max_headers_size: usize,
+}
+
+impl Parser {
+    pub(crate) fn new(boundary: &str) -> Self {
+        let mut delimiter = b"\r\n--".to_vec();
+        delimiter.extend_from_slice(boundary.as_bytes());
+
+        Parser {
+            dash_boundary: delimiter[2..].to_vec(),
+            delimiter,
+            buf: BytesMut::new(),
+            eof: false,
+            state: State::Preamble,
+            part_size: 0,
+            max_part_size: DEFAULT_MAX_PART_SIZE,
+            max_headers_size: DEFAULT_MAX_HEADERS_SIZE,
+        }
+    }
+
+    pub(crate) fn feed(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
+
+    pub(crate) fn feed_eof(&mut self) {
+        self.eof = true;
+    }
+
+    /// Returns the next event, or `None` if more data is needed.
+    pub(crate) fn next_event(&mut self) -> Result<Option<Event>, Error> {
+        loop {
+            let event = match self.state {
+                State::Preamble => self.preamble()?,
+                State::Boundary => self.boundary()?,
+                State::Headers => self.headers()?,
+                State::Body => return self.body(),
+                State::End => return Ok(Some(Event::End)),
+            };
+
+            match event {
+                Progress::Event(event) => return Ok(Some(event)),
+                Progress::Continue => {}
+                Progress::NeedData => {
+                    if self.eof {
+                        return Err(invalid("unexpected end of multipart body"));
+                    }
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    fn preamble(&mut self) -> Result<Progress, Error> {
+        let mut start = 0;
+        while let Some(pos) = memmem::find(&self.buf[start..], &self.dash_boundary) {
+            let pos = start + pos;
+            if pos == 0 || self.buf[..pos].ends_with(b"\r\n") {
+                let _ = self.buf.split_to(pos + self.dash_boundary.len());
+                self.state = State::Boundary;
+                return Ok(Progress::Continue);
+            }
+            start = pos + 1;
+        }
+
+        // Keep enough of the preamble to match a boundary split across chunks.
+        let keep = self.dash_boundary.len() + 2;
+        if self.buf.len() > keep {
+            let _ = self.buf.split_to(self.buf.len() - keep);
+        }
+        Ok(Progress::NeedData)
+    }
+
+    fn boundary(&mut self) -> Result<Progress, Error> {
+        if self.buf.starts_with(b"--") {
+            self.buf.clear();
+            self.state = State::End;
+            return Ok(Progress::Event(Event::End));
+        }
+
+        let Some(pos) = memmem::find(&self.buf, b"\r\n") else {
+            if self.buf.len() > MAX_BOUNDARY_LINE {
+                return Err(invalid("invalid multipart boundary line"));
+            }
+            // A lone `-` could still be the start of the closing delimiter.
+            return Ok(Progress::NeedData);
+        };
+
+        if !self.buf[..pos].iter().all(|b| *b == b' ' || *b == b'\t') {
+            return Err(invalid("invalid multipart boundary line"));
+        }
+        let _ = self.buf.split_to(pos + 2);
+        self.state = State::Headers;
+        Ok(Progress::Continue)
+    }
+
+    fn headers(&mut self) -> Result<Progress, Error> {
+        let (len, headers) = if self.buf.starts_with(b"\r\n") {
+            (2, HeaderMap::new())
+        } else {
+            let Some(pos) = memmem::find(&self.buf, b"\r\n\r\n") else {
+                if self.buf.len() > self.max_headers_size {
+                    return Err(headers_too_large());
+                }
+                return Ok(Progress::NeedData);
+            };
+            let len = pos + 4;
+            if len > self.max_headers_size {
+                return Err(headers_too_large());
+            }
+
+            let mut raw = [httparse::EMPTY_HEADER; MAX_HEADERS];
+            let raw = match httparse::parse_headers(&self.buf[..len], &mut raw) {
+                Ok(httparse::Status::Complete((_, raw))) => raw,
+                Ok(httparse::Status::Partial) => return Err(invalid("invalid multipart headers")),
+                Err(e) => return Err(Error::service_safe(e, InvalidArgument::new())),
+            };
+
+            let mut headers = HeaderMap::with_capacity(raw.len());
+            for header in raw {
+                let name = HeaderName::from_bytes(header.name.as_bytes())
+                    .map_err(|e| Error::service_safe(e, InvalidArgument::new()))?;
+                let value = HeaderValue::from_bytes(header.value)
+                    .map_err(|e| Error::service_safe(e, InvalidArgument::new()))?;
+                headers.append(name, value);
+            }
+            (len, headers)
+        };
+
+        let _ = self.buf.split_to(len);
+        self.part_size = 0;
+        self.state = State::Body;
+        Ok(Progress::Event(Event::Headers(headers)))
+    }
+
+    fn body(&mut self) -> Result<Option<Event>, Error> {
+        let len = match memmem::find(&self.buf, &self.delimiter) {
+            Some(0) => {
+                let _ = self.buf.split_to(self.delimiter.len());
+                self.state = State::Boundary;
+                return Ok(Some(Event::PartEnd));
+            }
+            Some(pos) => pos,
+            // Hold back enough data to match a delimiter split across chunks.
+            None => self.buf.len().saturating_sub(self.delimiter.len() - 1),
+        };
+
+        if len == 0 {
+            if self.eof {
+                return Err(invalid("unexpected end of multipart body"));
+            }
+            return Ok(None);
+        }
+
+        self.part_size += len as u64;
+        if self.part_size > self.max_part_size {
+            return Err(Error::service_safe(
+                "multipart part too large",
+                RequestEntityTooLarge::new(),
+            )
+            .with_safe_param("maxPartSize", self.max_part_size));
+        }
+
+        Ok(Some(Event::Data(self.buf.split_to(len).freeze())))
+    }
+}
+
+enum Progress {
+    Event(Event),
+    Continue,
+    NeedData,
+}
+
+fn headers_too_large() -> Error {
+    Error::service_safe(
+        "multipart part headers too large",
+        RequestEntityTooLarge::new(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use conjure_error::ErrorKind;
+    use futures_util::stream;
+
+    const BODY: &[u8] = b"preamble\r\n\
+        --boundary\r\n\
+        Content-Disposition: form-data; name=\"field\"\r\n\
+        \r\n\
+        value\r\n\
+        --boundary  \r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"a \\\"b\\\".txt\"\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        line one\r\n--boundar\r\nline two\r\n\
+        --boundary--\r\n\
+        epilogue";
+
+    fn multipart(chunk_size: usize) -> Multipart<impl Stream<Item = Result<Bytes, Error>> + Unpin> {
+        let chunks = BODY
+            .chunks(chunk_size)
+            .map(|c| Ok(Bytes::copy_from_slice(c)))
+            .collect::<Vec<_>>();
+        Multipart::with_boundary(stream::iter(chunks), "boundary")
+    }
+
+    #[tokio::test]
+    async fn parse() {
+        for chunk_size in [1, 2, 7, BODY.len()] {
+            let mut multipart = multipart(chunk_size);
+
+            let part = multipart.next_part().await.unwrap().unwrap();
+            assert_eq!(part.name(), Some("field"));
+            assert_eq!(part.file_name(), None);
+            assert_eq!(part.text().await.unwrap(), "value");
+
+            let part = multipart.next_part().await.unwrap().unwrap();
+            assert_eq!(part.name(), Some("file"));
+            assert_eq!(part.file_name(), Some("a \"b\".txt"));
+            assert_eq!(part.content_type().unwrap(), "text/plain");
+            assert_eq!(
+                part.bytes().await.unwrap(),
+                "line one\r\n--boundar\r\nline two"
+            );
+
+            assert!(multipart.next_part().await.unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn skip_unread_parts() {
+        let mut multipart = multipart(3);
+
+        multipart.next_part().await.unwrap().unwrap();
+        let part = multipart.next_part().await.unwrap().unwrap();
+        assert_eq!(part.name(), Some("file"));
+        assert!(multipart.next_part().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn part_too_large() {
+        let mut multipart = multipart(BODY.len()).max_part_size(10);
+
+        let part = multipart.next_part().await.unwrap().unwrap();
+        part.bytes().await.unwrap();
+        let part = multipart.next_part().await.unwrap().unwrap();
+        let error = part.bytes().await.unwrap_err();
+        match error.kind() {
+            ErrorKind::Service(e) => assert_eq!(e.error_code().status_code(), 413),
+            _ => panic!("expected a service error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn truncated() {
+        let body = &BODY[..BODY.len() - 30];
+        let mut multipart = Multipart::with_boundary(
+            stream::iter(vec![Ok(Bytes::copy_from_slice(body))]),
+            "boundary",
+        );
+
+        multipart.next_part().await.unwrap().unwrap();
+        let part = multipart.next_part().await.unwrap().unwrap();
+        assert!(part.bytes().await.is_err());
+    }
+
+    #[test]
+    fn content_type_boundary() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("multipart/form-data; charset=utf-8; boundary=\"a;b\""),
+        );
+        assert_eq!(boundary(&headers).unwrap(), "a;b");
+
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("Multipart/Form-Data;boundary=xyz"),
+        );
+        assert_eq!(boundary(&headers).unwrap(), "xyz");
+
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        assert!(boundary(&headers).is_err());
+    }
+}
@@ -0,0 +1,581 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! OCSP stapling.
+//!
+//! The stapler periodically fetches an OCSP response for the server's certificate from the responder listed in the
+//! certificate, and the TLS layer includes the most recent unexpired response in its handshakes. Responses are not
+//! verified beyond checking that they cover the server's certificate, since clients verify them anyway.
+use crate::service::tls::load_certificates;
+use bytes::Bytes;
+use conjure_error::Error;
+use http::header::{ACCEPT, CONTENT_TYPE, HOST};
+use http::uri::Scheme;
+use http::{Request, Uri};
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::client::conn::http1;
+use hyper_util::rt::TokioIo;
+use parking_lot::Mutex;
+use sha1::{Digest, Sha1};
+use std::path::PathBuf;
+use std::sync::Weak;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::TcpStream;
+use tokio::time::{self, Instant};
+use webpki::types::CertificateDer;
+use witchcraft_log::{debug, warn};
+use witchcraft_server_config::install::{InstallConfig, OcspStaplingConfig};
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::{GeneralName, ParsedExtension};
+use x509_parser::prelude::FromDer;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_RESPONSE_SIZE: usize = 64 * 1024;
+
+const ID_AD_OCSP: &str = "1.3.6.1.5.5.7.48.1";
+// DER encodings of the id-sha1 and id-pkix-ocsp-basic object identifiers.
+const ID_SHA1: &[u8] = &[0x2b, 0x0e, 0x03, 0x02, 0x1a];
+const ID_PKIX_OCSP_BASIC: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01];
+
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const NULL: u8 = 0x05;
+const OID: u8 = 0x06;
+const ENUMERATED: u8 = 0x0a;
+const GENERALIZED_TIME: u8 = 0x18;
+const SEQUENCE: u8 = 0x30;
+const CONTEXT_0: u8 = 0xa0;
+
+/// The revocation status of a certificate reported by an OCSP responder.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CertStatus {
+    Good,
+    Revoked,
+    Unknown,
+}
+
+struct Staple {
+    cert: CertificateDer<'static>,
+    response: Vec<u8>,
+    status: CertStatus,
+    next_update: Option<SystemTime>,
+}
+
+impl Staple {
+    fn expired(&self) -> bool {
+        self.next_update.is_some_and(|t| t <= SystemTime::now())
+    }
+}
+
+#[derive(Default)]
+struct State {
+    staple: Option<Staple>,
+    refresh_failed: bool,
+}
+
+/// The health of the stapler, as reported by its health check.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StaplingHealth {
+    /// A current response is being stapled.
+    Healthy,
+    /// The most recent refresh failed, but the current response has not expired.
+    RefreshFailing,
+    /// There is no unexpired response to staple.
+    Unavailable,
+    /// The responder does not recognize the certificate.
+    Unknown,
+    /// The certificate has been revoked.
+    Revoked,
+}
+
+/// Fetches and caches OCSP responses for the server's certificate.
+pub struct OcspStapler {
+    cert_path: PathBuf,
+    config: OcspStaplingConfig,
+    state: Mutex<State>,
+}
+
+impl OcspStapler {
+    /// Creates a new stapler if OCSP stapling is enabled.
+    pub fn new(config: &InstallConfig) -> Option<Self> {
+        let ocsp_stapling = config.tls().ocsp_stapling();
+        if !ocsp_stapling.enabled() {
+            return None;
+        }
+
+        Some(OcspStapler {
+            cert_path: config.keystore().cert_path().to_path_buf(),
+            config: ocsp_stapling.clone(),
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    /// Returns the current OCSP response for a certificate, if one is available and unexpired.
+    pub fn staple(&self, cert: &CertificateDer<'_>) -> Option<Vec<u8>> {
+        let state = self.state.lock();
+        let staple = state.staple.as_ref()?;
+        if staple.cert != *cert || staple.expired() {
+            return None;
+        }
+
+        Some(staple.response.clone())
+    }
+
+    pub fn health(&self) -> StaplingHealth {
+        let state = self.state.lock();
+        match &state.staple {
+            Some(staple) if staple.status == CertStatus::Revoked => StaplingHealth::Revoked,
+            Some(staple) if staple.expired() => StaplingHealth::Unavailable,
+            Some(staple) if staple.status == CertStatus::Unknown => StaplingHealth::Unknown,
+            Some(_) if state.refresh_failed => StaplingHealth::RefreshFailing,
+            None if state.refresh_failed => StaplingHealth::Unavailable,
+            _ => StaplingHealth::Healthy,
+        }
+    }
+
+    /// Refreshes the OCSP response periodically, and whenever the certificate changes, until the stapler is dropped.
+    pub async fn run(stapler: Weak<OcspStapler>) {
+        let mut interval = time::interval(CHECK_INTERVAL);
+        let mut next_refresh = Instant::now();
+        loop {
+            interval.tick().await;
+            let Some(stapler) = stapler.upgrade() else {
+                break;
+            };
+
+            let now = Instant::now();
+            if now < next_refresh && !stapler.certificate_changed() {
+                continue;
+            }
+
+            next_refresh = match stapler.refresh().await {
+                Ok(status) => {
+                    debug!(
+                        "refreshed OCSP response",
+                        safe: { status: format!("{status:?}") },
+                    );
+                    now + stapler.config.refresh_interval()
+                }
+                Err(e) => {
+                    warn!("error refreshing OCSP response", error: e);
+                    now + RETRY_INTERVAL
+                }
+            };
+        }
+    }
+
+    fn certificate_changed(&self) -> bool {
+        let Ok(chain) = load_certificates(&self.cert_path) else {
+            return false;
+        };
+
+        let state = self.state.lock();
+        match (&state.staple, chain.first()) {
+            (Some(staple), Some(cert)) => staple.cert != *cert,
+            _ => false,
+        }
+    }
+
+    async fn refresh(&self) -> Result<CertStatus, Error> {
+        let result = self.refresh_inner().await;
+        let mut state = self.state.lock();
+        match result {
+            Ok(staple) => {
+                let status = staple.status;
+                state.staple = Some(staple);
+                state.refresh_failed = false;
+                Ok(status)
+            }
+            Err(e) => {
+                state.refresh_failed = true;
+                Err(e)
+            }
+        }
+    }
+
+    async fn refresh_inner(&self) -> Result<Staple, Error> {
+        let chain = load_certificates(&self.cert_path)?;
+        let [cert, issuer, ..] = &chain[..] else {
+            return Err(Error::internal_safe(
+                "certificate file does not contain the issuer's certificate",
+            ));
+        };
+
+        let (url, cert_id) = {
+            let (_, cert) = X509Certificate::from_der(cert).map_err(Error::internal_safe)?;
+            let (_, issuer) = X509Certificate::from_der(issuer).map_err(Error::internal_safe)?;
+            (responder_url(&cert)?.to_string(), cert_id(&cert, &issuer))
+        };
+        let request = der(
+            SEQUENCE,
+            &der(SEQUENCE, &der(SEQUENCE, &der(SEQUENCE, &cert_id))),
+        );
+
+        let response = time::timeout(self.config.timeout(), fetch(&url, request))
+            .await
+            .map_err(|_| Error::internal_safe("OCSP request timed out"))?
+            .map_err(|e| e.with_safe_param("url", &url))?;
+        let single = parse_response(&response, &cert_id)?;
+
+        Ok(Staple {
+            cert: cert.clone(),
+            response,
+            status: single.status,
+            next_update: single.next_update,
+        })
+    }
+}
+
+fn responder_url<'a>(cert: &'a X509Certificate<'_>) -> Result<&'a str, Error> {
+    for extension in cert.extensions() {
+        let ParsedExtension::AuthorityInfoAccess(aia) = extension.parsed_extension() else {
+            continue;
+        };
+
+        for description in &aia.accessdescs {
+            if description.access_method.to_id_string() != ID_AD_OCSP {
+                continue;
+            }
+            if let GeneralName::URI(uri) = description.access_location {
+                return Ok(uri);
+            }
+        }
+    }
+
+    Err(Error::internal_safe(
+        "certificate does not specify an OCSP responder",
+    ))
+}
+
+// CertID ::= SEQUENCE { hashAlgorithm, issuerNameHash, issuerKeyHash, serialNumber }
+fn cert_id(cert: &X509Certificate<'_>, issuer: &X509Certificate<'_>) -> Vec<u8> {
+    let algorithm = [der(OID, ID_SHA1), der(NULL, &[])].concat();
+    let name_hash = Sha1::digest(issuer.subject().as_raw());
+    let key_hash = Sha1::digest(&issuer.public_key().subject_public_key.data);
+
+    let contents = [
+        der(SEQUENCE, &algorithm),
+        der(OCTET_STRING, &name_hash),
+        der(OCTET_STRING, &key_hash),
+        der(INTEGER, cert.raw_serial()),
+    ]
+    .concat();
+    der(SEQUENCE, &contents)
+}
+
+async fn fetch(url: &str, body: Vec<u8>) -> Result<Vec<u8>, Error> {
+    let uri = url.parse::<Uri>().map_err(Error::internal_safe)?;
+    if uri.scheme() != Some(&Scheme::HTTP) {
+        return Err(Error::internal_safe("OCSP responder URL must use http"));
+    }
+    let authority = uri
+        .authority()
+        .ok_or_else(|| Error::internal_safe("OCSP responder URL is missing a host"))?;
+
+    let stream = TcpStream::connect((authority.host(), authority.port_u16().unwrap_or(80)))
+        .await
+        .map_err(Error::internal_safe)?;
+    let (mut client, connection) = http1::handshake(TokioIo::new(stream))
+        .await
+        .map_err(Error::internal_safe)?;
+    tokio::spawn(connection);
+
+    let request = Request::post(uri.path_and_query().map_or("/", |p| p.as_str()))
+        .header(HOST, authority.as_str())
+        .header(CONTENT_TYPE, "application/ocsp-request")
+        .header(ACCEPT, "application/ocsp-response")
+        .body(Full::new(Bytes::from(body)))
+        .unwrap();
+    let response = client
+        .send_request(request)
+        .await
+        .map_err(Error::internal_safe)?;
+    if !response.status().is_success() {
+        return Err(
+            Error::internal_safe("OCSP responder returned an error status")
+                .with_safe_param("status", response.status().as_u16()),
+        );
+    }
+
+    let body = Limited::new(response.into_body(), MAX_RESPONSE_SIZE)
+        .collect()
+        .await
+        .map_err(Error::internal_safe)?;
+    Ok(body.to_bytes().to_vec())
+}
+
+struct SingleResponse {
+    status: CertStatus,
+    next_update: Option<SystemTime>,
+}
+
+// OCSPResponse ::= SEQUENCE { responseStatus ENUMERATED, responseBytes [0] EXPLICIT ResponseBytes OPTIONAL }
+// ResponseBytes ::= SEQUENCE { responseType OBJECT IDENTIFIER, response OCTET STRING }
+// BasicOCSPResponse ::= SEQUENCE { tbsResponseData ResponseData, signatureAlgorithm, signature, certs OPTIONAL }
+// ResponseData ::= SEQUENCE { version [0] OPTIONAL, responderID, producedAt, responses, responseExtensions OPTIONAL }
+fn parse_response(response: &[u8], cert_id: &[u8]) -> Result<SingleResponse, Error> {
+    let mut response = expect(&mut &*response, SEQUENCE)?;
+    let status = expect(&mut response, ENUMERATED)?;
+    if status != [0] {
+        return Err(
+            Error::internal_safe("OCSP responder returned an unsuccessful response")
+                .with_safe_param("responseStatus", status.first().copied()),
+        );
+    }
+
+    let mut response_bytes = expect(&mut expect(&mut response, CONTEXT_0)?, SEQUENCE)?;
+    if expect(&mut response_bytes, OID)? != ID_PKIX_OCSP_BASIC {
+        return Err(Error::internal_safe("unsupported OCSP response type"));
+    }
+    let basic = expect(&mut response_bytes, OCTET_STRING)?;
+    let mut basic = expect(&mut &*basic, SEQUENCE)?;
+
+    let mut data = expect(&mut basic, SEQUENCE)?;
+    if data.first() == Some(&CONTEXT_0) {
+        read(&mut data)?;
+    }
+    read(&mut data)?; // responderID
+    expect(&mut data, GENERALIZED_TIME)?; // producedAt
+
+    let mut responses = expect(&mut data, SEQUENCE)?;
+    while !responses.is_empty() {
+        let mut single = expect(&mut responses, SEQUENCE)?;
+        if read(&mut single)?.raw != cert_id {
+            continue;
+        }
+
+        let status = match read(&mut single)?.tag {
+            0x80 => CertStatus::Good,
+            0xa1 => CertStatus::Revoked,
+            0x82 => CertStatus::Unknown,
+            _ => return Err(Error::internal_safe("invalid OCSP certificate status")),
+        };
+        expect(&mut single, GENERALIZED_TIME)?; // thisUpdate
+        let next_update = if single.first() == Some(&CONTEXT_0) {
+            let time = expect(&mut expect(&mut single, CONTEXT_0)?, GENERALIZED_TIME)?;
+            Some(generalized_time(time)?)
+        } else {
+            None
+        };
+
+        return Ok(SingleResponse {
+            status,
+            next_update,
+        });
+    }
+
+    Err(Error::internal_safe(
+        "OCSP response does not cover the certificate",
+    ))
+}
+
+fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = contents.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|b| **b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+    out.extend_from_slice(contents);
+    out
+}
+
+struct Tlv<'a> {
+    tag: u8,
+    contents: &'a [u8],
+    raw: &'a [u8],
+}
+
+fn read<'a>(input: &mut &'a [u8]) -> Result<Tlv<'a>, Error> {
+    let invalid = || Error::internal_safe("malformed OCSP response");
+
+    let buf = *input;
+    let (&tag, rest) = buf.split_first().ok_or_else(invalid)?;
+    let (&first, mut rest) = rest.split_first().ok_or_else(invalid)?;
+    let len = if first < 0x80 {
+        usize::from(first)
+    } else {
+        let count = usize::from(first & 0x7f);
+        if count == 0 || count > 4 || rest.len() < count {
+            return Err(invalid());
+        }
+        let (len_bytes, tail) = rest.split_at(count);
+        rest = tail;
+        len_bytes
+            .iter()
+            .fold(0, |len, b| (len << 8) | usize::from(*b))
+    };
+    if rest.len() < len {
+        return Err(invalid());
+    }
+
+    let header_len = buf.len() - rest.len();
+    *input = &rest[len..];
+    Ok(Tlv {
+        tag,
+        contents: &rest[..len],
+        raw: &buf[..header_len + len],
+    })
+}
+
+fn expect<'a>(input: &mut &'a [u8], tag: u8) -> Result<&'a [u8], Error> {
+    let tlv = read(input)?;
+    if tlv.tag != tag {
+        return Err(Error::internal_safe("malformed OCSP response")
+            .with_safe_param("expectedTag", tag)
+            .with_safe_param("tag", tlv.tag));
+    }
+
+    Ok(tlv.contents)
+}
+
+// GeneralizedTime values in OCSP responses are formatted as YYYYMMDDHHMMSS[.fff]Z.
+fn generalized_time(value: &[u8]) -> Result<SystemTime, Error> {
+    let invalid = || Error::internal_safe("invalid OCSP response time");
+
+    let value = std::str::from_utf8(value).map_err(|_| invalid())?;
+    if value.len() < 15 || !value.ends_with('Z') {
+        return Err(invalid());
+    }
+    let field = |range| value.get(range).and_then(|s: &str| s.parse::<u64>().ok());
+    let (Some(year), Some(month), Some(day), Some(hour), Some(minute), Some(second)) = (
+        field(0..4),
+        field(4..6),
+        field(6..8),
+        field(8..10),
+        field(10..12),
+        field(12..14),
+    ) else {
+        return Err(invalid());
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || year < 1970 {
+        return Err(invalid());
+    }
+
+    // Days since the epoch of the civil date, from Howard Hinnant's algorithm.
+    let (year, month) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    let seconds = days * 86_400 + hour * 3_600 + minute * 60 + second;
+    Ok(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn der_lengths() {
+        assert_eq!(der(NULL, &[]), [NULL, 0]);
+        assert_eq!(der(OCTET_STRING, &[0; 0x7f])[..2], [OCTET_STRING, 0x7f]);
+        assert_eq!(
+            der(OCTET_STRING, &[0; 0x80])[..3],
+            [OCTET_STRING, 0x81, 0x80]
+        );
+        assert_eq!(
+            der(OCTET_STRING, &[0; 0x1234])[..4],
+            [OCTET_STRING, 0x82, 0x12, 0x34]
+        );
+
+        let encoded = der(OCTET_STRING, &[1; 0x1234]);
+        let mut input = &encoded[..];
+        let tlv = read(&mut input).unwrap();
+        assert_eq!(tlv.tag, OCTET_STRING);
+        assert_eq!(tlv.contents, &[1; 0x1234][..]);
+        assert_eq!(tlv.raw, &encoded[..]);
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn generalized_times() {
+        assert_eq!(generalized_time(b"19700101000000Z").unwrap(), UNIX_EPOCH);
+        assert_eq!(
+            generalized_time(b"20240229123456Z").unwrap(),
+            UNIX_EPOCH + Duration::from_secs(1_709_210_096),
+        );
+        assert_eq!(
+            generalized_time(b"20240229123456.789Z").unwrap(),
+            UNIX_EPOCH + Duration::from_secs(1_709_210_096),
+        );
+        generalized_time(b"20241301000000Z").unwrap_err();
+        generalized_time(b"20240101000000").unwrap_err();
+    }
+
+    fn ocsp_response(cert_id: &[u8], status: &[u8], next_update: Option<&[u8]>) -> Vec<u8> {
+        let mut single = [cert_id, status, &der(GENERALIZED_TIME, b"20240101000000Z")].concat();
+        if let Some(next_update) = next_update {
+            single.extend(der(CONTEXT_0, &der(GENERALIZED_TIME, next_update)));
+        }
+
+        let data = [
+            der(0xa2, &der(OCTET_STRING, &[0; 20])),
+            der(GENERALIZED_TIME, b"20240101000000Z"),
+            der(SEQUENCE, &der(SEQUENCE, &single)),
+        ]
+        .concat();
+        let basic = der(
+            SEQUENCE,
+            &[
+                der(SEQUENCE, &data),
+                der(SEQUENCE, &der(OID, &[0x2a])),
+                der(0x03, &[0]),
+            ]
+            .concat(),
+        );
+        let response_bytes = der(
+            SEQUENCE,
+            &[der(OID, ID_PKIX_OCSP_BASIC), der(OCTET_STRING, &basic)].concat(),
+        );
+        der(
+            SEQUENCE,
+            &[der(ENUMERATED, &[0]), der(CONTEXT_0, &response_bytes)].concat(),
+        )
+    }
+
+    #[test]
+    fn parse_responses() {
+        let cert_id = der(SEQUENCE, &der(INTEGER, &[1]));
+        let other_id = der(SEQUENCE, &der(INTEGER, &[2]));
+
+        let response = ocsp_response(&cert_id, &der(0x80, &[]), Some(b"20240108000000Z"));
+        let single = parse_response(&response, &cert_id).unwrap();
+        assert_eq!(single.status, CertStatus::Good);
+        assert_eq!(
+            single.next_update,
+            Some(UNIX_EPOCH + Duration::from_secs(1_704_672_000)),
+        );
+        parse_response(&response, &other_id).unwrap_err();
+
+        let revoked = der(0xa1, &der(GENERALIZED_TIME, b"20240101000000Z"));
+        let response = ocsp_response(&cert_id, &revoked, None);
+        let single = parse_response(&response, &cert_id).unwrap();
+        assert_eq!(single.status, CertStatus::Revoked);
+        assert_eq!(single.next_update, None);
+
+        let unauthorized = der(SEQUENCE, &der(ENUMERATED, &[6]));
+        parse_response(&unauthorized, &cert_id).unwrap_err();
+    }
+}
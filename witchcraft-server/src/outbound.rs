@@ -0,0 +1,217 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Outbound request tracking.
+//!
+//! Outbound requests made while handling an inbound request can be attributed to the inbound request's endpoint by
+//! wrapping them in [`track`] or [`track_blocking`]. The server reports the number of outbound requests made by each
+//! inbound request and the total time spent waiting on them, which reveals which endpoints amplify load onto their
+//! dependencies.
+//!
+//! The inbound request is tracked in a task-local context which is available while its handler and response body are
+//! running, including in the blocking thread pool. Work spawned onto other tasks or threads should capture the
+//! [`OutboundCalls`] of the request with [`OutboundCalls::current`] and use [`OutboundCalls::track`] instead.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! async fn get_user(&self, id: UserId) -> Result<User, Error> {
+//!     witchcraft_server::outbound::track(self.users_client.get_user(&id)).await
+//! }
+//! ```
+use pin_project::{pin_project, pinned_drop};
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+use witchcraft_metrics::Histogram;
+
+thread_local! {
+    static CURRENT: RefCell<Option<OutboundCalls>> = const { RefCell::new(None) };
+}
+
+/// Tracks a future making an outbound request on behalf of the current inbound request.
+///
+/// The future is passed through unchanged if it is not created while handling an inbound request.
+pub fn track<F>(future: F) -> Track<F>
+where
+    F: Future,
+{
+    Track {
+        future,
+        calls: OutboundCalls::current(),
+        start: None,
+    }
+}
+
+/// Tracks a closure making a blocking outbound request on behalf of the current inbound request.
+pub fn track_blocking<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let Some(calls) = OutboundCalls::current() else {
+        return f();
+    };
+
+    let start = Instant::now();
+    let r = f();
+    calls.record(start.elapsed());
+    r
+}
+
+/// The outbound requests made on behalf of an inbound request.
+///
+/// The totals are recorded once all clones have been dropped.
+#[derive(Clone)]
+pub struct OutboundCalls(Arc<OutboundCallsState>);
+
+struct OutboundCallsState {
+    count: AtomicU64,
+    nanos: AtomicU64,
+    requests: Arc<Histogram>,
+    time: Arc<Histogram>,
+}
+
+impl Drop for OutboundCallsState {
+    fn drop(&mut self) {
+        self.requests
+            .update(self.count.load(Ordering::Relaxed) as i64);
+        self.time
+            .update((self.nanos.load(Ordering::Relaxed) / 1_000) as i64);
+    }
+}
+
+impl OutboundCalls {
+    pub(crate) fn new(requests: Arc<Histogram>, time: Arc<Histogram>) -> Self {
+        OutboundCalls(Arc::new(OutboundCallsState {
+            count: AtomicU64::new(0),
+            nanos: AtomicU64::new(0),
+            requests,
+            time,
+        }))
+    }
+
+    /// Returns the outbound requests of the inbound request currently being handled.
+    pub fn current() -> Option<Self> {
+        CURRENT.with(|c| c.borrow().clone())
+    }
+
+    /// Records an outbound request which took the specified amount of time.
+    pub fn record(&self, duration: Duration) {
+        self.0.count.fetch_add(1, Ordering::Relaxed);
+        self.0
+            .nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Returns the number of outbound requests recorded so far.
+    pub(crate) fn count(&self) -> u64 {
+        self.0.count.load(Ordering::Relaxed)
+    }
+
+    /// Tracks a future making an outbound request.
+    pub fn track<F>(&self, future: F) -> Track<F>
+    where
+        F: Future,
+    {
+        Track {
+            future,
+            calls: Some(self.clone()),
+            start: None,
+        }
+    }
+
+    /// Runs the closure with this as the current inbound request's outbound requests.
+    pub(crate) fn scope<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        struct Reset(Option<OutboundCalls>);
+
+        impl Drop for Reset {
+            fn drop(&mut self) {
+                CURRENT.with(|c| *c.borrow_mut() = self.0.take());
+            }
+        }
+
+        let _reset = Reset(CURRENT.with(|c| c.replace(Some(self.clone()))));
+        f()
+    }
+}
+
+/// A future returned by [`track`] and [`OutboundCalls::track`].
+///
+/// The outbound request is recorded when the future completes or is dropped.
+#[pin_project(PinnedDrop)]
+pub struct Track<F> {
+    #[pin]
+    future: F,
+    calls: Option<OutboundCalls>,
+    start: Option<Instant>,
+}
+
+#[pinned_drop]
+impl<F> PinnedDrop for Track<F> {
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+        if let (Some(calls), Some(start)) = (this.calls.take(), this.start) {
+            calls.record(start.elapsed());
+        }
+    }
+}
+
+impl<F> Future for Track<F>
+where
+    F: Future,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let start = *this.start.get_or_insert_with(Instant::now);
+        let output = ready!(this.future.poll(cx));
+        if let Some(calls) = this.calls.take() {
+            calls.record(start.elapsed());
+        }
+
+        Poll::Ready(output)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn tracks_current_request() {
+        let requests = Arc::new(Histogram::default());
+        let time = Arc::new(Histogram::default());
+
+        let calls = OutboundCalls::new(requests.clone(), time.clone());
+        let (a, b) = calls.scope(|| (track(async {}), track(async {})));
+        a.await;
+        b.await;
+        track_blocking(|| {});
+        assert!(OutboundCalls::current().is_none());
+        assert_eq!(requests.count(), 0);
+
+        drop(calls);
+        assert_eq!(requests.count(), 1);
+        assert_eq!(requests.snapshot().max(), 2);
+        assert_eq!(time.count(), 1);
+    }
+}
@@ -0,0 +1,393 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Transactional outbox event emission.
+//!
+//! Publishing an event directly from a request handler is unreliable: the handler's state change may be committed while
+//! the publish fails, or vice versa. With the outbox pattern, handlers instead record events in an [`OutboxStore`],
+//! ideally in the same transaction as the state change they describe, and a background dispatcher installed via
+//! [`Witchcraft::outbox`] publishes them with an [`EventPublisher`] once they have been committed.
+//!
+//! The dispatcher wakes when [`Outbox::notify`] is called, which [`Outbox::enqueue`] does automatically, and otherwise
+//! polls the store every few seconds. Events are published in the order the store returns them. Failed publishes are
+//! retried with exponential backoff, and events are only removed from the store once they have been published, so
+//! delivery is at-least-once; consumers should deduplicate events by ID.
+//!
+//! The dispatcher reports the following metrics:
+//!
+//! * `server.outbox.published` (meter) - The rate of events successfully published.
+//! * `server.outbox.failed` (meter) - The rate of failed attempts to publish an event.
+//! * `server.outbox.lag` (timer) - The time between an event's creation and its successful publication.
+//!
+//! [`Witchcraft::outbox`]: crate::Witchcraft::outbox
+use bytes::Bytes;
+use conjure_error::Error;
+use conjure_object::{DateTime, Utc, Uuid};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::{self, Instant};
+use tokio_util::sync::CancellationToken;
+use witchcraft_log::warn;
+use witchcraft_metrics::{Meter, MetricId, MetricRegistry, Timer};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const BATCH_SIZE: usize = 100;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// An event awaiting publication.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutboxEvent {
+    id: Uuid,
+    topic: String,
+    payload: Bytes,
+    created_at: DateTime<Utc>,
+}
+
+impl OutboxEvent {
+    /// Creates a new event with a random ID.
+    pub fn new(topic: impl Into<String>, payload: impl Into<Bytes>) -> Self {
+        OutboxEvent {
+            id: Uuid::new_v4(),
+            topic: topic.into(),
+            payload: payload.into(),
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Creates a new event with a JSON-serialized payload.
+    pub fn json<T>(topic: impl Into<String>, payload: &T) -> Result<Self, Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let payload = serde_json::to_vec(payload).map_err(Error::internal)?;
+        Ok(Self::new(topic, payload))
+    }
+
+    /// Sets the event's ID.
+    ///
+    /// Stores use this to reconstruct events they have persisted.
+    pub fn with_id(mut self, id: Uuid) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// Sets the event's creation time.
+    ///
+    /// Stores use this to reconstruct events they have persisted.
+    pub fn with_created_at(mut self, created_at: DateTime<Utc>) -> Self {
+        self.created_at = created_at;
+        self
+    }
+
+    /// Returns the event's ID.
+    #[inline]
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// Returns the topic the event is published to.
+    #[inline]
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// Returns the event's payload.
+    #[inline]
+    pub fn payload(&self) -> &Bytes {
+        &self.payload
+    }
+
+    /// Returns the time the event was created.
+    #[inline]
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
+
+/// A persistent store of events awaiting publication.
+///
+/// The store's methods are called synchronously from the dispatcher, so implementations should not block for extended
+/// periods.
+pub trait OutboxStore {
+    /// Adds an event to the store.
+    ///
+    /// Applications which record events in the same transaction as their own state changes will typically write to
+    /// the underlying storage directly instead, and call [`Outbox::notify`] after committing.
+    fn enqueue(&self, event: OutboxEvent) -> Result<(), Error>;
+
+    /// Returns up to `limit` of the oldest unpublished events.
+    fn pending(&self, limit: usize) -> Result<Vec<OutboxEvent>, Error>;
+
+    /// Removes a published event from the store.
+    fn remove(&self, id: Uuid) -> Result<(), Error>;
+}
+
+/// An [`OutboxStore`] which keeps events in memory.
+///
+/// Events are lost if the process exits, so this is only appropriate for testing or for events which are not
+/// critical.
+#[derive(Default)]
+pub struct MemoryOutboxStore {
+    events: Mutex<VecDeque<OutboxEvent>>,
+}
+
+impl MemoryOutboxStore {
+    /// Creates a new, empty store.
+    pub fn new() -> Self {
+        MemoryOutboxStore::default()
+    }
+}
+
+impl OutboxStore for MemoryOutboxStore {
+    fn enqueue(&self, event: OutboxEvent) -> Result<(), Error> {
+        self.events.lock().push_back(event);
+        Ok(())
+    }
+
+    fn pending(&self, limit: usize) -> Result<Vec<OutboxEvent>, Error> {
+        Ok(self.events.lock().iter().take(limit).cloned().collect())
+    }
+
+    fn remove(&self, id: Uuid) -> Result<(), Error> {
+        self.events.lock().retain(|e| e.id != id);
+        Ok(())
+    }
+}
+
+/// A publisher of events to an external system such as a message broker.
+pub trait EventPublisher {
+    /// Publishes an event.
+    fn publish(&self, event: &OutboxEvent) -> impl Future<Output = Result<(), Error>> + Send;
+}
+
+/// A handle to the server's outbox.
+pub struct Outbox {
+    store: Arc<dyn OutboxStore + Sync + Send>,
+    notify: Notify,
+}
+
+impl Outbox {
+    /// Adds an event to the outbox's store and wakes the dispatcher.
+    pub fn enqueue(&self, event: OutboxEvent) -> Result<(), Error> {
+        self.store.enqueue(event)?;
+        self.notify();
+        Ok(())
+    }
+
+    /// Wakes the dispatcher to publish newly committed events.
+    pub fn notify(&self) {
+        self.notify.notify_one();
+    }
+}
+
+struct Metrics {
+    published: Arc<Meter>,
+    failed: Arc<Meter>,
+    lag: Arc<Timer>,
+}
+
+struct Retry {
+    attempts: u32,
+    next: Instant,
+}
+
+pub(crate) struct Dispatcher<P> {
+    outbox: Arc<Outbox>,
+    publisher: P,
+    metrics: Metrics,
+    retries: HashMap<Uuid, Retry>,
+}
+
+impl<P> Dispatcher<P>
+where
+    P: EventPublisher,
+{
+    pub(crate) fn new<S>(metrics: &MetricRegistry, store: S, publisher: P) -> Self
+    where
+        S: OutboxStore + 'static + Sync + Send,
+    {
+        Dispatcher {
+            outbox: Arc::new(Outbox {
+                store: Arc::new(store),
+                notify: Notify::new(),
+            }),
+            publisher,
+            metrics: Metrics {
+                published: metrics.meter(MetricId::new("server.outbox.published")),
+                failed: metrics.meter(MetricId::new("server.outbox.failed")),
+                lag: metrics.timer(MetricId::new("server.outbox.lag")),
+            },
+            retries: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn outbox(&self) -> &Arc<Outbox> {
+        &self.outbox
+    }
+
+    pub(crate) async fn run(mut self, shutdown: CancellationToken) {
+        loop {
+            let next = self.dispatch(&shutdown).await;
+            if shutdown.is_cancelled() {
+                break;
+            }
+
+            let sleep = next.map_or(POLL_INTERVAL, |next| {
+                next.saturating_duration_since(Instant::now())
+                    .min(POLL_INTERVAL)
+            });
+            tokio::select! {
+                _ = self.outbox.notify.notified() => {}
+                _ = time::sleep(sleep) => {}
+                _ = shutdown.cancelled() => break,
+            }
+        }
+    }
+
+    // Publishes a batch of pending events, returning the time the earliest retry is due.
+    async fn dispatch(&mut self, shutdown: &CancellationToken) -> Option<Instant> {
+        let events = match self.outbox.store.pending(BATCH_SIZE) {
+            Ok(events) => events,
+            Err(e) => {
+                warn!("error loading pending outbox events", error: e);
+                return None;
+            }
+        };
+
+        let mut next_retry = None::<Instant>;
+        for event in events {
+            if shutdown.is_cancelled() {
+                break;
+            }
+
+            if let Some(retry) = self.retries.get(&event.id) {
+                if retry.next > Instant::now() {
+                    next_retry = Some(next_retry.map_or(retry.next, |n| n.min(retry.next)));
+                    continue;
+                }
+            }
+
+            match self.publisher.publish(&event).await {
+                Ok(()) => {
+                    self.metrics.published.mark(1);
+                    let lag = (Utc::now() - event.created_at).to_std().unwrap_or_default();
+                    self.metrics.lag.update(lag);
+                    self.retries.remove(&event.id);
+                    if let Err(e) = self.outbox.store.remove(event.id) {
+                        warn!(
+                            "error removing published outbox event",
+                            safe: { eventId: event.id },
+                            error: e,
+                        );
+                    }
+                }
+                Err(e) => {
+                    self.metrics.failed.mark(1);
+                    let retry = self.retries.entry(event.id).or_insert(Retry {
+                        attempts: 0,
+                        next: Instant::now(),
+                    });
+                    retry.attempts += 1;
+                    retry.next = Instant::now() + backoff(retry.attempts);
+                    next_retry = Some(next_retry.map_or(retry.next, |n| n.min(retry.next)));
+
+                    warn!(
+                        "error publishing outbox event",
+                        safe: {
+                            eventId: event.id,
+                            topic: &event.topic,
+                            attempts: retry.attempts,
+                        },
+                        error: e,
+                    );
+                }
+            }
+        }
+
+        next_retry
+    }
+}
+
+fn backoff(attempts: u32) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(1 << (attempts - 1).min(16))
+        .min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct TestPublisher {
+        published: Arc<Mutex<Vec<String>>>,
+        failures: Arc<Mutex<u32>>,
+    }
+
+    impl EventPublisher for TestPublisher {
+        async fn publish(&self, event: &OutboxEvent) -> Result<(), Error> {
+            let mut failures = self.failures.lock();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err(Error::internal_safe("broker unavailable"));
+            }
+
+            self.published.lock().push(event.topic().to_string());
+            Ok(())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries() {
+        let publisher = TestPublisher::default();
+        *publisher.failures.lock() = 2;
+        let dispatcher = Dispatcher::new(
+            &MetricRegistry::new(),
+            MemoryOutboxStore::new(),
+            publisher.clone(),
+        );
+        let outbox = dispatcher.outbox().clone();
+        let shutdown = CancellationToken::new();
+        let handle = tokio::spawn(dispatcher.run(shutdown.clone()));
+
+        outbox
+            .enqueue(OutboxEvent::json("orders", &1).unwrap())
+            .unwrap();
+        outbox
+            .enqueue(OutboxEvent::json("orders", &2).unwrap())
+            .unwrap();
+        time::sleep(Duration::from_millis(100)).await;
+        // The first event failed once, and the second event's first attempt took the other failure.
+        assert!(publisher.published.lock().is_empty());
+
+        time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(*publisher.published.lock(), ["orders", "orders"]);
+        assert!(outbox.store.pending(BATCH_SIZE).unwrap().is_empty());
+
+        shutdown.cancel();
+        handle.await.unwrap();
+    }
+
+    #[test]
+    fn backoff_growth() {
+        assert_eq!(backoff(1), Duration::from_secs(1));
+        assert_eq!(backoff(3), Duration::from_secs(4));
+        assert_eq!(backoff(100), MAX_BACKOFF);
+    }
+}
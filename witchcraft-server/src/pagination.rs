@@ -0,0 +1,377 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pagination of list endpoints.
+//!
+//! A [`Paginator`] implements a consistent pagination scheme for endpoints returning large collections. Clients
+//! request a page with the optional `pageToken` and `pageSize` query parameters, and each response contains the
+//! page's values along with a `nextPageToken` and a `next` link to the following page, if there is one.
+//!
+//! Page tokens are opaque to clients. They contain an endpoint-defined cursor serialized as JSON and signed with an
+//! HMAC-SHA256 key, so a client cannot forge a token to skip access checks encoded in the cursor. Tokens which fail
+//! verification are rejected with a `Default:InvalidArgument` error, as are page sizes larger than the paginator's
+//! maximum.
+//!
+//! The `next` link is relative to the server's origin: it contains the path of the request and its query parameters,
+//! with `pageToken` replaced by the token of the next page.
+//!
+//! # Examples
+//!
+//! ```
+//! use conjure_error::Error;
+//! use conjure_http::server::{RequestContext, StdResponseSerializer};
+//! use conjure_http::{conjure_endpoints, endpoint};
+//! use witchcraft_server::pagination::{Page, Paginator};
+//!
+//! #[conjure_endpoints]
+//! trait ItemService {
+//!     #[endpoint(method = GET, path = "/items", produces = StdResponseSerializer)]
+//!     async fn list_items(&self, #[context] ctx: RequestContext<'_>) -> Result<Page<u64>, Error>;
+//! }
+//!
+//! struct ItemResource {
+//!     paginator: Paginator,
+//! }
+//!
+//! impl ItemService for ItemResource {
+//!     async fn list_items(&self, ctx: RequestContext<'_>) -> Result<Page<u64>, Error> {
+//!         let request = self.paginator.request_from_context::<u64>(&ctx)?;
+//!         let start = request.cursor().copied().unwrap_or(0);
+//!         let end = (start + request.page_size() as u64).min(1_000);
+//!
+//!         let values = (start..end).collect();
+//!         let next = if end < 1_000 { Some(end) } else { None };
+//!         Ok(self.paginator.page(&ctx, values, next.as_ref()))
+//!     }
+//! }
+//! ```
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use conjure_error::{Error, InvalidArgument};
+use conjure_http::server::RequestContext;
+use hmac::{Hmac, Mac};
+use http::Uri;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::Sha256;
+
+const PAGE_TOKEN: &str = "pageToken";
+const PAGE_SIZE: &str = "pageSize";
+
+/// A helper implementing token-based pagination for list endpoints.
+pub struct Paginator {
+    key: Vec<u8>,
+    default_page_size: u32,
+    max_page_size: u32,
+}
+
+impl Paginator {
+    /// Creates a new paginator signing page tokens with the specified key.
+    ///
+    /// The key should be a random secret of at least 32 bytes, and must be shared by every replica of the service so
+    /// tokens issued by one replica are accepted by the others.
+    ///
+    /// The default page size is 100, and the maximum page size is 1000.
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Paginator {
+            key: key.into(),
+            default_page_size: 100,
+            max_page_size: 1000,
+        }
+    }
+
+    /// Sets the page size used when a request does not specify one.
+    ///
+    /// It is clamped to the maximum page size.
+    pub fn default_page_size(mut self, default_page_size: u32) -> Self {
+        self.default_page_size = default_page_size;
+        self
+    }
+
+    /// Sets the largest page size a request may specify.
+    pub fn max_page_size(mut self, max_page_size: u32) -> Self {
+        self.max_page_size = max_page_size;
+        self
+    }
+
+    /// Parses a page request from a page token and page size.
+    ///
+    /// This is intended for endpoints which declare the `pageToken` and `pageSize` query parameters themselves.
+    pub fn request<C>(
+        &self,
+        page_token: Option<&str>,
+        page_size: Option<u32>,
+    ) -> Result<PageRequest<C>, Error>
+    where
+        C: DeserializeOwned,
+    {
+        let page_size = match page_size {
+            Some(0) => {
+                return Err(Error::service_safe(
+                    "page size must be positive",
+                    InvalidArgument::new(),
+                )
+                .with_safe_param("pageSize", 0))
+            }
+            Some(page_size) if page_size > self.max_page_size => {
+                return Err(Error::service_safe(
+                    "page size exceeds the maximum",
+                    InvalidArgument::new(),
+                )
+                .with_safe_param("pageSize", page_size)
+                .with_safe_param("maxPageSize", self.max_page_size))
+            }
+            Some(page_size) => page_size,
+            None => self.default_page_size.clamp(1, self.max_page_size.max(1)),
+        };
+
+        let cursor = page_token
+            .filter(|t| !t.is_empty())
+            .map(|t| self.decode_token(t))
+            .transpose()?;
+
+        Ok(PageRequest { cursor, page_size })
+    }
+
+    /// Parses a page request from the `pageToken` and `pageSize` query parameters of the request.
+    pub fn request_from_context<C>(&self, ctx: &RequestContext<'_>) -> Result<PageRequest<C>, Error>
+    where
+        C: DeserializeOwned,
+    {
+        let page_token = query_param(ctx.request_uri(), PAGE_TOKEN);
+        let page_size = query_param(ctx.request_uri(), PAGE_SIZE)
+            .map(|s| {
+                s.parse().map_err(|e| {
+                    Error::service_safe(e, InvalidArgument::new())
+                        .with_unsafe_param("pageSize", s.to_string())
+                })
+            })
+            .transpose()?;
+
+        self.request(page_token, page_size)
+    }
+
+    /// Encodes a cursor into a signed page token.
+    pub fn encode_token<C>(&self, cursor: &C) -> String
+    where
+        C: Serialize,
+    {
+        let payload = serde_json::to_vec(cursor).expect("cursor failed to serialize");
+        let signature = self.mac(&payload).finalize().into_bytes();
+
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(&payload),
+            URL_SAFE_NO_PAD.encode(signature),
+        )
+    }
+
+    /// Verifies a page token and decodes the cursor it contains.
+    pub fn decode_token<C>(&self, token: &str) -> Result<C, Error>
+    where
+        C: DeserializeOwned,
+    {
+        let invalid = || Error::service_safe("invalid page token", InvalidArgument::new());
+
+        let (payload, signature) = token.split_once('.').ok_or_else(invalid)?;
+        let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| invalid())?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid())?;
+        self.mac(&payload)
+            .verify_slice(&signature)
+            .map_err(|_| invalid())?;
+
+        serde_json::from_slice(&payload).map_err(|_| invalid())
+    }
+
+    /// Creates a page of results for the current request.
+    ///
+    /// If `next` is set, the page includes a token encoding it and a link to the next page.
+    pub fn page<T, C>(&self, ctx: &RequestContext<'_>, values: Vec<T>, next: Option<&C>) -> Page<T>
+    where
+        C: Serialize,
+    {
+        let next_page_token = next.map(|cursor| self.encode_token(cursor));
+        let next = next_page_token
+            .as_deref()
+            .map(|token| next_link(ctx.request_uri(), token));
+
+        Page {
+            values,
+            next_page_token,
+            next,
+        }
+    }
+
+    fn mac(&self, payload: &[u8]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).unwrap();
+        mac.update(payload);
+        mac
+    }
+}
+
+/// A request for a page of results.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageRequest<C> {
+    cursor: Option<C>,
+    page_size: u32,
+}
+
+impl<C> PageRequest<C> {
+    /// Returns the cursor of the requested page, or `None` if the first page was requested.
+    #[inline]
+    pub fn cursor(&self) -> Option<&C> {
+        self.cursor.as_ref()
+    }
+
+    /// Returns the maximum number of values to return.
+    #[inline]
+    pub fn page_size(&self) -> u32 {
+        self.page_size
+    }
+}
+
+/// A page of results.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Page<T> {
+    values: Vec<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_page_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// Returns the values in the page.
+    #[inline]
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    /// Returns the token of the next page, or `None` if this is the last page.
+    #[inline]
+    pub fn next_page_token(&self) -> Option<&str> {
+        self.next_page_token.as_deref()
+    }
+
+    /// Returns a link to the next page, or `None` if this is the last page.
+    #[inline]
+    pub fn next(&self) -> Option<&str> {
+        self.next.as_deref()
+    }
+
+    /// Consumes the page, returning its values.
+    #[inline]
+    pub fn into_values(self) -> Vec<T> {
+        self.values
+    }
+}
+
+// Page tokens and sizes never need to be percent-encoded, so we can match on the raw query.
+fn query_param<'a>(uri: &'a Uri, name: &str) -> Option<&'a str> {
+    uri.query()?
+        .split('&')
+        .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+fn next_link(uri: &Uri, page_token: &str) -> String {
+    let mut link = uri.path().to_string();
+    link.push('?');
+    for pair in uri.query().into_iter().flat_map(|q| q.split('&')) {
+        let key = pair.split_once('=').map_or(pair, |(key, _)| key);
+        if pair.is_empty() || key == PAGE_TOKEN {
+            continue;
+        }
+        link.push_str(pair);
+        link.push('&');
+    }
+    link.push_str(PAGE_TOKEN);
+    link.push('=');
+    link.push_str(page_token);
+    link
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Cursor {
+        after: String,
+    }
+
+    #[test]
+    fn token_round_trip() {
+        let paginator = Paginator::new("secret");
+        let cursor = Cursor {
+            after: "foo".to_string(),
+        };
+
+        let token = paginator.encode_token(&cursor);
+        assert_eq!(paginator.decode_token::<Cursor>(&token).unwrap(), cursor);
+    }
+
+    #[test]
+    fn tampered_tokens() {
+        let paginator = Paginator::new("secret");
+        let token = paginator.encode_token(&1);
+        let (_, signature) = token.split_once('.').unwrap();
+
+        let forged = format!("{}.{signature}", URL_SAFE_NO_PAD.encode("2"));
+        assert!(paginator.decode_token::<i32>(&forged).is_err());
+        assert!(paginator.decode_token::<i32>("garbage").is_err());
+        assert!(Paginator::new("other").decode_token::<i32>(&token).is_err());
+    }
+
+    #[test]
+    fn page_size() {
+        let paginator = Paginator::new("secret")
+            .default_page_size(10)
+            .max_page_size(50);
+
+        let request = paginator.request::<i32>(None, None).unwrap();
+        assert_eq!(request.page_size(), 10);
+        assert_eq!(request.cursor(), None);
+
+        let request = paginator.request::<i32>(Some(""), Some(50)).unwrap();
+        assert_eq!(request.page_size(), 50);
+
+        assert!(paginator.request::<i32>(None, Some(51)).is_err());
+        assert!(paginator.request::<i32>(None, Some(0)).is_err());
+    }
+
+    #[test]
+    fn query_params() {
+        let uri = Uri::from_static("/items?pageSize=5&flag&pageToken=abc");
+        assert_eq!(query_param(&uri, PAGE_SIZE), Some("5"));
+        assert_eq!(query_param(&uri, PAGE_TOKEN), Some("abc"));
+        assert_eq!(query_param(&uri, "flag"), Some(""));
+        assert_eq!(query_param(&uri, "missing"), None);
+    }
+
+    #[test]
+    fn next_links() {
+        let uri = Uri::from_static("/ctx/items");
+        assert_eq!(next_link(&uri, "t"), "/ctx/items?pageToken=t");
+
+        let uri = Uri::from_static("/ctx/items?pageSize=5&pageToken=old&q=x");
+        assert_eq!(
+            next_link(&uri, "t"),
+            "/ctx/items?pageSize=5&q=x&pageToken=t"
+        );
+    }
+}
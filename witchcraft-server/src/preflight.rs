@@ -0,0 +1,198 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Startup checks of the server's environment.
+//!
+//! Each check is run independently, and any failures are reported together in a single error so that an operator can
+//! fix every problem at once rather than one restart at a time. Problems which do not prevent the server from running,
+//! like overly broad permissions on the private key, are logged as warnings instead.
+use crate::metrics;
+use conjure_error::Error;
+use std::fs;
+use std::net::{SocketAddr, TcpListener};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use witchcraft_log::warn;
+use witchcraft_server_config::install::InstallConfig;
+
+// 2024-01-01T00:00:00Z
+const MIN_SANE_TIME: Duration = Duration::from_secs(1_704_067_200);
+
+/// Validates the server's environment against its install configuration.
+pub fn run(config: &InstallConfig) -> Result<(), Error> {
+    let mut report = Report::default();
+
+    if !config.use_console_log() {
+        check_log_dir(Path::new("var/log"), &mut report);
+    }
+    check_key_file(config.keystore().key_path(), &mut report);
+    check_readable("certificate", config.keystore().cert_path(), &mut report);
+    check_bind_addresses(config, &mut report);
+    check_file_descriptor_limit(config, &mut report);
+    check_clock(SystemTime::now(), &mut report);
+
+    for warning in &report.warnings {
+        warn!("server preflight warning", safe: { warning: warning });
+    }
+
+    if report.failures.is_empty() {
+        return Ok(());
+    }
+
+    Err(Error::internal_safe("server preflight checks failed")
+        .with_safe_param("failures", report.failures))
+}
+
+#[derive(Default)]
+struct Report {
+    failures: Vec<String>,
+    warnings: Vec<String>,
+}
+
+fn check_log_dir(dir: &Path, report: &mut Report) {
+    if let Err(e) = fs::create_dir_all(dir) {
+        report.failures.push(format!(
+            "unable to create log directory {}: {e}",
+            dir.display()
+        ));
+        return;
+    }
+
+    if let Err(e) = tempfile::tempfile_in(dir) {
+        report.failures.push(format!(
+            "log directory {} is not writable: {e}",
+            dir.display()
+        ));
+    }
+}
+
+fn check_key_file(path: &Path, report: &mut Report) {
+    if !check_readable("private key", path, report) {
+        return;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        if let Ok(metadata) = fs::metadata(path) {
+            let mode = metadata.permissions().mode();
+            if mode & 0o007 != 0 {
+                report.warnings.push(format!(
+                    "private key file {} is accessible to all users (mode {:o}); remove world permissions with \
+                        `chmod o-rwx`",
+                    path.display(),
+                    mode & 0o777,
+                ));
+            }
+        }
+    }
+}
+
+fn check_readable(kind: &str, path: &Path, report: &mut Report) -> bool {
+    match fs::File::open(path) {
+        Ok(_) => true,
+        Err(e) => {
+            report.failures.push(format!(
+                "unable to read {kind} file {}: {e}",
+                path.display()
+            ));
+            false
+        }
+    }
+}
+
+fn check_bind_addresses(config: &InstallConfig, report: &mut Report) {
+    let mut ports = vec![config.port()];
+    for port in [config.management_port(), config.http_redirect_port()]
+        .into_iter()
+        .flatten()
+    {
+        if !ports.contains(&port) {
+            ports.push(port);
+        }
+    }
+
+    for ip in config.server().bind_addresses() {
+        for port in &ports {
+            let addr = SocketAddr::new(*ip, *port);
+            if let Err(e) = TcpListener::bind(addr) {
+                report
+                    .failures
+                    .push(format!("unable to bind to {addr}: {e}"));
+            }
+        }
+    }
+}
+
+fn check_file_descriptor_limit(config: &InstallConfig, report: &mut Report) {
+    let Some(limit) = metrics::file_descriptor_limit() else {
+        return;
+    };
+
+    let max_connections = config.server().max_connections() as u64;
+    if limit < max_connections {
+        report.failures.push(format!(
+            "open file limit ({limit}) is below server.max-connections ({max_connections}); raise the limit with \
+                `ulimit -n` or lower max-connections"
+        ));
+    }
+}
+
+fn check_clock(now: SystemTime, report: &mut Report) {
+    if now < UNIX_EPOCH + MIN_SANE_TIME {
+        let secs = now.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        report.failures.push(format!(
+            "system clock is set to {secs} seconds since the Unix epoch, which is before 2024; synchronize the clock"
+        ));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clock() {
+        let mut report = Report::default();
+        check_clock(SystemTime::now(), &mut report);
+        assert_eq!(report.failures, Vec::<String>::new());
+
+        check_clock(UNIX_EPOCH + Duration::from_secs(60), &mut report);
+        assert_eq!(report.failures.len(), 1);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn key_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("key.pem");
+        fs::write(&path, "").unwrap();
+
+        let mut report = Report::default();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        check_key_file(&path, &mut report);
+        assert_eq!(report.failures, Vec::<String>::new());
+        assert_eq!(report.warnings, Vec::<String>::new());
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        check_key_file(&path, &mut report);
+        assert_eq!(report.failures, Vec::<String>::new());
+        assert_eq!(report.warnings.len(), 1);
+
+        check_key_file(&dir.path().join("missing.pem"), &mut report);
+        assert_eq!(report.failures.len(), 1);
+    }
+}
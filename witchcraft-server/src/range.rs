@@ -0,0 +1,484 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Range request support for binary endpoints.
+//!
+//! Endpoints serving large binary blobs can use the [`RangedResponseSerializer`] to honor single-range `Range`
+//! requests, allowing clients to resume interrupted downloads. The endpoint returns a [`Ranged`] value containing the
+//! total length of the blob and a function creating a body for a range of it. The serializer selects the range from
+//! the request headers and responds with `206 Partial Content`, `416 Range Not Satisfiable`, or the full blob with a
+//! `200 OK`.
+//!
+//! Requests for multiple ranges, malformed `Range` headers, and `If-Range` headers that don't match the blob's strong
+//! entity tag are answered with the full blob.
+//!
+//! Static files can be served with [`Ranged::file`], which reads the requested range directly from the file and tags
+//! it with an entity tag derived from the file's length and modification time.
+//!
+//! # Examples
+//!
+//! ```
+//! use bytes::Bytes;
+//! use conjure_error::Error;
+//! use conjure_http::server::AsyncWriteBody;
+//! use conjure_http::{conjure_endpoints, endpoint};
+//! use std::ops;
+//! use std::pin::Pin;
+//! use tokio::io::{AsyncWrite, AsyncWriteExt};
+//! use witchcraft_server::headers::EntityTag;
+//! use witchcraft_server::range::{Ranged, RangedResponseSerializer};
+//!
+//! struct BlobBody(Bytes);
+//!
+//! impl<W> AsyncWriteBody<W> for BlobBody
+//! where
+//!     W: AsyncWrite + Send,
+//! {
+//!     async fn write_body(self, mut w: Pin<&mut W>) -> Result<(), Error> {
+//!         w.write_all(&self.0).await.map_err(Error::internal_safe)
+//!     }
+//! }
+//!
+//! type RangedBlob = Ranged<Box<dyn FnOnce(ops::Range<u64>) -> BlobBody + Send>>;
+//!
+//! #[conjure_endpoints]
+//! trait BlobService<#[response_writer] O>
+//! where
+//!     O: AsyncWrite + Send,
+//! {
+//!     #[endpoint(method = GET, path = "/blob", produces = RangedResponseSerializer)]
+//!     async fn blob(&self) -> Result<RangedBlob, Error>;
+//! }
+//!
+//! struct BlobResource {
+//!     blob: Bytes,
+//! }
+//!
+//! impl<O> BlobService<O> for BlobResource
+//! where
+//!     O: AsyncWrite + Send,
+//! {
+//!     async fn blob(&self) -> Result<RangedBlob, Error> {
+//!         let blob = self.blob.clone();
+//!         let ranged = Ranged::new(
+//!             blob.len() as u64,
+//!             Box::new(move |range: ops::Range<u64>| {
+//!                 BlobBody(blob.slice(range.start as usize..range.end as usize))
+//!             }) as _,
+//!         );
+//!
+//!         Ok(ranged.etag(EntityTag::new(false, "v1").unwrap()))
+//!     }
+//! }
+//! ```
+use crate::headers::{EntityTag, HeaderMapExt, IfRange, Range};
+use conjure_error::Error;
+use conjure_http::server::{
+    AsyncResponseBody, AsyncSerializeResponse, AsyncWriteBody, BoxAsyncWriteBody, ConjureRuntime,
+    ResponseBody, SerializeResponse, WriteBody,
+};
+use http::header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG};
+use http::{HeaderMap, HeaderValue, Response, StatusCode};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops;
+use std::pin::Pin;
+use std::time::UNIX_EPOCH;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWrite};
+
+#[allow(clippy::declare_interior_mutable_const)]
+const BYTES: HeaderValue = HeaderValue::from_static("bytes");
+#[allow(clippy::declare_interior_mutable_const)]
+const APPLICATION_OCTET_STREAM: HeaderValue = HeaderValue::from_static("application/octet-stream");
+
+/// The portion of a representation selected by a request's range headers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeSelection {
+    /// The full representation.
+    Full,
+    /// A single satisfiable range of the representation.
+    Partial(ops::Range<u64>),
+    /// A range which does not overlap the representation.
+    Unsatisfiable,
+}
+
+impl RangeSelection {
+    /// Selects the portion of a representation with the specified length and optional entity tag requested by the
+    /// `Range` and `If-Range` headers.
+    pub fn new(request_headers: &HeaderMap, len: u64, etag: Option<&EntityTag>) -> Self {
+        let Ok(Some(range)) = request_headers.typed_get::<Range>() else {
+            return RangeSelection::Full;
+        };
+        let [range] = range.ranges() else {
+            return RangeSelection::Full;
+        };
+
+        match request_headers.typed_get::<IfRange>() {
+            Ok(None) => {}
+            Ok(Some(if_range)) if etag.is_some_and(|e| if_range.matches(e)) => {}
+            _ => return RangeSelection::Full,
+        }
+
+        match range.resolve(len) {
+            Some(range) => RangeSelection::Partial(range),
+            None => RangeSelection::Unsatisfiable,
+        }
+    }
+}
+
+/// A binary response supporting range requests.
+///
+/// It should be returned from a `GET` endpoint using the [`RangedResponseSerializer`].
+pub struct Ranged<F> {
+    len: u64,
+    etag: Option<EntityTag>,
+    content_type: HeaderValue,
+    body: F,
+}
+
+impl<F> Ranged<F> {
+    /// Creates a new `Ranged` response for a blob of the specified length.
+    ///
+    /// The body function is called with the half-open range of the blob to write. The body it returns must write
+    /// exactly that many bytes.
+    pub fn new(len: u64, body: F) -> Self {
+        Ranged {
+            len,
+            etag: None,
+            content_type: APPLICATION_OCTET_STREAM,
+            body,
+        }
+    }
+
+    /// Sets the entity tag of the blob.
+    ///
+    /// `If-Range` headers are only honored if a strong entity tag is set.
+    pub fn etag(mut self, etag: EntityTag) -> Self {
+        self.etag = Some(etag);
+        self
+    }
+
+    /// Sets the content type of the blob.
+    ///
+    /// Defaults to `application/octet-stream`.
+    pub fn content_type(mut self, content_type: HeaderValue) -> Self {
+        self.content_type = content_type;
+        self
+    }
+
+    fn into_response<B, T>(
+        self,
+        request_headers: &HeaderMap,
+        empty: T,
+        streaming: impl FnOnce(B) -> T,
+    ) -> Response<T>
+    where
+        F: FnOnce(ops::Range<u64>) -> B,
+    {
+        let selection = RangeSelection::new(request_headers, self.len, self.etag.as_ref());
+
+        let (status, range) = match selection {
+            RangeSelection::Full => (StatusCode::OK, 0..self.len),
+            RangeSelection::Partial(range) => (StatusCode::PARTIAL_CONTENT, range),
+            RangeSelection::Unsatisfiable => {
+                let mut response = Response::new(empty);
+                *response.status_mut() = StatusCode::RANGE_NOT_SATISFIABLE;
+                response.headers_mut().insert(ACCEPT_RANGES, BYTES);
+                response.headers_mut().insert(
+                    CONTENT_RANGE,
+                    HeaderValue::try_from(format!("bytes */{}", self.len)).unwrap(),
+                );
+                return response;
+            }
+        };
+
+        let mut response = Response::new(streaming((self.body)(range.clone())));
+        *response.status_mut() = status;
+        let headers = response.headers_mut();
+        headers.insert(ACCEPT_RANGES, BYTES);
+        headers.insert(CONTENT_TYPE, self.content_type);
+        headers.insert(CONTENT_LENGTH, HeaderValue::from(range.end - range.start));
+        if status == StatusCode::PARTIAL_CONTENT {
+            headers.insert(
+                CONTENT_RANGE,
+                HeaderValue::try_from(format!(
+                    "bytes {}-{}/{}",
+                    range.start,
+                    range.end - 1,
+                    self.len
+                ))
+                .unwrap(),
+            );
+        }
+        if let Some(etag) = self.etag {
+            headers.insert(ETAG, HeaderValue::try_from(etag.to_string()).unwrap());
+        }
+
+        response
+    }
+}
+
+/// A function creating the body for a range of a file.
+pub type FileRangeFn = Box<dyn FnOnce(ops::Range<u64>) -> FileRange + Send>;
+
+impl Ranged<FileRangeFn> {
+    /// Creates a new `Ranged` response serving a file.
+    ///
+    /// The response's strong entity tag is derived from the file's length and modification time, so `If-Range`
+    /// requests are only honored while the file is unchanged.
+    pub fn file(file: File) -> Result<Self, Error> {
+        let metadata = file.metadata().map_err(Error::internal_safe)?;
+        let len = metadata.len();
+
+        let mut ranged = Ranged::new(
+            len,
+            Box::new(move |range: ops::Range<u64>| FileRange { file, range }) as FileRangeFn,
+        );
+
+        if let Ok(modified) = metadata.modified() {
+            let modified = modified
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            let etag = EntityTag::new(false, &format!("{modified:x}-{len:x}")).unwrap();
+            ranged = ranged.etag(etag);
+        }
+
+        Ok(ranged)
+    }
+}
+
+/// A body writing a range of a file.
+///
+/// It supports both blocking and async endpoints.
+pub struct FileRange {
+    file: File,
+    range: ops::Range<u64>,
+}
+
+impl<W> WriteBody<W> for FileRange
+where
+    W: Write,
+{
+    fn write_body(self: Box<Self>, w: &mut W) -> Result<(), Error> {
+        let FileRange { mut file, range } = *self;
+
+        file.seek(SeekFrom::Start(range.start))
+            .map_err(Error::internal_safe)?;
+        let written =
+            io::copy(&mut file.take(range.end - range.start), w).map_err(Error::internal_safe)?;
+
+        check_len(&range, written)
+    }
+}
+
+impl<W> AsyncWriteBody<W> for FileRange
+where
+    W: AsyncWrite + Send,
+{
+    async fn write_body(self, mut w: Pin<&mut W>) -> Result<(), Error> {
+        let FileRange { file, range } = self;
+
+        let mut file = tokio::fs::File::from_std(file);
+        file.seek(SeekFrom::Start(range.start))
+            .await
+            .map_err(Error::internal_safe)?;
+        let written = tokio::io::copy(&mut file.take(range.end - range.start), &mut w)
+            .await
+            .map_err(Error::internal_safe)?;
+
+        check_len(&range, written)
+    }
+}
+
+fn check_len(range: &ops::Range<u64>, written: u64) -> Result<(), Error> {
+    let expected = range.end - range.start;
+    if written == expected {
+        return Ok(());
+    }
+
+    Err(Error::internal_safe("file shorter than expected")
+        .with_safe_param("expected", expected)
+        .with_safe_param("written", written))
+}
+
+/// A response serializer for [`Ranged`] values.
+///
+/// It supports both blocking and async endpoints.
+pub enum RangedResponseSerializer {}
+
+impl<F, B, W> SerializeResponse<Ranged<F>, W> for RangedResponseSerializer
+where
+    F: FnOnce(ops::Range<u64>) -> B,
+    B: WriteBody<W> + 'static,
+{
+    fn serialize(
+        _: &ConjureRuntime,
+        request_headers: &HeaderMap,
+        value: Ranged<F>,
+    ) -> Result<Response<ResponseBody<W>>, Error> {
+        Ok(
+            value.into_response(request_headers, ResponseBody::Empty, |body| {
+                ResponseBody::Streaming(Box::new(body))
+            }),
+        )
+    }
+}
+
+impl<F, B, W> AsyncSerializeResponse<Ranged<F>, W> for RangedResponseSerializer
+where
+    F: FnOnce(ops::Range<u64>) -> B,
+    B: AsyncWriteBody<W> + Send + 'static,
+{
+    fn serialize(
+        _: &ConjureRuntime,
+        request_headers: &HeaderMap,
+        value: Ranged<F>,
+    ) -> Result<Response<AsyncResponseBody<W>>, Error> {
+        Ok(
+            value.into_response(request_headers, AsyncResponseBody::Empty, |body| {
+                AsyncResponseBody::Streaming(BoxAsyncWriteBody::new(body))
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use http::header::{IF_RANGE, RANGE};
+
+    const BLOB: &[u8] = b"0123456789";
+
+    fn serialize(request_headers: &[(&str, &'static str)]) -> (Response<()>, Vec<u8>) {
+        let request_headers = request_headers
+            .iter()
+            .map(|(k, v)| (k.parse().unwrap(), HeaderValue::from_static(v)))
+            .collect();
+        let ranged = Ranged::new(BLOB.len() as u64, |range: ops::Range<u64>| {
+            BLOB[range.start as usize..range.end as usize].to_vec()
+        })
+        .etag(EntityTag::new(false, "v1").unwrap());
+
+        let response = <RangedResponseSerializer as SerializeResponse<_, Vec<u8>>>::serialize(
+            &ConjureRuntime::new(),
+            &request_headers,
+            ranged,
+        )
+        .unwrap();
+
+        let (parts, body) = response.into_parts();
+        let mut buf = vec![];
+        match body {
+            ResponseBody::Empty => {}
+            ResponseBody::Streaming(body) => body.write_body(&mut buf).unwrap(),
+            ResponseBody::Fixed(_) => panic!("unexpected fixed body"),
+        }
+
+        (Response::from_parts(parts, ()), buf)
+    }
+
+    #[test]
+    fn full() {
+        let (response, body) = serialize(&[]);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get(ACCEPT_RANGES).unwrap(), "bytes");
+        assert_eq!(response.headers().get(CONTENT_LENGTH).unwrap(), "10");
+        assert_eq!(response.headers().get(ETAG).unwrap(), "\"v1\"");
+        assert_eq!(response.headers().get(CONTENT_RANGE), None);
+        assert_eq!(body, BLOB);
+    }
+
+    #[test]
+    fn partial() {
+        let (response, body) = serialize(&[(RANGE.as_str(), "bytes=4-")]);
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            response.headers().get(CONTENT_RANGE).unwrap(),
+            "bytes 4-9/10"
+        );
+        assert_eq!(response.headers().get(CONTENT_LENGTH).unwrap(), "6");
+        assert_eq!(body, b"456789");
+
+        let (response, body) = serialize(&[(RANGE.as_str(), "bytes=-3")]);
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            response.headers().get(CONTENT_RANGE).unwrap(),
+            "bytes 7-9/10"
+        );
+        assert_eq!(body, b"789");
+    }
+
+    #[test]
+    fn unsatisfiable() {
+        let (response, body) = serialize(&[(RANGE.as_str(), "bytes=10-20")]);
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers().get(CONTENT_RANGE).unwrap(), "bytes */10");
+        assert_eq!(body, b"");
+    }
+
+    #[test]
+    fn file() {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(BLOB).unwrap();
+
+        let ranged = Ranged::file(file).unwrap();
+        let etag = ranged.etag.clone().unwrap();
+        assert!(!etag.is_weak());
+
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert(RANGE, HeaderValue::from_static("bytes=2-5"));
+        request_headers.insert(IF_RANGE, HeaderValue::try_from(etag.to_string()).unwrap());
+        let response = <RangedResponseSerializer as SerializeResponse<_, Vec<u8>>>::serialize(
+            &ConjureRuntime::new(),
+            &request_headers,
+            ranged,
+        )
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            response.headers().get(CONTENT_RANGE).unwrap(),
+            "bytes 2-5/10"
+        );
+
+        let mut buf = vec![];
+        match response.into_body() {
+            ResponseBody::Streaming(body) => body.write_body(&mut buf).unwrap(),
+            _ => panic!("expected streaming body"),
+        }
+        assert_eq!(buf, b"2345");
+    }
+
+    #[test]
+    fn ignored_ranges() {
+        for headers in [
+            &[(RANGE.as_str(), "bytes=0-1,4-5")][..],
+            &[(RANGE.as_str(), "lines=0-1")],
+            &[(RANGE.as_str(), "bytes=0-1"), (IF_RANGE.as_str(), "\"v0\"")],
+            &[
+                (RANGE.as_str(), "bytes=0-1"),
+                (IF_RANGE.as_str(), "W/\"v1\""),
+            ],
+        ] {
+            let (response, body) = serialize(headers);
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(body, BLOB);
+        }
+
+        let (response, body) =
+            serialize(&[(RANGE.as_str(), "bytes=0-1"), (IF_RANGE.as_str(), "\"v1\"")]);
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body, b"01");
+    }
+}
@@ -74,13 +74,30 @@ impl ReadinessCheckRegistry {
         self.register_inner(Arc::new(check))
     }
 
-    fn register_inner(&self, check: Arc<dyn ReadinessCheck>) {
+    /// Registers a new check if one does not already exist with the same type.
+    ///
+    /// The registered check will be returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the check's type is not `SCREAMING_SNAKE_CASE`.
+    pub fn register_if_absent<T>(&self, check: T) -> Arc<dyn ReadinessCheck>
+    where
+        T: ReadinessCheck,
+    {
         let type_ = check.type_();
+        self.check_type(type_);
 
-        assert!(
-            TYPE_PATTERN.is_match(type_),
-            "{type_} must `SCREAMING_SNAKE_CASE",
-        );
+        self.checks
+            .lock()
+            .entry(type_.to_string())
+            .or_insert_with(|| Arc::new(check))
+            .clone()
+    }
+
+    fn register_inner(&self, check: Arc<dyn ReadinessCheck>) {
+        let type_ = check.type_();
+        self.check_type(type_);
 
         match self.checks.lock().entry(type_.to_string()) {
             hash_map::Entry::Occupied(_) => {
@@ -92,6 +109,13 @@ impl ReadinessCheckRegistry {
         }
     }
 
+    fn check_type(&self, type_: &str) {
+        assert!(
+            TYPE_PATTERN.is_match(type_),
+            "{type_} must `SCREAMING_SNAKE_CASE",
+        );
+    }
+
     pub(crate) fn run_checks(&self) -> BTreeMap<String, ReadinessCheckMetadata> {
         // A bit of extra complexity to allow registration while we're running checks.
         let mut results = BTreeMap::new();
@@ -117,7 +141,7 @@ impl ReadinessCheckRegistry {
     }
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 pub(crate) struct ReadinessCheckMetadata {
     r#type: String,
     pub(crate) successful: bool,
@@ -0,0 +1,208 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Retryability signaling for errors.
+//!
+//! Handlers can declare whether the failure an [`Error`] represents is worth retrying with the [`ErrorExt`] extension
+//! trait. When the error is returned to a client, the server translates that into response headers:
+//!
+//! * `Retryable: true` or `Retryable: false` indicates whether the client should retry the request.
+//! * `Retry-After` contains the number of seconds the client should wait before retrying, if known.
+//!
+//! Errors which are not explicitly marked fall back to the semantics of their kind: throttle and unavailable errors
+//! are retryable, with throttle errors specifying their delay, and other errors are left unspecified so clients apply
+//! their default policy based on the status code.
+//!
+//! The retryability of each error response is also recorded in the `server.response.retryability` meter, tagged with
+//! the service name, endpoint, and `retryable`.
+//!
+//! # Examples
+//!
+//! ```
+//! use conjure_error::{Error, InvalidArgument};
+//! use std::time::Duration;
+//! use witchcraft_server::retry::ErrorExt;
+//!
+//! fn lock_conflict() -> Error {
+//!     Error::internal_safe("lock held by another writer").retry_after(Duration::from_secs(2))
+//! }
+//!
+//! fn bad_input() -> Error {
+//!     Error::service_safe("malformed input", InvalidArgument::new()).non_retryable()
+//! }
+//! ```
+use conjure_error::{Error, ErrorKind};
+use conjure_object::Any;
+use http::header::RETRY_AFTER;
+use http::{HeaderMap, HeaderName, HeaderValue};
+use std::time::Duration;
+
+/// The name of the header indicating whether a failed request should be retried.
+#[allow(clippy::declare_interior_mutable_const)]
+pub const RETRYABLE: HeaderName = HeaderName::from_static("retryable");
+
+const RETRYABLE_PARAM: &str = "retryable";
+const RETRY_AFTER_PARAM: &str = "retryAfterSeconds";
+
+/// An extension trait adding retryability markers to [`Error`].
+///
+/// The markers are stored as the `retryable` and `retryAfterSeconds` safe parameters of the error, so they are also
+/// included when the error is logged.
+pub trait ErrorExt {
+    /// Marks the error as retryable.
+    fn retryable(self) -> Self;
+
+    /// Marks the error as retryable after the specified delay.
+    ///
+    /// The delay is rounded up to the nearest second.
+    fn retry_after(self, delay: Duration) -> Self;
+
+    /// Marks the error as not retryable.
+    fn non_retryable(self) -> Self;
+}
+
+impl ErrorExt for Error {
+    fn retryable(self) -> Self {
+        self.with_safe_param(RETRYABLE_PARAM, true)
+    }
+
+    fn retry_after(self, delay: Duration) -> Self {
+        let seconds = delay.as_secs() + u64::from(delay.subsec_nanos() > 0);
+        self.retryable().with_safe_param(RETRY_AFTER_PARAM, seconds)
+    }
+
+    fn non_retryable(self) -> Self {
+        self.with_safe_param(RETRYABLE_PARAM, false)
+    }
+}
+
+/// Whether a failed request should be retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retryability {
+    /// The request can be retried, optionally after a delay.
+    Retryable(Option<Duration>),
+    /// The request should not be retried.
+    NonRetryable,
+}
+
+impl Retryability {
+    /// Determines the retryability of an error.
+    ///
+    /// Returns `None` if the error has not been marked and its kind does not imply a retryability.
+    pub fn of(error: &Error) -> Option<Self> {
+        let (retryable, delay) = match error.kind() {
+            ErrorKind::Throttle(throttle) => (Some(true), throttle.duration()),
+            ErrorKind::Unavailable(_) => (Some(true), None),
+            _ => (None, None),
+        };
+
+        let params = error.safe_params();
+        let retryable = match params.get(RETRYABLE_PARAM) {
+            Some(retryable) => *retryable == Any::new(true).unwrap(),
+            None => retryable?,
+        };
+        if !retryable {
+            return Some(Retryability::NonRetryable);
+        }
+
+        let delay = params
+            .get(RETRY_AFTER_PARAM)
+            .and_then(|s| s.clone().deserialize_into::<u64>().ok())
+            .map(Duration::from_secs)
+            .or(delay);
+        Some(Retryability::Retryable(delay))
+    }
+
+    /// Returns `true` if the request can be retried.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Retryability::Retryable(_))
+    }
+
+    pub(crate) fn apply(&self, headers: &mut HeaderMap) {
+        match self {
+            Retryability::Retryable(delay) => {
+                headers.insert(RETRYABLE, HeaderValue::from_static("true"));
+                if let Some(delay) = delay {
+                    headers.insert(RETRY_AFTER, HeaderValue::from(delay.as_secs()));
+                }
+            }
+            Retryability::NonRetryable => {
+                headers.insert(RETRYABLE, HeaderValue::from_static("false"));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use conjure_error::{InvalidArgument, NotFound};
+
+    #[test]
+    fn markers() {
+        let error = Error::service_safe("", NotFound::new());
+        assert_eq!(Retryability::of(&error), None);
+
+        let error = Error::internal_safe("").retryable();
+        assert_eq!(
+            Retryability::of(&error),
+            Some(Retryability::Retryable(None))
+        );
+
+        let error = Error::internal_safe("").retry_after(Duration::from_millis(1500));
+        assert_eq!(
+            Retryability::of(&error),
+            Some(Retryability::Retryable(Some(Duration::from_secs(2))))
+        );
+
+        let error = Error::service_safe("", InvalidArgument::new()).non_retryable();
+        assert_eq!(Retryability::of(&error), Some(Retryability::NonRetryable));
+    }
+
+    #[test]
+    fn kind_defaults() {
+        let error = Error::throttle_for_safe("", Duration::from_secs(5));
+        assert_eq!(
+            Retryability::of(&error),
+            Some(Retryability::Retryable(Some(Duration::from_secs(5))))
+        );
+
+        let error = Error::unavailable_safe("");
+        assert_eq!(
+            Retryability::of(&error),
+            Some(Retryability::Retryable(None))
+        );
+
+        let error = Error::throttle_for_safe("", Duration::from_secs(5)).retryable();
+        assert_eq!(
+            Retryability::of(&error),
+            Some(Retryability::Retryable(Some(Duration::from_secs(5))))
+        );
+
+        let error = Error::unavailable_safe("").non_retryable();
+        assert_eq!(Retryability::of(&error), Some(Retryability::NonRetryable));
+    }
+
+    #[test]
+    fn headers() {
+        let mut headers = HeaderMap::new();
+        Retryability::Retryable(Some(Duration::from_secs(3))).apply(&mut headers);
+        assert_eq!(headers[RETRYABLE], "true");
+        assert_eq!(headers[RETRY_AFTER], "3");
+
+        let mut headers = HeaderMap::new();
+        Retryability::NonRetryable.apply(&mut headers);
+        assert_eq!(headers[RETRYABLE], "false");
+        assert!(!headers.contains_key(RETRY_AFTER));
+    }
+}
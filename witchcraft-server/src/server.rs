@@ -13,53 +13,84 @@
 // limitations under the License.
 use crate::logging::Loggers;
 use crate::service::accept::AcceptService;
+use crate::service::admission::AdmissionLayer;
 use crate::service::audit_log::AuditLogLayer;
+use crate::service::base_url::BaseUrlLayer;
+use crate::service::cache_control::CacheControlLayer;
 use crate::service::cancellation::CancellationLayer;
 use crate::service::catch_unwind::CatchUnwindLayer;
+use crate::service::classification::ClassificationLayer;
 use crate::service::client_certificate::ClientCertificateLayer;
+use crate::service::compression::{CompressionLayer, DecompressionBody};
 use crate::service::connection_limit::ConnectionLimitLayer;
 use crate::service::connection_metrics::ConnectionMetricsLayer;
+use crate::service::cost_accounting::{CostAccountingLayer, CostAccountingRequestBody};
+use crate::service::deadline::DeadlineLayer;
 use crate::service::deprecation_header::DeprecationHeaderLayer;
 use crate::service::endpoint_health::EndpointHealthLayer;
 use crate::service::endpoint_metrics::EndpointMetricsLayer;
 use crate::service::error_log::ErrorLogLayer;
+use crate::service::fault_injection::FaultInjectionLayer;
+use crate::service::file_descriptor_limit::FileDescriptorLimitLayer;
+use crate::service::geo::GeoLayer;
 use crate::service::graceful_shutdown::GracefulShutdownLayer;
-use crate::service::gzip::GzipLayer;
 use crate::service::handler::HandlerService;
 use crate::service::hyper::{HyperService, NewConnection};
 use crate::service::idle_connection::IdleConnectionLayer;
+use crate::service::in_flight::InFlightLayer;
+use crate::service::ip_filter::{IpFilterLayer, IpFilterRequestLayer};
 use crate::service::keep_alive_header::KeepAliveHeaderLayer;
 use crate::service::mdc::MdcLayer;
-use crate::service::no_caching::NoCachingLayer;
 use crate::service::peer_addr::PeerAddrLayer;
+use crate::service::plaintext_detection::PlaintextDetectionLayer;
+use crate::service::read_timeout::{ReadTimeoutBody, ReadTimeoutLayer};
+use crate::service::redirect::RedirectLayer;
 use crate::service::request_id::RequestIdLayer;
-use crate::service::request_log::{RequestLogLayer, RequestLogRequestBody};
+use crate::service::request_log::{Disposition, RequestLogLayer, RequestLogRequestBody};
+use crate::service::request_priority::RequestPriorityLayer;
+use crate::service::request_size_limit::{RequestSizeLimitBody, RequestSizeLimitLayer};
+use crate::service::response_limit::ResponseLimitLayer;
 use crate::service::routing::RoutingLayer;
 use crate::service::server_header::ServerHeaderLayer;
 use crate::service::server_metrics::ServerMetricsLayer;
+use crate::service::shutdown_signal::ShutdownSignalLayer;
+use crate::service::slow_client::{MinTransferRateBody, SlowClientLayer};
 use crate::service::spans::{SpannedBody, SpansLayer};
+use crate::service::strict_parsing::StrictParsingLayer;
 use crate::service::tls::TlsLayer;
 use crate::service::tls_metrics::TlsMetricsLayer;
 use crate::service::trace_id_header::TraceIdHeaderLayer;
 use crate::service::trace_propagation::TracePropagationLayer;
 use crate::service::unverified_jwt::UnverifiedJwtLayer;
+use crate::service::user_agent::UserAgentLayer;
 use crate::service::web_security::WebSecurityLayer;
 use crate::service::witchcraft_mdc::WitchcraftMdcLayer;
+use crate::service::write_timeout::WriteTimeoutLayer;
 use crate::service::{Service, ServiceBuilder};
-use crate::Witchcraft;
+use crate::{ShutdownPhase, Witchcraft};
 use conjure_error::Error;
 use hyper::body::Incoming;
+use refreshable::Refreshable;
 use std::mem;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::task;
-use witchcraft_log::debug;
+use witchcraft_log::{debug, info};
+use witchcraft_server_config::runtime::RuntimeConfig;
 
-pub type RawBody = RequestLogRequestBody<SpannedBody<Incoming>>;
+pub type RawBody = RequestSizeLimitBody<
+    DecompressionBody<
+        CostAccountingRequestBody<
+            RequestLogRequestBody<SpannedBody<ReadTimeoutBody<MinTransferRateBody<Incoming>>>>,
+        >,
+    >,
+>;
 
 #[derive(Copy, Clone)]
 pub enum Listener {
     Service,
     Management,
+    HttpRedirect,
 }
 
 impl Listener {
@@ -67,6 +98,7 @@ impl Listener {
         match self {
             Listener::Service => "service",
             Listener::Management => "management",
+            Listener::HttpRedirect => "http-redirect",
         }
     }
 }
@@ -74,55 +106,144 @@ impl Listener {
 pub(crate) async fn start(
     witchcraft: &mut Witchcraft,
     loggers: &Loggers,
+    runtime_config: &Refreshable<RuntimeConfig, Error>,
     listener: Listener,
     port: u16,
-) -> Result<(), Error> {
+) -> Result<SocketAddr, Error> {
     // This service handles individual HTTP requests, each running concurrently.
     let request_service = ServiceBuilder::new()
-        .layer(RoutingLayer::new(mem::take(&mut witchcraft.endpoints)))
+        .layer(StrictParsingLayer::new(
+            &witchcraft.install_config,
+            &witchcraft.metrics,
+        ))
+        .layer(ReadTimeoutLayer::new(&witchcraft.install_config))
+        .layer(RedirectLayer::new(runtime_config))
+        .layer(RoutingLayer::new(witchcraft.take_endpoints()))
         .layer(RequestIdLayer)
         .layer(TracePropagationLayer)
         .layer(SpansLayer)
         .layer(UnverifiedJwtLayer)
         .layer(MdcLayer)
         .layer(WitchcraftMdcLayer)
-        .layer(RequestLogLayer::new(loggers.request_logger.clone()))
+        .layer(GeoLayer::new(
+            witchcraft.geo_lookup.clone(),
+            &witchcraft.metrics,
+        ))
+        .layer(ClassificationLayer::new(
+            witchcraft.request_classifier.clone(),
+            &witchcraft.metrics,
+        ))
+        .layer(RequestPriorityLayer::new(
+            runtime_config,
+            &witchcraft.metrics,
+        ))
+        .layer(ShutdownSignalLayer::new(&witchcraft.shutdown_signal))
+        .layer(DeadlineLayer)
+        .layer(BaseUrlLayer::new(&witchcraft.install_config))
+        .layer(RequestLogLayer::new(
+            loggers.request_logger.clone(),
+            loggers.access_logger.clone(),
+        ))
         .layer(AuditLogLayer::new(loggers.audit_logger.clone()))
         .layer(CancellationLayer)
-        .layer(GzipLayer::new(&witchcraft.install_config))
+        .layer(CostAccountingLayer::new(witchcraft.cost_ledger.clone()))
+        .layer(CompressionLayer::new(
+            &witchcraft.install_config,
+            runtime_config,
+        ))
+        .layer(RequestSizeLimitLayer::new(runtime_config))
         .layer(DeprecationHeaderLayer)
         .layer(KeepAliveHeaderLayer::new(&witchcraft.install_config))
         .layer(ServerHeaderLayer::new(&witchcraft.install_config)?)
-        .layer(NoCachingLayer)
+        .layer(CacheControlLayer::new(mem::take(
+            &mut witchcraft.cache_policies,
+        )))
         .layer(WebSecurityLayer)
         .layer(TraceIdHeaderLayer)
         .layer(ServerMetricsLayer::new(&witchcraft.metrics, listener))
-        .layer(EndpointMetricsLayer)
+        .layer(InFlightLayer::new(&witchcraft.in_flight))
+        .layer(EndpointMetricsLayer::new(runtime_config))
         .layer(EndpointHealthLayer)
         .layer(ErrorLogLayer)
         .layer(CatchUnwindLayer)
-        .service(HandlerService);
+        .layer(IpFilterRequestLayer::new(runtime_config))
+        .layer(UserAgentLayer::new(&witchcraft.metrics, runtime_config))
+        .layer(AdmissionLayer::new(
+            &witchcraft.memory_admission,
+            &witchcraft.standby,
+        ))
+        .layer(ResponseLimitLayer::new(runtime_config, &witchcraft.metrics))
+        .layer(FaultInjectionLayer::new(
+            runtime_config,
+            &witchcraft.metrics,
+        ))
+        .service(HandlerService::new(runtime_config));
 
     // This layer handles individual TCP connections, each running concurrently.
     let handle_service = ServiceBuilder::new()
         .layer(PeerAddrLayer)
-        .layer(TlsLayer::new(&witchcraft.install_config)?)
+        .layer(IpFilterLayer::new(
+            &witchcraft.metrics,
+            runtime_config,
+            listener,
+        ))
+        .layer(PlaintextDetectionLayer::new(&witchcraft.metrics, listener))
+        .layer(TlsLayer::new(
+            &witchcraft.install_config,
+            &witchcraft.acme_challenges,
+            witchcraft.ocsp_stapler.as_ref(),
+            witchcraft.private_key_provider.as_ref(),
+        )?)
         .layer(TlsMetricsLayer::new(&witchcraft.metrics))
         .layer(ClientCertificateLayer)
-        .layer(GracefulShutdownLayer::new(&mut witchcraft.shutdown_hooks))
+        .layer(GracefulShutdownLayer::new(
+            &mut witchcraft.shutdown_hooks,
+            &witchcraft.metrics,
+            listener,
+        ))
         .layer(IdleConnectionLayer::new(&witchcraft.install_config))
-        .service(HyperService::new(request_service));
+        .layer(SlowClientLayer::new(
+            &witchcraft.install_config,
+            &witchcraft.metrics,
+        ))
+        .service(HyperService::new(
+            &witchcraft.install_config,
+            &witchcraft.metrics,
+            witchcraft.extended_connect,
+            request_service,
+        ));
     let handle_service = Arc::new(handle_service);
 
+    let accept = AcceptService::new(
+        &witchcraft.install_config,
+        &witchcraft.metrics,
+        listener,
+        port,
+    )?;
+    let local_addrs = accept.local_addrs()?;
+    info!(
+        "server listening",
+        safe: {
+            listener: listener.tag(),
+            port: local_addrs[0].port(),
+            addresses: format_args!("{local_addrs:?}"),
+        },
+    );
+
     // This layer produces TCP connections, running serially.
     let accept_service = ServiceBuilder::new()
+        .layer(FileDescriptorLimitLayer::new(&witchcraft.file_descriptors))
         .layer(ConnectionLimitLayer::new(&witchcraft.install_config))
         .layer(ConnectionMetricsLayer::new(
             &witchcraft.install_config,
             &witchcraft.metrics,
             listener,
         ))
-        .service(AcceptService::new(port)?);
+        .layer(WriteTimeoutLayer::new(
+            &witchcraft.install_config,
+            &witchcraft.metrics,
+        ))
+        .service(accept);
 
     let handle = task::spawn(async move {
         loop {
@@ -136,16 +257,23 @@ pub(crate) async fn start(
                 let handle_service = handle_service.clone();
                 async move {
                     if let Err(e) = handle_service.call(connection).await {
-                        debug!("http connection terminated", error: e);
+                        let disposition = Disposition::from_error(e.cause());
+                        debug!(
+                            "http connection terminated",
+                            safe: {
+                                disposition: disposition.as_str(),
+                            },
+                            error: e,
+                        );
                     }
                 }
             });
         }
     });
 
-    witchcraft.on_shutdown(async move {
+    witchcraft.on_shutdown_phase(ShutdownPhase::StopAccepting, listener.tag(), async move {
         handle.abort();
     });
 
-    Ok(())
+    Ok(local_addrs[0])
 }
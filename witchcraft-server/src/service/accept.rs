@@ -11,46 +11,120 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use crate::server::Listener;
 use crate::service::peer_addr::GetPeerAddr;
+use crate::service::plaintext_detection::Peek;
 use crate::service::Service;
 use conjure_error::Error;
+use futures_util::future;
 use socket2::{Domain, SockAddr, SockRef, Socket, TcpKeepalive, Type};
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
 use std::time::Duration;
 use std::{fs, io};
+use tokio::io::ReadBuf;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::time;
 use witchcraft_log::warn;
+use witchcraft_metrics::{Meter, MetricId, MetricRegistry};
+use witchcraft_server_config::install::InstallConfig;
 
 // This is pretty arbitrary - I just copied it from some Cloudflare blog post.
 const TCP_KEEPALIVE: Duration = Duration::from_secs(3 * 60);
 
 /// The root service of the socket service stack which accept raw TCP connections.
 pub struct AcceptService {
+    listeners: Vec<BoundListener>,
+    next: AtomicUsize,
+}
+
+struct BoundListener {
     listener: TcpListener,
+    accepted: Arc<Meter>,
 }
 
 impl AcceptService {
-    pub fn new(port: u16) -> Result<Self, Error> {
-        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port);
-
-        let listener =
-            Socket::new(Domain::IPV4, Type::STREAM, None).map_err(Error::internal_safe)?;
-        listener
-            .set_nonblocking(true)
-            .map_err(Error::internal_safe)?;
-        listener
-            .set_reuse_address(true)
-            .map_err(Error::internal_safe)?;
-        listener
-            .bind(&SockAddr::from(addr))
-            .map_err(Error::internal_safe)?;
-        listener.listen(somaxconn()).map_err(Error::internal_safe)?;
-
-        let listener = TcpListener::from_std(listener.into()).map_err(Error::internal_safe)?;
-
-        Ok(AcceptService { listener })
+    pub fn new(
+        config: &InstallConfig,
+        metrics: &MetricRegistry,
+        listener: Listener,
+        port: u16,
+    ) -> Result<Self, Error> {
+        let mut listeners = vec![];
+        let mut port = port;
+
+        for ip in config.server().bind_addresses() {
+            let tcp_listener = bind(SocketAddr::new(*ip, port))?;
+
+            // If an ephemeral port was requested, all addresses should share the one assigned to the first.
+            port = tcp_listener
+                .local_addr()
+                .map_err(Error::internal_safe)?
+                .port();
+
+            let accepted = metrics.meter(
+                MetricId::new("server.connection.accepted")
+                    .with_tag("listener", listener.tag())
+                    .with_tag("address", ip.to_string()),
+            );
+
+            listeners.push(BoundListener {
+                listener: tcp_listener,
+                accepted,
+            });
+        }
+
+        Ok(AcceptService {
+            listeners,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn local_addrs(&self) -> Result<Vec<SocketAddr>, Error> {
+        self.listeners
+            .iter()
+            .map(|l| l.listener.local_addr().map_err(Error::internal_safe))
+            .collect()
+    }
+
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<(io::Result<TcpStream>, &BoundListener)> {
+        // Start after the listener which last accepted a connection so a busy one can't starve the others.
+        let start = self.next.load(Ordering::Relaxed);
+        let listeners = self.listeners.iter().enumerate().cycle();
+
+        for (i, listener) in listeners.skip(start).take(self.listeners.len()) {
+            if let Poll::Ready(r) = listener.listener.poll_accept(cx) {
+                self.next
+                    .store((i + 1) % self.listeners.len(), Ordering::Relaxed);
+                return Poll::Ready((r.map(|(socket, _)| socket), listener));
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+fn bind(addr: SocketAddr) -> Result<TcpListener, Error> {
+    let listener =
+        Socket::new(Domain::for_address(addr), Type::STREAM, None).map_err(Error::internal_safe)?;
+    if addr.is_ipv6() {
+        // Allow a separate IPv4 listener to bind the same port.
+        listener.set_only_v6(true).map_err(Error::internal_safe)?;
     }
+    listener
+        .set_nonblocking(true)
+        .map_err(Error::internal_safe)?;
+    listener
+        .set_reuse_address(true)
+        .map_err(Error::internal_safe)?;
+    listener
+        .bind(&SockAddr::from(addr))
+        .map_err(|e| Error::internal_safe(e).with_safe_param("address", addr.to_string()))?;
+    listener.listen(somaxconn()).map_err(Error::internal_safe)?;
+
+    TcpListener::from_std(listener.into()).map_err(Error::internal_safe)
 }
 
 impl Service<()> for AcceptService {
@@ -58,9 +132,13 @@ impl Service<()> for AcceptService {
 
     async fn call(&self, _: ()) -> Self::Response {
         loop {
-            match self.listener.accept().await {
-                Ok((socket, _)) => match setup_socket(&socket) {
-                    Ok(()) => return socket,
+            let (result, listener) = future::poll_fn(|cx| self.poll_accept(cx)).await;
+            match result {
+                Ok(socket) => match setup_socket(&socket) {
+                    Ok(()) => {
+                        listener.accepted.mark(1);
+                        return socket;
+                    }
                     Err(e) => warn!("error configuring socket", error: Error::internal_safe(e)),
                 },
                 // There are 3 broad categories of error we can encounter from accept:
@@ -104,3 +182,45 @@ impl GetPeerAddr for TcpStream {
         self.peer_addr().map_err(Error::internal_safe)
     }
 }
+
+impl Peek for TcpStream {
+    fn poll_peek(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<usize>> {
+        self.poll_peek(cx, buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    async fn listener() -> BoundListener {
+        BoundListener {
+            listener: TcpListener::bind("127.0.0.1:0").await.unwrap(),
+            accepted: MetricRegistry::new().meter("server.connection.accepted"),
+        }
+    }
+
+    #[tokio::test]
+    async fn listeners_take_turns() {
+        let service = AcceptService {
+            listeners: vec![listener().await, listener().await],
+            next: AtomicUsize::new(0),
+        };
+        let addrs = service.local_addrs().unwrap();
+
+        let mut clients = vec![];
+        for addr in &addrs {
+            for _ in 0..2 {
+                clients.push(TcpStream::connect(addr).await.unwrap());
+            }
+        }
+        // give the reactor a chance to see that both listeners are ready
+        time::sleep(Duration::from_millis(100)).await;
+
+        let mut accepted = vec![];
+        for _ in 0..4 {
+            accepted.push(service.call(()).await.local_addr().unwrap());
+        }
+        assert_eq!(accepted, [addrs[0], addrs[1], addrs[0], addrs[1]]);
+    }
+}
@@ -0,0 +1,107 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::extensions::{Priority, RequestClass};
+use crate::memory_admission::MemoryAdmission;
+use crate::service::handler::{self, BodyWriteAborted};
+use crate::service::routing::Route;
+use crate::service::{Layer, Service};
+use crate::standby::Standby;
+use bytes::Bytes;
+use conjure_error::Error;
+use http::{Request, Response};
+use http_body_util::combinators::BoxBody;
+use std::sync::Arc;
+
+const DEBUG_SERVICE: &str = "DebugService";
+
+/// A layer which rejects requests with a `503 Service Unavailable` response while the server is in standby, and low
+/// priority requests while it is low on memory.
+///
+/// Only requests to application endpoints are rejected, so the server's built-in status and debug endpoints remain
+/// available. The [`Priority`] of a request's [`RequestClass`] determines whether it is shed due to memory usage.
+/// It must be installed after routing and request priority.
+pub struct AdmissionLayer {
+    admission: Arc<MemoryAdmission>,
+    standby: Arc<Standby>,
+}
+
+impl AdmissionLayer {
+    pub fn new(admission: &Arc<MemoryAdmission>, standby: &Arc<Standby>) -> Self {
+        AdmissionLayer {
+            admission: admission.clone(),
+            standby: standby.clone(),
+        }
+    }
+}
+
+impl<S> Layer<S> for AdmissionLayer {
+    type Service = AdmissionService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        AdmissionService {
+            inner,
+            admission: self.admission,
+            standby: self.standby,
+        }
+    }
+}
+
+pub struct AdmissionService<S> {
+    inner: S,
+    admission: Arc<MemoryAdmission>,
+    standby: Arc<Standby>,
+}
+
+impl<S, B> Service<Request<B>> for AdmissionService<S>
+where
+    S: Service<Request<B>, Response = Response<BoxBody<Bytes, BodyWriteAborted>>> + Sync,
+    B: Send,
+{
+    type Response = S::Response;
+
+    async fn call(&self, req: Request<B>) -> Self::Response {
+        if let Some(error) = self.rejection(&req) {
+            return handler::error_response(error);
+        }
+
+        self.inner.call(req).await
+    }
+}
+
+impl<S> AdmissionService<S> {
+    fn rejection<B>(&self, req: &Request<B>) -> Option<Error> {
+        let Some(Route::Resolved(endpoint)) = req.extensions().get::<Route>() else {
+            return None;
+        };
+        if endpoint.metrics().is_none() || endpoint.service_name() == DEBUG_SERVICE {
+            return None;
+        }
+
+        if self.standby.is_standby() {
+            return Some(Error::unavailable("server is in standby"));
+        }
+
+        let priority = req
+            .extensions()
+            .get::<RequestClass>()
+            .map_or(Priority::Normal, |c| c.priority());
+        if !self.admission.admit(&**endpoint, priority) {
+            return Some(Error::unavailable(
+                "shedding requests due to high memory usage",
+            ));
+        }
+
+        None
+    }
+}
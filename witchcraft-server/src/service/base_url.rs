@@ -0,0 +1,238 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::extensions::BaseUrl;
+use crate::service::{Layer, Service};
+use http::header::{FORWARDED, HOST};
+use http::{HeaderMap, HeaderName, Request};
+use witchcraft_server_config::install::InstallConfig;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+#[allow(clippy::declare_interior_mutable_const)]
+const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+
+/// A layer which adds a [`BaseUrl`] to the extensions of requests.
+pub struct BaseUrlLayer {
+    canonical_url: Option<String>,
+    context_path: String,
+}
+
+impl BaseUrlLayer {
+    pub fn new(config: &InstallConfig) -> Self {
+        let context_path = match config.context_path() {
+            "/" => String::new(),
+            context_path => context_path.to_string(),
+        };
+
+        BaseUrlLayer {
+            canonical_url: config
+                .canonical_url()
+                .map(|url| format!("{}{context_path}", url.trim_end_matches('/'))),
+            context_path,
+        }
+    }
+}
+
+impl<S> Layer<S> for BaseUrlLayer {
+    type Service = BaseUrlService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        BaseUrlService {
+            inner,
+            canonical_url: self.canonical_url,
+            context_path: self.context_path,
+        }
+    }
+}
+
+pub struct BaseUrlService<S> {
+    inner: S,
+    canonical_url: Option<String>,
+    context_path: String,
+}
+
+impl<S> BaseUrlService<S> {
+    fn base_url<B>(&self, req: &Request<B>) -> Option<String> {
+        if let Some(canonical_url) = &self.canonical_url {
+            return Some(canonical_url.clone());
+        }
+
+        let forwarded = forwarded(req.headers());
+
+        let scheme = match forwarded
+            .proto
+            .or_else(|| first_value(req.headers(), &X_FORWARDED_PROTO))
+        {
+            Some(proto) if proto.eq_ignore_ascii_case("http") => "http",
+            _ => "https",
+        };
+
+        let host = forwarded
+            .host
+            .or_else(|| first_value(req.headers(), &X_FORWARDED_HOST))
+            .or_else(|| req.headers().get(HOST)?.to_str().ok())
+            .or_else(|| req.uri().authority().map(|a| a.as_str()))
+            .filter(|h| valid_host(h))?;
+
+        Some(format!("{scheme}://{host}{}", self.context_path))
+    }
+}
+
+impl<S, B> Service<Request<B>> for BaseUrlService<S>
+where
+    S: Service<Request<B>> + Sync,
+    B: Send,
+{
+    type Response = S::Response;
+
+    async fn call(&self, mut req: Request<B>) -> Self::Response {
+        if let Some(base_url) = self.base_url(&req) {
+            req.extensions_mut().insert(BaseUrl(base_url));
+        }
+
+        self.inner.call(req).await
+    }
+}
+
+#[derive(Default)]
+struct Forwarded<'a> {
+    proto: Option<&'a str>,
+    host: Option<&'a str>,
+}
+
+/// Parses the `proto` and `host` parameters of the first element of the `Forwarded` header, which was added by the
+/// proxy closest to the client.
+fn forwarded(headers: &HeaderMap) -> Forwarded<'_> {
+    let mut forwarded = Forwarded::default();
+
+    let Some(element) = first_value(headers, &FORWARDED) else {
+        return forwarded;
+    };
+
+    for pair in element.split(';') {
+        let Some((key, value)) = pair.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"');
+
+        match key.trim() {
+            k if k.eq_ignore_ascii_case("proto") => forwarded.proto = Some(value),
+            k if k.eq_ignore_ascii_case("host") => forwarded.host = Some(value),
+            _ => {}
+        }
+    }
+
+    forwarded
+}
+
+fn first_value<'a>(headers: &'a HeaderMap, name: &HeaderName) -> Option<&'a str> {
+    let value = headers.get(name)?.to_str().ok()?;
+    let value = value.split(',').next()?.trim();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+fn valid_host(host: &str) -> bool {
+    !host.is_empty()
+        && host
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-._:[]".contains(&b))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::service::test_util::service_fn;
+
+    fn config(canonical_url: Option<&str>) -> InstallConfig {
+        InstallConfig::builder()
+            .product_name("foo")
+            .product_version("1.0.0")
+            .port(0)
+            .context_path("/foo")
+            .canonical_url(canonical_url.map(|s| s.to_string()))
+            .build()
+            .unwrap()
+    }
+
+    async fn base_url(config: &InstallConfig, req: Request<()>) -> Option<String> {
+        let service = BaseUrlLayer::new(config).layer(service_fn(|req: Request<()>| async move {
+            req.extensions().get::<BaseUrl>().cloned()
+        }));
+
+        service.call(req).await.map(|u| u.to_string())
+    }
+
+    #[tokio::test]
+    async fn host_header() {
+        let req = Request::builder()
+            .header(HOST, "localhost:8443")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            base_url(&config(None), req).await.as_deref(),
+            Some("https://localhost:8443/foo"),
+        );
+    }
+
+    #[tokio::test]
+    async fn forwarded_headers() {
+        let req = Request::builder()
+            .header(HOST, "localhost:8443")
+            .header(
+                FORWARDED,
+                "for=1.2.3.4;proto=http;host=\"example.com\", for=5.6.7.8",
+            )
+            .body(())
+            .unwrap();
+        assert_eq!(
+            base_url(&config(None), req).await.as_deref(),
+            Some("http://example.com/foo"),
+        );
+
+        let req = Request::builder()
+            .header(HOST, "localhost:8443")
+            .header(X_FORWARDED_PROTO, "http")
+            .header(X_FORWARDED_HOST, "example.com, proxy.internal")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            base_url(&config(None), req).await.as_deref(),
+            Some("http://example.com/foo"),
+        );
+
+        let req = Request::builder()
+            .header(X_FORWARDED_HOST, "example.com/evil")
+            .body(())
+            .unwrap();
+        assert_eq!(base_url(&config(None), req).await, None);
+    }
+
+    #[tokio::test]
+    async fn canonical_url() {
+        let req = Request::builder()
+            .header(X_FORWARDED_HOST, "example.com")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            base_url(&config(Some("https://api.example.com/")), req)
+                .await
+                .as_deref(),
+            Some("https://api.example.com/foo"),
+        );
+    }
+}
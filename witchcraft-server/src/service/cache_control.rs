@@ -0,0 +1,89 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::cache::CachePolicies;
+use crate::service::routing::Route;
+use crate::service::{Layer, Service};
+use http::header::{Entry, CACHE_CONTROL, VARY};
+use http::{HeaderValue, Method, Request, Response, StatusCode};
+use std::sync::Arc;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const DO_NOT_CACHE: HeaderValue = HeaderValue::from_static("no-cache, no-store, must-revalidate");
+
+/// A layer which renders the `Cache-Control` and `Vary` headers of responses to GET requests that do not already
+/// contain a `Cache-Control` header.
+///
+/// Successful responses from endpoints with a registered cache policy use that policy, and all others disable caching.
+/// It must be installed after routing.
+pub struct CacheControlLayer {
+    policies: Arc<CachePolicies>,
+}
+
+impl CacheControlLayer {
+    pub fn new(policies: CachePolicies) -> Self {
+        CacheControlLayer {
+            policies: Arc::new(policies),
+        }
+    }
+}
+
+impl<S> Layer<S> for CacheControlLayer {
+    type Service = CacheControlService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        CacheControlService {
+            inner,
+            policies: self.policies,
+        }
+    }
+}
+
+pub struct CacheControlService<S> {
+    inner: S,
+    policies: Arc<CachePolicies>,
+}
+
+impl<S, B1, B2> Service<Request<B1>> for CacheControlService<S>
+where
+    S: Service<Request<B1>, Response = Response<B2>> + Sync,
+    B1: Send,
+{
+    type Response = S::Response;
+
+    async fn call(&self, req: Request<B1>) -> Self::Response {
+        let is_get = req.method() == Method::GET;
+        let policy = match req.extensions().get::<Route>() {
+            Some(Route::Resolved(endpoint)) if is_get => self.policies.get(&**endpoint),
+            _ => None,
+        };
+
+        let mut response = self.inner.call(req).await;
+        if !is_get {
+            return response;
+        }
+
+        let cacheable =
+            response.status().is_success() || response.status() == StatusCode::NOT_MODIFIED;
+        let policy = policy.filter(|_| cacheable);
+
+        if let Some(vary) = policy.and_then(|p| p.vary.clone()) {
+            response.headers_mut().append(VARY, vary);
+        }
+        if let Entry::Vacant(e) = response.headers_mut().entry(CACHE_CONTROL) {
+            e.insert(policy.map_or(DO_NOT_CACHE, |p| p.cache_control.clone()));
+        }
+
+        response
+    }
+}
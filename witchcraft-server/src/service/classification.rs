@@ -0,0 +1,156 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::classification::{RequestClass, RequestClassifier};
+use crate::service::{Layer, Service};
+use http::Request;
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::Arc;
+use witchcraft_metrics::{Meter, MetricId, MetricRegistry};
+
+/// A layer which classifies requests with a user-provided [`RequestClassifier`].
+pub struct ClassificationLayer {
+    classifier: Option<Arc<dyn RequestClassifier + Sync + Send>>,
+    metrics: Arc<MetricRegistry>,
+}
+
+impl ClassificationLayer {
+    pub fn new(
+        classifier: Option<Arc<dyn RequestClassifier + Sync + Send>>,
+        metrics: &Arc<MetricRegistry>,
+    ) -> Self {
+        ClassificationLayer {
+            classifier,
+            metrics: metrics.clone(),
+        }
+    }
+}
+
+impl<S> Layer<S> for ClassificationLayer {
+    type Service = ClassificationService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        ClassificationService {
+            inner,
+            classifier: self.classifier,
+            metrics: self.metrics,
+            meters: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+pub struct ClassificationService<S> {
+    inner: S,
+    classifier: Option<Arc<dyn RequestClassifier + Sync + Send>>,
+    metrics: Arc<MetricRegistry>,
+    meters: Mutex<HashMap<BTreeMap<String, String>, Arc<Meter>>>,
+}
+
+impl<S> ClassificationService<S> {
+    fn meter(&self, class: &RequestClass) -> Arc<Meter> {
+        self.meters
+            .lock()
+            .entry(class.labels().clone())
+            .or_insert_with(|| {
+                let mut id = MetricId::new("server.request.classified");
+                for (key, value) in class.labels() {
+                    id = id.with_tag(key.clone(), value.clone());
+                }
+                self.metrics.meter(id)
+            })
+            .clone()
+    }
+}
+
+impl<S, B> Service<Request<B>> for ClassificationService<S>
+where
+    S: Service<Request<B>> + Sync,
+    B: Send,
+{
+    type Response = S::Response;
+
+    // This returns the inner future directly rather than nesting it in another async block, which would add to the
+    // stack usage of every request.
+    fn call(&self, mut req: Request<B>) -> impl Future<Output = Self::Response> + Send {
+        if let Some(classifier) = &self.classifier {
+            let class = classifier.classify(req.method(), req.uri().path(), req.headers());
+            self.meter(&class).mark(1);
+            req.extensions_mut().insert(class);
+        }
+
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::classification::Priority;
+    use crate::service::test_util::service_fn;
+    use http::{HeaderMap, Method};
+
+    struct TestClassifier;
+
+    impl RequestClassifier for TestClassifier {
+        fn classify(&self, method: &Method, path: &str, _: &HeaderMap) -> RequestClass {
+            let class = RequestClass::new().with_label("method", method.as_str());
+            if path.starts_with("/batch/") {
+                class
+                    .with_label("workload", "batch")
+                    .with_priority(Priority::Low)
+            } else {
+                class.with_label("workload", "interactive")
+            }
+        }
+    }
+
+    fn request(path: &str) -> Request<()> {
+        Request::builder().uri(path).body(()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn classify() {
+        let metrics = Arc::new(MetricRegistry::new());
+        let service =
+            ClassificationLayer::new(Some(Arc::new(TestClassifier)), &metrics).layer(service_fn(
+                |req: Request<()>| async move { req.extensions().get::<RequestClass>().cloned() },
+            ));
+
+        let class = service.call(request("/batch/job")).await.unwrap();
+        assert_eq!(class.label("workload"), Some("batch"));
+        assert_eq!(class.priority(), Priority::Low);
+
+        service.call(request("/api/foo")).await.unwrap();
+        service.call(request("/api/bar")).await.unwrap();
+
+        let classified = |workload: &'static str| {
+            MetricId::new("server.request.classified")
+                .with_tag("method", "GET")
+                .with_tag("workload", workload)
+        };
+        assert_eq!(metrics.meter(classified("batch")).count(), 1);
+        assert_eq!(metrics.meter(classified("interactive")).count(), 2);
+    }
+
+    #[tokio::test]
+    async fn no_classifier() {
+        let metrics = Arc::new(MetricRegistry::new());
+        let service = ClassificationLayer::new(None, &metrics).layer(service_fn(
+            |req: Request<()>| async move { req.extensions().get::<RequestClass>().cloned() },
+        ));
+
+        assert_eq!(service.call(request("/api/foo")).await, None);
+    }
+}
@@ -13,12 +13,12 @@
 // limitations under the License.
 use crate::service::hyper::NewConnection;
 use crate::service::{Layer, Service, Stack};
-use crate::tls::ClientCertificate;
+use crate::tls::{ClientCertificate, TlsSession};
 use http::Request;
 use tokio_rustls::server::TlsStream;
-use webpki::types::CertificateDer;
 
-/// A layer which injects a [`ClientCertificate`] extension into all requests made over the connection.
+/// A layer which injects [`ClientCertificate`] and [`TlsSession`] extensions into all requests made over the
+/// connection.
 pub struct ClientCertificateLayer;
 
 impl<S> Layer<S> for ClientCertificateLayer {
@@ -42,22 +42,30 @@ where
     type Response = S::Response;
 
     async fn call(&self, req: NewConnection<TlsStream<T>, L>) -> Self::Response {
-        let cert = req
-            .stream
-            .get_ref()
-            .1
+        let connection = req.stream.get_ref().1;
+        let session = TlsSession::new(
+            connection
+                .protocol_version()
+                .and_then(|p| p.as_str())
+                .unwrap_or("unknown"),
+            connection
+                .negotiated_cipher_suite()
+                .and_then(|c| c.suite().as_str())
+                .unwrap_or("unknown"),
+            connection.alpn_protocol(),
+            connection.server_name(),
+        );
+
+        let cert = connection
             .peer_certificates()
-            .and_then(|c| c.first())
-            .cloned()
-            .map(CertificateDer::into_owned)
-            .map(ClientCertificate::new);
+            .and_then(ClientCertificate::from_chain);
 
         self.inner
             .call(NewConnection {
                 stream: req.stream,
                 service_builder: req
                     .service_builder
-                    .layer(ClientCertificateRequestLayer { cert }),
+                    .layer(ClientCertificateRequestLayer { cert, session }),
             })
             .await
     }
@@ -65,6 +73,7 @@ where
 
 pub struct ClientCertificateRequestLayer {
     cert: Option<ClientCertificate>,
+    session: TlsSession,
 }
 
 impl<S> Layer<S> for ClientCertificateRequestLayer {
@@ -74,6 +83,7 @@ impl<S> Layer<S> for ClientCertificateRequestLayer {
         ClientCertificateRequestService {
             inner,
             cert: self.cert,
+            session: self.session,
         }
     }
 }
@@ -81,6 +91,7 @@ impl<S> Layer<S> for ClientCertificateRequestLayer {
 pub struct ClientCertificateRequestService<S> {
     inner: S,
     cert: Option<ClientCertificate>,
+    session: TlsSession,
 }
 
 impl<S, B> Service<Request<B>> for ClientCertificateRequestService<S>
@@ -94,6 +105,7 @@ where
         if let Some(cert) = &self.cert {
             req.extensions_mut().insert(cert.clone());
         }
+        req.extensions_mut().insert(self.session.clone());
 
         self.inner.call(req).await
     }
@@ -0,0 +1,1143 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::body::ClientIo;
+use crate::service::{Layer, Service};
+use brotli::CompressorWriter;
+use bytes::buf::Writer;
+use bytes::{BufMut, Bytes, BytesMut};
+use conjure_error::{Error, InvalidArgument, RequestEntityTooLarge};
+use flate2::write::{GzDecoder, GzEncoder};
+use flate2::Compression;
+use http::header::{
+    ACCEPT_ENCODING, ACCEPT_RANGES, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE,
+};
+use http::{HeaderValue, Request, Response};
+use http_body::{Body, Frame, SizeHint};
+use pin_project::pin_project;
+use refreshable::Refreshable;
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::{error, mem};
+use witchcraft_server_config::install::InstallConfig;
+use witchcraft_server_config::runtime::{CompressionConfig, ContentCoding, RuntimeConfig};
+use zstd::stream::raw::{InBuffer, Operation, OutBuffer};
+
+const EXCLUDED_CONTENT_TYPE_PREFIXES: &[&str] = &[
+    "video/",
+    "audio/",
+    "image/",
+    "application/bzip2",
+    "application/brotli",
+    "application/x-rar-compressed",
+    "application/gzip",
+    "application/compress",
+    "application/zip",
+    "application/x-xz",
+    "application/zstd",
+];
+
+const BROTLI_BUFFER_SIZE: usize = 4096;
+const BROTLI_QUALITY: u32 = 4;
+const BROTLI_WINDOW: u32 = 22;
+const ZSTD_LEVEL: i32 = 1;
+const ZSTD_BUFFER_SIZE: usize = 32 * 1024;
+
+/// A content coding supported by the server.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Coding {
+    Zstd,
+    Brotli,
+    Gzip,
+}
+
+impl Coding {
+    const ALL: [Coding; 3] = [Coding::Zstd, Coding::Brotli, Coding::Gzip];
+
+    fn from_config(coding: ContentCoding) -> Option<Self> {
+        match coding {
+            ContentCoding::Zstd => Some(Coding::Zstd),
+            ContentCoding::Brotli => Some(Coding::Brotli),
+            ContentCoding::Gzip => Some(Coding::Gzip),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Coding::Zstd => "zstd",
+            Coding::Brotli => "br",
+            Coding::Gzip => "gzip",
+        }
+    }
+
+    fn encoder(self) -> Encoder {
+        let writer = BytesMut::new().writer();
+        match self {
+            Coding::Zstd => Encoder::Zstd(
+                zstd::stream::write::Encoder::new(writer, ZSTD_LEVEL)
+                    .expect("failed to create zstd encoder"),
+            ),
+            Coding::Brotli => Encoder::Brotli(Box::new(CompressorWriter::new(
+                writer,
+                BROTLI_BUFFER_SIZE,
+                BROTLI_QUALITY,
+                BROTLI_WINDOW,
+            ))),
+            Coding::Gzip => Encoder::Gzip(GzEncoder::new(writer, Compression::fast())),
+        }
+    }
+}
+
+/// A layer which decompresses request bodies and compresses large response bodies.
+pub struct CompressionLayer {
+    enabled: bool,
+    config: Refreshable<CompressionConfig, Error>,
+}
+
+impl CompressionLayer {
+    pub fn new(
+        install_config: &InstallConfig,
+        runtime_config: &Refreshable<RuntimeConfig, Error>,
+    ) -> Self {
+        CompressionLayer {
+            enabled: install_config.server().gzip(),
+            config: runtime_config.map(|c| c.compression().clone()),
+        }
+    }
+}
+
+impl<S> Layer<S> for CompressionLayer {
+    type Service = CompressionService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        CompressionService {
+            inner,
+            enabled: self.enabled,
+            config: self.config,
+        }
+    }
+}
+
+pub struct CompressionService<S> {
+    inner: S,
+    enabled: bool,
+    config: Refreshable<CompressionConfig, Error>,
+}
+
+impl<S, B1, B2> Service<Request<B1>> for CompressionService<S>
+where
+    S: Service<Request<DecompressionBody<B1>>, Response = Response<B2>> + Sync,
+    B1: Send,
+    B2: Body<Data = Bytes>,
+    B2::Error: Into<Box<dyn error::Error + Sync + Send>>,
+{
+    type Response = Response<CompressionBody<B2>>;
+
+    async fn call(&self, mut req: Request<B1>) -> Self::Response {
+        let (coding, limit) = {
+            let config = self.config.get();
+            let coding = if self.enabled {
+                negotiate(&req, config.codings())
+            } else {
+                None
+            };
+            (coding, config.max_decompressed_request_size())
+        };
+        let decoder = match Decoder::new(&mut req, limit) {
+            Ok(decoder) => decoder,
+            Err(unsupported) => {
+                req.extensions_mut().insert(unsupported);
+                None
+            }
+        };
+        let req = req.map(|body| DecompressionBody { body, decoder });
+
+        let mut response = self.inner.call(req).await;
+        let state = match coding {
+            Some(coding) if should_compress(&self.config.get(), &response) => {
+                response.headers_mut().remove(CONTENT_LENGTH);
+                response
+                    .headers_mut()
+                    .insert(CONTENT_ENCODING, HeaderValue::from_static(coding.name()));
+
+                State::Compressing(coding.encoder())
+            }
+            _ => State::Done,
+        };
+
+        response.map(|body| CompressionBody { body, state })
+    }
+}
+
+/// Selects the enabled coding with the highest weight in the request's `Accept-Encoding` header, breaking ties by the
+/// order the codings are enabled in.
+fn negotiate<B>(request: &Request<B>, codings: &[ContentCoding]) -> Option<Coding> {
+    let accept_encoding = request
+        .headers()
+        .get(ACCEPT_ENCODING)
+        .and_then(|h| h.to_str().ok())?;
+
+    let mut weights = [None; Coding::ALL.len()];
+    let mut wildcard = None;
+    for quality_item in accept_encoding.split(',') {
+        let mut it = quality_item.splitn(2, ';');
+        let coding = it.next().unwrap().trim();
+        let weight = match it.next() {
+            Some(weight) => match weight
+                .trim()
+                .strip_prefix("q=")
+                .and_then(|q| q.parse::<f32>().ok())
+            {
+                Some(weight) => weight,
+                None => continue,
+            },
+            None => 1.,
+        };
+
+        if coding == "*" {
+            wildcard = Some(weight);
+            continue;
+        }
+        if let Some(i) = Coding::ALL
+            .iter()
+            .position(|c| coding.eq_ignore_ascii_case(c.name()))
+        {
+            weights[i] = Some(weight);
+        }
+    }
+
+    let mut best = None;
+    for coding in codings.iter().filter_map(|c| Coding::from_config(*c)) {
+        let weight = weights[coding as usize].or(wildcard).unwrap_or(0.);
+        if weight > 0. && best.map_or(true, |(_, w)| weight > w) {
+            best = Some((coding, weight));
+        }
+    }
+
+    best.map(|(coding, _)| coding)
+}
+
+fn should_compress<B>(config: &CompressionConfig, response: &Response<B>) -> bool
+where
+    B: Body,
+{
+    // We don't compress bodies known to be smaller than the minimum size
+    if response
+        .body()
+        .size_hint()
+        .upper()
+        .is_some_and(|s| s < config.min_size())
+    {
+        return false;
+    }
+
+    // We don't want to modify already encoded bodies
+    if response.headers().contains_key(CONTENT_ENCODING) {
+        return false;
+    }
+
+    // We don't want to change the byte offsets of range-addressable bodies
+    if response.headers().contains_key(ACCEPT_RANGES)
+        || response.headers().contains_key(CONTENT_RANGE)
+    {
+        return false;
+    }
+
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok());
+
+    // If an allowlist is configured, we only compress the content types it contains
+    if !config.content_types().is_empty() {
+        return content_type.is_some_and(|content_type| {
+            config
+                .content_types()
+                .iter()
+                .any(|prefix| content_type.starts_with(&**prefix))
+        });
+    }
+
+    // Otherwise, we don't compress bodies with content types that indicate they're already compressed
+    if let Some(content_type) = content_type {
+        if EXCLUDED_CONTENT_TYPE_PREFIXES
+            .iter()
+            .any(|prefix| content_type.starts_with(prefix))
+        {
+            return false;
+        }
+    }
+
+    true
+}
+
+enum Encoder {
+    Gzip(GzEncoder<Writer<BytesMut>>),
+    Brotli(Box<CompressorWriter<Writer<BytesMut>>>),
+    Zstd(zstd::stream::write::Encoder<'static, Writer<BytesMut>>),
+}
+
+impl Encoder {
+    fn write(&mut self, data: &[u8]) {
+        match self {
+            Encoder::Gzip(e) => e.write_all(data).unwrap(),
+            Encoder::Brotli(e) => e.write_all(data).unwrap(),
+            Encoder::Zstd(e) => e.write_all(data).unwrap(),
+        }
+    }
+
+    /// Flushes the encoder and returns everything it has produced so far.
+    fn flush(&mut self) -> Bytes {
+        match self {
+            Encoder::Gzip(e) => {
+                e.flush().unwrap();
+                e.get_mut().get_mut().split().freeze()
+            }
+            Encoder::Brotli(e) => {
+                e.flush().unwrap();
+                e.get_mut().get_mut().split().freeze()
+            }
+            Encoder::Zstd(e) => {
+                e.flush().unwrap();
+                e.get_mut().get_mut().split().freeze()
+            }
+        }
+    }
+
+    /// Finishes the stream and returns the remainder of the encoder's output.
+    fn finish(self) -> Bytes {
+        let writer = match self {
+            Encoder::Gzip(e) => e.finish().unwrap(),
+            Encoder::Brotli(e) => e.into_inner(),
+            Encoder::Zstd(e) => e.finish().unwrap(),
+        };
+        writer.into_inner().freeze()
+    }
+}
+
+/// A streaming decoder for a compressed request body.
+///
+/// The codecs write their output into a [`LimitedWriter`] so that a single small chunk of highly compressed input
+/// can't expand past the size limit.
+struct Decoder {
+    codec: Codec,
+    trailers: Option<Frame<Bytes>>,
+}
+
+enum Codec {
+    Gzip(Box<GzDecoder<LimitedWriter>>),
+    Zstd(Box<ZstdDecoder>),
+}
+
+impl Decoder {
+    /// Creates a decoder for the request's body if it has a `Content-Encoding`, failing if the coding is not supported.
+    ///
+    /// The `Content-Encoding` and `Content-Length` headers are removed since they no longer describe the body.
+    fn new<B>(
+        request: &mut Request<B>,
+        limit: u64,
+    ) -> Result<Option<Box<Self>>, UnsupportedContentEncoding> {
+        let Some(content_encoding) = request.headers().get(CONTENT_ENCODING) else {
+            return Ok(None);
+        };
+        let content_encoding = content_encoding.as_bytes().trim_ascii();
+
+        let writer = LimitedWriter {
+            buf: BytesMut::new(),
+            remaining: limit,
+            exceeded: false,
+        };
+        let codec = if content_encoding.eq_ignore_ascii_case(b"gzip")
+            || content_encoding.eq_ignore_ascii_case(b"x-gzip")
+        {
+            Codec::Gzip(Box::new(GzDecoder::new(writer)))
+        } else if content_encoding.eq_ignore_ascii_case(b"zstd") {
+            Codec::Zstd(Box::new(ZstdDecoder::new(writer)))
+        } else if content_encoding.eq_ignore_ascii_case(b"identity") {
+            request.headers_mut().remove(CONTENT_ENCODING);
+            return Ok(None);
+        } else {
+            return Err(UnsupportedContentEncoding);
+        };
+
+        request.headers_mut().remove(CONTENT_ENCODING);
+        request.headers_mut().remove(CONTENT_LENGTH);
+
+        Some(Box::new(Decoder {
+            codec,
+            trailers: None,
+        }))
+    }
+
+    /// Decodes a chunk of the body, returning everything the decoder has produced so far.
+    fn decode(&mut self, data: &[u8]) -> Result<Bytes, Error> {
+        let result = match &mut self.codec {
+            Codec::Gzip(d) => d.write_all(data).and_then(|()| d.flush()),
+            Codec::Zstd(d) => d.decode(data),
+        };
+        self.output(result)
+    }
+
+    /// Finishes the stream, returning the remainder of the decoder's output.
+    fn finish(&mut self) -> Result<Bytes, Error> {
+        let result = match &mut self.codec {
+            Codec::Gzip(d) => d.try_finish(),
+            Codec::Zstd(d) => d.finish(),
+        };
+        self.output(result)
+    }
+
+    fn output(&mut self, result: io::Result<()>) -> Result<Bytes, Error> {
+        let writer = match &mut self.codec {
+            Codec::Gzip(d) => d.get_mut(),
+            Codec::Zstd(d) => &mut d.writer,
+        };
+        if writer.exceeded {
+            return Err(Error::service_safe(
+                "decompressed request body too large",
+                RequestEntityTooLarge::new(),
+            ));
+        }
+        result.map_err(|e| Error::service_safe(e, InvalidArgument::new()))?;
+
+        Ok(writer.buf.split().freeze())
+    }
+}
+
+/// A zstd decoder which rejects a stream ending partway through a frame.
+struct ZstdDecoder {
+    decoder: zstd::stream::raw::Decoder<'static>,
+    buf: Box<[u8]>,
+    writer: LimitedWriter,
+    // zstd returns a hint of 0 only once a frame has been completely decoded and flushed.
+    hint: usize,
+}
+
+impl ZstdDecoder {
+    fn new(writer: LimitedWriter) -> Self {
+        ZstdDecoder {
+            decoder: zstd::stream::raw::Decoder::new().expect("failed to create zstd decoder"),
+            buf: vec![0; ZSTD_BUFFER_SIZE].into_boxed_slice(),
+            writer,
+            hint: 1,
+        }
+    }
+
+    fn decode(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut input = InBuffer::around(data);
+        loop {
+            let mut output = OutBuffer::around(&mut *self.buf);
+            self.hint = self.decoder.run(&mut input, &mut output)?;
+            // the decoder has flushed everything it can once it stops filling the output buffer
+            let flushed = output.pos() < output.capacity();
+            self.writer.write_all(output.as_slice())?;
+            if input.pos() == data.len() && flushed {
+                return Ok(());
+            }
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.hint != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated zstd frame",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// A request extension marking a request whose `Content-Encoding` is not supported.
+///
+/// The request's headers are left unmodified, and the handler rejects it with a `415 Unsupported Media Type` response.
+#[derive(Copy, Clone)]
+pub struct UnsupportedContentEncoding;
+
+struct LimitedWriter {
+    buf: BytesMut,
+    remaining: u64,
+    exceeded: bool,
+}
+
+impl Write for LimitedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() as u64 > self.remaining {
+            self.exceeded = true;
+            return Err(io::Error::other("decompressed size limit exceeded"));
+        }
+
+        self.remaining -= buf.len() as u64;
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A request body which is transparently decompressed if the request has a supported `Content-Encoding`.
+#[pin_project]
+pub struct DecompressionBody<B> {
+    #[pin]
+    body: B,
+    decoder: Option<Box<Decoder>>,
+}
+
+impl<B> DecompressionBody<B> {
+    /// Returns a body which passes its inner body through without decompression.
+    pub(crate) fn passthrough(body: B) -> Self {
+        DecompressionBody {
+            body,
+            decoder: None,
+        }
+    }
+}
+
+impl<B> Body for DecompressionBody<B>
+where
+    B: Body<Data = Bytes>,
+    B::Error: Into<Box<dyn error::Error + Sync + Send>>,
+{
+    type Data = Bytes;
+
+    type Error = Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+
+        if let Some(trailers) = this.decoder.as_mut().and_then(|d| d.trailers.take()) {
+            *this.decoder = None;
+            return Poll::Ready(Some(Ok(trailers)));
+        }
+
+        loop {
+            let frame = ready!(this.body.as_mut().poll_frame(cx))
+                .transpose()
+                .map_err(|e| Error::service_safe(e, ClientIo))?;
+
+            let Some(decoder) = this.decoder else {
+                return Poll::Ready(frame.map(Ok));
+            };
+
+            let buf = match frame {
+                Some(frame) => match frame.into_data() {
+                    Ok(data) => decoder.decode(&data)?,
+                    Err(frame) => {
+                        let buf = decoder.finish()?;
+                        if buf.is_empty() {
+                            *this.decoder = None;
+                            return Poll::Ready(Some(Ok(frame)));
+                        }
+                        decoder.trailers = Some(frame);
+                        buf
+                    }
+                },
+                None => {
+                    let buf = decoder.finish()?;
+                    *this.decoder = None;
+                    if buf.is_empty() {
+                        return Poll::Ready(None);
+                    }
+                    buf
+                }
+            };
+
+            if !buf.is_empty() {
+                return Poll::Ready(Some(Ok(Frame::data(buf))));
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.decoder.is_none() && self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self.decoder {
+            Some(_) => SizeHint::new(),
+            None => self.body.size_hint(),
+        }
+    }
+}
+
+enum State {
+    Compressing(Encoder),
+    Last(Frame<Bytes>),
+    Done,
+}
+
+#[pin_project]
+pub struct CompressionBody<B> {
+    #[pin]
+    body: B,
+    state: State,
+}
+
+impl<B> Body for CompressionBody<B>
+where
+    B: Body<Data = Bytes>,
+{
+    type Data = Bytes;
+
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+
+        match mem::replace(this.state, State::Done) {
+            State::Compressing(mut encoder) => match this.body.as_mut().poll_frame(cx) {
+                Poll::Ready(Some(Ok(frame))) => match frame.data_ref() {
+                    Some(data) => {
+                        encoder.write(data);
+                        if this.body.is_end_stream() {
+                            Poll::Ready(Some(Ok(Frame::data(encoder.finish()))))
+                        } else {
+                            // FIXME only flush on Poll::Pending, cut a chunk if the buffer is large
+                            let buf = encoder.flush();
+                            *this.state = State::Compressing(encoder);
+                            Poll::Ready(Some(Ok(Frame::data(buf))))
+                        }
+                    }
+                    None => {
+                        let buf = encoder.finish();
+                        if buf.is_empty() {
+                            Poll::Ready(Some(Ok(frame)))
+                        } else {
+                            *this.state = State::Last(frame);
+                            Poll::Ready(Some(Ok(Frame::data(buf))))
+                        }
+                    }
+                },
+                Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => {
+                    let buf = encoder.finish();
+                    if buf.is_empty() {
+                        Poll::Ready(None)
+                    } else {
+                        Poll::Ready(Some(Ok(Frame::data(buf))))
+                    }
+                }
+                Poll::Pending => {
+                    *this.state = State::Compressing(encoder);
+                    Poll::Pending
+                }
+            },
+            State::Last(frame) => Poll::Ready(Some(Ok(frame))),
+            State::Done => this.body.poll_frame(cx),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self.state {
+            State::Compressing(_) | State::Done => self.body.is_end_stream(),
+            State::Last(_) => false,
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.state {
+            State::Compressing(_) => SizeHint::new(),
+            State::Last(frame) => match frame.data_ref() {
+                Some(data) => SizeHint::with_exact(data.len() as u64),
+                None => SizeHint::with_exact(0),
+            },
+            State::Done => self.body.size_hint(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::service::test_util::service_fn;
+    use conjure_error::ErrorKind;
+    use futures_channel::mpsc;
+    use futures_util::SinkExt;
+    use http::StatusCode;
+    use http_body_util::{BodyExt, Full, StreamBody};
+    use std::{convert::Infallible, io::Write};
+    use tokio::task;
+    use witchcraft_server_config::runtime::CompressionConfig;
+
+    const MIN_SIZE: u64 = 1024 * 1024;
+
+    fn layer() -> CompressionLayer {
+        layer_with(CompressionConfig::default())
+    }
+
+    fn layer_with(config: CompressionConfig) -> CompressionLayer {
+        CompressionLayer {
+            enabled: true,
+            config: Refreshable::new(config).0,
+        }
+    }
+
+    fn request(accept_encoding: &'static str) -> Request<()> {
+        Request::builder()
+            .header(ACCEPT_ENCODING, accept_encoding)
+            .body(())
+            .unwrap()
+    }
+
+    const ALL_CODINGS: &[ContentCoding] = &[
+        ContentCoding::Zstd,
+        ContentCoding::Brotli,
+        ContentCoding::Gzip,
+    ];
+
+    #[test]
+    fn negotiation() {
+        let n = |accept_encoding| negotiate(&request(accept_encoding), ALL_CODINGS);
+        assert_eq!(n("gzip"), Some(Coding::Gzip));
+        assert_eq!(n("gzip, deflate, br, zstd"), Some(Coding::Zstd));
+        assert_eq!(n("gzip, br;q=0.5"), Some(Coding::Gzip));
+        assert_eq!(n("*"), Some(Coding::Zstd));
+        assert_eq!(n("*, zstd;q=0"), Some(Coding::Brotli));
+        assert_eq!(n("gzip;q=0"), None);
+        assert_eq!(n("deflate"), None);
+    }
+
+    #[test]
+    fn negotiation_default_codings() {
+        let codings = CompressionConfig::default().codings().to_vec();
+        let n = |accept_encoding| negotiate(&request(accept_encoding), &codings);
+        assert_eq!(n("gzip, deflate, br, zstd"), Some(Coding::Gzip));
+        assert_eq!(n("*"), Some(Coding::Gzip));
+        assert_eq!(n("br, zstd"), None);
+    }
+
+    #[test]
+    fn negotiation_configured_order() {
+        let codings = [ContentCoding::Gzip, ContentCoding::Zstd];
+        let n = |accept_encoding| negotiate(&request(accept_encoding), &codings);
+        assert_eq!(n("zstd, gzip"), Some(Coding::Gzip));
+        assert_eq!(n("zstd, gzip;q=0.5"), Some(Coding::Zstd));
+        assert_eq!(n("br"), None);
+    }
+
+    #[tokio::test]
+    async fn brotli_and_zstd() {
+        let config = CompressionConfig::builder()
+            .codings(ALL_CODINGS.to_vec())
+            .build();
+        let service = layer_with(config).layer(service_fn(|_| async {
+            Response::new(Full::new(Bytes::from(vec![0; MIN_SIZE as usize + 1])))
+        }));
+
+        let response = service.call(request("br")).await;
+        assert_eq!(response.headers().get(CONTENT_ENCODING).unwrap(), "br");
+        let buf = response.into_body().collect().await.unwrap().to_bytes();
+        let mut decompressor = brotli::DecompressorWriter::new(vec![], 4096);
+        decompressor.write_all(&buf).unwrap();
+        assert_eq!(
+            decompressor.into_inner().unwrap(),
+            [0; MIN_SIZE as usize + 1]
+        );
+
+        let response = service.call(request("zstd")).await;
+        assert_eq!(response.headers().get(CONTENT_ENCODING).unwrap(), "zstd");
+        let buf = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(zstd::decode_all(&*buf).unwrap(), [0; MIN_SIZE as usize + 1]);
+    }
+
+    #[tokio::test]
+    async fn configured_min_size_and_content_types() {
+        let config = CompressionConfig::builder()
+            .min_size(5)
+            .content_types(["application/json".to_string()])
+            .build();
+        let service = layer_with(config).layer(service_fn(|req: Request<_>| async move {
+            Response::builder()
+                .header(CONTENT_TYPE, req.uri().path().trim_start_matches('/'))
+                .body(Full::new(Bytes::from(vec![0; 10])))
+                .unwrap()
+        }));
+
+        let mut req = request("gzip");
+        *req.uri_mut() = "/application/json".parse().unwrap();
+        let response = service.call(req).await;
+        assert_eq!(response.headers().get(CONTENT_ENCODING).unwrap(), "gzip");
+
+        let mut req = request("gzip");
+        *req.uri_mut() = "/text/plain".parse().unwrap();
+        let response = service.call(req).await;
+        assert_eq!(response.headers().get(CONTENT_ENCODING), None);
+    }
+
+    #[tokio::test]
+    async fn gzip_large_response() {
+        let service = layer().layer(service_fn(|_| async {
+            Response::new(Full::new(Bytes::from(vec![0; MIN_SIZE as usize + 1])))
+        }));
+
+        let response = service
+            .call(
+                Request::builder()
+                    .header(ACCEPT_ENCODING, "gzip")
+                    .body(())
+                    .unwrap(),
+            )
+            .await;
+
+        assert_eq!(response.headers().get(CONTENT_ENCODING).unwrap(), "gzip");
+
+        let mut body = response.into_body();
+
+        let mut decompressor = GzDecoder::new(vec![]);
+        while let Some(chunk) = body.frame().await {
+            decompressor
+                .write_all(chunk.unwrap().data_ref().unwrap())
+                .unwrap();
+        }
+
+        assert_eq!(decompressor.finish().unwrap(), [0; MIN_SIZE as usize + 1]);
+    }
+
+    #[tokio::test]
+    async fn respect_missing_accept_encoding() {
+        let service = layer().layer(service_fn(|_| async {
+            Response::new(Full::new(Bytes::from(vec![0; MIN_SIZE as usize + 1])))
+        }));
+
+        let response = service.call(Request::builder().body(()).unwrap()).await;
+
+        assert_eq!(response.headers().get(CONTENT_ENCODING), None);
+
+        let body = response.into_body();
+        let buf = body.collect().await.unwrap().to_bytes();
+        assert_eq!(&*buf, [0; MIN_SIZE as usize + 1]);
+    }
+
+    #[tokio::test]
+    async fn respect_rejecting_accept_encoding() {
+        let service = layer().layer(service_fn(|_| async {
+            Response::new(Full::new(Bytes::from(vec![0; MIN_SIZE as usize + 1])))
+        }));
+
+        let response = service
+            .call(
+                Request::builder()
+                    .header(ACCEPT_ENCODING, "gzip;q=0")
+                    .body(())
+                    .unwrap(),
+            )
+            .await;
+
+        assert_eq!(response.headers().get(CONTENT_ENCODING), None);
+
+        let body = response.into_body();
+        let buf = body.collect().await.unwrap().to_bytes();
+        assert_eq!(&*buf, [0; MIN_SIZE as usize + 1]);
+    }
+
+    #[tokio::test]
+    async fn dont_gzip_small_response() {
+        let service = layer().layer(service_fn(|_| async {
+            Response::new(Full::new(Bytes::from(vec![0; 10])))
+        }));
+
+        let response = service
+            .call(
+                Request::builder()
+                    .header(ACCEPT_ENCODING, "gzip")
+                    .body(())
+                    .unwrap(),
+            )
+            .await;
+
+        assert_eq!(response.headers().get(CONTENT_ENCODING), None);
+
+        let body = response.into_body();
+        let buf = body.collect().await.unwrap().to_bytes();
+        assert_eq!(&*buf, [0; 10]);
+    }
+
+    #[tokio::test]
+    async fn preserve_existing_encodings() {
+        let service = layer().layer(service_fn(|_| async {
+            Response::builder()
+                .header(CONTENT_ENCODING, "deflate")
+                .body(Full::new(Bytes::from(vec![0; MIN_SIZE as usize + 1])))
+                .unwrap()
+        }));
+
+        let response = service
+            .call(
+                Request::builder()
+                    .header(ACCEPT_ENCODING, "gzip")
+                    .body(())
+                    .unwrap(),
+            )
+            .await;
+
+        assert_eq!(response.headers().get(CONTENT_ENCODING).unwrap(), "deflate");
+
+        let body = response.into_body();
+        let buf = body.collect().await.unwrap().to_bytes();
+        assert_eq!(&*buf, [0; MIN_SIZE as usize + 1]);
+    }
+
+    #[tokio::test]
+    async fn dont_compress_images() {
+        let service = layer().layer(service_fn(|_| async {
+            Response::builder()
+                .header(CONTENT_TYPE, "image/jpeg")
+                .body(Full::new(Bytes::from(vec![0; MIN_SIZE as usize + 1])))
+                .unwrap()
+        }));
+
+        let response = service
+            .call(
+                Request::builder()
+                    .header(ACCEPT_ENCODING, "gzip")
+                    .body(())
+                    .unwrap(),
+            )
+            .await;
+
+        assert_eq!(response.headers().get(CONTENT_ENCODING), None);
+
+        let body = response.into_body();
+        let buf = body.collect().await.unwrap().to_bytes();
+        assert_eq!(&*buf, [0; MIN_SIZE as usize + 1]);
+    }
+
+    #[tokio::test]
+    async fn dont_compress_ranged() {
+        let service = layer().layer(service_fn(|_| async {
+            Response::builder()
+                .header(ACCEPT_RANGES, "bytes")
+                .body(Full::new(Bytes::from(vec![0; MIN_SIZE as usize + 1])))
+                .unwrap()
+        }));
+
+        let response = service
+            .call(
+                Request::builder()
+                    .header(ACCEPT_ENCODING, "gzip")
+                    .body(())
+                    .unwrap(),
+            )
+            .await;
+
+        assert_eq!(response.headers().get(CONTENT_ENCODING), None);
+    }
+
+    #[tokio::test]
+    async fn each_chunk_is_decodable() {
+        let service = layer().layer(service_fn(|_| async {
+            let (mut tx, rx) = mpsc::channel::<Result<_, Infallible>>(1);
+            task::spawn(async move {
+                let _ = tx.send(Ok(Frame::data(Bytes::from("hello")))).await;
+                let _ = tx.send(Ok(Frame::data(Bytes::from("world")))).await;
+            });
+
+            Response::builder().body(StreamBody::new(rx)).unwrap()
+        }));
+
+        let response = service
+            .call(
+                Request::builder()
+                    .header(ACCEPT_ENCODING, "gzip")
+                    .body(())
+                    .unwrap(),
+            )
+            .await;
+
+        assert_eq!(response.headers().get(CONTENT_ENCODING).unwrap(), "gzip");
+
+        let chunk = response
+            .into_body()
+            .frame()
+            .await
+            .unwrap()
+            .unwrap()
+            .into_data()
+            .unwrap();
+        let mut decoder = GzDecoder::new(vec![]);
+        decoder.write_all(&chunk).unwrap();
+        decoder.flush().unwrap();
+
+        assert_eq!(decoder.get_ref(), b"hello");
+    }
+
+    fn echo_layer(
+        config: CompressionConfig,
+    ) -> impl Service<Request<Full<Bytes>>, Response = Response<CompressionBody<Full<Bytes>>>> {
+        layer_with(config).layer(service_fn(
+            |req: Request<DecompressionBody<Full<Bytes>>>| async move {
+                assert!(!req.headers().contains_key(CONTENT_ENCODING));
+                match req.into_body().collect().await {
+                    Ok(body) => Response::new(Full::new(body.to_bytes())),
+                    Err(e) => {
+                        let ErrorKind::Service(e) = e.kind() else {
+                            panic!("unexpected error kind");
+                        };
+                        Response::builder()
+                            .status(e.error_code().status_code())
+                            .body(Full::new(Bytes::new()))
+                            .unwrap()
+                    }
+                }
+            },
+        ))
+    }
+
+    fn compressed_request(coding: &'static str, body: Vec<u8>) -> Request<Full<Bytes>> {
+        Request::builder()
+            .header(CONTENT_ENCODING, coding)
+            .body(Full::new(Bytes::from(body)))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn decompress_gzip_request() {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(b"hello world").unwrap();
+        let body = encoder.finish().unwrap();
+
+        let response = echo_layer(CompressionConfig::default())
+            .call(compressed_request("gzip", body))
+            .await;
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello world");
+    }
+
+    #[tokio::test]
+    async fn decompress_zstd_request() {
+        let body = zstd::encode_all(&b"hello world"[..], 0).unwrap();
+
+        let response = echo_layer(CompressionConfig::default())
+            .call(compressed_request("zstd", body))
+            .await;
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello world");
+    }
+
+    #[tokio::test]
+    async fn truncated_request() {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(b"hello world").unwrap();
+        let mut body = encoder.finish().unwrap();
+        body.pop();
+
+        let response = echo_layer(CompressionConfig::default())
+            .call(compressed_request("gzip", body))
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let mut body = zstd::encode_all(&b"hello world"[..], 0).unwrap();
+        body.pop();
+
+        let response = echo_layer(CompressionConfig::default())
+            .call(compressed_request("zstd", body))
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = echo_layer(CompressionConfig::default())
+            .call(compressed_request("zstd", vec![]))
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn decompress_multiple_zstd_frames() {
+        let mut body = zstd::encode_all(&b"hello "[..], 0).unwrap();
+        body.extend(zstd::encode_all(&b"world"[..], 0).unwrap());
+
+        let response = echo_layer(CompressionConfig::default())
+            .call(compressed_request("zstd", body))
+            .await;
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello world");
+    }
+
+    #[tokio::test]
+    async fn decompressed_request_size_limit() {
+        let config = CompressionConfig::builder()
+            .max_decompressed_request_size(1024)
+            .build();
+        let body = zstd::encode_all(&[0; 1025][..], 0).unwrap();
+
+        let response = echo_layer(config.clone())
+            .call(compressed_request("zstd", body))
+            .await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let body = zstd::encode_all(&[0; 1024][..], 0).unwrap();
+        let response = echo_layer(config)
+            .call(compressed_request("zstd", body))
+            .await;
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, [0; 1024][..]);
+    }
+
+    #[tokio::test]
+    async fn content_encoding_case_insensitive() {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(b"hello world").unwrap();
+        let body = encoder.finish().unwrap();
+
+        let response = echo_layer(CompressionConfig::default())
+            .call(compressed_request("GZip", body))
+            .await;
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello world");
+    }
+
+    #[tokio::test]
+    async fn unsupported_content_encoding() {
+        let service = layer().layer(service_fn(
+            |req: Request<DecompressionBody<Full<Bytes>>>| async move {
+                assert_eq!(req.headers().get(CONTENT_ENCODING).unwrap(), "br");
+                let status = if req
+                    .extensions()
+                    .get::<UnsupportedContentEncoding>()
+                    .is_some()
+                {
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE
+                } else {
+                    StatusCode::OK
+                };
+                Response::builder()
+                    .status(status)
+                    .body(Full::new(Bytes::new()))
+                    .unwrap()
+            },
+        ));
+
+        let response = service
+            .call(compressed_request("br", b"hello world".to_vec()))
+            .await;
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn invalid_compressed_request() {
+        let response = echo_layer(CompressionConfig::default())
+            .call(compressed_request("gzip", b"hello world".to_vec()))
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 use crate::service::peer_addr::GetPeerAddr;
+use crate::service::plaintext_detection::Peek;
 use crate::service::{Layer, Service};
 use conjure_error::Error;
 use pin_project::pin_project;
@@ -131,3 +132,12 @@ where
         self.inner.peer_addr()
     }
 }
+
+impl<S> Peek for ConnectionLimitStream<S>
+where
+    S: Peek,
+{
+    fn poll_peek(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<usize>> {
+        self.inner.poll_peek(cx, buf)
+    }
+}
@@ -13,6 +13,7 @@
 // limitations under the License.
 use crate::server::Listener;
 use crate::service::peer_addr::GetPeerAddr;
+use crate::service::plaintext_detection::Peek;
 use crate::service::{Layer, Service};
 use pin_project::{pin_project, pinned_drop};
 use std::io;
@@ -149,3 +150,12 @@ where
         self.inner.peer_addr()
     }
 }
+
+impl<S> Peek for ConnectionMetricsStream<S>
+where
+    S: Peek,
+{
+    fn poll_peek(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<usize>> {
+        self.inner.poll_peek(cx, buf)
+    }
+}
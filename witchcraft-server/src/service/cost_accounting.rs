@@ -0,0 +1,231 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::cost::{CostLedger, Usage};
+use crate::outbound::OutboundCalls;
+use crate::service::endpoint_metrics::CpuTime;
+use crate::service::{Layer, Service};
+use bytes::Buf;
+use http::{Request, Response};
+use http_body::{Body, Frame};
+use parking_lot::Mutex;
+use pin_project::{pin_project, pinned_drop};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+/// A request extension collecting the resources used by a request for cost accounting.
+///
+/// The endpoint metrics layer attaches the request's CPU time and outbound request trackers.
+#[derive(Clone)]
+pub struct RequestUsage(Arc<RequestUsageState>);
+
+struct RequestUsageState {
+    request_bytes: AtomicU64,
+    trackers: Mutex<(Option<CpuTime>, Option<OutboundCalls>)>,
+}
+
+impl RequestUsage {
+    pub fn attach(&self, cpu_time: Option<&CpuTime>, outbound: Option<&OutboundCalls>) {
+        *self.0.trackers.lock() = (cpu_time.cloned(), outbound.cloned());
+    }
+}
+
+/// A layer which records the resources used by each request in the server's cost ledger.
+///
+/// It must be installed before decompression, and before the endpoint metrics layer.
+pub struct CostAccountingLayer {
+    ledger: Option<Arc<CostLedger>>,
+}
+
+impl CostAccountingLayer {
+    pub fn new(ledger: Option<Arc<CostLedger>>) -> Self {
+        CostAccountingLayer { ledger }
+    }
+}
+
+impl<S> Layer<S> for CostAccountingLayer {
+    type Service = CostAccountingService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        CostAccountingService {
+            inner,
+            ledger: self.ledger,
+        }
+    }
+}
+
+pub struct CostAccountingService<S> {
+    inner: S,
+    ledger: Option<Arc<CostLedger>>,
+}
+
+impl<S, B1, B2> Service<Request<B1>> for CostAccountingService<S>
+where
+    S: Service<Request<CostAccountingRequestBody<B1>>, Response = Response<B2>> + Sync,
+    B1: Send,
+{
+    type Response = Response<CostAccountingResponseBody<B2>>;
+
+    async fn call(&self, mut req: Request<B1>) -> Self::Response {
+        let state = self.ledger.as_ref().map(|ledger| {
+            let tenant = ledger.tenant(
+                req.method(),
+                req.uri().path(),
+                req.headers(),
+                req.extensions(),
+            );
+            State {
+                ledger: ledger.clone(),
+                tenant,
+                usage: RequestUsage(Arc::new(RequestUsageState {
+                    request_bytes: AtomicU64::new(0),
+                    trackers: Mutex::new((None, None)),
+                })),
+                response_bytes: 0,
+            }
+        });
+
+        if let Some(state) = &state {
+            req.extensions_mut().insert(state.usage.clone());
+        }
+        let usage = state.as_ref().map(|s| s.usage.clone());
+
+        let response = self
+            .inner
+            .call(req.map(|inner| CostAccountingRequestBody { inner, usage }))
+            .await;
+
+        response.map(|inner| CostAccountingResponseBody { inner, state })
+    }
+}
+
+#[pin_project]
+pub struct CostAccountingRequestBody<B> {
+    #[pin]
+    inner: B,
+    usage: Option<RequestUsage>,
+}
+
+impl<B> CostAccountingRequestBody<B> {
+    /// Returns a body which is not attributed to any request.
+    pub(crate) fn passthrough(inner: B) -> Self {
+        CostAccountingRequestBody { inner, usage: None }
+    }
+}
+
+impl<B> Body for CostAccountingRequestBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+
+        let value = ready!(this.inner.poll_frame(cx));
+        if let (Some(usage), Some(Ok(frame))) = (this.usage, &value) {
+            if let Some(chunk) = frame.data_ref() {
+                usage
+                    .0
+                    .request_bytes
+                    .fetch_add(chunk.remaining() as u64, Ordering::Relaxed);
+            }
+        }
+
+        Poll::Ready(value)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+struct State {
+    ledger: Arc<CostLedger>,
+    tenant: String,
+    usage: RequestUsage,
+    response_bytes: u64,
+}
+
+#[pin_project(PinnedDrop)]
+pub struct CostAccountingResponseBody<B> {
+    #[pin]
+    inner: B,
+    state: Option<State>,
+}
+
+#[pinned_drop]
+impl<B> PinnedDrop for CostAccountingResponseBody<B> {
+    fn drop(self: Pin<&mut Self>) {
+        let Some(state) = self.project().state.take() else {
+            return;
+        };
+
+        let trackers = state.usage.0.trackers.lock();
+        let (cpu_time, outbound) = &*trackers;
+        let usage = Usage {
+            requests: 1,
+            cpu_time: cpu_time.as_ref().map_or(Duration::ZERO, |c| c.total()),
+            request_bytes: state.usage.0.request_bytes.load(Ordering::Relaxed),
+            response_bytes: state.response_bytes,
+            outbound_requests: outbound.as_ref().map_or(0, |o| o.count()),
+        };
+        drop(trackers);
+        state.ledger.record(state.tenant, usage);
+    }
+}
+
+impl<B> Body for CostAccountingResponseBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+
+        let value = ready!(this.inner.poll_frame(cx));
+        if let (Some(state), Some(Ok(frame))) = (this.state, &value) {
+            if let Some(chunk) = frame.data_ref() {
+                state.response_bytes += chunk.remaining() as u64;
+            }
+        }
+
+        Poll::Ready(value)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
@@ -0,0 +1,91 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::extensions::RequestDeadline;
+use crate::service::{Layer, Service};
+use http::{HeaderName, Request};
+use std::time::{Duration, Instant};
+
+#[allow(clippy::declare_interior_mutable_const)]
+const X_REQUEST_TIMEOUT: HeaderName = HeaderName::from_static("x-request-timeout");
+
+/// A layer which adds a [`RequestDeadline`] to the extensions of requests with an `X-Request-Timeout` header.
+///
+/// The header contains the number of milliseconds the client is willing to wait for a response. Invalid values are
+/// ignored.
+pub struct DeadlineLayer;
+
+impl<S> Layer<S> for DeadlineLayer {
+    type Service = DeadlineService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        DeadlineService { inner }
+    }
+}
+
+pub struct DeadlineService<S> {
+    inner: S,
+}
+
+impl<S, B> Service<Request<B>> for DeadlineService<S>
+where
+    S: Service<Request<B>> + Sync,
+    B: Send,
+{
+    type Response = S::Response;
+
+    async fn call(&self, mut req: Request<B>) -> Self::Response {
+        let timeout = req
+            .headers()
+            .get(X_REQUEST_TIMEOUT)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_millis);
+
+        if let Some(deadline) = timeout.and_then(|t| Instant::now().checked_add(t)) {
+            req.extensions_mut().insert(RequestDeadline(deadline));
+        }
+
+        self.inner.call(req).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::service::test_util::service_fn;
+
+    #[tokio::test]
+    async fn parse_timeout() {
+        let service = DeadlineLayer.layer(service_fn(|req: Request<()>| async move {
+            req.extensions().get::<RequestDeadline>().copied()
+        }));
+
+        let before = Instant::now();
+        let req = Request::builder()
+            .header(X_REQUEST_TIMEOUT, "1000")
+            .body(())
+            .unwrap();
+        let deadline = service.call(req).await.unwrap();
+        assert!(*deadline >= before + Duration::from_secs(1));
+        assert!(*deadline <= Instant::now() + Duration::from_secs(1));
+
+        let req = Request::builder()
+            .header(X_REQUEST_TIMEOUT, "soon")
+            .body(())
+            .unwrap();
+        assert_eq!(service.call(req).await, None);
+
+        assert_eq!(service.call(Request::new(())).await, None);
+    }
+}
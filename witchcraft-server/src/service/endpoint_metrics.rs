@@ -11,27 +11,57 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use crate::metrics::rusage;
+use crate::outbound::OutboundCalls;
+use crate::retry::Retryability;
+use crate::service::cost_accounting::RequestUsage;
 use crate::service::routing::Route;
 use crate::service::{Layer, Service};
+use crate::slo::{EndpointSlo, SloRegistry};
+use conjure_error::Error;
 use conjure_http::server::EndpointMetadata;
 use http::{Request, Response};
 use http_body::{Body, Frame};
 use pin_project::{pin_project, pinned_drop};
-use std::pin::Pin;
+use refreshable::Refreshable;
+use std::future::{self, Future};
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Duration;
 use tokio::time::Instant;
-use witchcraft_metrics::{Meter, MetricId, MetricRegistry, Timer};
+use witchcraft_log::warn;
+use witchcraft_metrics::{Histogram, Meter, MetricId, MetricRegistry, Timer};
+use witchcraft_server_config::runtime::{RuntimeConfig, SlowPollsConfig};
 
 #[derive(Clone)]
 pub struct EndpointMetrics {
     response: Arc<Timer>,
     response_error: Arc<Meter>,
+    retryable: Arc<Meter>,
+    non_retryable: Arc<Meter>,
+    cpu_time: Option<Arc<Histogram>>,
+    outbound_requests: Arc<Histogram>,
+    outbound_time: Arc<Histogram>,
+    slow_polls: Arc<SlowPollMetrics>,
+    slo: Arc<EndpointSlo>,
+}
+
+struct SlowPollMetrics {
+    service_name: String,
+    endpoint: String,
+    timer: Arc<Timer>,
 }
 
 impl EndpointMetrics {
-    pub fn new(metrics: &MetricRegistry, endpoint: &dyn EndpointMetadata) -> Self {
+    pub fn new(
+        metrics: &MetricRegistry,
+        slos: &SloRegistry,
+        endpoint: &dyn EndpointMetadata,
+    ) -> Self {
         EndpointMetrics {
+            slo: slos.endpoint(endpoint),
             response: metrics.timer(
                 MetricId::new("server.response")
                     .with_tag("service-name", endpoint.service_name().to_string())
@@ -42,25 +72,158 @@ impl EndpointMetrics {
                     .with_tag("service-name", endpoint.service_name().to_string())
                     .with_tag("endpoint", endpoint.name().to_string()),
             ),
+            retryable: metrics.meter(
+                MetricId::new("server.response.retryability")
+                    .with_tag("service-name", endpoint.service_name().to_string())
+                    .with_tag("endpoint", endpoint.name().to_string())
+                    .with_tag("retryable", "true"),
+            ),
+            non_retryable: metrics.meter(
+                MetricId::new("server.response.retryability")
+                    .with_tag("service-name", endpoint.service_name().to_string())
+                    .with_tag("endpoint", endpoint.name().to_string())
+                    .with_tag("retryable", "false"),
+            ),
+            cpu_time: rusage::thread_cpu_time().map(|_| {
+                metrics.histogram(
+                    MetricId::new("server.response.cpu-time")
+                        .with_tag("service-name", endpoint.service_name().to_string())
+                        .with_tag("endpoint", endpoint.name().to_string()),
+                )
+            }),
+            outbound_requests: metrics.histogram(
+                MetricId::new("server.response.outbound-requests")
+                    .with_tag("service-name", endpoint.service_name().to_string())
+                    .with_tag("endpoint", endpoint.name().to_string()),
+            ),
+            outbound_time: metrics.histogram(
+                MetricId::new("server.response.outbound-time")
+                    .with_tag("service-name", endpoint.service_name().to_string())
+                    .with_tag("endpoint", endpoint.name().to_string()),
+            ),
+            slow_polls: Arc::new(SlowPollMetrics {
+                service_name: endpoint.service_name().to_string(),
+                endpoint: endpoint.name().to_string(),
+                timer: metrics.timer(
+                    MetricId::new("server.response.slow-poll")
+                        .with_tag("service-name", endpoint.service_name().to_string())
+                        .with_tag("endpoint", endpoint.name().to_string()),
+                ),
+            }),
+        }
+    }
+}
+
+/// A request extension which accumulates the CPU time spent processing a request.
+///
+/// Work done on other threads on behalf of the request (e.g. in the blocking thread pool) should be wrapped in
+/// [`CpuTime::measure`]. The total is recorded once all clones have been dropped.
+#[derive(Clone)]
+pub struct CpuTime(Arc<CpuTimeState>);
+
+struct CpuTimeState {
+    nanos: AtomicU64,
+    histogram: Arc<Histogram>,
+}
+
+impl Drop for CpuTimeState {
+    fn drop(&mut self) {
+        let micros = self.nanos.load(Ordering::Relaxed) / 1_000;
+        self.histogram.update(micros as i64);
+    }
+}
+
+impl CpuTime {
+    fn new(histogram: Arc<Histogram>) -> Self {
+        CpuTime(Arc::new(CpuTimeState {
+            nanos: AtomicU64::new(0),
+            histogram,
+        }))
+    }
+
+    /// Runs the closure, adding the CPU time it consumes on the current thread to the request's total.
+    pub fn measure<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        let start = rusage::thread_cpu_time();
+        let r = f();
+        if let (Some(start), Some(end)) = (start, rusage::thread_cpu_time()) {
+            let nanos = end.saturating_sub(start).as_nanos() as u64;
+            self.0.nanos.fetch_add(nanos, Ordering::Relaxed);
+        }
+        r
+    }
+
+    /// Returns the CPU time accumulated so far.
+    pub(crate) fn total(&self) -> Duration {
+        Duration::from_nanos(self.0.nanos.load(Ordering::Relaxed))
+    }
+}
+
+#[derive(Clone)]
+struct SlowPolls {
+    threshold: Duration,
+    metrics: Arc<SlowPollMetrics>,
+}
+
+impl SlowPolls {
+    fn measure<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        let start = Instant::now();
+        let r = f();
+        let elapsed = start.elapsed();
+        if elapsed > self.threshold {
+            self.metrics.timer.update(elapsed);
+            warn!(
+                "endpoint future blocked the runtime",
+                safe: {
+                    serviceName: self.metrics.service_name,
+                    endpoint: self.metrics.endpoint,
+                    pollMillis: elapsed.as_millis() as u64,
+                    thresholdMillis: self.threshold.as_millis() as u64,
+                },
+            );
         }
+        r
     }
 }
 
 /// A layer which records endpoint-specific metrics.
 ///
 /// It must be installed after routing.
-pub struct EndpointMetricsLayer;
+pub struct EndpointMetricsLayer {
+    slow_polls: Refreshable<SlowPollsConfig, Error>,
+    cpu_time: Refreshable<bool, Error>,
+}
+
+impl EndpointMetricsLayer {
+    pub fn new(runtime_config: &Refreshable<RuntimeConfig, Error>) -> Self {
+        EndpointMetricsLayer {
+            slow_polls: runtime_config.map(|c| c.slow_polls().clone()),
+            cpu_time: runtime_config.map(|c| c.cpu_time().enabled()),
+        }
+    }
+}
 
 impl<S> Layer<S> for EndpointMetricsLayer {
     type Service = EndpointMetricsService<S>;
 
     fn layer(self, inner: S) -> Self::Service {
-        EndpointMetricsService { inner }
+        EndpointMetricsService {
+            inner,
+            slow_polls: self.slow_polls,
+            cpu_time: self.cpu_time,
+        }
     }
 }
 
 pub struct EndpointMetricsService<S> {
     inner: S,
+    slow_polls: Refreshable<SlowPollsConfig, Error>,
+    cpu_time: Refreshable<bool, Error>,
 }
 
 impl<S, B1, B2> Service<Request<B1>> for EndpointMetricsService<S>
@@ -70,7 +233,7 @@ where
 {
     type Response = Response<EndpointMetricsBody<B2>>;
 
-    async fn call(&self, req: Request<B1>) -> Self::Response {
+    async fn call(&self, mut req: Request<B1>) -> Self::Response {
         let endpoint_metrics = match req
             .extensions()
             .get::<Route>()
@@ -80,35 +243,116 @@ where
             _ => None,
         };
 
+        let cpu_time = endpoint_metrics
+            .as_ref()
+            .filter(|_| *self.cpu_time.get())
+            .and_then(|m| m.cpu_time.clone())
+            .map(CpuTime::new);
+        if let Some(cpu_time) = &cpu_time {
+            req.extensions_mut().insert(cpu_time.clone());
+        }
+
+        let outbound = endpoint_metrics
+            .as_ref()
+            .map(|m| OutboundCalls::new(m.outbound_requests.clone(), m.outbound_time.clone()));
+        if let Some(outbound) = &outbound {
+            req.extensions_mut().insert(outbound.clone());
+        }
+
+        if let Some(usage) = req.extensions().get::<RequestUsage>() {
+            usage.attach(cpu_time.as_ref(), outbound.as_ref());
+        }
+
+        let slow_polls = endpoint_metrics.as_ref().and_then(|m| {
+            let config = self.slow_polls.get();
+            config.enabled().then(|| SlowPolls {
+                threshold: config.threshold(),
+                metrics: m.slow_polls.clone(),
+            })
+        });
+
         let start_time = Instant::now();
-        let response = self.inner.call(req).await;
-        if response.status().is_server_error() {
+        // Async handlers run on the calling task, so we measure each poll of the inner service.
+        let mut inner = pin!(self.inner.call(req));
+        let response = future::poll_fn(|cx| {
+            scope(&cpu_time, &outbound, &slow_polls, || {
+                inner.as_mut().poll(cx)
+            })
+        })
+        .await;
+        let error = response.status().is_server_error();
+        if error {
             if let Some(metrics) = &endpoint_metrics {
                 metrics.response_error.mark(1);
             }
         }
 
+        let retryability = response
+            .extensions()
+            .get::<Arc<Error>>()
+            .and_then(|e| Retryability::of(e));
+        if let (Some(retryability), Some(metrics)) = (retryability, &endpoint_metrics) {
+            if retryability.is_retryable() {
+                metrics.retryable.mark(1);
+            } else {
+                metrics.non_retryable.mark(1);
+            }
+        }
+
         response.map(|inner| EndpointMetricsBody {
             inner,
             start_time,
-            response: endpoint_metrics.map(|m| m.response.clone()),
+            error,
+            metrics: endpoint_metrics,
+            cpu_time,
+            outbound,
+            slow_polls,
         })
     }
 }
 
+fn scope<F, R>(
+    cpu_time: &Option<CpuTime>,
+    outbound: &Option<OutboundCalls>,
+    slow_polls: &Option<SlowPolls>,
+    f: F,
+) -> R
+where
+    F: FnOnce() -> R,
+{
+    let f = || match slow_polls {
+        Some(slow_polls) => slow_polls.measure(f),
+        None => f(),
+    };
+    let f = || match outbound {
+        Some(outbound) => outbound.scope(f),
+        None => f(),
+    };
+    match cpu_time {
+        Some(cpu_time) => cpu_time.measure(f),
+        None => f(),
+    }
+}
+
 #[pin_project(PinnedDrop)]
 pub struct EndpointMetricsBody<B> {
     #[pin]
     inner: B,
     start_time: Instant,
-    response: Option<Arc<Timer>>,
+    error: bool,
+    metrics: Option<EndpointMetrics>,
+    cpu_time: Option<CpuTime>,
+    outbound: Option<OutboundCalls>,
+    slow_polls: Option<SlowPolls>,
 }
 
 #[pinned_drop]
 impl<B> PinnedDrop for EndpointMetricsBody<B> {
     fn drop(self: Pin<&mut Self>) {
-        if let Some(response) = &self.response {
-            response.update(self.start_time.elapsed());
+        if let Some(metrics) = &self.metrics {
+            let elapsed = self.start_time.elapsed();
+            metrics.response.update(elapsed);
+            metrics.slo.record(self.error, elapsed);
         }
     }
 }
@@ -125,7 +369,10 @@ where
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
-        self.project().inner.poll_frame(cx)
+        let this = self.project();
+        scope(this.cpu_time, this.outbound, this.slow_polls, || {
+            this.inner.poll_frame(cx)
+        })
     }
 
     fn is_end_stream(&self) -> bool {
@@ -136,3 +383,47 @@ where
         self.inner.size_hint()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn cpu_time() {
+        let histogram = Arc::new(Histogram::default());
+
+        let cpu_time = CpuTime::new(histogram.clone());
+        let other = cpu_time.clone();
+        cpu_time.measure(|| {
+            let start = std::time::Instant::now();
+            while start.elapsed() < std::time::Duration::from_millis(20) {}
+        });
+        drop(cpu_time);
+        assert_eq!(histogram.count(), 0);
+
+        drop(other);
+        assert_eq!(histogram.count(), 1);
+        assert!(histogram.snapshot().max() >= 10_000);
+    }
+
+    #[test]
+    fn slow_polls() {
+        let timer = Arc::new(Timer::default());
+        let slow_polls = SlowPolls {
+            threshold: Duration::from_millis(10),
+            metrics: Arc::new(SlowPollMetrics {
+                service_name: "service".to_string(),
+                endpoint: "endpoint".to_string(),
+                timer: timer.clone(),
+            }),
+        };
+
+        slow_polls.measure(|| {});
+        assert_eq!(timer.count(), 0);
+
+        slow_polls.measure(|| std::thread::sleep(Duration::from_millis(20)));
+        assert_eq!(timer.count(), 1);
+        assert!(timer.snapshot().min() >= 20_000_000);
+    }
+}
@@ -0,0 +1,215 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::service::handler::{self, BodyWriteAborted};
+use crate::service::routing::Route;
+use crate::service::{Layer, Service};
+use bytes::Bytes;
+use conjure_error::Error;
+use futures_util::{future, stream};
+use http::{Request, Response};
+use http_body::Frame;
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, StreamBody};
+use refreshable::Refreshable;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time;
+use witchcraft_log::debug;
+use witchcraft_metrics::{Meter, MetricId, MetricRegistry};
+use witchcraft_server_config::runtime::{FaultInjectionConfig, RuntimeConfig};
+
+#[derive(Debug, PartialEq)]
+enum Fault {
+    Error,
+    Reset,
+}
+
+struct Faults {
+    latency: Option<Duration>,
+    fault: Option<Fault>,
+}
+
+struct FaultMeters {
+    latency: Arc<Meter>,
+    error: Arc<Meter>,
+    reset: Arc<Meter>,
+}
+
+/// A layer which injects latency, errors, and aborted responses into requests to endpoints configured in the runtime
+/// config's `fault-injection` section.
+///
+/// It must be installed after routing.
+pub struct FaultInjectionLayer {
+    config: Refreshable<FaultInjectionConfig, Error>,
+    meters: Arc<FaultMeters>,
+}
+
+impl FaultInjectionLayer {
+    pub fn new(
+        runtime_config: &Refreshable<RuntimeConfig, Error>,
+        metrics: &MetricRegistry,
+    ) -> Self {
+        let meter = |fault| {
+            metrics.meter(MetricId::new("server.fault-injection.injected").with_tag("fault", fault))
+        };
+
+        FaultInjectionLayer {
+            config: runtime_config.map(|c| c.fault_injection().clone()),
+            meters: Arc::new(FaultMeters {
+                latency: meter("latency"),
+                error: meter("error"),
+                reset: meter("reset"),
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for FaultInjectionLayer {
+    type Service = FaultInjectionService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        FaultInjectionService {
+            inner,
+            config: self.config,
+            meters: self.meters,
+        }
+    }
+}
+
+pub struct FaultInjectionService<S> {
+    inner: S,
+    config: Refreshable<FaultInjectionConfig, Error>,
+    meters: Arc<FaultMeters>,
+}
+
+impl<S, B> Service<Request<B>> for FaultInjectionService<S>
+where
+    S: Service<Request<B>, Response = Response<BoxBody<Bytes, BodyWriteAborted>>> + Sync,
+    B: Send,
+{
+    type Response = S::Response;
+
+    async fn call(&self, req: Request<B>) -> Self::Response {
+        let Some(faults) = self.faults(&req) else {
+            return self.inner.call(req).await;
+        };
+
+        if let Some(latency) = faults.latency {
+            self.meters.latency.mark(1);
+            time::sleep(latency).await;
+        }
+
+        match faults.fault {
+            Some(Fault::Error) => {
+                self.meters.error.mark(1);
+                handler::error_response(Error::unavailable("injected fault"))
+            }
+            Some(Fault::Reset) => {
+                self.meters.reset.mark(1);
+                let body = StreamBody::new(stream::once(future::ready(Err::<Frame<Bytes>, _>(
+                    BodyWriteAborted,
+                ))));
+                Response::new(body.boxed())
+            }
+            None => self.inner.call(req).await,
+        }
+    }
+}
+
+impl<S> FaultInjectionService<S> {
+    fn faults<B>(&self, req: &Request<B>) -> Option<Faults> {
+        let config = self.config.get();
+        if !config.enabled() {
+            return None;
+        }
+
+        let Some(Route::Resolved(endpoint)) = req.extensions().get::<Route>() else {
+            return None;
+        };
+        let key = format!("{}.{}", endpoint.service_name(), endpoint.name());
+        let endpoint_config = config.endpoints().get(&key)?;
+
+        let faults = select_faults(
+            endpoint_config.latency(),
+            endpoint_config.latency_rate(),
+            endpoint_config.error_rate(),
+            endpoint_config.reset_rate(),
+            rand::random(),
+            rand::random(),
+        );
+        if faults.latency.is_some() || faults.fault.is_some() {
+            debug!(
+                "injecting fault",
+                safe: {
+                    endpoint: key,
+                    latency: format_args!("{:?}", faults.latency),
+                    fault: format_args!("{:?}", faults.fault),
+                },
+            );
+        }
+
+        Some(faults)
+    }
+}
+
+fn select_faults(
+    latency: Duration,
+    latency_rate: f64,
+    error_rate: f64,
+    reset_rate: f64,
+    latency_roll: f64,
+    fault_roll: f64,
+) -> Faults {
+    let latency = if latency_roll < latency_rate && !latency.is_zero() {
+        Some(latency)
+    } else {
+        None
+    };
+
+    let fault = if fault_roll < error_rate {
+        Some(Fault::Error)
+    } else if fault_roll < error_rate + reset_rate {
+        Some(Fault::Reset)
+    } else {
+        None
+    };
+
+    Faults { latency, fault }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fault_selection() {
+        let latency = Duration::from_millis(100);
+
+        let faults = select_faults(latency, 0.5, 0.2, 0.3, 0.4, 0.1);
+        assert_eq!(faults.latency, Some(latency));
+        assert_eq!(faults.fault, Some(Fault::Error));
+
+        let faults = select_faults(latency, 0.5, 0.2, 0.3, 0.6, 0.4);
+        assert_eq!(faults.latency, None);
+        assert_eq!(faults.fault, Some(Fault::Reset));
+
+        let faults = select_faults(latency, 0.5, 0.2, 0.3, 0.6, 0.5);
+        assert_eq!(faults.latency, None);
+        assert_eq!(faults.fault, None);
+
+        let faults = select_faults(Duration::ZERO, 1.0, 0.0, 0.0, 0.0, 0.0);
+        assert_eq!(faults.latency, None);
+        assert_eq!(faults.fault, None);
+    }
+}
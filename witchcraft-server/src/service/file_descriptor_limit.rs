@@ -0,0 +1,60 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::file_descriptors::FileDescriptorMonitor;
+use crate::service::{Layer, Service};
+use std::sync::Arc;
+
+/// A layer which stops accepting new connections while the process's file descriptor usage is above its limit.
+///
+/// Pending connections remain in the listener's backlog until usage falls, rather than failing to accept with `EMFILE`.
+pub struct FileDescriptorLimitLayer {
+    monitor: Arc<FileDescriptorMonitor>,
+}
+
+impl FileDescriptorLimitLayer {
+    pub fn new(monitor: &Arc<FileDescriptorMonitor>) -> Self {
+        FileDescriptorLimitLayer {
+            monitor: monitor.clone(),
+        }
+    }
+}
+
+impl<S> Layer<S> for FileDescriptorLimitLayer {
+    type Service = FileDescriptorLimitService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        FileDescriptorLimitService {
+            inner,
+            monitor: self.monitor,
+        }
+    }
+}
+
+pub struct FileDescriptorLimitService<S> {
+    inner: S,
+    monitor: Arc<FileDescriptorMonitor>,
+}
+
+impl<S, R> Service<R> for FileDescriptorLimitService<S>
+where
+    S: Service<R> + Sync,
+    R: Send,
+{
+    type Response = S::Response;
+
+    async fn call(&self, req: R) -> Self::Response {
+        self.monitor.wait_available().await;
+        self.inner.call(req).await
+    }
+}
@@ -0,0 +1,159 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::extensions::PeerAddr;
+use crate::geo::GeoLookup;
+use crate::service::{Layer, Service};
+use http::Request;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+use witchcraft_metrics::{Meter, MetricId, MetricRegistry};
+
+const UNKNOWN_COUNTRY: &str = "unknown";
+
+/// A layer which enriches requests with geographic information about the client.
+///
+/// It must be installed after the peer address has been added to the request's extensions.
+pub struct GeoLayer {
+    lookup: Option<Arc<dyn GeoLookup + Sync + Send>>,
+    metrics: Arc<MetricRegistry>,
+}
+
+impl GeoLayer {
+    pub fn new(
+        lookup: Option<Arc<dyn GeoLookup + Sync + Send>>,
+        metrics: &Arc<MetricRegistry>,
+    ) -> Self {
+        GeoLayer {
+            lookup,
+            metrics: metrics.clone(),
+        }
+    }
+}
+
+impl<S> Layer<S> for GeoLayer {
+    type Service = GeoService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        GeoService {
+            inner,
+            lookup: self.lookup,
+            metrics: self.metrics,
+            meters: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+pub struct GeoService<S> {
+    inner: S,
+    lookup: Option<Arc<dyn GeoLookup + Sync + Send>>,
+    metrics: Arc<MetricRegistry>,
+    meters: Mutex<HashMap<String, Arc<Meter>>>,
+}
+
+impl<S> GeoService<S> {
+    fn meter(&self, country: &str) -> Arc<Meter> {
+        self.meters
+            .lock()
+            .entry(country.to_string())
+            .or_insert_with(|| {
+                self.metrics.meter(
+                    MetricId::new("server.request.country")
+                        .with_tag("country", country.to_string()),
+                )
+            })
+            .clone()
+    }
+}
+
+impl<S, B> Service<Request<B>> for GeoService<S>
+where
+    S: Service<Request<B>> + Sync,
+    B: Send,
+{
+    type Response = S::Response;
+
+    async fn call(&self, mut req: Request<B>) -> Self::Response {
+        if let Some(lookup) = &self.lookup {
+            if let Some(addr) = req.extensions().get::<PeerAddr>() {
+                let info = lookup.lookup(addr.ip());
+                self.meter(info.country().unwrap_or(UNKNOWN_COUNTRY))
+                    .mark(1);
+                req.extensions_mut().insert(info);
+            }
+        }
+
+        self.inner.call(req).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::extensions::GeoInfo;
+    use crate::service::test_util::service_fn;
+    use std::net::{IpAddr, SocketAddr};
+
+    struct TestLookup;
+
+    impl GeoLookup for TestLookup {
+        fn lookup(&self, addr: IpAddr) -> GeoInfo {
+            if addr.is_loopback() {
+                GeoInfo::new()
+            } else {
+                GeoInfo::new().with_country("US").with_asn(1234)
+            }
+        }
+    }
+
+    fn request(ip: &str) -> Request<()> {
+        let mut req = Request::new(());
+        req.extensions_mut()
+            .insert(PeerAddr(SocketAddr::new(ip.parse().unwrap(), 1234)));
+        req
+    }
+
+    #[tokio::test]
+    async fn enrich() {
+        let metrics = Arc::new(MetricRegistry::new());
+        let service = GeoLayer::new(Some(Arc::new(TestLookup)), &metrics).layer(service_fn(
+            |req: Request<()>| async move { req.extensions().get::<GeoInfo>().cloned() },
+        ));
+
+        assert_eq!(
+            service.call(request("1.2.3.4")).await,
+            Some(GeoInfo::new().with_country("US").with_asn(1234)),
+        );
+        assert_eq!(
+            service.call(request("127.0.0.1")).await,
+            Some(GeoInfo::new())
+        );
+
+        let country =
+            |c: &str| MetricId::new("server.request.country").with_tag("country", c.to_string());
+        assert_eq!(metrics.meter(country("US")).count(), 1);
+        assert_eq!(metrics.meter(country(UNKNOWN_COUNTRY)).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn no_lookup() {
+        let metrics = Arc::new(MetricRegistry::new());
+        let service =
+            GeoLayer::new(None, &metrics).layer(service_fn(|req: Request<()>| async move {
+                req.extensions().get::<GeoInfo>().cloned()
+            }));
+
+        assert_eq!(service.call(request("1.2.3.4")).await, None);
+    }
+}
@@ -11,9 +11,10 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use crate::server::Listener;
 use crate::service::hyper::GracefulShutdown;
 use crate::service::{Layer, Service};
-use crate::shutdown_hooks::ShutdownHooks;
+use crate::shutdown_hooks::{ShutdownHooks, ShutdownPhase};
 use futures_util::future::{self, FusedFuture};
 use futures_util::FutureExt;
 use parking_lot::Mutex;
@@ -22,31 +23,47 @@ use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll, Waker};
+use tokio::time::Instant;
 use tokio_util::sync::CancellationToken;
+use witchcraft_metrics::{MetricId, MetricRegistry};
 
 use super::hyper::ShutdownService;
 
-/// A layer which registers a shutdown hook to initiate a graceful shutdown of all futures returned by the delegate
+/// A layer which registers shutdown hooks to initiate a graceful shutdown of all futures returned by the delegate
 /// service, and waits for them to complete.
+///
+/// The graceful shutdown is initiated in the [`ShutdownPhase::StopAccepting`] phase, and connections are drained in
+/// the [`ShutdownPhase::DrainRequests`] phase. HTTP/2 connections are sent a `GOAWAY` frame and HTTP/1 connections are
+/// closed once their in-flight request completes. The time taken to drain the connections is recorded in a
+/// `server.connection.drain` timer.
 pub struct GracefulShutdownLayer {
     shared: Arc<Shared>,
 }
 
 impl GracefulShutdownLayer {
-    pub fn new(hooks: &mut ShutdownHooks) -> Self {
+    pub fn new(hooks: &mut ShutdownHooks, metrics: &MetricRegistry, listener: Listener) -> Self {
         let shared = Arc::new(Shared {
             cancellation_token: CancellationToken::new(),
             state: Mutex::new(State {
                 connections: 0,
                 waker: None,
+                shutdown_start: None,
             }),
         });
 
-        hooks.push({
+        hooks.push(ShutdownPhase::StopAccepting, "graceful-shutdown", {
             let shared = shared.clone();
             async move {
+                shared.state.lock().shutdown_start = Some(Instant::now());
                 shared.cancellation_token.cancel();
+            }
+        });
 
+        let drain = metrics
+            .timer(MetricId::new("server.connection.drain").with_tag("listener", listener.tag()));
+        hooks.push(ShutdownPhase::DrainRequests, "connections", {
+            let shared = shared.clone();
+            async move {
                 future::poll_fn(|cx| {
                     let mut state = shared.state.lock();
                     if state
@@ -63,7 +80,11 @@ impl GracefulShutdownLayer {
                         Poll::Pending
                     }
                 })
-                .await
+                .await;
+
+                if let Some(shutdown_start) = shared.state.lock().shutdown_start {
+                    drain.update(shutdown_start.elapsed());
+                }
             }
         });
 
@@ -153,6 +174,7 @@ where
 struct State {
     connections: usize,
     waker: Option<Waker>,
+    shutdown_start: Option<Instant>,
 }
 
 struct Shared {
@@ -214,8 +236,11 @@ mod test {
     #[tokio::test(start_paused = true)]
     async fn basic() {
         let mut hooks = ShutdownHooks::new();
+        let metrics = MetricRegistry::new();
 
-        let service = Arc::new(GracefulShutdownLayer::new(&mut hooks).layer(TestService));
+        let service = Arc::new(
+            GracefulShutdownLayer::new(&mut hooks, &metrics, Listener::Service).layer(TestService),
+        );
 
         let a = task::spawn({
             let service = service.clone();
@@ -256,9 +281,14 @@ mod test {
         assert_eq!(start.elapsed(), Duration::from_secs(1));
 
         let start = Instant::now();
-        hooks.await;
+        hooks.run(None).await;
         assert_eq!(start.elapsed(), Duration::from_secs(2));
 
+        let drain = metrics.timer(
+            MetricId::new("server.connection.drain").with_tag("listener", Listener::Service.tag()),
+        );
+        assert_eq!(drain.count(), 1);
+
         let start = Instant::now();
         b.await.unwrap();
         c.await.unwrap();
@@ -11,36 +11,109 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use crate::endpoint::errors;
 use crate::server::RawBody;
+use crate::service::compression::UnsupportedContentEncoding;
+use crate::service::redirect::Redirect;
 use crate::service::routing::Route;
 use crate::service::Service;
 use bytes::Bytes;
-use http::header::ALLOW;
+use conjure_error::{Error, RequestEntityTooLarge};
+use http::header::{ACCEPT_ENCODING, ALLOW, CONTENT_LENGTH, EXPECT};
 use http::{HeaderValue, Method, Request, Response, StatusCode};
 use http_body::{Body, Frame, SizeHint};
 use http_body_util::combinators::BoxBody;
-use http_body_util::BodyExt;
+use http_body_util::{BodyExt, Full};
 use itertools::Itertools;
+use refreshable::Refreshable;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::{error, fmt};
+use witchcraft_server_config::runtime::RuntimeConfig;
 
 /// The terminal service in the handler stack which turns [`Request`]s into [`Response`]s.
 ///
 /// It must be installed after routing.
-pub struct HandlerService;
+///
+/// Requests routed to an endpoint are checked against their `Expect` and `Content-Length` headers before the endpoint
+/// is invoked. Hyper only sends a `100 Continue` response once the request body is first read, so a client waiting on
+/// one will not transmit a body that is rejected here.
+///
+/// The bodies of responses to `HEAD` requests are discarded, which allows `GET` endpoints to serve them.
+pub struct HandlerService {
+    max_request_size: Refreshable<Option<u64>, Error>,
+}
+
+impl HandlerService {
+    pub fn new(runtime_config: &Refreshable<RuntimeConfig, Error>) -> Self {
+        HandlerService {
+            max_request_size: runtime_config.map(|c| c.max_request_size()),
+        }
+    }
+
+    fn check_request(
+        &self,
+        req: &Request<RawBody>,
+    ) -> Option<Response<BoxBody<Bytes, BodyWriteAborted>>> {
+        let headers = req.headers();
+        if let Some(expect) = headers.get(EXPECT) {
+            if !expect.as_bytes().eq_ignore_ascii_case(b"100-continue") {
+                let mut response = Response::new(EmptyBody.boxed());
+                *response.status_mut() = StatusCode::EXPECTATION_FAILED;
+                return Some(response);
+            }
+        }
+
+        if req
+            .extensions()
+            .get::<UnsupportedContentEncoding>()
+            .is_some()
+        {
+            let mut response = Response::new(EmptyBody.boxed());
+            *response.status_mut() = StatusCode::UNSUPPORTED_MEDIA_TYPE;
+            response.headers_mut().insert(
+                ACCEPT_ENCODING,
+                HeaderValue::from_static("gzip, zstd, identity"),
+            );
+            return Some(response);
+        }
+
+        let max_request_size = (*self.max_request_size.get())?;
+        let content_length = headers
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())?;
+        if content_length > max_request_size {
+            return Some(error_response(
+                Error::service_safe("request body too large", RequestEntityTooLarge::new())
+                    .with_safe_param("contentLength", content_length)
+                    .with_safe_param("maxRequestSize", max_request_size),
+            ));
+        }
+
+        None
+    }
+}
 
 impl Service<Request<RawBody>> for HandlerService {
     type Response = Response<BoxBody<Bytes, BodyWriteAborted>>;
 
     async fn call(&self, mut req: Request<RawBody>) -> Self::Response {
+        if let Some(redirect) = req.extensions_mut().remove::<Redirect>() {
+            return redirect.into_response();
+        }
+
         let route = req
             .extensions_mut()
             .remove::<Route>()
             .expect("Route missing from request extensions");
 
         match route {
-            Route::Resolved(endpoint) => endpoint.handle(req).await,
+            Route::Resolved(endpoint) => match self.check_request(&req) {
+                Some(response) => response,
+                None if req.method() == Method::HEAD => head_response(endpoint.handle(req).await),
+                None => endpoint.handle(req).await,
+            },
             Route::MethodNotAllowed(methods) => {
                 let mut response = Response::new(EmptyBody.boxed());
                 *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
@@ -67,6 +140,29 @@ impl Service<Request<RawBody>> for HandlerService {
     }
 }
 
+/// Creates a response for an error raised outside of an endpoint.
+pub fn error_response(error: Error) -> Response<BoxBody<Bytes, BodyWriteAborted>> {
+    errors::to_response(error, |body| match body {
+        Some(body) => Full::new(body).map_err(|e| match e {}).boxed(),
+        None => EmptyBody.boxed(),
+    })
+}
+
+// HEAD requests may be routed to GET endpoints, so we strip the body here. Dropping it aborts any writes the endpoint
+// has not yet made, and its length is preserved in the `Content-Length` header when known.
+fn head_response(
+    response: Response<BoxBody<Bytes, BodyWriteAborted>>,
+) -> Response<BoxBody<Bytes, BodyWriteAborted>> {
+    let (mut parts, body) = response.into_parts();
+    if !parts.headers.contains_key(CONTENT_LENGTH) {
+        if let Some(len) = body.size_hint().exact() {
+            parts.headers.insert(CONTENT_LENGTH, HeaderValue::from(len));
+        }
+    }
+
+    Response::from_parts(parts, EmptyBody.boxed())
+}
+
 fn allow_header(methods: &[Method]) -> HeaderValue {
     let header = methods.iter().map(|m| m.to_string()).join(", ");
     HeaderValue::try_from(header).unwrap()
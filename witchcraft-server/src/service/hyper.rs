@@ -11,17 +11,19 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use crate::extensions::DeclaredTrailers;
 use crate::service::{Layer, Service, ServiceBuilder};
 use conjure_error::Error;
 use futures_util::future::BoxFuture;
-use http::{Request, Response};
+use http::header::TRAILER;
+use http::{HeaderValue, Request, Response};
 use http_body::Body;
 use hyper::body::Incoming;
 use hyper::rt::bounds::Http2ServerConnExec;
 use hyper::rt::{Read, Write};
 use hyper::server::conn::{http1, http2};
 use hyper::service::HttpService;
-use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
 use pin_project::pin_project;
 use std::convert::Infallible;
 use std::error;
@@ -29,8 +31,11 @@ use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Duration;
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_rustls::server::TlsStream;
+use witchcraft_metrics::{Meter, MetricId, MetricRegistry};
+use witchcraft_server_config::install::{Http2Config, InstallConfig};
 
 pub struct NewConnection<S, L> {
     pub stream: S,
@@ -50,14 +55,67 @@ pub trait GracefulShutdown {
 /// The bridge between the Witchcraft `Service` and Hyper's `Service`.
 pub struct HyperService<S> {
     request_service: Arc<S>,
+    http2_config: Http2Config,
+    header_read_timeout: Duration,
+    extended_connect: bool,
+    malformed: Arc<Meter>,
 }
 
 impl<S> HyperService<S> {
-    pub fn new(request_service: S) -> Self {
+    pub fn new(
+        config: &InstallConfig,
+        metrics: &MetricRegistry,
+        extended_connect: bool,
+        request_service: S,
+    ) -> Self {
         HyperService {
             request_service: Arc::new(request_service),
+            http2_config: config.server().http2_settings().clone(),
+            header_read_timeout: config.server().header_read_timeout(),
+            extended_connect,
+            malformed: metrics
+                .meter(MetricId::new("server.request.rejected").with_tag("reason", "malformed")),
         }
     }
+
+    fn http1_builder(&self) -> http1::Builder {
+        let mut builder = http1::Builder::new();
+        builder
+            .timer(TokioTimer::new())
+            .header_read_timeout(self.header_read_timeout);
+
+        builder
+    }
+
+    fn http2_builder(&self) -> http2::Builder<TokioExecutor> {
+        let mut builder = http2::Builder::new(TokioExecutor::new());
+        builder.timer(TokioTimer::new());
+        if self.extended_connect {
+            builder.enable_connect_protocol();
+        }
+
+        let config = &self.http2_config;
+        if let Some(max_concurrent_streams) = config.max_concurrent_streams() {
+            builder.max_concurrent_streams(max_concurrent_streams);
+        }
+        if let Some(initial_stream_window_size) = config.initial_stream_window_size() {
+            builder.initial_stream_window_size(initial_stream_window_size);
+        }
+        if let Some(initial_connection_window_size) = config.initial_connection_window_size() {
+            builder.initial_connection_window_size(initial_connection_window_size);
+        }
+        if let Some(max_frame_size) = config.max_frame_size() {
+            builder.max_frame_size(max_frame_size);
+        }
+        if let Some(keep_alive_interval) = config.keep_alive_interval() {
+            builder.keep_alive_interval(keep_alive_interval);
+        }
+        if let Some(keep_alive_timeout) = config.keep_alive_timeout() {
+            builder.keep_alive_timeout(keep_alive_timeout);
+        }
+
+        builder
+    }
 }
 
 impl<S, R, L, B> ShutdownService<NewConnection<TlsStream<R>, L>> for HyperService<S>
@@ -76,19 +134,31 @@ where
         req: NewConnection<TlsStream<R>, L>,
     ) -> impl Future<Output = Self::Response> + GracefulShutdown + Send {
         if req.stream.get_ref().1.alpn_protocol() == Some(b"h2") {
-            HyperFuture::Http2(http2::Builder::new(TokioExecutor::new()).serve_connection(
-                TokioIo::new(req.stream),
-                AdaptorService {
-                    inner: Arc::new(req.service_builder.service(self.request_service.clone())),
-                },
-            ))
+            HyperFuture::Http2(
+                self.http2_builder().serve_connection(
+                    TokioIo::new(req.stream),
+                    AdaptorService {
+                        inner: Arc::new(req.service_builder.service(self.request_service.clone())),
+                        http1: false,
+                    },
+                ),
+                self.malformed.clone(),
+            )
         } else {
-            HyperFuture::Http1(http1::Builder::new().serve_connection(
-                TokioIo::new(req.stream),
-                AdaptorService {
-                    inner: Arc::new(req.service_builder.service(self.request_service.clone())),
-                },
-            ))
+            HyperFuture::Http1(
+                self.http1_builder()
+                    .serve_connection(
+                        TokioIo::new(req.stream),
+                        AdaptorService {
+                            inner: Arc::new(
+                                req.service_builder.service(self.request_service.clone()),
+                            ),
+                            http1: true,
+                        },
+                    )
+                    .with_upgrades(),
+                self.malformed.clone(),
+            )
         }
     }
 }
@@ -98,15 +168,15 @@ pub enum HyperFuture<T, S, E>
 where
     S: HttpService<Incoming>,
 {
-    Http1(#[pin] http1::Connection<T, S>),
-    Http2(#[pin] http2::Connection<T, S, E>),
+    Http1(#[pin] http1::UpgradeableConnection<T, S>, Arc<Meter>),
+    Http2(#[pin] http2::Connection<T, S, E>, Arc<Meter>),
 }
 
 impl<T, S, E, B> Future for HyperFuture<T, S, E>
 where
     S: HttpService<Incoming, ResBody = B>,
     S::Error: Into<Box<dyn error::Error + Sync + Send>>,
-    T: Read + Write + Unpin + 'static,
+    T: Read + Write + Unpin + 'static + Send,
     B: Body + 'static,
     B::Error: Into<Box<dyn error::Error + Sync + Send>>,
     E: Http2ServerConnExec<S::Future, B>,
@@ -115,37 +185,52 @@ where
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         match self.project() {
-            HyperFutureProj::Http1(s) => s.poll(cx).map_err(Error::internal_safe),
-            HyperFutureProj::Http2(s) => s.poll(cx).map_err(Error::internal_safe),
+            HyperFutureProj::Http1(s, malformed) => {
+                s.poll(cx).map_err(|e| connection_error(e, malformed))
+            }
+            HyperFutureProj::Http2(s, malformed) => {
+                s.poll(cx).map_err(|e| connection_error(e, malformed))
+            }
         }
     }
 }
 
+// Hyper responds to requests it can't parse with a `400 Bad Request` itself, so they never reach the request service.
+fn connection_error(error: hyper::Error, malformed: &Meter) -> Error {
+    if error.is_parse() {
+        malformed.mark(1);
+    }
+
+    Error::internal_safe(error)
+}
+
 impl<T, S, E, B> GracefulShutdown for HyperFuture<T, S, E>
 where
     S: HttpService<Incoming, ResBody = B>,
     S::Error: Into<Box<dyn error::Error + Sync + Send>>,
-    T: Read + Write + Unpin + 'static,
+    T: Read + Write + Unpin + 'static + Send,
     B: Body + 'static,
     B::Error: Into<Box<dyn error::Error + Sync + Send>>,
     E: Http2ServerConnExec<S::Future, B>,
 {
     fn graceful_shutdown(self: Pin<&mut Self>) {
         match self.project() {
-            HyperFutureProj::Http1(s) => s.graceful_shutdown(),
-            HyperFutureProj::Http2(s) => s.graceful_shutdown(),
+            HyperFutureProj::Http1(s, _) => s.graceful_shutdown(),
+            HyperFutureProj::Http2(s, _) => s.graceful_shutdown(),
         }
     }
 }
 
 struct AdaptorService<S> {
     inner: Arc<S>,
+    http1: bool,
 }
 
-impl<S, R> hyper::service::Service<R> for AdaptorService<S>
+impl<S, R, B> hyper::service::Service<R> for AdaptorService<S>
 where
-    S: Service<R> + 'static + Sync + Send,
+    S: Service<R, Response = Response<B>> + 'static + Sync + Send,
     R: 'static + Send,
+    B: Body,
 {
     type Response = S::Response;
 
@@ -156,7 +241,38 @@ where
     fn call(&self, req: R) -> Self::Future {
         Box::pin({
             let inner = self.inner.clone();
-            async move { Ok(inner.call(req).await) }
+            let http1 = self.http1;
+            async move {
+                let mut response = inner.call(req).await;
+                if http1 {
+                    declare_trailers(&mut response);
+                }
+                Ok(response)
+            }
         })
     }
 }
+
+/// Hyper only sends HTTP/1.1 trailers which have been declared in the response's `Trailer` header.
+fn declare_trailers<B>(response: &mut Response<B>)
+where
+    B: Body,
+{
+    let Some(trailers) = response.extensions().get::<DeclaredTrailers>() else {
+        return;
+    };
+
+    // Trailers can only be sent with chunked encoding, which isn't used for bodies of a known length.
+    if trailers.names().is_empty() || response.body().size_hint().exact().is_some() {
+        return;
+    }
+
+    let value = trailers
+        .names()
+        .iter()
+        .map(|name| name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let value = HeaderValue::try_from(value).expect("header names are valid header values");
+    response.headers_mut().insert(TRAILER, value);
+}
@@ -0,0 +1,230 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::endpoint::WitchcraftEndpoint;
+use crate::service::request_id::RequestId;
+use crate::service::routing::Route;
+use crate::service::{Layer, Service};
+use http::{Method, Request, Response};
+use http_body::{Body, Frame};
+use parking_lot::Mutex;
+use pin_project::pin_project;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// A registry of the requests currently being processed by the server.
+#[derive(Default)]
+pub struct InFlightRequests {
+    next_id: AtomicU64,
+    requests: Mutex<HashMap<u64, InFlightRequest>>,
+}
+
+impl InFlightRequests {
+    /// Returns the `limit` longest-running requests, slowest first.
+    pub fn slowest(&self, limit: usize) -> Vec<InFlightSnapshot> {
+        let now = Instant::now();
+        let mut requests = self
+            .requests
+            .lock()
+            .values()
+            .map(|r| InFlightSnapshot {
+                method: r.method.clone(),
+                endpoint: r.endpoint.clone(),
+                request_id: r.request_id,
+                duration: now.saturating_duration_since(r.start),
+            })
+            .collect::<Vec<_>>();
+        requests.sort_by(|a, b| b.duration.cmp(&a.duration));
+        requests.truncate(limit);
+        requests
+    }
+
+    fn insert(self: &Arc<Self>, request: InFlightRequest) -> InFlightGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.requests.lock().insert(id, request);
+        InFlightGuard {
+            requests: self.clone(),
+            id,
+        }
+    }
+}
+
+struct InFlightRequest {
+    method: Method,
+    endpoint: Option<Arc<dyn WitchcraftEndpoint + Sync + Send>>,
+    request_id: Option<RequestId>,
+    start: Instant,
+}
+
+/// A point-in-time view of an in-flight request.
+pub struct InFlightSnapshot {
+    method: Method,
+    endpoint: Option<Arc<dyn WitchcraftEndpoint + Sync + Send>>,
+    request_id: Option<RequestId>,
+    duration: Duration,
+}
+
+impl InFlightSnapshot {
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// Returns the service and endpoint names of the request, or `None` if it didn't match an endpoint.
+    pub fn endpoint(&self) -> Option<(&str, &str)> {
+        self.endpoint.as_ref().map(|e| (e.service_name(), e.name()))
+    }
+
+    pub fn request_id(&self) -> Option<RequestId> {
+        self.request_id
+    }
+
+    /// Returns how long the request has been in flight.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+/// A layer which records each request in an [`InFlightRequests`] registry until its response body completes.
+///
+/// It must be installed after routing.
+pub struct InFlightLayer {
+    requests: Arc<InFlightRequests>,
+}
+
+impl InFlightLayer {
+    pub fn new(requests: &Arc<InFlightRequests>) -> Self {
+        InFlightLayer {
+            requests: requests.clone(),
+        }
+    }
+}
+
+impl<S> Layer<S> for InFlightLayer {
+    type Service = InFlightService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        InFlightService {
+            inner,
+            requests: self.requests,
+        }
+    }
+}
+
+pub struct InFlightService<S> {
+    inner: S,
+    requests: Arc<InFlightRequests>,
+}
+
+impl<S, B1, B2> Service<Request<B1>> for InFlightService<S>
+where
+    S: Service<Request<B1>, Response = Response<B2>> + Sync,
+    B1: Send,
+{
+    type Response = Response<InFlightBody<B2>>;
+
+    async fn call(&self, req: Request<B1>) -> Self::Response {
+        let endpoint = match req.extensions().get::<Route>() {
+            Some(Route::Resolved(endpoint)) => Some(endpoint.clone()),
+            _ => None,
+        };
+        let guard = self.requests.insert(InFlightRequest {
+            method: req.method().clone(),
+            endpoint,
+            request_id: req.extensions().get::<RequestId>().copied(),
+            start: Instant::now(),
+        });
+
+        self.inner.call(req).await.map(|inner| InFlightBody {
+            inner,
+            _guard: guard,
+        })
+    }
+}
+
+#[pin_project]
+pub struct InFlightBody<B> {
+    #[pin]
+    inner: B,
+    _guard: InFlightGuard,
+}
+
+impl<B> Body for InFlightBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        self.project().inner.poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+struct InFlightGuard {
+    requests: Arc<InFlightRequests>,
+    id: u64,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.requests.requests.lock().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(start: Instant) -> InFlightRequest {
+        InFlightRequest {
+            method: Method::GET,
+            endpoint: None,
+            request_id: None,
+            start,
+        }
+    }
+
+    #[test]
+    fn slowest_first() {
+        let requests = Arc::new(InFlightRequests::default());
+        let now = Instant::now();
+        let _a = requests.insert(request(now));
+        let b = requests.insert(request(now - Duration::from_secs(2)));
+        let _c = requests.insert(request(now - Duration::from_secs(1)));
+
+        let slowest = requests.slowest(2);
+        assert_eq!(slowest.len(), 2);
+        assert!(slowest[0].duration() >= Duration::from_secs(2));
+        assert!(slowest[1].duration() >= Duration::from_secs(1));
+        assert!(slowest[1].duration() < Duration::from_secs(2));
+
+        drop(b);
+        assert_eq!(requests.slowest(10).len(), 2);
+    }
+}
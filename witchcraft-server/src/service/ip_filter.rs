@@ -0,0 +1,226 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::extensions::PeerAddr;
+use crate::server::Listener;
+use crate::service::handler::{self, BodyWriteAborted};
+use crate::service::hyper::NewConnection;
+use crate::service::peer_addr::GetPeerAddr;
+use crate::service::{Layer, Service};
+use bytes::Bytes;
+use conjure_error::{Error, PermissionDenied};
+use http::{Request, Response};
+use http_body_util::combinators::BoxBody;
+use refreshable::Refreshable;
+use std::sync::Arc;
+use witchcraft_metrics::{Meter, MetricId, MetricRegistry};
+use witchcraft_server_config::runtime::{IpFilterConfig, RuntimeConfig};
+
+/// A layer which closes connections from clients not permitted by the runtime IP filter configuration.
+///
+/// It runs before the TLS handshake, so rejected clients consume as few resources as possible.
+pub struct IpFilterLayer {
+    config: Refreshable<IpFilterConfig, Error>,
+    rejected: Arc<Meter>,
+}
+
+impl IpFilterLayer {
+    pub fn new(
+        metrics: &MetricRegistry,
+        runtime_config: &Refreshable<RuntimeConfig, Error>,
+        listener: Listener,
+    ) -> Self {
+        IpFilterLayer {
+            config: runtime_config.map(|c| c.ip_filter().clone()),
+            rejected: metrics.meter(
+                MetricId::new("server.connection.filtered").with_tag("listener", listener.tag()),
+            ),
+        }
+    }
+}
+
+impl<S> Layer<S> for IpFilterLayer {
+    type Service = IpFilterService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        IpFilterService {
+            inner,
+            config: self.config,
+            rejected: self.rejected,
+        }
+    }
+}
+
+pub struct IpFilterService<S> {
+    inner: S,
+    config: Refreshable<IpFilterConfig, Error>,
+    rejected: Arc<Meter>,
+}
+
+impl<S, T, L, R> Service<NewConnection<T, L>> for IpFilterService<S>
+where
+    S: Service<NewConnection<T, L>, Response = Result<R, Error>> + Sync,
+    T: GetPeerAddr + Send,
+    L: Send,
+{
+    type Response = S::Response;
+
+    async fn call(&self, req: NewConnection<T, L>) -> Self::Response {
+        let addr = req.stream.peer_addr()?;
+
+        if !self.config.get().permits(addr.ip()) {
+            self.rejected.mark(1);
+            return Err(Error::internal_safe("connection rejected by IP filter")
+                .with_unsafe_param("peerAddr", addr.to_string()));
+        }
+
+        self.inner.call(req).await
+    }
+}
+
+/// A layer which rejects requests from clients not permitted by the runtime IP filter configuration.
+///
+/// This complements [`IpFilterLayer`] by applying configuration changes to connections that were established before
+/// the change. It must be installed after the peer address has been added to the request's extensions.
+pub struct IpFilterRequestLayer {
+    config: Refreshable<IpFilterConfig, Error>,
+}
+
+impl IpFilterRequestLayer {
+    pub fn new(runtime_config: &Refreshable<RuntimeConfig, Error>) -> Self {
+        IpFilterRequestLayer {
+            config: runtime_config.map(|c| c.ip_filter().clone()),
+        }
+    }
+}
+
+impl<S> Layer<S> for IpFilterRequestLayer {
+    type Service = IpFilterRequestService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        IpFilterRequestService {
+            inner,
+            config: self.config,
+        }
+    }
+}
+
+pub struct IpFilterRequestService<S> {
+    inner: S,
+    config: Refreshable<IpFilterConfig, Error>,
+}
+
+impl<S, B> Service<Request<B>> for IpFilterRequestService<S>
+where
+    S: Service<Request<B>, Response = Response<BoxBody<Bytes, BodyWriteAborted>>> + Sync,
+    B: Send,
+{
+    type Response = S::Response;
+
+    async fn call(&self, req: Request<B>) -> Self::Response {
+        if let Some(addr) = req.extensions().get::<PeerAddr>() {
+            if !self.config.get().permits(addr.ip()) {
+                let error =
+                    Error::service_safe("request rejected by IP filter", PermissionDenied::new())
+                        .with_unsafe_param("peerAddr", addr.to_string());
+                return handler::error_response(error);
+            }
+        }
+
+        self.inner.call(req).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::service::handler::EmptyBody;
+    use crate::service::test_util::service_fn;
+    use http::StatusCode;
+    use http_body_util::BodyExt;
+    use std::net::{IpAddr, SocketAddr};
+    use witchcraft_server_config::net::IpNetwork;
+
+    fn filter_config(allow: &[&str], deny: &[&str]) -> IpFilterConfig {
+        IpFilterConfig::builder()
+            .allow(allow.iter().map(|n| n.parse::<IpNetwork>().unwrap()))
+            .deny(deny.iter().map(|n| n.parse::<IpNetwork>().unwrap()))
+            .build()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn networks() {
+        let network = "10.1.2.3/16".parse::<IpNetwork>().unwrap();
+        assert_eq!(network.to_string(), "10.1.0.0/16");
+        assert!(network.contains(ip("10.1.255.255")));
+        assert!(network.contains(ip("::ffff:10.1.0.1")));
+        assert!(!network.contains(ip("10.2.0.0")));
+        assert!(!network.contains(ip("::a01:1")));
+
+        let network = "2001:db8::/32".parse::<IpNetwork>().unwrap();
+        assert!(network.contains(ip("2001:db8:ffff::1")));
+        assert!(!network.contains(ip("2001:db9::1")));
+
+        assert!("0.0.0.0/0"
+            .parse::<IpNetwork>()
+            .unwrap()
+            .contains(ip("1.2.3.4")));
+        assert!("1.2.3.4"
+            .parse::<IpNetwork>()
+            .unwrap()
+            .contains(ip("1.2.3.4")));
+        assert!("1.2.3.4/33".parse::<IpNetwork>().is_err());
+        assert!("1.2.3/8".parse::<IpNetwork>().is_err());
+    }
+
+    #[test]
+    fn permits() {
+        let empty = filter_config(&[], &[]);
+        assert!(empty.permits(ip("1.2.3.4")));
+
+        let deny = filter_config(&[], &["10.0.0.0/8"]);
+        assert!(deny.permits(ip("1.2.3.4")));
+        assert!(!deny.permits(ip("10.0.0.1")));
+
+        let allow = filter_config(&["10.0.0.0/8"], &["10.0.0.0/24"]);
+        assert!(!allow.permits(ip("1.2.3.4")));
+        assert!(allow.permits(ip("10.1.0.1")));
+        assert!(!allow.permits(ip("10.0.0.1")));
+    }
+
+    #[tokio::test]
+    async fn reject_requests() {
+        let (config, mut handle) = Refreshable::new(filter_config(&[], &[]));
+        let service = IpFilterRequestLayer { config }
+            .layer(service_fn(|_| async { Response::new(EmptyBody.boxed()) }));
+
+        let request = || {
+            let mut req = Request::new(());
+            req.extensions_mut()
+                .insert(PeerAddr(SocketAddr::new(ip("10.0.0.1"), 1234)));
+            req
+        };
+
+        assert_eq!(service.call(request()).await.status(), StatusCode::OK);
+
+        handle.refresh(filter_config(&[], &["10.0.0.0/8"])).unwrap();
+        assert_eq!(
+            service.call(request()).await.status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+}
@@ -11,15 +11,21 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use crate::extensions::ShutdownSignal;
 use crate::service::{Layer, Service};
-use http::header::HeaderName;
+use http::header::{HeaderName, CONNECTION};
 use http::{HeaderValue, Request, Response, Version};
 use witchcraft_server_config::install::InstallConfig;
 
 #[allow(clippy::declare_interior_mutable_const)]
 const KEEP_ALIVE: HeaderName = HeaderName::from_static("keep-alive");
+#[allow(clippy::declare_interior_mutable_const)]
+const CLOSE: HeaderValue = HeaderValue::from_static("close");
 
-/// A layer which adds a `Keep-Alive` header to responses.
+/// A layer which adds a `Keep-Alive` header to HTTP/1 responses.
+///
+/// Once the server has started shutting down, a `Connection: close` header is added instead so clients stop reusing
+/// the connection. It must be installed after the [`ShutdownSignal`] is added to request extensions.
 pub struct KeepAliveHeaderLayer {
     value: Option<HeaderValue>,
 }
@@ -60,16 +66,58 @@ where
 
     async fn call(&self, req: Request<B1>) -> Self::Response {
         // https://datatracker.ietf.org/doc/html/rfc7540#section-8.1.2.2
-        let value = match req.version() {
-            Version::HTTP_09 | Version::HTTP_10 | Version::HTTP_11 => self.value.clone(),
-            _ => None,
-        };
+        let http1 = matches!(
+            req.version(),
+            Version::HTTP_09 | Version::HTTP_10 | Version::HTTP_11
+        );
+        let shutdown_signal = req.extensions().get::<ShutdownSignal>().cloned();
 
         let mut response = self.inner.call(req).await;
-        if let Some(value) = value {
+        if !http1 {
+            return response;
+        }
+
+        if shutdown_signal.is_some_and(|s| s.is_shutting_down()) {
+            response.headers_mut().insert(CONNECTION, CLOSE);
+        } else if let Some(value) = self.value.clone() {
             response.headers_mut().insert(KEEP_ALIVE, value);
         }
 
         response
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::service::test_util::service_fn;
+
+    #[tokio::test]
+    async fn close_on_shutdown() {
+        let signal = ShutdownSignal::new();
+        let service = KeepAliveHeaderService {
+            inner: service_fn(|_| async { Response::new(()) }),
+            value: Some(HeaderValue::from_static("timeout=60")),
+        };
+
+        let request = || {
+            let mut request = Request::new(());
+            request.extensions_mut().insert(signal.clone());
+            request
+        };
+
+        let response = service.call(request()).await;
+        assert_eq!(response.headers().get(KEEP_ALIVE).unwrap(), "timeout=60");
+        assert_eq!(response.headers().get(CONNECTION), None);
+
+        signal.trigger();
+        let response = service.call(request()).await;
+        assert_eq!(response.headers().get(KEEP_ALIVE), None);
+        assert_eq!(response.headers().get(CONNECTION).unwrap(), "close");
+
+        let mut request = request();
+        *request.version_mut() = Version::HTTP_2;
+        let response = service.call(request).await;
+        assert_eq!(response.headers().get(CONNECTION), None);
+    }
+}
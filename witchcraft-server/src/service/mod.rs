@@ -15,31 +15,50 @@ use std::future::Future;
 use std::sync::Arc;
 
 pub mod accept;
+pub mod admission;
 pub mod audit_log;
+pub mod base_url;
+pub mod cache_control;
 pub mod cancellation;
 pub mod catch_unwind;
+pub mod classification;
 pub mod client_certificate;
+pub mod compression;
 pub mod connection_limit;
 pub mod connection_metrics;
+pub mod cost_accounting;
+pub mod deadline;
 pub mod deprecation_header;
 pub mod endpoint_health;
 pub mod endpoint_metrics;
 pub mod error_log;
+pub mod fault_injection;
+pub mod file_descriptor_limit;
+pub mod geo;
 pub mod graceful_shutdown;
-pub mod gzip;
 pub mod handler;
 pub mod hyper;
 pub mod idle_connection;
+pub mod in_flight;
+pub mod ip_filter;
 pub mod keep_alive_header;
 pub mod mdc;
-pub mod no_caching;
 pub mod peer_addr;
+pub mod plaintext_detection;
+pub mod read_timeout;
+pub mod redirect;
 pub mod request_id;
 pub mod request_log;
+pub mod request_priority;
+pub mod request_size_limit;
+pub mod response_limit;
 pub mod routing;
 pub mod server_header;
 pub mod server_metrics;
+pub mod shutdown_signal;
+pub mod slow_client;
 pub mod spans;
+pub mod strict_parsing;
 #[cfg(test)]
 mod test_util;
 pub mod tls;
@@ -47,8 +66,10 @@ pub mod tls_metrics;
 pub mod trace_id_header;
 pub mod trace_propagation;
 pub mod unverified_jwt;
+pub mod user_agent;
 pub mod web_security;
 pub mod witchcraft_mdc;
+pub mod write_timeout;
 
 // This infrastructure is adapted from `tower`, with a few changes:
 //
@@ -0,0 +1,163 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::server::Listener;
+use crate::service::hyper::NewConnection;
+use crate::service::{Layer, Service};
+use conjure_error::Error;
+use std::future;
+use std::io;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::time;
+use witchcraft_metrics::{Meter, MetricId, MetricRegistry};
+
+// The content type of a TLS handshake record, which every TLS session starts with.
+const TLS_HANDSHAKE: u8 = 0x16;
+
+const MAX_REQUEST_HEAD_SIZE: usize = 8 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+const RESPONSE: &[u8] = b"HTTP/1.1 400 Bad Request\r\n\
+    Content-Type: text/plain; charset=utf-8\r\n\
+    Content-Length: 65\r\n\
+    Connection: close\r\n\
+    \r\n\
+    This server requires TLS. Retry the request with an https:// URL.";
+
+pub trait Peek {
+    fn poll_peek(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<usize>>;
+}
+
+/// A layer which detects plaintext HTTP requests made to a TLS port.
+///
+/// Rather than failing the TLS handshake with an opaque error, the client is sent a `400 Bad Request` response
+/// explaining that TLS is required, and the `server.connection.plaintext` meter is marked. It must be installed before
+/// the TLS layer.
+pub struct PlaintextDetectionLayer {
+    plaintext: Arc<Meter>,
+}
+
+impl PlaintextDetectionLayer {
+    pub fn new(metrics: &MetricRegistry, listener: Listener) -> Self {
+        PlaintextDetectionLayer {
+            plaintext: metrics.meter(
+                MetricId::new("server.connection.plaintext").with_tag("listener", listener.tag()),
+            ),
+        }
+    }
+}
+
+impl<S> Layer<S> for PlaintextDetectionLayer {
+    type Service = PlaintextDetectionService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        PlaintextDetectionService {
+            inner,
+            plaintext: self.plaintext,
+        }
+    }
+}
+
+pub struct PlaintextDetectionService<S> {
+    inner: S,
+    plaintext: Arc<Meter>,
+}
+
+impl<S, T, L, R> Service<NewConnection<T, L>> for PlaintextDetectionService<S>
+where
+    S: Service<NewConnection<T, L>, Response = Result<R, Error>> + Sync,
+    T: Peek + AsyncRead + AsyncWrite + Unpin + Send,
+    L: Send,
+{
+    type Response = S::Response;
+
+    async fn call(&self, mut req: NewConnection<T, L>) -> Self::Response {
+        let mut first = [0];
+        let mut buf = ReadBuf::new(&mut first);
+        future::poll_fn(|cx| req.stream.poll_peek(cx, &mut buf))
+            .await
+            .map_err(Error::internal_safe)?;
+
+        if !buf.filled().first().is_some_and(|b| is_plaintext(*b)) {
+            return self.inner.call(req).await;
+        }
+
+        self.plaintext.mark(1);
+        // Consume the request head before responding so the client sees the response rather than a connection reset.
+        let _ = time::timeout(READ_TIMEOUT, read_request_head(&mut req.stream)).await;
+        req.stream
+            .write_all(RESPONSE)
+            .await
+            .map_err(Error::internal_safe)?;
+        req.stream.shutdown().await.map_err(Error::internal_safe)?;
+
+        Err(Error::internal_safe(
+            "plaintext HTTP request received on a TLS port",
+        ))
+    }
+}
+
+fn is_plaintext(first: u8) -> bool {
+    // HTTP/1 requests start with a method, which is an uppercase token in practice.
+    first != TLS_HANDSHAKE && first.is_ascii_uppercase()
+}
+
+async fn read_request_head<T>(stream: &mut T) -> io::Result<()>
+where
+    T: AsyncRead + Unpin,
+{
+    let mut buf = Vec::with_capacity(1024);
+    while buf.len() < MAX_REQUEST_HEAD_SIZE && !has_request_head(&buf) {
+        let mut chunk = [0; 1024];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    Ok(())
+}
+
+fn has_request_head(buf: &[u8]) -> bool {
+    buf.windows(4).any(|w| w == b"\r\n\r\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detection() {
+        assert!(is_plaintext(b'G'));
+        assert!(is_plaintext(b'P'));
+        assert!(!is_plaintext(TLS_HANDSHAKE));
+        assert!(!is_plaintext(0));
+    }
+
+    #[test]
+    fn response() {
+        let body_start = RESPONSE.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        assert_eq!(RESPONSE.len() - body_start, 65);
+    }
+
+    #[tokio::test]
+    async fn request_head() {
+        let mut stream = &b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n"[..];
+        read_request_head(&mut stream).await.unwrap();
+        assert!(stream.is_empty());
+    }
+}
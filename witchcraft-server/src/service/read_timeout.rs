@@ -0,0 +1,247 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::service::{Layer, Service};
+use http::Request;
+use http_body::{Body, Frame, SizeHint};
+use pin_project::pin_project;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use std::{error, fmt, io};
+use tokio::time::{self, Instant, Sleep};
+use witchcraft_server_config::install::InstallConfig;
+
+/// A layer which fails reads of request bodies which wait too long for the client to send the next chunk.
+pub struct ReadTimeoutLayer {
+    timeout: Option<Duration>,
+}
+
+impl ReadTimeoutLayer {
+    pub fn new(config: &InstallConfig) -> Self {
+        ReadTimeoutLayer {
+            timeout: config.server().request_body_read_timeout(),
+        }
+    }
+}
+
+impl<S> Layer<S> for ReadTimeoutLayer {
+    type Service = ReadTimeoutService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        ReadTimeoutService {
+            inner,
+            timeout: self.timeout,
+        }
+    }
+}
+
+pub struct ReadTimeoutService<S> {
+    inner: S,
+    timeout: Option<Duration>,
+}
+
+impl<S, B> Service<Request<B>> for ReadTimeoutService<S>
+where
+    S: Service<Request<ReadTimeoutBody<B>>> + Sync,
+    B: Send,
+{
+    type Response = S::Response;
+
+    async fn call(&self, req: Request<B>) -> Self::Response {
+        let req = req.map(|inner| ReadTimeoutBody {
+            inner,
+            timeout: self.timeout,
+            sleep: None,
+        });
+
+        self.inner.call(req).await
+    }
+}
+
+#[pin_project]
+pub struct ReadTimeoutBody<B> {
+    #[pin]
+    inner: B,
+    timeout: Option<Duration>,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<B> ReadTimeoutBody<B> {
+    /// Returns a body which never times out.
+    pub(crate) fn passthrough(inner: B) -> Self {
+        ReadTimeoutBody {
+            inner,
+            timeout: None,
+            sleep: None,
+        }
+    }
+}
+
+impl<B> Body for ReadTimeoutBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+
+    type Error = ReadTimeoutError<B::Error>;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+
+        if let Poll::Ready(frame) = this.inner.poll_frame(cx) {
+            // The timer restarts with each read.
+            *this.sleep = None;
+            return Poll::Ready(frame.map(|r| r.map_err(ReadTimeoutError::Inner)));
+        }
+
+        let Some(timeout) = *this.timeout else {
+            return Poll::Pending;
+        };
+
+        let sleep = this
+            .sleep
+            .get_or_insert_with(|| Box::pin(time::sleep_until(Instant::now() + timeout)));
+        if sleep.as_mut().poll(cx).is_ready() {
+            *this.timeout = None;
+            return Poll::Ready(Some(Err(ReadTimeoutError::TimedOut(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "timed out reading request body",
+            )))));
+        }
+
+        Poll::Pending
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[derive(Debug)]
+pub enum ReadTimeoutError<E> {
+    Inner(E),
+    TimedOut(io::Error),
+}
+
+impl<E> fmt::Display for ReadTimeoutError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadTimeoutError::Inner(e) => fmt::Display::fmt(e, f),
+            ReadTimeoutError::TimedOut(e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl<E> error::Error for ReadTimeoutError<E>
+where
+    E: error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ReadTimeoutError::Inner(e) => Some(e),
+            ReadTimeoutError::TimedOut(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::service::test_util::service_fn;
+    use bytes::Bytes;
+    use futures_channel::mpsc;
+    use futures_util::SinkExt;
+    use http_body_util::{BodyExt, StreamBody};
+    use std::convert::Infallible;
+    use witchcraft_server_config::install::ServerConfig;
+
+    type TestBody = StreamBody<mpsc::Receiver<Result<Frame<Bytes>, Infallible>>>;
+
+    #[tokio::test(start_paused = true)]
+    async fn timeout_restarts_on_read() {
+        let (mut tx, rx) = mpsc::channel::<Result<Frame<Bytes>, Infallible>>(1);
+        let mut body = ReadTimeoutBody {
+            inner: StreamBody::new(rx),
+            timeout: Some(Duration::from_secs(5)),
+            sleep: None,
+        };
+
+        tokio::spawn(async move {
+            for _ in 0..3 {
+                time::sleep(Duration::from_secs(4)).await;
+                tx.send(Ok(Frame::data(Bytes::from_static(b"hello"))))
+                    .await
+                    .unwrap();
+            }
+            time::sleep(Duration::from_secs(10)).await;
+        });
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            body.frame().await.unwrap().unwrap();
+        }
+        assert_eq!(start.elapsed(), Duration::from_secs(12));
+
+        let error = body.frame().await.unwrap().unwrap_err();
+        assert!(matches!(error, ReadTimeoutError::TimedOut(_)));
+        assert_eq!(start.elapsed(), Duration::from_secs(17));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn layer_uses_read_timeout() {
+        let config = InstallConfig::builder()
+            .product_name("product")
+            .product_version("1.0.0")
+            .port(0)
+            .server(
+                ServerConfig::builder()
+                    .request_body_read_timeout(Duration::from_secs(5))
+                    .response_write_timeout(Duration::from_secs(30))
+                    .build(),
+            )
+            .build();
+        let service = ReadTimeoutLayer::new(&config).layer(service_fn(
+            |req: Request<ReadTimeoutBody<TestBody>>| async move {
+                let start = Instant::now();
+                let error = req.into_body().collect().await.unwrap_err();
+                (error, start.elapsed())
+            },
+        ));
+
+        let (mut tx, rx) = mpsc::channel(1);
+        tokio::spawn(async move {
+            time::sleep(Duration::from_secs(4)).await;
+            tx.send(Ok(Frame::data(Bytes::from_static(b"hello"))))
+                .await
+                .unwrap();
+            // stall without closing the body
+            time::sleep(Duration::from_secs(60)).await;
+        });
+
+        let (error, elapsed) = service.call(Request::new(StreamBody::new(rx))).await;
+        assert!(matches!(error, ReadTimeoutError::TimedOut(_)));
+        assert_eq!(elapsed, Duration::from_secs(9));
+    }
+}
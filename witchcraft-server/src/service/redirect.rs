@@ -0,0 +1,278 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::service::handler::{BodyWriteAborted, EmptyBody};
+use crate::service::{Layer, Service};
+use bytes::Bytes;
+use conjure_error::Error;
+use http::header::LOCATION;
+use http::uri::PathAndQuery;
+use http::{HeaderValue, Request, Response, StatusCode, Uri};
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use refreshable::Refreshable;
+use regex::Regex;
+use std::future::Future;
+use std::sync::Arc;
+use witchcraft_server_config::runtime::{PathMatch, RedirectAction, RedirectRule, RuntimeConfig};
+
+enum Matcher {
+    Exact(String),
+    Prefix(String),
+    Regex(Regex),
+}
+
+impl PartialEq for Matcher {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Matcher::Exact(a), Matcher::Exact(b)) => a == b,
+            (Matcher::Prefix(a), Matcher::Prefix(b)) => a == b,
+            (Matcher::Regex(a), Matcher::Regex(b)) => a.as_str() == b.as_str(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(PartialEq)]
+struct Rule {
+    matcher: Matcher,
+    to: String,
+    action: RedirectAction,
+}
+
+impl Rule {
+    fn new(config: &RedirectRule) -> Option<Self> {
+        let matcher = match config.path_match() {
+            PathMatch::Exact => Matcher::Exact(config.from().to_string()),
+            PathMatch::Prefix => Matcher::Prefix(config.from().trim_end_matches('/').to_string()),
+            // the config has already validated the regex
+            PathMatch::Regex => {
+                Matcher::Regex(Regex::new(&format!("^(?:{})$", config.from())).ok()?)
+            }
+            _ => return None,
+        };
+
+        Some(Rule {
+            matcher,
+            to: config.to().to_string(),
+            action: config.action(),
+        })
+    }
+
+    fn target(&self, path: &str) -> Option<String> {
+        match &self.matcher {
+            Matcher::Exact(from) => (path == from).then(|| self.to.clone()),
+            Matcher::Prefix(from) => {
+                let rest = path.strip_prefix(from.as_str())?;
+                if !(rest.is_empty() || rest.starts_with('/')) {
+                    return None;
+                }
+                let target = format!("{}{rest}", self.to.trim_end_matches('/'));
+                Some(if target.is_empty() {
+                    "/".to_string()
+                } else {
+                    target
+                })
+            }
+            Matcher::Regex(regex) => {
+                let captures = regex.captures(path)?;
+                let mut target = String::new();
+                captures.expand(&self.to, &mut target);
+                Some(target)
+            }
+        }
+    }
+}
+
+#[derive(PartialEq)]
+struct Rules(Vec<Rule>);
+
+impl Rules {
+    fn new(config: &[RedirectRule]) -> Self {
+        Rules(config.iter().filter_map(Rule::new).collect())
+    }
+
+    /// Returns the action and target of the first rule matching the URI, with the URI's query appended to the target
+    /// if it doesn't have its own.
+    fn resolve(&self, uri: &Uri) -> Option<(RedirectAction, String)> {
+        let (rule, mut target) = self
+            .0
+            .iter()
+            .find_map(|rule| rule.target(uri.path()).map(|target| (rule, target)))?;
+
+        if let Some(query) = uri.query() {
+            if !target.contains('?') {
+                target.push('?');
+                target.push_str(query);
+            }
+        }
+
+        Some((rule.action, target))
+    }
+}
+
+/// A redirect to be returned in place of the request's response.
+#[derive(Clone)]
+pub struct Redirect {
+    status: StatusCode,
+    location: HeaderValue,
+}
+
+impl Redirect {
+    pub fn into_response(self) -> Response<BoxBody<Bytes, BodyWriteAborted>> {
+        let mut response = Response::new(EmptyBody.boxed());
+        *response.status_mut() = self.status;
+        response.headers_mut().insert(LOCATION, self.location);
+        response
+    }
+}
+
+/// A layer which applies the runtime redirect and rewrite rules to requests.
+///
+/// It must be installed before routing so rewritten requests are routed to their target. Redirects are recorded in the
+/// request's extensions and returned by the [`HandlerService`](crate::service::handler::HandlerService), so they pass
+/// through the server's logging and metrics layers like any other response.
+pub struct RedirectLayer {
+    rules: Refreshable<Arc<Rules>, Error>,
+}
+
+impl RedirectLayer {
+    pub fn new(runtime_config: &Refreshable<RuntimeConfig, Error>) -> Self {
+        RedirectLayer {
+            rules: runtime_config.map(|c| Arc::new(Rules::new(c.redirects()))),
+        }
+    }
+}
+
+impl<S> Layer<S> for RedirectLayer {
+    type Service = RedirectService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        RedirectService {
+            inner,
+            rules: self.rules,
+        }
+    }
+}
+
+pub struct RedirectService<S> {
+    inner: S,
+    rules: Refreshable<Arc<Rules>, Error>,
+}
+
+impl<S, B> Service<Request<B>> for RedirectService<S>
+where
+    S: Service<Request<B>> + Sync,
+    B: Send,
+{
+    type Response = S::Response;
+
+    // This wraps the entire request stack, so it returns the inner future directly rather than nesting it in another
+    // async block, which would add to the stack usage of every request.
+    fn call(&self, mut req: Request<B>) -> impl Future<Output = Self::Response> + Send {
+        let resolved = self.rules.get().resolve(req.uri());
+
+        match resolved {
+            Some((RedirectAction::Rewrite, target)) => {
+                if let Ok(path_and_query) = target.parse::<PathAndQuery>() {
+                    let mut parts = req.uri().clone().into_parts();
+                    parts.path_and_query = Some(path_and_query);
+                    if let Ok(uri) = Uri::from_parts(parts) {
+                        *req.uri_mut() = uri;
+                    }
+                }
+            }
+            Some((action, target)) => {
+                let status = match action {
+                    RedirectAction::TemporaryRedirect => StatusCode::TEMPORARY_REDIRECT,
+                    _ => StatusCode::PERMANENT_REDIRECT,
+                };
+                if let Ok(location) = HeaderValue::try_from(target) {
+                    req.extensions_mut().insert(Redirect { status, location });
+                }
+            }
+            None => {}
+        }
+
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rules(rules: Vec<RedirectRule>) -> Rules {
+        Rules::new(&rules)
+    }
+
+    fn resolve(rules: &Rules, uri: &str) -> Option<(RedirectAction, String)> {
+        rules.resolve(&uri.parse().unwrap())
+    }
+
+    #[test]
+    fn exact() {
+        let rules = rules(vec![RedirectRule::builder()
+            .from("/old")
+            .to("/new")
+            .build()
+            .unwrap()]);
+
+        assert_eq!(
+            resolve(&rules, "/old?a=b"),
+            Some((RedirectAction::PermanentRedirect, "/new?a=b".to_string())),
+        );
+        assert_eq!(resolve(&rules, "/old/foo"), None);
+    }
+
+    #[test]
+    fn prefix() {
+        let rules = rules(vec![RedirectRule::builder()
+            .from("/legacy/")
+            .to("/service/api")
+            .path_match(PathMatch::Prefix)
+            .action(RedirectAction::Rewrite)
+            .build()
+            .unwrap()]);
+
+        assert_eq!(
+            resolve(&rules, "/legacy/foo/bar"),
+            Some((RedirectAction::Rewrite, "/service/api/foo/bar".to_string())),
+        );
+        assert_eq!(
+            resolve(&rules, "/legacy"),
+            Some((RedirectAction::Rewrite, "/service/api".to_string())),
+        );
+        assert_eq!(resolve(&rules, "/legacyfoo"), None);
+    }
+
+    #[test]
+    fn regex() {
+        let rules = rules(vec![RedirectRule::builder()
+            .from("/users/(?P<id>[0-9]+)/profile")
+            .to("https://example.com/profiles/${id}?source=legacy")
+            .path_match(PathMatch::Regex)
+            .action(RedirectAction::TemporaryRedirect)
+            .build()
+            .unwrap()]);
+
+        assert_eq!(
+            resolve(&rules, "/users/123/profile?a=b"),
+            Some((
+                RedirectAction::TemporaryRedirect,
+                "https://example.com/profiles/123?source=legacy".to_string()
+            )),
+        );
+        assert_eq!(resolve(&rules, "/users/123/profile/extra"), None);
+    }
+}
@@ -11,23 +11,28 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use crate::extensions::{GeoInfo, PeerAddr, RequestClass};
+use crate::logging::access::AccessLogEntry;
 use crate::logging::api::{OrganizationId, RequestLogV2, SessionId, TokenId, TraceId, UserId};
-use crate::logging::{self, Appender, Payload};
+use crate::logging::{self, AccessLogger, Appender, Payload};
 use crate::service::routing::Route;
 use crate::service::{Layer, Service};
+use crate::tls::{ClientCertificate, TlsSession};
 use bytes::Buf;
 use conjure_http::SafeParams;
 use conjure_object::{Any, SafeLong, Utc};
 use futures_util::ready;
-use http::{Request, Response};
+use http::{HeaderMap, Request, Response};
 use http_body::{Body, Frame};
 use pin_project::pin_project;
 use serde::Deserialize;
-use std::mem;
+use std::error::Error;
 use std::pin::Pin;
-use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::atomic::{AtomicI64, AtomicU8, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Duration;
+use std::{io, mem};
 use tokio::time::Instant;
 use witchcraft_log::mdc;
 
@@ -65,17 +70,125 @@ const SAFE_HEADERS: &[&str] = &[
 // We only want a few witchcraft-specific MDC entries in request log params
 const MDC_KEYS: &[&str] = &[logging::REQUEST_ID_KEY, logging::SAMPLED_KEY];
 
+const DISPOSITION_KEY: &str = "disposition";
+const COUNTRY_KEY: &str = "country";
+const ASN_KEY: &str = "asn";
+const REQUEST_CLASS_KEY: &str = "requestClass";
+const TLS_PROTOCOL_KEY: &str = "tlsProtocol";
+const TLS_CIPHER_KEY: &str = "tlsCipher";
+const CLIENT_CERT_SUBJECT_KEY: &str = "clientCertificateSubject";
+const CLIENT_CERT_CN_KEY: &str = "clientCertificateCommonName";
+const CLIENT_CERT_SANS_KEY: &str = "clientCertificateSubjectAltNames";
+const CLIENT_CERT_FINGERPRINT_KEY: &str = "clientCertificateFingerprint";
+
+/// The manner in which the processing of a request finished.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Disposition {
+    /// The full response was sent to the client.
+    Completed = 1,
+    /// The client closed the connection or stopped sending data before the request completed.
+    ClientAbort,
+    /// The connection timed out waiting on the client.
+    ClientTimeout,
+    /// The client reset the connection.
+    ClientReset,
+    /// The server failed to produce the full response.
+    ServerError,
+}
+
+impl Disposition {
+    /// Classifies the disposition of a request that failed with the provided error.
+    ///
+    /// Errors which can't be attributed to the client are treated as server errors.
+    pub fn from_error(error: &(dyn Error + 'static)) -> Self {
+        let mut cur = Some(error);
+        while let Some(error) = cur {
+            if let Some(error) = error.downcast_ref::<hyper::Error>() {
+                if error.is_timeout() {
+                    return Disposition::ClientTimeout;
+                }
+                if error.is_incomplete_message() || error.is_canceled() {
+                    return Disposition::ClientAbort;
+                }
+            }
+
+            if let Some(error) = error.downcast_ref::<io::Error>() {
+                match error.kind() {
+                    io::ErrorKind::ConnectionReset | io::ErrorKind::BrokenPipe => {
+                        return Disposition::ClientReset
+                    }
+                    io::ErrorKind::TimedOut => return Disposition::ClientTimeout,
+                    io::ErrorKind::ConnectionAborted | io::ErrorKind::UnexpectedEof => {
+                        return Disposition::ClientAbort
+                    }
+                    _ => {}
+                }
+            }
+
+            cur = error.source();
+        }
+
+        Disposition::ServerError
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Disposition::Completed => "COMPLETED",
+            Disposition::ClientAbort => "CLIENT_ABORT",
+            Disposition::ClientTimeout => "CLIENT_TIMEOUT",
+            Disposition::ClientReset => "CLIENT_RESET",
+            Disposition::ServerError => "SERVER_ERROR",
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Disposition::Completed),
+            2 => Some(Disposition::ClientAbort),
+            3 => Some(Disposition::ClientTimeout),
+            4 => Some(Disposition::ClientReset),
+            5 => Some(Disposition::ServerError),
+            _ => None,
+        }
+    }
+}
+
+/// A write-once disposition shared between the request and response bodies.
+#[derive(Default)]
+struct DispositionCell(AtomicU8);
+
+impl DispositionCell {
+    fn set(&self, disposition: Disposition) {
+        let _ = self
+            .0
+            .compare_exchange(0, disposition as u8, Ordering::Relaxed, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> Option<Disposition> {
+        Disposition::from_u8(self.0.load(Ordering::Relaxed))
+    }
+}
+
 /// A layer which records request logs.
 ///
 /// It must be installed after routing and logger MDC initialization. It will add the contents of the response's
-/// [`SafeParams`] extension as safe parameters.
+/// [`SafeParams`] extension as safe parameters. If an access logger is provided, it will additionally record an
+/// access log entry for each request.
 pub struct RequestLogLayer {
     appender: Arc<Appender<RequestLogV2>>,
+    access_logger: Option<Arc<AccessLogger>>,
 }
 
 impl RequestLogLayer {
-    pub fn new(appender: Arc<Appender<RequestLogV2>>) -> Self {
-        RequestLogLayer { appender }
+    pub fn new(
+        appender: Arc<Appender<RequestLogV2>>,
+        access_logger: Option<Arc<AccessLogger>>,
+    ) -> Self {
+        RequestLogLayer {
+            appender,
+            access_logger,
+        }
     }
 }
 
@@ -86,6 +199,7 @@ impl<S> Layer<S> for RequestLogLayer {
         RequestLogService {
             inner,
             appender: self.appender,
+            access_logger: self.access_logger,
         }
     }
 }
@@ -93,12 +207,14 @@ impl<S> Layer<S> for RequestLogLayer {
 pub struct RequestLogService<S> {
     inner: S,
     appender: Arc<Appender<RequestLogV2>>,
+    access_logger: Option<Arc<AccessLogger>>,
 }
 
 impl<S, B1, B2> Service<Request<B1>> for RequestLogService<S>
 where
     S: Service<Request<RequestLogRequestBody<B1>>, Response = Response<B2>> + Sync,
     B1: Send,
+    B2: Body,
 {
     type Response = Response<RequestLogResponseBody<B2>>;
 
@@ -176,6 +292,60 @@ where
             }
         }
 
+        if let Some(geo) = req.extensions().get::<GeoInfo>() {
+            if let Some(country) = geo.country() {
+                params.push((COUNTRY_KEY.to_string(), Any::new(country).unwrap()));
+            }
+            if let Some(asn) = geo.asn() {
+                params.push((ASN_KEY.to_string(), Any::new(asn).unwrap()));
+            }
+        }
+
+        if let Some(class) = req.extensions().get::<RequestClass>() {
+            params.push((
+                REQUEST_CLASS_KEY.to_string(),
+                Any::new(class.labels()).unwrap(),
+            ));
+        }
+
+        if let Some(session) = req.extensions().get::<TlsSession>() {
+            params.push((
+                TLS_PROTOCOL_KEY.to_string(),
+                Any::new(session.protocol()).unwrap(),
+            ));
+            params.push((
+                TLS_CIPHER_KEY.to_string(),
+                Any::new(session.cipher_suite()).unwrap(),
+            ));
+        }
+
+        if let Some(cert) = req.extensions().get::<ClientCertificate>() {
+            if !cert.subject().is_empty() {
+                params.push((
+                    CLIENT_CERT_SUBJECT_KEY.to_string(),
+                    Any::new(cert.subject()).unwrap(),
+                ));
+            }
+            if let Some(common_name) = cert.common_name() {
+                params.push((
+                    CLIENT_CERT_CN_KEY.to_string(),
+                    Any::new(common_name).unwrap(),
+                ));
+            }
+            if !cert.subject_alt_names().is_empty() {
+                let names = cert
+                    .subject_alt_names()
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect::<Vec<_>>();
+                params.push((CLIENT_CERT_SANS_KEY.to_string(), Any::new(names).unwrap()));
+            }
+            params.push((
+                CLIENT_CERT_FINGERPRINT_KEY.to_string(),
+                Any::new(cert.fingerprint()).unwrap(),
+            ));
+        }
+
         let mut unsafe_params = vec![];
         if let Some(path_and_query) = req.uri().path_and_query() {
             unsafe_params.push((
@@ -184,6 +354,30 @@ where
             ));
         }
 
+        let access_log = self.access_logger.as_ref().map(|logger| {
+            let mut headers = HeaderMap::new();
+            for name in logger.format.request_headers() {
+                for value in req.headers().get_all(name) {
+                    headers.append(name.clone(), value.clone());
+                }
+            }
+
+            let entry = AccessLogEntry {
+                remote_addr: req.extensions().get::<PeerAddr>().map(|a| a.ip()),
+                user: uid.as_ref().map(|uid| uid.to_string()),
+                time: Utc::now(),
+                method: req.method().as_str().to_string(),
+                path: req.uri().path().to_string(),
+                query: req.uri().query().map(|q| q.to_string()),
+                protocol: protocol.clone(),
+                headers,
+                status: 0,
+                response_size: 0,
+                duration: Duration::ZERO,
+            };
+            (logger.clone(), entry)
+        });
+
         let mut state = State {
             protocol,
             method,
@@ -199,7 +393,9 @@ where
             start_time: Instant::now(),
             request_size: Arc::new(AtomicI64::new(0)),
             response_size: 0,
+            disposition: Arc::new(DispositionCell::default()),
             appender: self.appender.clone(),
+            access_log,
         };
 
         let response = self
@@ -207,9 +403,15 @@ where
             .call(req.map(|inner| RequestLogRequestBody {
                 inner,
                 request_size: state.request_size.clone(),
+                disposition: state.disposition.clone(),
             }))
             .await;
 
+        // Hyper won't poll a body which reports that it's already finished.
+        if response.body().is_end_stream() {
+            state.disposition.set(Disposition::Completed);
+        }
+
         state.status = i32::from(response.status().as_u16());
         if let Some(safe_params) = response.extensions().get::<SafeParams>() {
             state
@@ -226,11 +428,24 @@ pub struct RequestLogRequestBody<B> {
     #[pin]
     inner: B,
     request_size: Arc<AtomicI64>,
+    disposition: Arc<DispositionCell>,
+}
+
+impl<B> RequestLogRequestBody<B> {
+    /// Returns a body which is not associated with any request log.
+    pub(crate) fn passthrough(inner: B) -> Self {
+        RequestLogRequestBody {
+            inner,
+            request_size: Arc::new(AtomicI64::new(0)),
+            disposition: Arc::new(DispositionCell::default()),
+        }
+    }
 }
 
 impl<B> Body for RequestLogRequestBody<B>
 where
     B: Body,
+    B::Error: Error + 'static,
 {
     type Data = B::Data;
 
@@ -243,11 +458,15 @@ where
         let this = self.project();
 
         let value = ready!(this.inner.poll_frame(cx));
-        if let Some(Ok(frame)) = &value {
-            if let Some(chunk) = frame.data_ref() {
-                this.request_size
-                    .fetch_add(chunk.remaining() as i64, Ordering::Relaxed);
+        match &value {
+            Some(Ok(frame)) => {
+                if let Some(chunk) = frame.data_ref() {
+                    this.request_size
+                        .fetch_add(chunk.remaining() as i64, Ordering::Relaxed);
+                }
             }
+            Some(Err(e)) => this.disposition.set(Disposition::from_error(e)),
+            None => {}
         }
 
         Poll::Ready(value)
@@ -281,12 +500,19 @@ where
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
-        let this = self.project();
-        let value = ready!(this.inner.poll_frame(cx));
-        if let Some(Ok(frame)) = &value {
-            if let Some(chunk) = frame.data_ref() {
-                this.state.response_size += chunk.remaining() as i64;
+        let mut this = self.project();
+        let value = ready!(this.inner.as_mut().poll_frame(cx));
+        match &value {
+            Some(Ok(frame)) => {
+                if let Some(chunk) = frame.data_ref() {
+                    this.state.response_size += chunk.remaining() as i64;
+                }
+                if this.inner.is_end_stream() {
+                    this.state.disposition.set(Disposition::Completed);
+                }
             }
+            Some(Err(_)) => this.state.disposition.set(Disposition::ServerError),
+            None => this.state.disposition.set(Disposition::Completed),
         }
         Poll::Ready(value)
     }
@@ -315,12 +541,26 @@ struct State {
     start_time: Instant,
     request_size: Arc<AtomicI64>,
     response_size: i64,
+    disposition: Arc<DispositionCell>,
     appender: Arc<Appender<RequestLogV2>>,
+    access_log: Option<(Arc<AccessLogger>, AccessLogEntry)>,
 }
 
 impl Drop for State {
     fn drop(&mut self) {
-        let duration = SafeLong::try_from(self.start_time.elapsed().as_micros())
+        let elapsed = self.start_time.elapsed();
+
+        if let Some((logger, mut entry)) = self.access_log.take() {
+            entry.status = self.status as u16;
+            entry.response_size = self.response_size;
+            entry.duration = elapsed;
+            let _ = logger.appender.try_send(Payload {
+                value: logger.format.render(&entry),
+                cb: None,
+            });
+        }
+
+        let duration = SafeLong::try_from(elapsed.as_micros())
             .ok()
             .unwrap_or_else(SafeLong::max_value);
         let request_size = SafeLong::try_from(self.request_size.load(Ordering::Relaxed))
@@ -329,6 +569,12 @@ impl Drop for State {
         let response_size = SafeLong::try_from(self.response_size)
             .ok()
             .unwrap_or_else(SafeLong::max_value);
+        // If the response body was dropped before it finished, the connection to the client was lost.
+        let disposition = self.disposition.get().unwrap_or(Disposition::ClientAbort);
+        self.params.push((
+            DISPOSITION_KEY.to_string(),
+            Any::new(disposition.as_str()).unwrap(),
+        ));
 
         let request_log = RequestLogV2::builder()
             .type_("request.2")
@@ -355,3 +601,36 @@ impl Drop for State {
         });
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn classify_io_errors() {
+        let error = io::Error::from(io::ErrorKind::ConnectionReset);
+        assert_eq!(Disposition::from_error(&error), Disposition::ClientReset);
+
+        let error = io::Error::from(io::ErrorKind::TimedOut);
+        assert_eq!(Disposition::from_error(&error), Disposition::ClientTimeout);
+
+        let error = io::Error::from(io::ErrorKind::UnexpectedEof);
+        assert_eq!(Disposition::from_error(&error), Disposition::ClientAbort);
+    }
+
+    #[test]
+    fn classify_wrapped_errors() {
+        let error =
+            conjure_error::Error::internal_safe(io::Error::from(io::ErrorKind::ConnectionReset));
+        assert_eq!(
+            Disposition::from_error(error.cause()),
+            Disposition::ClientReset
+        );
+    }
+
+    #[test]
+    fn classify_unknown_errors() {
+        let error = io::Error::other("blammo");
+        assert_eq!(Disposition::from_error(&error), Disposition::ServerError);
+    }
+}
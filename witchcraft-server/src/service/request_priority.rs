@@ -0,0 +1,207 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::classification::{Priority, RequestClass, REQUEST_PRIORITY};
+use crate::extensions::PeerAddr;
+use crate::service::{Layer, Service};
+use conjure_error::Error;
+use http::Request;
+use refreshable::Refreshable;
+use std::future::Future;
+use std::sync::Arc;
+use witchcraft_metrics::{Meter, MetricId, MetricRegistry};
+use witchcraft_server_config::runtime::{RequestPriorityConfig, RuntimeConfig};
+
+/// A layer which applies the priority set in the `Request-Priority` header of requests from trusted clients to their
+/// [`RequestClass`], overriding the priority assigned by the request classifier.
+///
+/// It must be installed after classification.
+pub struct RequestPriorityLayer {
+    config: Refreshable<RequestPriorityConfig, Error>,
+    meters: PriorityMeters,
+}
+
+impl RequestPriorityLayer {
+    pub fn new(
+        runtime_config: &Refreshable<RuntimeConfig, Error>,
+        metrics: &MetricRegistry,
+    ) -> Self {
+        let meter = |priority| {
+            metrics.meter(MetricId::new("server.request.priority").with_tag("priority", priority))
+        };
+
+        RequestPriorityLayer {
+            config: runtime_config.map(|c| c.request_priority().clone()),
+            meters: PriorityMeters {
+                low: meter("low"),
+                normal: meter("normal"),
+                critical: meter("critical"),
+                untrusted: metrics.meter("server.request.priority.untrusted"),
+            },
+        }
+    }
+}
+
+impl<S> Layer<S> for RequestPriorityLayer {
+    type Service = RequestPriorityService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        RequestPriorityService {
+            inner,
+            config: self.config,
+            meters: self.meters,
+        }
+    }
+}
+
+struct PriorityMeters {
+    low: Arc<Meter>,
+    normal: Arc<Meter>,
+    critical: Arc<Meter>,
+    untrusted: Arc<Meter>,
+}
+
+pub struct RequestPriorityService<S> {
+    inner: S,
+    config: Refreshable<RequestPriorityConfig, Error>,
+    meters: PriorityMeters,
+}
+
+impl<S, B> Service<Request<B>> for RequestPriorityService<S>
+where
+    S: Service<Request<B>> + Sync,
+    B: Send,
+{
+    type Response = S::Response;
+
+    fn call(&self, mut req: Request<B>) -> impl Future<Output = Self::Response> + Send {
+        if let Some(priority) = self.priority(&req) {
+            match req.extensions_mut().get_mut::<RequestClass>() {
+                Some(class) => *class = class.clone().with_priority(priority),
+                None => {
+                    req.extensions_mut()
+                        .insert(RequestClass::new().with_priority(priority));
+                }
+            }
+        }
+
+        self.inner.call(req)
+    }
+}
+
+impl<S> RequestPriorityService<S> {
+    fn priority<B>(&self, req: &Request<B>) -> Option<Priority> {
+        let value = req.headers().get(REQUEST_PRIORITY)?;
+
+        let trusted = req
+            .extensions()
+            .get::<PeerAddr>()
+            .is_some_and(|addr| self.config.get().trusts(addr.ip()));
+        if !trusted {
+            self.meters.untrusted.mark(1);
+            return None;
+        }
+
+        let (priority, meter) = match value.as_bytes() {
+            v if v.eq_ignore_ascii_case(b"low") => (Priority::Low, &self.meters.low),
+            v if v.eq_ignore_ascii_case(b"normal") => (Priority::Normal, &self.meters.normal),
+            v if v.eq_ignore_ascii_case(b"critical") => (Priority::Critical, &self.meters.critical),
+            _ => return None,
+        };
+        meter.mark(1);
+
+        Some(priority)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::service::test_util::service_fn;
+    use witchcraft_server_config::net::IpNetwork;
+    use witchcraft_server_config::runtime::{DiagnosticsConfig, HealthChecksConfig};
+
+    fn layer(metrics: &MetricRegistry) -> RequestPriorityLayer {
+        let config = RuntimeConfig::builder()
+            .diagnostics(DiagnosticsConfig::builder().debug_shared_secret("").build())
+            .health_checks(HealthChecksConfig::builder().shared_secret("").build())
+            .request_priority(
+                RequestPriorityConfig::builder()
+                    .trusted_networks(["10.0.0.0/8".parse::<IpNetwork>().unwrap()])
+                    .build(),
+            )
+            .build();
+        let (config, _handle) = Refreshable::new(config);
+
+        RequestPriorityLayer::new(&config, metrics)
+    }
+
+    fn request(peer: &str, priority: Option<&'static str>) -> Request<()> {
+        let mut builder = Request::builder();
+        if let Some(priority) = priority {
+            builder = builder.header(REQUEST_PRIORITY, priority);
+        }
+        let mut request = builder.body(()).unwrap();
+        request
+            .extensions_mut()
+            .insert(PeerAddr(peer.parse().unwrap()));
+        request
+    }
+
+    #[tokio::test]
+    async fn trusted() {
+        let metrics = MetricRegistry::new();
+        let service = layer(&metrics).layer(service_fn(|req: Request<()>| async move {
+            req.extensions().get::<RequestClass>().cloned()
+        }));
+
+        let class = service.call(request("10.1.2.3:1234", Some("low"))).await;
+        assert_eq!(class.unwrap().priority(), Priority::Low);
+
+        let mut req = request("10.1.2.3:1234", Some("CRITICAL"));
+        req.extensions_mut()
+            .insert(RequestClass::new().with_label("workload", "batch"));
+        let class = service.call(req).await.unwrap();
+        assert_eq!(class.priority(), Priority::Critical);
+        assert_eq!(class.label("workload"), Some("batch"));
+
+        assert_eq!(
+            service.call(request("10.1.2.3:1234", Some("bogus"))).await,
+            None
+        );
+        assert_eq!(service.call(request("10.1.2.3:1234", None)).await, None);
+
+        let meter =
+            metrics.meter(MetricId::new("server.request.priority").with_tag("priority", "low"));
+        assert_eq!(meter.count(), 1);
+    }
+
+    #[tokio::test]
+    async fn untrusted() {
+        let metrics = MetricRegistry::new();
+        let service = layer(&metrics).layer(service_fn(|req: Request<()>| async move {
+            req.extensions().get::<RequestClass>().cloned()
+        }));
+
+        assert_eq!(
+            service
+                .call(request("192.168.0.1:1234", Some("critical")))
+                .await,
+            None
+        );
+        assert_eq!(
+            metrics.meter("server.request.priority.untrusted").count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,181 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::service::{Layer, Service};
+use bytes::Bytes;
+use conjure_error::{Error, RequestEntityTooLarge};
+use http::Request;
+use http_body::{Body, Frame, SizeHint};
+use pin_project::pin_project;
+use refreshable::Refreshable;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use witchcraft_server_config::runtime::RuntimeConfig;
+
+/// A layer which fails reads of request bodies once they exceed the runtime configuration's `max-request-size`.
+///
+/// The handler rejects requests declaring a `Content-Length` over the limit before their body is read, but chunked and
+/// HTTP/2 requests need not declare a length, and compressed requests are limited by their decompressed size. The
+/// error raised here is a `RequestEntityTooLarge` service error, so endpoints reading the body respond with
+/// `413 Request Entity Too Large`. It must be installed after the compression layer.
+pub struct RequestSizeLimitLayer {
+    max_request_size: Refreshable<Option<u64>, Error>,
+}
+
+impl RequestSizeLimitLayer {
+    pub fn new(runtime_config: &Refreshable<RuntimeConfig, Error>) -> Self {
+        RequestSizeLimitLayer {
+            max_request_size: runtime_config.map(|c| c.max_request_size()),
+        }
+    }
+}
+
+impl<S> Layer<S> for RequestSizeLimitLayer {
+    type Service = RequestSizeLimitService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        RequestSizeLimitService {
+            inner,
+            max_request_size: self.max_request_size,
+        }
+    }
+}
+
+pub struct RequestSizeLimitService<S> {
+    inner: S,
+    max_request_size: Refreshable<Option<u64>, Error>,
+}
+
+impl<S, B> Service<Request<B>> for RequestSizeLimitService<S>
+where
+    S: Service<Request<RequestSizeLimitBody<B>>> + Sync,
+    B: Send,
+{
+    type Response = S::Response;
+
+    async fn call(&self, req: Request<B>) -> Self::Response {
+        let limit = *self.max_request_size.get();
+        let req = req.map(|inner| RequestSizeLimitBody {
+            inner,
+            limit,
+            read: 0,
+        });
+
+        self.inner.call(req).await
+    }
+}
+
+#[pin_project]
+pub struct RequestSizeLimitBody<B> {
+    #[pin]
+    inner: B,
+    limit: Option<u64>,
+    read: u64,
+}
+
+impl<B> RequestSizeLimitBody<B> {
+    /// Returns a body which is not limited.
+    pub(crate) fn passthrough(inner: B) -> Self {
+        RequestSizeLimitBody {
+            inner,
+            limit: None,
+            read: 0,
+        }
+    }
+}
+
+impl<B> Body for RequestSizeLimitBody<B>
+where
+    B: Body<Data = Bytes, Error = Error>,
+{
+    type Data = Bytes;
+
+    type Error = Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+
+        let frame = ready!(this.inner.poll_frame(cx));
+        if let (Some(Ok(frame)), Some(limit)) = (&frame, *this.limit) {
+            if let Some(data) = frame.data_ref() {
+                *this.read += data.len() as u64;
+                if *this.read > limit {
+                    return Poll::Ready(Some(Err(Error::service_safe(
+                        "request body too large",
+                        RequestEntityTooLarge::new(),
+                    )
+                    .with_safe_param("maxRequestSize", limit))));
+                }
+            }
+        }
+
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use conjure_error::ErrorKind;
+    use http_body_util::{BodyExt, StreamBody};
+
+    fn body(
+        chunks: &[&'static [u8]],
+        limit: Option<u64>,
+    ) -> impl Body<Data = Bytes, Error = Error> {
+        let chunks = chunks
+            .iter()
+            .map(|c| Ok(Frame::data(Bytes::from_static(c))))
+            .collect::<Vec<_>>();
+        RequestSizeLimitBody {
+            inner: StreamBody::new(futures_util::stream::iter(chunks)),
+            limit,
+            read: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn within_limit() {
+        let body = body(&[b"hello", b"world"], Some(10));
+        let bytes = body.collect().await.unwrap().to_bytes();
+        assert_eq!(bytes, "helloworld");
+    }
+
+    #[tokio::test]
+    async fn over_limit() {
+        let mut body = body(&[b"hello", b"world!"], Some(10));
+        body.frame().await.unwrap().unwrap();
+        let error = body.frame().await.unwrap().unwrap_err();
+        let ErrorKind::Service(service) = error.kind() else {
+            panic!("expected a service error");
+        };
+        assert_eq!(service.error_name(), "Default:RequestEntityTooLarge");
+    }
+
+    #[tokio::test]
+    async fn unlimited() {
+        let body = body(&[b"hello", b"world!"], None);
+        body.collect().await.unwrap();
+    }
+}
@@ -0,0 +1,269 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::service::handler::{self, BodyWriteAborted};
+use crate::service::routing::Route;
+use crate::service::{Layer, Service};
+use bytes::{Buf, Bytes};
+use conjure_error::Error;
+use http::{Request, Response};
+use http_body::{Body, Frame, SizeHint};
+use http_body_util::combinators::BoxBody;
+use http_body_util::BodyExt;
+use pin_project::pin_project;
+use refreshable::Refreshable;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use witchcraft_log::error;
+use witchcraft_metrics::{MetricId, MetricRegistry};
+use witchcraft_server_config::runtime::{ResponseLimitsConfig, RuntimeConfig};
+
+/// A layer which aborts responses whose bodies exceed the size limits in the runtime config's `response-limits`
+/// section.
+///
+/// Responses whose size is known up front are replaced with a `500 Internal Server Error` response, while streaming
+/// responses are aborted once the limit is crossed. Either way, an error is logged and the
+/// `server.response.too-large` meter is marked.
+///
+/// It must be installed after routing.
+pub struct ResponseLimitLayer {
+    config: Refreshable<ResponseLimitsConfig, Error>,
+    metrics: Arc<MetricRegistry>,
+}
+
+impl ResponseLimitLayer {
+    pub fn new(
+        runtime_config: &Refreshable<RuntimeConfig, Error>,
+        metrics: &Arc<MetricRegistry>,
+    ) -> Self {
+        ResponseLimitLayer {
+            config: runtime_config.map(|c| c.response_limits().clone()),
+            metrics: metrics.clone(),
+        }
+    }
+}
+
+impl<S> Layer<S> for ResponseLimitLayer {
+    type Service = ResponseLimitService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        ResponseLimitService {
+            inner,
+            config: self.config,
+            metrics: self.metrics,
+        }
+    }
+}
+
+pub struct ResponseLimitService<S> {
+    inner: S,
+    config: Refreshable<ResponseLimitsConfig, Error>,
+    metrics: Arc<MetricRegistry>,
+}
+
+impl<S, B> Service<Request<B>> for ResponseLimitService<S>
+where
+    S: Service<Request<B>, Response = Response<BoxBody<Bytes, BodyWriteAborted>>> + Sync,
+    B: Send,
+{
+    type Response = S::Response;
+
+    async fn call(&self, req: Request<B>) -> Self::Response {
+        let Some(limit) = self.limit(&req) else {
+            return self.inner.call(req).await;
+        };
+
+        let response = self.inner.call(req).await;
+        if let Some(size) = response.body().size_hint().exact() {
+            if size > limit.max_size {
+                limit.exceeded(size);
+                return handler::error_response(
+                    Error::internal_safe("response body too large")
+                        .with_safe_param("serviceName", &limit.service_name)
+                        .with_safe_param("endpoint", &limit.endpoint)
+                        .with_safe_param("size", size)
+                        .with_safe_param("maxResponseSize", limit.max_size),
+                );
+            }
+            return response;
+        }
+
+        response.map(|inner| {
+            ResponseLimitBody {
+                inner,
+                written: 0,
+                limit,
+            }
+            .boxed()
+        })
+    }
+}
+
+impl<S> ResponseLimitService<S> {
+    fn limit<B>(&self, req: &Request<B>) -> Option<Limit> {
+        let config = self.config.get();
+        if config.max_size().is_none() && config.endpoints().is_empty() {
+            return None;
+        }
+
+        let Some(Route::Resolved(endpoint)) = req.extensions().get::<Route>() else {
+            return None;
+        };
+        let key = format!("{}.{}", endpoint.service_name(), endpoint.name());
+        let max_size = config
+            .endpoints()
+            .get(&key)
+            .copied()
+            .or(config.max_size())?;
+
+        Some(Limit {
+            max_size,
+            service_name: endpoint.service_name().to_string(),
+            endpoint: endpoint.name().to_string(),
+            metrics: self.metrics.clone(),
+        })
+    }
+}
+
+struct Limit {
+    max_size: u64,
+    service_name: String,
+    endpoint: String,
+    metrics: Arc<MetricRegistry>,
+}
+
+impl Limit {
+    fn exceeded(&self, size: u64) {
+        self.metrics
+            .meter(
+                MetricId::new("server.response.too-large")
+                    .with_tag("service-name", self.service_name.clone())
+                    .with_tag("endpoint", self.endpoint.clone()),
+            )
+            .mark(1);
+        error!(
+            "response body exceeded the maximum size",
+            safe: {
+                serviceName: self.service_name,
+                endpoint: self.endpoint,
+                size: size,
+                maxResponseSize: self.max_size,
+            },
+        );
+    }
+}
+
+#[pin_project]
+struct ResponseLimitBody<B> {
+    #[pin]
+    inner: B,
+    written: u64,
+    limit: Limit,
+}
+
+impl<B> Body for ResponseLimitBody<B>
+where
+    B: Body<Data = Bytes, Error = BodyWriteAborted>,
+{
+    type Data = Bytes;
+
+    type Error = BodyWriteAborted;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+
+        let frame = match this.inner.poll_frame(cx) {
+            Poll::Ready(Some(Ok(frame))) => frame,
+            poll => return poll,
+        };
+
+        if let Some(data) = frame.data_ref() {
+            *this.written += data.remaining() as u64;
+            if *this.written > this.limit.max_size {
+                this.limit.exceeded(*this.written);
+                return Poll::Ready(Some(Err(BodyWriteAborted)));
+            }
+        }
+
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures_util::stream;
+    use http_body_util::StreamBody;
+
+    fn limit(max_size: u64) -> Limit {
+        Limit {
+            max_size,
+            service_name: "TestService".to_string(),
+            endpoint: "test".to_string(),
+            metrics: Arc::new(MetricRegistry::new()),
+        }
+    }
+
+    fn body(chunks: &[&'static str]) -> BoxBody<Bytes, BodyWriteAborted> {
+        let frames = chunks
+            .iter()
+            .map(|c| Ok(Frame::data(Bytes::from_static(c.as_bytes()))))
+            .collect::<Vec<_>>();
+        StreamBody::new(stream::iter(frames)).boxed()
+    }
+
+    #[tokio::test]
+    async fn under_limit() {
+        let body = ResponseLimitBody {
+            inner: body(&["hello", " ", "world"]),
+            written: 0,
+            limit: limit(11),
+        };
+        let data = body.collect().await.unwrap().to_bytes();
+        assert_eq!(data, "hello world");
+    }
+
+    #[tokio::test]
+    async fn over_limit() {
+        let limit = limit(8);
+        let metrics = limit.metrics.clone();
+        let mut body = ResponseLimitBody {
+            inner: body(&["hello", " ", "world"]),
+            written: 0,
+            limit,
+        };
+
+        assert!(body.frame().await.unwrap().is_ok());
+        assert!(body.frame().await.unwrap().is_ok());
+        assert!(body.frame().await.unwrap().is_err());
+
+        let meter = metrics.meter(
+            MetricId::new("server.response.too-large")
+                .with_tag("service-name", "TestService")
+                .with_tag("endpoint", "test"),
+        );
+        assert_eq!(meter.count(), 1);
+    }
+}
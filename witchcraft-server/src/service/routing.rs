@@ -170,14 +170,35 @@ pub struct RoutingService<S> {
 }
 
 impl<S> RoutingService<S> {
+    fn route(&self, method: &Method, path: &str) -> Option<&Endpoint> {
+        let endpoint = self.endpoints.get(method).and_then(|r| r.route(path));
+
+        // HEAD requests fall back to the GET endpoint for the path. The handler discards the response body.
+        if endpoint.is_none() && method == Method::HEAD {
+            return self.endpoints.get(&Method::GET).and_then(|r| r.route(path));
+        }
+
+        endpoint
+    }
+
     fn supported_methods(&self, path: &str) -> Vec<Method> {
-        self.endpoints
+        let mut methods = self
+            .endpoints
             .iter()
             .filter(|(_, routes)| routes.is_match(path))
-            .map(|(method, _)| method)
-            .sorted_by_key(|m| m.as_str())
-            .cloned()
-            .collect()
+            .map(|(method, _)| method.clone())
+            .collect::<Vec<_>>();
+
+        if methods.contains(&Method::GET) && !methods.contains(&Method::HEAD) {
+            methods.push(Method::HEAD);
+        }
+        // The routing layer itself answers OPTIONS requests for any path with an endpoint.
+        if !methods.is_empty() && !methods.contains(&Method::OPTIONS) {
+            methods.push(Method::OPTIONS);
+        }
+
+        methods.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        methods
     }
 }
 
@@ -192,20 +213,14 @@ where
         let (route, endpoint) = if req.method() == Method::OPTIONS && req.uri() == "*" {
             (Route::StarOptions, None)
         } else {
-            match self
-                .endpoints
-                .get(req.method())
-                .and_then(|r| r.route(req.uri().path()))
-            {
+            match self.route(req.method(), req.uri().path()) {
                 Some(endpoint) => (Route::Resolved(endpoint.endpoint.clone()), Some(endpoint)),
-                None if req.method() == Method::OPTIONS => (
-                    Route::Options(self.supported_methods(req.uri().path())),
-                    None,
-                ),
                 None => {
                     let methods = self.supported_methods(req.uri().path());
                     if methods.is_empty() {
                         (Route::Unresolved, None)
+                    } else if req.method() == Method::OPTIONS {
+                        (Route::Options(methods), None)
                     } else {
                         (Route::MethodNotAllowed(methods), None)
                     }
@@ -328,7 +343,7 @@ mod test {
             )
             .await;
         match req.extensions().get() {
-            Some(Route::Options(methods)) => assert!(methods.is_empty()),
+            Some(Route::Unresolved) => {}
             _ => panic!("bad route"),
         }
 
@@ -382,7 +397,12 @@ mod test {
             )
             .await;
         match req.extensions().get() {
-            Some(Route::Options(methods)) => assert_eq!(*methods, [Method::GET, Method::POST]),
+            Some(Route::Options(methods)) => {
+                assert_eq!(
+                    *methods,
+                    [Method::GET, Method::HEAD, Method::OPTIONS, Method::POST]
+                )
+            }
             _ => panic!("bad route"),
         }
 
@@ -426,7 +446,10 @@ mod test {
             .await;
         match req.extensions().get() {
             Some(Route::MethodNotAllowed(methods)) => {
-                assert_eq!(*methods, [Method::GET, Method::POST])
+                assert_eq!(
+                    *methods,
+                    [Method::GET, Method::HEAD, Method::OPTIONS, Method::POST]
+                )
             }
             _ => panic!("bad route"),
         }
@@ -543,4 +566,70 @@ mod test {
             _ => panic!("bad route"),
         }
     }
+
+    #[tokio::test]
+    async fn head_fallback() {
+        let service = RoutingLayer::new(vec![
+            endpoint(
+                Method::GET,
+                vec![PathSegment::Literal(Cow::Borrowed("foo"))],
+                "a",
+            ),
+            endpoint(
+                Method::GET,
+                vec![PathSegment::Literal(Cow::Borrowed("bar"))],
+                "b",
+            ),
+            endpoint(
+                Method::HEAD,
+                vec![PathSegment::Literal(Cow::Borrowed("bar"))],
+                "c",
+            ),
+        ])
+        .layer(service_fn(|req: Request<Empty<Bytes>>| async { req }));
+
+        let req = service
+            .call(
+                Request::builder()
+                    .method(Method::HEAD)
+                    .uri("/foo")
+                    .body(Empty::new())
+                    .unwrap(),
+            )
+            .await;
+        match req.extensions().get() {
+            Some(Route::Resolved(endpoint)) => assert_eq!(endpoint.name(), "a"),
+            _ => panic!("bad route"),
+        }
+
+        let req = service
+            .call(
+                Request::builder()
+                    .method(Method::HEAD)
+                    .uri("/bar")
+                    .body(Empty::new())
+                    .unwrap(),
+            )
+            .await;
+        match req.extensions().get() {
+            Some(Route::Resolved(endpoint)) => assert_eq!(endpoint.name(), "c"),
+            _ => panic!("bad route"),
+        }
+
+        let req = service
+            .call(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/foo")
+                    .body(Empty::new())
+                    .unwrap(),
+            )
+            .await;
+        match req.extensions().get() {
+            Some(Route::MethodNotAllowed(methods)) => {
+                assert_eq!(*methods, [Method::GET, Method::HEAD, Method::OPTIONS])
+            }
+            _ => panic!("bad route"),
+        }
+    }
 }
@@ -0,0 +1,84 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::extensions::ShutdownSignal;
+use crate::service::{Layer, Service};
+use http::Request;
+use std::future::Future;
+
+/// A layer which adds the server's [`ShutdownSignal`] to request extensions.
+pub struct ShutdownSignalLayer {
+    signal: ShutdownSignal,
+}
+
+impl ShutdownSignalLayer {
+    pub fn new(signal: &ShutdownSignal) -> Self {
+        ShutdownSignalLayer {
+            signal: signal.clone(),
+        }
+    }
+}
+
+impl<S> Layer<S> for ShutdownSignalLayer {
+    type Service = ShutdownSignalService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        ShutdownSignalService {
+            inner,
+            signal: self.signal,
+        }
+    }
+}
+
+pub struct ShutdownSignalService<S> {
+    inner: S,
+    signal: ShutdownSignal,
+}
+
+impl<S, B> Service<Request<B>> for ShutdownSignalService<S>
+where
+    S: Service<Request<B>> + Sync,
+    B: Send,
+{
+    type Response = S::Response;
+
+    // This returns the inner future directly rather than nesting it in another async block to reduce the stack usage of
+    // every request.
+    fn call(&self, mut req: Request<B>) -> impl Future<Output = Self::Response> + Send {
+        req.extensions_mut().insert(self.signal.clone());
+
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::service::test_util::service_fn;
+
+    #[tokio::test]
+    async fn signal_requests() {
+        let signal = ShutdownSignal::new();
+        let service =
+            ShutdownSignalLayer::new(&signal).layer(service_fn(|req: Request<()>| async move {
+                req.extensions().get::<ShutdownSignal>().cloned()
+            }));
+
+        let request_signal = service.call(Request::new(())).await.unwrap();
+        assert!(!request_signal.is_shutting_down());
+
+        signal.trigger();
+        request_signal.wait().await;
+        assert!(request_signal.is_shutting_down());
+    }
+}
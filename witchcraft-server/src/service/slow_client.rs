@@ -0,0 +1,487 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::service::hyper::{GracefulShutdown, NewConnection, ShutdownService};
+use crate::service::{Layer, Service, Stack};
+use bytes::Buf;
+use conjure_error::Error;
+use futures_util::ready;
+use http::Request;
+use http_body::{Body, Frame, SizeHint};
+use parking_lot::Mutex;
+use pin_project::pin_project;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+use std::{error, fmt, io};
+use tokio::time::{self, Instant, Sleep};
+use witchcraft_metrics::{Meter, MetricId, MetricRegistry};
+use witchcraft_server_config::install::InstallConfig;
+
+/// A layer which closes connections whose clients send requests too slowly.
+///
+/// Connections which time out waiting for the headers of an HTTP/1 request are closed by Hyper, and are only recorded
+/// here. Request bodies sent more slowly than the configured minimum rate cause their connection to be closed.
+///
+/// The implementation is unfortunately somewhat tightly coupled to the hyper layer with the `NewConnection` type.
+pub struct SlowClientLayer {
+    min_rate: Option<MinRate>,
+    header_read_timeouts: Arc<Meter>,
+    min_rate_violations: Arc<Meter>,
+}
+
+impl SlowClientLayer {
+    pub fn new(config: &InstallConfig, metrics: &MetricRegistry) -> Self {
+        let killed = |reason| {
+            metrics.meter(MetricId::new("server.connection.killed").with_tag("reason", reason))
+        };
+
+        SlowClientLayer {
+            min_rate: config.server().min_request_body_rate().map(|rate| MinRate {
+                bytes_per_second: rate,
+                grace_period: config.server().min_request_body_rate_grace_period(),
+            }),
+            header_read_timeouts: killed("header-read-timeout"),
+            min_rate_violations: killed("min-transfer-rate"),
+        }
+    }
+}
+
+impl<S> Layer<S> for SlowClientLayer {
+    type Service = SlowClientService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        SlowClientService {
+            inner,
+            min_rate: self.min_rate,
+            header_read_timeouts: self.header_read_timeouts,
+            min_rate_violations: self.min_rate_violations,
+        }
+    }
+}
+
+pub struct SlowClientService<S> {
+    inner: S,
+    min_rate: Option<MinRate>,
+    header_read_timeouts: Arc<Meter>,
+    min_rate_violations: Arc<Meter>,
+}
+
+impl<S, R, L> ShutdownService<NewConnection<R, L>> for SlowClientService<S>
+where
+    S: ShutdownService<
+        NewConnection<R, Stack<L, MinTransferRateLayer>>,
+        Response = Result<(), Error>,
+    >,
+{
+    type Response = S::Response;
+
+    fn call(
+        &self,
+        req: NewConnection<R, L>,
+    ) -> impl Future<Output = Self::Response> + GracefulShutdown + Send {
+        let shared = Arc::new(Shared {
+            killed: AtomicBool::new(false),
+            waker: Mutex::new(None),
+        });
+
+        SlowClientFuture {
+            inner: self.inner.call(NewConnection {
+                stream: req.stream,
+                service_builder: req.service_builder.layer(MinTransferRateLayer {
+                    min_rate: self.min_rate,
+                    shared: shared.clone(),
+                }),
+            }),
+            shared,
+            header_read_timeouts: self.header_read_timeouts.clone(),
+            min_rate_violations: self.min_rate_violations.clone(),
+        }
+    }
+}
+
+#[pin_project]
+pub struct SlowClientFuture<F> {
+    #[pin]
+    inner: F,
+    shared: Arc<Shared>,
+    header_read_timeouts: Arc<Meter>,
+    min_rate_violations: Arc<Meter>,
+}
+
+impl<F> Future for SlowClientFuture<F>
+where
+    F: Future<Output = Result<(), Error>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        if this.shared.poll_killed(cx).is_ready() {
+            this.min_rate_violations.mark(1);
+            return Poll::Ready(Err(Error::internal_safe(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "request body sent below the minimum transfer rate",
+            ))));
+        }
+
+        let result = ready!(this.inner.poll(cx));
+        if let Err(e) = &result {
+            let header_timeout = e
+                .cause()
+                .downcast_ref::<hyper::Error>()
+                .is_some_and(|e| e.is_timeout());
+            if header_timeout {
+                this.header_read_timeouts.mark(1);
+            }
+        }
+
+        Poll::Ready(result)
+    }
+}
+
+impl<F> GracefulShutdown for SlowClientFuture<F>
+where
+    F: GracefulShutdown,
+{
+    fn graceful_shutdown(self: Pin<&mut Self>) {
+        self.project().inner.graceful_shutdown();
+    }
+}
+
+#[derive(Copy, Clone)]
+struct MinRate {
+    bytes_per_second: u64,
+    grace_period: Duration,
+}
+
+impl MinRate {
+    // The total time the server can spend waiting on a body which has sent `bytes` bytes so far.
+    fn allowance(&self, bytes: u64) -> Duration {
+        // A zero rate is rejected by config validation, but saturate rather than panic in the body poll regardless.
+        let required = Duration::try_from_secs_f64(bytes as f64 / self.bytes_per_second as f64)
+            .unwrap_or(Duration::MAX);
+        Duration::max(self.grace_period, required)
+    }
+}
+
+struct Shared {
+    killed: AtomicBool,
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Shared {
+    fn poll_killed(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut waker = self.waker.lock();
+        if waker.as_ref().map_or(true, |w| !cx.waker().will_wake(w)) {
+            *waker = Some(cx.waker().clone());
+        }
+
+        if self.killed.load(Ordering::Acquire) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn kill(&self) {
+        self.killed.store(true, Ordering::Release);
+        if let Some(waker) = self.waker.lock().take() {
+            waker.wake();
+        }
+    }
+}
+
+pub struct MinTransferRateLayer {
+    min_rate: Option<MinRate>,
+    shared: Arc<Shared>,
+}
+
+impl<S> Layer<S> for MinTransferRateLayer {
+    type Service = MinTransferRateService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        MinTransferRateService {
+            inner,
+            min_rate: self.min_rate,
+            shared: self.shared,
+        }
+    }
+}
+
+pub struct MinTransferRateService<S> {
+    inner: S,
+    min_rate: Option<MinRate>,
+    shared: Arc<Shared>,
+}
+
+impl<S, B> Service<Request<B>> for MinTransferRateService<S>
+where
+    S: Service<Request<MinTransferRateBody<B>>> + Sync,
+    B: Send,
+{
+    type Response = S::Response;
+
+    async fn call(&self, req: Request<B>) -> Self::Response {
+        let req = req.map(|inner| MinTransferRateBody {
+            inner,
+            state: self.min_rate.map(|min_rate| RateState {
+                min_rate,
+                shared: self.shared.clone(),
+                bytes: 0,
+                waited: Duration::ZERO,
+                pending_since: None,
+                sleep: None,
+            }),
+        });
+
+        self.inner.call(req).await
+    }
+}
+
+struct RateState {
+    min_rate: MinRate,
+    shared: Arc<Shared>,
+    bytes: u64,
+    waited: Duration,
+    pending_since: Option<Instant>,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+/// A request body which fails, closing its connection, if the client sends it too slowly.
+#[pin_project]
+pub struct MinTransferRateBody<B> {
+    #[pin]
+    inner: B,
+    state: Option<RateState>,
+}
+
+impl<B> MinTransferRateBody<B> {
+    /// Returns a body with no minimum transfer rate.
+    pub(crate) fn passthrough(inner: B) -> Self {
+        MinTransferRateBody { inner, state: None }
+    }
+}
+
+impl<B> Body for MinTransferRateBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+
+    type Error = MinTransferRateError<B::Error>;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+
+        let Some(state) = this.state.as_mut() else {
+            return this
+                .inner
+                .poll_frame(cx)
+                .map(|r| r.map(|r| r.map_err(MinTransferRateError::Inner)));
+        };
+
+        match this.inner.poll_frame(cx) {
+            Poll::Ready(frame) => {
+                if let Some(pending_since) = state.pending_since.take() {
+                    state.waited += pending_since.elapsed();
+                }
+                if let Some(data) = frame.as_ref().and_then(|r| r.as_ref().ok()?.data_ref()) {
+                    state.bytes += data.remaining() as u64;
+                }
+
+                Poll::Ready(frame.map(|r| r.map_err(MinTransferRateError::Inner)))
+            }
+            Poll::Pending => {
+                let now = Instant::now();
+                let pending_since = *state.pending_since.get_or_insert(now);
+                let deadline = pending_since
+                    + state
+                        .min_rate
+                        .allowance(state.bytes)
+                        .saturating_sub(state.waited);
+
+                let sleep = state
+                    .sleep
+                    .get_or_insert_with(|| Box::pin(time::sleep_until(deadline)));
+                if sleep.deadline() != deadline {
+                    sleep.as_mut().reset(deadline);
+                }
+
+                if sleep.as_mut().poll(cx).is_ready() {
+                    state.shared.kill();
+                    *this.state = None;
+                    return Poll::Ready(Some(Err(MinTransferRateError::TooSlow(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "request body sent below the minimum transfer rate",
+                    )))));
+                }
+
+                Poll::Pending
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[derive(Debug)]
+pub enum MinTransferRateError<E> {
+    Inner(E),
+    TooSlow(io::Error),
+}
+
+impl<E> fmt::Display for MinTransferRateError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MinTransferRateError::Inner(e) => fmt::Display::fmt(e, f),
+            MinTransferRateError::TooSlow(e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl<E> error::Error for MinTransferRateError<E>
+where
+    E: error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            MinTransferRateError::Inner(e) => Some(e),
+            MinTransferRateError::TooSlow(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bytes::Bytes;
+    use futures_channel::mpsc;
+    use futures_util::SinkExt;
+    use http_body_util::{BodyExt, StreamBody};
+    use std::convert::Infallible;
+
+    fn body(
+        min_rate: MinRate,
+        shared: &Arc<Shared>,
+    ) -> (
+        mpsc::Sender<Result<Frame<Bytes>, Infallible>>,
+        MinTransferRateBody<StreamBody<mpsc::Receiver<Result<Frame<Bytes>, Infallible>>>>,
+    ) {
+        let (tx, rx) = mpsc::channel(1);
+        let body = MinTransferRateBody {
+            inner: StreamBody::new(rx),
+            state: Some(RateState {
+                min_rate,
+                shared: shared.clone(),
+                bytes: 0,
+                waited: Duration::ZERO,
+                pending_since: None,
+                sleep: None,
+            }),
+        };
+        (tx, body)
+    }
+
+    #[test]
+    fn zero_rate_allowance() {
+        let min_rate = MinRate {
+            bytes_per_second: 0,
+            grace_period: Duration::from_secs(5),
+        };
+        assert_eq!(min_rate.allowance(0), Duration::MAX);
+        assert_eq!(min_rate.allowance(100), Duration::MAX);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fast_body() {
+        let shared = Arc::new(Shared {
+            killed: AtomicBool::new(false),
+            waker: Mutex::new(None),
+        });
+        let min_rate = MinRate {
+            bytes_per_second: 10,
+            grace_period: Duration::from_secs(5),
+        };
+        let (mut tx, mut body) = body(min_rate, &shared);
+
+        tokio::spawn(async move {
+            for _ in 0..5 {
+                time::sleep(Duration::from_secs(2)).await;
+                tx.send(Ok(Frame::data(Bytes::from_static(&[0; 40]))))
+                    .await
+                    .unwrap();
+            }
+        });
+
+        while let Some(frame) = body.frame().await {
+            frame.unwrap();
+        }
+        assert!(!shared.killed.load(Ordering::Acquire));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn slow_body() {
+        let shared = Arc::new(Shared {
+            killed: AtomicBool::new(false),
+            waker: Mutex::new(None),
+        });
+        let min_rate = MinRate {
+            bytes_per_second: 10,
+            grace_period: Duration::from_secs(5),
+        };
+        let (mut tx, mut body) = body(min_rate, &shared);
+
+        tokio::spawn(async move {
+            loop {
+                time::sleep(Duration::from_secs(2)).await;
+                if tx
+                    .send(Ok(Frame::data(Bytes::from_static(&[0; 5]))))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        let start = Instant::now();
+        let error = loop {
+            match body.frame().await {
+                Some(Ok(_)) => {}
+                Some(Err(e)) => break e,
+                None => panic!("body completed"),
+            }
+        };
+        assert!(matches!(error, MinTransferRateError::TooSlow(_)));
+        assert!(shared.killed.load(Ordering::Acquire));
+        // 5 bytes every 2 seconds falls behind 10 bytes per second once the 5 second grace period has elapsed.
+        assert_eq!(start.elapsed(), Duration::from_secs(5));
+    }
+}
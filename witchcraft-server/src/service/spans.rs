@@ -94,7 +94,7 @@ pub struct SpannedBody<B> {
 }
 
 impl<B> SpannedBody<B> {
-    fn new(inner: B, name: &'static str, context: Option<TraceContext>) -> Self {
+    pub(crate) fn new(inner: B, name: &'static str, context: Option<TraceContext>) -> Self {
         SpannedBody {
             inner,
             span: Some(LazySpan::Pending { name, context }),
@@ -0,0 +1,270 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::service::handler::{self, BodyWriteAborted};
+use crate::service::{Layer, Service};
+use bytes::Bytes;
+use conjure_error::{Error, InvalidArgument};
+use http::header::{CONNECTION, CONTENT_LENGTH, TRANSFER_ENCODING};
+use http::{HeaderMap, HeaderValue, Request, Response, Version};
+use http_body_util::combinators::BoxBody;
+use std::sync::Arc;
+use witchcraft_metrics::{Meter, MetricId, MetricRegistry};
+use witchcraft_server_config::install::InstallConfig;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const CLOSE: HeaderValue = HeaderValue::from_static("close");
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Violation {
+    ConflictingFraming,
+    InvalidTransferEncoding,
+    InvalidHeaderValue,
+}
+
+impl Violation {
+    const ALL: [Violation; 3] = [
+        Violation::ConflictingFraming,
+        Violation::InvalidTransferEncoding,
+        Violation::InvalidHeaderValue,
+    ];
+
+    fn reason(self) -> &'static str {
+        match self {
+            Violation::ConflictingFraming => "conflicting-framing",
+            Violation::InvalidTransferEncoding => "invalid-transfer-encoding",
+            Violation::InvalidHeaderValue => "invalid-header-value",
+        }
+    }
+}
+
+// Hyper has already rejected invalid and conflicting `Content-Length` headers, `Transfer-Encoding` headers not ending
+// in `chunked`, obsolete line folding, and control characters, so only what it tolerates is checked here.
+fn check(headers: &HeaderMap) -> Result<(), Violation> {
+    let transfer_encodings = headers
+        .get_all(TRANSFER_ENCODING)
+        .iter()
+        .collect::<Vec<_>>();
+
+    if headers.contains_key(CONTENT_LENGTH) && !transfer_encodings.is_empty() {
+        return Err(Violation::ConflictingFraming);
+    }
+
+    match &*transfer_encodings {
+        [] => {}
+        [value] if value.as_bytes().eq_ignore_ascii_case(b"chunked") => {}
+        _ => return Err(Violation::InvalidTransferEncoding),
+    }
+
+    if !headers.values().all(|value| value.as_bytes().is_ascii()) {
+        return Err(Violation::InvalidHeaderValue);
+    }
+
+    Ok(())
+}
+
+/// A layer which rejects requests with ambiguous or malformed framing when strict HTTP parsing is enabled.
+///
+/// It must be installed before any layer which routes or logs the request.
+pub struct StrictParsingLayer {
+    rejections: Option<Vec<(Violation, Arc<Meter>)>>,
+}
+
+impl StrictParsingLayer {
+    pub fn new(config: &InstallConfig, metrics: &MetricRegistry) -> Self {
+        let rejections = config.server().strict_http_parsing().then(|| {
+            Violation::ALL
+                .iter()
+                .map(|v| (*v, metrics.meter(rejection_id(*v))))
+                .collect()
+        });
+
+        StrictParsingLayer { rejections }
+    }
+}
+
+fn rejection_id(violation: Violation) -> MetricId {
+    MetricId::new("server.request.rejected").with_tag("reason", violation.reason())
+}
+
+impl<S> Layer<S> for StrictParsingLayer {
+    type Service = StrictParsingService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        StrictParsingService {
+            inner,
+            rejections: self.rejections,
+        }
+    }
+}
+
+pub struct StrictParsingService<S> {
+    inner: S,
+    rejections: Option<Vec<(Violation, Arc<Meter>)>>,
+}
+
+impl<S, B> Service<Request<B>> for StrictParsingService<S>
+where
+    S: Service<Request<B>, Response = Response<BoxBody<Bytes, BodyWriteAborted>>> + Sync,
+    B: Send,
+{
+    type Response = S::Response;
+
+    async fn call(&self, req: Request<B>) -> Self::Response {
+        let Some(rejections) = &self.rejections else {
+            return self.inner.call(req).await;
+        };
+
+        let Err(violation) = check(req.headers()) else {
+            return self.inner.call(req).await;
+        };
+
+        if let Some((_, meter)) = rejections.iter().find(|(v, _)| *v == violation) {
+            meter.mark(1);
+        }
+
+        let error = Error::service_safe("malformed HTTP request", InvalidArgument::new())
+            .with_safe_param("reason", violation.reason());
+        let mut response = handler::error_response(error);
+        // The request body's framing can't be trusted, so the connection can't safely be reused.
+        if req.version() < Version::HTTP_2 {
+            response.headers_mut().insert(CONNECTION, CLOSE);
+        }
+
+        response
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::service::handler::EmptyBody;
+    use crate::service::test_util::service_fn;
+    use http_body_util::BodyExt;
+    use hyper::body::Incoming;
+    use hyper::server::conn::http1;
+    use hyper_util::rt::TokioIo;
+    use std::convert::Infallible;
+    use std::net::Ipv4Addr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use witchcraft_server_config::install::ServerConfig;
+
+    /// Sends a raw request to a server with strict parsing enabled, returning the response's status line and the
+    /// server's metrics.
+    async fn send(request: &[u8]) -> (String, MetricRegistry) {
+        let config = InstallConfig::builder()
+            .product_name("foo")
+            .product_version("0.0.0")
+            .port(0)
+            .server(ServerConfig::builder().strict_http_parsing(true).build())
+            .build();
+        let metrics = MetricRegistry::new();
+        let service = Arc::new(StrictParsingLayer::new(&config, &metrics).layer(service_fn(
+            |_: Request<Incoming>| async { Response::new(EmptyBody.boxed()) },
+        )));
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = hyper::service::service_fn(|req| {
+                let service = service.clone();
+                async move { Ok::<_, Infallible>(service.call(req).await) }
+            });
+            let _ = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request).await.unwrap();
+        let mut response = vec![];
+        let _ = stream.read_to_end(&mut response).await;
+        let response = String::from_utf8(response).unwrap();
+        let status = response.lines().next().unwrap_or_default().to_string();
+
+        (status, metrics)
+    }
+
+    fn rejections(metrics: &MetricRegistry, violation: Violation) -> i64 {
+        metrics.meter(rejection_id(violation)).count()
+    }
+
+    #[tokio::test]
+    async fn valid() {
+        for request in [
+            &b"POST / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: 2\r\n\r\nhi"[..],
+            b"POST / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nTransfer-Encoding: Chunked\r\n\r\n\
+              2\r\nhi\r\n0\r\n\r\n",
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nUser-Agent: foo/1.0\t(bar)\r\n\r\n",
+        ] {
+            let (status, _) = send(request).await;
+            assert_eq!(status, "HTTP/1.1 200 OK", "{}", String::from_utf8_lossy(request));
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid() {
+        for (request, violation) in [
+            (
+                &b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 2\r\nTransfer-Encoding: chunked\r\n\r\n\
+                   2\r\nhi\r\n0\r\n\r\n"[..],
+                Violation::ConflictingFraming,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: gzip, chunked\r\n\r\n0\r\n\r\n",
+                Violation::InvalidTransferEncoding,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\
+                  Transfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+                Violation::InvalidTransferEncoding,
+            ),
+            (
+                b"GET / HTTP/1.1\r\nHost: localhost\r\nX-Foo: caf\xc3\xa9\r\n\r\n",
+                Violation::InvalidHeaderValue,
+            ),
+        ] {
+            let (status, metrics) = send(request).await;
+            assert_eq!(
+                status,
+                "HTTP/1.1 400 Bad Request",
+                "{}",
+                String::from_utf8_lossy(request),
+            );
+            assert_eq!(rejections(&metrics, violation), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejected_by_hyper() {
+        for request in [
+            &b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: +2\r\n\r\nhi"[..],
+            b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 2\r\nContent-Length: 3\r\n\r\nhi",
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nX-Foo: a\r\n b\r\n\r\n",
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nX-Foo: a\x0bb\r\n\r\n",
+        ] {
+            let (status, metrics) = send(request).await;
+            assert_eq!(
+                status,
+                "HTTP/1.1 400 Bad Request",
+                "{}",
+                String::from_utf8_lossy(request),
+            );
+            for violation in Violation::ALL {
+                assert_eq!(rejections(&metrics, violation), 0);
+            }
+        }
+    }
+}
@@ -11,48 +11,47 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use crate::acme::{Challenges, ACME_TLS_ALPN_PROTOCOL};
+use crate::ocsp::OcspStapler;
 use crate::service::hyper::NewConnection;
 use crate::service::{Layer, Service};
+use crate::tls::{crypto_provider, KeyLogWriter, PrivateKeyProvider, Ticketer};
+use arc_swap::ArcSwap;
 use conjure_error::Error;
+use parking_lot::Mutex;
 use rustls_pemfile::Item;
-use std::fs::File;
+use std::fmt;
+use std::fs::{self, File};
 use std::io::BufReader;
-use std::path::Path;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime};
 use tokio::io::{AsyncRead, AsyncWrite};
-use tokio_rustls::rustls::crypto::aws_lc_rs::cipher_suite::{
-    TLS13_AES_128_GCM_SHA256, TLS13_AES_256_GCM_SHA384, TLS13_CHACHA20_POLY1305_SHA256,
-    TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256, TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384,
-    TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256, TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
-    TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384, TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256,
+use tokio::time;
+use tokio_rustls::rustls::crypto::CryptoProvider;
+use tokio_rustls::rustls::server::{
+    ClientHello, NoServerSessionStorage, ResolvesServerCert, WebPkiClientVerifier,
 };
-use tokio_rustls::rustls::crypto::aws_lc_rs::kx_group::{SECP256R1, SECP384R1, X25519};
-use tokio_rustls::rustls::crypto::{aws_lc_rs, CryptoProvider, SupportedKxGroup};
-use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::sign::CertifiedKey;
 use tokio_rustls::rustls::version::{TLS12, TLS13};
 use tokio_rustls::rustls::{
-    RootCertStore, ServerConfig, SupportedCipherSuite, SupportedProtocolVersion,
+    self, InconsistentKeys, RootCertStore, ServerConfig, SupportedProtocolVersion,
 };
 use tokio_rustls::server::TlsStream;
 use tokio_rustls::TlsAcceptor;
 use webpki::types::{CertificateDer, PrivateKeyDer};
-use witchcraft_server_config::install::InstallConfig;
+use witchcraft_log::{info, warn};
+use witchcraft_server_config::install::{AcmeChallenge, ClientAuth, InstallConfig, TlsVersion};
 
-static CIPHER_SUITES: [SupportedCipherSuite; 9] = [
-    TLS13_AES_256_GCM_SHA384,
-    TLS13_AES_128_GCM_SHA256,
-    TLS13_CHACHA20_POLY1305_SHA256,
-    TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384,
-    TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256,
-    TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256,
-    TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384,
-    TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
-    TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256,
-];
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
 
-static KX_GROUPS: [&dyn SupportedKxGroup; 3] = [SECP256R1, SECP384R1, X25519];
-
-static PROTOCOL_VERSIONS: [&SupportedProtocolVersion; 2] = [&TLS12, &TLS13];
+fn protocol_version(version: &TlsVersion) -> &'static SupportedProtocolVersion {
+    match version {
+        TlsVersion::Tls12 => &TLS12,
+        TlsVersion::Tls13 => &TLS13,
+        _ => unreachable!("unsupported protocol version {version:?}"),
+    }
+}
 
 /// A layer which wraps streams in a TLS session.
 pub struct TlsLayer {
@@ -60,43 +59,79 @@ pub struct TlsLayer {
 }
 
 impl TlsLayer {
-    pub fn new(config: &InstallConfig) -> Result<Self, Error> {
-        let provider = CryptoProvider {
-            cipher_suites: CIPHER_SUITES.to_vec(),
-            kx_groups: KX_GROUPS.to_vec(),
-            ..aws_lc_rs::default_provider()
-        };
+    pub fn new(
+        config: &InstallConfig,
+        acme_challenges: &Arc<Challenges>,
+        ocsp_stapler: Option<&Arc<OcspStapler>>,
+        private_key_provider: Option<&Arc<dyn PrivateKeyProvider + Sync + Send>>,
+    ) -> Result<Self, Error> {
+        let tls = config.tls();
+        let protocol_versions = tls
+            .protocol_versions()
+            .iter()
+            .map(protocol_version)
+            .collect::<Vec<_>>();
 
-        let builder = ServerConfig::builder_with_provider(Arc::new(provider))
-            .with_protocol_versions(&PROTOCOL_VERSIONS)
+        let provider = Arc::new(crypto_provider::server_provider(tls)?);
+
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_protocol_versions(&protocol_versions)
             .map_err(Error::internal_safe)?;
 
         let builder = match config.client_auth_truststore() {
-            Some(client_auth_truststore) => {
+            Some(client_auth_truststore)
+                if client_auth_truststore.client_auth() != ClientAuth::None =>
+            {
                 let certs = load_certificates(client_auth_truststore.path())?;
                 let mut store = RootCertStore::empty();
                 store.add_parsable_certificates(certs);
-                builder.with_client_cert_verifier(
-                    WebPkiClientVerifier::builder(Arc::new(store))
-                        .allow_unauthenticated()
-                        .build()
-                        .map_err(Error::internal_safe)?,
-                )
+                let mut verifier = WebPkiClientVerifier::builder(Arc::new(store));
+                if client_auth_truststore.client_auth() != ClientAuth::Required {
+                    verifier = verifier.allow_unauthenticated();
+                }
+                builder.with_client_cert_verifier(verifier.build().map_err(Error::internal_safe)?)
             }
-            None => builder.with_no_client_auth(),
+            _ => builder.with_no_client_auth(),
         };
 
-        let cert_chain = load_certificates(config.keystore().cert_path())?;
-        let key_der = load_private_key(config.keystore().key_path())?;
+        let resolver = Arc::new(CertificateResolver::new(
+            config,
+            &provider,
+            acme_challenges,
+            ocsp_stapler,
+            private_key_provider,
+        )?);
+        tokio::spawn(CertificateResolver::run(Arc::downgrade(&resolver)));
 
-        let mut server_config = builder
-            .with_single_cert(cert_chain, key_der)
-            .map_err(Error::internal_safe)?;
+        let mut server_config = builder.with_cert_resolver(resolver);
 
         server_config.ignore_client_order = true;
+
+        let session_resumption = tls.session_resumption();
+        if session_resumption.enabled() {
+            server_config.ticketer = Arc::new(Ticketer::new(tls, &provider)?);
+        } else {
+            server_config.session_storage = Arc::new(NoServerSessionStorage {});
+            server_config.send_tls13_tickets = 0;
+        }
+
+        if let Some(key_log_file) = tls.key_log_file() {
+            server_config.key_log = Arc::new(KeyLogWriter::open(key_log_file)?);
+        }
         if config.server().http2() {
             server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
         }
+        if config
+            .acme()
+            .is_some_and(|acme| acme.challenge() == AcmeChallenge::TlsAlpn01)
+        {
+            if server_config.alpn_protocols.is_empty() {
+                server_config.alpn_protocols.push(b"http/1.1".to_vec());
+            }
+            server_config
+                .alpn_protocols
+                .push(ACME_TLS_ALPN_PROTOCOL.to_vec());
+        }
 
         Ok(TlsLayer {
             acceptor: TlsAcceptor::from(Arc::new(server_config)),
@@ -104,7 +139,172 @@ impl TlsLayer {
     }
 }
 
-fn load_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, Error> {
+/// Resolves the server's certificate, reloading it when the key or certificate files change or a new OCSP response is
+/// available to staple.
+///
+/// Only the handshakes of new connections use the reloaded certificate.
+#[derive(Debug)]
+struct CertificateResolver {
+    cert_path: PathBuf,
+    key_source: KeySource,
+    provider: Arc<CryptoProvider>,
+    modified: Mutex<(Option<SystemTime>, Option<SystemTime>)>,
+    key: ArcSwap<CertifiedKey>,
+    acme_challenges: Arc<Challenges>,
+    ocsp_stapler: Option<Arc<OcspStapler>>,
+}
+
+impl CertificateResolver {
+    fn new(
+        config: &InstallConfig,
+        provider: &Arc<CryptoProvider>,
+        acme_challenges: &Arc<Challenges>,
+        ocsp_stapler: Option<&Arc<OcspStapler>>,
+        private_key_provider: Option<&Arc<dyn PrivateKeyProvider + Sync + Send>>,
+    ) -> Result<Self, Error> {
+        let cert_path = config.keystore().cert_path().to_path_buf();
+        let key_source = match private_key_provider {
+            Some(private_key_provider) => KeySource::Provider(private_key_provider.clone()),
+            None => KeySource::File(config.keystore().key_path().to_path_buf()),
+        };
+        let modified = (modified(&cert_path), key_source.modified());
+        let key = load_certified_key(&cert_path, &key_source, provider)?;
+
+        Ok(CertificateResolver {
+            cert_path,
+            key_source,
+            provider: provider.clone(),
+            modified: Mutex::new(modified),
+            key: ArcSwap::new(Arc::new(key)),
+            acme_challenges: acme_challenges.clone(),
+            ocsp_stapler: ocsp_stapler.cloned(),
+        })
+    }
+
+    /// Periodically checks for changes to the key and certificate files until the resolver is dropped.
+    async fn run(resolver: Weak<CertificateResolver>) {
+        let mut interval = time::interval(RELOAD_INTERVAL);
+        loop {
+            interval.tick().await;
+            let Some(resolver) = resolver.upgrade() else {
+                break;
+            };
+
+            resolver.reload();
+        }
+    }
+
+    fn reload(&self) {
+        self.reload_certificate();
+        self.update_staple();
+    }
+
+    fn reload_certificate(&self) {
+        let modified = (modified(&self.cert_path), self.key_source.modified());
+        if *self.modified.lock() == modified {
+            return;
+        }
+
+        // The files may not be replaced atomically, so a failed load is retried on the next check rather than
+        // remembering the new modification times.
+        match load_certified_key(&self.cert_path, &self.key_source, &self.provider) {
+            Ok(key) => {
+                self.key.store(Arc::new(key));
+                *self.modified.lock() = modified;
+                info!("reloaded TLS certificate");
+            }
+            Err(e) => warn!("error reloading TLS certificate", error: e),
+        }
+    }
+
+    fn update_staple(&self) {
+        let Some(ocsp_stapler) = &self.ocsp_stapler else {
+            return;
+        };
+
+        let key = self.key.load();
+        let Ok(cert) = key.end_entity_cert() else {
+            return;
+        };
+        let ocsp = ocsp_stapler.staple(cert);
+        if ocsp != key.ocsp {
+            self.key.store(Arc::new(CertifiedKey {
+                ocsp,
+                ..CertifiedKey::clone(&key)
+            }));
+        }
+    }
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let acme = client_hello
+            .alpn()
+            .is_some_and(|mut protocols| protocols.all(|p| p == ACME_TLS_ALPN_PROTOCOL));
+        if acme {
+            return self.acme_challenges.tls_alpn(client_hello.server_name()?);
+        }
+
+        Some(self.key.load_full())
+    }
+}
+
+/// The source of the server's private key.
+enum KeySource {
+    File(PathBuf),
+    Provider(Arc<dyn PrivateKeyProvider + Sync + Send>),
+}
+
+impl fmt::Debug for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeySource::File(path) => f.debug_tuple("File").field(path).finish(),
+            KeySource::Provider(_) => f.debug_tuple("Provider").finish_non_exhaustive(),
+        }
+    }
+}
+
+impl KeySource {
+    fn modified(&self) -> Option<SystemTime> {
+        match self {
+            KeySource::File(path) => modified(path),
+            // Provided keys are refreshed along with the certificate.
+            KeySource::Provider(_) => None,
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn load_certified_key(
+    cert_path: &Path,
+    key_source: &KeySource,
+    provider: &CryptoProvider,
+) -> Result<CertifiedKey, Error> {
+    let cert_chain = load_certificates(cert_path)?;
+    let key = match key_source {
+        KeySource::File(key_path) => {
+            let key_der = load_private_key(key_path)?;
+            provider
+                .key_provider
+                .load_private_key(key_der)
+                .map_err(Error::internal_safe)?
+        }
+        KeySource::Provider(private_key_provider) => private_key_provider.signing_key()?,
+    };
+    let key = CertifiedKey::new(cert_chain, key);
+
+    // The key and certificate files are replaced separately, so a reload between the two must not pair the new key
+    // with the old certificate or vice versa.
+    match key.keys_match() {
+        Ok(()) | Err(rustls::Error::InconsistentKeys(InconsistentKeys::Unknown)) => Ok(key),
+        Err(e) => Err(Error::internal_safe(e)),
+    }
+}
+
+pub(crate) fn load_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, Error> {
     let file = File::open(path).map_err(Error::internal_safe)?;
     let mut reader = BufReader::new(file);
     rustls_pemfile::certs(&mut reader)
@@ -166,6 +366,12 @@ where
             .accept(req.stream)
             .await
             .map_err(Error::internal_safe)?;
+
+        // ACME challenge connections are closed once the handshake completes.
+        if stream.get_ref().1.alpn_protocol() == Some(ACME_TLS_ALPN_PROTOCOL) {
+            return Ok(());
+        }
+
         self.inner
             .call(NewConnection {
                 stream,
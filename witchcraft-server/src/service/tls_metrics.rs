@@ -14,6 +14,7 @@
 use crate::service::hyper::NewConnection;
 use crate::service::{Layer, Service};
 use std::sync::Arc;
+use tokio_rustls::rustls::HandshakeKind;
 use tokio_rustls::server::TlsStream;
 use witchcraft_metrics::{MetricId, MetricRegistry};
 
@@ -69,6 +70,18 @@ where
             .negotiated_cipher_suite()
             .expect("session is active");
 
+        let kind = match req.stream.get_ref().1.handshake_kind() {
+            Some(HandshakeKind::Resumed) => "resumed",
+            _ => "full",
+        };
+        self.metrics
+            .meter(
+                MetricId::new("tls.handshake.kind")
+                    .with_tag("context", "server")
+                    .with_tag("kind", kind),
+            )
+            .mark(1);
+
         self.metrics
             .meter(
                 MetricId::new("tls.handshake")
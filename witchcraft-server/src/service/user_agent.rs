@@ -0,0 +1,304 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::service::handler::{self, BodyWriteAborted};
+use crate::service::{Layer, Service};
+use bytes::Bytes;
+use conjure_error::{Error, PermissionDenied};
+use http::header::USER_AGENT;
+use http::{Request, Response};
+use http_body_util::combinators::BoxBody;
+use parking_lot::Mutex;
+use refreshable::Refreshable;
+use std::collections::HashMap;
+use std::sync::Arc;
+use witchcraft_metrics::{Meter, MetricId, MetricRegistry};
+use witchcraft_server_config::runtime::RuntimeConfig;
+
+// Beyond this many distinct agents, requests are recorded against the `other` agent to bound metric cardinality.
+const MAX_TRACKED_AGENTS: usize = 100;
+const OTHER_AGENT: &str = "other";
+const UNKNOWN_AGENT: &str = "unknown";
+
+/// An agent parsed from a `User-Agent` header.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Agent {
+    name: String,
+    version: Option<String>,
+}
+
+impl Agent {
+    fn new(name: &str, version: Option<&str>) -> Option<Self> {
+        let valid = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if name.is_empty() || !valid {
+            return None;
+        }
+
+        Some(Agent {
+            name: name.to_ascii_lowercase(),
+            version: version.filter(|v| !v.is_empty()).map(str::to_string),
+        })
+    }
+
+    /// Parses the agents from a `User-Agent` header.
+    ///
+    /// The header is expected to be in the Conjure format of space-separated `name/version` pairs optionally followed
+    /// by parenthesized comments, e.g. `my-service/1.2.3 (nodeId:1) conjure-java-runtime/4.0.0`. Comments and
+    /// malformed agents are skipped, and names are normalized to lowercase.
+    pub fn parse_all(header: &str) -> Vec<Agent> {
+        let mut agents = vec![];
+        let mut rest = header;
+
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() {
+                break;
+            }
+
+            if let Some(comment) = rest.strip_prefix('(') {
+                rest = comment.split_once(')').map_or("", |(_, rest)| rest);
+                continue;
+            }
+
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == '(')
+                .unwrap_or(rest.len());
+            let (token, tail) = rest.split_at(end);
+            rest = tail;
+
+            let agent = match token.split_once('/') {
+                Some((name, version)) => Agent::new(name, Some(version)),
+                None => Agent::new(token, None),
+            };
+            agents.extend(agent);
+        }
+
+        agents
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn matches(&self, blocked: &Agent) -> bool {
+        self.name == blocked.name && (blocked.version.is_none() || self.version == blocked.version)
+    }
+}
+
+struct AgentMeters {
+    metrics: Arc<MetricRegistry>,
+    meters: Mutex<HashMap<String, Arc<Meter>>>,
+    other: Arc<Meter>,
+}
+
+impl AgentMeters {
+    fn meter(&self, agent: &str) -> Arc<Meter> {
+        let mut meters = self.meters.lock();
+        if let Some(meter) = meters.get(agent) {
+            return meter.clone();
+        }
+
+        if meters.len() >= MAX_TRACKED_AGENTS {
+            return self.other.clone();
+        }
+
+        let meter = self.metrics.meter(agent_id(agent));
+        meters.insert(agent.to_string(), meter.clone());
+        meter
+    }
+}
+
+fn agent_id(agent: &str) -> MetricId {
+    MetricId::new("server.request.user-agent").with_tag("agent", agent.to_string())
+}
+
+/// A layer which records per-agent request metrics and rejects requests from blocked agents.
+pub struct UserAgentLayer {
+    meters: AgentMeters,
+    blocked: Refreshable<Vec<Agent>, Error>,
+}
+
+impl UserAgentLayer {
+    pub fn new(
+        metrics: &Arc<MetricRegistry>,
+        runtime_config: &Refreshable<RuntimeConfig, Error>,
+    ) -> Self {
+        UserAgentLayer {
+            meters: AgentMeters {
+                metrics: metrics.clone(),
+                meters: Mutex::new(HashMap::new()),
+                other: metrics.meter(agent_id(OTHER_AGENT)),
+            },
+            blocked: runtime_config.map(|c| {
+                c.user_agents()
+                    .blocked()
+                    .iter()
+                    .flat_map(|b| Agent::parse_all(b))
+                    .collect()
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for UserAgentLayer {
+    type Service = UserAgentService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        UserAgentService {
+            inner,
+            meters: self.meters,
+            blocked: self.blocked,
+        }
+    }
+}
+
+pub struct UserAgentService<S> {
+    inner: S,
+    meters: AgentMeters,
+    blocked: Refreshable<Vec<Agent>, Error>,
+}
+
+impl<S, B> Service<Request<B>> for UserAgentService<S>
+where
+    S: Service<Request<B>, Response = Response<BoxBody<Bytes, BodyWriteAborted>>> + Sync,
+    B: Send,
+{
+    type Response = S::Response;
+
+    async fn call(&self, req: Request<B>) -> Self::Response {
+        let agents = req
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map_or_else(Vec::new, Agent::parse_all);
+
+        let primary = agents.first().map_or(UNKNOWN_AGENT, |a| a.name());
+        self.meters.meter(primary).mark(1);
+
+        let blocked = self
+            .blocked
+            .get()
+            .iter()
+            .find(|b| agents.iter().any(|a| a.matches(b)))
+            .map(|b| b.name().to_string());
+        if let Some(blocked) = blocked {
+            let error = Error::service_safe("user agent is blocked", PermissionDenied::new())
+                .with_safe_param("agent", blocked);
+            return handler::error_response(error);
+        }
+
+        self.inner.call(req).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::service::handler::EmptyBody;
+    use crate::service::test_util::service_fn;
+    use http::StatusCode;
+    use http_body_util::BodyExt;
+    use witchcraft_server_config::runtime::{
+        DiagnosticsConfig, HealthChecksConfig, UserAgentsConfig,
+    };
+
+    fn runtime_config(user_agents: UserAgentsConfig) -> RuntimeConfig {
+        RuntimeConfig::builder()
+            .diagnostics(DiagnosticsConfig::builder().debug_shared_secret("").build())
+            .health_checks(HealthChecksConfig::builder().shared_secret("").build())
+            .user_agents(user_agents)
+            .build()
+    }
+
+    #[test]
+    fn parse_conjure_agents() {
+        assert_eq!(
+            Agent::parse_all("My-Service/1.2.3 (nodeId:1) conjure-java-runtime/4.0.0"),
+            vec![
+                Agent {
+                    name: "my-service".to_string(),
+                    version: Some("1.2.3".to_string()),
+                },
+                Agent {
+                    name: "conjure-java-runtime".to_string(),
+                    version: Some("4.0.0".to_string()),
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn parse_malformed_agents() {
+        assert_eq!(
+            Agent::parse_all("  curl (unterminated"),
+            vec![Agent {
+                name: "curl".to_string(),
+                version: None,
+            }],
+        );
+        assert_eq!(Agent::parse_all("/1.0 ??/2.0 (comment)"), vec![]);
+    }
+
+    async fn call(layer: UserAgentLayer, user_agent: &str) -> StatusCode {
+        let service = layer.layer(service_fn(|_| async { Response::new(EmptyBody.boxed()) }));
+        let req = Request::builder()
+            .header(USER_AGENT, user_agent)
+            .body(())
+            .unwrap();
+        service.call(req).await.status()
+    }
+
+    #[tokio::test]
+    async fn block_agents() {
+        let metrics = Arc::new(MetricRegistry::new());
+        let config = UserAgentsConfig::builder()
+            .push_blocked("bad-client")
+            .push_blocked("flaky-client/1.0.0")
+            .build();
+        let (config, _handle) = Refreshable::new(runtime_config(config));
+
+        for (user_agent, status) in [
+            ("foo/1.0.0", StatusCode::OK),
+            ("foo/1.0.0 Bad-Client/2.0.0", StatusCode::FORBIDDEN),
+            ("flaky-client/1.0.0", StatusCode::FORBIDDEN),
+            ("flaky-client/1.0.1", StatusCode::OK),
+        ] {
+            let layer = UserAgentLayer::new(&metrics, &config);
+            assert_eq!(call(layer, user_agent).await, status, "{user_agent}");
+        }
+
+        assert_eq!(metrics.meter(agent_id("foo")).count(), 2);
+        assert_eq!(metrics.meter(agent_id("flaky-client")).count(), 2);
+    }
+
+    #[tokio::test]
+    async fn cap_agent_cardinality() {
+        let metrics = Arc::new(MetricRegistry::new());
+        let (config, _handle) = Refreshable::new(runtime_config(UserAgentsConfig::default()));
+        let layer = UserAgentLayer::new(&metrics, &config);
+        let service = layer.layer(service_fn(|_| async { Response::new(EmptyBody.boxed()) }));
+
+        for i in 0..MAX_TRACKED_AGENTS + 5 {
+            let req = Request::builder()
+                .header(USER_AGENT, format!("agent-{i}/1.0.0"))
+                .body(())
+                .unwrap();
+            service.call(req).await;
+        }
+
+        assert_eq!(metrics.meter(agent_id(OTHER_AGENT)).count(), 5);
+    }
+}
@@ -0,0 +1,267 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::service::peer_addr::GetPeerAddr;
+use crate::service::plaintext_detection::Peek;
+use crate::service::{Layer, Service};
+use pin_project::pin_project;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{self, Instant, Sleep};
+use witchcraft_metrics::{Meter, MetricId, MetricRegistry};
+use witchcraft_server_config::install::InstallConfig;
+
+/// A layer which fails writes to connections whose clients have not accepted any data for too long.
+///
+/// The failed write closes the connection.
+pub struct WriteTimeoutLayer {
+    timeout: Option<Duration>,
+    timeouts: Arc<Meter>,
+}
+
+impl WriteTimeoutLayer {
+    pub fn new(config: &InstallConfig, metrics: &MetricRegistry) -> Self {
+        WriteTimeoutLayer {
+            timeout: config.server().response_write_timeout(),
+            timeouts: metrics.meter(
+                MetricId::new("server.connection.killed").with_tag("reason", "write-timeout"),
+            ),
+        }
+    }
+}
+
+impl<S> Layer<S> for WriteTimeoutLayer {
+    type Service = WriteTimeoutService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        WriteTimeoutService {
+            inner,
+            timeout: self.timeout,
+            timeouts: self.timeouts,
+        }
+    }
+}
+
+pub struct WriteTimeoutService<S> {
+    inner: S,
+    timeout: Option<Duration>,
+    timeouts: Arc<Meter>,
+}
+
+impl<S, R> Service<R> for WriteTimeoutService<S>
+where
+    S: Service<R> + Sync,
+    R: Send,
+{
+    type Response = WriteTimeoutStream<S::Response>;
+
+    async fn call(&self, req: R) -> Self::Response {
+        let inner = self.inner.call(req).await;
+
+        WriteTimeoutStream {
+            inner,
+            timeout: self.timeout,
+            sleep: None,
+            timeouts: self.timeouts.clone(),
+        }
+    }
+}
+
+#[pin_project]
+pub struct WriteTimeoutStream<S> {
+    #[pin]
+    inner: S,
+    timeout: Option<Duration>,
+    sleep: Option<Pin<Box<Sleep>>>,
+    timeouts: Arc<Meter>,
+}
+
+impl<S> WriteTimeoutStream<S> {
+    fn poll_timeout<T>(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        f: impl FnOnce(Pin<&mut S>, &mut Context<'_>) -> Poll<io::Result<T>>,
+    ) -> Poll<io::Result<T>> {
+        let this = self.project();
+
+        if let Poll::Ready(r) = f(this.inner, cx) {
+            // The timer restarts whenever the client makes progress.
+            *this.sleep = None;
+            return Poll::Ready(r);
+        }
+
+        let Some(timeout) = *this.timeout else {
+            return Poll::Pending;
+        };
+
+        let sleep = this
+            .sleep
+            .get_or_insert_with(|| Box::pin(time::sleep_until(Instant::now() + timeout)));
+        if sleep.as_mut().poll(cx).is_ready() {
+            this.timeouts.mark(1);
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "timed out writing to client",
+            )));
+        }
+
+        Poll::Pending
+    }
+}
+
+impl<S> AsyncRead for WriteTimeoutStream<S>
+where
+    S: AsyncRead,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.project().inner.poll_read(cx, buf)
+    }
+}
+
+impl<S> AsyncWrite for WriteTimeoutStream<S>
+where
+    S: AsyncWrite,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_timeout(cx, |inner, cx| inner.poll_write(cx, buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_timeout(cx, |inner, cx| inner.poll_flush(cx))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_timeout(cx, |inner, cx| inner.poll_shutdown(cx))
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_timeout(cx, |inner, cx| inner.poll_write_vectored(cx, bufs))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
+
+impl<S> GetPeerAddr for WriteTimeoutStream<S>
+where
+    S: GetPeerAddr,
+{
+    fn peer_addr(&self) -> Result<SocketAddr, conjure_error::Error> {
+        self.inner.peer_addr()
+    }
+}
+
+impl<S> Peek for WriteTimeoutStream<S>
+where
+    S: Peek,
+{
+    fn poll_peek(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<usize>> {
+        self.inner.poll_peek(cx, buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::service::test_util::service_fn;
+    use tokio::io::{self as tokio_io, AsyncReadExt, AsyncWriteExt, DuplexStream};
+    use witchcraft_server_config::install::ServerConfig;
+
+    #[tokio::test(start_paused = true)]
+    async fn stalled_client() {
+        let (client, server) = tokio_io::duplex(8);
+        let mut stream = WriteTimeoutStream {
+            inner: server,
+            timeout: Some(Duration::from_secs(5)),
+            sleep: None,
+            timeouts: MetricRegistry::new().meter("test"),
+        };
+
+        let reader = tokio::spawn(async move {
+            let mut client = client;
+            let mut buf = [0; 8];
+            for _ in 0..3 {
+                time::sleep(Duration::from_secs(4)).await;
+                client.read_exact(&mut buf).await.unwrap();
+            }
+            client
+        });
+
+        let start = Instant::now();
+        stream.write_all(&[0; 32]).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(12));
+
+        let _client = reader.await.unwrap();
+        let error = stream.write_all(&[0; 16]).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(start.elapsed(), Duration::from_secs(17));
+        assert_eq!(stream.timeouts.count(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn layer_uses_write_timeout() {
+        let config = InstallConfig::builder()
+            .product_name("product")
+            .product_version("1.0.0")
+            .port(0)
+            .server(
+                ServerConfig::builder()
+                    .request_body_read_timeout(Duration::from_secs(30))
+                    .response_write_timeout(Duration::from_secs(5))
+                    .build(),
+            )
+            .build();
+        let metrics = MetricRegistry::new();
+        let service = WriteTimeoutLayer::new(&config, &metrics)
+            .layer(service_fn(|stream: DuplexStream| async move { stream }));
+
+        let (mut client, server) = tokio_io::duplex(8);
+        let mut stream = service.call(server).await;
+
+        tokio::spawn(async move {
+            time::sleep(Duration::from_secs(4)).await;
+            let mut buf = [0; 8];
+            client.read_exact(&mut buf).await.unwrap();
+            // stall without closing the connection
+            time::sleep(Duration::from_secs(60)).await;
+        });
+
+        let start = Instant::now();
+        let error = stream.write_all(&[0; 32]).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(start.elapsed(), Duration::from_secs(9));
+
+        let timeouts = metrics
+            .meter(MetricId::new("server.connection.killed").with_tag("reason", "write-timeout"));
+        assert_eq!(timeouts.count(), 1);
+    }
+}
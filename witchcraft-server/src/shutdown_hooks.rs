@@ -13,40 +13,247 @@
 // limitations under the License.
 use futures_util::future::BoxFuture;
 use futures_util::stream::FuturesUnordered;
-use futures_util::{ready, Stream};
-use pin_project::pin_project;
-use std::future::Future;
-use std::pin::Pin;
+use futures_util::StreamExt;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::future::{self, Future};
+use std::pin::{pin, Pin};
 use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::{self, Instant};
+use witchcraft_log::{info, warn};
+
+// The time given to each phase which starts after the overall shutdown timeout has elapsed.
+const MIN_PHASE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A phase of the server's shutdown process.
+///
+/// Phases run in the order they are declared. All hooks registered in a phase run concurrently, and the next phase
+/// starts once they have all completed or the phase's timeout elapses.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum ShutdownPhase {
+    /// The server stops accepting new connections and requests, and deregisters itself from service discovery.
+    StopAccepting,
+    /// The server waits for in-flight requests and upgraded connections to complete.
+    DrainRequests,
+    /// Background work such as jobs and event dispatchers is stopped.
+    StopBackgroundTasks,
+    /// Buffered logs are flushed. The server's own logs are flushed after this phase completes.
+    FlushLogs,
+}
+
+impl ShutdownPhase {
+    /// Returns the kebab-case name of the phase.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShutdownPhase::StopAccepting => "stop-accepting",
+            ShutdownPhase::DrainRequests => "drain-requests",
+            ShutdownPhase::StopBackgroundTasks => "stop-background-tasks",
+            ShutdownPhase::FlushLogs => "flush-logs",
+        }
+    }
+}
+
+struct Hook {
+    name: Cow<'static, str>,
+    future: BoxFuture<'static, ()>,
+}
+
+impl Future for Hook {
+    type Output = Cow<'static, str>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.future.as_mut().poll(cx).map(|()| self.name.clone())
+    }
+}
 
-#[pin_project]
 pub struct ShutdownHooks {
-    #[pin]
-    hooks: FuturesUnordered<BoxFuture<'static, ()>>,
+    phases: BTreeMap<ShutdownPhase, Vec<Hook>>,
+    timeouts: HashMap<ShutdownPhase, Duration>,
 }
 
 impl ShutdownHooks {
     pub fn new() -> Self {
         ShutdownHooks {
-            hooks: FuturesUnordered::new(),
+            phases: BTreeMap::new(),
+            timeouts: HashMap::new(),
         }
     }
 
-    #[allow(dead_code)]
-    pub fn push<F>(&mut self, future: F)
+    pub fn push<F>(&mut self, phase: ShutdownPhase, name: impl Into<Cow<'static, str>>, future: F)
     where
         F: Future<Output = ()> + 'static + Send,
     {
-        self.hooks.push(Box::pin(future));
+        self.phases.entry(phase).or_default().push(Hook {
+            name: name.into(),
+            future: Box::pin(future),
+        });
+    }
+
+    pub fn set_timeout(&mut self, phase: ShutdownPhase, timeout: Duration) {
+        self.timeouts.insert(phase, timeout);
+    }
+
+    /// Runs each phase's hooks in order, abandoning any which remain once `timeout` has elapsed.
+    ///
+    /// Every phase is started even if the timeout has already elapsed, so background work is still told to stop and
+    /// logs are still flushed when draining requests takes the entire timeout. Such phases get `MIN_PHASE_TIMEOUT`.
+    pub async fn run(self, timeout: Option<Duration>) {
+        let start = Instant::now();
+        let deadline = timeout.and_then(|t| start.checked_add(t));
+        let mut timed_out = false;
+
+        for (phase, hooks) in self.phases {
+            let now = Instant::now();
+            let phase_deadline = self.timeouts.get(&phase).and_then(|t| now.checked_add(*t));
+            let overall_deadline = deadline.map(|d| d.max(now + MIN_PHASE_TIMEOUT));
+            let phase_deadline = match (phase_deadline, overall_deadline) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+
+            run_phase(phase, hooks, phase_deadline).await;
+
+            if !timed_out && deadline.map_or(false, |d| d <= Instant::now()) {
+                timed_out = true;
+                info!(
+                    "graceful shutdown timed out",
+                    safe: {
+                        phase: phase.as_str(),
+                        timeout: format_args!("{:?}", start.elapsed()),
+                    },
+                );
+            }
+        }
     }
 }
 
-impl Future for ShutdownHooks {
-    type Output = ();
+async fn run_phase(phase: ShutdownPhase, hooks: Vec<Hook>, deadline: Option<Instant>) {
+    let start = Instant::now();
+    let count = hooks.len();
+    let mut hooks = hooks.into_iter().collect::<FuturesUnordered<_>>();
 
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        while let Some(()) = ready!(self.as_mut().project().hooks.poll_next(cx)) {}
+    let mut timeout = pin!(async {
+        match deadline {
+            Some(deadline) => time::sleep_until(deadline).await,
+            None => future::pending().await,
+        }
+    });
+
+    loop {
+        tokio::select! {
+            next = hooks.next() => match next {
+                Some(name) => info!(
+                    "shutdown hook completed",
+                    safe: {
+                        phase: phase.as_str(),
+                        hook: name,
+                        durationMillis: start.elapsed().as_millis() as u64,
+                    },
+                ),
+                None => break,
+            },
+            _ = &mut timeout => {
+                warn!(
+                    "shutdown phase timed out",
+                    safe: {
+                        phase: phase.as_str(),
+                        durationMillis: start.elapsed().as_millis() as u64,
+                        pendingHooks: hooks.iter().map(|h| &*h.name).collect::<Vec<_>>(),
+                    },
+                );
+                return;
+            }
+        }
+    }
+
+    info!(
+        "shutdown phase completed",
+        safe: {
+            phase: phase.as_str(),
+            hooks: count,
+            durationMillis: start.elapsed().as_millis() as u64,
+        },
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    #[tokio::test(start_paused = true)]
+    async fn phases_run_in_order() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let mut hooks = ShutdownHooks::new();
+
+        for (phase, name, delay) in [
+            (ShutdownPhase::FlushLogs, "logs", 0),
+            (ShutdownPhase::DrainRequests, "slow", 20),
+            (ShutdownPhase::DrainRequests, "fast", 10),
+            (ShutdownPhase::StopAccepting, "listener", 0),
+        ] {
+            let events = events.clone();
+            hooks.push(phase, name, async move {
+                time::sleep(Duration::from_secs(delay)).await;
+                events.lock().push(name);
+            });
+        }
+
+        hooks.run(None).await;
+        assert_eq!(*events.lock(), ["listener", "fast", "slow", "logs"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn phase_timeouts() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let mut hooks = ShutdownHooks::new();
+        hooks.set_timeout(ShutdownPhase::DrainRequests, Duration::from_secs(3));
+
+        for (phase, name, delay) in [
+            (ShutdownPhase::DrainRequests, "stuck", 1000),
+            (ShutdownPhase::StopBackgroundTasks, "jobs", 0),
+            (ShutdownPhase::StopBackgroundTasks, "slow-jobs", 10),
+            (ShutdownPhase::FlushLogs, "logs", 0),
+        ] {
+            let events = events.clone();
+            hooks.push(phase, name, async move {
+                time::sleep(Duration::from_secs(delay)).await;
+                events.lock().push(name);
+            });
+        }
+
+        let start = Instant::now();
+        hooks.run(Some(Duration::from_secs(5))).await;
+        // The drain phase is cut off after 3 seconds and the slow job hook runs out the remaining budget, but the logs
+        // are still flushed.
+        assert_eq!(*events.lock(), ["jobs", "logs"]);
+        assert_eq!(start.elapsed(), Duration::from_secs(5));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn late_phases_run_after_timeout() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let mut hooks = ShutdownHooks::new();
+
+        for (phase, name, delay) in [
+            (ShutdownPhase::DrainRequests, "stuck", 1000),
+            (ShutdownPhase::StopBackgroundTasks, "jobs", 0),
+            (ShutdownPhase::FlushLogs, "slow-logs", 10),
+            (ShutdownPhase::FlushLogs, "logs", 0),
+        ] {
+            let events = events.clone();
+            hooks.push(phase, name, async move {
+                time::sleep(Duration::from_secs(delay)).await;
+                events.lock().push(name);
+            });
+        }
 
-        Poll::Ready(())
+        let start = Instant::now();
+        hooks.run(Some(Duration::from_secs(5))).await;
+        assert_eq!(*events.lock(), ["jobs", "logs"]);
+        assert_eq!(start.elapsed(), Duration::from_secs(5) + MIN_PHASE_TIMEOUT);
     }
 }
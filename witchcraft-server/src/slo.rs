@@ -0,0 +1,418 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use conjure_error::Error;
+use conjure_http::server::EndpointMetadata;
+use parking_lot::Mutex;
+use refreshable::Refreshable;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tokio::time::Instant;
+use witchcraft_metrics::{MetricId, MetricRegistry};
+use witchcraft_server_config::runtime::{RuntimeConfig, SloConfig};
+
+const BUCKET_WIDTH: Duration = Duration::from_secs(60);
+// Avoid declaring a budget exhausted based on a handful of requests.
+const MIN_REQUESTS: u64 = 10;
+const SLA_WINDOW: Duration = Duration::from_secs(10 * 60);
+// The fraction of requests which may exceed an endpoint's declared p99 latency.
+const SLA_TOLERANCE: f64 = 0.01;
+
+#[derive(Copy, Clone)]
+pub enum Objective {
+    Availability,
+    Latency,
+}
+
+impl Objective {
+    const ALL: [Objective; 2] = [Objective::Availability, Objective::Latency];
+
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Objective::Availability => "availability",
+            Objective::Latency => "latency",
+        }
+    }
+
+    fn target(&self, config: &SloConfig) -> Option<f64> {
+        match self {
+            Objective::Availability => config.availability(),
+            Objective::Latency => config.latency().map(|l| l.target()),
+        }
+    }
+}
+
+/// The tracker of the service level objectives of all endpoints.
+pub struct SloRegistry {
+    metrics: Arc<MetricRegistry>,
+    config: Refreshable<RuntimeConfig, Error>,
+    endpoints: Mutex<Vec<Arc<EndpointSlo>>>,
+    slas: Mutex<HashMap<String, Duration>>,
+}
+
+impl SloRegistry {
+    pub fn new(
+        metrics: &Arc<MetricRegistry>,
+        runtime_config: Refreshable<RuntimeConfig, Error>,
+    ) -> Self {
+        SloRegistry {
+            metrics: metrics.clone(),
+            config: runtime_config,
+            endpoints: Mutex::new(vec![]),
+            slas: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Declares the expected p99 latency of an endpoint.
+    pub fn declare_sla(&self, service_name: &str, endpoint_name: &str, p99: Duration) {
+        let key = format!("{service_name}.{endpoint_name}");
+        for endpoint in &*self.endpoints.lock() {
+            if endpoint.key() == key {
+                endpoint.sla.lock().p99 = Some(p99);
+            }
+        }
+        self.slas.lock().insert(key, p99);
+    }
+
+    /// Creates the tracker for an endpoint.
+    pub fn endpoint(&self, endpoint: &dyn EndpointMetadata) -> Arc<EndpointSlo> {
+        let key = format!("{}.{}", endpoint.service_name(), endpoint.name());
+        let p99 = self.slas.lock().get(&key).copied();
+        let config = self.config.map({
+            let key = key.clone();
+            move |c| c.slos().get(&key).cloned()
+        });
+        let slo = Arc::new(EndpointSlo {
+            service_name: endpoint.service_name().to_string(),
+            name: endpoint.name().to_string(),
+            key,
+            metrics: self.metrics.clone(),
+            config,
+            registered: Default::default(),
+            buckets: Mutex::new(VecDeque::new()),
+            sla: Mutex::new(Sla {
+                p99,
+                buckets: VecDeque::new(),
+            }),
+        });
+
+        self.endpoints.lock().push(slo.clone());
+        slo
+    }
+
+    pub fn endpoints(&self) -> Vec<Arc<EndpointSlo>> {
+        self.endpoints.lock().clone()
+    }
+}
+
+#[derive(Default)]
+struct Bucket {
+    requests: u64,
+    errors: u64,
+    slow: u64,
+}
+
+struct Sla {
+    p99: Option<Duration>,
+    buckets: VecDeque<(Instant, Bucket)>,
+}
+
+/// A rolling record of an endpoint's request outcomes.
+pub struct EndpointSlo {
+    service_name: String,
+    name: String,
+    key: String,
+    metrics: Arc<MetricRegistry>,
+    config: Refreshable<Option<SloConfig>, Error>,
+    registered: [AtomicBool; 2],
+    buckets: Mutex<VecDeque<(Instant, Bucket)>>,
+    sla: Mutex<Sla>,
+}
+
+impl EndpointSlo {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn record(self: &Arc<Self>, error: bool, latency: Duration) {
+        self.record_sla(latency);
+
+        let config = self.config.get();
+        let Some(config) = &*config else {
+            return;
+        };
+
+        // Burn rate gauges are registered once an objective is first configured to avoid reporting them for every
+        // endpoint.
+        for (objective, registered) in Objective::ALL.into_iter().zip(&self.registered) {
+            if objective.target(config).is_some() && !registered.swap(true, Ordering::Relaxed) {
+                let weak = Arc::downgrade(self);
+                self.metrics.gauge(
+                    MetricId::new("server.slo.burn-rate")
+                        .with_tag("service-name", self.service_name.clone())
+                        .with_tag("endpoint", self.name.clone())
+                        .with_tag("objective", objective.tag()),
+                    move || {
+                        Weak::upgrade(&weak).map_or(0., |s| s.burn_rate(objective).unwrap_or(0.))
+                    },
+                );
+            }
+        }
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock();
+        prune(&mut buckets, now, config.window());
+        let bucket = current_bucket(&mut buckets, now);
+
+        bucket.requests += 1;
+        if error {
+            bucket.errors += 1;
+        }
+        if config.latency().is_some_and(|l| latency > l.threshold()) {
+            bucket.slow += 1;
+        }
+    }
+
+    fn record_sla(&self, latency: Duration) {
+        let mut sla = self.sla.lock();
+        let Some(p99) = sla.p99 else {
+            return;
+        };
+
+        let now = Instant::now();
+        prune(&mut sla.buckets, now, SLA_WINDOW);
+        let bucket = current_bucket(&mut sla.buckets, now);
+
+        bucket.requests += 1;
+        if latency > p99 {
+            bucket.slow += 1;
+        }
+    }
+
+    /// Determines if the endpoint's observed p99 latency exceeds its declared p99 latency.
+    ///
+    /// Returns the declared latency and the fraction of requests which exceeded it over the window if so.
+    pub fn sla_violation(&self) -> Option<(Duration, f64)> {
+        let mut sla = self.sla.lock();
+        let p99 = sla.p99?;
+        prune(&mut sla.buckets, Instant::now(), SLA_WINDOW);
+
+        let mut requests = 0;
+        let mut slow = 0;
+        for (_, bucket) in &sla.buckets {
+            requests += bucket.requests;
+            slow += bucket.slow;
+        }
+
+        // The observed p99 latency exceeds the declaration exactly when more than 1% of requests are slower than it.
+        let slow_fraction = slow as f64 / requests as f64;
+        if requests >= MIN_REQUESTS && slow_fraction > SLA_TOLERANCE {
+            Some((p99, slow_fraction))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the rate at which the objective's error budget is being consumed over its window.
+    ///
+    /// A burn rate of 1 means that the budget will be exactly used up over the window, and a burn rate above 1 means
+    /// that the objective is not being met. Returns `None` if the objective is not configured.
+    pub fn burn_rate(&self, objective: Objective) -> Option<f64> {
+        self.evaluate(objective).map(|(_, burn_rate)| burn_rate)
+    }
+
+    /// Determines if the objective's error budget has been used up over its window.
+    pub fn exhausted(&self, objective: Objective) -> bool {
+        self.evaluate(objective)
+            .is_some_and(|(requests, burn_rate)| requests >= MIN_REQUESTS && burn_rate >= 1.)
+    }
+
+    fn evaluate(&self, objective: Objective) -> Option<(u64, f64)> {
+        let config = self.config.get();
+        let config = config.as_ref()?;
+        let target = objective.target(config)?;
+
+        let mut buckets = self.buckets.lock();
+        prune(&mut buckets, Instant::now(), config.window());
+
+        let mut requests = 0;
+        let mut failures = 0;
+        for (_, bucket) in &*buckets {
+            requests += bucket.requests;
+            failures += match objective {
+                Objective::Availability => bucket.errors,
+                Objective::Latency => bucket.slow,
+            };
+        }
+
+        if requests == 0 {
+            return Some((0, 0.));
+        }
+
+        Some((requests, failures as f64 / requests as f64 / (1. - target)))
+    }
+}
+
+fn current_bucket(buckets: &mut VecDeque<(Instant, Bucket)>, now: Instant) -> &mut Bucket {
+    if buckets.back().map_or(true, |(start, _)| {
+        now.duration_since(*start) >= BUCKET_WIDTH
+    }) {
+        buckets.push_back((now, Bucket::default()));
+    }
+    &mut buckets.back_mut().unwrap().1
+}
+
+fn prune(buckets: &mut VecDeque<(Instant, Bucket)>, now: Instant, window: Duration) {
+    while buckets
+        .front()
+        .is_some_and(|(start, _)| now.duration_since(*start) >= window)
+    {
+        buckets.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use witchcraft_server_config::runtime::{
+        DiagnosticsConfig, HealthChecksConfig, LatencyObjectiveConfig,
+    };
+
+    struct TestEndpoint;
+
+    impl EndpointMetadata for TestEndpoint {
+        fn method(&self) -> http::Method {
+            http::Method::GET
+        }
+
+        fn path(&self) -> &[conjure_http::server::PathSegment] {
+            &[]
+        }
+
+        fn template(&self) -> &str {
+            "/"
+        }
+
+        fn service_name(&self) -> &str {
+            "TestService"
+        }
+
+        fn name(&self) -> &str {
+            "test"
+        }
+
+        fn deprecated(&self) -> Option<&str> {
+            None
+        }
+    }
+
+    fn registry() -> SloRegistry {
+        let slo = SloConfig::builder()
+            .window(Duration::from_secs(10 * 60))
+            .availability(0.9)
+            .latency(
+                LatencyObjectiveConfig::builder()
+                    .threshold(Duration::from_millis(100))
+                    .target(0.5)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        let config = RuntimeConfig::builder()
+            .diagnostics(DiagnosticsConfig::builder().debug_shared_secret("").build())
+            .health_checks(HealthChecksConfig::builder().shared_secret("").build())
+            .insert_slos("TestService.test", slo)
+            .build();
+        let (config, _) = Refreshable::new(config);
+
+        SloRegistry::new(&Arc::new(MetricRegistry::new()), config)
+    }
+
+    fn assert_burn_rate(slo: &EndpointSlo, objective: Objective, expected: f64) {
+        let actual = slo.burn_rate(objective).unwrap();
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn burn_rate() {
+        let registry = registry();
+        let slo = registry.endpoint(&TestEndpoint);
+        assert_burn_rate(&slo, Objective::Availability, 0.);
+
+        slo.record(true, Duration::from_millis(50));
+        assert!(!slo.exhausted(Objective::Availability));
+
+        for _ in 0..9 {
+            slo.record(false, Duration::from_millis(50));
+        }
+        assert_burn_rate(&slo, Objective::Availability, 1.);
+        assert!(slo.exhausted(Objective::Availability));
+        assert_burn_rate(&slo, Objective::Latency, 0.);
+        assert!(!slo.exhausted(Objective::Latency));
+
+        tokio::time::advance(Duration::from_secs(5 * 60)).await;
+        for _ in 0..10 {
+            slo.record(true, Duration::from_millis(200));
+        }
+        assert_burn_rate(&slo, Objective::Availability, 5.5);
+        assert_burn_rate(&slo, Objective::Latency, 1.);
+
+        // The first batch of requests ages out of the window.
+        tokio::time::advance(Duration::from_secs(5 * 60)).await;
+        assert_burn_rate(&slo, Objective::Availability, 10.);
+        assert_burn_rate(&slo, Objective::Latency, 2.);
+
+        let gauges = registry
+            .metrics
+            .metrics()
+            .iter()
+            .filter(|(id, _)| id.name() == "server.slo.burn-rate")
+            .count();
+        assert_eq!(gauges, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sla() {
+        let registry = registry();
+        let slo = registry.endpoint(&TestEndpoint);
+        for _ in 0..100 {
+            slo.record(false, Duration::from_millis(200));
+        }
+        assert_eq!(slo.sla_violation(), None);
+
+        // Declarations apply to endpoints which have already been installed.
+        registry.declare_sla("TestService", "test", Duration::from_millis(100));
+        for _ in 0..99 {
+            slo.record(false, Duration::from_millis(50));
+        }
+        slo.record(false, Duration::from_millis(200));
+        assert_eq!(slo.sla_violation(), None);
+
+        slo.record(false, Duration::from_millis(200));
+        let (p99, slow_fraction) = slo.sla_violation().unwrap();
+        assert_eq!(p99, Duration::from_millis(100));
+        assert!((slow_fraction - 2. / 101.).abs() < 1e-9);
+
+        tokio::time::advance(SLA_WINDOW).await;
+        assert_eq!(slo.sla_violation(), None);
+
+        let slo = registry.endpoint(&TestEndpoint);
+        for _ in 0..10 {
+            slo.record(false, Duration::from_millis(200));
+        }
+        assert!(slo.sla_violation().is_some());
+    }
+}
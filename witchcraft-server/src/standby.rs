@@ -0,0 +1,99 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use conjure_error::Error;
+use refreshable::{Refreshable, Subscription};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use witchcraft_log::info;
+use witchcraft_metrics::MetricRegistry;
+
+/// Tracks whether the server is in standby mode.
+///
+/// A server in standby rejects requests to application endpoints until it is activated, either by its runtime
+/// configuration leaving standby or by an explicit call to [`Standby::activate`]. Activation is one-way: a server
+/// never returns to standby once it has been activated.
+pub struct Standby {
+    standby: Arc<AtomicBool>,
+    _subscription: Subscription<bool, Error>,
+}
+
+impl Standby {
+    pub fn new(metrics: &MetricRegistry, config: Refreshable<bool, Error>) -> Arc<Self> {
+        let standby = Arc::new(AtomicBool::new(*config.get()));
+        if standby.load(Ordering::Relaxed) {
+            info!("server starting in standby mode");
+        }
+
+        let subscription = config.subscribe({
+            let standby = standby.clone();
+            move |config| {
+                if !*config {
+                    activate(&standby);
+                }
+            }
+        });
+
+        metrics.gauge("server.standby", {
+            let standby = Arc::downgrade(&standby);
+            move || standby.upgrade().is_some_and(|s| s.load(Ordering::Relaxed)) as i64
+        });
+
+        Arc::new(Standby {
+            standby,
+            _subscription: subscription,
+        })
+    }
+
+    /// Returns `true` if the server has not yet been activated.
+    pub fn is_standby(&self) -> bool {
+        self.standby.load(Ordering::Relaxed)
+    }
+
+    /// Activates the server, allowing requests to application endpoints.
+    pub fn activate(&self) {
+        activate(&self.standby);
+    }
+}
+
+fn activate(standby: &AtomicBool) {
+    if standby.swap(false, Ordering::Relaxed) {
+        info!("server activated from standby mode");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn activation_is_permanent() {
+        let (config, mut handle) = Refreshable::new(true);
+        let standby = Standby::new(&MetricRegistry::new(), config);
+        assert!(standby.is_standby());
+
+        handle.refresh(false).unwrap();
+        assert!(!standby.is_standby());
+
+        handle.refresh(true).unwrap();
+        assert!(!standby.is_standby());
+    }
+
+    #[test]
+    fn explicit_activation() {
+        let (config, _handle) = Refreshable::<_, Error>::new(true);
+        let standby = Standby::new(&MetricRegistry::new(), config);
+        standby.activate();
+        assert!(!standby.is_standby());
+    }
+}
@@ -11,19 +11,29 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use crate::cluster::{ClusterInfo, ClusterRegistry};
+use crate::headers::{EntityTag, HeaderMapExt, IfNoneMatch};
 use crate::health::api::HealthStatus;
 use crate::health::HealthCheckRegistry;
 use crate::readiness::{ReadinessCheckMetadata, ReadinessCheckRegistry};
-use conjure_error::{Error, PermissionDenied};
+use conjure_error::{Error, NotFound, PermissionDenied};
 use conjure_http::server::{
     AsyncResponseBody, AsyncSerializeResponse, ConjureRuntime, StdResponseSerializer,
 };
 use conjure_http::{conjure_endpoints, endpoint};
 use conjure_object::BearerToken;
-use http::{HeaderMap, Response, StatusCode};
+use http::header::{AGE, CACHE_CONTROL, CONTENT_TYPE, ETAG};
+use http::{HeaderMap, HeaderValue, Response, StatusCode};
+use parking_lot::Mutex;
+use rand::Rng;
 use refreshable::Refreshable;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use subtle::ConstantTimeEq;
 use tokio::task;
 use witchcraft_server_config::runtime::RuntimeConfig;
@@ -34,16 +44,23 @@ pub trait StatusService {
     async fn liveness(&self) -> Result<(), Error>;
 
     #[endpoint(path = "/status/readiness", method = GET, produces = LivenessResponseSerializer)]
-    async fn readiness(&self) -> Result<BTreeMap<String, ReadinessCheckMetadata>, Error>;
+    async fn readiness(&self) -> Result<Cached<BTreeMap<String, ReadinessCheckMetadata>>, Error>;
 
-    #[endpoint(path = "/status/health", method = GET, produces = StdResponseSerializer)]
-    async fn health(&self, #[auth] token: BearerToken) -> Result<HealthStatus, Error>;
+    #[endpoint(path = "/status/health", method = GET, produces = CachedResponseSerializer)]
+    async fn health(&self, #[auth] token: BearerToken) -> Result<Cached<HealthStatus>, Error>;
+
+    #[endpoint(path = "/status/cluster", method = GET, produces = StdResponseSerializer)]
+    async fn cluster(&self, #[auth] token: BearerToken) -> Result<ClusterInfo, Error>;
 }
 
 pub struct StatusResource {
     health_check_secret: Refreshable<String, Error>,
     health_checks: Arc<HealthCheckRegistry>,
     readiness_checks: Arc<ReadinessCheckRegistry>,
+    health_cache: ResponseCache<HealthStatus>,
+    readiness_cache: ResponseCache<BTreeMap<String, ReadinessCheckMetadata>>,
+    cache_validators: Refreshable<bool, Error>,
+    cluster: Arc<ClusterRegistry>,
 }
 
 impl StatusResource {
@@ -51,6 +68,7 @@ impl StatusResource {
         runtime_config: &Refreshable<R, Error>,
         health_checks: &Arc<HealthCheckRegistry>,
         readiness_checks: &Arc<ReadinessCheckRegistry>,
+        cluster: &Arc<ClusterRegistry>,
     ) -> Self
     where
         R: AsRef<RuntimeConfig> + PartialEq + 'static + Sync + Send,
@@ -60,7 +78,27 @@ impl StatusResource {
                 .map(|c| c.as_ref().health_checks().shared_secret().to_string()),
             health_checks: health_checks.clone(),
             readiness_checks: readiness_checks.clone(),
+            health_cache: ResponseCache::new(
+                runtime_config.map(|c| c.as_ref().health_checks().cache_ttl()),
+            ),
+            readiness_cache: ResponseCache::new(
+                runtime_config.map(|c| c.as_ref().health_checks().cache_ttl()),
+            ),
+            cache_validators: runtime_config.map(|c| c.as_ref().health_checks().cache_validators()),
+            cluster: cluster.clone(),
+        }
+    }
+
+    fn authorize(&self, token: &BearerToken) -> Result<(), Error> {
+        let expected = self.health_check_secret.get();
+        if !bool::from(token.as_str().as_bytes().ct_eq(expected.as_bytes())) {
+            return Err(Error::service_safe(
+                "invalid health check secret",
+                PermissionDenied::new(),
+            ));
         }
+
+        Ok(())
     }
 }
 
@@ -69,49 +107,396 @@ impl StatusService for StatusResource {
         Ok(())
     }
 
-    async fn readiness(&self) -> Result<BTreeMap<String, ReadinessCheckMetadata>, Error> {
-        let readiness_checks = task::spawn_blocking({
-            let readiness_checks = self.readiness_checks.clone();
-            move || readiness_checks.run_checks()
+    async fn readiness(&self) -> Result<Cached<BTreeMap<String, ReadinessCheckMetadata>>, Error> {
+        let readiness_checks = self
+            .readiness_cache
+            .get(|| async {
+                task::spawn_blocking({
+                    let readiness_checks = self.readiness_checks.clone();
+                    move || readiness_checks.run_checks()
+                })
+                .await
+                .unwrap()
+            })
+            .await;
+
+        Ok(readiness_checks.validators(*self.cache_validators.get(), false))
+    }
+
+    async fn health(&self, token: BearerToken) -> Result<Cached<HealthStatus>, Error> {
+        self.authorize(&token)?;
+
+        let health_checks = self
+            .health_cache
+            .get(|| async { self.health_checks.run_checks() })
+            .await;
+
+        Ok(health_checks.validators(*self.cache_validators.get(), true))
+    }
+
+    async fn cluster(&self, token: BearerToken) -> Result<ClusterInfo, Error> {
+        self.authorize(&token)?;
+
+        self.cluster.cluster_info().ok_or_else(|| {
+            Error::service_safe("no cluster info provider installed", NotFound::new())
         })
-        .await
-        .unwrap();
+    }
+}
+
+/// A response value along with the amount of time that has elapsed since it was computed.
+pub struct Cached<T> {
+    value: T,
+    age: Duration,
+    validators: Option<Validators>,
+}
 
-        Ok(readiness_checks)
+impl<T> Cached<T> {
+    fn validators(mut self, enabled: bool, private: bool) -> Self {
+        self.validators = enabled.then_some(Validators { private });
+        self
     }
+}
 
-    async fn health(&self, token: BearerToken) -> Result<HealthStatus, Error> {
-        let expected = self.health_check_secret.get();
-        if !bool::from(token.as_str().as_bytes().ct_eq(expected.as_bytes())) {
-            return Err(Error::service_safe(
-                "invalid health check secret",
-                PermissionDenied::new(),
-            ));
+struct Validators {
+    private: bool,
+}
+
+struct CacheEntry<T> {
+    value: T,
+    computed: Instant,
+    expiration: Instant,
+}
+
+/// A cache of a single response value, expiring after a jittered fraction of a configurable TTL.
+struct ResponseCache<T> {
+    ttl: Refreshable<Duration, Error>,
+    entry: Mutex<Option<CacheEntry<T>>>,
+    computing: tokio::sync::Mutex<()>,
+}
+
+impl<T> ResponseCache<T>
+where
+    T: Clone,
+{
+    fn new(ttl: Refreshable<Duration, Error>) -> Self {
+        ResponseCache {
+            ttl,
+            entry: Mutex::new(None),
+            computing: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    async fn get<F, Fut>(&self, compute: F) -> Cached<T>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        if let Some(cached) = self.cached() {
+            return cached;
+        }
+
+        let ttl = *self.ttl.get();
+        if ttl.is_zero() {
+            return Cached {
+                value: compute().await,
+                age: Duration::ZERO,
+                validators: None,
+            };
+        }
+
+        // Concurrent misses wait for a single computation rather than all recomputing the value.
+        let _guard = self.computing.lock().await;
+        if let Some(cached) = self.cached() {
+            return cached;
+        }
+
+        let computed = Instant::now();
+        let value = compute().await;
+
+        // Jitter the expiration so that pollers with identical intervals don't all trigger recomputation at once.
+        let ttl = ttl.mul_f64(rand::thread_rng().gen_range(0.75..=1.0));
+        *self.entry.lock() = Some(CacheEntry {
+            value: value.clone(),
+            computed,
+            expiration: computed + ttl,
+        });
+
+        Cached {
+            value,
+            age: Duration::ZERO,
+            validators: None,
         }
+    }
+
+    fn cached(&self) -> Option<Cached<T>> {
+        let entry = self.entry.lock();
+        let entry = entry.as_ref()?;
+        let now = Instant::now();
+        if now >= entry.expiration {
+            return None;
+        }
+
+        Some(Cached {
+            value: entry.value.clone(),
+            age: now - entry.computed,
+            validators: None,
+        })
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const REVALIDATE: HeaderValue = HeaderValue::from_static("no-cache");
+#[allow(clippy::declare_interior_mutable_const)]
+const REVALIDATE_PRIVATE: HeaderValue = HeaderValue::from_static("private, no-cache");
+
+enum CachedResponseSerializer {}
 
-        Ok(self.health_checks.run_checks())
+impl<T, W> AsyncSerializeResponse<Cached<T>, W> for CachedResponseSerializer
+where
+    T: Serialize,
+{
+    fn serialize(
+        runtime: &ConjureRuntime,
+        request_headers: &HeaderMap,
+        value: Cached<T>,
+    ) -> Result<Response<AsyncResponseBody<W>>, Error> {
+        serialize_cached(runtime, request_headers, value, StatusCode::OK)
     }
 }
 
 enum LivenessResponseSerializer {}
 
-impl<W> AsyncSerializeResponse<BTreeMap<String, ReadinessCheckMetadata>, W>
+impl<W> AsyncSerializeResponse<Cached<BTreeMap<String, ReadinessCheckMetadata>>, W>
     for LivenessResponseSerializer
 {
     fn serialize(
         runtime: &ConjureRuntime,
         request_headers: &HeaderMap,
-        value: BTreeMap<String, ReadinessCheckMetadata>,
+        value: Cached<BTreeMap<String, ReadinessCheckMetadata>>,
     ) -> Result<Response<AsyncResponseBody<W>>, Error> {
-        let status = if value.values().all(|r| r.successful) {
+        let status = if value.value.values().all(|r| r.successful) {
             StatusCode::OK
         } else {
             StatusCode::SERVICE_UNAVAILABLE
         };
 
-        let mut response = StdResponseSerializer::serialize(runtime, request_headers, value)?;
-        *response.status_mut() = status;
+        serialize_cached(runtime, request_headers, value, status)
+    }
+}
+
+fn serialize_cached<T, W>(
+    runtime: &ConjureRuntime,
+    request_headers: &HeaderMap,
+    value: Cached<T>,
+    status: StatusCode,
+) -> Result<Response<AsyncResponseBody<W>>, Error>
+where
+    T: Serialize,
+{
+    let mut response = StdResponseSerializer::serialize(runtime, request_headers, value.value)?;
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(AGE, HeaderValue::from(value.age.as_secs()));
+
+    // Only successful responses are conditional, so pollers always see failures in full.
+    let Some(validators) = value.validators else {
+        return Ok(response);
+    };
+    if status != StatusCode::OK {
+        return Ok(response);
+    }
+    let AsyncResponseBody::Fixed(body) = response.body() else {
+        return Ok(response);
+    };
+
+    let etag = etag(body);
+    let cache_control = if validators.private {
+        REVALIDATE_PRIVATE
+    } else {
+        REVALIDATE
+    };
+    response.headers_mut().insert(CACHE_CONTROL, cache_control);
+
+    if if_none_match(request_headers, &etag) {
+        *response.status_mut() = StatusCode::NOT_MODIFIED;
+        *response.body_mut() = AsyncResponseBody::Empty;
+        response.headers_mut().remove(CONTENT_TYPE);
+    }
+    response.headers_mut().insert(ETAG, etag);
+
+    Ok(response)
+}
+
+fn etag(body: &[u8]) -> HeaderValue {
+    let hash = Sha256::digest(body);
+    let mut etag = String::with_capacity(34);
+    etag.push('"');
+    for b in &hash[..16] {
+        write!(etag, "{b:02x}").unwrap();
+    }
+    etag.push('"');
+
+    HeaderValue::try_from(etag).unwrap()
+}
+
+/// Determines if an `If-None-Match` header matches an entity tag. Malformed headers never match.
+fn if_none_match(request_headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let Some(etag) = etag.to_str().ok().and_then(EntityTag::parse) else {
+        return false;
+    };
+
+    match request_headers.typed_get::<IfNoneMatch>() {
+        Ok(Some(if_none_match)) => if_none_match.matches(&etag),
+        Ok(None) | Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::readiness::{ReadinessCheck, ReadinessCheckResult};
+    use crate::testing::TestServer;
+    use bytes::Bytes;
+    use http::header::IF_NONE_MATCH;
+    use http::Request;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::runtime::Handle;
+    use witchcraft_server_config::runtime::{DiagnosticsConfig, HealthChecksConfig};
+
+    fn serialize(
+        value: &str,
+        validators: bool,
+        request_headers: &HeaderMap,
+    ) -> Response<AsyncResponseBody<()>> {
+        let value = Cached {
+            value: value.to_string(),
+            age: Duration::ZERO,
+            validators: None,
+        }
+        .validators(validators, false);
+
+        serialize_cached(
+            &ConjureRuntime::new(),
+            request_headers,
+            value,
+            StatusCode::OK,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn coalesced_misses() {
+        let cache = ResponseCache::new(Refreshable::new(Duration::from_secs(10)).0);
+        let computations = &AtomicUsize::new(0);
+        let compute = move || async move {
+            computations.fetch_add(1, Ordering::Relaxed);
+            task::yield_now().await;
+            "foo".to_string()
+        };
+
+        let (a, b) = tokio::join!(cache.get(compute), cache.get(compute));
+        assert_eq!(a.value, "foo");
+        assert_eq!(b.value, "foo");
+        assert_eq!(computations.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn revalidation() {
+        let response = serialize("foo", true, &HeaderMap::new());
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get(CACHE_CONTROL).unwrap(), "no-cache");
+        let etag = response.headers().get(ETAG).unwrap().clone();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(IF_NONE_MATCH, etag.clone());
+        let response = serialize("foo", true, &headers);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert!(matches!(response.body(), AsyncResponseBody::Empty));
+        assert_eq!(response.headers().get(ETAG).unwrap(), etag);
+
+        let response = serialize("bar", true, &headers);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(response.headers().get(ETAG).unwrap(), etag);
+    }
+
+    #[test]
+    fn disabled() {
+        let mut headers = HeaderMap::new();
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_static("*"));
+        let response = serialize("foo", false, &headers);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get(ETAG), None);
+        assert_eq!(response.headers().get(CACHE_CONTROL), None);
+    }
+
+    struct TestCheck {
+        type_: &'static str,
+        successful: bool,
+    }
+
+    impl ReadinessCheck for TestCheck {
+        fn type_(&self) -> &str {
+            self.type_
+        }
+
+        fn result(&self) -> ReadinessCheckResult {
+            ReadinessCheckResult::builder()
+                .successful(self.successful)
+                .build()
+        }
+    }
+
+    #[tokio::test]
+    async fn readiness_check_registered_after_startup() {
+        let readiness_checks = Arc::new(ReadinessCheckRegistry::new());
+        let (runtime_config, _) = Refreshable::new(
+            RuntimeConfig::builder()
+                .diagnostics(DiagnosticsConfig::builder().debug_shared_secret("").build())
+                .health_checks(HealthChecksConfig::builder().shared_secret("").build())
+                .build(),
+        );
+        let resource = StatusResource::new(
+            &runtime_config,
+            &Arc::new(HealthCheckRegistry::new(&Handle::current())),
+            &readiness_checks,
+            &Arc::new(ClusterRegistry::new()),
+        );
+        let mut server = TestServer::new();
+        server.app(StatusServiceEndpoints::new(resource));
+        let client = server.client();
+        let readiness = || async {
+            let request = Request::get("/status/readiness")
+                .body(Bytes::new())
+                .unwrap();
+            let response = client.send(request).await.unwrap();
+            let body = serde_json::from_slice::<serde_json::Value>(response.body()).unwrap();
+            (response.status(), body)
+        };
+
+        assert_eq!(readiness().await, (StatusCode::OK, json!({})));
+
+        let check = readiness_checks.register_if_absent(TestCheck {
+            type_: "LATE_CHECK",
+            successful: false,
+        });
+        assert!(!check.result().successful());
+        let expected = json!({"LATE_CHECK": {"type": "LATE_CHECK", "successful": false}});
+        assert_eq!(
+            readiness().await,
+            (StatusCode::SERVICE_UNAVAILABLE, expected.clone()),
+        );
 
-        Ok(response)
+        // a second registration of the same type returns the existing check and leaves it in place
+        let check = readiness_checks.register_if_absent(TestCheck {
+            type_: "LATE_CHECK",
+            successful: true,
+        });
+        assert!(!check.result().successful());
+        assert_eq!(
+            readiness().await,
+            (StatusCode::SERVICE_UNAVAILABLE, expected)
+        );
     }
 }
@@ -0,0 +1,443 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Utilities for unit testing endpoints.
+//!
+//! A [`TestServer`] dispatches requests to Conjure services over an in-memory HTTP/1 connection rather than a socket.
+//! Requests are routed to the same endpoint wrappers a real server uses, so tests observe the server's handling of
+//! errors, panics, streaming response bodies, and endpoint metrics. The connection-level and logging layers of a real
+//! server are not applied.
+//!
+//! A [`Snapshot`] renders requests and responses in a canonical text form suitable for comparison against snapshots
+//! stored alongside the tests.
+//!
+//! # Examples
+//!
+//! ```
+//! use bytes::Bytes;
+//! use conjure_error::Error;
+//! use conjure_http::server::StdResponseSerializer;
+//! use conjure_http::{conjure_endpoints, endpoint};
+//! use http::{Request, StatusCode};
+//! use witchcraft_metrics::MetricId;
+//! use witchcraft_server::testing::TestServer;
+//!
+//! #[conjure_endpoints]
+//! trait GreetingService {
+//!     #[endpoint(method = GET, path = "/greeting", produces = StdResponseSerializer)]
+//!     async fn greeting(&self) -> Result<String, Error>;
+//! }
+//!
+//! struct GreetingResource;
+//!
+//! impl GreetingService for GreetingResource {
+//!     async fn greeting(&self) -> Result<String, Error> {
+//!         Ok("hello".to_string())
+//!     }
+//! }
+//!
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//! let mut server = TestServer::new();
+//! server.api(GreetingServiceEndpoints::new(GreetingResource));
+//! let client = server.client();
+//!
+//! let response = client
+//!     .send(Request::get("/api/greeting").body(Bytes::new()).unwrap())
+//!     .await
+//!     .unwrap();
+//! assert_eq!(response.status(), StatusCode::OK);
+//! assert_eq!(response.body(), "\"hello\"");
+//!
+//! let timer = client.metrics().timer(
+//!     MetricId::new("server.response")
+//!         .with_tag("service-name", "GreetingService")
+//!         .with_tag("endpoint", "greeting"),
+//! );
+//! assert_eq!(timer.count(), 1);
+//! # });
+//! ```
+use crate::endpoint::conjure::ConjureEndpoint;
+use crate::endpoint::extended_path::ExtendedPathEndpoint;
+use crate::endpoint::{validation, WitchcraftEndpoint};
+use crate::server::RawBody;
+use crate::service::compression::DecompressionBody;
+use crate::service::cost_accounting::CostAccountingRequestBody;
+use crate::service::endpoint_metrics::{EndpointMetricsLayer, EndpointMetricsService};
+use crate::service::handler::HandlerService;
+use crate::service::read_timeout::ReadTimeoutBody;
+use crate::service::request_log::RequestLogRequestBody;
+use crate::service::request_size_limit::RequestSizeLimitBody;
+use crate::service::routing::{RoutingLayer, RoutingService};
+use crate::service::slow_client::MinTransferRateBody;
+use crate::service::spans::SpannedBody;
+use crate::service::{Service, ServiceBuilder};
+use crate::slo::SloRegistry;
+use crate::{RequestBody, ResponseWriter};
+use bytes::Bytes;
+use conjure_error::Error;
+use conjure_http::server::{AsyncService, ConjureRuntime};
+use http::header::{CONTENT_TYPE, DATE};
+use http::{HeaderMap, HeaderName, Request, Response};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper::client::conn::http1 as client_http1;
+use hyper::server::conn::http1 as server_http1;
+use hyper_util::rt::TokioIo;
+use refreshable::Refreshable;
+use serde_json::Value;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::sync::Arc;
+use tokio::io;
+use witchcraft_metrics::MetricRegistry;
+use witchcraft_server_config::runtime::{DiagnosticsConfig, HealthChecksConfig, RuntimeConfig};
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+const REDACTED: &str = "<redacted>";
+
+type RequestService = RoutingService<EndpointMetricsService<HandlerService>>;
+
+/// A server which dispatches requests to its endpoints without binding a socket.
+pub struct TestServer {
+    metrics: Arc<MetricRegistry>,
+    slos: Arc<SloRegistry>,
+    runtime_config: Refreshable<RuntimeConfig, Error>,
+    conjure_runtime: Arc<ConjureRuntime>,
+    endpoints: Vec<Box<dyn WitchcraftEndpoint + Sync + Send>>,
+}
+
+impl Default for TestServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestServer {
+    /// Creates a new server with the default runtime configuration.
+    pub fn new() -> Self {
+        Self::with_runtime_config(
+            RuntimeConfig::builder()
+                .diagnostics(DiagnosticsConfig::builder().debug_shared_secret("").build())
+                .health_checks(HealthChecksConfig::builder().shared_secret("").build())
+                .build(),
+        )
+    }
+
+    /// Creates a new server with the provided runtime configuration.
+    ///
+    /// The runtime configuration controls behavior like the maximum request size and endpoint SLOs.
+    pub fn with_runtime_config(runtime_config: RuntimeConfig) -> Self {
+        let metrics = Arc::new(MetricRegistry::new());
+        let (runtime_config, _) = Refreshable::new(runtime_config);
+
+        TestServer {
+            slos: Arc::new(SloRegistry::new(&metrics, runtime_config.clone())),
+            metrics,
+            runtime_config,
+            conjure_runtime: Arc::new(validation::runtime()),
+            endpoints: vec![],
+        }
+    }
+
+    /// Returns a reference to the server's metric registry.
+    pub fn metrics(&self) -> &Arc<MetricRegistry> {
+        &self.metrics
+    }
+
+    /// Installs an async service at the server's root.
+    pub fn app<T>(&mut self, service: T)
+    where
+        T: AsyncService<RequestBody, ResponseWriter>,
+    {
+        self.install(None, service);
+    }
+
+    /// Installs an async service under the server's `/api` prefix.
+    pub fn api<T>(&mut self, service: T)
+    where
+        T: AsyncService<RequestBody, ResponseWriter>,
+    {
+        self.install(Some("/api"), service);
+    }
+
+    fn install<T>(&mut self, prefix: Option<&str>, service: T)
+    where
+        T: AsyncService<RequestBody, ResponseWriter>,
+    {
+        let endpoints = service
+            .endpoints(&self.conjure_runtime)
+            .into_iter()
+            .map(|e| {
+                let endpoint = Box::new(ConjureEndpoint::new(
+                    Some((&*self.metrics, &*self.slos)),
+                    e,
+                    false,
+                )) as Box<dyn WitchcraftEndpoint + Sync + Send>;
+
+                match prefix {
+                    Some(prefix) => Box::new(ExtendedPathEndpoint::new(endpoint, prefix)) as _,
+                    None => endpoint,
+                }
+            });
+        self.endpoints.extend(endpoints);
+    }
+
+    /// Returns a client which sends requests to the server's installed endpoints.
+    pub fn client(self) -> TestClient {
+        let request_service = ServiceBuilder::new()
+            .layer(RoutingLayer::new(self.endpoints))
+            .layer(EndpointMetricsLayer::new(&self.runtime_config))
+            .service(HandlerService::new(&self.runtime_config));
+
+        TestClient {
+            metrics: self.metrics,
+            request_service: Arc::new(request_service),
+        }
+    }
+}
+
+/// A client sending requests to a [`TestServer`].
+pub struct TestClient {
+    metrics: Arc<MetricRegistry>,
+    request_service: Arc<RequestService>,
+}
+
+impl TestClient {
+    /// Returns a reference to the server's metric registry.
+    pub fn metrics(&self) -> &Arc<MetricRegistry> {
+        &self.metrics
+    }
+
+    /// Sends a request to the server, returning its response once the response body has been fully received.
+    ///
+    /// The request's URI should consist of only a path and query. Each request is sent over a new connection.
+    pub async fn send(&self, request: Request<Bytes>) -> Result<Response<Bytes>, Error> {
+        let (client, server) = io::duplex(BUFFER_SIZE);
+
+        let request_service = self.request_service.clone();
+        let connection = server_http1::Builder::new().serve_connection(
+            TokioIo::new(server),
+            hyper::service::service_fn(move |req: Request<Incoming>| {
+                let request_service = request_service.clone();
+                async move { Ok::<_, Infallible>(request_service.call(req.map(raw_body)).await) }
+            }),
+        );
+        tokio::spawn(connection);
+
+        let (mut sender, connection) = client_http1::handshake(TokioIo::new(client))
+            .await
+            .map_err(Error::internal_safe)?;
+        tokio::spawn(connection);
+
+        let response = sender
+            .send_request(request.map(Full::new))
+            .await
+            .map_err(Error::internal_safe)?;
+        let (parts, body) = response.into_parts();
+        let body = body
+            .collect()
+            .await
+            .map_err(Error::internal_safe)?
+            .to_bytes();
+
+        Ok(Response::from_parts(parts, body))
+    }
+}
+
+/// A renderer of requests and responses for snapshot testing.
+///
+/// Headers are rendered sorted by name, and JSON bodies are pretty-printed with their object keys sorted. The values of
+/// volatile headers and JSON fields which vary from run to run are replaced with `<redacted>`. By default, the `Date`
+/// and `X-B3-TraceId` headers and the `errorInstanceId` field of Conjure errors are redacted.
+pub struct Snapshot {
+    redacted_headers: Vec<HeaderName>,
+    redacted_fields: Vec<String>,
+}
+
+impl Default for Snapshot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Snapshot {
+    /// Creates a new snapshot renderer with the default redactions.
+    pub fn new() -> Self {
+        Snapshot {
+            redacted_headers: vec![DATE, HeaderName::from_static("x-b3-traceid")],
+            redacted_fields: vec!["errorInstanceId".to_string()],
+        }
+    }
+
+    /// Redacts the value of a header.
+    pub fn redact_header(mut self, name: HeaderName) -> Self {
+        self.redacted_headers.push(name);
+        self
+    }
+
+    /// Redacts the value of every JSON object field with the specified name, at any depth of the body.
+    pub fn redact_field(mut self, name: &str) -> Self {
+        self.redacted_fields.push(name.to_string());
+        self
+    }
+
+    /// Renders a request.
+    pub fn request(&self, request: &Request<Bytes>) -> String {
+        let mut out = format!("{} {}\n", request.method(), request.uri());
+        self.render(&mut out, request.headers(), request.body());
+        out
+    }
+
+    /// Renders a response.
+    pub fn response(&self, response: &Response<Bytes>) -> String {
+        let mut out = format!("{}\n", response.status());
+        self.render(&mut out, response.headers(), response.body());
+        out
+    }
+
+    fn render(&self, out: &mut String, headers: &HeaderMap, body: &Bytes) {
+        let mut names = headers.keys().collect::<Vec<_>>();
+        names.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        for name in names {
+            for value in headers.get_all(name) {
+                let value = if self.redacted_headers.contains(name) {
+                    REDACTED
+                } else {
+                    value.to_str().unwrap_or("<binary>")
+                };
+                writeln!(out, "{name}: {value}").unwrap();
+            }
+        }
+
+        if body.is_empty() {
+            return;
+        }
+        out.push('\n');
+
+        let is_json = headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .is_some_and(|v| v.trim() == "application/json" || v.trim().ends_with("+json"));
+        if is_json {
+            if let Ok(value) = serde_json::from_slice::<Value>(body) {
+                let value = self.canonicalize(value);
+                out.push_str(&serde_json::to_string_pretty(&value).unwrap());
+                out.push('\n');
+                return;
+            }
+        }
+
+        match std::str::from_utf8(body) {
+            Ok(body) => {
+                out.push_str(body);
+                if !body.ends_with('\n') {
+                    out.push('\n');
+                }
+            }
+            Err(_) => writeln!(out, "<{} bytes of binary data>", body.len()).unwrap(),
+        }
+    }
+
+    fn canonicalize(&self, value: Value) -> Value {
+        match value {
+            Value::Object(map) => {
+                let mut fields = map.into_iter().collect::<Vec<_>>();
+                fields.sort_by(|a, b| a.0.cmp(&b.0));
+                let fields = fields
+                    .into_iter()
+                    .map(|(key, value)| {
+                        let value = if self.redacted_fields.contains(&key) {
+                            Value::String(REDACTED.to_string())
+                        } else {
+                            self.canonicalize(value)
+                        };
+                        (key, value)
+                    })
+                    .collect();
+                Value::Object(fields)
+            }
+            Value::Array(values) => {
+                Value::Array(values.into_iter().map(|v| self.canonicalize(v)).collect())
+            }
+            value => value,
+        }
+    }
+}
+
+fn raw_body(body: Incoming) -> RawBody {
+    let body = SpannedBody::new(
+        ReadTimeoutBody::passthrough(MinTransferRateBody::passthrough(body)),
+        "witchcraft: read-request-body",
+        None,
+    );
+
+    RequestSizeLimitBody::passthrough(DecompressionBody::passthrough(
+        CostAccountingRequestBody::passthrough(RequestLogRequestBody::passthrough(body)),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use http::StatusCode;
+
+    #[tokio::test]
+    async fn unknown_path() {
+        let client = TestServer::new().client();
+
+        let response = client
+            .send(Request::get("/missing").body(Bytes::new()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.body(), "");
+    }
+
+    #[test]
+    fn snapshot() {
+        let response = Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .header("date", "Thu, 15 Oct 2026 00:00:00 GMT")
+            .header("content-type", "application/json")
+            .header("b-header", "b")
+            .header("a-header", "a")
+            .body(Bytes::from_static(
+                br#"{"errorCode":"INTERNAL","errorInstanceId":"a1b2","parameters":{"z":1,"a":[{"at":"now"}]}}"#,
+            ))
+            .unwrap();
+
+        let snapshot = Snapshot::new().redact_field("at").response(&response);
+        let expected = r#"500 Internal Server Error
+a-header: a
+b-header: b
+content-type: application/json
+date: <redacted>
+
+{
+  "errorCode": "INTERNAL",
+  "errorInstanceId": "<redacted>",
+  "parameters": {
+    "a": [
+      {
+        "at": "<redacted>"
+      }
+    ],
+    "z": 1
+  }
+}
+"#;
+        assert_eq!(snapshot, expected);
+    }
+}
@@ -0,0 +1,288 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Token bucket rate limiting.
+//!
+//! A [`TokenBucket`] is the shared primitive for limiting the rate at which some resource is consumed, whether that
+//! be requests, bytes, or connections. Buckets can be arranged hierarchically: a bucket created with
+//! [`TokenBucket::child`] only grants tokens when both it and its ancestors have them available, so for example
+//! per-client limits compose with a global limit. Each bucket's rate and burst size are read from a [`Refreshable`]
+//! configuration and take effect immediately when it changes.
+//!
+//! Tokens can be acquired with a [`Priority`], typically that of the request's [`RequestClass`]. Low priority
+//! acquisitions are throttled once the bucket is half empty, reserving the remaining tokens for other traffic, while
+//! critical acquisitions are never throttled and may leave the bucket in debt.
+//!
+//! Every bucket reports the `server.token-bucket.available` gauge and `server.token-bucket.throttled` meter, tagged
+//! with the bucket's name.
+//!
+//! [`RequestClass`]: crate::classification::RequestClass
+use crate::classification::Priority;
+use conjure_error::Error;
+use parking_lot::Mutex;
+use refreshable::Refreshable;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::{self, Instant};
+use witchcraft_metrics::{Meter, MetricId, MetricRegistry};
+use witchcraft_server_config::runtime::TokenBucketConfig;
+
+// Waiters periodically wake up to pick up changes to the bucket's configuration.
+const MAX_SLEEP: Duration = Duration::from_secs(1);
+
+// The fraction of a bucket's burst size which low priority acquisitions can't consume.
+const LOW_PRIORITY_RESERVE: f64 = 0.5;
+
+/// A rate limiter which refills with tokens at a fixed rate up to a maximum burst size.
+///
+/// Buckets are cheaply cloneable, and clones share the same tokens.
+#[derive(Clone)]
+pub struct TokenBucket {
+    shared: Arc<Shared>,
+}
+
+impl TokenBucket {
+    /// Creates a new root token bucket, initially full.
+    ///
+    /// The bucket's metrics are registered with the provided registry tagged with `name`, which should be unique.
+    pub fn new(
+        metrics: &MetricRegistry,
+        name: &str,
+        config: Refreshable<TokenBucketConfig, Error>,
+    ) -> Self {
+        Self::new_inner(metrics, name, config, None)
+    }
+
+    /// Creates a new token bucket, initially full, which additionally draws its tokens from this bucket.
+    pub fn child(
+        &self,
+        metrics: &MetricRegistry,
+        name: &str,
+        config: Refreshable<TokenBucketConfig, Error>,
+    ) -> Self {
+        Self::new_inner(metrics, name, config, Some(self.clone()))
+    }
+
+    fn new_inner(
+        metrics: &MetricRegistry,
+        name: &str,
+        config: Refreshable<TokenBucketConfig, Error>,
+        parent: Option<TokenBucket>,
+    ) -> Self {
+        let state = State {
+            tokens: config.get().burst() as f64,
+            updated: Instant::now(),
+        };
+        let shared = Arc::new(Shared {
+            parent,
+            config,
+            state: Mutex::new(state),
+            throttled: metrics.meter(
+                MetricId::new("server.token-bucket.throttled").with_tag("name", name.to_string()),
+            ),
+        });
+
+        metrics.gauge(
+            MetricId::new("server.token-bucket.available").with_tag("name", name.to_string()),
+            {
+                let shared = Arc::downgrade(&shared);
+                move || shared.upgrade().map_or(0, |s| s.available(Instant::now()))
+            },
+        );
+
+        TokenBucket { shared }
+    }
+
+    /// Attempts to take tokens from the bucket and its ancestors without waiting.
+    ///
+    /// If not enough tokens are available, nothing is taken and an estimate of how long to wait before retrying is
+    /// returned. Requests for more tokens than the bucket's burst size succeed once the bucket is full, leaving it in
+    /// debt until it refills.
+    pub fn try_acquire(&self, tokens: u64) -> Result<(), Duration> {
+        self.try_acquire_with_priority(tokens, Priority::Normal)
+    }
+
+    /// Like [`Self::try_acquire`], but with the specified priority.
+    ///
+    /// Low priority acquisitions fail unless at least half of the bucket's burst size would remain afterwards, and
+    /// critical acquisitions always succeed.
+    pub fn try_acquire_with_priority(
+        &self,
+        tokens: u64,
+        priority: Priority,
+    ) -> Result<(), Duration> {
+        self.shared.try_acquire(tokens, priority, Instant::now())
+    }
+
+    /// Takes tokens from the bucket and its ancestors, waiting until they are available.
+    pub async fn acquire(&self, tokens: u64) {
+        self.acquire_with_priority(tokens, Priority::Normal).await
+    }
+
+    /// Like [`Self::acquire`], but with the specified priority.
+    pub async fn acquire_with_priority(&self, tokens: u64, priority: Priority) {
+        while let Err(wait) = self.try_acquire_with_priority(tokens, priority) {
+            time::sleep(wait.min(MAX_SLEEP)).await;
+        }
+    }
+}
+
+struct Shared {
+    parent: Option<TokenBucket>,
+    config: Refreshable<TokenBucketConfig, Error>,
+    state: Mutex<State>,
+    throttled: Arc<Meter>,
+}
+
+impl Shared {
+    fn available(&self, now: Instant) -> i64 {
+        let config = self.config.get();
+        let mut state = self.state.lock();
+        state.refill(&config, now);
+        state.tokens as i64
+    }
+
+    fn try_acquire(&self, tokens: u64, priority: Priority, now: Instant) -> Result<(), Duration> {
+        let config = self.config.get();
+        let mut state = self.state.lock();
+        state.refill(&config, now);
+
+        let burst = config.burst() as f64;
+        let required = match priority {
+            Priority::Critical => f64::NEG_INFINITY,
+            Priority::Low => (tokens as f64 + burst * LOW_PRIORITY_RESERVE).min(burst),
+            _ => tokens.min(config.burst()) as f64,
+        };
+        if state.tokens < required {
+            self.throttled.mark(1);
+            let wait = (required - state.tokens) / config.rate();
+            return Err(Duration::try_from_secs_f64(wait).unwrap_or(Duration::MAX));
+        }
+        state.tokens -= tokens as f64;
+        drop(state);
+
+        // Locks are never held across levels so concurrent acquisitions can't deadlock.
+        if let Some(parent) = &self.parent {
+            if let Err(wait) = parent.shared.try_acquire(tokens, priority, now) {
+                let mut state = self.state.lock();
+                state.tokens = (state.tokens + tokens as f64).min(config.burst() as f64);
+                return Err(wait);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct State {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl State {
+    fn refill(&mut self, config: &TokenBucketConfig, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * config.rate()).min(config.burst() as f64);
+        self.updated = now;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(rate: f64, burst: u64) -> Refreshable<TokenBucketConfig, Error> {
+        let config = TokenBucketConfig::builder()
+            .rate(rate)
+            .burst(burst)
+            .build()
+            .unwrap();
+        Refreshable::new(config).0
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn refill() {
+        let metrics = MetricRegistry::new();
+        let bucket = TokenBucket::new(&metrics, "test", config(10., 5));
+
+        bucket.try_acquire(5).unwrap();
+        assert_eq!(bucket.try_acquire(1), Err(Duration::from_millis(100)));
+
+        time::advance(Duration::from_millis(300)).await;
+        bucket.try_acquire(3).unwrap();
+        bucket.try_acquire(1).unwrap_err();
+
+        time::advance(Duration::from_secs(10)).await;
+        bucket.try_acquire(5).unwrap();
+        bucket.try_acquire(1).unwrap_err();
+
+        let throttled =
+            metrics.meter(MetricId::new("server.token-bucket.throttled").with_tag("name", "test"));
+        assert_eq!(throttled.count(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn oversized() {
+        let metrics = MetricRegistry::new();
+        let bucket = TokenBucket::new(&metrics, "test", config(10., 5));
+
+        bucket.try_acquire(20).unwrap();
+        assert_eq!(bucket.try_acquire(1), Err(Duration::from_millis(1600)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn priorities() {
+        let metrics = MetricRegistry::new();
+        let bucket = TokenBucket::new(&metrics, "test", config(10., 10));
+
+        bucket.try_acquire_with_priority(5, Priority::Low).unwrap();
+        assert_eq!(
+            bucket.try_acquire_with_priority(1, Priority::Low),
+            Err(Duration::from_millis(100))
+        );
+        bucket.try_acquire(5).unwrap();
+        bucket
+            .try_acquire_with_priority(3, Priority::Critical)
+            .unwrap();
+        assert_eq!(bucket.try_acquire(1), Err(Duration::from_millis(400)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hierarchy() {
+        let metrics = MetricRegistry::new();
+        let parent = TokenBucket::new(&metrics, "parent", config(1., 3));
+        let a = parent.child(&metrics, "a", config(1., 2));
+        let b = parent.child(&metrics, "b", config(1., 2));
+
+        a.try_acquire(2).unwrap();
+        a.try_acquire(1).unwrap_err();
+        b.try_acquire(1).unwrap();
+        // b has a token but the parent is exhausted, so b's token is returned
+        assert_eq!(b.try_acquire(1), Err(Duration::from_secs(1)));
+
+        time::advance(Duration::from_secs(1)).await;
+        b.try_acquire(1).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn acquire() {
+        let metrics = MetricRegistry::new();
+        let bucket = TokenBucket::new(&metrics, "test", config(2., 1));
+
+        let start = Instant::now();
+        bucket.acquire(1).await;
+        bucket.acquire(1).await;
+        bucket.acquire(1).await;
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+    }
+}
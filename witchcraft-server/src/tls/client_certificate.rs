@@ -11,25 +11,170 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-
+use sha2::{Digest, Sha256};
+use std::fmt::{self, Write};
+use std::net::IpAddr;
+use std::sync::Arc;
 use webpki::types::CertificateDer;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::FromDer;
 
 /// A client's identity provided during the TLS handshake.
 ///
 /// If client authentication is enabled and a client provides a certificate during the TLS handshake, this will be added
 /// to the extensions of each request made on that connection.
 #[derive(Clone)]
-pub struct ClientCertificate {
+pub struct ClientCertificate(Arc<Inner>);
+
+struct Inner {
     cert: CertificateDer<'static>,
+    subject: String,
+    common_name: Option<String>,
+    subject_alt_names: Vec<SubjectAltName>,
+    fingerprint: String,
+    chain: Vec<ClientCertificate>,
 }
 
-// FIXME(sfackler) what accessors should we expose here? We probably want to avoid exposing `rustls` APIs directly.
 impl ClientCertificate {
-    pub(crate) fn new(cert: CertificateDer<'static>) -> Self {
-        ClientCertificate { cert }
+    /// Creates a certificate from the chain presented by the client, starting with the leaf certificate.
+    pub(crate) fn from_chain(chain: &[CertificateDer<'_>]) -> Option<Self> {
+        let (leaf, intermediates) = chain.split_first()?;
+        let intermediates = intermediates
+            .iter()
+            .map(|cert| ClientCertificate::new(cert.clone().into_owned(), vec![]))
+            .collect();
+        Some(ClientCertificate::new(
+            leaf.clone().into_owned(),
+            intermediates,
+        ))
+    }
+
+    fn new(cert: CertificateDer<'static>, chain: Vec<ClientCertificate>) -> Self {
+        let mut subject = String::new();
+        let mut common_name = None;
+        let mut subject_alt_names = vec![];
+        if let Ok((_, parsed)) = X509Certificate::from_der(&cert) {
+            subject = parsed.subject().to_string();
+            common_name = parsed
+                .subject()
+                .iter_common_name()
+                .next()
+                .and_then(|cn| cn.as_str().ok())
+                .map(str::to_string);
+            if let Ok(Some(san)) = parsed.subject_alternative_name() {
+                subject_alt_names = san
+                    .value
+                    .general_names
+                    .iter()
+                    .filter_map(SubjectAltName::from_general_name)
+                    .collect();
+            }
+        }
+
+        let mut fingerprint = String::with_capacity(64);
+        for b in Sha256::digest(&cert) {
+            write!(fingerprint, "{b:02x}").unwrap();
+        }
+
+        ClientCertificate(Arc::new(Inner {
+            cert,
+            subject,
+            common_name,
+            subject_alt_names,
+            fingerprint,
+            chain,
+        }))
+    }
+
+    /// Returns the certificate's subject distinguished name in its RFC 4514 string form, e.g.
+    /// `CN=client.example.com, O=Example`.
+    ///
+    /// The string is empty if the certificate could not be parsed.
+    #[inline]
+    pub fn subject(&self) -> &str {
+        &self.0.subject
+    }
+
+    /// Returns the common name of the certificate's subject, if present.
+    #[inline]
+    pub fn common_name(&self) -> Option<&str> {
+        self.0.common_name.as_deref()
+    }
+
+    /// Returns the DNS names, email addresses, URIs, and IP addresses in the certificate's subject alternative name
+    /// extension.
+    #[inline]
+    pub fn subject_alt_names(&self) -> &[SubjectAltName] {
+        &self.0.subject_alt_names
+    }
+
+    /// Returns the lowercase hex-encoded SHA-256 fingerprint of the certificate's DER encoding.
+    #[inline]
+    pub fn fingerprint(&self) -> &str {
+        &self.0.fingerprint
+    }
+
+    /// Returns the certificate's DER encoding.
+    #[inline]
+    pub fn der(&self) -> &[u8] {
+        &self.0.cert
+    }
+
+    /// Returns the intermediate certificates the client presented after this one, in order.
+    ///
+    /// The chain has been verified against the server's client authentication trust roots. It is empty for the
+    /// intermediate certificates themselves.
+    #[inline]
+    pub fn chain(&self) -> &[ClientCertificate] {
+        &self.0.chain
     }
 
     pub(crate) fn cert(&self) -> &CertificateDer<'static> {
-        &self.cert
+        &self.0.cert
+    }
+}
+
+/// An entry in a certificate's subject alternative name extension.
+///
+/// The [`fmt::Display`] implementation renders the entry with the conventional OpenSSL prefix, e.g.
+/// `DNS:client.example.com`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum SubjectAltName {
+    /// A DNS name.
+    Dns(String),
+    /// An RFC 822 email address.
+    Email(String),
+    /// A URI, e.g. a SPIFFE ID.
+    Uri(String),
+    /// An IP address.
+    Ip(IpAddr),
+}
+
+impl SubjectAltName {
+    fn from_general_name(name: &GeneralName<'_>) -> Option<Self> {
+        match name {
+            GeneralName::DNSName(name) => Some(SubjectAltName::Dns(name.to_string())),
+            GeneralName::RFC822Name(name) => Some(SubjectAltName::Email(name.to_string())),
+            GeneralName::URI(uri) => Some(SubjectAltName::Uri(uri.to_string())),
+            GeneralName::IPAddress(ip) => <[u8; 4]>::try_from(*ip)
+                .map(IpAddr::from)
+                .or_else(|_| <[u8; 16]>::try_from(*ip).map(IpAddr::from))
+                .ok()
+                .map(SubjectAltName::Ip),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SubjectAltName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubjectAltName::Dns(name) => write!(f, "DNS:{name}"),
+            SubjectAltName::Email(email) => write!(f, "email:{email}"),
+            SubjectAltName::Uri(uri) => write!(f, "URI:{uri}"),
+            SubjectAltName::Ip(ip) => write!(f, "IP:{ip}"),
+        }
     }
 }
@@ -0,0 +1,267 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use conjure_error::Error;
+use tokio_rustls::rustls::crypto::CryptoProvider;
+use tokio_rustls::rustls::NamedGroup;
+use witchcraft_log::info;
+use witchcraft_server_config::install::{self, CipherSuite, KeyExchangeGroup, TlsConfig};
+
+const DEFAULT_CIPHER_SUITES: [CipherSuite; 9] = [
+    CipherSuite::Aes256GcmSha384,
+    CipherSuite::Aes128GcmSha256,
+    CipherSuite::Chacha20Poly1305Sha256,
+    CipherSuite::EcdheEcdsaWithAes256GcmSha384,
+    CipherSuite::EcdheEcdsaWithAes128GcmSha256,
+    CipherSuite::EcdheEcdsaWithChacha20Poly1305Sha256,
+    CipherSuite::EcdheRsaWithAes256GcmSha384,
+    CipherSuite::EcdheRsaWithAes128GcmSha256,
+    CipherSuite::EcdheRsaWithChacha20Poly1305Sha256,
+];
+
+const DEFAULT_KX_GROUPS: [KeyExchangeGroup; 3] = [
+    KeyExchangeGroup::Secp256r1,
+    KeyExchangeGroup::Secp384r1,
+    KeyExchangeGroup::X25519,
+];
+
+fn cipher_suite(suite: &CipherSuite) -> tokio_rustls::rustls::CipherSuite {
+    use tokio_rustls::rustls::CipherSuite as Rustls;
+
+    match suite {
+        CipherSuite::Aes256GcmSha384 => Rustls::TLS13_AES_256_GCM_SHA384,
+        CipherSuite::Aes128GcmSha256 => Rustls::TLS13_AES_128_GCM_SHA256,
+        CipherSuite::Chacha20Poly1305Sha256 => Rustls::TLS13_CHACHA20_POLY1305_SHA256,
+        CipherSuite::EcdheEcdsaWithAes256GcmSha384 => {
+            Rustls::TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384
+        }
+        CipherSuite::EcdheEcdsaWithAes128GcmSha256 => {
+            Rustls::TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256
+        }
+        CipherSuite::EcdheEcdsaWithChacha20Poly1305Sha256 => {
+            Rustls::TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256
+        }
+        CipherSuite::EcdheRsaWithAes256GcmSha384 => Rustls::TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384,
+        CipherSuite::EcdheRsaWithAes128GcmSha256 => Rustls::TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256,
+        CipherSuite::EcdheRsaWithChacha20Poly1305Sha256 => {
+            Rustls::TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256
+        }
+        _ => unreachable!("unsupported cipher suite {suite:?}"),
+    }
+}
+
+fn kx_group(group: &KeyExchangeGroup) -> NamedGroup {
+    match group {
+        KeyExchangeGroup::Secp256r1 => NamedGroup::secp256r1,
+        KeyExchangeGroup::Secp384r1 => NamedGroup::secp384r1,
+        KeyExchangeGroup::X25519 => NamedGroup::X25519,
+        _ => unreachable!("unsupported key exchange group {group:?}"),
+    }
+}
+
+/// Installs the configured crypto provider as the process-wide default, failing if it is not available.
+///
+/// The default provider is used by the ACME client and any other users of rustls in the process which don't specify
+/// a provider themselves. Only the first default installed in a process takes effect, so an application which has
+/// already installed its own keeps it.
+pub(crate) fn init(config: &TlsConfig) -> Result<(), Error> {
+    let provider = base_provider(config)?;
+    if provider.install_default().is_ok() {
+        info!(
+            "installed TLS crypto provider",
+            safe: {
+                provider: format_args!("{:?}", config.crypto_provider()),
+                fips: config.fips(),
+            },
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns the crypto provider used by the server's TLS listeners, restricted to the configured cipher suites and key
+/// exchange groups.
+///
+/// When FIPS mode is enabled, unapproved algorithms are dropped from the defaults and rejected if explicitly
+/// configured.
+pub(crate) fn server_provider(config: &TlsConfig) -> Result<CryptoProvider, Error> {
+    let base = base_provider(config)?;
+
+    let mut cipher_suites = vec![];
+    if config.cipher_suites().is_empty() {
+        for suite in &DEFAULT_CIPHER_SUITES {
+            let id = cipher_suite(suite);
+            cipher_suites.extend(base.cipher_suites.iter().find(|s| s.suite() == id));
+        }
+    } else {
+        for suite in config.cipher_suites() {
+            let id = cipher_suite(suite);
+            let supported = base
+                .cipher_suites
+                .iter()
+                .find(|s| s.suite() == id)
+                .ok_or_else(|| {
+                    Error::internal_safe("cipher suite is not supported by the crypto provider")
+                        .with_safe_param("cipherSuite", format!("{suite:?}"))
+                        .with_safe_param("fips", config.fips())
+                })?;
+            cipher_suites.push(*supported);
+        }
+    }
+
+    let mut kx_groups = vec![];
+    if config.key_exchange_groups().is_empty() {
+        for group in &DEFAULT_KX_GROUPS {
+            let name = kx_group(group);
+            kx_groups.extend(base.kx_groups.iter().find(|g| g.name() == name));
+        }
+    } else {
+        for group in config.key_exchange_groups() {
+            let name = kx_group(group);
+            let supported = base
+                .kx_groups
+                .iter()
+                .find(|g| g.name() == name)
+                .ok_or_else(|| {
+                    Error::internal_safe(
+                        "key exchange group is not supported by the crypto provider",
+                    )
+                    .with_safe_param("keyExchangeGroup", format!("{group:?}"))
+                    .with_safe_param("fips", config.fips())
+                })?;
+            kx_groups.push(*supported);
+        }
+    }
+
+    Ok(CryptoProvider {
+        cipher_suites,
+        kx_groups,
+        ..base
+    })
+}
+
+fn base_provider(config: &TlsConfig) -> Result<CryptoProvider, Error> {
+    let mut provider = match config.crypto_provider() {
+        install::CryptoProvider::AwsLcRs => aws_lc_rs_provider(config.fips())?,
+        install::CryptoProvider::Ring => ring_provider()?,
+        provider => unreachable!("unsupported crypto provider {provider:?}"),
+    };
+
+    if config.fips() {
+        provider.cipher_suites.retain(|s| s.fips());
+        provider.kx_groups.retain(|g| g.fips());
+        if !provider.fips() {
+            return Err(Error::internal_safe(
+                "tls.fips is enabled but the crypto provider is not FIPS-approved",
+            ));
+        }
+    }
+
+    Ok(provider)
+}
+
+#[cfg(feature = "aws-lc-rs")]
+fn aws_lc_rs_provider(fips: bool) -> Result<CryptoProvider, Error> {
+    // With the feature enabled, aws-lc-rs is built from AWS-LC's FIPS module.
+    if fips && !cfg!(feature = "fips") {
+        return Err(Error::internal_safe(
+            "tls.fips is enabled but witchcraft-server was built without the `fips` cargo feature",
+        ));
+    }
+
+    Ok(tokio_rustls::rustls::crypto::aws_lc_rs::default_provider())
+}
+
+#[cfg(not(feature = "aws-lc-rs"))]
+fn aws_lc_rs_provider(_: bool) -> Result<CryptoProvider, Error> {
+    Err(Error::internal_safe(
+        "tls.crypto-provider is aws-lc-rs but witchcraft-server was built without the `aws-lc-rs` cargo feature",
+    ))
+}
+
+#[cfg(feature = "ring")]
+fn ring_provider() -> Result<CryptoProvider, Error> {
+    Ok(tokio_rustls::rustls::crypto::ring::default_provider())
+}
+
+#[cfg(not(feature = "ring"))]
+fn ring_provider() -> Result<CryptoProvider, Error> {
+    Err(Error::internal_safe(
+        "tls.crypto-provider is ring but witchcraft-server was built without the `ring` cargo feature",
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "aws-lc-rs")]
+    #[test]
+    fn defaults() {
+        let provider = server_provider(&TlsConfig::default()).unwrap();
+        assert_eq!(provider.cipher_suites.len(), DEFAULT_CIPHER_SUITES.len());
+        assert_eq!(provider.kx_groups.len(), DEFAULT_KX_GROUPS.len());
+    }
+
+    #[cfg(feature = "aws-lc-rs")]
+    #[test]
+    fn configured_order() {
+        let config = TlsConfig::builder()
+            .push_cipher_suites(CipherSuite::Aes128GcmSha256)
+            .push_cipher_suites(CipherSuite::Aes256GcmSha384)
+            .push_key_exchange_groups(KeyExchangeGroup::X25519)
+            .build();
+        let provider = server_provider(&config).unwrap();
+
+        let suites = provider
+            .cipher_suites
+            .iter()
+            .map(|s| s.suite())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            suites,
+            [
+                tokio_rustls::rustls::CipherSuite::TLS13_AES_128_GCM_SHA256,
+                tokio_rustls::rustls::CipherSuite::TLS13_AES_256_GCM_SHA384,
+            ],
+        );
+        let groups = provider
+            .kx_groups
+            .iter()
+            .map(|g| g.name())
+            .collect::<Vec<_>>();
+        assert_eq!(groups, [NamedGroup::X25519]);
+    }
+
+    #[cfg(not(feature = "aws-lc-rs"))]
+    #[test]
+    fn aws_lc_rs_not_compiled_in() {
+        server_provider(&TlsConfig::default()).unwrap_err();
+    }
+
+    #[cfg(not(feature = "ring"))]
+    #[test]
+    fn ring_not_compiled_in() {
+        let config = TlsConfig::builder()
+            .crypto_provider(install::CryptoProvider::Ring)
+            .build();
+        server_provider(&config).unwrap_err();
+    }
+
+    #[cfg(not(feature = "fips"))]
+    #[test]
+    fn fips_not_compiled_in() {
+        let config = TlsConfig::builder().fips(true).build();
+        server_provider(&config).unwrap_err();
+    }
+}
@@ -0,0 +1,90 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use conjure_error::Error;
+use parking_lot::Mutex;
+use std::fmt::{self, Write as _};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use tokio_rustls::rustls::KeyLog;
+use witchcraft_log::warn;
+
+/// A [`KeyLog`] which appends TLS session secrets to a file in the `SSLKEYLOGFILE` format.
+pub(crate) struct KeyLogWriter {
+    file: Mutex<File>,
+}
+
+impl KeyLogWriter {
+    pub(crate) fn open(path: &Path) -> Result<Self, Error> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .mode(0o600)
+            .open(path)
+            .map_err(|e| Error::internal_safe(e).with_safe_param("path", path.to_string_lossy()))?;
+
+        warn!(
+            "TLS key logging is enabled - anyone with access to the key log file can decrypt captured traffic",
+            safe: {
+                path: path.to_string_lossy(),
+            },
+        );
+
+        Ok(KeyLogWriter {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl fmt::Debug for KeyLogWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyLogWriter").finish_non_exhaustive()
+    }
+}
+
+impl KeyLog for KeyLogWriter {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        let line = format_line(label, client_random, secret);
+        if let Err(e) = self.file.lock().write_all(line.as_bytes()) {
+            warn!("error writing to TLS key log", error: Error::internal_safe(e));
+        }
+    }
+}
+
+fn format_line(label: &str, client_random: &[u8], secret: &[u8]) -> String {
+    let mut line = format!("{label} ");
+    for b in client_random {
+        write!(line, "{b:02x}").unwrap();
+    }
+    line.push(' ');
+    for b in secret {
+        write!(line, "{b:02x}").unwrap();
+    }
+    line.push('\n');
+    line
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format() {
+        assert_eq!(
+            format_line("CLIENT_RANDOM", &[0x01, 0xab], &[0xff, 0x00, 0x10]),
+            "CLIENT_RANDOM 01ab ff0010\n",
+        );
+    }
+}
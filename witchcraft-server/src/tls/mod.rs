@@ -12,8 +12,21 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 //! Advanced TLS features.
-pub use client_certificate::ClientCertificate;
+//!
+//! The version of [`rustls`] used by the server is re-exported so that implementations of [`PrivateKeyProvider`] can
+//! refer to its types.
+pub use client_certificate::{ClientCertificate, SubjectAltName};
+pub(crate) use key_log::KeyLogWriter;
+pub use private_key::PrivateKeyProvider;
+pub use session::TlsSession;
+pub(crate) use ticketer::Ticketer;
 pub use tls_client_authentication::TlsClientAuthenticationService;
+pub use tokio_rustls::rustls;
 
 mod client_certificate;
+pub(crate) mod crypto_provider;
+mod key_log;
+mod private_key;
+mod session;
+mod ticketer;
 mod tls_client_authentication;
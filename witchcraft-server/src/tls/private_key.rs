@@ -0,0 +1,33 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use conjure_error::Error;
+use std::sync::Arc;
+use tokio_rustls::rustls::sign::SigningKey;
+
+/// A provider of the private key the server uses to authenticate itself in TLS handshakes.
+///
+/// By default, the server loads its private key from the PEM file at the install configuration's `keystore.key-path`.
+/// A provider installed via [`Witchcraft::tls_private_key_provider`] replaces that file with a [`SigningKey`] which
+/// can delegate signing to a PKCS#11 module, a hardware security module, or any other signing callback, so the private
+/// key never needs to be present on disk. The certificate chain is still loaded from `keystore.cert-path`.
+///
+/// [`Witchcraft::tls_private_key_provider`]: crate::Witchcraft::tls_private_key_provider
+pub trait PrivateKeyProvider {
+    /// Returns the key used to sign TLS handshakes.
+    ///
+    /// This is called when the server starts, and again whenever the certificate file changes so a rotated key can be
+    /// picked up along with its certificate. An error at startup prevents the server from starting, while an error
+    /// during a reload leaves the previous key and certificate in place.
+    fn signing_key(&self) -> Result<Arc<dyn SigningKey>, Error>;
+}
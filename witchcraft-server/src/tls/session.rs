@@ -0,0 +1,65 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+
+/// Parameters negotiated during the TLS handshake.
+///
+/// This will be added to the extensions of each request made over a TLS connection.
+#[derive(Clone, Debug)]
+pub struct TlsSession {
+    protocol: &'static str,
+    cipher_suite: &'static str,
+    alpn_protocol: Option<Arc<str>>,
+    server_name: Option<Arc<str>>,
+}
+
+impl TlsSession {
+    pub(crate) fn new(
+        protocol: &'static str,
+        cipher_suite: &'static str,
+        alpn_protocol: Option<&[u8]>,
+        server_name: Option<&str>,
+    ) -> Self {
+        TlsSession {
+            protocol,
+            cipher_suite,
+            alpn_protocol: alpn_protocol.map(|p| Arc::from(String::from_utf8_lossy(p))),
+            server_name: server_name.map(Arc::from),
+        }
+    }
+
+    /// Returns the negotiated TLS protocol version, e.g. `TLSv1_3`.
+    #[inline]
+    pub fn protocol(&self) -> &str {
+        self.protocol
+    }
+
+    /// Returns the negotiated cipher suite, e.g. `TLS13_AES_128_GCM_SHA256`.
+    #[inline]
+    pub fn cipher_suite(&self) -> &str {
+        self.cipher_suite
+    }
+
+    /// Returns the application protocol negotiated via ALPN, if any.
+    #[inline]
+    pub fn alpn_protocol(&self) -> Option<&str> {
+        self.alpn_protocol.as_deref()
+    }
+
+    /// Returns the server name the client requested via SNI, if any.
+    #[inline]
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+}
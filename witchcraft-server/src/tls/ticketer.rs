@@ -0,0 +1,370 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use conjure_error::Error;
+use parking_lot::{Mutex, MutexGuard};
+use std::collections::VecDeque;
+use std::fmt;
+use std::iter;
+use std::mem;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::crypto::{CryptoProvider, SecureRandom};
+use tokio_rustls::rustls::server::ProducesTickets;
+use witchcraft_server_config::install::{self, TlsConfig};
+
+const KEY_NAME_LEN: usize = 16;
+const ISSUED_LEN: usize = 8;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = KEY_NAME_LEN + ISSUED_LEN + NONCE_LEN;
+const KEY_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+
+/// A session ticket encrypter with a configurable ticket lifetime and key rotation interval.
+///
+/// Tickets consist of the name of the key that encrypted them, the time they were issued, a nonce, and the encrypted
+/// session state. The header is authenticated along with the state, so a ticket's issue time can't be extended.
+/// Tickets are encrypted with AES-256-GCM from the configured crypto provider's library, and enough previous keys are
+/// retained to decrypt any ticket which has not yet expired.
+pub(crate) struct Ticketer {
+    lifetime: Duration,
+    rotation: Duration,
+    max_previous: usize,
+    provider: install::CryptoProvider,
+    random: &'static dyn SecureRandom,
+    keys: Mutex<Keys>,
+}
+
+struct Keys {
+    current: Arc<TicketKey>,
+    previous: VecDeque<Arc<TicketKey>>,
+    next_rotation: Instant,
+}
+
+impl Keys {
+    fn find(&self, name: &[u8]) -> Option<Arc<TicketKey>> {
+        iter::once(&self.current)
+            .chain(&self.previous)
+            .find(|k| k.name == name)
+            .cloned()
+    }
+}
+
+struct TicketKey {
+    name: [u8; KEY_NAME_LEN],
+    key: AeadKey,
+}
+
+impl TicketKey {
+    fn generate(
+        provider: install::CryptoProvider,
+        random: &dyn SecureRandom,
+    ) -> Result<Self, Error> {
+        let mut name = [0; KEY_NAME_LEN];
+        random
+            .fill(&mut name)
+            .map_err(|e| Error::internal_safe(rustls::Error::from(e)))?;
+        let mut key = [0; KEY_LEN];
+        random
+            .fill(&mut key)
+            .map_err(|e| Error::internal_safe(rustls::Error::from(e)))?;
+
+        Ok(TicketKey {
+            name,
+            key: AeadKey::new(provider, &key)?,
+        })
+    }
+}
+
+enum AeadKey {
+    #[cfg(feature = "aws-lc-rs")]
+    AwsLcRs(aws_lc_rs::aead::LessSafeKey),
+    #[cfg(feature = "ring")]
+    Ring(ring::aead::LessSafeKey),
+}
+
+impl AeadKey {
+    fn new(provider: install::CryptoProvider, key: &[u8; KEY_LEN]) -> Result<Self, Error> {
+        match provider {
+            #[cfg(feature = "aws-lc-rs")]
+            install::CryptoProvider::AwsLcRs => {
+                use aws_lc_rs::aead::{LessSafeKey, UnboundKey, AES_256_GCM};
+
+                let key = UnboundKey::new(&AES_256_GCM, key).map_err(Error::internal_safe)?;
+                Ok(AeadKey::AwsLcRs(LessSafeKey::new(key)))
+            }
+            #[cfg(feature = "ring")]
+            install::CryptoProvider::Ring => {
+                use ring::aead::{LessSafeKey, UnboundKey, AES_256_GCM};
+
+                let key = UnboundKey::new(&AES_256_GCM, key).map_err(Error::internal_safe)?;
+                Ok(AeadKey::Ring(LessSafeKey::new(key)))
+            }
+            provider => Err(Error::internal_safe(
+                "crypto provider is not supported for session tickets",
+            )
+            .with_safe_param("provider", format!("{provider:?}"))),
+        }
+    }
+
+    fn seal(&self, nonce: [u8; NONCE_LEN], aad: &[u8], in_out: &mut Vec<u8>) -> Option<()> {
+        match *self {
+            #[cfg(feature = "aws-lc-rs")]
+            AeadKey::AwsLcRs(ref key) => {
+                use aws_lc_rs::aead::{Aad, Nonce};
+
+                key.seal_in_place_append_tag(
+                    Nonce::assume_unique_for_key(nonce),
+                    Aad::from(aad),
+                    in_out,
+                )
+                .ok()
+            }
+            #[cfg(feature = "ring")]
+            AeadKey::Ring(ref key) => {
+                use ring::aead::{Aad, Nonce};
+
+                key.seal_in_place_append_tag(
+                    Nonce::assume_unique_for_key(nonce),
+                    Aad::from(aad),
+                    in_out,
+                )
+                .ok()
+            }
+        }
+    }
+
+    fn open<'a>(&self, nonce: &[u8], aad: &[u8], in_out: &'a mut [u8]) -> Option<&'a mut [u8]> {
+        match *self {
+            #[cfg(feature = "aws-lc-rs")]
+            AeadKey::AwsLcRs(ref key) => {
+                use aws_lc_rs::aead::{Aad, Nonce};
+
+                key.open_in_place(
+                    Nonce::try_assume_unique_for_key(nonce).ok()?,
+                    Aad::from(aad),
+                    in_out,
+                )
+                .ok()
+            }
+            #[cfg(feature = "ring")]
+            AeadKey::Ring(ref key) => {
+                use ring::aead::{Aad, Nonce};
+
+                key.open_in_place(
+                    Nonce::try_assume_unique_for_key(nonce).ok()?,
+                    Aad::from(aad),
+                    in_out,
+                )
+                .ok()
+            }
+        }
+    }
+}
+
+impl Ticketer {
+    pub(crate) fn new(config: &TlsConfig, provider: &CryptoProvider) -> Result<Self, Error> {
+        let session_resumption = config.session_resumption();
+        let lifetime = session_resumption.ticket_lifetime();
+        let rotation = session_resumption.ticket_key_rotation();
+
+        // A key is replaced at most one rotation interval after it issues a ticket, and the ticket must remain
+        // decryptable for its entire lifetime after that.
+        let max_previous = lifetime.as_nanos() / rotation.as_nanos() + 1;
+
+        Ok(Ticketer {
+            lifetime,
+            rotation,
+            max_previous: max_previous.try_into().unwrap_or(usize::MAX),
+            provider: config.crypto_provider(),
+            random: provider.secure_random,
+            keys: Mutex::new(Keys {
+                current: Arc::new(TicketKey::generate(
+                    config.crypto_provider(),
+                    provider.secure_random,
+                )?),
+                previous: VecDeque::new(),
+                next_rotation: Instant::now() + rotation,
+            }),
+        })
+    }
+
+    fn keys(&self) -> Option<MutexGuard<'_, Keys>> {
+        let mut keys = self.keys.lock();
+
+        let now = Instant::now();
+        if now >= keys.next_rotation {
+            let new = Arc::new(TicketKey::generate(self.provider, self.random).ok()?);
+            let old = mem::replace(&mut keys.current, new);
+            keys.previous.push_front(old);
+            keys.previous.truncate(self.max_previous);
+            keys.next_rotation = now + self.rotation;
+        }
+
+        Some(keys)
+    }
+}
+
+impl fmt::Debug for Ticketer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ticketer")
+            .field("lifetime", &self.lifetime)
+            .field("rotation", &self.rotation)
+            .field("provider", &self.provider)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ProducesTickets for Ticketer {
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    fn lifetime(&self) -> u32 {
+        self.lifetime.as_secs().try_into().unwrap_or(u32::MAX)
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        let key = self.keys()?.current.clone();
+        let issued = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+        let mut nonce = [0; NONCE_LEN];
+        self.random.fill(&mut nonce).ok()?;
+
+        let mut ticket = Vec::with_capacity(HEADER_LEN + plain.len() + TAG_LEN);
+        ticket.extend_from_slice(&key.name);
+        ticket.extend_from_slice(&issued.to_be_bytes());
+        ticket.extend_from_slice(&nonce);
+
+        let mut body = plain.to_vec();
+        key.key.seal(nonce, &ticket, &mut body)?;
+        ticket.extend_from_slice(&body);
+
+        Some(ticket)
+    }
+
+    fn decrypt(&self, cipher: &[u8]) -> Option<Vec<u8>> {
+        if cipher.len() < HEADER_LEN {
+            return None;
+        }
+        let (header, body) = cipher.split_at(HEADER_LEN);
+        let (name, rest) = header.split_at(KEY_NAME_LEN);
+        let (issued, nonce) = rest.split_at(ISSUED_LEN);
+
+        let issued = UNIX_EPOCH + Duration::from_secs(u64::from_be_bytes(issued.try_into().ok()?));
+        let age = SystemTime::now().duration_since(issued).ok()?;
+        if age > self.lifetime {
+            return None;
+        }
+
+        let key = self.keys()?.find(name)?;
+
+        let mut body = body.to_vec();
+        let plain = key.key.open(nonce, header, &mut body)?;
+        Some(plain.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tls::crypto_provider;
+    use witchcraft_server_config::install::SessionResumptionConfig;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    fn ticketer_with(
+        provider: install::CryptoProvider,
+        lifetime: Duration,
+        rotation: Duration,
+    ) -> Ticketer {
+        let config = TlsConfig::builder()
+            .crypto_provider(provider)
+            .session_resumption(
+                SessionResumptionConfig::builder()
+                    .ticket_lifetime(lifetime)
+                    .ticket_key_rotation(rotation)
+                    .build(),
+            )
+            .build();
+        let provider = crypto_provider::server_provider(&config).unwrap();
+        Ticketer::new(&config, &provider).unwrap()
+    }
+
+    fn ticketer(lifetime: Duration, rotation: Duration) -> Ticketer {
+        let provider = if cfg!(feature = "aws-lc-rs") {
+            install::CryptoProvider::AwsLcRs
+        } else {
+            install::CryptoProvider::Ring
+        };
+        ticketer_with(provider, lifetime, rotation)
+    }
+
+    fn rotate(ticketer: &Ticketer) {
+        ticketer.keys.lock().next_rotation = Instant::now();
+        ticketer.keys().unwrap();
+    }
+
+    #[test]
+    fn roundtrip() {
+        let ticketer = ticketer(HOUR, HOUR);
+        let ticket = ticketer.encrypt(b"session state").unwrap();
+        assert_eq!(ticketer.decrypt(&ticket).unwrap(), b"session state");
+    }
+
+    #[cfg(feature = "ring")]
+    #[test]
+    fn ring_roundtrip() {
+        let ticketer = ticketer_with(install::CryptoProvider::Ring, HOUR, HOUR);
+        let ticket = ticketer.encrypt(b"session state").unwrap();
+        assert_eq!(ticketer.decrypt(&ticket).unwrap(), b"session state");
+    }
+
+    #[test]
+    fn tampered() {
+        let ticketer = ticketer(HOUR, HOUR);
+        let mut ticket = ticketer.encrypt(b"session state").unwrap();
+
+        // extend the issue time
+        ticket[KEY_NAME_LEN + ISSUED_LEN - 1] ^= 1;
+        assert_eq!(ticketer.decrypt(&ticket), None);
+        assert_eq!(ticketer.decrypt(&ticket[..HEADER_LEN - 1]), None);
+    }
+
+    #[test]
+    fn expired() {
+        let ticketer = ticketer(Duration::ZERO, HOUR);
+        let ticket = ticketer.encrypt(b"session state").unwrap();
+        assert_eq!(ticketer.decrypt(&ticket), None);
+    }
+
+    #[test]
+    fn rotation() {
+        // a ticket issued just before a rotation can still be presented 3 rotations later
+        let ticketer = ticketer(3 * HOUR, HOUR);
+        let ticket = ticketer.encrypt(b"session state").unwrap();
+        for _ in 0..4 {
+            rotate(&ticketer);
+            assert_eq!(ticketer.decrypt(&ticket).unwrap(), b"session state");
+        }
+
+        rotate(&ticketer);
+        assert_eq!(ticketer.decrypt(&ticket), None);
+    }
+
+    #[test]
+    fn other_ticketer() {
+        let ticket = ticketer(HOUR, HOUR).encrypt(b"session state").unwrap();
+        assert_eq!(ticketer(HOUR, HOUR).decrypt(&ticket), None);
+    }
+}
@@ -0,0 +1,148 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Protocol upgrades.
+//!
+//! An [`UpgradeHandler`] installed via [`Witchcraft::upgrade`] takes over the raw connection of requests to its path
+//! which ask to switch to its protocol, allowing custom protocols and tunnels to be served on the server's port. Two
+//! forms of request are supported:
+//!
+//! * HTTP/1.1 requests with a `Connection: upgrade` header and an `Upgrade` header naming the protocol. The server
+//!     responds with `101 Switching Protocols`.
+//! * HTTP/2 extended `CONNECT` requests ([RFC 8441]) with a `:protocol` pseudo-header naming the protocol. The server
+//!     responds with `200 OK` and the request's stream becomes the connection. The server only advertises support for
+//!     extended `CONNECT` if an upgrade handler has been installed.
+//!
+//! Classic HTTP/1.1 `CONNECT` requests, whose target is a `host:port` authority rather than a path, cannot be routed
+//! and are not supported.
+//!
+//! Other requests to the path are rejected with a `400 Bad Request` response. Before the upgrade is accepted, the
+//! handler's [`UpgradeHandler::accept`] method can inspect the request, reject it, or add headers to the response.
+//! Upgrade requests otherwise pass through the server like any other request: they are recorded in the request log and
+//! in endpoint metrics with a `service-name` of `Upgrade` and an `endpoint` of the path, and they are rejected while
+//! the server is in standby or shedding load.
+//!
+//! When the server begins to shut down, the [`ShutdownSignal`] of each connection fires, and the server waits for the
+//! handlers to return before exiting, up to the configured shutdown timeout.
+//!
+//! [`Witchcraft::upgrade`]: crate::Witchcraft::upgrade
+//! [RFC 8441]: https://www.rfc-editor.org/rfc/rfc8441
+use crate::extensions::ShutdownSignal;
+use conjure_error::Error;
+use http::request::Parts;
+use http::{Extensions, HeaderMap, Method, Uri};
+use hyper::upgrade::Upgraded;
+use hyper_util::rt::TokioIo;
+use pin_project::pin_project;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// A handler of connections upgraded to a custom protocol.
+pub trait UpgradeHandler {
+    /// Validates an upgrade request before it is accepted, returning additional headers to include in the response.
+    ///
+    /// If an error is returned, the upgrade is rejected with the error's response. Defaults to accepting every request.
+    fn accept(&self, parts: &Parts) -> Result<HeaderMap, Error> {
+        let _ = parts;
+        Ok(HeaderMap::new())
+    }
+
+    /// Handles an upgraded connection.
+    ///
+    /// The connection is closed when it is dropped.
+    fn handle(&self, connection: UpgradedConnection) -> impl Future<Output = ()> + Send;
+}
+
+/// A connection which has been upgraded to a custom protocol.
+///
+/// Bytes are exchanged with the client through its [`AsyncRead`] and [`AsyncWrite`] implementations.
+#[pin_project]
+pub struct UpgradedConnection {
+    #[pin]
+    io: TokioIo<Upgraded>,
+    parts: Parts,
+    shutdown_signal: ShutdownSignal,
+}
+
+impl UpgradedConnection {
+    pub(crate) fn new(io: Upgraded, parts: Parts, shutdown_signal: ShutdownSignal) -> Self {
+        UpgradedConnection {
+            io: TokioIo::new(io),
+            parts,
+            shutdown_signal,
+        }
+    }
+
+    /// Returns the method of the upgrade request.
+    #[inline]
+    pub fn method(&self) -> &Method {
+        &self.parts.method
+    }
+
+    /// Returns the URI of the upgrade request.
+    #[inline]
+    pub fn uri(&self) -> &Uri {
+        &self.parts.uri
+    }
+
+    /// Returns the headers of the upgrade request.
+    #[inline]
+    pub fn headers(&self) -> &HeaderMap {
+        &self.parts.headers
+    }
+
+    /// Returns the extensions of the upgrade request.
+    #[inline]
+    pub fn extensions(&self) -> &Extensions {
+        &self.parts.extensions
+    }
+
+    /// Returns a signal which fires when the server begins to shut down.
+    ///
+    /// Handlers should wrap up the connection promptly once it fires.
+    #[inline]
+    pub fn shutdown_signal(&self) -> &ShutdownSignal {
+        &self.shutdown_signal
+    }
+}
+
+impl AsyncRead for UpgradedConnection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.project().io.poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for UpgradedConnection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.project().io.poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().io.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().io.poll_shutdown(cx)
+    }
+}
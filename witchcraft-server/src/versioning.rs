@@ -0,0 +1,104 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! API versioning of endpoints.
+//!
+//! Services making breaking changes to their request or response payloads can register an implementation of their API
+//! for each payload schema version with [`Witchcraft::versioned_api`] and [`Witchcraft::blocking_versioned_api`].
+//! Endpoints with the same method and path in different versions are combined, and each request is dispatched to an
+//! implementation based on its `Api-Version` header:
+//!
+//! * Requests without the header are handled by the oldest version of the endpoint, so existing clients continue to
+//!   work unchanged.
+//! * Otherwise, requests are handled by the newest version of the endpoint which is not newer than the requested
+//!   version. Requests for a version older than every implementation are rejected with a `Default:InvalidArgument`
+//!   error.
+//!
+//! The selected version is inserted into the request's extensions as an [`ApiVersion`], and returned to the client in
+//! the `Api-Version` header of the response. Handlers of unversioned endpoints can read the requested version with
+//! [`HeaderMapExt::typed_get`].
+//!
+//! Requests to versioned endpoints are counted in the `server.request.api-version` meter.
+//!
+//! [`Witchcraft::versioned_api`]: crate::Witchcraft::versioned_api
+//! [`Witchcraft::blocking_versioned_api`]: crate::Witchcraft::blocking_versioned_api
+//! [`HeaderMapExt::typed_get`]: crate::headers::HeaderMapExt::typed_get
+use crate::headers::{self, InvalidHeader, TypedHeader};
+use http::{HeaderName, HeaderValue};
+use std::fmt;
+
+/// An API payload schema version.
+///
+/// It is both the [`TypedHeader`] of the `Api-Version` header and an extension present in requests to versioned
+/// endpoints.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ApiVersion(u32);
+
+impl ApiVersion {
+    /// Creates a new `ApiVersion`.
+    #[inline]
+    pub fn new(version: u32) -> Self {
+        ApiVersion(version)
+    }
+
+    /// Returns the version number.
+    #[inline]
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl TypedHeader for ApiVersion {
+    const NAME: HeaderName = HeaderName::from_static("api-version");
+
+    const SAFE: bool = true;
+
+    fn decode<'a, I>(values: I) -> Result<Self, InvalidHeader>
+    where
+        I: IntoIterator<Item = &'a HeaderValue>,
+    {
+        headers::single_value(values)?
+            .parse()
+            .map(ApiVersion)
+            .map_err(|_| InvalidHeader::new("expected a non-negative integer"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::headers::HeaderMapExt;
+    use http::HeaderMap;
+
+    #[test]
+    fn header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(headers.typed_get::<ApiVersion>().unwrap(), None);
+
+        headers.insert(ApiVersion::NAME, HeaderValue::from_static("3"));
+        assert_eq!(
+            headers.typed_get::<ApiVersion>().unwrap(),
+            Some(ApiVersion::new(3))
+        );
+
+        headers.insert(ApiVersion::NAME, HeaderValue::from_static("v3"));
+        headers.typed_get::<ApiVersion>().unwrap_err();
+    }
+}
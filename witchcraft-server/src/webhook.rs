@@ -0,0 +1,302 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Outbound webhook delivery.
+//!
+//! A [`Webhook`] pairs a client created by the server's [`ClientFactory`] with a request path and a shared secret, so
+//! the target's URL, TLS, and proxy settings come from the `service-discovery` section of the runtime configuration
+//! like any other remote service. Webhooks are registered with the server's [`WebhookDispatcher`], obtained via
+//! [`Witchcraft::webhooks`], and events are then delivered to them in the background with [`WebhookDispatcher::send`].
+//!
+//! Each event is `POST`ed as JSON with two additional headers:
+//!
+//! * `X-Webhook-Id` - A random UUID identifying the event, which stays the same across retries so receivers can
+//!     deduplicate deliveries.
+//! * `X-Webhook-Signature` - `sha256=` followed by the hex-encoded HMAC-SHA256 of the request body, keyed by the
+//!     webhook's secret.
+//!
+//! Failed deliveries are retried with jittered exponential backoff. Events which still have not been delivered after
+//! the final attempt, or whose retries are cut short by the server shutting down, are written to the service log along
+//! with their payloads so they can be replayed manually.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let client = witchcraft.client_factory().client("partner-webhooks")?;
+//! let webhook = Webhook::new("partner", client, "/events", &config.partner_webhook_secret)?;
+//! witchcraft.webhooks().register(webhook);
+//!
+//! // later, while handling a request
+//! self.webhooks.send("partner", &OrderShipped { order_id })?;
+//! ```
+//!
+//! [`ClientFactory`]: conjure_runtime::ClientFactory
+//! [`Witchcraft::webhooks`]: crate::Witchcraft::webhooks
+use bytes::Bytes;
+use conjure_error::Error;
+use conjure_http::client::{AsyncClient, AsyncRequestBody, Endpoint};
+use conjure_object::Uuid;
+use conjure_runtime::Client;
+use hmac::{Hmac, Mac};
+use http::header::{HeaderName, CONTENT_TYPE};
+use http::{HeaderValue, Method, Request, Uri};
+use parking_lot::Mutex;
+use rand::Rng;
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use witchcraft_log::warn;
+use witchcraft_metrics::{Meter, MetricId, MetricRegistry, Timer};
+
+const MAX_ATTEMPTS: u32 = 8;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(2 * 60);
+
+#[allow(clippy::declare_interior_mutable_const)]
+const WEBHOOK_ID: HeaderName = HeaderName::from_static("x-webhook-id");
+#[allow(clippy::declare_interior_mutable_const)]
+const WEBHOOK_SIGNATURE: HeaderName = HeaderName::from_static("x-webhook-signature");
+#[allow(clippy::declare_interior_mutable_const)]
+const APPLICATION_JSON: HeaderValue = HeaderValue::from_static("application/json");
+
+/// A registered webhook target.
+pub struct Webhook {
+    name: String,
+    client: Client,
+    path: Uri,
+    secret: Vec<u8>,
+}
+
+impl Webhook {
+    /// Creates a new webhook.
+    ///
+    /// Events will be sent to `path`, which must be an absolute path with an optional query, relative to the URIs
+    /// configured for the client's service. Returns an error if the path is invalid.
+    pub fn new(name: &str, client: Client, path: &str, secret: &str) -> Result<Self, Error> {
+        let path = path
+            .parse::<Uri>()
+            .ok()
+            .filter(|uri| uri.scheme().is_none() && uri.path().starts_with('/'))
+            .ok_or_else(|| {
+                Error::internal_safe("invalid webhook path").with_unsafe_param("path", path)
+            })?;
+
+        Ok(Webhook {
+            name: name.to_string(),
+            client,
+            path,
+            secret: secret.as_bytes().to_vec(),
+        })
+    }
+
+    /// Returns the webhook's name.
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn deliver(&self, id: Uuid, body: &Bytes) -> Result<(), Error> {
+        let mut request = Request::new(AsyncRequestBody::Fixed(body.clone()));
+        *request.method_mut() = Method::POST;
+        *request.uri_mut() = self.path.clone();
+
+        let headers = request.headers_mut();
+        headers.insert(CONTENT_TYPE, APPLICATION_JSON);
+        headers.insert(WEBHOOK_ID, HeaderValue::try_from(id.to_string()).unwrap());
+        headers.insert(
+            WEBHOOK_SIGNATURE,
+            HeaderValue::try_from(signature(&self.secret, body)).unwrap(),
+        );
+
+        request
+            .extensions_mut()
+            .insert(Endpoint::new("Webhook", None, "deliver", "/"));
+
+        self.client.send(request).await?;
+        Ok(())
+    }
+}
+
+fn signature(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+    mac.update(body);
+
+    let mut signature = "sha256=".to_string();
+    for byte in mac.finalize().into_bytes() {
+        write!(signature, "{byte:02x}").unwrap();
+    }
+    signature
+}
+
+struct WebhookMetrics {
+    delivery: Arc<Timer>,
+    delivered: Arc<Meter>,
+    retried: Arc<Meter>,
+    dead_lettered: Arc<Meter>,
+}
+
+impl WebhookMetrics {
+    fn new(metrics: &MetricRegistry, name: &str) -> Self {
+        let id = |metric| MetricId::new(metric).with_tag("webhook", name.to_string());
+        WebhookMetrics {
+            delivery: metrics.timer(id("server.webhook.delivery")),
+            delivered: metrics.meter(id("server.webhook.delivered")),
+            retried: metrics.meter(id("server.webhook.retried")),
+            dead_lettered: metrics.meter(id("server.webhook.dead-lettered")),
+        }
+    }
+}
+
+struct Registration {
+    webhook: Webhook,
+    metrics: WebhookMetrics,
+}
+
+/// The server's dispatcher of webhook events.
+pub struct WebhookDispatcher {
+    metrics: Arc<MetricRegistry>,
+    handle: Handle,
+    webhooks: Mutex<HashMap<String, Arc<Registration>>>,
+    tasks: TaskTracker,
+    shutdown: CancellationToken,
+}
+
+impl WebhookDispatcher {
+    pub(crate) fn new(metrics: &Arc<MetricRegistry>, handle: &Handle) -> Self {
+        WebhookDispatcher {
+            metrics: metrics.clone(),
+            handle: handle.clone(),
+            webhooks: Mutex::new(HashMap::new()),
+            tasks: TaskTracker::new(),
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    /// Registers a webhook, replacing any existing webhook with the same name.
+    ///
+    /// Deliveries already in progress to a replaced webhook are unaffected.
+    pub fn register(&self, webhook: Webhook) {
+        let registration = Registration {
+            metrics: WebhookMetrics::new(&self.metrics, &webhook.name),
+            webhook,
+        };
+        self.webhooks
+            .lock()
+            .insert(registration.webhook.name.clone(), Arc::new(registration));
+    }
+
+    /// Sends an event to the named webhook, returning the event's ID.
+    ///
+    /// The event is serialized immediately and delivered in the background. An error is returned if no webhook with
+    /// the name is registered, the event fails to serialize, or the server is shutting down.
+    pub fn send<T>(&self, webhook: &str, event: &T) -> Result<Uuid, Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        if self.shutdown.is_cancelled() {
+            return Err(Error::unavailable("server is shutting down"));
+        }
+
+        let registration = self.webhooks.lock().get(webhook).cloned().ok_or_else(|| {
+            Error::internal_safe("webhook not registered").with_safe_param("webhook", webhook)
+        })?;
+        let body = Bytes::from(serde_json::to_vec(event).map_err(Error::internal)?);
+
+        let id = Uuid::new_v4();
+        self.tasks.spawn_on(
+            deliver(registration, id, body, self.shutdown.clone()),
+            &self.handle,
+        );
+
+        Ok(id)
+    }
+
+    /// Stops retrying failed deliveries and waits for in-progress deliveries to complete.
+    pub(crate) async fn shutdown(&self) {
+        self.shutdown.cancel();
+        self.tasks.close();
+        self.tasks.wait().await;
+    }
+}
+
+async fn deliver(
+    registration: Arc<Registration>,
+    id: Uuid,
+    body: Bytes,
+    shutdown: CancellationToken,
+) {
+    let Registration { webhook, metrics } = &*registration;
+
+    let mut attempt = 1;
+    let error = loop {
+        let start = Instant::now();
+        let error = match webhook.deliver(id, &body).await {
+            Ok(()) => {
+                metrics.delivery.update(start.elapsed());
+                metrics.delivered.mark(1);
+                return;
+            }
+            Err(error) => error,
+        };
+
+        if attempt == MAX_ATTEMPTS {
+            break error;
+        }
+
+        let backoff = INITIAL_BACKOFF
+            .saturating_mul(1 << (attempt - 1))
+            .min(MAX_BACKOFF)
+            .mul_f64(rand::thread_rng().gen_range(0.5..=1.0));
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = shutdown.cancelled() => break error,
+        }
+
+        metrics.retried.mark(1);
+        attempt += 1;
+    };
+
+    metrics.dead_lettered.mark(1);
+    warn!(
+        "abandoned webhook delivery",
+        safe: {
+            webhook: &webhook.name,
+            eventId: id,
+            attempts: attempt,
+        },
+        unsafe: {
+            payload: String::from_utf8_lossy(&body),
+        },
+        error: error,
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hmac_signature() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            signature(b"Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+        );
+    }
+}
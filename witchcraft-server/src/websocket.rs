@@ -0,0 +1,146 @@
+// Copyright 2022 Palantir Technologies, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! WebSocket endpoints.
+//!
+//! A handler installed via [`Witchcraft::websocket`] is invoked with a [`WebSocket`] for each HTTP/1.1 `GET` request to
+//! its path which asks to upgrade to the WebSocket protocol. The upgrade is performed by the server rather than a
+//! Conjure endpoint, and other requests to the path are rejected with a `400 Bad Request` response. Upgrade requests
+//! otherwise pass through the server like any other request: they are recorded in the request log and in endpoint
+//! metrics with a `service-name` of `WebSocket` and an `endpoint` of the path, and they are rejected while the server
+//! is in standby or shedding load.
+//!
+//! When the server begins to shut down, a close frame with the [`CloseCode::Away`] status is sent on every open
+//! socket the next time it is read from or written to, and the server waits for the handlers to return before
+//! exiting, up to the configured shutdown timeout.
+//!
+//! [`Witchcraft::websocket`]: crate::Witchcraft::websocket
+use futures_sink::Sink;
+use futures_util::future::BoxFuture;
+use futures_util::{ready, Stream};
+use http::request::Parts;
+use http::{Extensions, HeaderMap, Uri};
+use hyper::upgrade::Upgraded;
+use hyper_util::rt::TokioIo;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+pub use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+pub use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+pub use tokio_tungstenite::tungstenite::{Error, Message};
+use tokio_tungstenite::WebSocketStream;
+
+pub(crate) type Handler = Arc<dyn Fn(WebSocket) -> BoxFuture<'static, ()> + Sync + Send>;
+
+/// An upgraded WebSocket connection.
+///
+/// Messages are received through its [`Stream`] implementation and sent through its [`Sink`] implementation.
+pub struct WebSocket {
+    stream: WebSocketStream<TokioIo<Upgraded>>,
+    parts: Parts,
+    shutdown: Option<BoxFuture<'static, ()>>,
+    close_pending: bool,
+}
+
+impl WebSocket {
+    pub(crate) fn new(
+        stream: WebSocketStream<TokioIo<Upgraded>>,
+        parts: Parts,
+        shutdown: impl Future<Output = ()> + 'static + Send,
+    ) -> Self {
+        WebSocket {
+            stream,
+            parts,
+            shutdown: Some(Box::pin(shutdown)),
+            close_pending: false,
+        }
+    }
+
+    /// Returns the URI of the upgrade request.
+    #[inline]
+    pub fn uri(&self) -> &Uri {
+        &self.parts.uri
+    }
+
+    /// Returns the headers of the upgrade request.
+    #[inline]
+    pub fn headers(&self) -> &HeaderMap {
+        &self.parts.headers
+    }
+
+    /// Returns the extensions of the upgrade request.
+    #[inline]
+    pub fn extensions(&self) -> &Extensions {
+        &self.parts.extensions
+    }
+
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(shutdown) = &mut self.shutdown {
+            if shutdown.as_mut().poll(cx).is_pending() {
+                return Poll::Ready(());
+            }
+            self.shutdown = None;
+            self.close_pending = true;
+        }
+
+        if self.close_pending {
+            // Errors are ignored here since the peer may have already closed the socket, and any other problem will be
+            // reported by the next read or write.
+            match Pin::new(&mut self.stream).poll_ready(cx) {
+                Poll::Ready(Ok(())) => {
+                    let _ =
+                        Pin::new(&mut self.stream).start_send(Message::Close(Some(CloseFrame {
+                            code: CloseCode::Away,
+                            reason: "server shutting down".into(),
+                        })));
+                }
+                Poll::Ready(Err(_)) => {}
+                Poll::Pending => return Poll::Pending,
+            }
+            self.close_pending = false;
+        }
+
+        Poll::Ready(())
+    }
+}
+
+impl Stream for WebSocket {
+    type Item = Result<Message, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        ready!(self.poll_shutdown(cx));
+        Pin::new(&mut self.stream).poll_next(cx)
+    }
+}
+
+impl Sink<Message> for WebSocket {
+    type Error = Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.poll_shutdown(cx));
+        Pin::new(&mut self.stream).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
+        Pin::new(&mut self.stream).start_send(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.stream).poll_close(cx)
+    }
+}
@@ -11,23 +11,81 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use crate::acme::Challenges;
+use crate::announcement::{Announcement, DeregistrationReason};
 use crate::blocking::conjure::ConjureBlockingEndpoint;
 use crate::blocking::pool::ThreadPool;
+use crate::cache::{CachePolicies, CachePolicy};
+use crate::classification::RequestClassifier;
+use crate::cluster::{ClusterInfoProvider, ClusterRegistry};
+use crate::cost::{CostAttributor, CostLedger};
 use crate::debug::DiagnosticRegistry;
+use crate::deregistration::DeregisterHook;
+use crate::endpoint::alias::AliasEndpoint;
 use crate::endpoint::conjure::ConjureEndpoint;
 use crate::endpoint::extended_path::ExtendedPathEndpoint;
+#[cfg(feature = "grpc")]
+use crate::endpoint::grpc::{self, GrpcBody, GrpcEndpoint};
+use crate::endpoint::upgrade::UpgradeEndpoint;
+use crate::endpoint::versioned::VersionedEndpoint;
+#[cfg(feature = "websocket")]
+use crate::endpoint::websocket::WebSocketEndpoint;
 use crate::endpoint::WitchcraftEndpoint;
+use crate::extensions::ShutdownSignal;
+use crate::file_descriptors::FileDescriptorMonitor;
+use crate::geo::GeoLookup;
 use crate::health::HealthCheckRegistry;
+use crate::jobs::{JobStore, Jobs, MemoryJobStore};
+use crate::memory_admission::MemoryAdmission;
+use crate::metric_aliases::MetricAliases;
+use crate::ocsp::OcspStapler;
+use crate::outbox::{Dispatcher, EventPublisher, Outbox, OutboxStore};
 use crate::readiness::ReadinessCheckRegistry;
-use crate::shutdown_hooks::ShutdownHooks;
+use crate::service::in_flight::InFlightRequests;
+use crate::shutdown_hooks::{ShutdownHooks, ShutdownPhase};
+use crate::slo::SloRegistry;
+use crate::standby::Standby;
+use crate::tls::PrivateKeyProvider;
+use crate::upgrade::UpgradeHandler;
+use crate::versioning::ApiVersion;
+use crate::webhook::WebhookDispatcher;
+#[cfg(feature = "websocket")]
+use crate::websocket::{self, WebSocket};
 use crate::{blocking, RequestBody, ResponseWriter};
-use conjure_http::server::{AsyncService, BoxAsyncEndpoint, ConjureRuntime, Endpoint, Service};
+#[cfg(feature = "grpc")]
+use bytes::Bytes;
+use conjure_error::Error;
+use conjure_http::server::{
+    AsyncService, BoxAsyncEndpoint, ConjureRuntime, Endpoint, EndpointMetadata, Service,
+};
 use conjure_runtime::ClientFactory;
-use futures_util::Future;
+#[cfg(feature = "grpc")]
+use futures_util::future;
+use futures_util::{Future, FutureExt};
+use http::{Method, Request, Response};
+#[cfg(feature = "grpc")]
+use http_body::Body;
+use refreshable::Refreshable;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+#[cfg(feature = "grpc")]
+use std::error;
+use std::mem;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::runtime::Handle;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+#[cfg(feature = "grpc")]
+use tonic::server::NamedService;
+#[cfg(feature = "grpc")]
+use tower_service::Service as TowerService;
 use witchcraft_metrics::MetricRegistry;
 use witchcraft_server_config::install::InstallConfig;
+use witchcraft_server_config::runtime::ThreadPoolConfig;
+
+pub(crate) type VersionedEndpoints =
+    HashMap<(Method, String), BTreeMap<ApiVersion, Box<dyn WitchcraftEndpoint + Sync + Send>>>;
 
 /// The Witchcraft server context.
 pub struct Witchcraft {
@@ -39,9 +97,34 @@ pub struct Witchcraft {
     pub(crate) handle: Handle,
     pub(crate) install_config: InstallConfig,
     pub(crate) thread_pool: Option<Arc<ThreadPool>>,
+    pub(crate) thread_pool_config: Refreshable<ThreadPoolConfig, Error>,
     pub(crate) endpoints: Vec<Box<dyn WitchcraftEndpoint + Sync + Send>>,
+    pub(crate) versioned_endpoints: VersionedEndpoints,
+    pub(crate) cache_policies: CachePolicies,
+    pub(crate) may_block: HashMap<(String, String), bool>,
     pub(crate) shutdown_hooks: ShutdownHooks,
+    pub(crate) startup_hooks: Vec<Box<dyn FnOnce(Announcement) + Send>>,
+    pub(crate) deregister_hooks: Vec<DeregisterHook>,
     pub(crate) conjure_runtime: Arc<ConjureRuntime>,
+    pub(crate) geo_lookup: Option<Arc<dyn GeoLookup + Sync + Send>>,
+    pub(crate) request_classifier: Option<Arc<dyn RequestClassifier + Sync + Send>>,
+    pub(crate) cost_ledger: Option<Arc<CostLedger>>,
+    pub(crate) cluster: Arc<ClusterRegistry>,
+    pub(crate) upgrade_tasks: TaskTracker,
+    pub(crate) extended_connect: bool,
+    pub(crate) shutdown_signal: ShutdownSignal,
+    pub(crate) slos: Arc<SloRegistry>,
+    pub(crate) metric_aliases: Arc<MetricAliases>,
+    pub(crate) memory_admission: Arc<MemoryAdmission>,
+    pub(crate) in_flight: Arc<InFlightRequests>,
+    pub(crate) standby: Arc<Standby>,
+    pub(crate) file_descriptors: Arc<FileDescriptorMonitor>,
+    pub(crate) acme_challenges: Arc<Challenges>,
+    pub(crate) ocsp_stapler: Option<Arc<OcspStapler>>,
+    pub(crate) private_key_provider: Option<Arc<dyn PrivateKeyProvider + Sync + Send>>,
+    pub(crate) jobs: Option<Arc<Jobs>>,
+    pub(crate) job_store: Option<Arc<dyn JobStore + Sync + Send>>,
+    pub(crate) webhooks: Option<Arc<WebhookDispatcher>>,
 }
 
 impl Witchcraft {
@@ -51,13 +134,29 @@ impl Witchcraft {
         &self.metrics
     }
 
+    /// Reports metrics named `name` under `alias` as well, with the same tags and values.
+    ///
+    /// This is intended for renamed application metrics: registering the old name as an alias of the new one keeps
+    /// dashboards and alerts built on the old name working while they are migrated. The server registers no aliases for
+    /// its own metrics. Aliases are not reported if the install configuration's `server.metric-aliases` value is
+    /// `false`.
+    pub fn metric_alias(&self, name: &str, alias: &str) {
+        self.metric_aliases.register(name, alias);
+    }
+
     /// Returns a reference to the server's health check registry.
+    ///
+    /// The registry can be cloned and retained past initialization to register checks from background tasks after the
+    /// server has started.
     #[inline]
     pub fn health_checks(&self) -> &Arc<HealthCheckRegistry> {
         &self.health_checks
     }
 
     /// Returns a reference to the server's readiness check registry.
+    ///
+    /// The registry can be cloned and retained past initialization to register checks from background tasks after the
+    /// server has started.
     #[inline]
     pub fn readiness_checks(&self) -> &Arc<ReadinessCheckRegistry> {
         &self.readiness_checks
@@ -86,7 +185,8 @@ impl Witchcraft {
     where
         T: AsyncService<RequestBody, ResponseWriter>,
     {
-        self.endpoints(None, service.endpoints(&self.conjure_runtime), true)
+        let endpoints = self.conjure_endpoints(service.endpoints(&self.conjure_runtime), true);
+        self.install(None, endpoints);
     }
 
     /// Installs an async service under the server's `/api` prefix.
@@ -94,7 +194,19 @@ impl Witchcraft {
     where
         T: AsyncService<RequestBody, ResponseWriter>,
     {
-        self.endpoints(Some("/api"), service.endpoints(&self.conjure_runtime), true)
+        let endpoints = self.conjure_endpoints(service.endpoints(&self.conjure_runtime), true);
+        self.install(Some("/api"), endpoints);
+    }
+
+    /// Installs an async service implementing a version of an API under the server's `/api` prefix.
+    ///
+    /// See the [`versioning`](crate::versioning) module for details.
+    pub fn versioned_api<T>(&mut self, version: u32, service: T)
+    where
+        T: AsyncService<RequestBody, ResponseWriter>,
+    {
+        let endpoints = self.conjure_endpoints(service.endpoints(&self.conjure_runtime), true);
+        self.install_version(version, endpoints);
     }
 
     pub(crate) fn endpoints(
@@ -103,18 +215,61 @@ impl Witchcraft {
         endpoints: Vec<BoxAsyncEndpoint<'static, RequestBody, ResponseWriter>>,
         track_metrics: bool,
     ) {
+        let endpoints = self.conjure_endpoints(endpoints, track_metrics);
+        self.install(prefix, endpoints);
+    }
+
+    fn conjure_endpoints(
+        &mut self,
+        endpoints: Vec<BoxAsyncEndpoint<'static, RequestBody, ResponseWriter>>,
+        track_metrics: bool,
+    ) -> Vec<Box<dyn WitchcraftEndpoint + Sync + Send>> {
         let metrics = if track_metrics {
-            Some(&*self.metrics)
+            Some((&*self.metrics, &*self.slos))
         } else {
             None
         };
 
-        self.endpoints.extend(
-            endpoints
-                .into_iter()
-                .map(|e| Box::new(ConjureEndpoint::new(metrics, e)))
-                .map(|e| extend_path(e, self.install_config.context_path(), prefix)),
-        )
+        endpoints
+            .into_iter()
+            .map(|e| {
+                let may_block = match self
+                    .may_block
+                    .get_mut(&(e.service_name().to_string(), e.name().to_string()))
+                {
+                    Some(installed) => {
+                        *installed = true;
+                        true
+                    }
+                    None => false,
+                };
+                Box::new(ConjureEndpoint::new(metrics, e, may_block)) as _
+            })
+            .collect()
+    }
+
+    /// Marks an async endpoint, identified by its service and endpoint names, as one which may block the thread it
+    /// runs on.
+    ///
+    /// The handlers of marked endpoints are run to completion on a thread of Tokio's blocking pool via
+    /// [`tokio::task::spawn_blocking`], so a blocking handler doesn't stall the other tasks scheduled on the runtime's
+    /// worker threads. This is intended as a stopgap for handlers which have not yet been ported off of blocking APIs;
+    /// it is less efficient than installing the service with the blocking methods like [`Witchcraft::blocking_api`].
+    ///
+    /// Endpoints are marked when their service is installed, so this must be called before then. A warning is logged at
+    /// startup for each marked endpoint which was never installed.
+    pub fn may_block(&mut self, service_name: &str, endpoint_name: &str) {
+        self.may_block
+            .entry((service_name.to_string(), endpoint_name.to_string()))
+            .or_insert(false);
+    }
+
+    /// Declares the expected p99 latency of an endpoint, identified by its service and endpoint names.
+    ///
+    /// The server tracks the endpoint's latency over a rolling window, and the `ENDPOINT_SLA` health check reports a
+    /// warning while the observed p99 latency exceeds the declaration.
+    pub fn endpoint_sla(&self, service_name: &str, endpoint_name: &str, p99: Duration) {
+        self.slos.declare_sla(service_name, endpoint_name, p99);
     }
 
     /// Installs a blocking service at the server's root.
@@ -122,7 +277,8 @@ impl Witchcraft {
     where
         T: Service<blocking::RequestBody, blocking::ResponseWriter>,
     {
-        self.blocking_endpoints(None, service.endpoints(&self.conjure_runtime))
+        let endpoints = self.blocking_endpoints(service.endpoints(&self.conjure_runtime));
+        self.install(None, endpoints);
     }
 
     /// Installs a blocking service under the server's `/api` prefix.
@@ -130,37 +286,381 @@ impl Witchcraft {
     where
         T: Service<blocking::RequestBody, blocking::ResponseWriter>,
     {
-        self.blocking_endpoints(Some("/api"), service.endpoints(&self.conjure_runtime))
+        let endpoints = self.blocking_endpoints(service.endpoints(&self.conjure_runtime));
+        self.install(Some("/api"), endpoints);
+    }
+
+    /// Installs a blocking service implementing a version of an API under the server's `/api` prefix.
+    ///
+    /// See the [`versioning`](crate::versioning) module for details.
+    pub fn blocking_versioned_api<T>(&mut self, version: u32, service: T)
+    where
+        T: Service<blocking::RequestBody, blocking::ResponseWriter>,
+    {
+        let endpoints = self.blocking_endpoints(service.endpoints(&self.conjure_runtime));
+        self.install_version(version, endpoints);
     }
 
     fn blocking_endpoints(
         &mut self,
-        prefix: Option<&str>,
         endpoints: Vec<
             Box<dyn Endpoint<blocking::RequestBody, blocking::ResponseWriter> + Sync + Send>,
         >,
-    ) {
-        let thread_pool = self
-            .thread_pool
-            .get_or_insert_with(|| Arc::new(ThreadPool::new(&self.install_config, &self.metrics)));
+    ) -> Vec<Box<dyn WitchcraftEndpoint + Sync + Send>> {
+        let thread_pool = self.thread_pool.get_or_insert_with(|| {
+            Arc::new(ThreadPool::new(
+                &self.install_config,
+                &self.thread_pool_config,
+                &self.metrics,
+            ))
+        });
 
+        endpoints
+            .into_iter()
+            .map(|e| {
+                Box::new(ConjureBlockingEndpoint::new(
+                    &self.metrics,
+                    &self.slos,
+                    thread_pool,
+                    e,
+                )) as _
+            })
+            .collect()
+    }
+
+    fn install(
+        &mut self,
+        prefix: Option<&str>,
+        endpoints: Vec<Box<dyn WitchcraftEndpoint + Sync + Send>>,
+    ) {
         self.endpoints.extend(
             endpoints
                 .into_iter()
-                .map(|e| Box::new(ConjureBlockingEndpoint::new(&self.metrics, thread_pool, e)))
-                .map(|e| extend_path(e, self.install_config.context_path(), prefix)),
+                .flat_map(|e| extend_paths(e, &self.install_config, &self.metrics, prefix)),
         )
     }
 
+    fn install_version(
+        &mut self,
+        version: u32,
+        endpoints: Vec<Box<dyn WitchcraftEndpoint + Sync + Send>>,
+    ) {
+        for endpoint in endpoints {
+            self.versioned_endpoints
+                .entry((endpoint.method(), endpoint.template().to_string()))
+                .or_default()
+                .insert(ApiVersion::new(version), endpoint);
+        }
+    }
+
+    /// Returns all installed endpoints, combining the versions of versioned endpoints.
+    pub(crate) fn take_endpoints(&mut self) -> Vec<Box<dyn WitchcraftEndpoint + Sync + Send>> {
+        for (_, versions) in mem::take(&mut self.versioned_endpoints) {
+            let endpoint = VersionedEndpoint::new(&self.metrics, versions);
+            self.install(Some("/api"), vec![Box::new(endpoint)]);
+        }
+
+        mem::take(&mut self.endpoints)
+    }
+
+    /// Sets the cache policy of an endpoint, identified by its service and endpoint names.
+    ///
+    /// See the [`cache`](crate::cache) module for details.
+    pub fn cache_policy(&mut self, service_name: &str, endpoint_name: &str, policy: CachePolicy) {
+        self.cache_policies
+            .insert(service_name, endpoint_name, &policy);
+    }
+
+    /// Adds a callback that will be invoked once the server has started listening on its service port.
+    ///
+    /// The callback is provided with the addresses the server is bound to. This is primarily useful when the ports
+    /// were not known ahead of time, such as when an ephemeral port is used in tests, or to announce the server to a
+    /// service discovery system. The callback runs within the server's runtime, so it can spawn tasks which perform
+    /// network IO.
+    pub fn on_startup<F>(&mut self, callback: F)
+    where
+        F: FnOnce(Announcement) + 'static + Send,
+    {
+        self.startup_hooks.push(Box::new(callback))
+    }
+
+    /// Adds a callback that will be invoked to remove the server from a service discovery system.
+    ///
+    /// The callback is invoked at most once, when the server begins its shutdown process or when its health has
+    /// remained at `ERROR` or worse for longer than the configured `server.unhealthy-deregistration-delay`, whichever
+    /// happens first. The server will not shut down until the returned future completes or the configured shutdown
+    /// timeout elapses.
+    pub fn on_deregister<F, G>(&mut self, callback: F)
+    where
+        F: FnOnce(DeregistrationReason) -> G + 'static + Send,
+        G: Future<Output = ()> + 'static + Send,
+    {
+        self.deregister_hooks
+            .push(Box::new(move |reason| callback(reason).boxed()))
+    }
+
+    /// Installs a lookup used to enrich requests to the service port with geographic information about the client.
+    ///
+    /// See the [`geo`](crate::geo) module for details.
+    pub fn geo_lookup<T>(&mut self, lookup: T)
+    where
+        T: GeoLookup + 'static + Sync + Send,
+    {
+        self.geo_lookup = Some(Arc::new(lookup));
+    }
+
+    /// Installs a classifier used to label requests to the service port for metrics, logging, and load shedding.
+    ///
+    /// See the [`classification`](crate::classification) module for details.
+    pub fn request_classifier<T>(&mut self, classifier: T)
+    where
+        T: RequestClassifier + 'static + Sync + Send,
+    {
+        self.request_classifier = Some(Arc::new(classifier));
+    }
+
+    /// Installs an attributor used to aggregate the resource usage of requests to the service port per tenant.
+    ///
+    /// See the [`cost`](crate::cost) module for details.
+    pub fn cost_attributor<T>(&mut self, attributor: T)
+    where
+        T: CostAttributor + 'static + Sync + Send,
+    {
+        self.cost_ledger = Some(Arc::new(CostLedger::new(Arc::new(attributor))));
+    }
+
+    /// Installs a provider of information about the cluster the server is a member of.
+    ///
+    /// See the [`cluster`](crate::cluster) module for details.
+    pub fn cluster_info_provider<T>(&mut self, provider: T)
+    where
+        T: ClusterInfoProvider + 'static + Sync + Send,
+    {
+        self.cluster.set_provider(Arc::new(provider));
+    }
+
+    /// Installs a provider of the private key used in TLS handshakes, replacing the key file in the install
+    /// configuration.
+    ///
+    /// See the [`PrivateKeyProvider`] documentation for details.
+    pub fn tls_private_key_provider<T>(&mut self, provider: T)
+    where
+        T: PrivateKeyProvider + 'static + Sync + Send,
+    {
+        self.private_key_provider = Some(Arc::new(provider));
+    }
+
+    /// Installs a handler for WebSocket connections to a path under the server's context path.
+    ///
+    /// See the [`websocket`](crate::websocket) module for details. Requires the `websocket` cargo feature.
+    #[cfg(feature = "websocket")]
+    pub fn websocket<F, G>(&mut self, path: &str, handler: F)
+    where
+        F: Fn(WebSocket) -> G + 'static + Sync + Send,
+        G: Future<Output = ()> + 'static + Send,
+    {
+        let handler: websocket::Handler = Arc::new(move |socket| handler(socket).boxed());
+        let endpoint = WebSocketEndpoint::new(
+            &self.metrics,
+            &self.slos,
+            path,
+            handler,
+            &self.shutdown_signal,
+            &self.upgrade_tasks,
+        );
+        self.install(None, vec![Box::new(endpoint)]);
+    }
+
+    /// Installs a handler for connections to a path under the server's context path which upgrade to a custom protocol.
+    ///
+    /// See the [`upgrade`](crate::upgrade) module for details.
+    pub fn upgrade<T>(&mut self, path: &str, protocol: &str, handler: T)
+    where
+        T: UpgradeHandler + 'static + Sync + Send,
+    {
+        let endpoints = UpgradeEndpoint::new_pair(
+            &self.metrics,
+            &self.slos,
+            path,
+            protocol,
+            handler,
+            &self.shutdown_signal,
+            &self.upgrade_tasks,
+        );
+        self.extended_connect = true;
+        self.install(
+            None,
+            endpoints
+                .into_iter()
+                .map(|e| Box::new(e) as Box<dyn WitchcraftEndpoint + Sync + Send>)
+                .collect(),
+        );
+    }
+
+    /// Installs a gRPC service, such as a server generated by `tonic`.
+    ///
+    /// Requests are routed on their `/<package>.<service>/<method>` path and `application/grpc` content type. Since
+    /// gRPC clients don't support path prefixes, the service is installed at the root of the server rather than under
+    /// its context path. It shares the server's port, TLS configuration, request logging, metrics, and trace
+    /// propagation with its Conjure endpoints. gRPC requires HTTP/2, which clients negotiate via TLS ALPN.
+    ///
+    /// Requires the `grpc` cargo feature.
+    #[cfg(feature = "grpc")]
+    pub fn grpc<S, B>(&mut self, service: S)
+    where
+        S: TowerService<Request<tonic::body::BoxBody>, Response = Response<B>>
+            + NamedService
+            + Clone
+            + 'static
+            + Sync
+            + Send,
+        S::Error: Into<Box<dyn error::Error + Sync + Send>>,
+        S::Future: Send,
+        B: Body<Data = Bytes> + 'static + Send,
+        B::Error: Into<Box<dyn error::Error + Sync + Send>>,
+    {
+        let handler: grpc::Handler = Arc::new(move |req| {
+            let mut service = service.clone();
+            async move {
+                future::poll_fn(|cx| service.poll_ready(cx))
+                    .await
+                    .map_err(Error::internal)?;
+                let response = service.call(req).await.map_err(Error::internal)?;
+                Ok(response.map(GrpcBody::new))
+            }
+            .boxed()
+        });
+        let endpoint = GrpcEndpoint::new(&self.metrics, &self.slos, S::NAME, handler);
+        self.endpoints.push(Box::new(endpoint));
+    }
+
+    /// Returns the server's manager of long-running jobs.
+    ///
+    /// The manager is created on first use, and the endpoints clients use to poll and cancel jobs are only installed if
+    /// it has been. See the [`jobs`](crate::jobs) module for details.
+    pub fn jobs(&mut self) -> &Arc<Jobs> {
+        self.jobs.get_or_insert_with(|| {
+            let store = self
+                .job_store
+                .clone()
+                .unwrap_or_else(|| Arc::new(MemoryJobStore::new()));
+            Arc::new(Jobs::new(&self.install_config, &self.handle, store))
+        })
+    }
+
+    /// Sets the store which persists the records of jobs submitted to the server's job manager.
+    ///
+    /// Defaults to a [`MemoryJobStore`]. The store is used when the manager is created, so this must be called before
+    /// [`Witchcraft::jobs`].
+    pub fn job_store<S>(&mut self, store: S)
+    where
+        S: JobStore + 'static + Sync + Send,
+    {
+        self.job_store = Some(Arc::new(store));
+    }
+
+    /// Returns the server's dispatcher of outbound webhook events.
+    ///
+    /// The dispatcher is created on first use. See the [`webhook`](crate::webhook) module for details.
+    pub fn webhooks(&mut self) -> &Arc<WebhookDispatcher> {
+        self.webhooks
+            .get_or_insert_with(|| Arc::new(WebhookDispatcher::new(&self.metrics, &self.handle)))
+    }
+
+    /// Installs a transactional outbox backed by the provided store and publisher, returning a handle to it.
+    ///
+    /// The dispatcher publishing events from the store starts immediately, and stops when the server begins its
+    /// shutdown process. See the [`outbox`](crate::outbox) module for details.
+    pub fn outbox<S, P>(&mut self, store: S, publisher: P) -> Arc<Outbox>
+    where
+        S: OutboxStore + 'static + Sync + Send,
+        P: EventPublisher + 'static + Sync + Send,
+    {
+        let dispatcher = Dispatcher::new(&self.metrics, store, publisher);
+        let outbox = dispatcher.outbox().clone();
+
+        let shutdown = CancellationToken::new();
+        let task = self.handle.spawn(dispatcher.run(shutdown.clone()));
+        self.on_shutdown_phase(ShutdownPhase::StopBackgroundTasks, "outbox", async move {
+            shutdown.cancel();
+            let _ = task.await;
+        });
+
+        outbox
+    }
+
+    /// Returns a signal which fires when the server begins its shutdown process.
+    ///
+    /// The same signal is available to request handlers as a [`ShutdownSignal`] request extension.
+    #[inline]
+    pub fn shutdown_signal(&self) -> &ShutdownSignal {
+        &self.shutdown_signal
+    }
+
     /// Adds a future that will be run when the server begins its shutdown process.
     ///
-    /// The server will not shut down until the future completes or the configured shutdown timeout elapses.
+    /// The future runs in the [`ShutdownPhase::DrainRequests`] phase. The server will not shut down until the future
+    /// completes or the configured shutdown timeout elapses.
     pub fn on_shutdown<F>(&mut self, future: F)
     where
         F: Future<Output = ()> + 'static + Send,
     {
-        self.shutdown_hooks.push(future)
+        self.on_shutdown_phase(ShutdownPhase::DrainRequests, "on-shutdown", future)
     }
+
+    /// Adds a named future that will be run in the specified phase of the server's shutdown process.
+    ///
+    /// Phases run one after another, and all futures within a phase run concurrently. The name identifies the future
+    /// in the server's shutdown logs. The server will not move on to the next phase until the future completes or the
+    /// phase's timeout elapses, and will not shut down until the configured shutdown timeout elapses.
+    pub fn on_shutdown_phase<F>(
+        &mut self,
+        phase: ShutdownPhase,
+        name: impl Into<Cow<'static, str>>,
+        future: F,
+    ) where
+        F: Future<Output = ()> + 'static + Send,
+    {
+        self.shutdown_hooks.push(phase, name, future)
+    }
+
+    /// Sets a timeout for a phase of the server's shutdown process.
+    ///
+    /// Once the timeout elapses, futures in the phase which have not yet completed are abandoned and the next phase
+    /// begins. Phases without a timeout are only bounded by the configured shutdown timeout, which applies to the
+    /// shutdown process as a whole.
+    pub fn shutdown_phase_timeout(&mut self, phase: ShutdownPhase, timeout: Duration) {
+        self.shutdown_hooks.set_timeout(phase, timeout)
+    }
+}
+
+fn extend_paths(
+    endpoint: Box<dyn WitchcraftEndpoint + Sync + Send>,
+    install_config: &InstallConfig,
+    metrics: &MetricRegistry,
+    prefix: Option<&str>,
+) -> Vec<Box<dyn WitchcraftEndpoint + Sync + Send>> {
+    let aliases = install_config.context_path_aliases();
+    if aliases.is_empty() {
+        return vec![extend_path(endpoint, install_config.context_path(), prefix)];
+    }
+
+    let endpoint = Arc::<dyn WitchcraftEndpoint + Sync + Send>::from(endpoint);
+
+    let mut endpoints = vec![extend_path(
+        Box::new(AliasEndpoint::primary(endpoint.clone())),
+        install_config.context_path(),
+        prefix,
+    )];
+    for alias in aliases {
+        endpoints.push(extend_path(
+            Box::new(AliasEndpoint::alias(metrics, endpoint.clone(), alias)),
+            alias,
+            prefix,
+        ));
+    }
+
+    endpoints
 }
 
 fn extend_path(